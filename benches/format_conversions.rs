@@ -3,14 +3,50 @@
 //! Run with: cargo bench
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 // Import the standalone conversion functions from the library
-use camera_box::display::{convert_rgba_to_bgra, convert_uyvy_to_bgra, scale_nearest_neighbor};
-use camera_box::ndi::{convert_bgra_to_uyvy, convert_nv12_to_uyvy, convert_yuyv_to_uyvy_scalar};
+use camera_box::color_range::ColorRange;
+use camera_box::compositor::blend_over;
+use camera_box::display::{
+    convert_rgba_to_bgra, convert_uyvy_to_bgra, scale_nearest_neighbor, scale_uyvy_nearest,
+};
+use camera_box::ndi::{
+    convert_bgra_to_uyvy, convert_nv12_to_uyvy, convert_yuyv_to_uyvy_scalar, UyvyBuffer,
+};
 
 #[cfg(target_arch = "x86_64")]
 use camera_box::ndi::{convert_yuyv_to_uyvy_avx2, has_avx2};
 
+/// Counts heap allocations so the reuse benchmarks can report them
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Allocations made by `frames` calls of `f`
+fn count_allocations(frames: usize, mut f: impl FnMut()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..frames {
+        f();
+    }
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
 fn bench_yuyv_to_uyvy(c: &mut Criterion) {
     let frame_1080p = vec![128u8; 1920 * 1080 * 2]; // YUYV is 2 bytes/pixel
 
@@ -28,7 +64,47 @@ fn bench_yuyv_to_uyvy(c: &mut Criterion) {
         });
     }
 
+    // Scratch buffer reused across frames, as NdiSender does
+    let mut buffer = UyvyBuffer::new();
+    group.bench_function("scalar_1080p_reused", |b| {
+        b.iter(|| buffer.convert_yuyv(black_box(&frame_1080p), false))
+    });
+
+    // Streaming loads, as used for DMA-BUF frames. On cached heap memory
+    // this measures their overhead; the gain only shows on uncached
+    // mappings, so compare against a capture with capture.io_mode = "dmabuf"
+    #[cfg(target_arch = "x86_64")]
+    if has_avx2() {
+        group.bench_function("avx2_stream_1080p_reused", |b| {
+            b.iter(|| buffer.convert_yuyv_uncached(black_box(&frame_1080p), true))
+        });
+    }
+
+    let frame_4k = vec![128u8; 3840 * 2160 * 2];
+    group.throughput(Throughput::Bytes(frame_4k.len() as u64));
+    let mut buffer_4k = UyvyBuffer::new();
+    buffer_4k.reserve(frame_4k.len());
+    group.bench_function("scalar_4k_reused", |b| {
+        b.iter(|| buffer_4k.convert_yuyv(black_box(&frame_4k), false))
+    });
+
+    #[cfg(target_arch = "x86_64")]
+    if has_avx2() {
+        group.bench_function("avx2_4k_reused", |b| {
+            b.iter(|| buffer_4k.convert_yuyv(black_box(&frame_4k), true))
+        });
+    }
+
     group.finish();
+
+    let fresh = count_allocations(100, || {
+        black_box(convert_yuyv_to_uyvy_scalar(black_box(&frame_1080p)));
+    });
+    let reused = count_allocations(100, || buffer.convert_yuyv(black_box(&frame_1080p), false));
+    println!(
+        "yuyv_to_uyvy scalar allocations per 100 frames: fresh={} reused={}",
+        fresh, reused
+    );
 }
 
 fn bench_uyvy_to_bgra(c: &mut Criterion) {
@@ -38,7 +114,13 @@ fn bench_uyvy_to_bgra(c: &mut Criterion) {
     group.throughput(Throughput::Bytes(frame_1080p.len() as u64));
 
     group.bench_function("1080p", |b| {
-        b.iter(|| convert_uyvy_to_bgra(black_box(&frame_1080p), 1920, 1080))
+        b.iter(|| convert_uyvy_to_bgra(black_box(&frame_1080p), 1920, 1080, ColorRange::Limited))
+    });
+
+    let frame_4k = vec![128u8; 3840 * 2160 * 2];
+    group.throughput(Throughput::Bytes(frame_4k.len() as u64));
+    group.bench_function("4k", |b| {
+        b.iter(|| convert_uyvy_to_bgra(black_box(&frame_4k), 3840, 2160, ColorRange::Limited))
     });
 
     group.finish();
@@ -51,7 +133,29 @@ fn bench_bgra_to_uyvy(c: &mut Criterion) {
     group.throughput(Throughput::Bytes(frame_1080p.len() as u64));
 
     group.bench_function("1080p", |b| {
-        b.iter(|| convert_bgra_to_uyvy(black_box(&frame_1080p), 1920, 1080))
+        b.iter(|| {
+            convert_bgra_to_uyvy(
+                black_box(&frame_1080p),
+                1920,
+                1080,
+                1920 * 4,
+                ColorRange::Limited,
+            )
+        })
+    });
+
+    let frame_4k = vec![128u8; 3840 * 2160 * 4];
+    group.throughput(Throughput::Bytes(frame_4k.len() as u64));
+    group.bench_function("4k", |b| {
+        b.iter(|| {
+            convert_bgra_to_uyvy(
+                black_box(&frame_4k),
+                3840,
+                2160,
+                3840 * 4,
+                ColorRange::Limited,
+            )
+        })
     });
 
     group.finish();
@@ -70,6 +174,12 @@ fn bench_nv12_to_uyvy(c: &mut Criterion) {
         b.iter(|| convert_nv12_to_uyvy(black_box(&frame_1080p), 1920, 1080))
     });
 
+    let frame_4k = vec![128u8; 3840 * 2160 * 3 / 2];
+    group.throughput(Throughput::Bytes(frame_4k.len() as u64));
+    group.bench_function("4k", |b| {
+        b.iter(|| convert_nv12_to_uyvy(black_box(&frame_4k), 3840, 2160))
+    });
+
     group.finish();
 }
 
@@ -106,6 +216,98 @@ fn bench_scale_nearest(c: &mut Criterion) {
     group.finish();
 }
 
+fn bench_display_downscale(c: &mut Criterion) {
+    // A 1080p UYVY source on a 720p monitor, both orders
+    let frame_1080p = vec![128u8; 1920 * 1080 * 2];
+
+    let mut group = c.benchmark_group("display_1080p_to_720p");
+    group.throughput(Throughput::Bytes(frame_1080p.len() as u64));
+
+    group.bench_function("convert_then_scale", |b| {
+        b.iter(|| {
+            let bgra =
+                convert_uyvy_to_bgra(black_box(&frame_1080p), 1920, 1080, ColorRange::Limited);
+            scale_nearest_neighbor(&bgra, 1920, 1080, 1280, 720)
+        })
+    });
+
+    group.bench_function("scale_then_convert", |b| {
+        b.iter(|| {
+            let uyvy = scale_uyvy_nearest(black_box(&frame_1080p), 1920, 1080, 1280, 720);
+            convert_uyvy_to_bgra(&uyvy, 1280, 720, ColorRange::Limited)
+        })
+    });
+
+    group.finish();
+
+    // A 4K source on a 1080p monitor
+    let frame_4k = vec![128u8; 3840 * 2160 * 2];
+
+    let mut group = c.benchmark_group("display_4k_to_1080p");
+    group.throughput(Throughput::Bytes(frame_4k.len() as u64));
+
+    group.bench_function("convert_then_scale", |b| {
+        b.iter(|| {
+            let bgra = convert_uyvy_to_bgra(black_box(&frame_4k), 3840, 2160, ColorRange::Limited);
+            scale_nearest_neighbor(&bgra, 3840, 2160, 1920, 1080)
+        })
+    });
+
+    group.bench_function("scale_then_convert", |b| {
+        b.iter(|| {
+            let uyvy = scale_uyvy_nearest(black_box(&frame_4k), 3840, 2160, 1920, 1080);
+            convert_uyvy_to_bgra(&uyvy, 1920, 1080, ColorRange::Limited)
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_blend_over(c: &mut Criterion) {
+    // A full-frame translucent overlay (every pixel takes the blend path)
+    // and an opaque one (every pixel is copied) over a 1080p frame
+    let mut frame_1080p = vec![64u8; 1920 * 1080 * 4];
+    let translucent = [64u8, 64, 64, 128].repeat(1920 * 1080);
+    let opaque = [200u8, 100, 50, 255].repeat(1920 * 1080);
+
+    let mut group = c.benchmark_group("blend_over");
+    group.throughput(Throughput::Bytes(frame_1080p.len() as u64));
+
+    group.bench_function("1080p_translucent", |b| {
+        b.iter(|| {
+            blend_over(
+                black_box(&mut frame_1080p),
+                black_box(&translucent),
+                1920,
+                1080,
+                1920,
+                1080,
+                0,
+                0,
+                0.8,
+            )
+        })
+    });
+
+    group.bench_function("1080p_opaque", |b| {
+        b.iter(|| {
+            blend_over(
+                black_box(&mut frame_1080p),
+                black_box(&opaque),
+                1920,
+                1080,
+                1920,
+                1080,
+                0,
+                0,
+                1.0,
+            )
+        })
+    });
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     bench_yuyv_to_uyvy,
@@ -114,5 +316,7 @@ criterion_group!(
     bench_nv12_to_uyvy,
     bench_rgba_to_bgra,
     bench_scale_nearest,
+    bench_display_downscale,
+    bench_blend_over,
 );
 criterion_main!(benches);
@@ -0,0 +1,83 @@
+//! Soak test comparing mmap and DMA-BUF capture
+//!
+//! Captures the same number of frames from a device in each I/O mode and
+//! checks that every DMA-BUF frame matches a frame captured through mmap.
+//! Point the device at a still picture (a paused player, a test pattern
+//! without a clock, or `vivid` with its OSD text off) so the checksums
+//! repeat:
+//!
+//! ```text
+//! cargo run --release --example dmabuf_soak -- /dev/video0 3600
+//! ```
+
+use anyhow::{ensure, Result};
+use std::collections::HashMap;
+use std::time::Instant;
+
+use camera_box::capture::{IoMode, VideoCapture};
+
+/// FNV-1a over the frame bytes
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Frames seen per checksum in `frames` frames captured with `mode`
+fn capture(device: &str, mode: IoMode, frames: u32) -> Result<HashMap<u64, u32>> {
+    let mut capture = VideoCapture::open(device)?;
+    capture.set_io_mode(mode)?;
+    ensure!(
+        capture.io_mode() == mode,
+        "{} capture not available on {}",
+        mode.name(),
+        device
+    );
+
+    let mut checksums = HashMap::new();
+    let mut captured = 0;
+    let started = Instant::now();
+    while captured < frames {
+        if capture
+            .process_frame(|data, _info| *checksums.entry(checksum(data)).or_insert(0) += 1)?
+        {
+            captured += 1;
+        }
+    }
+    let secs = started.elapsed().as_secs_f64();
+    println!(
+        "{}: {} frames in {:.1} s ({:.2} fps), {} distinct",
+        mode.name(),
+        captured,
+        secs,
+        captured as f64 / secs,
+        checksums.len()
+    );
+    Ok(checksums)
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt().init();
+
+    let mut args = std::env::args().skip(1);
+    let device = args.next().unwrap_or_else(|| "/dev/video0".to_string());
+    let frames = match args.next() {
+        Some(frames) => frames.parse()?,
+        None => 600,
+    };
+
+    let mmap = capture(&device, IoMode::Mmap, frames)?;
+    let dmabuf = capture(&device, IoMode::Dmabuf, frames)?;
+
+    let mismatched: u32 = dmabuf
+        .iter()
+        .filter(|(sum, _)| !mmap.contains_key(sum))
+        .map(|(_, count)| count)
+        .sum();
+    println!(
+        "{} of {} DMA-BUF frames match no mmap frame",
+        mismatched, frames
+    );
+    ensure!(mismatched == 0, "DMA-BUF frames differ from mmap frames");
+    Ok(())
+}
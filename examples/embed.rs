@@ -0,0 +1,62 @@
+//! Embed the capture → NDI pipeline in another application
+//!
+//! Streams a capture device (or color bars with `--test-pattern`) to NDI for
+//! 30 seconds, printing pipeline events and a stats line every second:
+//!
+//! ```text
+//! cargo run --example embed -- /etc/camera-box/config.toml
+//! cargo run --example embed -- --test-pattern
+//! ```
+
+use anyhow::Result;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use camera_box::capture::FrameRate;
+use camera_box::config::Config;
+use camera_box::pipeline::Pipeline;
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt().init();
+
+    let mut test_pattern = false;
+    let mut config = Config::default();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--test-pattern" => test_pattern = true,
+            path => config = Config::load(path)?,
+        }
+    }
+
+    let mut builder = Pipeline::builder(config);
+    if test_pattern {
+        let rate = FrameRate {
+            numerator: 30000,
+            denominator: 1001,
+        };
+        builder = builder.test_pattern(1280, 720, rate);
+    }
+    let mut pipeline = builder.build()?;
+
+    let events = pipeline.subscribe();
+    pipeline.start()?;
+    let stats = pipeline.stats();
+
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while Instant::now() < deadline {
+        while let Ok(event) = events.try_recv() {
+            println!("event: {:?}", event);
+        }
+        println!(
+            "frames={} sent={} connections={} losses={}",
+            stats.frames_captured.load(Ordering::Relaxed),
+            stats.sender.frames_sent.load(Ordering::Relaxed),
+            stats.ndi_connections.load(Ordering::Relaxed),
+            stats.device_losses.load(Ordering::Relaxed),
+        );
+        std::thread::sleep(Duration::from_secs(1));
+    }
+
+    pipeline.stop();
+    Ok(())
+}
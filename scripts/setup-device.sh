@@ -218,7 +218,9 @@ After=network-online.target avahi-daemon.service
 Wants=network-online.target
 
 [Service]
-Type=simple
+# Ready once the first frame is captured; a slow boot may wait on devices
+Type=notify
+TimeoutStartSec=infinity
 ExecStart=/usr/local/bin/camera-box --display "STRIH-SNV (interkom)"
 Restart=always
 RestartSec=3
@@ -243,6 +245,7 @@ ProtectHome=yes
 PrivateTmp=yes
 ReadOnlyPaths=/
 ReadWritePaths=/dev /sys /run
+StateDirectory=camera-box
 
 # Allow access to video devices
 SupplementaryGroups=video
@@ -0,0 +1,194 @@
+//! Picture aspect ratio of the frames sent and shown
+//!
+//! NDI frames carry the shape the picture should be shown at, with 0
+//! meaning square pixels. Some SDI to USB converters deliver anamorphic
+//! frames such as 1440x1080 meant to be seen at 16:9, so `ndi.aspect`
+//! either names the ratio or, in auto mode, infers it from the frame size.
+//! The local display letterboxes received frames by the same rules, so the
+//! monitor and NDI receivers agree.
+
+use anyhow::Result;
+
+use crate::image_source::Letterbox;
+
+/// Anamorphic frame sizes and the display aspect they are meant for
+const ANAMORPHIC: [((u32, u32), f32); 4] = [
+    // HDV and XDCAM HD 1080
+    ((1440, 1080), 16.0 / 9.0),
+    // DVCPRO HD 1080
+    ((1280, 1080), 16.0 / 9.0),
+    // DVCPRO HD 720
+    ((960, 720), 16.0 / 9.0),
+    // Widescreen PAL
+    ((720, 576), 16.0 / 9.0),
+];
+
+/// Display aspect of a `width`x`height` frame known to be anamorphic
+pub fn anamorphic_aspect(width: u32, height: u32) -> Option<f32> {
+    ANAMORPHIC
+        .iter()
+        .find(|(size, _)| *size == (width, height))
+        .map(|&(_, aspect)| aspect)
+}
+
+/// The aspect ratio sent with each frame
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AspectRatio {
+    /// Inferred from anamorphic frame sizes, square pixels otherwise
+    #[default]
+    Auto,
+    /// Display width over height, e.g. 1.778 for 16:9
+    Fixed(f32),
+}
+
+impl AspectRatio {
+    /// "auto", a ratio such as "16:9" or "4:3", or a number such as "2.39"
+    pub fn from_name(name: &str) -> Result<Self> {
+        let name = name.trim();
+        if name.eq_ignore_ascii_case("auto") {
+            return Ok(AspectRatio::Auto);
+        }
+        let aspect = match name.split_once(':') {
+            Some((width, height)) => {
+                let width: f32 = width.trim().parse().unwrap_or(f32::NAN);
+                let height: f32 = height.trim().parse().unwrap_or(f32::NAN);
+                width / height
+            }
+            None => name.parse().unwrap_or(f32::NAN),
+        };
+        Self::from_ratio(aspect).map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid aspect ratio: {}. Use \"auto\", \"W:H\" (e.g. \"16:9\") or a number",
+                name
+            )
+        })
+    }
+
+    /// A display width over height
+    pub fn from_ratio(aspect: f32) -> Result<Self> {
+        anyhow::ensure!(
+            aspect.is_finite() && (0.1..=10.0).contains(&aspect),
+            "Aspect ratio {} out of range (0.1-10)",
+            aspect
+        );
+        Ok(AspectRatio::Fixed(aspect))
+    }
+
+    /// NDI's `picture_aspect_ratio` for a `width`x`height` frame: 0.0
+    /// (square pixels) unless fixed or inferred
+    pub fn picture_aspect_ratio(self, width: u32, height: u32) -> f32 {
+        match self {
+            AspectRatio::Auto => anamorphic_aspect(width, height).unwrap_or(0.0),
+            AspectRatio::Fixed(aspect) => aspect,
+        }
+    }
+}
+
+/// Shape a received `width`x`height` frame should be shown at: the
+/// sender's `picture_aspect_ratio` when set, else as [`AspectRatio::Auto`]
+/// would send it, else square pixels
+pub fn display_aspect(width: u32, height: u32, picture_aspect_ratio: f32) -> f32 {
+    if picture_aspect_ratio.is_finite() && picture_aspect_ratio > 0.0 {
+        return picture_aspect_ratio;
+    }
+    anamorphic_aspect(width, height).unwrap_or(width.max(1) as f32 / height.max(1) as f32)
+}
+
+/// Largest rectangle of `aspect` centred in a `width`x`height` screen.
+/// Like [`crate::image_source::letterbox`], `x` and the width of a
+/// pillarbox are even so UYVY pairs stay whole.
+pub fn fit(aspect: f32, width: u32, height: u32) -> Letterbox {
+    let (fit_w, fit_h) = if aspect >= width as f32 / height.max(1) as f32 {
+        // Wider than the screen: bars top and bottom
+        (
+            width,
+            ((width as f32 / aspect).round() as u32).clamp(1, height.max(1)),
+        )
+    } else {
+        // Narrower: bars left and right
+        (
+            ((height as f32 * aspect).round() as u32).clamp(2, width.max(2)) & !1,
+            height,
+        )
+    };
+    Letterbox {
+        x: (width.saturating_sub(fit_w) / 2) & !1,
+        y: height.saturating_sub(fit_h) / 2,
+        width: fit_w,
+        height: fit_h,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_aspect_ratio() {
+        assert_eq!(AspectRatio::from_name("auto").unwrap(), AspectRatio::Auto);
+        assert_eq!(AspectRatio::from_name("AUTO").unwrap(), AspectRatio::Auto);
+        assert_eq!(
+            AspectRatio::from_name("16:9").unwrap(),
+            AspectRatio::Fixed(16.0 / 9.0)
+        );
+        assert_eq!(
+            AspectRatio::from_name(" 4 : 3 ").unwrap(),
+            AspectRatio::Fixed(4.0 / 3.0)
+        );
+        assert_eq!(
+            AspectRatio::from_name("2.39").unwrap(),
+            AspectRatio::Fixed(2.39)
+        );
+        assert_eq!(
+            AspectRatio::from_name("1.85:1").unwrap(),
+            AspectRatio::Fixed(1.85)
+        );
+        for bad in [
+            "", "wide", "16:", ":9", "16:0", "0", "-1.5", "16/9", "100:1", "nan",
+        ] {
+            assert!(AspectRatio::from_name(bad).is_err(), "{:?}", bad);
+        }
+        assert!(AspectRatio::from_ratio(f32::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_anamorphic_inference() {
+        for (width, height) in [(1440, 1080), (1280, 1080), (960, 720), (720, 576)] {
+            assert_eq!(anamorphic_aspect(width, height), Some(16.0 / 9.0));
+        }
+        // Square pixel sizes keep NDI's default
+        for (width, height) in [(1920, 1080), (1280, 720), (3840, 2160), (640, 480)] {
+            assert_eq!(anamorphic_aspect(width, height), None);
+            assert_eq!(AspectRatio::Auto.picture_aspect_ratio(width, height), 0.0);
+        }
+        assert_eq!(
+            AspectRatio::Auto.picture_aspect_ratio(1440, 1080),
+            16.0 / 9.0
+        );
+        // A fixed ratio applies to every size
+        let fixed = AspectRatio::Fixed(4.0 / 3.0);
+        assert_eq!(fixed.picture_aspect_ratio(720, 576), 4.0 / 3.0);
+        assert_eq!(fixed.picture_aspect_ratio(1920, 1080), 4.0 / 3.0);
+    }
+
+    #[test]
+    fn test_display_aspect_and_fit() {
+        // The sender's ratio wins, then the table, then square pixels
+        assert_eq!(display_aspect(1440, 1080, 2.39), 2.39);
+        assert_eq!(display_aspect(1440, 1080, 0.0), 16.0 / 9.0);
+        assert_eq!(display_aspect(640, 480, 0.0), 4.0 / 3.0);
+        assert_eq!(display_aspect(0, 0, f32::NAN), 1.0);
+
+        let boxed = |x, y, width, height| Letterbox {
+            x,
+            y,
+            width,
+            height,
+        };
+        // Anamorphic 16:9 fills a 1080p monitor
+        assert_eq!(fit(16.0 / 9.0, 1920, 1080), boxed(0, 0, 1920, 1080));
+        // 4:3 is pillarboxed, 2.39:1 letterboxed
+        assert_eq!(fit(4.0 / 3.0, 1920, 1080), boxed(240, 0, 1440, 1080));
+        assert_eq!(fit(2.39, 1920, 1080), boxed(0, 138, 1920, 803));
+    }
+}
@@ -0,0 +1,414 @@
+//! Level meter for program audio
+//!
+//! Peak and RMS per channel of planar float audio, as NDI delivers it, for
+//! a "PGM" meter pair that shows program audio is alive without
+//! headphones. Only a meter, so it reads every `stride`-th sample: at most
+//! [`MAX_METER_RATE`] samples per second over all channels, whatever the
+//! channel count and rate. Levels fall by [`DECAY_DB_PER_SEC`] between
+//! audio frames, so the bars drop smoothly rather than blinking.
+//!
+//! [`MeterOverlay`] draws the levels in the bottom-right corner of the
+//! display; the NDI receiver feeds a [`ProgramMeter`] once it decodes audio.
+
+use std::time::Instant;
+
+use crate::compositor::{blend_over, Surface};
+
+/// Samples per second read over all channels
+pub const MAX_METER_RATE: u32 = 48_000;
+
+/// How fast the shown levels fall once the audio gets quieter
+pub const DECAY_DB_PER_SEC: f32 = 20.0;
+
+/// Lowest level shown; the bars are empty below it
+pub const METER_FLOOR_DB: f32 = -60.0;
+
+/// Width of one channel's bar
+const BAR_WIDTH: u32 = 12;
+/// Space around the bars
+const BAR_GAP: u32 = 4;
+const METER_HEIGHT: u32 = 96;
+/// Distance of the meter from the screen edges
+const METER_MARGIN: u32 = 16;
+const METER_BACKGROUND: [u8; 4] = [0, 0, 0, 160];
+/// RMS bar (green, BGRA)
+const RMS_COLOR: [u8; 4] = [0x40, 0xC0, 0x40, 0xFF];
+/// Peak line (white, red once it reaches [`CLIP_DB`])
+const PEAK_COLOR: [u8; 4] = [0xE6, 0xE6, 0xE6, 0xFF];
+const CLIP_COLOR: [u8; 4] = [0x30, 0x30, 0xFF, 0xFF];
+/// Peak level drawn as clipping
+const CLIP_DB: f32 = -1.0;
+
+/// Level of one channel as a fraction of full scale
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChannelLevel {
+    pub peak: f32,
+    pub rms: f32,
+}
+
+impl ChannelLevel {
+    /// Peak in dBFS (-inf for silence)
+    pub fn peak_dbfs(&self) -> f32 {
+        20.0 * self.peak.log10()
+    }
+
+    /// RMS in dBFS (-inf for silence)
+    pub fn rms_dbfs(&self) -> f32 {
+        20.0 * self.rms.log10()
+    }
+
+    /// Both levels lowered by `db`
+    fn decayed(self, db: f32) -> Self {
+        let gain = 10f32.powf(-db / 20.0);
+        Self {
+            peak: self.peak * gain,
+            rms: self.rms * gain,
+        }
+    }
+}
+
+/// Step between the samples read of each channel, so `channels` channels
+/// at `sample_rate` stay within [`MAX_METER_RATE`]
+pub fn meter_stride(sample_rate: u32, channels: usize) -> usize {
+    let total = sample_rate as u64 * channels as u64;
+    (total.div_ceil(MAX_METER_RATE as u64) as usize).max(1)
+}
+
+/// Peak and RMS of every `stride`-th sample of `samples`
+pub fn measure(samples: &[f32], stride: usize) -> ChannelLevel {
+    let mut peak = 0f32;
+    let mut sum = 0f32;
+    let mut count = 0usize;
+    for &sample in samples.iter().step_by(stride.max(1)) {
+        peak = peak.max(sample.abs());
+        sum += sample * sample;
+        count += 1;
+    }
+    if count == 0 {
+        return ChannelLevel::default();
+    }
+    ChannelLevel {
+        peak: peak.min(1.0),
+        rms: (sum / count as f32).sqrt().min(1.0),
+    }
+}
+
+/// Levels of the received audio, held and decaying between frames
+#[derive(Debug, Default)]
+pub struct ProgramMeter {
+    levels: Vec<ChannelLevel>,
+    updated: Option<Instant>,
+}
+
+impl ProgramMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Meter one audio frame: `channels` planes of `samples` samples,
+    /// each plane `channel_stride` samples after the previous one
+    pub fn feed(
+        &mut self,
+        planar: &[f32],
+        channels: usize,
+        samples: usize,
+        channel_stride: usize,
+        sample_rate: u32,
+        now: Instant,
+    ) {
+        let previous = self.levels(now);
+        let stride = meter_stride(sample_rate, channels);
+        self.levels = (0..channels)
+            .map(|channel| {
+                let start = (channel * channel_stride).min(planar.len());
+                let end = (start + samples).min(planar.len());
+                let level = measure(&planar[start..end], stride);
+                let held = previous.get(channel).copied().unwrap_or_default();
+                ChannelLevel {
+                    peak: level.peak.max(held.peak),
+                    rms: level.rms.max(held.rms),
+                }
+            })
+            .collect();
+        self.updated = Some(now);
+    }
+
+    /// Per-channel levels at `now`, decayed since the last frame; empty
+    /// before the first one
+    pub fn levels(&self, now: Instant) -> Vec<ChannelLevel> {
+        let Some(updated) = self.updated else {
+            return Vec::new();
+        };
+        let db = now.saturating_duration_since(updated).as_secs_f32() * DECAY_DB_PER_SEC;
+        self.levels.iter().map(|level| level.decayed(db)).collect()
+    }
+}
+
+/// Rows of a `height` bar lit by `dbfs`, from [`METER_FLOOR_DB`] to 0
+fn bar_rows(dbfs: f32, height: u32) -> u32 {
+    if dbfs.is_nan() || dbfs <= METER_FLOOR_DB {
+        return 0;
+    }
+    let fraction = (1.0 - dbfs / METER_FLOOR_DB).min(1.0);
+    (fraction * height as f32).round() as u32
+}
+
+/// "PGM" meter for the display: one bar per channel, filled to the RMS
+/// level with a line at the peak, on a translucent background
+pub struct MeterOverlay {
+    surface: Surface,
+    /// Whether the last levels had any channel, i.e. audio was metered
+    visible: bool,
+}
+
+impl MeterOverlay {
+    pub fn new() -> Self {
+        Self {
+            surface: Surface::new(BAR_GAP, METER_HEIGHT),
+            visible: false,
+        }
+    }
+
+    /// Render `levels`; no channels hides the meter
+    pub fn set_levels(&mut self, levels: &[ChannelLevel]) {
+        self.visible = !levels.is_empty();
+        if !self.visible {
+            return;
+        }
+        let width = BAR_GAP + levels.len() as u32 * (BAR_WIDTH + BAR_GAP);
+        if self.surface.width() != width {
+            self.surface = Surface::new(width, METER_HEIGHT);
+        }
+        self.surface.fill(METER_BACKGROUND);
+
+        let inner = METER_HEIGHT - 2 * BAR_GAP;
+        let bottom = (METER_HEIGHT - BAR_GAP) as usize;
+        let stride = width as usize * 4;
+        let data = self.surface.data_mut();
+        for (channel, level) in levels.iter().enumerate() {
+            let left = (BAR_GAP + channel as u32 * (BAR_WIDTH + BAR_GAP)) as usize;
+            let columns = left * 4..(left + BAR_WIDTH as usize) * 4;
+            let rms = bar_rows(level.rms_dbfs(), inner) as usize;
+            for y in bottom - rms..bottom {
+                for pixel in data[y * stride..][columns.clone()].chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&RMS_COLOR);
+                }
+            }
+            let peak = bar_rows(level.peak_dbfs(), inner) as usize;
+            if peak > 0 {
+                let color = if level.peak_dbfs() >= CLIP_DB {
+                    CLIP_COLOR
+                } else {
+                    PEAK_COLOR
+                };
+                let y = bottom - peak;
+                for pixel in data[y * stride..][columns].chunks_exact_mut(4) {
+                    pixel.copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Draw the meter in the bottom-right corner of the frame as it goes
+    /// to the screen
+    pub fn draw_output(&self, bgra: &mut [u8], width: u32, height: u32) {
+        if !self.visible {
+            return;
+        }
+        let meter_width = self.surface.width();
+        blend_over(
+            bgra,
+            self.surface.data(),
+            meter_width,
+            METER_HEIGHT,
+            width,
+            height,
+            width as i32 - (meter_width + METER_MARGIN) as i32,
+            height as i32 - (METER_HEIGHT + METER_MARGIN) as i32,
+            1.0,
+        );
+    }
+}
+
+impl Default for MeterOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// 997 Hz, a test tone that doesn't repeat on the sample grid
+    fn sine(sample_rate: u32, samples: usize, amplitude: f32) -> Vec<f32> {
+        (0..samples)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                amplitude * (2.0 * std::f32::consts::PI * 997.0 * t).sin()
+            })
+            .collect()
+    }
+
+    /// Deterministic white noise in -amplitude..amplitude
+    fn noise(samples: usize, amplitude: f32) -> Vec<f32> {
+        let mut state = 0x2545_f491u32;
+        (0..samples)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                amplitude * ((state >> 8) as f32 / (1u32 << 23) as f32 - 1.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_meter_stride() {
+        assert_eq!(meter_stride(48_000, 1), 1);
+        assert_eq!(meter_stride(48_000, 2), 2);
+        assert_eq!(meter_stride(44_100, 2), 2);
+        assert_eq!(meter_stride(96_000, 8), 16);
+        assert_eq!(meter_stride(48_000, 0), 1);
+    }
+
+    #[test]
+    fn test_strided_meter_matches_full_rate() {
+        // 100 ms of 8 channels at 48 kHz: every 8th sample is read
+        let stride = meter_stride(48_000, 8);
+        assert_eq!(stride, 8);
+        for (name, signal) in [
+            ("sine", sine(48_000, 4800, 0.5)),
+            ("quiet sine", sine(48_000, 4800, 0.01)),
+            ("noise", noise(4800, 0.25)),
+        ] {
+            let full = measure(&signal, 1);
+            let strided = measure(&signal, stride);
+            let peak_error = (full.peak_dbfs() - strided.peak_dbfs()).abs();
+            let rms_error = (full.rms_dbfs() - strided.rms_dbfs()).abs();
+            assert!(peak_error < 0.5, "{} peak off by {} dB", name, peak_error);
+            assert!(rms_error < 0.5, "{} rms off by {} dB", name, rms_error);
+        }
+
+        // A full-scale sine is -3 dB RMS either way
+        let level = measure(&sine(48_000, 4800, 1.0), stride);
+        assert!((level.rms_dbfs() + 3.01).abs() < 0.1);
+        assert_eq!(measure(&[], stride), ChannelLevel::default());
+        assert_eq!(measure(&[0.0; 16], stride).peak_dbfs(), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_program_meter_holds_and_decays() {
+        let t0 = Instant::now();
+        let mut meter = ProgramMeter::new();
+        assert!(meter.levels(t0).is_empty());
+
+        // Stereo planes with a channel stride past the samples
+        let (samples, channel_stride) = (960, 1024);
+        let mut planar = vec![0.0f32; channel_stride * 2];
+        planar[..samples].copy_from_slice(&sine(48_000, samples, 0.5));
+        meter.feed(&planar, 2, samples, channel_stride, 48_000, t0);
+        let levels = meter.levels(t0);
+        assert_eq!(levels.len(), 2);
+        assert!((levels[0].peak_dbfs() + 6.02).abs() < 0.5);
+        assert_eq!(levels[1], ChannelLevel::default());
+
+        // Half a second on, 10 dB lower
+        let later = meter.levels(t0 + Duration::from_millis(500));
+        assert!((levels[0].peak_dbfs() - later[0].peak_dbfs() - 10.0).abs() < 0.01);
+
+        // A quieter frame doesn't cut the bar short; a louder one lifts it
+        let quiet = sine(48_000, samples, 0.05).repeat(2);
+        meter.feed(
+            &quiet,
+            2,
+            samples,
+            samples,
+            48_000,
+            t0 + Duration::from_millis(100),
+        );
+        let held = meter.levels(t0 + Duration::from_millis(100))[0];
+        assert!((held.peak_dbfs() - (levels[0].peak_dbfs() - 2.0)).abs() < 0.01);
+        assert!(meter.levels(t0 + Duration::from_millis(100))[1].peak > 0.04);
+    }
+
+    #[test]
+    fn test_bar_rows() {
+        assert_eq!(bar_rows(0.0, 88), 88);
+        assert_eq!(bar_rows(3.0, 88), 88);
+        assert_eq!(bar_rows(-30.0, 88), 44);
+        assert_eq!(bar_rows(METER_FLOOR_DB, 88), 0);
+        assert_eq!(bar_rows(f32::NEG_INFINITY, 88), 0);
+        assert_eq!(bar_rows(f32::NAN, 88), 0);
+    }
+
+    #[test]
+    fn test_meter_overlay_bars() {
+        let mut overlay = MeterOverlay::new();
+        let levels = [
+            // -6 dB peak, -30 dB RMS
+            ChannelLevel {
+                peak: 0.5,
+                rms: 0.0316,
+            },
+            // Clipping
+            ChannelLevel {
+                peak: 1.0,
+                rms: 0.5,
+            },
+        ];
+        overlay.set_levels(&levels);
+        assert!(overlay.is_visible());
+        let width = overlay.surface.width();
+        assert_eq!(width, BAR_GAP + 2 * (BAR_WIDTH + BAR_GAP));
+
+        let data = overlay.surface.data();
+        let pixel = |x: u32, y: u32| {
+            let i = ((y * width + x) * 4) as usize;
+            [data[i], data[i + 1], data[i + 2], data[i + 3]]
+        };
+        let bottom = METER_HEIGHT - BAR_GAP - 1;
+        let inner = METER_HEIGHT - 2 * BAR_GAP;
+        // Left bar: RMS half way up, the peak line above it
+        assert_eq!(pixel(BAR_GAP, bottom), RMS_COLOR);
+        assert_eq!(pixel(BAR_GAP, bottom + 1 - inner / 2), RMS_COLOR);
+        assert_eq!(pixel(BAR_GAP, bottom - inner / 2 - 1), METER_BACKGROUND);
+        let peak = bar_rows(levels[0].peak_dbfs(), inner);
+        assert_eq!(pixel(BAR_GAP + 5, bottom + 1 - peak), PEAK_COLOR);
+        // Right bar peaks at the top, in red
+        assert_eq!(pixel(2 * BAR_GAP + BAR_WIDTH, BAR_GAP), CLIP_COLOR);
+        // Gaps stay background
+        assert_eq!(pixel(0, bottom), METER_BACKGROUND);
+        assert_eq!(pixel(BAR_GAP + BAR_WIDTH, bottom), METER_BACKGROUND);
+
+        overlay.set_levels(&[]);
+        assert!(!overlay.is_visible());
+    }
+
+    #[test]
+    fn test_meter_overlay_from_program_meter() {
+        let t0 = Instant::now();
+        let mut meter = ProgramMeter::new();
+        let mut overlay = MeterOverlay::new();
+        overlay.set_levels(&meter.levels(t0));
+        let mut bgra = vec![0u8; 320 * 240 * 4];
+        overlay.draw_output(&mut bgra, 320, 240);
+        assert!(bgra.iter().all(|&c| c == 0));
+
+        let tone = sine(48_000, 960, 1.0).repeat(2);
+        meter.feed(&tone, 2, 960, 960, 48_000, t0);
+        overlay.set_levels(&meter.levels(t0));
+        overlay.draw_output(&mut bgra, 320, 240);
+        // The bottom of the left bar sits above the bottom-right margin
+        let x = 320 - (overlay.surface.width() + METER_MARGIN) + BAR_GAP;
+        let y = 240 - METER_MARGIN - BAR_GAP - 1;
+        let i = ((y * 320 + x) * 4) as usize;
+        assert_eq!(bgra[i..i + 4], RMS_COLOR);
+        // Nothing above the meter
+        let top = 240 - METER_HEIGHT - METER_MARGIN;
+        assert!(bgra[..(top * 320 * 4) as usize].iter().all(|&c| c == 0));
+    }
+}
@@ -0,0 +1,158 @@
+//! Audio-only NDI streaming for intercom beltpacks
+//!
+//! Boxes deployed without a camera still advertise an NDI source so they
+//! show up in NDI tooling: the intercom mic (after gain and processing) is
+//! sent as NDI audio, plus a tiny black frame once per second for receivers
+//! that only list sources with video.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::capture::FrameRate;
+use crate::intercom;
+use crate::ndi::NdiSender;
+use crate::stats::{Component, StatsRegistry};
+use crate::threads;
+
+/// Mic chunks buffered between the intercom and the NDI thread (~1.4 s of
+/// 256-sample periods)
+pub const MIC_TAP_DEPTH: usize = 256;
+
+/// Delay between attempts to create the NDI sender
+const CREATE_RETRY: Duration = Duration::from_secs(5);
+
+/// Counters of the audio-only stream
+#[derive(Debug, Default)]
+pub struct AudioOnlyStats {
+    pub audio_frames: AtomicU64,
+    pub keepalive_frames: AtomicU64,
+    pub send_errors: AtomicU64,
+}
+
+impl AudioOnlyStats {
+    /// Report the counters as "audio_only"
+    pub fn register(self: &Arc<Self>, registry: &StatsRegistry) {
+        registry.register(
+            Component::new("audio_only")
+                .counter("frames", self, |s| &s.audio_frames)
+                .counter("keepalives", self, |s| &s.keepalive_frames)
+                .counter("send_errors", self, |s| &s.send_errors),
+        );
+    }
+}
+
+/// Channel the intercom copies its transmitted mic audio into
+/// (see `IntercomConfig::mic_tap`)
+pub fn mic_tap() -> (SyncSender<Vec<i16>>, Receiver<Vec<i16>>) {
+    sync_channel(MIC_TAP_DEPTH)
+}
+
+/// NDI sender thread streaming the mic tap
+pub struct AudioOnlyStream {
+    running: Arc<AtomicBool>,
+    stats: Arc<AudioOnlyStats>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl AudioOnlyStream {
+    /// Advertise `name` in `groups` and stream the mic audio from `mic`
+    pub fn start(name: &str, groups: Option<&str>, mic: Receiver<Vec<i16>>) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let stats = Arc::new(AudioOnlyStats::default());
+        let name = name.to_string();
+        let groups = groups.map(str::to_string);
+        let handle = {
+            let running = Arc::clone(&running);
+            let stats = Arc::clone(&stats);
+            threads::spawn(threads::AUDIO_ONLY, move || {
+                run(&name, groups.as_deref(), &mic, &running, &stats)
+            })
+        };
+        Self {
+            running,
+            stats,
+            handle: Some(handle),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<AudioOnlyStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Stop streaming and destroy the sender
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for AudioOnlyStream {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn run(
+    name: &str,
+    groups: Option<&str>,
+    mic: &Receiver<Vec<i16>>,
+    running: &AtomicBool,
+    stats: &AudioOnlyStats,
+) {
+    let rate = FrameRate {
+        numerator: 1,
+        denominator: 1,
+    };
+    let mut sender = loop {
+        if !running.load(Ordering::Relaxed) {
+            return;
+        }
+        match NdiSender::with_groups(name, rate, groups) {
+            Ok(sender) => break sender,
+            Err(e) => {
+                tracing::warn!("NDI audio sender unavailable: {:#} - retrying...", e);
+                let retry_at = Instant::now() + CREATE_RETRY;
+                while running.load(Ordering::Relaxed) && Instant::now() < retry_at {
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+    };
+    tracing::info!("NDI audio-only source '{}' started", name);
+
+    while running.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        match sender.send_keepalive_video(now) {
+            Ok(true) => {
+                stats.keepalive_frames.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(false) => {}
+            Err(e) => {
+                stats.send_errors.fetch_add(1, Ordering::Relaxed);
+                tracing::debug!("Keepalive frame failed: {:#}", e);
+            }
+        }
+        // Wake up for the next keepalive even while muted (no mic audio)
+        let wait = sender.keepalive_wait(now).max(Duration::from_millis(1));
+        match mic.recv_timeout(wait) {
+            Ok(samples) => match sender.send_audio(&samples, 1, intercom::SAMPLE_RATE, None) {
+                Ok(()) => {
+                    stats.audio_frames.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    stats.send_errors.fetch_add(1, Ordering::Relaxed);
+                    tracing::debug!("NDI audio send failed: {:#}", e);
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => {}
+            // The intercom is gone; keep the source listed until shutdown
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(wait),
+        }
+    }
+    tracing::info!("NDI audio-only source stopped");
+}
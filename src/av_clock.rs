@@ -0,0 +1,146 @@
+//! Shared clock for NDI audio and video timecodes
+//!
+//! Video frames and audio blocks are stamped by when they were captured,
+//! not when they are sent. Both run on the monotonic clock, which is mapped
+//! onto NDI's timecode epoch (100ns ticks since the Unix epoch) through one
+//! offset taken at startup, so a wall-clock step can't pull audio and video
+//! apart.
+//!
+//! An audio block's first sample was captured before the read returned by
+//! the samples still in the ALSA buffer (`snd_pcm_delay`) plus the block
+//! itself.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// NDI timecodes count 100ns ticks
+const TICK_NANOS: i128 = 100;
+
+/// Monotonic to NDI timecode mapping
+#[derive(Debug, Clone, Copy)]
+pub struct NdiClock {
+    epoch: Instant,
+    /// Timecode of `epoch`
+    epoch_timecode: i64,
+}
+
+impl NdiClock {
+    /// Clock on which `epoch` is the wall-clock time `wall`
+    pub fn new(epoch: Instant, wall: SystemTime) -> Self {
+        let since_unix = wall.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            epoch,
+            epoch_timecode: (since_unix.as_nanos() as i128 / TICK_NANOS) as i64,
+        }
+    }
+
+    /// The process-wide clock, anchored the first time it's used
+    pub fn global() -> &'static NdiClock {
+        static CLOCK: OnceLock<NdiClock> = OnceLock::new();
+        CLOCK.get_or_init(|| NdiClock::new(Instant::now(), SystemTime::now()))
+    }
+
+    /// NDI timecode of the monotonic instant `at`
+    pub fn timecode(&self, at: Instant) -> i64 {
+        let offset = match at.checked_duration_since(self.epoch) {
+            Some(after) => after.as_nanos() as i128,
+            None => -(self.epoch.duration_since(at).as_nanos() as i128),
+        };
+        self.epoch_timecode + (offset / TICK_NANOS) as i64
+    }
+}
+
+/// Duration of `frames` sample frames at `sample_rate`
+pub fn frames_duration(frames: u64, sample_rate: u32) -> Duration {
+    if sample_rate == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_nanos((frames as u128 * 1_000_000_000 / sample_rate as u128) as u64)
+}
+
+/// Capture time of the first sample of a block of `block_frames` read at
+/// `read_at`, while `delay_frames` more were still waiting in the ALSA
+/// buffer. A negative delay (reported around an xrun) counts as none.
+pub fn block_start(
+    read_at: Instant,
+    delay_frames: i64,
+    block_frames: u64,
+    sample_rate: u32,
+) -> Instant {
+    let behind = delay_frames.max(0) as u64 + block_frames;
+    let age = frames_duration(behind, sample_rate);
+    read_at.checked_sub(age).unwrap_or(read_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timecode_follows_monotonic_clock() {
+        let epoch = Instant::now();
+        let wall = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let clock = NdiClock::new(epoch, wall);
+        assert_eq!(clock.timecode(epoch), 17_000_000_000_000_000);
+        assert_eq!(
+            clock.timecode(epoch + Duration::from_millis(20)),
+            17_000_000_000_200_000
+        );
+        // Instants before the anchor map backwards too
+        let start = epoch + Duration::from_secs(1);
+        let clock = NdiClock::new(start, wall);
+        assert_eq!(clock.timecode(epoch), 17_000_000_000_000_000 - 10_000_000);
+    }
+
+    #[test]
+    fn test_frames_duration() {
+        assert_eq!(frames_duration(480, 48000), Duration::from_millis(10));
+        assert_eq!(frames_duration(1, 48000), Duration::from_nanos(20_833));
+        assert_eq!(frames_duration(441, 44100), Duration::from_millis(10));
+        assert_eq!(frames_duration(480, 0), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_block_start_compensates_alsa_delay() {
+        let start = Instant::now();
+        let read_at = start + Duration::from_secs(1);
+
+        // Nothing left in the buffer: the block spans the 10ms before the read
+        assert_eq!(
+            block_start(read_at, 0, 480, 48000),
+            read_at - Duration::from_millis(10)
+        );
+        // Two more periods waiting: the block is 20ms older still
+        assert_eq!(
+            block_start(read_at, 960, 480, 48000),
+            read_at - Duration::from_millis(30)
+        );
+        // A bogus negative delay is ignored
+        assert_eq!(
+            block_start(read_at, -240, 480, 48000),
+            read_at - Duration::from_millis(10)
+        );
+    }
+
+    #[test]
+    fn test_consecutive_blocks_are_contiguous() {
+        // Reads returning late by varying amounts still stamp blocks 10ms apart
+        // as long as the delay reflects what was left behind
+        let start = Instant::now() + Duration::from_secs(1);
+        let clock = NdiClock::new(start, UNIX_EPOCH + Duration::from_secs(100));
+        let mut previous = None;
+        for (i, late_frames) in [0u64, 240, 96, 480, 0].into_iter().enumerate() {
+            let end_of_block = start + frames_duration(480 * (i as u64 + 1), 48000);
+            let read_at = end_of_block + frames_duration(late_frames, 48000);
+            let stamped = clock.timecode(block_start(read_at, late_frames as i64, 480, 48000));
+            if let Some(previous) = previous {
+                assert!(
+                    (stamped - previous - 100_000).abs() <= 1,
+                    "{}",
+                    stamped - previous
+                );
+            }
+            previous = Some(stamped);
+        }
+    }
+}
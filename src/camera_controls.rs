@@ -0,0 +1,506 @@
+//! V4L2 camera controls and their persistence
+//!
+//! Capture dongles reset exposure and white balance to auto on every power
+//! cycle. Values changed through the control socket are written to a small
+//! TOML store keyed by device (card name + serial) and reapplied after the
+//! capture format is negotiated on the next start.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use v4l::control::{Control, Flags, Type, Value};
+use v4l::device::Device;
+
+/// Default control store location
+pub const CONTROLS_FILE: &str = "/var/lib/camera-box/controls.toml";
+
+/// Current store format version
+pub const STORE_VERSION: u32 = 1;
+
+/// sysfs directory with one entry per video device
+const SYSFS_VIDEO4LINUX: &str = "/sys/class/video4linux";
+
+/// Stable key for a control name: "Exposure, Absolute" -> "exposure_absolute"
+pub fn control_key(name: &str) -> String {
+    let mut key = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            key.push(c.to_ascii_lowercase());
+        } else if !key.is_empty() && !key.ends_with('_') {
+            key.push('_');
+        }
+    }
+    key.trim_end_matches('_').to_string()
+}
+
+// =============================================================================
+// Device controls
+// =============================================================================
+
+/// A writable integer, boolean or menu control
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CameraControl {
+    pub key: String,
+    pub id: u32,
+    pub value: i64,
+    pub min: i64,
+    pub max: i64,
+    pub step: u64,
+    pub default: i64,
+    pub boolean: bool,
+}
+
+impl CameraControl {
+    /// Clamp `value` into the control's range
+    pub fn clamp(&self, value: i64) -> i64 {
+        value.clamp(self.min, self.max)
+    }
+}
+
+/// Enumerate the writable scalar controls of a device with their current values
+pub fn list_controls(device: &Device) -> Result<Vec<CameraControl>> {
+    let descriptions = device
+        .query_controls()
+        .context("Failed to query controls")?;
+    let mut controls = Vec::new();
+    for desc in descriptions {
+        if !matches!(desc.typ, Type::Integer | Type::Boolean | Type::Menu) {
+            continue;
+        }
+        if desc.flags.intersects(Flags::DISABLED | Flags::READ_ONLY) {
+            continue;
+        }
+        let value = match device.control(desc.id) {
+            Ok(Control {
+                value: Value::Integer(v),
+                ..
+            }) => v,
+            Ok(Control {
+                value: Value::Boolean(b),
+                ..
+            }) => b as i64,
+            Ok(_) => continue,
+            Err(e) => {
+                tracing::debug!("Cannot read control {}: {}", desc.name, e);
+                continue;
+            }
+        };
+        controls.push(CameraControl {
+            key: control_key(&desc.name),
+            id: desc.id,
+            value,
+            min: desc.minimum,
+            max: desc.maximum,
+            step: desc.step,
+            default: desc.default,
+            boolean: desc.typ == Type::Boolean,
+        });
+    }
+    Ok(controls)
+}
+
+/// Write one control value
+pub fn set_control(device: &Device, control: &CameraControl, value: i64) -> Result<()> {
+    let value = if control.boolean {
+        Value::Boolean(value != 0)
+    } else {
+        Value::Integer(value)
+    };
+    device
+        .set_control(Control {
+            id: control.id,
+            value,
+        })
+        .with_context(|| format!("Failed to set control {}", control.key))
+}
+
+/// Look up a control by key and set it, returning the applied (clamped) value
+pub fn set_control_by_key(device: &Device, key: &str, value: i64) -> Result<i64> {
+    let controls = list_controls(device)?;
+    let control = controls
+        .iter()
+        .find(|c| c.key == key)
+        .ok_or_else(|| anyhow!("Unknown control: {}", key))?;
+    let value = control.clamp(value);
+    set_control(device, control, value)?;
+    Ok(value)
+}
+
+// =============================================================================
+// Device identity
+// =============================================================================
+
+/// Which physical device a set of saved controls belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub card: String,
+    pub serial: String,
+}
+
+impl DeviceIdentity {
+    /// Identify the device at `device_path` (e.g. /dev/video0)
+    pub fn of(device: &Device, device_path: &str) -> Result<Self> {
+        let caps = device
+            .query_caps()
+            .context("Failed to query capabilities")?;
+        let serial = usb_serial(Path::new(SYSFS_VIDEO4LINUX), device_path)
+            .unwrap_or_else(|| caps.bus.clone());
+        Ok(Self {
+            card: caps.card,
+            serial,
+        })
+    }
+}
+
+/// USB serial number of a video device from sysfs. The `device` link points
+/// at the USB interface; the serial lives on its parent USB device.
+pub fn usb_serial(sysfs_root: &Path, device_path: &str) -> Option<String> {
+    let node = Path::new(device_path).file_name()?;
+    let interface = std::fs::canonicalize(sysfs_root.join(node).join("device")).ok()?;
+    let serial = std::fs::read_to_string(interface.parent()?.join("serial")).ok()?;
+    let serial = serial.trim();
+    (!serial.is_empty()).then(|| serial.to_string())
+}
+
+// =============================================================================
+// Store
+// =============================================================================
+
+/// Saved control values for one device
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceControls {
+    pub card: String,
+    pub serial: String,
+    #[serde(default)]
+    pub controls: BTreeMap<String, i64>,
+}
+
+impl DeviceControls {
+    fn matches(&self, identity: &DeviceIdentity) -> bool {
+        self.card == identity.card && self.serial == identity.serial
+    }
+}
+
+/// Contents of the controls file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlStore {
+    pub version: u32,
+    #[serde(default)]
+    pub devices: Vec<DeviceControls>,
+}
+
+impl Default for ControlStore {
+    fn default() -> Self {
+        Self {
+            version: STORE_VERSION,
+            devices: Vec::new(),
+        }
+    }
+}
+
+impl ControlStore {
+    /// Load the store; a missing file is an empty store. A file written by a
+    /// newer version is ignored rather than misread.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let store: Self = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if store.version > STORE_VERSION {
+            tracing::warn!(
+                "{} has version {} (supported: {}), ignoring saved controls",
+                path.display(),
+                store.version,
+                STORE_VERSION
+            );
+            return Ok(Self::default());
+        }
+        Ok(Self {
+            version: STORE_VERSION,
+            ..store
+        })
+    }
+
+    /// Write the store atomically (temp file + rename), creating the directory
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let content = toml::to_string(self).context("Failed to serialize controls")?;
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, content)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Saved controls for a device, if any
+    pub fn get(&self, identity: &DeviceIdentity) -> Option<&BTreeMap<String, i64>> {
+        self.devices
+            .iter()
+            .find(|d| d.matches(identity))
+            .map(|d| &d.controls)
+    }
+
+    /// Merge `values` into the device's saved controls, keeping any other keys
+    pub fn merge(&mut self, identity: &DeviceIdentity, values: &BTreeMap<String, i64>) {
+        let index = match self.devices.iter().position(|d| d.matches(identity)) {
+            Some(index) => index,
+            None => {
+                self.devices.push(DeviceControls {
+                    card: identity.card.clone(),
+                    serial: identity.serial.clone(),
+                    controls: BTreeMap::new(),
+                });
+                self.devices.len() - 1
+            }
+        };
+        self.devices[index]
+            .controls
+            .extend(values.iter().map(|(k, v)| (k.clone(), *v)));
+    }
+}
+
+// =============================================================================
+// Restore
+// =============================================================================
+
+/// Result of matching saved values against the controls a device offers
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RestorePlan {
+    /// (control index, clamped value) to apply, auto/mode switches first
+    pub apply: Vec<(usize, i64)>,
+    /// Saved keys the device no longer offers
+    pub missing: Vec<String>,
+}
+
+/// Plan restoring `saved` onto `available`. Auto controls (exposure_auto,
+/// white_balance_temperature_auto, ...) go first: manual values are rejected
+/// while the matching auto mode is still on.
+pub fn plan_restore(saved: &BTreeMap<String, i64>, available: &[CameraControl]) -> RestorePlan {
+    let mut plan = RestorePlan::default();
+    for (key, &value) in saved {
+        match available.iter().position(|c| &c.key == key) {
+            Some(index) => plan.apply.push((index, available[index].clamp(value))),
+            None => plan.missing.push(key.clone()),
+        }
+    }
+    plan.apply
+        .sort_by_key(|&(index, _)| !available[index].key.contains("auto"));
+    plan
+}
+
+/// Reapply saved controls to the device at `device_path`. Returns the number
+/// of controls applied; an unknown device or empty store applies nothing.
+pub fn restore_saved(device_path: &str, store_path: &str) -> Result<usize> {
+    let store = ControlStore::load(store_path)?;
+    let device = Device::with_path(device_path)
+        .with_context(|| format!("Failed to open {}", device_path))?;
+    let identity = DeviceIdentity::of(&device, device_path)?;
+    let Some(saved) = store.get(&identity) else {
+        tracing::debug!(
+            "No saved controls for {} ({})",
+            identity.card,
+            identity.serial
+        );
+        return Ok(0);
+    };
+
+    let available = list_controls(&device)?;
+    let plan = plan_restore(saved, &available);
+    for key in &plan.missing {
+        tracing::warn!("Saved control {} no longer exists, skipping", key);
+    }
+    let mut applied = 0;
+    for &(index, value) in &plan.apply {
+        match set_control(&device, &available[index], value) {
+            Ok(()) => applied += 1,
+            Err(e) => tracing::warn!("{:#}", e),
+        }
+    }
+    tracing::info!("Restored {} camera controls for {}", applied, identity.card);
+    Ok(applied)
+}
+
+/// Set a control on the device and persist the applied value
+pub fn set_and_save(device_path: &str, store_path: &str, key: &str, value: i64) -> Result<i64> {
+    let device = Device::with_path(device_path)
+        .with_context(|| format!("Failed to open {}", device_path))?;
+    let value = set_control_by_key(&device, key, value)?;
+    if store_path.is_empty() {
+        return Ok(value);
+    }
+    let identity = DeviceIdentity::of(&device, device_path)?;
+    let mut store = ControlStore::load(store_path)?;
+    store.merge(&identity, &BTreeMap::from([(key.to_string(), value)]));
+    store.save(store_path)?;
+    Ok(value)
+}
+
+/// Current value of one control
+pub fn get_control(device_path: &str, key: &str) -> Result<CameraControl> {
+    let device = Device::with_path(device_path)
+        .with_context(|| format!("Failed to open {}", device_path))?;
+    list_controls(&device)?
+        .into_iter()
+        .find(|c| c.key == key)
+        .ok_or_else(|| anyhow!("Unknown control: {}", key))
+}
+
+/// All controls of the device at `device_path`
+pub fn device_controls(device_path: &str) -> Result<Vec<CameraControl>> {
+    let device = Device::with_path(device_path)
+        .with_context(|| format!("Failed to open {}", device_path))?;
+    let controls = list_controls(&device)?;
+    if controls.is_empty() {
+        bail!("{} has no adjustable controls", device_path);
+    }
+    Ok(controls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(serial: &str) -> DeviceIdentity {
+        DeviceIdentity {
+            card: "USB3 Video".to_string(),
+            serial: serial.to_string(),
+        }
+    }
+
+    fn control(key: &str, min: i64, max: i64) -> CameraControl {
+        CameraControl {
+            key: key.to_string(),
+            id: 0,
+            value: 0,
+            min,
+            max,
+            step: 1,
+            default: 0,
+            boolean: false,
+        }
+    }
+
+    #[test]
+    fn test_control_key() {
+        assert_eq!(control_key("Exposure, Absolute"), "exposure_absolute");
+        assert_eq!(
+            control_key("White Balance Temperature, Auto"),
+            "white_balance_temperature_auto"
+        );
+        assert_eq!(control_key("Brightness"), "brightness");
+        assert_eq!(control_key(" Gain (dB) "), "gain_db");
+    }
+
+    #[test]
+    fn test_load_missing_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ControlStore::load(dir.path().join("controls.toml")).unwrap();
+        assert_eq!(store, ControlStore::default());
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state/controls.toml");
+        let mut store = ControlStore::default();
+        store.merge(
+            &identity("A1"),
+            &BTreeMap::from([("exposure_absolute".to_string(), 250)]),
+        );
+        store.save(&path).unwrap();
+
+        let loaded = ControlStore::load(&path).unwrap();
+        assert_eq!(loaded, store);
+        assert_eq!(
+            loaded.get(&identity("A1")).unwrap()["exposure_absolute"],
+            250
+        );
+        assert!(loaded.get(&identity("B2")).is_none());
+        assert!(!path.with_extension("toml.tmp").exists());
+    }
+
+    #[test]
+    fn test_merge_keeps_other_keys_and_devices() {
+        let mut store = ControlStore::default();
+        store.merge(
+            &identity("A1"),
+            &BTreeMap::from([("brightness".to_string(), 10), ("gain".to_string(), 3)]),
+        );
+        store.merge(
+            &identity("B2"),
+            &BTreeMap::from([("brightness".to_string(), -5)]),
+        );
+        store.merge(
+            &identity("A1"),
+            &BTreeMap::from([("brightness".to_string(), 20)]),
+        );
+
+        assert_eq!(store.devices.len(), 2);
+        let a1 = store.get(&identity("A1")).unwrap();
+        assert_eq!(a1["brightness"], 20);
+        assert_eq!(a1["gain"], 3);
+        assert_eq!(store.get(&identity("B2")).unwrap()["brightness"], -5);
+    }
+
+    #[test]
+    fn test_load_newer_version_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("controls.toml");
+        std::fs::write(
+            &path,
+            "version = 99\n[[devices]]\ncard = \"x\"\nserial = \"y\"\n",
+        )
+        .unwrap();
+        assert_eq!(ControlStore::load(&path).unwrap(), ControlStore::default());
+
+        std::fs::write(&path, "not toml [").unwrap();
+        assert!(ControlStore::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_plan_restore_skips_missing_and_clamps() {
+        let available = vec![
+            control("exposure_absolute", 3, 2047),
+            control("brightness", -64, 64),
+            control("exposure_auto", 0, 3),
+        ];
+        let saved = BTreeMap::from([
+            ("brightness".to_string(), 100),
+            ("exposure_absolute".to_string(), 250),
+            ("exposure_auto".to_string(), 1),
+            ("focus_absolute".to_string(), 30),
+        ]);
+        let plan = plan_restore(&saved, &available);
+
+        assert_eq!(plan.missing, vec!["focus_absolute".to_string()]);
+        // Auto mode first, then the rest in key order
+        assert_eq!(plan.apply, vec![(2, 1), (1, 64), (0, 250)]);
+    }
+
+    #[test]
+    fn test_usb_serial_from_sysfs() {
+        let root = tempfile::tempdir().unwrap();
+        let usb_device = root.path().join("devices/usb1/1-1");
+        let interface = usb_device.join("1-1:1.0");
+        std::fs::create_dir_all(&interface).unwrap();
+        std::fs::write(usb_device.join("serial"), "CAFE1234\n").unwrap();
+        std::fs::create_dir_all(root.path().join("video0")).unwrap();
+        std::os::unix::fs::symlink(&interface, root.path().join("video0/device")).unwrap();
+
+        assert_eq!(
+            usb_serial(root.path(), "/dev/video0").as_deref(),
+            Some("CAFE1234")
+        );
+        assert_eq!(usb_serial(root.path(), "/dev/video1"), None);
+    }
+}
@@ -1,9 +1,124 @@
-use anyhow::{Context, Result};
+use anyhow::Context;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
 use v4l::buffer::Type;
-use v4l::io::mmap::Stream;
-use v4l::io::traits::CaptureStream;
+use v4l::v4l2::{self, vidioc};
 use v4l::video::Capture;
-use v4l::{Device, FourCC};
+use v4l::{v4l_sys, Device, FourCC};
+
+use crate::capture_dmabuf::DmabufStream;
+use crate::capture_mmap::MmapStream;
+use crate::capture_mplane::{self, MplaneStream, PixFormatMplane};
+use crate::color_range::{ColorRange, RangeMode};
+use crate::crop::{self, CropRect, SoftwareCrop};
+use crate::deinterlace::FieldOrder;
+use crate::probe::{self, DeviceReport};
+
+/// Longest wait for a frame in `process_frame`, so a wedged stream can't
+/// keep the capture loop from checking the running flag and stall watchdog
+pub const FRAME_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Why a capture device could not be opened or read
+#[derive(Debug, thiserror::Error)]
+pub enum CaptureError {
+    #[error("Capture device not found: {path}")]
+    DeviceNotFound { path: String },
+    /// The node exists but may not be opened (e.g. not in the video group)
+    #[error("Permission denied opening capture device {path}")]
+    PermissionDenied { path: String },
+    /// The driver delivers a format this capture path can't handle
+    #[error("Unsupported capture format: {fourcc}")]
+    UnsupportedFormat { fourcc: FourCC },
+    #[error("Capture stream not available")]
+    StreamUnavailable,
+    /// A device ioctl or read failed, e.g. because the device was unplugged
+    #[error("Capture device I/O failed")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl CaptureError {
+    /// Classify a failure to open the device node at `path`
+    pub fn open_failed(path: &str, error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => CaptureError::DeviceNotFound {
+                path: path.to_string(),
+            },
+            io::ErrorKind::PermissionDenied => CaptureError::PermissionDenied {
+                path: path.to_string(),
+            },
+            _ => anyhow::Error::new(error)
+                .context(format!("Failed to open device: {}", path))
+                .into(),
+        }
+    }
+}
+
+/// Maximum image planes tracked per frame (Y, U/UV, V)
+pub const MAX_FRAME_PLANES: usize = 3;
+
+/// Byte offsets of each image plane within a frame buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaneOffsets {
+    offsets: [usize; MAX_FRAME_PLANES],
+    count: usize,
+}
+
+impl PlaneOffsets {
+    /// One plane starting at the beginning of the buffer
+    pub fn single() -> Self {
+        Self {
+            offsets: [0; MAX_FRAME_PLANES],
+            count: 1,
+        }
+    }
+
+    /// Planes of `fourcc` packed in one buffer with luma stride `stride`
+    pub fn packed(fourcc: FourCC, stride: u32, height: u32) -> Self {
+        let luma = stride as usize * height as usize;
+        match &fourcc.repr {
+            b"NV12" | b"NV21" | b"NV16" | b"NV61" => Self::from_offsets(&[0, luma]),
+            b"YU12" | b"YV12" => Self::from_offsets(&[0, luma, luma + luma / 4]),
+            _ => Self::single(),
+        }
+    }
+
+    /// Offsets of planes with the given lengths laid end to end
+    pub fn stitched(lengths: &[usize]) -> Self {
+        let mut offsets = [0; MAX_FRAME_PLANES];
+        let mut at = 0;
+        for (offset, length) in offsets.iter_mut().zip(lengths) {
+            *offset = at;
+            at += length;
+        }
+        Self {
+            offsets,
+            count: lengths.len().clamp(1, MAX_FRAME_PLANES),
+        }
+    }
+
+    fn from_offsets(offsets: &[usize]) -> Self {
+        let mut planes = Self::single();
+        planes.offsets[..offsets.len()].copy_from_slice(offsets);
+        planes.count = offsets.len();
+        planes
+    }
+
+    /// Offset of `plane`, if the frame has it
+    pub fn get(&self, plane: usize) -> Option<usize> {
+        (plane < self.count).then(|| self.offsets[plane])
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
 
 /// Video frame metadata (data passed separately as zero-copy reference)
 #[derive(Clone, Copy)]
@@ -12,6 +127,55 @@ pub struct FrameInfo {
     pub height: u32,
     pub fourcc: FourCC,
     pub stride: u32,
+    pub planes: PlaneOffsets,
+    /// Quantization range of YUV samples
+    pub range: ColorRange,
+    /// When the frame was dequeued; stamps its NDI timecode
+    pub captured: Option<Instant>,
+    /// The buffer stays valid and untouched until the callback for the
+    /// source's next frame has returned, so it may be lent to an
+    /// asynchronous send
+    pub held: bool,
+    /// The buffer is a DMA-BUF mapping that may be uncached, which
+    /// converters read with streaming loads
+    pub uncached: bool,
+}
+
+impl FrameInfo {
+    /// Frame info for a single-buffer layout (planes packed after each other)
+    pub fn new(width: u32, height: u32, fourcc: FourCC, stride: u32) -> Self {
+        Self {
+            width,
+            height,
+            fourcc,
+            stride,
+            planes: PlaneOffsets::packed(fourcc, stride, height),
+            range: ColorRange::Limited,
+            captured: None,
+            held: false,
+            uncached: false,
+        }
+    }
+
+    pub fn with_range(mut self, range: ColorRange) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn with_captured(mut self, at: Instant) -> Self {
+        self.captured = Some(at);
+        self
+    }
+
+    pub fn with_held(mut self, held: bool) -> Self {
+        self.held = held;
+        self
+    }
+
+    pub fn with_uncached(mut self, uncached: bool) -> Self {
+        self.uncached = uncached;
+        self
+    }
 }
 
 /// Video frame data with metadata (for compatibility, still used for owned data)
@@ -24,7 +188,7 @@ pub struct Frame {
 }
 
 /// Frame rate as numerator/denominator
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FrameRate {
     pub numerator: u32,
     pub denominator: u32,
@@ -40,38 +204,283 @@ impl Default for FrameRate {
     }
 }
 
+impl FrameRate {
+    /// Parse "60", "59.94" or "60000/1001". A decimal within 0.01 of an
+    /// NTSC rate (N*1000/1001) is that rate exactly.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let text = text.trim();
+        let rate = match text.split_once('/') {
+            Some((numerator, denominator)) => Self {
+                numerator: numerator.trim().parse()?,
+                denominator: denominator.trim().parse()?,
+            },
+            None => {
+                let fps: f64 = text.parse()?;
+                anyhow::ensure!(fps.is_finite(), "{:?} is not a frame rate", text);
+                let ntsc = (fps * 1.001).round();
+                if fps.fract() == 0.0 {
+                    Self {
+                        numerator: fps as u32,
+                        denominator: 1,
+                    }
+                } else if ntsc >= 1.0 && (ntsc * 1000.0 / 1001.0 - fps).abs() < 0.01 {
+                    Self {
+                        numerator: ntsc as u32 * 1000,
+                        denominator: 1001,
+                    }
+                } else {
+                    let millis = (fps * 1000.0).round() as u32;
+                    let divisor = gcd(millis, 1000);
+                    Self {
+                        numerator: millis / divisor,
+                        denominator: 1000 / divisor,
+                    }
+                }
+            }
+        };
+        anyhow::ensure!(
+            rate.numerator > 0 && rate.denominator > 0,
+            "{:?} is not a frame rate",
+            text
+        );
+        Ok(rate)
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        self.numerator as f64 / self.denominator.max(1) as f64
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a.max(1)
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// How capture buffers reach the process (`capture.io_mode`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IoMode {
+    /// Buffers mapped through the device node
+    #[default]
+    Mmap,
+    /// Buffers exported as DMA-BUFs and mapped once; falls back to mmap
+    /// when the driver can't export
+    Dmabuf,
+}
+
+impl IoMode {
+    /// Parse a mode name from configuration ("mmap", "dmabuf")
+    pub fn from_name(name: &str) -> anyhow::Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "mmap" => Ok(IoMode::Mmap),
+            "dmabuf" => Ok(IoMode::Dmabuf),
+            other => anyhow::bail!("Unsupported I/O mode: {}. Supported: mmap, dmabuf", other),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IoMode::Mmap => "mmap",
+            IoMode::Dmabuf => "dmabuf",
+        }
+    }
+}
+
+/// Buffer streaming backend for the device's capture API
+enum StreamBackend {
+    Single(SingleStream),
+    Multi(MplaneStream),
+    Dmabuf(DmabufStream),
+}
+
+/// Single-planar mmap stream. The device is kept so the buffers can be
+/// handed over to a DMA-BUF stream before the first frame.
+struct SingleStream {
+    device: Device,
+    stream: Option<MmapStream>,
+}
+
+impl SingleStream {
+    fn new(device: Device) -> Result<Self, CaptureError> {
+        let mut single = Self {
+            device,
+            stream: None,
+        };
+        single.create_stream()?;
+        Ok(single)
+    }
+
+    fn create_stream(&mut self) -> Result<(), CaptureError> {
+        // Release the old buffers before requesting new ones
+        drop(self.stream.take());
+
+        // Create memory-mapped stream with enough buffers to avoid frame drops
+        // 4 buffers to handle processing time variance, one of them held
+        let stream =
+            MmapStream::new(self.device.handle(), 4).context("Failed to create capture stream")?;
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn stream(&mut self) -> Result<&mut MmapStream, CaptureError> {
+        self.stream.as_mut().ok_or(CaptureError::StreamUnavailable)
+    }
+}
+
+/// Y'CbCr encoding of the current single-planar capture format, which the
+/// v4l crate's `Format` doesn't carry (0, the default, if the query fails)
+fn ycbcr_encoding(device: &Device) -> u32 {
+    let mut format: v4l_sys::v4l2_format = unsafe { std::mem::zeroed() };
+    format.type_ = Type::VideoCapture as u32;
+    let result = unsafe {
+        v4l2::ioctl(
+            device.handle().fd(),
+            vidioc::VIDIOC_G_FMT,
+            &mut format as *mut v4l_sys::v4l2_format as *mut std::ffi::c_void,
+        )
+    };
+    match result {
+        Ok(()) => unsafe { format.fmt.pix.__bindgen_anon_1.ycbcr_enc },
+        Err(_) => 0,
+    }
+}
+
+/// Wait up to `timeout` for `fd` to become readable (or report an error
+/// condition, which the following read surfaces). Returns false on timeout.
+pub fn wait_readable(fd: RawFd, timeout: Duration) -> io::Result<bool> {
+    let timeout_ms = timeout.as_millis().min(i32::MAX as u128) as i32;
+    loop {
+        let mut pfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut pfd, 1, timeout_ms) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Ok(false),
+            _ => return Ok(true),
+        }
+    }
+}
+
 /// V4L2 video capture wrapper
 pub struct VideoCapture {
-    stream: Stream<'static>,
+    stream: StreamBackend,
     width: u32,
     height: u32,
     fourcc: FourCC,
     stride: u32,
     frame_rate: FrameRate,
+    field_order: FieldOrder,
+    /// Range the driver reports for the negotiated format
+    detected_range: ColorRange,
+    /// Range frames are tagged with, after any `capture.range` override
+    range: ColorRange,
+    /// Crop applied to each frame when the driver can't crop
+    crop: Option<SoftwareCrop>,
 }
 
 impl VideoCapture {
-    /// Open capture device and start streaming at 1920x1080 @ 60fps
-    pub fn open(device_path: &str) -> Result<Self> {
+    /// Enumerate the modes a capture device offers without configuring it,
+    /// with the selection aiming at `target_rate` among `format_priority`
+    pub fn probe(
+        device_path: &str,
+        target_rate: FrameRate,
+        format_priority: &[FourCC],
+    ) -> Result<DeviceReport, CaptureError> {
+        let device = Device::with_path(device_path)
+            .map_err(|e| CaptureError::open_failed(device_path, e))?;
+        Ok(DeviceReport::enumerate(
+            &device,
+            device_path,
+            target_rate,
+            format_priority,
+        )?)
+    }
+
+    /// Open capture device and start streaming in the mode picked from its
+    /// enumerated formats (see [`DeviceReport::select`])
+    pub fn open(device_path: &str) -> Result<Self, CaptureError> {
+        Self::open_with_crop(
+            device_path,
+            None,
+            probe::TARGET_RATE,
+            &probe::default_format_priority(),
+        )
+    }
+
+    /// Open like [`VideoCapture::open`], delivering only `crop` of each frame
+    /// at the offered rate closest to `target_rate` (see
+    /// [`probe::best_rate`]) in the first format of `format_priority` that
+    /// gets there. The driver crops when it supports the selection API;
+    /// otherwise frames are cropped in software.
+    pub fn open_with_crop(
+        device_path: &str,
+        crop: Option<CropRect>,
+        target_rate: FrameRate,
+        format_priority: &[FourCC],
+    ) -> Result<Self, CaptureError> {
         tracing::info!("Opening capture device: {}", device_path);
 
         let device = Device::with_path(device_path)
-            .with_context(|| format!("Failed to open device: {}", device_path))?;
+            .map_err(|e| CaptureError::open_failed(device_path, e))?;
 
         // Query device capabilities
         let caps = device.query_caps()?;
         tracing::info!("Device: {} ({})", caps.card, caps.driver);
 
+        let flags = caps.capabilities;
+        if !flags.contains(v4l::capability::Flags::VIDEO_CAPTURE)
+            && flags.contains(v4l::capability::Flags::VIDEO_CAPTURE_MPLANE)
+        {
+            return Self::open_mplane(&device, crop, target_rate);
+        }
+
+        let report = DeviceReport::enumerate(&device, device_path, target_rate, format_priority)?;
+        let selected = report.select();
+
         // Get current format as starting point
         let mut format = Capture::format(&device)?;
+        match selected {
+            Some(mode) => {
+                tracing::info!(
+                    "Selected {} {}x{} @ {:.2} fps of {} offered modes",
+                    mode.fourcc,
+                    mode.width,
+                    mode.height,
+                    mode.rate.as_f64(),
+                    report.modes.len()
+                );
+                format.width = mode.width;
+                format.height = mode.height;
+                format.fourcc = mode.fourcc;
+            }
+            None => {
+                // Nothing enumerable (or convertible): ask for 1920x1080 YUYV
+                // and let the driver adjust
+                tracing::warn!(
+                    "No usable modes enumerated ({} offered), requesting 1920x1080 YUYV",
+                    report.modes.len()
+                );
+                format.width = probe::TARGET_WIDTH;
+                format.height = probe::TARGET_HEIGHT;
+                format.fourcc = FourCC::new(b"YUYV");
+            }
+        }
 
-        // Set 1920x1080 YUYV (best for NDI conversion)
-        format.width = 1920;
-        format.height = 1080;
-        format.fourcc = FourCC::new(b"YUYV");
-
-        let final_format =
-            Capture::set_format(&device, &format).context("Failed to set 1920x1080 YUYV format")?;
+        let final_format = Capture::set_format(&device, &format).with_context(|| {
+            format!(
+                "Failed to set {}x{} {} format",
+                format.width, format.height, format.fourcc
+            )
+        })?;
 
         tracing::info!(
             "Capture format: {}x{} {} (stride: {})",
@@ -81,96 +490,332 @@ impl VideoCapture {
             final_format.stride
         );
 
-        let width = final_format.width;
-        let height = final_format.height;
+        let mut width = final_format.width;
+        let mut height = final_format.height;
         let fourcc = final_format.fourcc;
-        let stride = final_format.stride;
+        let mut stride = final_format.stride;
+        let field_order = FieldOrder::from_v4l(final_format.field_order, height);
+        if field_order.is_interlaced() {
+            tracing::info!(
+                "Interlaced source: {} ({:?})",
+                final_format.field_order,
+                field_order
+            );
+        }
+
+        let range = ColorRange::from_v4l(
+            final_format.colorspace as u32,
+            ycbcr_encoding(&device),
+            final_format.quantization as u32,
+        );
+        tracing::info!(
+            "Color range: {:?} (colorspace {}, quantization {})",
+            range,
+            final_format.colorspace,
+            final_format.quantization
+        );
+
+        // Request the selected interval and report what the driver accepted
+        let requested = selected.map_or(target_rate, |mode| mode.rate);
+        let frame_rate = Capture::params(&device)
+            .and_then(|mut params| {
+                params.interval.numerator = requested.denominator;
+                params.interval.denominator = requested.numerator;
+                Capture::set_params(&device, &params)
+            })
+            .ok()
+            .filter(|params| params.interval.numerator > 0 && params.interval.denominator > 0)
+            .map_or(requested, |params| FrameRate {
+                numerator: params.interval.denominator,
+                denominator: params.interval.numerator,
+            });
+        tracing::info!(
+            "Frame rate: {}/{} fps",
+            frame_rate.numerator,
+            frame_rate.denominator
+        );
 
-        // Set 60fps
-        if let Ok(mut params) = Capture::params(&device) {
-            params.interval.numerator = 1;
-            params.interval.denominator = 60;
-            let _ = Capture::set_params(&device, &params);
+        let mut software_crop = None;
+        if let Some(requested) = crop {
+            let rect = requested.fit(width, height)?;
+            let cropped = crop::set_selection(device.handle().fd(), rect)
+                .ok()
+                .filter(|applied| *applied == rect)
+                .and_then(|_| Capture::format(&device).ok())
+                .filter(|format| format.width == rect.width && format.height == rect.height);
+            match cropped {
+                Some(format) => {
+                    tracing::info!("Driver crop: {}", rect);
+                    (width, height, stride) = (format.width, format.height, format.stride);
+                }
+                None => {
+                    // The driver may have taken the crop but scaled it back up
+                    let _ = crop::set_selection(
+                        device.handle().fd(),
+                        CropRect::full(final_format.width, final_format.height),
+                    );
+                    let software = SoftwareCrop::new(rect, fourcc, stride)
+                        .map_err(|_| CaptureError::UnsupportedFormat { fourcc })?;
+                    tracing::info!("Software crop: {}", rect);
+                    (width, height, stride) = (rect.width, rect.height, software.stride());
+                    software_crop = Some(software);
+                }
+            }
         }
 
-        // Fixed frame rate: 60fps
-        let frame_rate = FrameRate {
-            numerator: 60,
-            denominator: 1,
-        };
-        tracing::info!("Frame rate: 60 fps");
+        let stream = SingleStream::new(device)?;
 
-        // Create memory-mapped stream with enough buffers to avoid frame drops
-        // 4 buffers to handle processing time variance
-        let stream = Stream::with_buffers(&device, Type::VideoCapture, 4)
-            .context("Failed to create capture stream")?;
+        Ok(Self {
+            stream: StreamBackend::Single(stream),
+            width,
+            height,
+            fourcc,
+            stride,
+            frame_rate,
+            field_order,
+            detected_range: range,
+            range,
+            crop: software_crop,
+        })
+    }
+
+    /// Open a device that only offers the multi-planar capture API. Crops
+    /// are always done in software here.
+    fn open_mplane(
+        device: &Device,
+        crop: Option<CropRect>,
+        target_rate: FrameRate,
+    ) -> Result<Self, CaptureError> {
+        let handle = device.handle();
+        let format = capture_mplane::set_format(
+            &handle,
+            PixFormatMplane::new(1920, 1080, FourCC::new(b"YUYV")),
+        )
+        .context("Failed to set 1920x1080 YUYV format")?;
+
+        let mut width = format.width;
+        let mut height = format.height;
+        let fourcc = format.fourcc();
+        let mut stride = format.stride();
+        tracing::info!(
+            "Capture format (MPLANE): {}x{} {} (stride: {}, planes: {})",
+            width,
+            height,
+            fourcc,
+            stride,
+            format.plane_count()
+        );
+
+        let field_order = v4l::format::FieldOrder::try_from(format.field)
+            .map_or(FieldOrder::Progressive, |field| {
+                FieldOrder::from_v4l(field, height)
+            });
+        if field_order.is_interlaced() {
+            tracing::info!("Interlaced source: {:?}", field_order);
+        }
+        let range = ColorRange::from_v4l(
+            format.colorspace,
+            format.ycbcr_enc as u32,
+            format.quantization as u32,
+        );
+        tracing::info!("Color range: {:?}", range);
+
+        // Ask for the best interval the driver lists for this format and size
+        let offered: Vec<FrameRate> =
+            Capture::enum_frameintervals(device, fourcc, format.width, format.height)
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(|interval| probe::frame_rates(interval.interval, target_rate))
+                .collect();
+        let requested = probe::best_rate(&offered, target_rate).unwrap_or(target_rate);
+        // Use the rate the driver settled on, which may not be the one asked for
+        let frame_rate = capture_mplane::set_frame_rate(&handle, requested)
+            .or_else(|e| {
+                tracing::debug!("{:#}", e);
+                capture_mplane::frame_rate(&handle)
+            })
+            .unwrap_or_else(|e| {
+                tracing::warn!("Unknown capture frame rate: {:#}", e);
+                requested
+            });
+        tracing::info!(
+            "Frame rate: {}/{} fps ({} offered)",
+            frame_rate.numerator,
+            frame_rate.denominator,
+            offered.len()
+        );
+
+        let software_crop = match crop {
+            Some(requested) => {
+                let rect = requested.fit(width, height)?;
+                let software = SoftwareCrop::new(rect, fourcc, stride)
+                    .map_err(|_| CaptureError::UnsupportedFormat { fourcc })?;
+                tracing::info!("Software crop: {}", rect);
+                (width, height, stride) = (rect.width, rect.height, software.stride());
+                Some(software)
+            }
+            None => None,
+        };
 
-        // Leak the device to get 'static lifetime (it lives for program duration)
-        let stream = unsafe { std::mem::transmute::<Stream<'_>, Stream<'static>>(stream) };
+        let stream = MplaneStream::new(handle, &format, 4)?;
 
         Ok(Self {
-            stream,
+            stream: StreamBackend::Multi(stream),
             width,
             height,
             fourcc,
             stride,
             frame_rate,
+            field_order,
+            detected_range: range,
+            range,
+            crop: software_crop,
         })
     }
 
-    /// Capture next frame (blocking) - COPIES DATA
+    /// Capture next frame - COPIES DATA. Returns None if no frame arrived
+    /// within `timeout`.
     #[allow(dead_code)]
-    pub fn next_frame(&mut self) -> Result<Frame> {
-        let (buffer, _metadata) = self.stream.next()?;
-
+    pub fn next_frame_timeout(&mut self, timeout: Duration) -> Result<Option<Frame>, CaptureError> {
         // Copy frame data (zero-copy would require unsafe lifetime tricks)
-        let data = buffer.to_vec();
+        let mut data = Vec::new();
+        if !self.process_frame_timeout(timeout, |buffer, _info| data = buffer.to_vec())? {
+            return Ok(None);
+        }
 
-        Ok(Frame {
+        Ok(Some(Frame {
             data,
             width: self.width,
             height: self.height,
             fourcc: self.fourcc,
             stride: self.stride,
-        })
+        }))
     }
 
-    /// Process next frame with zero-copy callback (FAST PATH)
-    /// The callback receives a direct reference to the mmap buffer - no copying!
-    /// Buffer is automatically requeued after callback returns.
+    /// Process next frame with zero-copy callback (FAST PATH), waiting at
+    /// most `FRAME_TIMEOUT`. Returns false if no frame arrived in time.
     #[inline]
-    pub fn process_frame<F>(&mut self, mut callback: F) -> Result<()>
+    pub fn process_frame<F>(&mut self, callback: F) -> Result<bool, CaptureError>
     where
         F: FnMut(&[u8], FrameInfo),
     {
-        let (buffer, _metadata) = self.stream.next()?;
+        self.process_frame_timeout(FRAME_TIMEOUT, callback)
+    }
 
-        let info = FrameInfo {
-            width: self.width,
-            height: self.height,
-            fourcc: self.fourcc,
-            stride: self.stride,
-        };
+    /// Process next frame with zero-copy callback, waiting at most `timeout`.
+    /// The callback receives a direct reference to the mmap buffer - no copying!
+    /// Buffer is requeued after the next frame's callback returns. Returns false
+    /// if no frame arrived in time; poll returns at once while frames flow.
+    #[inline]
+    pub fn process_frame_timeout<F>(
+        &mut self,
+        timeout: Duration,
+        mut callback: F,
+    ) -> Result<bool, CaptureError>
+    where
+        F: FnMut(&[u8], FrameInfo),
+    {
+        let info = self.frame_info();
+        let crop = &mut self.crop;
+        match &mut self.stream {
+            StreamBackend::Single(single) => {
+                // Zero-copy: pass buffer slice directly to callback. The
+                // buffer is requeued after the next frame's callback, so it
+                // is only held when the callback gets it uncropped.
+                let info = info.with_held(crop.is_none());
+                Ok(single.stream()?.process(timeout, |buffer| match crop {
+                    Some(crop) => callback(crop.apply(buffer), info),
+                    None => callback(buffer, info),
+                })?)
+            }
+            StreamBackend::Multi(stream) => {
+                if !wait_readable(stream.fd(), timeout)? {
+                    return Ok(false);
+                }
+                stream.process(|buffer, planes| match crop {
+                    Some(crop) => callback(crop.apply(buffer), info),
+                    None => callback(buffer, FrameInfo { planes, ..info }),
+                })?;
+                Ok(true)
+            }
+            StreamBackend::Dmabuf(stream) => {
+                let info = info.with_held(crop.is_none()).with_uncached(true);
+                Ok(stream.process(timeout, |buffer| match crop {
+                    Some(crop) => callback(crop.apply(buffer), info),
+                    None => callback(buffer, info),
+                })?)
+            }
+        }
+    }
 
-        // Zero-copy: pass buffer slice directly to callback
-        #[allow(clippy::needless_borrow)]
-        callback(&buffer, info);
+    /// Dequeue and requeue a frame that is already waiting without passing
+    /// it on (no crop, no plane stitching). A held buffer stays held.
+    /// Returns false if none was ready.
+    pub fn skip_frame(&mut self) -> Result<bool, CaptureError> {
+        match &mut self.stream {
+            StreamBackend::Single(single) => Ok(single.stream()?.skip()?),
+            StreamBackend::Multi(stream) => {
+                if !wait_readable(stream.fd(), Duration::ZERO)? {
+                    return Ok(false);
+                }
+                stream.skip()?;
+                Ok(true)
+            }
+            StreamBackend::Dmabuf(stream) => Ok(stream.skip()?),
+        }
+    }
 
-        // Buffer automatically requeued when it goes out of scope
+    /// Switch to `mode` before the first frame. DMA-BUF capture falls back
+    /// to mmap when the driver can't export its buffers; multi-planar
+    /// devices always use mmap.
+    pub fn set_io_mode(&mut self, mode: IoMode) -> Result<(), CaptureError> {
+        if mode != IoMode::Dmabuf {
+            return Ok(());
+        }
+        let StreamBackend::Single(single) = &mut self.stream else {
+            if matches!(self.stream, StreamBackend::Multi(_)) {
+                tracing::warn!("DMA-BUF capture not supported for MPLANE devices, using mmap");
+            }
+            return Ok(());
+        };
+        // The device has one set of buffers: free the mmap ones first
+        drop(single.stream.take());
+        match DmabufStream::new(single.device.handle(), 4) {
+            Ok(stream) => self.stream = StreamBackend::Dmabuf(stream),
+            Err(e) => {
+                tracing::warn!("DMA-BUF capture unavailable, using mmap: {:#}", e);
+                single.create_stream()?;
+            }
+        }
         Ok(())
     }
 
+    /// Buffer I/O mode in use
+    pub fn io_mode(&self) -> IoMode {
+        match self.stream {
+            StreamBackend::Dmabuf(_) => IoMode::Dmabuf,
+            StreamBackend::Single(_) | StreamBackend::Multi(_) => IoMode::Mmap,
+        }
+    }
+
     /// Get frame info without capturing
-    #[allow(dead_code)]
     pub fn frame_info(&self) -> FrameInfo {
-        FrameInfo {
-            width: self.width,
-            height: self.height,
-            fourcc: self.fourcc,
-            stride: self.stride,
+        FrameInfo::new(self.width, self.height, self.fourcc, self.stride).with_range(self.range)
+    }
+
+    /// Apply the `capture.range` setting on top of the driver's report
+    pub fn set_range_mode(&mut self, mode: RangeMode) {
+        self.range = mode.resolve(self.detected_range);
+        if self.range != self.detected_range {
+            tracing::info!("Color range overridden to {:?}", self.range);
         }
     }
 
+    /// Quantization range frames are tagged with
+    pub fn color_range(&self) -> ColorRange {
+        self.range
+    }
+
     /// Get frame dimensions
     pub fn dimensions(&self) -> (u32, u32) {
         (self.width, self.height)
@@ -186,6 +831,11 @@ impl VideoCapture {
     pub fn frame_rate(&self) -> FrameRate {
         self.frame_rate
     }
+
+    /// Get field order of the captured frames
+    pub fn field_order(&self) -> FieldOrder {
+        self.field_order
+    }
 }
 
 #[cfg(test)]
@@ -219,6 +869,30 @@ mod tests {
         assert!((fps_ntsc - 59.94).abs() < 0.01);
     }
 
+    #[test]
+    fn test_frame_rate_parse() {
+        let rate = |text| FrameRate::parse(text).unwrap();
+        let fraction = |numerator, denominator| FrameRate {
+            numerator,
+            denominator,
+        };
+        assert_eq!(rate("60"), fraction(60, 1));
+        assert_eq!(rate("50.0"), fraction(50, 1));
+        assert_eq!(rate("59.94"), fraction(60000, 1001));
+        assert_eq!(rate("29.97"), fraction(30000, 1001));
+        assert_eq!(rate("23.976"), fraction(24000, 1001));
+        assert_eq!(rate("60000/1001"), fraction(60000, 1001));
+        assert_eq!(rate(" 30 / 1 "), fraction(30, 1));
+        // Not near an NTSC rate: kept as written
+        assert_eq!(rate("12.5"), fraction(25, 2));
+        assert_eq!(rate("59.5"), fraction(119, 2));
+
+        for bad in ["", "fast", "0", "-30", "60/0", "0/1", "1/x", "NaN", "inf"] {
+            assert!(FrameRate::parse(bad).is_err(), "{:?}", bad);
+        }
+        assert!((rate("59.94").as_f64() - 59.94).abs() < 0.001);
+    }
+
     #[test]
     fn test_frame_rate_clone() {
         let rate = FrameRate {
@@ -232,12 +906,7 @@ mod tests {
 
     #[test]
     fn test_frame_info_clone_copy() {
-        let info = FrameInfo {
-            width: 1920,
-            height: 1080,
-            fourcc: FourCC::new(b"YUYV"),
-            stride: 3840,
-        };
+        let info = FrameInfo::new(1920, 1080, FourCC::new(b"YUYV"), 3840);
         // Test Copy trait
         let copied = info;
         assert_eq!(info.width, copied.width);
@@ -247,15 +916,107 @@ mod tests {
 
     #[test]
     fn test_frame_info_fields() {
-        let info = FrameInfo {
-            width: 1280,
-            height: 720,
-            fourcc: FourCC::new(b"MJPG"),
-            stride: 2560,
-        };
+        let info = FrameInfo::new(1280, 720, FourCC::new(b"MJPG"), 2560);
         assert_eq!(info.width, 1280);
         assert_eq!(info.height, 720);
         assert_eq!(info.stride, 2560);
+        assert_eq!(info.planes, PlaneOffsets::single());
+    }
+
+    fn pipe() -> (std::fs::File, std::fs::File) {
+        use std::os::unix::io::FromRawFd;
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        unsafe {
+            (
+                std::fs::File::from_raw_fd(fds[0]),
+                std::fs::File::from_raw_fd(fds[1]),
+            )
+        }
+    }
+
+    #[test]
+    fn test_wait_readable_times_out() {
+        use std::os::unix::io::AsRawFd;
+        let (reader, _writer) = pipe();
+        let start = std::time::Instant::now();
+        assert!(!wait_readable(reader.as_raw_fd(), Duration::from_millis(30)).unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(25));
+    }
+
+    #[test]
+    fn test_wait_readable_returns_when_ready() {
+        use std::io::Write;
+        use std::os::unix::io::AsRawFd;
+        let (reader, mut writer) = pipe();
+        writer.write_all(&[1]).unwrap();
+        let start = std::time::Instant::now();
+        assert!(wait_readable(reader.as_raw_fd(), Duration::from_secs(5)).unwrap());
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // A hung-up peer counts as ready so the read reports the condition
+        let (reader, writer) = pipe();
+        drop(writer);
+        assert!(wait_readable(reader.as_raw_fd(), Duration::from_secs(5)).unwrap());
+    }
+
+    #[test]
+    fn test_wait_readable_bad_fd() {
+        // poll reports POLLNVAL as an event rather than failing
+        assert!(wait_readable(-1, Duration::from_millis(10)).is_ok());
+    }
+
+    #[test]
+    fn test_open_missing_device() {
+        let error = VideoCapture::open("/dev/does-not-exist/video9")
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            CaptureError::DeviceNotFound { ref path } if path == "/dev/does-not-exist/video9"
+        ));
+    }
+
+    #[test]
+    fn test_open_failed_classifies_io_errors() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(matches!(
+            CaptureError::open_failed("/dev/video0", denied),
+            CaptureError::PermissionDenied { .. }
+        ));
+        let busy = io::Error::from_raw_os_error(libc::EBUSY);
+        let error = CaptureError::open_failed("/dev/video0", busy);
+        assert!(matches!(error, CaptureError::Other(_)));
+        assert!(error.to_string().contains("/dev/video0"));
+    }
+
+    #[test]
+    fn test_plane_offsets_packed() {
+        let nv12 = PlaneOffsets::packed(FourCC::new(b"NV12"), 2048, 1080);
+        assert_eq!(nv12.len(), 2);
+        assert_eq!(nv12.get(0), Some(0));
+        assert_eq!(nv12.get(1), Some(2048 * 1080));
+        assert_eq!(nv12.get(2), None);
+
+        let yu12 = PlaneOffsets::packed(FourCC::new(b"YU12"), 1920, 1080);
+        assert_eq!(yu12.get(1), Some(1920 * 1080));
+        assert_eq!(yu12.get(2), Some(1920 * 1080 * 5 / 4));
+
+        let yuyv = PlaneOffsets::packed(FourCC::new(b"YUYV"), 3840, 1080);
+        assert_eq!(yuyv.len(), 1);
+
+        // 4K NV12: the chroma plane starts after 3840 * 2160 luma bytes
+        let nv12 = PlaneOffsets::packed(FourCC::new(b"NV12"), 3840, 2160);
+        assert_eq!(nv12.get(1), Some(3840 * 2160));
+    }
+
+    #[test]
+    fn test_plane_offsets_stitched() {
+        let planes = PlaneOffsets::stitched(&[100, 50, 25]);
+        assert_eq!(planes.get(0), Some(0));
+        assert_eq!(planes.get(1), Some(100));
+        assert_eq!(planes.get(2), Some(150));
+        assert_eq!(PlaneOffsets::stitched(&[]).len(), 1);
     }
 
     #[test]
@@ -280,6 +1041,21 @@ mod tests {
         assert!(display.contains('Y') || display.len() == 4);
     }
 
+    #[test]
+    fn test_io_mode_from_name() {
+        assert_eq!(IoMode::from_name("mmap").unwrap(), IoMode::Mmap);
+        assert_eq!(IoMode::from_name("DMABUF").unwrap(), IoMode::Dmabuf);
+        assert_eq!(IoMode::default(), IoMode::Mmap);
+        for mode in [IoMode::Mmap, IoMode::Dmabuf] {
+            assert_eq!(IoMode::from_name(mode.name()).unwrap(), mode);
+        }
+        let error = IoMode::from_name("userptr").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unsupported I/O mode: userptr. Supported: mmap, dmabuf"
+        );
+    }
+
     #[test]
     fn test_frame_rate_debug() {
         let rate = FrameRate {
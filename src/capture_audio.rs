@@ -0,0 +1,419 @@
+//! Embedded HDMI audio capture
+//!
+//! Most HDMI→USB dongles expose the HDMI audio as a separate ALSA capture
+//! device. A dedicated thread reads it into an [`AudioQueue`]; the video loop
+//! drains the queue after every frame and hands the samples to the NDI sender,
+//! so audio goes out on the same sender, interleaved with the video it belongs to.
+
+use alsa::pcm::{Access, Format, HwParams, PCM};
+use alsa::{Direction, ValueOr};
+use anyhow::{anyhow, Context, Result};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::av_clock::{block_start, frames_duration};
+use crate::config::CaptureAudioConfig;
+
+/// ALSA period: 10ms at 48kHz
+const PERIOD_FRAMES: usize = 480;
+const BUFFER_PERIODS: usize = 4;
+
+/// Audio held while the video loop isn't draining (e.g. during a stall)
+const QUEUE_DURATION: Duration = Duration::from_millis(200);
+
+/// Delay between attempts to open a missing audio device
+const REOPEN_DELAY: Duration = Duration::from_secs(1);
+
+// =============================================================================
+// Quirks
+// =============================================================================
+
+/// Device-specific workarounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioQuirk {
+    None,
+    /// MacroSilicon MS2109: advertises 96kHz mono S16_LE, but the stream is
+    /// really 48kHz stereo with big-endian samples. Consecutive "mono"
+    /// samples alternate left/right and need their bytes swapped.
+    Ms2109,
+}
+
+impl AudioQuirk {
+    /// Parse a quirk name from configuration ("" or "none", "ms2109")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "" | "none" => Ok(AudioQuirk::None),
+            "ms2109" => Ok(AudioQuirk::Ms2109),
+            other => Err(anyhow!(
+                "Unsupported audio quirk: {}. Supported: none, ms2109",
+                other
+            )),
+        }
+    }
+
+    /// Hardware (channels, rate) to open for the requested output format
+    pub fn hw_format(self, channels: u32, sample_rate: u32) -> (u32, u32) {
+        match self {
+            AudioQuirk::None => (channels, sample_rate),
+            AudioQuirk::Ms2109 => (1, 96000),
+        }
+    }
+
+    /// Output (channels, rate) delivered to NDI
+    pub fn output_format(self, channels: u32, sample_rate: u32) -> (u32, u32) {
+        match self {
+            AudioQuirk::None => (channels, sample_rate),
+            AudioQuirk::Ms2109 => (2, 48000),
+        }
+    }
+}
+
+/// Reshuffle an MS2109 "96kHz mono" buffer into 48kHz interleaved stereo in
+/// place: fix the byte order of every sample. A trailing odd sample (half a
+/// stereo frame) is dropped; returns the number of valid samples.
+pub fn ms2109_to_stereo(samples: &mut [i16]) -> usize {
+    let len = samples.len() & !1;
+    for sample in &mut samples[..len] {
+        *sample = sample.swap_bytes();
+    }
+    len
+}
+
+// =============================================================================
+// Conversion
+// =============================================================================
+
+/// Convert interleaved 16-bit samples to planar float (one contiguous plane
+/// per channel, as NDI expects). Returns the number of frames per channel.
+pub fn interleaved_to_planar_f32(samples: &[i16], channels: usize, out: &mut Vec<f32>) -> usize {
+    if channels == 0 {
+        out.clear();
+        return 0;
+    }
+    let frames = samples.len() / channels;
+    out.clear();
+    out.resize(frames * channels, 0.0);
+    for (frame, chunk) in samples.chunks_exact(channels).enumerate() {
+        for (channel, &sample) in chunk.iter().enumerate() {
+            out[channel * frames + frame] = sample as f32 / 32768.0;
+        }
+    }
+    frames
+}
+
+// =============================================================================
+// Queue
+// =============================================================================
+
+/// Bounded FIFO of interleaved samples between the ALSA thread and the
+/// video loop. When full, the oldest whole frames are dropped. The capture
+/// time of the front sample travels with the samples.
+pub struct AudioQueue {
+    samples: Mutex<Queued>,
+    capacity: usize,
+    channels: u32,
+    sample_rate: u32,
+    dropped_frames: AtomicU64,
+}
+
+struct Queued {
+    samples: VecDeque<i16>,
+    /// Capture time of the first queued sample
+    front: Option<Instant>,
+}
+
+impl AudioQueue {
+    /// Queue holding up to `duration` of audio in the given format
+    pub fn new(channels: u32, sample_rate: u32, duration: Duration) -> Self {
+        let frames = (sample_rate as f64 * duration.as_secs_f64()).ceil() as usize;
+        let capacity = frames.max(1) * channels.max(1) as usize;
+        Self {
+            samples: Mutex::new(Queued {
+                samples: VecDeque::with_capacity(capacity),
+                front: None,
+            }),
+            capacity,
+            channels,
+            sample_rate,
+            dropped_frames: AtomicU64::new(0),
+        }
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Append interleaved samples (a whole number of frames), the first of
+    /// which was captured at `captured`
+    pub fn push(&self, samples: &[i16], captured: Instant) {
+        let channels = self.channels.max(1) as usize;
+        let mut queue = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        if queue.samples.is_empty() {
+            queue.front = Some(captured);
+        }
+        let overflow = (queue.samples.len() + samples.len()).saturating_sub(self.capacity);
+        if overflow > 0 {
+            let drop = overflow.div_ceil(channels) * channels;
+            let drop = drop.min(queue.samples.len());
+            queue.samples.drain(..drop);
+            let frames = (drop / channels) as u64;
+            queue.front = if queue.samples.is_empty() {
+                Some(captured)
+            } else {
+                queue
+                    .front
+                    .map(|front| front + frames_duration(frames, self.sample_rate))
+            };
+            self.dropped_frames.fetch_add(frames, Ordering::Relaxed);
+        }
+        let skip = samples.len().saturating_sub(self.capacity);
+        if skip > 0 {
+            let frames = (skip / channels) as u64;
+            queue.front = Some(captured + frames_duration(frames, self.sample_rate));
+        }
+        queue.samples.extend(&samples[skip..]);
+    }
+
+    /// Move all queued samples into `out` (cleared first). Returns the
+    /// capture time of the first one, if any were queued.
+    pub fn drain_into(&self, out: &mut Vec<i16>) -> Option<Instant> {
+        out.clear();
+        let mut queue = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        out.extend(queue.samples.drain(..));
+        queue.front.take().filter(|_| !out.is_empty())
+    }
+
+    /// Frames discarded because the queue was full
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+}
+
+// =============================================================================
+// ALSA Capture
+// =============================================================================
+
+/// Resolved audio capture settings
+#[derive(Debug, Clone)]
+pub struct AudioCaptureSettings {
+    pub device: String,
+    pub channels: u32,
+    pub sample_rate: u32,
+    pub quirk: AudioQuirk,
+}
+
+impl AudioCaptureSettings {
+    pub fn from_config(config: &CaptureAudioConfig) -> Result<Self> {
+        if config.channels == 0 {
+            anyhow::bail!("capture.audio.channels must be at least 1");
+        }
+        Ok(Self {
+            device: config.device.clone(),
+            channels: config.channels,
+            sample_rate: config.sample_rate,
+            quirk: AudioQuirk::from_name(&config.quirk)?,
+        })
+    }
+
+    /// Queue sized for the format delivered to NDI
+    pub fn queue(&self) -> AudioQueue {
+        let (channels, sample_rate) = self.quirk.output_format(self.channels, self.sample_rate);
+        AudioQueue::new(channels, sample_rate, QUEUE_DURATION)
+    }
+}
+
+/// Open the device; returns it with its channel count and actual rate
+fn open_pcm(settings: &AudioCaptureSettings) -> Result<(PCM, u32, u32)> {
+    let (channels, rate) = settings
+        .quirk
+        .hw_format(settings.channels, settings.sample_rate);
+    let pcm = PCM::new(&settings.device, Direction::Capture, false)
+        .with_context(|| format!("Failed to open ALSA capture device {}", settings.device))?;
+
+    {
+        let hwp = HwParams::any(&pcm)?;
+        hwp.set_channels(channels)?;
+        hwp.set_rate(rate, ValueOr::Nearest)?;
+        hwp.set_format(Format::s16())?;
+        hwp.set_access(Access::RWInterleaved)?;
+        hwp.set_period_size(PERIOD_FRAMES as i64, ValueOr::Nearest)?;
+        hwp.set_buffer_size((PERIOD_FRAMES * BUFFER_PERIODS) as i64)?;
+        pcm.hw_params(&hwp)?;
+    }
+
+    let actual_rate = pcm.hw_params_current()?.get_rate()?;
+    if actual_rate != rate {
+        tracing::warn!(
+            "Audio device {} runs at {}Hz instead of {}Hz",
+            settings.device,
+            actual_rate,
+            rate
+        );
+    }
+    tracing::info!(
+        "Audio capture: {}, {}Hz, {} channel(s), quirk={:?}",
+        settings.device,
+        actual_rate,
+        channels,
+        settings.quirk
+    );
+    Ok((pcm, channels, actual_rate))
+}
+
+/// Capture audio into `queue` until `running` is cleared. A missing or
+/// unplugged device is retried every second.
+pub fn run_audio_capture(
+    settings: AudioCaptureSettings,
+    queue: Arc<AudioQueue>,
+    running: Arc<AtomicBool>,
+) {
+    while running.load(Ordering::Relaxed) {
+        let (pcm, hw_channels, hw_rate) = match open_pcm(&settings) {
+            Ok(opened) => opened,
+            Err(e) => {
+                tracing::warn!("Audio capture unavailable: {:#}", e);
+                std::thread::sleep(REOPEN_DELAY);
+                continue;
+            }
+        };
+        let format = (hw_channels, hw_rate);
+        if let Err(e) = capture_until_error(&pcm, format, &settings, &queue, &running) {
+            tracing::warn!("Audio capture error: {:#}, reopening", e);
+            std::thread::sleep(REOPEN_DELAY);
+        }
+    }
+}
+
+fn capture_until_error(
+    pcm: &PCM,
+    (hw_channels, hw_rate): (u32, u32),
+    settings: &AudioCaptureSettings,
+    queue: &AudioQueue,
+    running: &AtomicBool,
+) -> Result<()> {
+    let io = pcm.io_i16()?;
+    let mut buffer = vec![0i16; PERIOD_FRAMES * hw_channels as usize];
+    pcm.start()?;
+
+    while running.load(Ordering::Relaxed) {
+        let frames = match io.readi(&mut buffer) {
+            Ok(frames) => frames,
+            Err(e) => {
+                pcm.recover(e.errno(), true)
+                    .context("ALSA recovery failed")?;
+                continue;
+            }
+        };
+        // Stamp the block with when its first sample came in, not when the
+        // read returned: whatever is still buffered arrived after it
+        let read_at = Instant::now();
+        let delay = pcm.delay().unwrap_or(0);
+        let captured = block_start(read_at, delay, frames as u64, hw_rate);
+        let samples = &mut buffer[..frames * hw_channels as usize];
+        let len = match settings.quirk {
+            AudioQuirk::None => samples.len(),
+            AudioQuirk::Ms2109 => ms2109_to_stereo(samples),
+        };
+        queue.push(&samples[..len], captured);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quirk_from_name() {
+        assert_eq!(AudioQuirk::from_name("").unwrap(), AudioQuirk::None);
+        assert_eq!(AudioQuirk::from_name("none").unwrap(), AudioQuirk::None);
+        assert_eq!(AudioQuirk::from_name("MS2109").unwrap(), AudioQuirk::Ms2109);
+        assert!(AudioQuirk::from_name("ms2130").is_err());
+    }
+
+    #[test]
+    fn test_quirk_formats() {
+        assert_eq!(AudioQuirk::None.hw_format(2, 44100), (2, 44100));
+        assert_eq!(AudioQuirk::Ms2109.hw_format(2, 48000), (1, 96000));
+        assert_eq!(AudioQuirk::Ms2109.output_format(1, 96000), (2, 48000));
+    }
+
+    #[test]
+    fn test_ms2109_reshuffle() {
+        // Big-endian L/R pairs as read through the "96kHz mono" S16_LE view
+        let left: i16 = 1000;
+        let right: i16 = -2000;
+        let mut raw = vec![
+            left.swap_bytes(),
+            right.swap_bytes(),
+            left.swap_bytes(),
+            right.swap_bytes(),
+            0x0102,
+        ];
+        let len = ms2109_to_stereo(&mut raw);
+        assert_eq!(len, 4);
+        assert_eq!(&raw[..len], &[left, right, left, right]);
+    }
+
+    #[test]
+    fn test_interleaved_to_planar() {
+        let interleaved = [16384, -16384, 8192, 0, -32768, 32767];
+        let mut planar = Vec::new();
+        let frames = interleaved_to_planar_f32(&interleaved, 2, &mut planar);
+        assert_eq!(frames, 3);
+        assert_eq!(planar[..3], [0.5, 0.25, -1.0]);
+        assert_eq!(planar[3..], [-0.5, 0.0, 32767.0 / 32768.0]);
+
+        // A partial trailing frame is ignored
+        let frames = interleaved_to_planar_f32(&interleaved[..5], 2, &mut planar);
+        assert_eq!(frames, 2);
+        assert_eq!(planar.len(), 4);
+    }
+
+    #[test]
+    fn test_queue_drops_oldest_frames() {
+        // 4 stereo frames of capacity, 1ms each
+        let queue = AudioQueue::new(2, 1000, Duration::from_millis(4));
+        let start = Instant::now();
+        queue.push(&[1, 1, 2, 2, 3, 3], start);
+        queue.push(&[4, 4, 5, 5], start + Duration::from_millis(3));
+        assert_eq!(queue.dropped_frames(), 1);
+
+        // The front moved on by the dropped frame
+        let mut out = Vec::new();
+        let front = queue.drain_into(&mut out);
+        assert_eq!(out, vec![2, 2, 3, 3, 4, 4, 5, 5]);
+        assert_eq!(front, Some(start + Duration::from_millis(1)));
+        assert_eq!(queue.drain_into(&mut out), None);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_queue_front_is_first_sample_since_drain() {
+        let queue = AudioQueue::new(1, 1000, Duration::from_millis(10));
+        let start = Instant::now();
+        queue.push(&[1, 2], start);
+        queue.push(&[3, 4], start + Duration::from_millis(2));
+        let mut out = Vec::new();
+        assert_eq!(queue.drain_into(&mut out), Some(start));
+
+        let later = start + Duration::from_millis(4);
+        queue.push(&[5], later);
+        assert_eq!(queue.drain_into(&mut out), Some(later));
+        assert_eq!(out, vec![5]);
+
+        // A block bigger than the queue keeps its newest samples
+        queue.push(&[0; 12], later);
+        assert_eq!(
+            queue.drain_into(&mut out),
+            Some(later + Duration::from_millis(2))
+        );
+        assert_eq!(out.len(), 10);
+    }
+}
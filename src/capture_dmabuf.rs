@@ -0,0 +1,269 @@
+//! DMA-BUF capture
+//!
+//! The mmap stream maps each V4L2 buffer through the device node. Here the
+//! driver's buffers are exported as DMA-BUFs (`VIDIOC_EXPBUF`) and each is
+//! mapped once, read-only, for the life of the stream. Every frame is
+//! bracketed by `DMA_BUF_IOCTL_SYNC` so the kernel only does the cache
+//! maintenance a CPU read needs, and UYVY frames go to NDI straight from
+//! the mapping. Drivers that can't export fail in [`DmabufStream::new`], and
+//! the caller falls back to mmap.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::c_void;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+use v4l::device::Handle;
+use v4l::v4l2::{self, vidioc};
+use v4l::v4l_sys;
+
+use crate::capture::wait_readable;
+
+const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const V4L2_MEMORY_MMAP: u32 = 1;
+
+/// `DMA_BUF_IOCTL_SYNC`, `_IOW('b', 0, struct dma_buf_sync)`
+const DMA_BUF_IOCTL_SYNC: libc::c_ulong = 0x4008_6200;
+const DMA_BUF_SYNC_READ: u64 = 1;
+const DMA_BUF_SYNC_START: u64 = 0;
+const DMA_BUF_SYNC_END: u64 = 4;
+
+/// `struct v4l2_exportbuffer`
+#[repr(C)]
+#[derive(Debug, Default)]
+struct ExportBuffer {
+    type_: u32,
+    index: u32,
+    plane: u32,
+    flags: u32,
+    fd: i32,
+    reserved: [u32; 11],
+}
+
+/// `struct dma_buf_sync`
+#[repr(C)]
+struct DmaBufSync {
+    flags: u64,
+}
+
+/// One exported buffer and its read-only mapping
+struct ExportedBuffer {
+    fd: OwnedFd,
+    ptr: *const u8,
+    length: usize,
+}
+
+impl ExportedBuffer {
+    /// Tell the kernel a CPU read of the buffer starts or ends. Failures
+    /// only cost coherency on platforms that need the sync, so they are
+    /// logged rather than failing the frame.
+    fn sync(&self, flags: u64) {
+        let mut sync = DmaBufSync {
+            flags: flags | DMA_BUF_SYNC_READ,
+        };
+        let result = unsafe { libc::ioctl(self.fd.as_raw_fd(), DMA_BUF_IOCTL_SYNC, &mut sync) };
+        if result == -1 {
+            tracing::debug!(
+                "DMA_BUF_IOCTL_SYNC failed: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+/// Single-planar capture stream reading exported DMA-BUFs. A dequeued
+/// buffer stays mapped and untouched until the next frame's callback has
+/// returned, so frames can be lent to an asynchronous send.
+pub struct DmabufStream {
+    handle: Arc<Handle>,
+    buffers: Vec<ExportedBuffer>,
+    /// Buffer of the last frame, requeued once the next frame's callback
+    /// has returned
+    held: Option<u32>,
+    streaming: bool,
+}
+
+// SAFETY: the mappings are only accessed through &mut self
+unsafe impl Send for DmabufStream {}
+
+impl DmabufStream {
+    /// Allocate `count` buffers, export and map each one, then stream on.
+    /// Fails if the driver can't export its buffers.
+    pub fn new(handle: Arc<Handle>, count: u32) -> Result<Self> {
+        let mut stream = Self {
+            handle,
+            buffers: Vec::new(),
+            held: None,
+            streaming: false,
+        };
+
+        let mut request: v4l_sys::v4l2_requestbuffers = unsafe { std::mem::zeroed() };
+        request.count = count;
+        request.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        request.memory = V4L2_MEMORY_MMAP;
+        stream
+            .ioctl(vidioc::VIDIOC_REQBUFS, &mut request)
+            .context("VIDIOC_REQBUFS failed")?;
+        if request.count == 0 {
+            bail!("Driver allocated no capture buffers");
+        }
+
+        for index in 0..request.count {
+            let mut buffer = buffer_desc(index);
+            stream
+                .ioctl(vidioc::VIDIOC_QUERYBUF, &mut buffer)
+                .context("VIDIOC_QUERYBUF failed")?;
+            let length = buffer.length as usize;
+
+            let mut export = ExportBuffer {
+                type_: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+                index,
+                flags: (libc::O_RDONLY | libc::O_CLOEXEC) as u32,
+                ..ExportBuffer::default()
+            };
+            stream
+                .ioctl(vidioc::VIDIOC_EXPBUF, &mut export)
+                .context("VIDIOC_EXPBUF failed (driver can't export DMA-BUFs)")?;
+            // SAFETY: the driver just handed us this descriptor
+            let fd = unsafe { OwnedFd::from_raw_fd(export.fd) };
+
+            let ptr = unsafe {
+                v4l2::mmap(
+                    ptr::null_mut(),
+                    length,
+                    libc::PROT_READ,
+                    libc::MAP_SHARED,
+                    fd.as_raw_fd(),
+                    0,
+                )
+            }
+            .context("Failed to mmap DMA-BUF")?;
+            stream.buffers.push(ExportedBuffer {
+                fd,
+                ptr: ptr as *const u8,
+                length,
+            });
+        }
+
+        for index in 0..request.count {
+            stream.queue(index)?;
+        }
+
+        let mut buf_type = V4L2_BUF_TYPE_VIDEO_CAPTURE as std::os::raw::c_int;
+        stream
+            .ioctl(vidioc::VIDIOC_STREAMON, &mut buf_type)
+            .context("VIDIOC_STREAMON failed")?;
+        stream.streaming = true;
+
+        tracing::info!("DMA-BUF capture: {} buffers", stream.buffers.len());
+        Ok(stream)
+    }
+
+    fn ioctl<T>(&self, request: vidioc::_IOC_TYPE, arg: &mut T) -> std::io::Result<()> {
+        unsafe { v4l2::ioctl(self.handle.fd(), request, arg as *mut T as *mut c_void) }
+    }
+
+    fn queue(&self, index: u32) -> Result<()> {
+        let mut buffer = buffer_desc(index);
+        self.ioctl(vidioc::VIDIOC_QBUF, &mut buffer)
+            .context("VIDIOC_QBUF failed")
+    }
+
+    /// Dequeue a filled buffer: its index and the bytes used
+    fn dequeue(&self) -> Result<(u32, usize)> {
+        let mut buffer = buffer_desc(0);
+        self.ioctl(vidioc::VIDIOC_DQBUF, &mut buffer)
+            .context("VIDIOC_DQBUF failed")?;
+        let Some(exported) = self.buffers.get(buffer.index as usize) else {
+            bail!("Driver returned unknown buffer index {}", buffer.index);
+        };
+        Ok((
+            buffer.index,
+            (buffer.bytesused as usize).min(exported.length),
+        ))
+    }
+
+    /// Wait up to `timeout` for a frame and pass it to `callback`. The
+    /// previous frame's buffer is requeued after the callback returns, so
+    /// it stays valid while the callback works on this frame. Returns false
+    /// if no frame arrived in time.
+    pub fn process<F>(&mut self, timeout: Duration, callback: F) -> Result<bool>
+    where
+        F: FnOnce(&[u8]),
+    {
+        if !wait_readable(self.handle.fd(), timeout)? {
+            return Ok(false);
+        }
+        let (index, used) = self.dequeue()?;
+        let exported = &self.buffers[index as usize];
+        exported.sync(DMA_BUF_SYNC_START);
+        callback(unsafe { std::slice::from_raw_parts(exported.ptr, used) });
+
+        if let Some(previous) = self.held.replace(index) {
+            self.buffers[previous as usize].sync(DMA_BUF_SYNC_END);
+            self.queue(previous)?;
+        }
+        Ok(true)
+    }
+
+    /// Dequeue and requeue a frame that is already waiting. The held
+    /// buffer stays held. Returns false if none was ready.
+    pub fn skip(&mut self) -> Result<bool> {
+        if !wait_readable(self.handle.fd(), Duration::ZERO)? {
+            return Ok(false);
+        }
+        let (index, _) = self.dequeue()?;
+        self.queue(index)?;
+        Ok(true)
+    }
+}
+
+fn buffer_desc(index: u32) -> v4l_sys::v4l2_buffer {
+    let mut buffer: v4l_sys::v4l2_buffer = unsafe { std::mem::zeroed() };
+    buffer.index = index;
+    buffer.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    buffer.memory = V4L2_MEMORY_MMAP;
+    buffer
+}
+
+impl Drop for DmabufStream {
+    fn drop(&mut self) {
+        if let Some(index) = self.held.take() {
+            self.buffers[index as usize].sync(DMA_BUF_SYNC_END);
+        }
+        if self.streaming {
+            let mut buf_type = V4L2_BUF_TYPE_VIDEO_CAPTURE as std::os::raw::c_int;
+            let _ = self.ioctl(vidioc::VIDIOC_STREAMOFF, &mut buf_type);
+        }
+        // Unmapping and closing the exports lets the driver free the buffers
+        for buffer in self.buffers.drain(..) {
+            unsafe { libc::munmap(buffer.ptr as *mut c_void, buffer.length) };
+        }
+        let mut request: v4l_sys::v4l2_requestbuffers = unsafe { std::mem::zeroed() };
+        request.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        request.memory = V4L2_MEMORY_MMAP;
+        let _ = self.ioctl(vidioc::VIDIOC_REQBUFS, &mut request);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_struct_layout_matches_kernel() {
+        assert_eq!(std::mem::size_of::<ExportBuffer>(), 64);
+        assert_eq!(std::mem::size_of::<DmaBufSync>(), 8);
+        // _IOW('b', 0, 8 bytes)
+        let expected = (1 << 30) | (8 << 16) | ((b'b' as libc::c_ulong) << 8);
+        assert_eq!(DMA_BUF_IOCTL_SYNC, expected);
+    }
+
+    #[test]
+    fn test_export_fails_without_a_capture_device() {
+        // Not a V4L2 node: the first ioctl fails and nothing is leaked
+        let device = v4l::Device::with_path("/dev/null").unwrap();
+        assert!(DmabufStream::new(device.handle(), 4).is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Single-planar mmap capture with a held buffer
+//!
+//! The v4l crate's mmap stream requeues the previous buffer at the start of
+//! every dequeue, so a frame's buffer goes back to the driver before the
+//! next frame is looked at. This stream keeps the last frame's buffer
+//! dequeued until the callback for the following frame has returned. A UYVY
+//! frame lent to NDI's asynchronous send therefore stays untouched until the
+//! send of the next frame, which returns once NDI is done with it.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+use v4l::device::Handle;
+use v4l::v4l2::{self, vidioc};
+use v4l::v4l_sys;
+
+use crate::capture::wait_readable;
+
+const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const V4L2_MEMORY_MMAP: u32 = 1;
+
+/// One driver buffer mapped through the device node
+struct MappedBuffer {
+    ptr: *const u8,
+    length: usize,
+}
+
+/// Single-planar mmap capture stream that holds the last dequeued buffer
+/// across one frame
+pub struct MmapStream {
+    handle: Arc<Handle>,
+    buffers: Vec<MappedBuffer>,
+    /// Buffer of the last frame, requeued once the next frame's callback
+    /// has returned
+    held: Option<u32>,
+    streaming: bool,
+}
+
+// SAFETY: the mappings are only accessed through &mut self
+unsafe impl Send for MmapStream {}
+
+impl MmapStream {
+    /// Allocate and map `count` buffers, then stream on
+    pub fn new(handle: Arc<Handle>, count: u32) -> Result<Self> {
+        let mut stream = Self {
+            handle,
+            buffers: Vec::new(),
+            held: None,
+            streaming: false,
+        };
+
+        let mut request: v4l_sys::v4l2_requestbuffers = unsafe { std::mem::zeroed() };
+        request.count = count;
+        request.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        request.memory = V4L2_MEMORY_MMAP;
+        stream
+            .ioctl(vidioc::VIDIOC_REQBUFS, &mut request)
+            .context("VIDIOC_REQBUFS failed")?;
+        if request.count < 2 {
+            bail!(
+                "Driver allocated {} capture buffer(s), at least 2 are needed",
+                request.count
+            );
+        }
+
+        for index in 0..request.count {
+            let mut buffer = buffer_desc(index);
+            stream
+                .ioctl(vidioc::VIDIOC_QUERYBUF, &mut buffer)
+                .context("VIDIOC_QUERYBUF failed")?;
+            let length = buffer.length as usize;
+            let ptr = unsafe {
+                v4l2::mmap(
+                    ptr::null_mut(),
+                    length,
+                    libc::PROT_READ,
+                    libc::MAP_SHARED,
+                    stream.handle.fd(),
+                    buffer.m.offset as libc::off_t,
+                )
+            }
+            .context("Failed to mmap capture buffer")?;
+            stream.buffers.push(MappedBuffer {
+                ptr: ptr as *const u8,
+                length,
+            });
+        }
+
+        for index in 0..request.count {
+            stream.queue(index)?;
+        }
+
+        let mut buf_type = V4L2_BUF_TYPE_VIDEO_CAPTURE as std::os::raw::c_int;
+        stream
+            .ioctl(vidioc::VIDIOC_STREAMON, &mut buf_type)
+            .context("VIDIOC_STREAMON failed")?;
+        stream.streaming = true;
+
+        tracing::debug!("mmap capture: {} buffers", stream.buffers.len());
+        Ok(stream)
+    }
+
+    /// Device handle, e.g. to hand the device to another stream
+    pub fn handle(&self) -> Arc<Handle> {
+        Arc::clone(&self.handle)
+    }
+
+    fn ioctl<T>(&self, request: vidioc::_IOC_TYPE, arg: &mut T) -> std::io::Result<()> {
+        unsafe { v4l2::ioctl(self.handle.fd(), request, arg as *mut T as *mut c_void) }
+    }
+
+    fn queue(&self, index: u32) -> Result<()> {
+        let mut buffer = buffer_desc(index);
+        self.ioctl(vidioc::VIDIOC_QBUF, &mut buffer)
+            .context("VIDIOC_QBUF failed")
+    }
+
+    /// Dequeue a filled buffer: its index and the bytes used
+    fn dequeue(&self) -> Result<(u32, usize)> {
+        let mut buffer = buffer_desc(0);
+        self.ioctl(vidioc::VIDIOC_DQBUF, &mut buffer)
+            .context("VIDIOC_DQBUF failed")?;
+        let Some(mapped) = self.buffers.get(buffer.index as usize) else {
+            bail!("Driver returned unknown buffer index {}", buffer.index);
+        };
+        Ok((buffer.index, (buffer.bytesused as usize).min(mapped.length)))
+    }
+
+    /// Wait up to `timeout` for a frame and pass it to `callback`. The
+    /// previous frame's buffer is requeued after the callback returns, so
+    /// it stays valid while the callback works on this frame. Returns false
+    /// if no frame arrived in time.
+    pub fn process<F>(&mut self, timeout: Duration, callback: F) -> Result<bool>
+    where
+        F: FnOnce(&[u8]),
+    {
+        if !wait_readable(self.handle.fd(), timeout)? {
+            return Ok(false);
+        }
+        let (index, used) = self.dequeue()?;
+        let mapped = &self.buffers[index as usize];
+        callback(unsafe { std::slice::from_raw_parts(mapped.ptr, used) });
+
+        if let Some(previous) = self.held.replace(index) {
+            self.queue(previous)?;
+        }
+        Ok(true)
+    }
+
+    /// Dequeue and requeue a frame that is already waiting. The held
+    /// buffer stays held. Returns false if none was ready.
+    pub fn skip(&mut self) -> Result<bool> {
+        if !wait_readable(self.handle.fd(), Duration::ZERO)? {
+            return Ok(false);
+        }
+        let (index, _) = self.dequeue()?;
+        self.queue(index)?;
+        Ok(true)
+    }
+}
+
+fn buffer_desc(index: u32) -> v4l_sys::v4l2_buffer {
+    let mut buffer: v4l_sys::v4l2_buffer = unsafe { std::mem::zeroed() };
+    buffer.index = index;
+    buffer.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    buffer.memory = V4L2_MEMORY_MMAP;
+    buffer
+}
+
+impl Drop for MmapStream {
+    fn drop(&mut self) {
+        if self.streaming {
+            let mut buf_type = V4L2_BUF_TYPE_VIDEO_CAPTURE as std::os::raw::c_int;
+            let _ = self.ioctl(vidioc::VIDIOC_STREAMOFF, &mut buf_type);
+        }
+        for buffer in self.buffers.drain(..) {
+            unsafe { libc::munmap(buffer.ptr as *mut c_void, buffer.length) };
+        }
+        let mut request: v4l_sys::v4l2_requestbuffers = unsafe { std::mem::zeroed() };
+        request.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+        request.memory = V4L2_MEMORY_MMAP;
+        let _ = self.ioctl(vidioc::VIDIOC_REQBUFS, &mut request);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_desc() {
+        let buffer = buffer_desc(3);
+        assert_eq!(buffer.index, 3);
+        assert_eq!(buffer.type_, V4L2_BUF_TYPE_VIDEO_CAPTURE);
+        assert_eq!(buffer.memory, V4L2_MEMORY_MMAP);
+        assert_eq!(buffer.bytesused, 0);
+    }
+
+    #[test]
+    fn test_fails_without_a_capture_device() {
+        // Not a V4L2 node: the first ioctl fails and nothing is mapped
+        let device = v4l::Device::with_path("/dev/null").unwrap();
+        assert!(MmapStream::new(device.handle(), 4).is_err());
+    }
+}
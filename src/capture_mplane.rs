@@ -0,0 +1,525 @@
+//! V4L2 multi-planar (MPLANE) capture
+//!
+//! Rockchip and some i.MX capture drivers only expose
+//! `VIDEO_CAPTURE_MPLANE`, which the v4l crate's mmap stream doesn't drive.
+//! This issues the MPLANE ioctls directly and hands each frame to the
+//! converters in the contiguous layout they expect: single-plane buffers are
+//! passed through untouched, multi-plane buffers (e.g. NV12M) are stitched.
+
+use anyhow::{bail, Context, Result};
+use std::ffi::c_void;
+use std::ops::Range;
+use std::ptr;
+use std::sync::Arc;
+use v4l::device::Handle;
+use v4l::v4l2::{self, vidioc};
+use v4l::{v4l_sys, FourCC};
+
+use crate::capture::{FrameRate, PlaneOffsets};
+
+/// Kernel limit on planes per buffer
+pub const VIDEO_MAX_PLANES: usize = 8;
+
+const V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE: u32 = 9;
+const V4L2_MEMORY_MMAP: u32 = 1;
+
+// =============================================================================
+// Kernel Structures
+// =============================================================================
+
+/// `struct v4l2_plane_pix_format`
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PlanePixFormat {
+    pub sizeimage: u32,
+    pub bytesperline: u32,
+    reserved: [u16; 6],
+}
+
+/// `struct v4l2_pix_format_mplane`
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct PixFormatMplane {
+    pub width: u32,
+    pub height: u32,
+    pub pixelformat: u32,
+    pub field: u32,
+    pub colorspace: u32,
+    pub plane_fmt: [PlanePixFormat; VIDEO_MAX_PLANES],
+    pub num_planes: u8,
+    pub flags: u8,
+    pub ycbcr_enc: u8,
+    pub quantization: u8,
+    pub xfer_func: u8,
+    reserved: [u8; 7],
+}
+
+impl PixFormatMplane {
+    /// Format request for the given size and pixel format (driver picks planes)
+    pub fn new(width: u32, height: u32, fourcc: FourCC) -> Self {
+        Self {
+            width,
+            height,
+            pixelformat: u32::from(fourcc),
+            field: 0, // V4L2_FIELD_ANY
+            colorspace: 0,
+            plane_fmt: [PlanePixFormat::default(); VIDEO_MAX_PLANES],
+            num_planes: 0,
+            flags: 0,
+            ycbcr_enc: 0,
+            quantization: 0,
+            xfer_func: 0,
+            reserved: [0; 7],
+        }
+    }
+
+    pub fn fourcc(&self) -> FourCC {
+        FourCC::from(self.pixelformat)
+    }
+
+    /// Planes in use, clamped to the kernel limit
+    pub fn plane_count(&self) -> usize {
+        (self.num_planes as usize).clamp(1, VIDEO_MAX_PLANES)
+    }
+
+    /// Line stride of the first (luma) plane
+    pub fn stride(&self) -> u32 {
+        self.plane_fmt[0].bytesperline
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union FormatData {
+    pix_mp: PixFormatMplane,
+    raw_data: [u8; 200],
+    _align: [u64; 25],
+}
+
+/// `struct v4l2_format` restricted to the MPLANE capture member
+#[repr(C)]
+pub struct Format {
+    type_: u32,
+    fmt: FormatData,
+}
+
+impl Format {
+    pub fn capture(pix_mp: PixFormatMplane) -> Self {
+        let mut fmt = FormatData { raw_data: [0; 200] };
+        fmt.pix_mp = pix_mp;
+        Self {
+            type_: V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE,
+            fmt,
+        }
+    }
+
+    pub fn pix_mp(&self) -> PixFormatMplane {
+        // SAFETY: every bit pattern is a valid PixFormatMplane
+        unsafe { self.fmt.pix_mp }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union PlaneMemory {
+    mem_offset: u32,
+    userptr: std::os::raw::c_ulong,
+    fd: i32,
+}
+
+/// `struct v4l2_plane`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Plane {
+    pub bytesused: u32,
+    pub length: u32,
+    m: PlaneMemory,
+    pub data_offset: u32,
+    reserved: [u32; 11],
+}
+
+impl Default for Plane {
+    fn default() -> Self {
+        Self {
+            bytesused: 0,
+            length: 0,
+            m: PlaneMemory { userptr: 0 },
+            data_offset: 0,
+            reserved: [0; 11],
+        }
+    }
+}
+
+impl Plane {
+    fn mem_offset(&self) -> u32 {
+        // SAFETY: MMAP buffers report the mmap offset in this member
+        unsafe { self.m.mem_offset }
+    }
+
+    /// Byte range of image data within the mapped plane
+    pub fn data_range(&self) -> Range<usize> {
+        let end = (self.bytesused as usize).min(self.length as usize);
+        (self.data_offset as usize).min(end)..end
+    }
+}
+
+// =============================================================================
+// Plane Layout
+// =============================================================================
+
+/// Offsets of the planes once stitched end to end, and the total size
+pub fn stitched_layout(planes: &[Plane]) -> (PlaneOffsets, usize) {
+    let lengths: Vec<usize> = planes.iter().map(|p| p.data_range().len()).collect();
+    (PlaneOffsets::stitched(&lengths), lengths.iter().sum())
+}
+
+// =============================================================================
+// Capture Stream
+// =============================================================================
+
+struct MappedPlane {
+    ptr: *mut u8,
+    length: usize,
+}
+
+/// Memory-mapped MPLANE capture stream
+pub struct MplaneStream {
+    handle: Arc<Handle>,
+    buffers: Vec<Vec<MappedPlane>>,
+    num_planes: usize,
+    /// Offsets for single-plane buffers (planes packed within one buffer)
+    packed_offsets: PlaneOffsets,
+    stitch_buffer: Vec<u8>,
+    streaming: bool,
+}
+
+// SAFETY: the mappings are only accessed through &mut self
+unsafe impl Send for MplaneStream {}
+
+/// Negotiate an MPLANE capture format; the driver may adjust every field
+pub fn set_format(handle: &Handle, request: PixFormatMplane) -> Result<PixFormatMplane> {
+    let mut format = Format::capture(request);
+    unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            vidioc::VIDIOC_S_FMT,
+            &mut format as *mut Format as *mut c_void,
+        )
+    }
+    .context("VIDIOC_S_FMT (MPLANE) failed")?;
+    Ok(format.pix_mp())
+}
+
+/// Request a capture frame rate. Returns the rate the driver accepted,
+/// which may differ (`rate` itself if the driver doesn't say).
+pub fn set_frame_rate(handle: &Handle, rate: FrameRate) -> Result<FrameRate> {
+    let mut parm: v4l_sys::v4l2_streamparm = unsafe { std::mem::zeroed() };
+    parm.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE;
+    // Intervals are seconds per frame, the inverse of a rate
+    parm.parm.capture = v4l_sys::v4l2_captureparm {
+        timeperframe: v4l_sys::v4l2_fract {
+            numerator: rate.denominator,
+            denominator: rate.numerator,
+        },
+        ..unsafe { std::mem::zeroed() }
+    };
+    unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            vidioc::VIDIOC_S_PARM,
+            &mut parm as *mut _ as *mut c_void,
+        )
+    }
+    .context("VIDIOC_S_PARM (MPLANE) failed")?;
+    let accepted = unsafe { parm.parm.capture.timeperframe };
+    Ok(interval_rate(accepted).unwrap_or(rate))
+}
+
+/// The capture frame rate the device is currently set to
+pub fn frame_rate(handle: &Handle) -> Result<FrameRate> {
+    let mut parm: v4l_sys::v4l2_streamparm = unsafe { std::mem::zeroed() };
+    parm.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE;
+    unsafe {
+        v4l2::ioctl(
+            handle.fd(),
+            vidioc::VIDIOC_G_PARM,
+            &mut parm as *mut _ as *mut c_void,
+        )
+    }
+    .context("VIDIOC_G_PARM (MPLANE) failed")?;
+    interval_rate(unsafe { parm.parm.capture.timeperframe })
+        .context("Driver reports no frame interval")
+}
+
+/// Frame rate of a frame interval (seconds per frame), None if unset
+fn interval_rate(interval: v4l_sys::v4l2_fract) -> Option<FrameRate> {
+    (interval.numerator > 0 && interval.denominator > 0).then_some(FrameRate {
+        numerator: interval.denominator,
+        denominator: interval.numerator,
+    })
+}
+
+impl MplaneStream {
+    /// Allocate, map and queue `count` buffers for `format`, then stream on
+    pub fn new(handle: Arc<Handle>, format: &PixFormatMplane, count: u32) -> Result<Self> {
+        let num_planes = format.plane_count();
+        let mut stream = Self {
+            handle,
+            buffers: Vec::new(),
+            num_planes,
+            packed_offsets: PlaneOffsets::packed(format.fourcc(), format.stride(), format.height),
+            stitch_buffer: Vec::new(),
+            streaming: false,
+        };
+
+        let mut request: v4l_sys::v4l2_requestbuffers = unsafe { std::mem::zeroed() };
+        request.count = count;
+        request.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE;
+        request.memory = V4L2_MEMORY_MMAP;
+        stream
+            .ioctl(vidioc::VIDIOC_REQBUFS, &mut request)
+            .context("VIDIOC_REQBUFS (MPLANE) failed")?;
+        if request.count == 0 {
+            bail!("Driver allocated no MPLANE buffers");
+        }
+
+        for index in 0..request.count {
+            let mut planes = [Plane::default(); VIDEO_MAX_PLANES];
+            let mut buffer = stream.buffer_desc(index, &mut planes);
+            stream
+                .ioctl(vidioc::VIDIOC_QUERYBUF, &mut buffer)
+                .context("VIDIOC_QUERYBUF (MPLANE) failed")?;
+
+            let mut mapped = Vec::with_capacity(num_planes);
+            for plane in &planes[..num_planes] {
+                let length = plane.length as usize;
+                let ptr = unsafe {
+                    v4l2::mmap(
+                        ptr::null_mut(),
+                        length,
+                        libc::PROT_READ | libc::PROT_WRITE,
+                        libc::MAP_SHARED,
+                        stream.handle.fd(),
+                        plane.mem_offset() as libc::off_t,
+                    )
+                }
+                .context("Failed to mmap MPLANE buffer")?;
+                mapped.push(MappedPlane {
+                    ptr: ptr as *mut u8,
+                    length,
+                });
+            }
+            stream.buffers.push(mapped);
+        }
+
+        for index in 0..request.count {
+            stream.queue(index)?;
+        }
+
+        let mut buf_type = V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE as std::os::raw::c_int;
+        stream
+            .ioctl(vidioc::VIDIOC_STREAMON, &mut buf_type)
+            .context("VIDIOC_STREAMON (MPLANE) failed")?;
+        stream.streaming = true;
+
+        tracing::info!(
+            "MPLANE capture: {} buffers x {} plane(s)",
+            stream.buffers.len(),
+            num_planes
+        );
+        Ok(stream)
+    }
+
+    fn ioctl<T>(&self, request: vidioc::_IOC_TYPE, arg: &mut T) -> std::io::Result<()> {
+        unsafe { v4l2::ioctl(self.handle.fd(), request, arg as *mut T as *mut c_void) }
+    }
+
+    fn buffer_desc(&self, index: u32, planes: &mut [Plane]) -> v4l_sys::v4l2_buffer {
+        let mut buffer: v4l_sys::v4l2_buffer = unsafe { std::mem::zeroed() };
+        buffer.index = index;
+        buffer.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE;
+        buffer.memory = V4L2_MEMORY_MMAP;
+        buffer.length = self.num_planes as u32;
+        buffer.m.planes = planes.as_mut_ptr() as *mut _;
+        buffer
+    }
+
+    fn queue(&self, index: u32) -> Result<()> {
+        let mut planes = [Plane::default(); VIDEO_MAX_PLANES];
+        let mut buffer = self.buffer_desc(index, &mut planes);
+        self.ioctl(vidioc::VIDIOC_QBUF, &mut buffer)
+            .context("VIDIOC_QBUF (MPLANE) failed")
+    }
+
+    /// Device fd, for polling before `process`
+    pub fn fd(&self) -> std::os::raw::c_int {
+        self.handle.fd()
+    }
+
+    /// Dequeue the next frame (blocking), pass it to `callback` with its
+    /// plane offsets, then requeue the buffer
+    pub fn process<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnOnce(&[u8], PlaneOffsets),
+    {
+        let mut planes = [Plane::default(); VIDEO_MAX_PLANES];
+        let mut buffer = self.buffer_desc(0, &mut planes);
+        self.ioctl(vidioc::VIDIOC_DQBUF, &mut buffer)
+            .context("VIDIOC_DQBUF (MPLANE) failed")?;
+        let index = buffer.index;
+        let Some(mapped) = self.buffers.get(index as usize) else {
+            bail!("Driver returned unknown buffer index {}", index);
+        };
+        let planes = &planes[..self.num_planes];
+
+        if self.num_planes == 1 {
+            // Zero-copy: planes (if any) are packed within the one buffer
+            let range = planes[0].data_range();
+            let range = range.start.min(mapped[0].length)..range.end.min(mapped[0].length);
+            let data =
+                unsafe { std::slice::from_raw_parts(mapped[0].ptr.add(range.start), range.len()) };
+            callback(data, self.packed_offsets);
+        } else {
+            let (offsets, total) = stitched_layout(planes);
+            self.stitch_buffer.resize(total, 0);
+            let mut at = 0;
+            for (plane, map) in planes.iter().zip(mapped) {
+                let range = plane.data_range();
+                let range = range.start.min(map.length)..range.end.min(map.length);
+                let src =
+                    unsafe { std::slice::from_raw_parts(map.ptr.add(range.start), range.len()) };
+                self.stitch_buffer[at..at + src.len()].copy_from_slice(src);
+                at += src.len();
+            }
+            callback(&self.stitch_buffer[..at], offsets);
+        }
+
+        self.queue(index)
+    }
+
+    /// Dequeue the next frame (blocking) and requeue it untouched
+    pub fn skip(&mut self) -> Result<()> {
+        let mut planes = [Plane::default(); VIDEO_MAX_PLANES];
+        let mut buffer = self.buffer_desc(0, &mut planes);
+        self.ioctl(vidioc::VIDIOC_DQBUF, &mut buffer)
+            .context("VIDIOC_DQBUF (MPLANE) failed")?;
+        self.queue(buffer.index)
+    }
+}
+
+impl Drop for MplaneStream {
+    fn drop(&mut self) {
+        if self.streaming {
+            let mut buf_type = V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE as std::os::raw::c_int;
+            let _ = self.ioctl(vidioc::VIDIOC_STREAMOFF, &mut buf_type);
+        }
+        for plane in self.buffers.drain(..).flatten() {
+            unsafe { libc::munmap(plane.ptr as *mut c_void, plane.length) };
+        }
+        let mut request: v4l_sys::v4l2_requestbuffers = unsafe { std::mem::zeroed() };
+        request.type_ = V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE;
+        request.memory = V4L2_MEMORY_MMAP;
+        let _ = self.ioctl(vidioc::VIDIOC_REQBUFS, &mut request);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane(bytesused: u32, length: u32, data_offset: u32) -> Plane {
+        Plane {
+            bytesused,
+            length,
+            data_offset,
+            ..Plane::default()
+        }
+    }
+
+    #[test]
+    fn test_struct_layout_matches_kernel() {
+        assert_eq!(std::mem::size_of::<PlanePixFormat>(), 20);
+        assert_eq!(std::mem::size_of::<PixFormatMplane>(), 192);
+        assert_eq!(
+            std::mem::size_of::<Format>(),
+            std::mem::size_of::<v4l_sys::v4l2_format>()
+        );
+        #[cfg(target_pointer_width = "64")]
+        assert_eq!(std::mem::size_of::<Plane>(), 64);
+    }
+
+    #[test]
+    fn test_format_population() {
+        let format = Format::capture(PixFormatMplane::new(1920, 1080, FourCC::new(b"NV12")));
+        assert_eq!(format.type_, V4L2_BUF_TYPE_VIDEO_CAPTURE_MPLANE);
+
+        let pix = format.pix_mp();
+        assert_eq!({ pix.width }, 1920);
+        assert_eq!({ pix.height }, 1080);
+        assert_eq!(pix.fourcc(), FourCC::new(b"NV12"));
+        assert_eq!({ pix.field }, 0);
+        assert_eq!(pix.num_planes, 0);
+
+        // The pixel format sits right after the type and padding
+        let raw = unsafe {
+            std::slice::from_raw_parts(
+                &format as *const Format as *const u8,
+                std::mem::size_of::<Format>(),
+            )
+        };
+        assert_eq!(&raw[8..12], &1920u32.to_ne_bytes());
+        assert_eq!(&raw[16..20], b"NV12");
+    }
+
+    #[test]
+    fn test_plane_count_and_stride() {
+        let mut pix = PixFormatMplane::new(1920, 1080, FourCC::new(b"NM12"));
+        assert_eq!(pix.plane_count(), 1);
+        pix.num_planes = 2;
+        pix.plane_fmt[0].bytesperline = 1920;
+        assert_eq!(pix.plane_count(), 2);
+        assert_eq!(pix.stride(), 1920);
+        pix.num_planes = 20;
+        assert_eq!(pix.plane_count(), VIDEO_MAX_PLANES);
+    }
+
+    #[test]
+    fn test_plane_data_range() {
+        assert_eq!(plane(100, 128, 0).data_range(), 0..100);
+        assert_eq!(plane(100, 128, 16).data_range(), 16..100);
+        // bytesused beyond the mapping and offsets past the data are clamped
+        assert_eq!(plane(200, 128, 0).data_range(), 0..128);
+        assert_eq!(plane(10, 128, 16).data_range(), 10..10);
+    }
+
+    #[test]
+    fn test_interval_rate() {
+        let rate = interval_rate(v4l_sys::v4l2_fract {
+            numerator: 1001,
+            denominator: 30000,
+        })
+        .unwrap();
+        assert_eq!((rate.numerator, rate.denominator), (30000, 1001));
+        for (numerator, denominator) in [(0, 60), (1, 0), (0, 0)] {
+            assert!(interval_rate(v4l_sys::v4l2_fract {
+                numerator,
+                denominator
+            })
+            .is_none());
+        }
+    }
+
+    #[test]
+    fn test_stitched_layout_nv12m() {
+        let (width, height) = (1920u32, 1080u32);
+        let planes = [
+            plane(width * height, width * height, 0),
+            plane(width * height / 2 + 64, width * height / 2 + 64, 64),
+        ];
+        let (offsets, total) = stitched_layout(&planes);
+        assert_eq!(offsets.len(), 2);
+        assert_eq!(offsets.get(0), Some(0));
+        assert_eq!(offsets.get(1), Some((width * height) as usize));
+        assert_eq!(total, (width * height * 3 / 2) as usize);
+    }
+}
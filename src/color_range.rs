@@ -0,0 +1,165 @@
+//! YCbCr quantization range
+//!
+//! NDI and the BT.601/709 conversions here expect limited range video, where
+//! black is Y=16 and white Y=235. Some capture dongles deliver full range
+//! (0-255) YUV and say so in the V4L2 quantization field; sending that as is
+//! lifts blacks and clips whites at the receiver, so full range frames are
+//! compressed to limited range before sending.
+
+use anyhow::{anyhow, Result};
+
+/// V4L2 colorspace, Y'CbCr encoding and quantization values used here
+const V4L2_COLORSPACE_JPEG: u32 = 7;
+const V4L2_YCBCR_ENC_XV601: u32 = 3;
+const V4L2_YCBCR_ENC_XV709: u32 = 4;
+const V4L2_QUANTIZATION_FULL_RANGE: u32 = 1;
+const V4L2_QUANTIZATION_LIM_RANGE: u32 = 2;
+
+/// Quantization range of YCbCr samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorRange {
+    /// Y in 16-235, Cb/Cr in 16-240
+    #[default]
+    Limited,
+    /// Y, Cb and Cr use 0-255
+    Full,
+}
+
+impl ColorRange {
+    /// Range of a negotiated V4L2 format, following the kernel's
+    /// `V4L2_MAP_QUANTIZATION_DEFAULT` for YCbCr when the driver leaves the
+    /// quantization at default
+    pub fn from_v4l(colorspace: u32, ycbcr_enc: u32, quantization: u32) -> Self {
+        match quantization {
+            V4L2_QUANTIZATION_FULL_RANGE => ColorRange::Full,
+            V4L2_QUANTIZATION_LIM_RANGE => ColorRange::Limited,
+            // xvYCC is always limited range
+            _ if ycbcr_enc == V4L2_YCBCR_ENC_XV601 || ycbcr_enc == V4L2_YCBCR_ENC_XV709 => {
+                ColorRange::Limited
+            }
+            _ if colorspace == V4L2_COLORSPACE_JPEG => ColorRange::Full,
+            _ => ColorRange::Limited,
+        }
+    }
+}
+
+/// Range handling for the capture source (`capture.range`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeMode {
+    /// Use the range the driver reports
+    Auto,
+    /// Treat the source as limited range regardless of the driver
+    Limited,
+    /// Treat the source as full range regardless of the driver
+    Full,
+}
+
+impl RangeMode {
+    /// Parse a mode name from configuration ("auto", "limited", "full")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Ok(RangeMode::Auto),
+            "limited" => Ok(RangeMode::Limited),
+            "full" => Ok(RangeMode::Full),
+            other => Err(anyhow!(
+                "Unsupported color range: {}. Supported: auto, limited, full",
+                other
+            )),
+        }
+    }
+
+    /// The range to assume given what the driver reported
+    pub fn resolve(self, detected: ColorRange) -> ColorRange {
+        match self {
+            RangeMode::Auto => detected,
+            RangeMode::Limited => ColorRange::Limited,
+            RangeMode::Full => ColorRange::Full,
+        }
+    }
+}
+
+/// Full to limited range lookup for luma: 0-255 onto 16-235
+static LUMA_TO_LIMITED: [u8; 256] = range_table(0, 219, 16);
+
+/// Full to limited range lookup for chroma: 0-255 onto 16-240 around 128
+static CHROMA_TO_LIMITED: [u8; 256] = range_table(128, 224, 128);
+
+/// Table mapping `i` to `base + round((i - offset) * span / 255)`
+pub(crate) const fn range_table(offset: i32, span: i32, base: i32) -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        let scaled = (i as i32 - offset) * span;
+        let rounded = if scaled >= 0 {
+            (scaled + 127) / 255
+        } else {
+            (scaled - 127) / 255
+        };
+        table[i] = (base + rounded) as u8;
+        i += 1;
+    }
+    table
+}
+
+/// Compress full range UYVY to limited range in place
+pub fn uyvy_full_to_limited(uyvy: &mut [u8]) {
+    for px in uyvy.chunks_exact_mut(4) {
+        px[0] = CHROMA_TO_LIMITED[px[0] as usize];
+        px[1] = LUMA_TO_LIMITED[px[1] as usize];
+        px[2] = CHROMA_TO_LIMITED[px[2] as usize];
+        px[3] = LUMA_TO_LIMITED[px[3] as usize];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_v4l() {
+        // Explicit quantization wins
+        assert_eq!(ColorRange::from_v4l(3, 0, 1), ColorRange::Full);
+        assert_eq!(ColorRange::from_v4l(7, 0, 2), ColorRange::Limited);
+        // Default quantization: full only for the JPEG colorspace
+        assert_eq!(ColorRange::from_v4l(7, 0, 0), ColorRange::Full);
+        assert_eq!(
+            ColorRange::from_v4l(7, V4L2_YCBCR_ENC_XV709, 0),
+            ColorRange::Limited
+        );
+        assert_eq!(ColorRange::from_v4l(3, 0, 0), ColorRange::Limited);
+        assert_eq!(ColorRange::from_v4l(0, 0, 0), ColorRange::Limited);
+    }
+
+    #[test]
+    fn test_range_mode() {
+        assert_eq!(RangeMode::from_name("Auto").unwrap(), RangeMode::Auto);
+        assert_eq!(RangeMode::from_name("full").unwrap(), RangeMode::Full);
+        assert!(RangeMode::from_name("pc").is_err());
+        assert_eq!(RangeMode::Auto.resolve(ColorRange::Full), ColorRange::Full);
+        assert_eq!(
+            RangeMode::Limited.resolve(ColorRange::Full),
+            ColorRange::Limited
+        );
+        assert_eq!(
+            RangeMode::Full.resolve(ColorRange::Limited),
+            ColorRange::Full
+        );
+    }
+
+    #[test]
+    fn test_full_to_limited_known_values() {
+        // U Y0 V Y1: black/white endpoints, neutral chroma, chroma extremes
+        let mut uyvy = [128, 0, 128, 255, 0, 128, 255, 64];
+        uyvy_full_to_limited(&mut uyvy);
+        assert_eq!(uyvy, [128, 16, 128, 235, 16, 126, 240, 71]);
+    }
+
+    #[test]
+    fn test_full_to_limited_is_monotonic() {
+        for table in [&LUMA_TO_LIMITED, &CHROMA_TO_LIMITED] {
+            assert!(table.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+        assert_eq!(LUMA_TO_LIMITED[255], 235);
+        assert_eq!(CHROMA_TO_LIMITED[128], 128);
+    }
+}
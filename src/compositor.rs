@@ -0,0 +1,328 @@
+//! Alpha compositing for overlays
+//!
+//! Overlays (logo, text, VU meter, tally) are drawn as BGRA [`Surface`]s
+//! and composited with [`blend_over`]: source-over on premultiplied alpha,
+//! clipped to the destination on all four edges, with an overall opacity.
+//! Surfaces keep their pixels premultiplied, so translucent edges stay
+//! clean when one surface is composited onto another; opaque video frames
+//! are premultiplied as they are.
+//!
+//! Overlays blended straight into UYVY frames use [`mix`] and
+//! [`scale_alpha`], the same operator on one channel over an opaque frame.
+
+use anyhow::{bail, Result};
+
+/// Divide by 255 with rounding, exact for products of two bytes
+#[inline]
+fn div255(x: u32) -> u32 {
+    (x + 128 + ((x + 128) >> 8)) >> 8
+}
+
+/// Opacity 0.0-1.0 as a coverage 0-255
+#[inline]
+fn opacity_to_u8(opacity: f32) -> u32 {
+    (opacity.clamp(0.0, 1.0) * 255.0).round() as u32
+}
+
+/// `alpha` (0-255) scaled by `opacity` (0.0-1.0)
+#[inline]
+pub fn scale_alpha(alpha: u8, opacity: f32) -> u8 {
+    div255(alpha as u32 * opacity_to_u8(opacity)) as u8
+}
+
+/// One channel of a straight-alpha source `src` with coverage `alpha`
+/// (0-255) over an opaque `dst`
+#[inline]
+pub fn mix(dst: u8, src: u8, alpha: u32) -> u8 {
+    div255(src as u32 * alpha + dst as u32 * (255 - alpha)) as u8
+}
+
+/// Composite the premultiplied `src_w`x`src_h` BGRA image `src_bgra` over
+/// the `dst_w`x`dst_h` BGRA buffer `dst_bgra` with its top-left corner at
+/// `x`,`y`, scaled by `opacity` (0.0-1.0). Parts outside the destination
+/// are clipped; either buffer being shorter than its size clips too.
+#[allow(clippy::too_many_arguments)]
+pub fn blend_over(
+    dst_bgra: &mut [u8],
+    src_bgra: &[u8],
+    src_w: u32,
+    src_h: u32,
+    dst_w: u32,
+    dst_h: u32,
+    x: i32,
+    y: i32,
+    opacity: f32,
+) {
+    let opacity = opacity_to_u8(opacity);
+    if opacity == 0 {
+        return;
+    }
+    let (x, y) = (x as i64, y as i64);
+    let left = x.max(0);
+    let top = y.max(0);
+    let right = (x + src_w as i64).min(dst_w as i64);
+    let bottom = (y + src_h as i64).min(dst_h as i64);
+    if left >= right || top >= bottom {
+        return;
+    }
+    let columns = (right - left) as usize;
+    let src_stride = src_w as usize * 4;
+    let dst_stride = dst_w as usize * 4;
+
+    for row in top..bottom {
+        let src_start = (row - y) as usize * src_stride + (left - x) as usize * 4;
+        let dst_start = row as usize * dst_stride + left as usize * 4;
+        let (Some(src), Some(dst)) = (
+            src_bgra.get(src_start..src_start + columns * 4),
+            dst_bgra.get_mut(dst_start..dst_start + columns * 4),
+        ) else {
+            break;
+        };
+        for (d, s) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            let alpha = div255(s[3] as u32 * opacity);
+            if alpha == 0 {
+                continue;
+            }
+            if alpha == 255 && opacity == 255 {
+                d.copy_from_slice(s);
+                continue;
+            }
+            let keep = 255 - alpha;
+            for (dc, &sc) in d.iter_mut().zip(s) {
+                let value = div255(sc as u32 * opacity) + div255(*dc as u32 * keep);
+                *dc = value.min(255) as u8;
+            }
+        }
+    }
+}
+
+/// A premultiplied BGRA image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Surface {
+    data: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl Surface {
+    /// Fully transparent surface
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            data: vec![0; width as usize * height as usize * 4],
+            width,
+            height,
+        }
+    }
+
+    /// Surface from premultiplied BGRA pixels
+    pub fn from_premultiplied(data: Vec<u8>, width: u32, height: u32) -> Result<Self> {
+        let expected = width as usize * height as usize * 4;
+        if width == 0 || height == 0 || data.len() != expected {
+            bail!(
+                "Image is {} bytes, expected {} for {}x{} BGRA",
+                data.len(),
+                expected,
+                width,
+                height
+            );
+        }
+        Ok(Self {
+            data,
+            width,
+            height,
+        })
+    }
+
+    /// Surface from straight (non-premultiplied) BGRA pixels, as image
+    /// files store them
+    pub fn from_bgra(mut data: Vec<u8>, width: u32, height: u32) -> Result<Self> {
+        for pixel in data.chunks_exact_mut(4) {
+            let alpha = pixel[3] as u32;
+            for channel in &mut pixel[..3] {
+                *channel = div255(*channel as u32 * alpha) as u8;
+            }
+        }
+        Self::from_premultiplied(data, width, height)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Premultiplied BGRA pixels, row by row
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Fill with one straight BGRA color
+    pub fn fill(&mut self, bgra: [u8; 4]) {
+        let alpha = bgra[3] as u32;
+        let pixel = [
+            div255(bgra[0] as u32 * alpha) as u8,
+            div255(bgra[1] as u32 * alpha) as u8,
+            div255(bgra[2] as u32 * alpha) as u8,
+            bgra[3],
+        ];
+        for chunk in self.data.chunks_exact_mut(4) {
+            chunk.copy_from_slice(&pixel);
+        }
+    }
+
+    /// Composite `src` over this surface at `x`,`y` (see [`blend_over`])
+    pub fn draw(&mut self, src: &Surface, x: i32, y: i32, opacity: f32) {
+        blend_over(
+            &mut self.data,
+            &src.data,
+            src.width,
+            src.height,
+            self.width,
+            self.height,
+            x,
+            y,
+            opacity,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREY: [u8; 4] = [100, 100, 100, 255];
+
+    fn grey(width: u32, height: u32) -> Surface {
+        let mut surface = Surface::new(width, height);
+        surface.fill(GREY);
+        surface
+    }
+
+    fn pixel(surface: &Surface, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * surface.width() + x) * 4) as usize;
+        surface.data()[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn test_div255_rounds_exactly() {
+        for x in 0..=255 * 255u32 {
+            assert_eq!(div255(x), (x as f64 / 255.0).round() as u32, "{}", x);
+        }
+    }
+
+    #[test]
+    fn test_full_and_zero_alpha() {
+        let mut dst = grey(2, 1);
+        let mut src = Surface::new(2, 1);
+        src.data_mut()[..4].copy_from_slice(&[10, 20, 30, 255]);
+        dst.draw(&src, 0, 0, 1.0);
+        // Opaque pixel replaces, transparent pixel leaves the destination
+        assert_eq!(pixel(&dst, 0, 0), [10, 20, 30, 255]);
+        assert_eq!(pixel(&dst, 1, 0), GREY);
+    }
+
+    #[test]
+    fn test_partial_alpha() {
+        let mut dst = grey(1, 1);
+        let src = Surface::from_bgra(vec![200, 0, 255, 128], 1, 1).unwrap();
+        assert_eq!(src.data(), [100, 0, 128, 128]);
+        dst.draw(&src, 0, 0, 1.0);
+        // Source plus 127/255 of the destination; alpha stays opaque
+        assert_eq!(pixel(&dst, 0, 0), [150, 50, 178, 255]);
+
+        // Onto a transparent surface the premultiplied source comes out as is
+        let mut clear = Surface::new(1, 1);
+        clear.draw(&src, 0, 0, 1.0);
+        assert_eq!(clear.data(), src.data());
+    }
+
+    #[test]
+    fn test_opacity_scaling() {
+        let mut src = Surface::new(1, 1);
+        src.fill([255, 255, 255, 255]);
+        let mut dst = Surface::new(1, 1);
+        dst.fill([0, 0, 0, 255]);
+
+        let mut half = dst.clone();
+        half.draw(&src, 0, 0, 0.5);
+        assert_eq!(pixel(&half, 0, 0), [128, 128, 128, 255]);
+
+        let mut none = dst.clone();
+        none.draw(&src, 0, 0, 0.0);
+        assert_eq!(none, dst);
+
+        // Opacity above 1.0 is clamped
+        let mut full = dst.clone();
+        full.draw(&src, 0, 0, 2.0);
+        assert_eq!(pixel(&full, 0, 0), [255, 255, 255, 255]);
+
+        assert_eq!(scale_alpha(255, 0.5), 128);
+        assert_eq!(scale_alpha(200, 1.0), 200);
+        assert_eq!(scale_alpha(200, 0.0), 0);
+    }
+
+    #[test]
+    fn test_clipping_at_all_edges() {
+        let mut src = Surface::new(3, 3);
+        src.fill([255, 255, 255, 255]);
+        let white = [255, 255, 255, 255];
+
+        // Hanging off the top-left corner: only the bottom-right 2x2 lands
+        let mut dst = grey(4, 4);
+        dst.draw(&src, -1, -1, 1.0);
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected = if x < 2 && y < 2 { white } else { GREY };
+                assert_eq!(pixel(&dst, x, y), expected, "{},{}", x, y);
+            }
+        }
+
+        // Hanging off the bottom-right corner
+        let mut dst = grey(4, 4);
+        dst.draw(&src, 2, 3, 1.0);
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected = if x >= 2 && y >= 3 { white } else { GREY };
+                assert_eq!(pixel(&dst, x, y), expected, "{},{}", x, y);
+            }
+        }
+
+        // Entirely outside on every side
+        for (x, y) in [(-3, 0), (4, 0), (0, -3), (0, 4), (i32::MIN, i32::MAX)] {
+            let mut dst = grey(4, 4);
+            dst.draw(&src, x, y, 1.0);
+            assert_eq!(dst, grey(4, 4));
+        }
+
+        // Short buffers clip instead of panicking
+        let mut short = vec![0u8; 4 * 4 * 2];
+        blend_over(&mut short, src.data(), 3, 3, 4, 4, 0, 0, 1.0);
+        assert_eq!(&short[..4], white);
+    }
+
+    #[test]
+    fn test_surface_rejects_wrong_size() {
+        assert!(Surface::from_bgra(vec![0; 12], 2, 2).is_err());
+        assert!(Surface::from_premultiplied(Vec::new(), 0, 0).is_err());
+    }
+
+    #[test]
+    fn test_mix_matches_blend_over_on_opaque_frames() {
+        for alpha in [0u8, 1, 64, 128, 200, 255] {
+            let src = Surface::from_bgra(vec![235, 16, 128, alpha], 1, 1).unwrap();
+            let mut dst = grey(1, 1);
+            dst.draw(&src, 0, 0, 1.0);
+            let blended = pixel(&dst, 0, 0);
+            for ((&out, &frame), &color) in blended.iter().zip(&GREY).zip(&[235, 16, 128]) {
+                let mixed = mix(frame, color, alpha as u32);
+                assert!((out as i32 - mixed as i32).abs() <= 1, "alpha {}", alpha);
+            }
+        }
+    }
+}
@@ -1,10 +1,16 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use serde::Deserialize;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Deserialize)]
+use crate::placeholders::{self, SystemValues};
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct Config {
+    /// Treat unknown keys as errors instead of warnings (default: false)
+    #[serde(default)]
+    pub strict: bool,
+
     /// Device hostname
     #[serde(default = "default_hostname")]
     pub hostname: String,
@@ -13,10 +19,49 @@ pub struct Config {
     #[serde(default = "default_ndi_name")]
     pub ndi_name: String,
 
-    /// Video capture device path ("auto" for auto-detection)
+    /// Video capture device path ("auto" for auto-detection, "image:PATH" for a
+    /// slate, "none" for no camera)
     #[serde(default = "default_device")]
     pub device: String,
 
+    /// NDI groups the source is advertised in, comma-separated (default: public group)
+    #[serde(default)]
+    pub ndi_groups: Option<String>,
+
+    /// Reload the NDI library when restarting a failing sender (default: false)
+    #[serde(default)]
+    pub ndi_reload_library: bool,
+
+    /// Unix socket for runtime control commands, empty to disable (default: "/run/camera-box.sock")
+    #[serde(default = "default_control_socket")]
+    pub control_socket: String,
+
+    /// Announce the box via mDNS/DNS-SD as _camera-box._tcp (default: false)
+    #[serde(default)]
+    pub announce: bool,
+
+    /// Port of the status page and its HTTP control endpoints, also
+    /// advertised in the mDNS announcement; 0 disables the page (default: 8080)
+    #[serde(default = "default_status_port")]
+    pub status_port: u16,
+
+    /// Seconds between the consolidated stats log lines (default: 10)
+    #[serde(default = "default_stats_interval_secs")]
+    pub stats_interval_secs: u64,
+
+    /// Where frame totals and the last run's length are kept between runs,
+    /// empty disables (default: /var/lib/camera-box/state.toml)
+    #[serde(default = "default_state_file")]
+    pub state_file: String,
+
+    /// Capture pipeline settings ([capture])
+    #[serde(default)]
+    pub capture: CaptureConfig,
+
+    /// NDI output settings ([ndi])
+    #[serde(default)]
+    pub ndi: NdiConfig,
+
     /// NDI display configuration (optional)
     #[serde(default)]
     pub display: Option<DisplayConfig>,
@@ -24,9 +69,488 @@ pub struct Config {
     /// VBAN intercom configuration (optional)
     #[serde(default)]
     pub intercom: Option<IntercomConfig>,
+
+    /// VBAN serial bridge to a local UART (optional)
+    #[serde(default)]
+    pub serial: Option<SerialConfig>,
+
+    /// Local recording of the frames sent ([record])
+    #[serde(default)]
+    pub record: RecordConfig,
+
+    /// Secondary outputs beside NDI ([[output]], optional)
+    #[serde(default, rename = "output")]
+    pub outputs: Vec<OutputConfig>,
+
+    /// Status page settings ([status])
+    #[serde(default)]
+    pub status: StatusConfig,
+
+    /// How long components wait for their prerequisites at boot ([startup])
+    #[serde(default)]
+    pub startup: StartupConfig,
+
+    /// Network provisioning for `camera-box netcfg apply` (optional)
+    #[serde(default)]
+    pub network: Option<NetworkConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CaptureConfig {
+    /// Interlaced source handling: "off", "bob", "blend" or "interlaced" (default: "off")
+    #[serde(default = "default_deinterlace")]
+    pub deinterlace: String,
+
+    /// Reopen the device after this many seconds without a frame, 0 disables (default: 5)
+    #[serde(default = "default_stall_timeout_secs")]
+    pub stall_timeout_secs: u64,
+
+    /// Re-enumerate the USB dongle when two reopens in a row don't bring
+    /// frames back; needs root (default: false)
+    #[serde(default)]
+    pub usb_reset: bool,
+
+    /// Where adjusted camera controls are saved and restored from, empty disables
+    /// (default: /var/lib/camera-box/controls.toml)
+    #[serde(default = "default_controls_file")]
+    pub controls_file: String,
+
+    /// YUV quantization range of the source: "auto" (as the driver reports),
+    /// "limited" or "full" (default: "auto")
+    #[serde(default = "default_range")]
+    pub range: String,
+
+    /// Capture rate to ask for, e.g. "60", "59.94" or "60000/1001"
+    /// (default: the highest offered up to 60)
+    #[serde(default)]
+    pub fps: Option<String>,
+
+    /// Pixel formats capture may use, most preferred first. Size and rate
+    /// are matched first, so this only orders formats that reach both;
+    /// formats left out are never picked
+    /// (default: UYVY, YUYV, NV12, NM12, BGRA, BGR4, RX24, MJPG)
+    #[serde(default = "default_format_priority")]
+    pub format_priority: Vec<String>,
+
+    /// How capture buffers reach the process: "mmap" or "dmabuf", which
+    /// falls back to mmap when the driver can't export (default: "mmap")
+    #[serde(default = "default_io_mode")]
+    pub io_mode: String,
+
+    /// Time each frame's dequeue, conversion, send and requeue and report
+    /// p50/p95/p99 per stage in the stats (default: false)
+    #[serde(default)]
+    pub timing: bool,
+
+    /// Embedded HDMI audio from the dongle's ALSA device ([capture.audio], optional)
+    #[serde(default)]
+    pub audio: Option<CaptureAudioConfig>,
+
+    /// Slate settings for `device = "image:PATH"` ([capture.image])
+    #[serde(default)]
+    pub image: ImageSourceConfig,
+
+    /// Raw clip settings for `device = "file:PATH"` ([capture.file])
+    #[serde(default)]
+    pub file: FileSourceConfig,
+
+    /// Region of the frame to send ([capture.crop], optional)
+    #[serde(default)]
+    pub crop: Option<CropConfig>,
+
+    /// Thumbnails of the last frames sent, for instant replay ([capture.replay], optional)
+    #[serde(default)]
+    pub replay: Option<ReplayConfig>,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            deinterlace: default_deinterlace(),
+            stall_timeout_secs: default_stall_timeout_secs(),
+            usb_reset: false,
+            controls_file: default_controls_file(),
+            range: default_range(),
+            fps: None,
+            format_priority: default_format_priority(),
+            io_mode: default_io_mode(),
+            timing: false,
+            audio: None,
+            image: ImageSourceConfig::default(),
+            file: FileSourceConfig::default(),
+            crop: None,
+            replay: None,
+        }
+    }
+}
+
+impl CaptureConfig {
+    /// The rate the capture mode is picked for
+    pub fn frame_rate(&self) -> Result<crate::capture::FrameRate> {
+        match &self.fps {
+            Some(fps) => crate::capture::FrameRate::parse(fps),
+            None => Ok(crate::probe::TARGET_RATE),
+        }
+    }
+
+    /// `format_priority` as fourccs
+    pub fn formats(&self) -> Result<Vec<v4l::FourCC>> {
+        crate::probe::parse_format_priority(&self.format_priority)
+    }
+}
+
+fn default_format_priority() -> Vec<String> {
+    crate::probe::CONVERTIBLE_FOURCCS
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct NdiConfig {
+    /// Output pacing: "off", "clock_video" or "software" (default: "off")
+    #[serde(default = "default_pacing")]
+    pub pacing: String,
+
+    /// Target frame rate numerator for pacing (default: capture rate)
+    #[serde(default)]
+    pub frame_rate_n: Option<u32>,
+
+    /// Target frame rate denominator for pacing (default: capture rate)
+    #[serde(default)]
+    pub frame_rate_d: Option<u32>,
+
+    /// When another machine already advertises our NDI name: "suffix",
+    /// "fail" or "off" to skip the check (default: "suffix")
+    #[serde(default = "default_on_conflict")]
+    pub on_conflict: String,
+
+    /// Burn the wall-clock time into the top-left corner (default: false)
+    #[serde(default)]
+    pub timestamp_burn_in: bool,
+
+    /// Image composited onto every frame ([ndi.overlay], optional)
+    #[serde(default)]
+    pub overlay: Option<OverlayConfig>,
+
+    /// Stream only the intercom mic, without a capture device (default: false)
+    #[serde(default)]
+    pub audio_only: bool,
+
+    /// Send UYVY capture buffers asynchronously without copying (default: false)
+    #[serde(default)]
+    pub zero_copy_uyvy: bool,
+
+    /// Picture aspect ratio sent with each frame: "auto" (inferred for
+    /// anamorphic sizes such as 1440x1080), "16:9", "4:3" or a number
+    /// (default: "auto")
+    #[serde(default)]
+    pub aspect: AspectValue,
+
+    /// Low-resolution second NDI sender for multiviewers ([ndi.proxy])
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+}
+
+impl Default for NdiConfig {
+    fn default() -> Self {
+        Self {
+            pacing: default_pacing(),
+            frame_rate_n: None,
+            frame_rate_d: None,
+            on_conflict: default_on_conflict(),
+            timestamp_burn_in: false,
+            overlay: None,
+            audio_only: false,
+            zero_copy_uyvy: false,
+            aspect: AspectValue::default(),
+            proxy: ProxyConfig::default(),
+        }
+    }
+}
+
+/// An aspect ratio name or a number
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum AspectValue {
+    Name(String),
+    Ratio(f32),
+}
+
+impl Default for AspectValue {
+    fn default() -> Self {
+        AspectValue::Name("auto".to_string())
+    }
+}
+
+impl AspectValue {
+    pub fn aspect(&self) -> Result<crate::aspect::AspectRatio> {
+        match self {
+            AspectValue::Name(name) => crate::aspect::AspectRatio::from_name(name),
+            AspectValue::Ratio(ratio) => crate::aspect::AspectRatio::from_ratio(*ratio),
+        }
+    }
+}
+
+fn default_pacing() -> String {
+    "off".to_string()
+}
+
+fn default_on_conflict() -> String {
+    "suffix".to_string()
+}
+
+/// Scaled-down copy of the stream under its own NDI name, see `ndi_proxy`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ProxyConfig {
+    /// Publish the proxy (default: false)
+    #[serde(default)]
+    pub enable: bool,
+
+    /// Appended to the NDI name to name the proxy (default: "-proxy")
+    #[serde(default = "default_proxy_name_suffix")]
+    pub name_suffix: String,
+
+    /// Frame width in pixels, even (default: 640)
+    #[serde(default = "default_output_width")]
+    pub width: u32,
+
+    /// Frame height in pixels (default: 360)
+    #[serde(default = "default_output_height")]
+    pub height: u32,
+
+    /// Send every Nth frame (default: 2)
+    #[serde(default = "default_proxy_fps_divisor")]
+    pub fps_divisor: u32,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            name_suffix: default_proxy_name_suffix(),
+            width: default_output_width(),
+            height: default_output_height(),
+            fps_divisor: default_proxy_fps_divisor(),
+        }
+    }
+}
+
+fn default_proxy_name_suffix() -> String {
+    "-proxy".to_string()
+}
+
+fn default_proxy_fps_divisor() -> u32 {
+    2
+}
+
+/// Raw BGRA image overlaid at `x`,`y`; x is rounded down to even
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct OverlayConfig {
+    /// Raw BGRA file, width * height * 4 bytes
+    pub image: String,
+
+    pub width: u32,
+    pub height: u32,
+
+    /// Left edge (default: 0)
+    #[serde(default)]
+    pub x: u32,
+
+    /// Top edge (default: 0)
+    #[serde(default)]
+    pub y: u32,
+
+    /// Opacity applied on top of the image's alpha, 0.0-1.0 (default: 1.0)
+    #[serde(default = "default_overlay_alpha")]
+    pub alpha: f32,
+}
+
+fn default_overlay_alpha() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CaptureAudioConfig {
+    /// ALSA capture device, e.g. "hw:CARD=MS2109"
+    pub device: String,
+
+    /// Number of audio channels (default: 2)
+    #[serde(default = "default_capture_audio_channels")]
+    pub channels: u32,
+
+    /// Sample rate in Hz (default: 48000)
+    #[serde(default = "default_capture_audio_sample_rate")]
+    pub sample_rate: u32,
+
+    /// Device workaround: "ms2109" for dongles that report 96kHz mono but
+    /// deliver 48kHz stereo (default: none)
+    #[serde(default)]
+    pub quirk: String,
+}
+
+/// Output format of the still-image slate source
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ImageSourceConfig {
+    /// Frame width; the image is letterboxed into it (default: 1920)
+    #[serde(default = "default_image_width")]
+    pub width: u32,
+
+    /// Frame height (default: 1080)
+    #[serde(default = "default_image_height")]
+    pub height: u32,
+
+    /// Frame rate numerator (default: 30)
+    #[serde(default = "default_image_frame_rate_n")]
+    pub frame_rate_n: u32,
+
+    /// Frame rate denominator (default: 1)
+    #[serde(default = "default_image_frame_rate_d")]
+    pub frame_rate_d: u32,
+}
+
+impl Default for ImageSourceConfig {
+    fn default() -> Self {
+        Self {
+            width: default_image_width(),
+            height: default_image_height(),
+            frame_rate_n: default_image_frame_rate_n(),
+            frame_rate_d: default_image_frame_rate_d(),
+        }
+    }
+}
+
+fn default_image_width() -> u32 {
+    1920
+}
+
+fn default_image_height() -> u32 {
+    1080
+}
+
+fn default_image_frame_rate_n() -> u32 {
+    30
+}
+
+fn default_image_frame_rate_d() -> u32 {
+    1
+}
+
+/// Frame format of the raw clip source; a `PATH.toml` sidecar next to the
+/// clip overrides any of these
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct FileSourceConfig {
+    /// Frame width (default: 1920)
+    #[serde(default = "default_image_width")]
+    pub width: u32,
+
+    /// Frame height (default: 1080)
+    #[serde(default = "default_image_height")]
+    pub height: u32,
+
+    /// Pixel format of the raw frames: "UYVY" or "YUYV" (default: "UYVY")
+    #[serde(default = "default_file_format")]
+    pub format: String,
+
+    /// Frame rate numerator (default: 30)
+    #[serde(default = "default_image_frame_rate_n")]
+    pub frame_rate_n: u32,
+
+    /// Frame rate denominator (default: 1)
+    #[serde(default = "default_image_frame_rate_d")]
+    pub frame_rate_d: u32,
+
+    /// Start over at the end of the clip; off, the source stops sending
+    /// (default: true)
+    #[serde(default = "default_loop_at_eof")]
+    pub loop_at_eof: bool,
+}
+
+impl Default for FileSourceConfig {
+    fn default() -> Self {
+        Self {
+            width: default_image_width(),
+            height: default_image_height(),
+            format: default_file_format(),
+            frame_rate_n: default_image_frame_rate_n(),
+            frame_rate_d: default_image_frame_rate_d(),
+            loop_at_eof: default_loop_at_eof(),
+        }
+    }
+}
+
+fn default_file_format() -> String {
+    "UYVY".to_string()
+}
+
+fn default_loop_at_eof() -> bool {
+    true
+}
+
+/// Crop rectangle in pixels; x and width are rounded down to even
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct CropConfig {
+    /// Left edge (default: 0)
+    #[serde(default)]
+    pub x: u32,
+
+    /// Top edge (default: 0)
+    #[serde(default)]
+    pub y: u32,
+
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Ring of 480p thumbnails dumped as PNGs on SIGUSR2 or `dump-ring`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ReplayConfig {
+    /// Thumbnails kept (default: 120)
+    #[serde(default = "default_replay_frames")]
+    pub frames: usize,
+
+    /// Directory dumps are written to (default: /var/lib/camera-box/replay)
+    #[serde(default = "default_replay_dir")]
+    pub dir: String,
+}
+
+fn default_replay_frames() -> usize {
+    120
+}
+
+fn default_replay_dir() -> String {
+    "/var/lib/camera-box/replay".to_string()
+}
+
+fn default_capture_audio_channels() -> u32 {
+    2
+}
+
+fn default_capture_audio_sample_rate() -> u32 {
+    48000
+}
+
+fn default_stall_timeout_secs() -> u64 {
+    5
+}
+
+fn default_controls_file() -> String {
+    crate::camera_controls::CONTROLS_FILE.to_string()
+}
+
+fn default_deinterlace() -> String {
+    "off".to_string()
+}
+
+fn default_range() -> String {
+    "auto".to_string()
+}
+
+fn default_io_mode() -> String {
+    "mmap".to_string()
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct DisplayConfig {
     /// NDI source name to display (partial match)
     pub source: String,
@@ -34,18 +558,87 @@ pub struct DisplayConfig {
     /// Framebuffer device (default: /dev/fb0)
     #[serde(default = "default_fb_device")]
     pub fb_device: String,
+
+    /// Color format requested from the sender: "uyvy" (converted here) or
+    /// "bgra" (converted by NDI; default: "uyvy")
+    #[serde(default = "default_display_color_format")]
+    pub color_format: String,
+
+    /// Drop received frames older than this by their NDI timestamp, 0
+    /// disables (default: 100)
+    #[serde(default = "default_display_max_age_ms")]
+    pub max_age_ms: u64,
+
+    /// Most frames drawn per second; frames in between are received but
+    /// skipped, 0 draws every frame (default: 30)
+    #[serde(default = "default_display_max_fps")]
+    pub max_fps: u32,
+
+    /// Draw a luma histogram in the bottom-left corner (UYVY only)
+    #[serde(default)]
+    pub histogram: bool,
+
+    /// Draw zebra stripes over areas above `zebra_percent` (UYVY only)
+    #[serde(default)]
+    pub zebra: bool,
+
+    /// Zebra threshold in percent of the video range (default: 95)
+    #[serde(default = "default_zebra_percent")]
+    pub zebra_percent: u8,
+
+    /// Brightness of the standby screen once dimmed, 0.0-1.0 (default: 0.3)
+    #[serde(default = "default_standby_dim")]
+    pub standby_dim: f32,
+
+    /// Seconds without video before the standby screen dims (default: 600)
+    #[serde(default = "default_standby_dim_secs")]
+    pub standby_dim_secs: u64,
+
+    /// Minutes without video before camera-box exits for systemd to start
+    /// again later, 0 never exits (default: 0)
+    #[serde(default)]
+    pub idle_exit_mins: u64,
 }
 
 fn default_fb_device() -> String {
     "/dev/fb0".to_string()
 }
 
-#[derive(Debug, Deserialize, Clone)]
+fn default_display_color_format() -> String {
+    "uyvy".to_string()
+}
+
+fn default_display_max_age_ms() -> u64 {
+    100
+}
+
+fn default_display_max_fps() -> u32 {
+    crate::ndi_display::DEFAULT_MAX_FPS
+}
+
+fn default_zebra_percent() -> u8 {
+    crate::exposure::DEFAULT_ZEBRA_PERCENT
+}
+
+fn default_standby_dim() -> f32 {
+    crate::standby::DEFAULT_DIM
+}
+
+fn default_standby_dim_secs() -> u64 {
+    crate::standby::DEFAULT_DIM_AFTER.as_secs()
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct IntercomConfig {
     /// VBAN stream name (default: "cam1")
     #[serde(default = "default_intercom_stream")]
     pub stream: String,
 
+    /// Accept incoming VBAN whose stream name differs only in ASCII case
+    /// (default: false)
+    #[serde(default)]
+    pub stream_ignore_case: bool,
+
     /// Target host for VBAN (default: "strih.lan")
     #[serde(default = "default_intercom_target")]
     pub target: String,
@@ -77,31 +670,306 @@ pub struct IntercomConfig {
     /// Limiter threshold as fraction of max (default: 0.5 = -6dB)
     #[serde(default = "default_limiter_threshold")]
     pub limiter_threshold: f32,
-}
 
-fn default_intercom_stream() -> String {
-    "cam1".to_string()
-}
+    /// Hosts allowed to send VBAN audio, as IPs or hostnames (default: any)
+    #[serde(default)]
+    pub allowed_sources: Vec<String>,
 
-fn default_intercom_target() -> String {
-    "strih.lan".to_string()
-}
+    /// Latch onto the first VBAN source seen and reject others (default: false)
+    #[serde(default)]
+    pub auto_lock: bool,
 
-fn default_intercom_sample_rate() -> u32 {
-    48000
-}
+    /// Time without VBAN packets before the link is reported down (default: 2000)
+    #[serde(default = "default_link_timeout_ms")]
+    pub link_timeout_ms: u64,
 
-fn default_intercom_channels() -> u8 {
-    2
-}
+    /// Transmit codec: "pcm16", "pcm24" or "float32" (default: "pcm16")
+    #[serde(default = "default_tx_codec")]
+    pub tx_codec: String,
 
-fn default_sidetone_gain() -> f32 {
-    100.0 // Direct gain multiplier for sidetone
-}
+    /// Send an unsolicited VBAN identification to the target every 10s (default: false)
+    #[serde(default)]
+    pub ping_announce: bool,
 
-fn default_mic_gain() -> f32 {
-    12.0 // +22dB boost for outbound mic
-}
+    /// DSCP value for VBAN packets (default: 46 = Expedited Forwarding)
+    #[serde(default = "default_dscp")]
+    pub dscp: u8,
+
+    /// VBAN receive socket buffer size in bytes (default: 262144)
+    #[serde(default = "default_recv_buffer_size")]
+    pub recv_buffer_size: usize,
+
+    /// Network interface to pin intercom sockets to, e.g. "eth0" (default: any)
+    #[serde(default)]
+    pub interface: Option<String>,
+
+    /// UDP port of the target's VBAN receiver (default: 6980)
+    #[serde(default = "default_intercom_port")]
+    pub port: u16,
+
+    /// Local address for the VBAN receiver; port 0 picks an ephemeral port and
+    /// "[::]" listens on IPv6 and IPv4 alike (default: "[::]:6980")
+    #[serde(default = "default_intercom_listen")]
+    pub listen: String,
+
+    /// Local IP the VBAN sender binds to (default: any, of the target's address family)
+    #[serde(default)]
+    pub bind_address: Option<String>,
+
+    /// Directory for troubleshooting WAV recordings of rx/tx audio (default: off)
+    #[serde(default)]
+    pub record_dir: Option<String>,
+
+    /// Length of each recording segment in seconds (default: 60)
+    #[serde(default = "default_record_segment_secs")]
+    pub record_segment_secs: u64,
+
+    /// Number of recording segments kept per direction (default: 10)
+    #[serde(default = "default_record_keep")]
+    pub record_keep: usize,
+
+    /// Echo suppression settings ([intercom.echo])
+    #[serde(default)]
+    pub echo: EchoConfig,
+
+    /// Dead mic detection settings ([intercom.silence])
+    #[serde(default)]
+    pub silence: SilenceConfig,
+
+    /// Ear placement of each headphone source ([intercom.routing])
+    #[serde(default)]
+    pub routing: RoutingConfig,
+
+    /// evdev key name that toggles mute, e.g. "KEY_F13" for a USB keypad (default: "KEY_POWER")
+    #[serde(default = "default_mute_key")]
+    pub mute_key: String,
+
+    /// LED of the mute key devices that shows mute: "auto" (a headset's
+    /// mute LED, else scroll lock), "none" or "scrolllock" (default: "auto")
+    #[serde(default = "default_mute_led")]
+    pub mute_led: String,
+
+    /// GPIO mute button as "gpiochipN:offset" (default: none)
+    #[serde(default)]
+    pub button_gpio: Option<String>,
+
+    /// Red/green tally LED GPIO lines (default: none)
+    #[serde(default)]
+    pub tally_led_gpio: Option<TallyLedConfig>,
+
+    /// Tally that opens the mic by itself: "never", "preview" (preview or
+    /// program) or "program" (default: "never")
+    #[serde(default = "default_auto_unmute")]
+    pub auto_unmute: String,
+
+    /// Mix a short tone burst into the tx audio when a long press on the
+    /// mute button calls the director (default: true)
+    #[serde(default = "default_call_tone")]
+    pub call_tone: bool,
+
+    /// Channel of a stereo capture device used as the mic: "left", "right"
+    /// or "mix" (average of both); mono devices ignore it (default: "mix")
+    #[serde(default = "default_mic_channel")]
+    pub mic_channel: String,
+
+    /// ALSA period size in frames; devices that refuse it get 128, 256, 512
+    /// or 1024 frames, the first that works above it (default: 256)
+    #[serde(default = "default_period_frames")]
+    pub period_frames: u32,
+
+    /// ALSA periods per buffer (default: 4)
+    #[serde(default = "default_periods")]
+    pub periods: u32,
+}
+
+/// GPIO lines of a bi-color tally LED
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct TallyLedConfig {
+    /// GPIO chip name, e.g. "gpiochip0"
+    pub chip: String,
+    /// Line offset of the red element
+    pub red: u32,
+    /// Line offset of the green element
+    pub green: u32,
+}
+
+/// Ear placement of the headphone mix sources: "left", "right", "both" or a
+/// pan from -1.0 (left) to 1.0 (right)
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct RoutingConfig {
+    /// Incoming VBAN audio (default: "both")
+    #[serde(default)]
+    pub vban: RouteValue,
+
+    /// Own microphone (default: "both")
+    #[serde(default)]
+    pub sidetone: RouteValue,
+
+    /// Link up/down notification tones (default: "both")
+    #[serde(default)]
+    pub tones: RouteValue,
+}
+
+impl RoutingConfig {
+    pub fn routing(&self) -> Result<crate::intercom::Routing> {
+        Ok(crate::intercom::Routing {
+            vban: self.vban.route()?,
+            sidetone: self.sidetone.route()?,
+            tones: self.tones.route()?,
+        })
+    }
+}
+
+/// A route name or a pan position
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum RouteValue {
+    Name(String),
+    Pan(f32),
+}
+
+impl Default for RouteValue {
+    fn default() -> Self {
+        RouteValue::Name("both".to_string())
+    }
+}
+
+impl RouteValue {
+    pub fn route(&self) -> Result<crate::intercom::Route> {
+        match self {
+            RouteValue::Name(name) => crate::intercom::Route::from_name(name),
+            RouteValue::Pan(pan) => crate::intercom::Route::from_pan(*pan),
+        }
+    }
+}
+
+/// Half-duplex style echo suppressor for open-ear headsets
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct EchoConfig {
+    /// Enable echo suppression (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Incoming VBAN RMS level (fraction of full scale) that counts as far-end speech (default: 0.02)
+    #[serde(default = "default_echo_far_threshold")]
+    pub far_threshold: f32,
+
+    /// Mic RMS level below which the mic is treated as echo only (default: 0.05)
+    #[serde(default = "default_echo_near_threshold")]
+    pub near_threshold: f32,
+
+    /// Attenuation applied to the mic while ducking, in dB (default: 20.0)
+    #[serde(default = "default_echo_duck_db")]
+    pub duck_db: f32,
+
+    /// Time to reach full ducking in ms (default: 10.0)
+    #[serde(default = "default_echo_attack_ms")]
+    pub attack_ms: f32,
+
+    /// Time to recover from ducking in ms (default: 200.0)
+    #[serde(default = "default_echo_release_ms")]
+    pub release_ms: f32,
+}
+
+impl Default for EchoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            far_threshold: default_echo_far_threshold(),
+            near_threshold: default_echo_near_threshold(),
+            duck_db: default_echo_duck_db(),
+            attack_ms: default_echo_attack_ms(),
+            release_ms: default_echo_release_ms(),
+        }
+    }
+}
+
+/// Dead mic detection: warns when the unmuted mic stays below a level
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct SilenceConfig {
+    /// Enable silence detection (default: true)
+    #[serde(default = "default_silence_enabled")]
+    pub enabled: bool,
+
+    /// Captured RMS level in dBFS, before the mic gain, below which the mic is silent (default: -60.0)
+    #[serde(default = "default_silence_threshold_db")]
+    pub threshold_db: f32,
+
+    /// Seconds below the threshold while unmuted before warning (default: 60)
+    #[serde(default = "default_silence_secs")]
+    pub secs: u64,
+
+    /// Also notify the intercom target with a VBAN TEXT packet (default: false)
+    #[serde(default)]
+    pub notify: bool,
+}
+
+impl Default for SilenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_silence_enabled(),
+            threshold_db: default_silence_threshold_db(),
+            secs: default_silence_secs(),
+            notify: false,
+        }
+    }
+}
+
+fn default_silence_enabled() -> bool {
+    true
+}
+
+fn default_silence_threshold_db() -> f32 {
+    -60.0 // Below the room tone of a working lav, above an open input
+}
+
+fn default_silence_secs() -> u64 {
+    60
+}
+
+fn default_echo_far_threshold() -> f32 {
+    0.02 // ~-34dBFS
+}
+
+fn default_echo_near_threshold() -> f32 {
+    0.05 // ~-26dBFS, above typical earpiece leakage
+}
+
+fn default_echo_duck_db() -> f32 {
+    20.0
+}
+
+fn default_echo_attack_ms() -> f32 {
+    10.0
+}
+
+fn default_echo_release_ms() -> f32 {
+    200.0 // Slow enough not to clip speech onsets after far-end pauses
+}
+
+fn default_intercom_stream() -> String {
+    "cam1".to_string()
+}
+
+fn default_intercom_target() -> String {
+    "strih.lan".to_string()
+}
+
+fn default_intercom_sample_rate() -> u32 {
+    48000
+}
+
+fn default_intercom_channels() -> u8 {
+    2
+}
+
+fn default_sidetone_gain() -> f32 {
+    100.0 // Direct gain multiplier for sidetone
+}
+
+fn default_mic_gain() -> f32 {
+    12.0 // +22dB boost for outbound mic
+}
 
 fn default_headphone_gain() -> f32 {
     15.0 // Headphone volume from strih.lan
@@ -115,29 +983,346 @@ fn default_limiter_threshold() -> f32 {
     0.5 // -6dB ceiling - balanced headroom with protection
 }
 
+fn default_link_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_tx_codec() -> String {
+    "pcm16".to_string()
+}
+
+fn default_dscp() -> u8 {
+    46 // Expedited Forwarding, prioritized by the switches
+}
+
+fn default_recv_buffer_size() -> usize {
+    256 * 1024
+}
+
+fn default_intercom_port() -> u16 {
+    6980
+}
+
+fn default_intercom_listen() -> String {
+    "[::]:6980".to_string()
+}
+
+fn default_record_segment_secs() -> u64 {
+    60
+}
+
+fn default_record_keep() -> usize {
+    10
+}
+
+fn default_mute_key() -> String {
+    "KEY_POWER".to_string()
+}
+
+fn default_mute_led() -> String {
+    "auto".to_string()
+}
+
+fn default_auto_unmute() -> String {
+    "never".to_string()
+}
+
+fn default_call_tone() -> bool {
+    true
+}
+
+fn default_mic_channel() -> String {
+    "mix".to_string()
+}
+
+fn default_period_frames() -> u32 {
+    256
+}
+
+fn default_periods() -> u32 {
+    4
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            strict: false,
             hostname: default_hostname(),
             ndi_name: default_ndi_name(),
             device: default_device(),
+            ndi_groups: None,
+            ndi_reload_library: false,
+            control_socket: default_control_socket(),
+            announce: false,
+            status_port: default_status_port(),
+            stats_interval_secs: default_stats_interval_secs(),
+            state_file: default_state_file(),
+            capture: CaptureConfig::default(),
+            ndi: NdiConfig::default(),
             display: None,
             intercom: None,
+            serial: None,
+            record: RecordConfig::default(),
+            outputs: Vec::new(),
+            status: StatusConfig::default(),
+            startup: StartupConfig::default(),
+            network: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct SerialConfig {
+    /// UART device (default: "/dev/ttyUSB0")
+    #[serde(default = "default_serial_device")]
+    pub device: String,
+
+    /// Baud rate, 8N1 without flow control (default: 9600)
+    #[serde(default = "default_serial_baud")]
+    pub baud: u32,
+
+    /// VBAN serial stream name, for both directions (default: "serial")
+    #[serde(default = "default_serial_stream")]
+    pub stream: String,
+
+    /// Channel ident of outgoing packets (default: 0)
+    #[serde(default)]
+    pub channel: u8,
+
+    /// Host that bytes read from the UART are sent to
+    pub target: String,
+
+    /// UDP port on the target (default: 6980)
+    #[serde(default = "default_intercom_port")]
+    pub port: u16,
+
+    /// Local address for incoming serial packets, apart from the intercom's
+    /// port (default: "[::]:6981")
+    #[serde(default = "default_serial_listen")]
+    pub listen: String,
+}
+
+fn default_serial_device() -> String {
+    "/dev/ttyUSB0".to_string()
+}
+
+fn default_serial_baud() -> u32 {
+    9600
+}
+
+fn default_serial_stream() -> String {
+    "serial".to_string()
+}
+
+fn default_serial_listen() -> String {
+    "[::]:6981".to_string()
+}
+
+/// Raw UYVY segments with an index per segment, see `recorder`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct RecordConfig {
+    /// Record every frame sent (default: false)
+    #[serde(default)]
+    pub enable: bool,
+
+    /// Directory the segments are written to (default: /var/lib/camera-box/record)
+    #[serde(default = "default_record_path")]
+    pub path: String,
+
+    /// Stop recording once the recordings in `path` reach this many MB, 0
+    /// for no limit but the disk (default: 0)
+    #[serde(default)]
+    pub max_disk_mb: u64,
+}
+
+impl Default for RecordConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            path: default_record_path(),
+            max_disk_mb: 0,
+        }
+    }
+}
+
+fn default_record_path() -> String {
+    "/var/lib/camera-box/record".to_string()
+}
+
+/// A secondary output fed beside NDI, see `output`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct OutputConfig {
+    /// Output type: "mjpeg_http" or "ndi"
+    #[serde(rename = "type")]
+    pub kind: String,
+
+    /// TCP port an "mjpeg_http" output is served on (default: 8081)
+    #[serde(default = "default_output_port")]
+    pub port: u16,
+
+    /// Appended to the NDI name to name an "ndi" output (default: "-preview")
+    #[serde(default = "default_output_name_suffix")]
+    pub name_suffix: String,
+
+    /// Frame width in pixels, even (default: 640)
+    #[serde(default = "default_output_width")]
+    pub width: u32,
+
+    /// Frame height in pixels (default: 360)
+    #[serde(default = "default_output_height")]
+    pub height: u32,
+
+    /// Frames per second handed to the output (default: 5)
+    #[serde(default = "default_output_fps")]
+    pub fps: u32,
+
+    /// JPEG quality 1-100 (default: 70)
+    #[serde(default = "default_output_quality")]
+    pub quality: u8,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            kind: "mjpeg_http".to_string(),
+            port: default_output_port(),
+            name_suffix: default_output_name_suffix(),
+            width: default_output_width(),
+            height: default_output_height(),
+            fps: default_output_fps(),
+            quality: default_output_quality(),
+        }
+    }
+}
+
+fn default_output_port() -> u16 {
+    8081
+}
+
+fn default_output_name_suffix() -> String {
+    "-preview".to_string()
+}
+
+fn default_output_width() -> u32 {
+    640
+}
+
+fn default_output_height() -> u32 {
+    360
+}
+
+fn default_output_fps() -> u32 {
+    5
+}
+
+fn default_output_quality() -> u8 {
+    70
+}
+
+/// Status page on `status_port`, see `status_server`
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+pub struct StatusConfig {
+    /// Bearer token the control endpoints require; empty leaves them off
+    /// (default: "")
+    #[serde(default)]
+    pub token: String,
+}
+
+/// Longest wait per prerequisite before a component starts anyway, see
+/// `startup`
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct StartupConfig {
+    /// Seconds to wait for a network route (default: 30)
+    #[serde(default = "default_network_timeout_secs")]
+    pub network_timeout_secs: u64,
+
+    /// Seconds to wait for device nodes such as /dev/video0 (default: 10)
+    #[serde(default = "default_device_timeout_secs")]
+    pub device_timeout_secs: u64,
+
+    /// Seconds to wait for ALSA cards (default: 10)
+    #[serde(default = "default_audio_timeout_secs")]
+    pub audio_timeout_secs: u64,
+
+    /// Seconds to wait for the NDI library (default: 10)
+    #[serde(default = "default_ndi_timeout_secs")]
+    pub ndi_timeout_secs: u64,
+}
+
+impl Default for StartupConfig {
+    fn default() -> Self {
+        Self {
+            network_timeout_secs: default_network_timeout_secs(),
+            device_timeout_secs: default_device_timeout_secs(),
+            audio_timeout_secs: default_audio_timeout_secs(),
+            ndi_timeout_secs: default_ndi_timeout_secs(),
+        }
+    }
+}
+
+impl StartupConfig {
+    pub fn timeouts(&self) -> crate::startup::Timeouts {
+        crate::startup::Timeouts {
+            network: std::time::Duration::from_secs(self.network_timeout_secs),
+            device: std::time::Duration::from_secs(self.device_timeout_secs),
+            audio: std::time::Duration::from_secs(self.audio_timeout_secs),
+            ndi: std::time::Duration::from_secs(self.ndi_timeout_secs),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
+fn default_network_timeout_secs() -> u64 {
+    30
+}
+
+fn default_device_timeout_secs() -> u64 {
+    10
+}
+
+fn default_audio_timeout_secs() -> u64 {
+    10
+}
+
+fn default_ndi_timeout_secs() -> u64 {
+    10
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct NetworkConfig {
     /// "dhcp" or "static"
     pub mode: String,
+    /// Interface to configure (default: "eth0")
+    #[serde(default = "default_network_interface")]
+    pub interface: String,
     /// Static IP address with CIDR (e.g., "192.168.1.100/24")
     pub address: Option<String>,
     /// Gateway IP
     pub gateway: Option<String>,
-    /// DNS server
+    /// DNS servers, comma-separated
     pub dns: Option<String>,
+    /// DHCP client run with the interface name in dhcp mode, e.g. "dhclient" (default: none)
+    #[serde(default)]
+    pub dhcp_client: Option<String>,
+}
+
+fn default_network_interface() -> String {
+    "eth0".to_string()
+}
+
+fn default_control_socket() -> String {
+    crate::control::DEFAULT_SOCKET_PATH.to_string()
+}
+
+fn default_status_port() -> u16 {
+    8080
+}
+
+fn default_stats_interval_secs() -> u64 {
+    crate::stats::DEFAULT_INTERVAL_SECS
+}
+
+fn default_state_file() -> String {
+    crate::session::STATE_FILE.to_string()
 }
 
 fn default_hostname() -> String {
@@ -152,25 +1337,78 @@ fn default_device() -> String {
     "auto".to_string()
 }
 
+/// `device` of a box without a camera, e.g. a monitor for an NDI source
+pub const NO_DEVICE: &str = "none";
+
 impl Config {
     /// Load configuration from file, or return defaults if file doesn't exist
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load_profile(path, None)
+    }
+
+    /// Load configuration from file with `[profile.<name>]` merged over the
+    /// base settings. Without a file the defaults apply, and only when no
+    /// profile was asked for.
+    pub fn load_profile<P: AsRef<Path>>(path: P, profile: Option<&str>) -> Result<Self> {
         let path = path.as_ref();
         if path.exists() {
             let content = fs::read_to_string(path)?;
-            let config: Config = toml::from_str(&content)?;
+            let config: Config = match profile {
+                None => toml::from_str(&content)?,
+                Some(_) => toml::Value::Table(profile_table(&content, profile)?)
+                    .try_into()
+                    .with_context(|| path.display().to_string())?,
+            };
+            let unknown = unknown_keys(&content);
+            if config.strict && !unknown.is_empty() {
+                let list: Vec<String> = unknown.iter().map(ToString::to_string).collect();
+                anyhow::bail!("{}: {}", path.display(), list.join("; "));
+            }
+            for error in unknown {
+                tracing::warn!("{}: {}", path.display(), error);
+            }
+            let mut config = config;
+            config
+                .expand_placeholders(&SystemValues::default())
+                .with_context(|| path.display().to_string())?;
             Ok(config)
+        } else if let Some(name) = profile {
+            anyhow::bail!("{} does not exist, no profile {:?}", path.display(), name)
         } else {
             Ok(Config::default())
         }
     }
 
-    /// Get the video device path, resolving "auto" to first available device
-    pub fn device_path(&self) -> Result<String> {
-        if self.device == "auto" {
-            find_capture_device()
-        } else {
-            Ok(self.device.clone())
+    /// Names that may contain placeholders, with their config keys
+    fn templated(&mut self) -> Vec<(&'static str, &mut String)> {
+        let mut fields = vec![("ndi_name", &mut self.ndi_name)];
+        if let Some(intercom) = &mut self.intercom {
+            fields.push(("intercom.stream", &mut intercom.stream));
+        }
+        fields
+    }
+
+    /// Replace `{hostname}`, `{serial}` and `{mac}` in the templated names
+    pub fn expand_placeholders(&mut self, values: &SystemValues) -> Result<()> {
+        for (field, value) in self.templated() {
+            *value = placeholders::expand(value, |name| values.value(name))
+                .with_context(|| field.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Whether the box only shows an NDI source: no camera, no audio-only
+    /// stream and no intercom, so stopping it takes nothing off air
+    pub fn is_display_only(&self) -> bool {
+        self.device == NO_DEVICE && !self.ndi.audio_only && self.intercom.is_none()
+    }
+
+    /// Get the video device path, resolving "auto" to first available device
+    pub fn device_path(&self) -> Result<String> {
+        if self.device == "auto" {
+            find_capture_device()
+        } else {
+            Ok(self.device.clone())
         }
     }
 }
@@ -182,34 +1420,1643 @@ fn find_capture_device() -> Result<String> {
     for i in 0..10 {
         let path = format!("/dev/video{}", i);
         if let Ok(device) = Device::with_path(&path) {
-            // Check if this device supports video capture
+            // Check if this device supports video capture (single- or multi-planar)
             let caps = device.query_caps()?;
-            if caps
-                .capabilities
-                .contains(v4l::capability::Flags::VIDEO_CAPTURE)
-            {
+            if caps.capabilities.intersects(
+                v4l::capability::Flags::VIDEO_CAPTURE
+                    | v4l::capability::Flags::VIDEO_CAPTURE_MPLANE,
+            ) {
                 tracing::info!("Auto-detected capture device: {}", path);
                 return Ok(path);
             }
         }
     }
-    anyhow::bail!("No video capture device found")
-}
+    anyhow::bail!("No video capture device found")
+}
+
+// ============================================================================
+// Validation
+// ============================================================================
+
+/// Fully commented configuration listing every setting with its default
+pub const DEFAULT_CONFIG: &str = include_str!("default_config.toml");
+
+/// A problem found in a configuration file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Dotted field path, e.g. "intercom.sample_rate"
+    pub field: String,
+    pub message: String,
+    /// 1-based line in the source file, when known
+    pub line: Option<usize>,
+}
+
+impl ConfigError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.into(),
+            line: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if self.field.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.field, self.message)
+        }
+    }
+}
+
+impl Config {
+    /// Cross-check configured values; an empty list means the config is usable
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        let mut check = |field: &str, result: Result<()>| {
+            if let Err(e) = result {
+                errors.push(ConfigError::new(field, format!("{:#}", e)));
+            }
+        };
+
+        check("hostname", non_empty(&self.hostname));
+        check("ndi_name", non_empty(&self.ndi_name));
+        check("device", non_empty(&self.device));
+        if self.announce && self.status_port == 0 {
+            check("status_port", Err(anyhow::anyhow!("must not be 0")));
+        }
+        check(
+            "stats_interval_secs",
+            in_range(self.stats_interval_secs, 1, 3600),
+        );
+
+        let capture = &self.capture;
+        check(
+            "capture.deinterlace",
+            crate::deinterlace::DeinterlaceMode::from_name(&capture.deinterlace).map(drop),
+        );
+        check(
+            "capture.range",
+            crate::color_range::RangeMode::from_name(&capture.range).map(drop),
+        );
+        check(
+            "capture.fps",
+            capture.frame_rate().and_then(|rate| {
+                anyhow::ensure!(rate.as_f64() <= 240.0, "above 240 fps");
+                Ok(())
+            }),
+        );
+        check("capture.format_priority", capture.formats().map(drop));
+        check(
+            "capture.io_mode",
+            crate::capture::IoMode::from_name(&capture.io_mode).map(drop),
+        );
+        if let Some(audio) = &capture.audio {
+            check("capture.audio.device", non_empty(&audio.device));
+            check("capture.audio.channels", in_range(audio.channels, 1, 8));
+            check(
+                "capture.audio.sample_rate",
+                in_range(audio.sample_rate, 8000, 192000),
+            );
+            check(
+                "capture.audio.quirk",
+                crate::capture_audio::AudioQuirk::from_name(&audio.quirk).map(drop),
+            );
+        }
+        let image = &capture.image;
+        check("capture.image.width", in_range(image.width, 2, 16384));
+        check("capture.image.height", in_range(image.height, 1, 16384));
+        check(
+            "capture.image.frame_rate_n",
+            in_range(image.frame_rate_n, 1, 240_000),
+        );
+        check(
+            "capture.image.frame_rate_d",
+            in_range(image.frame_rate_d, 1, 1001),
+        );
+        let file = &capture.file;
+        check("capture.file.width", in_range(file.width, 2, 16384));
+        if file.width % 2 != 0 {
+            check("capture.file.width", Err(anyhow::anyhow!("must be even")));
+        }
+        check("capture.file.height", in_range(file.height, 1, 16384));
+        check(
+            "capture.file.format",
+            crate::file_source::RawFormat::from_name(&file.format).map(drop),
+        );
+        check(
+            "capture.file.frame_rate_n",
+            in_range(file.frame_rate_n, 1, 240_000),
+        );
+        check(
+            "capture.file.frame_rate_d",
+            in_range(file.frame_rate_d, 1, 1001),
+        );
+        if let Some(crop) = &capture.crop {
+            check("capture.crop.width", in_range(crop.width, 2, 16384));
+            check("capture.crop.height", in_range(crop.height, 1, 16384));
+        }
+        if let Some(replay) = &capture.replay {
+            check("capture.replay.frames", in_range(replay.frames, 1, 3600));
+            check("capture.replay.dir", non_empty(&replay.dir));
+        }
+
+        check(
+            "ndi.pacing",
+            crate::pacing::PacingMode::from_name(&self.ndi.pacing).map(drop),
+        );
+        check(
+            "ndi.on_conflict",
+            crate::ndi_conflict::OnConflict::from_name(&self.ndi.on_conflict).map(drop),
+        );
+        check("ndi.aspect", self.ndi.aspect.aspect().map(drop));
+        if let Some(overlay) = &self.ndi.overlay {
+            check("ndi.overlay.image", non_empty(&overlay.image));
+            check("ndi.overlay.width", in_range(overlay.width, 1, 16384));
+            check("ndi.overlay.height", in_range(overlay.height, 1, 16384));
+            check("ndi.overlay.alpha", in_range(overlay.alpha, 0.0, 1.0));
+        }
+        let proxy = &self.ndi.proxy;
+        if proxy.enable {
+            check("ndi.proxy.name_suffix", non_empty(&proxy.name_suffix));
+            check("ndi.proxy.width", in_range(proxy.width, 16, 3840));
+            if proxy.width % 2 != 0 {
+                check("ndi.proxy.width", Err(anyhow::anyhow!("must be even")));
+            }
+            check("ndi.proxy.height", in_range(proxy.height, 16, 2160));
+            check("ndi.proxy.fps_divisor", in_range(proxy.fps_divisor, 1, 60));
+        }
+        if self.ndi.audio_only {
+            if self.device != default_device() {
+                check(
+                    "ndi.audio_only",
+                    Err(anyhow::anyhow!(
+                        "cannot be combined with device = {:?}",
+                        self.device
+                    )),
+                );
+            }
+            if self.intercom.is_none() {
+                check(
+                    "ndi.audio_only",
+                    Err(anyhow::anyhow!("requires an [intercom] section")),
+                );
+            }
+        }
+        match (self.ndi.frame_rate_n, self.ndi.frame_rate_d) {
+            (Some(0), _) => check("ndi.frame_rate_n", Err(anyhow::anyhow!("must not be 0"))),
+            (_, Some(0)) => check("ndi.frame_rate_d", Err(anyhow::anyhow!("must not be 0"))),
+            (Some(n), Some(d)) if n / d > 240 => check(
+                "ndi.frame_rate_n",
+                Err(anyhow::anyhow!("{}/{} is above 240 fps", n, d)),
+            ),
+            (Some(_), None) => check(
+                "ndi.frame_rate_d",
+                Err(anyhow::anyhow!("required with frame_rate_n")),
+            ),
+            (None, Some(_)) => check(
+                "ndi.frame_rate_n",
+                Err(anyhow::anyhow!("required with frame_rate_d")),
+            ),
+            _ => {}
+        }
+
+        if let Some(display) = &self.display {
+            check("display.source", non_empty(&display.source));
+            check("display.fb_device", non_empty(&display.fb_device));
+            check(
+                "display.color_format",
+                crate::ndi::RecvColorFormat::from_name(&display.color_format).map(drop),
+            );
+            check(
+                "display.max_age_ms",
+                in_range(display.max_age_ms, 0, 10_000),
+            );
+            check("display.max_fps", in_range(display.max_fps, 0, 240));
+            check(
+                "display.zebra_percent",
+                in_range(display.zebra_percent, 1, crate::exposure::MAX_ZEBRA_PERCENT),
+            );
+            check(
+                "display.standby_dim",
+                in_range(display.standby_dim, 0.0, 1.0),
+            );
+            check(
+                "display.standby_dim_secs",
+                in_range(display.standby_dim_secs, 0, 86_400),
+            );
+            check(
+                "display.idle_exit_mins",
+                in_range(display.idle_exit_mins, 0, 10_080),
+            );
+            // The exit stops the whole process, capture and intercom included
+            if display.idle_exit_mins > 0 && !self.is_display_only() {
+                check(
+                    "display.idle_exit_mins",
+                    Err(anyhow::anyhow!(
+                        "needs a display-only box (device = \"none\", no [intercom])"
+                    )),
+                );
+            }
+        }
+
+        if let Some(serial) = &self.serial {
+            check("serial.device", non_empty(&serial.device));
+            if crate::serial_bridge::baud_rate(serial.baud).is_none() {
+                check(
+                    "serial.baud",
+                    Err(anyhow::anyhow!("unsupported baud rate {}", serial.baud)),
+                );
+            }
+            check("serial.stream", non_empty(&serial.stream));
+            if serial.stream.len() > 16 {
+                check(
+                    "serial.stream",
+                    Err(anyhow::anyhow!("VBAN stream names are at most 16 bytes")),
+                );
+            }
+            check("serial.target", non_empty(&serial.target));
+            if serial.port == 0 {
+                check("serial.port", Err(anyhow::anyhow!("must not be 0")));
+            }
+            check(
+                "serial.listen",
+                serial
+                    .listen
+                    .parse::<std::net::SocketAddr>()
+                    .map(drop)
+                    .map_err(|_| anyhow::anyhow!("{:?} is not an address:port", serial.listen)),
+            );
+        }
+
+        if self.record.enable {
+            check("record.path", non_empty(&self.record.path));
+        }
+
+        let mut output_ports = Vec::new();
+        let mut output_names = Vec::new();
+        if self.ndi.proxy.enable {
+            output_names.push(&self.ndi.proxy.name_suffix);
+        }
+        for output in &self.outputs {
+            let kind = crate::output::OutputKind::from_name(&output.kind);
+            let ndi = matches!(kind, Ok(crate::output::OutputKind::Ndi));
+            check("output.type", kind.map(drop));
+            if ndi {
+                // The sender is named <ndi_name><name_suffix>
+                check("output.name_suffix", non_empty(&output.name_suffix));
+                if output_names.contains(&&output.name_suffix) {
+                    check(
+                        "output.name_suffix",
+                        Err(anyhow::anyhow!(
+                            "\"{}\" names another NDI sender",
+                            output.name_suffix
+                        )),
+                    );
+                }
+                output_names.push(&output.name_suffix);
+            } else if output.port == 0 {
+                check("output.port", Err(anyhow::anyhow!("must not be 0")));
+            } else if output_ports.contains(&output.port) {
+                check(
+                    "output.port",
+                    Err(anyhow::anyhow!("{} is used by another output", output.port)),
+                );
+            } else {
+                output_ports.push(output.port);
+            }
+            check("output.width", in_range(output.width, 16, 3840));
+            if output.width % 2 != 0 {
+                check("output.width", Err(anyhow::anyhow!("must be even")));
+            }
+            check("output.height", in_range(output.height, 16, 2160));
+            check("output.fps", in_range(output.fps, 1, 30));
+            check("output.quality", in_range(output.quality, 1, 100));
+        }
+
+        let startup = &self.startup;
+        check(
+            "startup.network_timeout_secs",
+            in_range(startup.network_timeout_secs, 0, 600),
+        );
+        check(
+            "startup.device_timeout_secs",
+            in_range(startup.device_timeout_secs, 0, 600),
+        );
+        check(
+            "startup.audio_timeout_secs",
+            in_range(startup.audio_timeout_secs, 0, 600),
+        );
+        check(
+            "startup.ndi_timeout_secs",
+            in_range(startup.ndi_timeout_secs, 0, 600),
+        );
+
+        if let Some(intercom) = &self.intercom {
+            check("intercom.stream", non_empty(&intercom.stream));
+            if intercom.stream.len() > 16 {
+                check(
+                    "intercom.stream",
+                    Err(anyhow::anyhow!("VBAN stream names are at most 16 bytes")),
+                );
+            }
+            check("intercom.target", non_empty(&intercom.target));
+            if !crate::vban::SAMPLE_RATES.contains(&intercom.sample_rate) {
+                check(
+                    "intercom.sample_rate",
+                    Err(anyhow::anyhow!(
+                        "{} Hz is not a VBAN sample rate",
+                        intercom.sample_rate
+                    )),
+                );
+            }
+            check("intercom.channels", in_range(intercom.channels, 1, 2));
+            check(
+                "intercom.sidetone_gain",
+                in_range(intercom.sidetone_gain, 0.0, 1000.0),
+            );
+            check(
+                "intercom.mic_gain",
+                in_range(intercom.mic_gain, 0.0, 1000.0),
+            );
+            check(
+                "intercom.headphone_gain",
+                in_range(intercom.headphone_gain, 0.0, 1000.0),
+            );
+            if !(intercom.limiter_threshold > 0.0 && intercom.limiter_threshold <= 1.0) {
+                check(
+                    "intercom.limiter_threshold",
+                    Err(anyhow::anyhow!(
+                        "{} is outside (0, 1]",
+                        intercom.limiter_threshold
+                    )),
+                );
+            }
+            check(
+                "intercom.tx_codec",
+                crate::vban::VbanCodec::from_name(&intercom.tx_codec).map(drop),
+            );
+            check("intercom.dscp", in_range(intercom.dscp, 0, 63));
+            if intercom.port == 0 {
+                check("intercom.port", Err(anyhow::anyhow!("must not be 0")));
+            }
+            check(
+                "intercom.listen",
+                intercom
+                    .listen
+                    .parse::<std::net::SocketAddr>()
+                    .map(drop)
+                    .map_err(|_| anyhow::anyhow!("{:?} is not an address:port", intercom.listen)),
+            );
+            if let Some(ref bind_address) = intercom.bind_address {
+                check(
+                    "intercom.bind_address",
+                    crate::net::unbracket(bind_address)
+                        .parse::<std::net::IpAddr>()
+                        .map(drop)
+                        .map_err(|_| anyhow::anyhow!("{:?} is not an IP address", bind_address)),
+                );
+            }
+            if intercom.record_dir.is_some() {
+                check(
+                    "intercom.record_segment_secs",
+                    in_range(intercom.record_segment_secs, 1, 86400),
+                );
+                check(
+                    "intercom.record_keep",
+                    in_range(intercom.record_keep, 1, 10000),
+                );
+            }
+            check(
+                "intercom.mute_key",
+                crate::input::parse_key_name(&intercom.mute_key).map(drop),
+            );
+            check(
+                "intercom.mute_led",
+                crate::input::MuteLed::from_name(&intercom.mute_led).map(drop),
+            );
+            if let Some(button) = &intercom.button_gpio {
+                check(
+                    "intercom.button_gpio",
+                    crate::gpio::GpioLine::parse(button).map(drop),
+                );
+            }
+            check(
+                "intercom.auto_unmute",
+                crate::intercom::AutoUnmute::from_name(&intercom.auto_unmute).map(drop),
+            );
+            check(
+                "intercom.mic_channel",
+                crate::intercom::MicChannel::from_name(&intercom.mic_channel).map(drop),
+            );
+            check(
+                "intercom.period_frames",
+                in_range(intercom.period_frames, 16, 4096),
+            );
+            check("intercom.periods", in_range(intercom.periods, 2, 16));
+            if let Some(led) = &intercom.tally_led_gpio {
+                check("intercom.tally_led_gpio.chip", non_empty(&led.chip));
+                if led.red == led.green {
+                    check(
+                        "intercom.tally_led_gpio.green",
+                        Err(anyhow::anyhow!("same line as red ({})", led.red)),
+                    );
+                }
+            }
+            let echo = &intercom.echo;
+            check(
+                "intercom.echo.far_threshold",
+                in_range(echo.far_threshold, 0.0, 1.0),
+            );
+            check(
+                "intercom.echo.near_threshold",
+                in_range(echo.near_threshold, 0.0, 1.0),
+            );
+            check("intercom.echo.duck_db", in_range(echo.duck_db, 0.0, 96.0));
+            check(
+                "intercom.echo.attack_ms",
+                in_range(echo.attack_ms, 0.0, 10000.0),
+            );
+            check(
+                "intercom.echo.release_ms",
+                in_range(echo.release_ms, 0.0, 10000.0),
+            );
+            let silence = &intercom.silence;
+            check(
+                "intercom.silence.threshold_db",
+                in_range(silence.threshold_db, -120.0, 0.0),
+            );
+            check("intercom.silence.secs", in_range(silence.secs, 1, 86400));
+            let routing = &intercom.routing;
+            check("intercom.routing.vban", routing.vban.route().map(drop));
+            check(
+                "intercom.routing.sidetone",
+                routing.sidetone.route().map(drop),
+            );
+            check("intercom.routing.tones", routing.tones.route().map(drop));
+        }
+
+        if let Some(network) = &self.network {
+            use crate::netcfg::Cidr;
+            check("network.interface", non_empty(&network.interface));
+            let mode = network.mode.to_ascii_lowercase();
+            if mode != "dhcp" && mode != "static" {
+                check(
+                    "network.mode",
+                    Err(anyhow::anyhow!(
+                        "unsupported mode {:?}, expected \"dhcp\" or \"static\"",
+                        network.mode
+                    )),
+                );
+            }
+            let address = match network.address.as_deref() {
+                Some(address) => Cidr::parse(address)
+                    .map_err(|e| check("network.address", Err(e)))
+                    .ok(),
+                None if mode == "static" => {
+                    check(
+                        "network.address",
+                        Err(anyhow::anyhow!("required in static mode")),
+                    );
+                    None
+                }
+                None => None,
+            };
+            if let Some(gateway) = network.gateway.as_deref() {
+                match gateway.trim().parse::<std::net::Ipv4Addr>() {
+                    Ok(gateway) if address.is_some_and(|cidr| !cidr.contains(gateway)) => check(
+                        "network.gateway",
+                        Err(anyhow::anyhow!(
+                            "{} is not on the address's subnet",
+                            gateway
+                        )),
+                    ),
+                    Ok(_) => {}
+                    Err(_) => check(
+                        "network.gateway",
+                        Err(anyhow::anyhow!("{:?} is not an IPv4 address", gateway)),
+                    ),
+                }
+            }
+            for server in network.dns.as_deref().unwrap_or("").split(',') {
+                let server = server.trim();
+                if !server.is_empty() && server.parse::<std::net::IpAddr>().is_err() {
+                    check(
+                        "network.dns",
+                        Err(anyhow::anyhow!("{:?} is not an IP address", server)),
+                    );
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Resolve the configured capture device, ALSA cards and GPIO chips on
+    /// this machine, without opening any streams
+    pub fn check_devices(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        match self.device_path() {
+            // Audio-only and display-only boxes have no camera
+            _ if self.ndi.audio_only || self.device == NO_DEVICE => {}
+            Ok(device) => {
+                // "image:PATH" slates and "file:PATH" clips are checked by
+                // their file; stdin ("file:-") always exists
+                let path = crate::image_source::image_path(&device)
+                    .or_else(|| crate::file_source::file_path(&device))
+                    .unwrap_or(&device);
+                if path != crate::file_source::STDIN && !Path::new(path).exists() {
+                    errors.push(ConfigError::new(
+                        "device",
+                        format!("{} does not exist", path),
+                    ));
+                }
+            }
+            Err(e) => errors.push(ConfigError::new("device", format!("{:#}", e))),
+        }
+
+        let asound = Path::new("/proc/asound");
+        if let Some(audio) = &self.capture.audio {
+            if alsa_card_present(asound, &audio.device) == Some(false) {
+                errors.push(ConfigError::new(
+                    "capture.audio.device",
+                    format!("no ALSA card for {:?}", audio.device),
+                ));
+            }
+        }
+        if let Some(intercom) = &self.intercom {
+            if alsa_card_present(asound, crate::intercom::ALSA_DEVICE) == Some(false) {
+                errors.push(ConfigError::new(
+                    "intercom",
+                    format!("headset card {} not found", crate::intercom::ALSA_DEVICE),
+                ));
+            }
+            if let Some(Ok(line)) = intercom
+                .button_gpio
+                .as_deref()
+                .map(crate::gpio::GpioLine::parse)
+            {
+                if !gpio_chip_path(&line.chip).exists() {
+                    errors.push(ConfigError::new(
+                        "intercom.button_gpio",
+                        format!("GPIO chip {} not found", line.chip),
+                    ));
+                }
+            }
+            if let Some(led) = &intercom.tally_led_gpio {
+                if !gpio_chip_path(&led.chip).exists() {
+                    errors.push(ConfigError::new(
+                        "intercom.tally_led_gpio.chip",
+                        format!("GPIO chip {} not found", led.chip),
+                    ));
+                }
+            }
+        }
+        if let Some(display) = &self.display {
+            if !Path::new(&display.fb_device).exists() {
+                errors.push(ConfigError::new(
+                    "display.fb_device",
+                    format!("{} does not exist", display.fb_device),
+                ));
+            }
+        }
+        if let Some(serial) = &self.serial {
+            if !Path::new(&serial.device).exists() {
+                errors.push(ConfigError::new(
+                    "serial.device",
+                    format!("{} does not exist", serial.device),
+                ));
+            }
+        }
+        errors
+    }
+}
+
+/// Parse and validate configuration source: syntax errors, unknown keys (which
+/// serde would otherwise ignore) and invalid values, each with its line.
+/// Returns the parsed config if the syntax was valid.
+pub fn check_source(source: &str) -> (Option<Config>, Vec<ConfigError>) {
+    check_profile_source(source, None)
+}
+
+/// `check_source` for the settings in effect under `profile`; values the
+/// profile sets are reported at their line in its section
+pub fn check_profile_source(
+    source: &str,
+    profile: Option<&str>,
+) -> (Option<Config>, Vec<ConfigError>) {
+    let config = match toml::from_str::<Config>(source) {
+        Ok(config) => config,
+        Err(e) => {
+            let line = e
+                .span()
+                .map(|span| source[..span.start.min(source.len())].matches('\n').count() + 1);
+            let error = ConfigError {
+                field: String::new(),
+                message: e.message().to_string(),
+                line,
+            };
+            return (None, vec![error]);
+        }
+    };
+    let config = match profile {
+        None => config,
+        Some(name) => {
+            let merged = profile_table(source, profile)
+                .and_then(|table| Ok(toml::Value::Table(table).try_into::<Config>()?));
+            match merged {
+                Ok(config) => config,
+                Err(e) => {
+                    let error = ConfigError {
+                        line: locate(source, &format!("profile.{}", name)),
+                        ..ConfigError::new("profile", format!("{:#}", e))
+                    };
+                    return (None, vec![error]);
+                }
+            }
+        }
+    };
+    let line_of = |field: &str| match profile {
+        Some(name) if profile_sets(source, name, field) => {
+            locate(source, &format!("profile.{}.{}", name, field))
+        }
+        _ => locate(source, field),
+    };
+
+    let mut errors = unknown_keys(source);
+    let mut unexpanded = config.clone();
+    for (field, value) in unexpanded.templated() {
+        if let Err(e) = placeholders::check(value) {
+            errors.push(ConfigError {
+                line: line_of(field),
+                ..ConfigError::new(field, e.to_string())
+            });
+        }
+    }
+    for mut error in config.validate() {
+        error.line = line_of(&error.field);
+        errors.push(error);
+    }
+    (Some(config), errors)
+}
+
+/// Check a configuration file; a missing file is valid (defaults apply)
+pub fn check_file<P: AsRef<Path>>(path: P) -> Result<(Option<Config>, Vec<ConfigError>)> {
+    check_profile_file(path, None)
+}
+
+/// `check_file` with `[profile.<name>]` applied
+pub fn check_profile_file<P: AsRef<Path>>(
+    path: P,
+    profile: Option<&str>,
+) -> Result<(Option<Config>, Vec<ConfigError>)> {
+    let path = path.as_ref();
+    if !path.exists() {
+        if let Some(name) = profile {
+            anyhow::bail!("{} does not exist, no profile {:?}", path.display(), name);
+        }
+        return Ok((Some(Config::default()), Vec::new()));
+    }
+    let source = fs::read_to_string(path)?;
+    Ok(check_profile_source(&source, profile))
+}
+
+// ============================================================================
+// Profiles
+// ============================================================================
+
+/// Environment variable selecting a profile when `--profile` isn't given
+pub const PROFILE_ENV: &str = "CAMERA_BOX_PROFILE";
+
+/// Overlay `overlay` onto `base`. Tables merge key by key at any depth;
+/// every other value, arrays included, replaces the base value whole, except
+/// that `false` in place of a table removes it (`intercom = false` turns the
+/// intercom off).
+pub fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(inner)), toml::Value::Table(value)) => {
+                merge_tables(inner, value)
+            }
+            (Some(toml::Value::Table(_)), toml::Value::Boolean(false)) => {
+                base.remove(&key);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Settings of `source` with `[profile.<name>]` merged over the base ones,
+/// without the profile sections themselves
+pub fn profile_table(source: &str, profile: Option<&str>) -> Result<toml::Table> {
+    let mut table: toml::Table = toml::from_str(source)?;
+    let mut profiles = match table.remove("profile") {
+        None => toml::Table::new(),
+        Some(toml::Value::Table(profiles)) => profiles,
+        Some(_) => anyhow::bail!("profile must be [profile.<name>] sections"),
+    };
+    let Some(name) = profile else {
+        return Ok(table);
+    };
+    match profiles.remove(name) {
+        Some(toml::Value::Table(overlay)) => merge_tables(&mut table, overlay),
+        Some(_) => anyhow::bail!("profile.{} is not a section", name),
+        None if profiles.is_empty() => {
+            anyhow::bail!("unknown profile {:?}, none are defined", name)
+        }
+        None => {
+            let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            anyhow::bail!(
+                "unknown profile {:?}, available: {}",
+                name,
+                names.join(", ")
+            )
+        }
+    }
+    Ok(table)
+}
+
+/// Whether `[profile.<name>]` sets the dotted `field`
+fn profile_sets(source: &str, name: &str, field: &str) -> bool {
+    let Ok(root) = toml::from_str::<toml::Value>(source) else {
+        return false;
+    };
+    let mut value = &root;
+    for part in ["profile", name].into_iter().chain(field.split('.')) {
+        match value.get(part) {
+            Some(inner) => value = inner,
+            None => return false,
+        }
+    }
+    true
+}
+
+fn non_empty(value: &str) -> Result<()> {
+    if value.trim().is_empty() {
+        anyhow::bail!("must not be empty");
+    }
+    Ok(())
+}
+
+fn in_range<T: PartialOrd + std::fmt::Display>(value: T, min: T, max: T) -> Result<()> {
+    if value < min || value > max {
+        anyhow::bail!("{} is outside {}..={}", value, min, max);
+    }
+    Ok(())
+}
+
+/// Valid keys per section, for unknown key detection ("" is the top level)
+const KNOWN_KEYS: &[(&str, &[&str])] = &[
+    (
+        "",
+        &[
+            "strict",
+            "hostname",
+            "ndi_name",
+            "device",
+            "ndi_groups",
+            "ndi_reload_library",
+            "control_socket",
+            "announce",
+            "status_port",
+            "stats_interval_secs",
+            "state_file",
+            "capture",
+            "ndi",
+            "display",
+            "intercom",
+            "serial",
+            "record",
+            "output",
+            "status",
+            "startup",
+            "network",
+            "profile",
+        ],
+    ),
+    (
+        "capture",
+        &[
+            "deinterlace",
+            "stall_timeout_secs",
+            "usb_reset",
+            "controls_file",
+            "range",
+            "fps",
+            "format_priority",
+            "io_mode",
+            "timing",
+            "audio",
+            "image",
+            "file",
+            "crop",
+            "replay",
+        ],
+    ),
+    (
+        "capture.image",
+        &["width", "height", "frame_rate_n", "frame_rate_d"],
+    ),
+    (
+        "capture.file",
+        &[
+            "width",
+            "height",
+            "format",
+            "frame_rate_n",
+            "frame_rate_d",
+            "loop_at_eof",
+        ],
+    ),
+    (
+        "capture.audio",
+        &["device", "channels", "sample_rate", "quirk"],
+    ),
+    ("capture.crop", &["x", "y", "width", "height"]),
+    ("capture.replay", &["frames", "dir"]),
+    (
+        "ndi",
+        &[
+            "pacing",
+            "frame_rate_n",
+            "frame_rate_d",
+            "on_conflict",
+            "timestamp_burn_in",
+            "overlay",
+            "audio_only",
+            "zero_copy_uyvy",
+            "aspect",
+            "proxy",
+        ],
+    ),
+    (
+        "ndi.overlay",
+        &["image", "width", "height", "x", "y", "alpha"],
+    ),
+    (
+        "ndi.proxy",
+        &["enable", "name_suffix", "width", "height", "fps_divisor"],
+    ),
+    (
+        "display",
+        &[
+            "source",
+            "fb_device",
+            "color_format",
+            "max_age_ms",
+            "max_fps",
+            "histogram",
+            "zebra",
+            "zebra_percent",
+            "standby_dim",
+            "standby_dim_secs",
+            "idle_exit_mins",
+        ],
+    ),
+    (
+        "intercom",
+        &[
+            "stream",
+            "stream_ignore_case",
+            "target",
+            "sample_rate",
+            "channels",
+            "sidetone_gain",
+            "mic_gain",
+            "headphone_gain",
+            "limiter_enabled",
+            "limiter_threshold",
+            "allowed_sources",
+            "auto_lock",
+            "link_timeout_ms",
+            "tx_codec",
+            "ping_announce",
+            "dscp",
+            "recv_buffer_size",
+            "interface",
+            "port",
+            "listen",
+            "bind_address",
+            "record_dir",
+            "record_segment_secs",
+            "record_keep",
+            "echo",
+            "silence",
+            "routing",
+            "mute_key",
+            "mute_led",
+            "button_gpio",
+            "tally_led_gpio",
+            "auto_unmute",
+            "call_tone",
+            "mic_channel",
+            "period_frames",
+            "periods",
+        ],
+    ),
+    ("intercom.tally_led_gpio", &["chip", "red", "green"]),
+    ("intercom.routing", &["vban", "sidetone", "tones"]),
+    ("record", &["enable", "path", "max_disk_mb"]),
+    (
+        "output",
+        &[
+            "type",
+            "port",
+            "name_suffix",
+            "width",
+            "height",
+            "fps",
+            "quality",
+        ],
+    ),
+    ("status", &["token"]),
+    (
+        "startup",
+        &[
+            "network_timeout_secs",
+            "device_timeout_secs",
+            "audio_timeout_secs",
+            "ndi_timeout_secs",
+        ],
+    ),
+    (
+        "serial",
+        &[
+            "device", "baud", "stream", "channel", "target", "port", "listen",
+        ],
+    ),
+    (
+        "network",
+        &[
+            "mode",
+            "interface",
+            "address",
+            "gateway",
+            "dns",
+            "dhcp_client",
+        ],
+    ),
+    (
+        "intercom.echo",
+        &[
+            "enabled",
+            "far_threshold",
+            "near_threshold",
+            "duck_db",
+            "attack_ms",
+            "release_ms",
+        ],
+    ),
+    (
+        "intercom.silence",
+        &["enabled", "threshold_db", "secs", "notify"],
+    ),
+];
+
+fn section_keys(section: &str) -> Option<&'static [&'static str]> {
+    KNOWN_KEYS
+        .iter()
+        .find(|(name, _)| *name == section)
+        .map(|(_, keys)| *keys)
+}
+
+/// Keys serde would silently ignore, each with the closest known key of its
+/// section as a hint
+pub fn unknown_keys(source: &str) -> Vec<ConfigError> {
+    let Ok(table) = toml::from_str::<toml::Table>(source) else {
+        return Vec::new();
+    };
+    let mut errors = Vec::new();
+    collect_unknown(&table, "", "", &mut errors);
+    for error in &mut errors {
+        error.line = locate(source, &error.field);
+    }
+    errors
+}
+
+/// `prefix` is where `section` sits in the file: "profile.<name>" inside a
+/// profile, else empty
+fn collect_unknown(
+    table: &toml::Table,
+    section: &str,
+    prefix: &str,
+    errors: &mut Vec<ConfigError>,
+) {
+    let Some(known) = section_keys(section) else {
+        return;
+    };
+    let join = |parent: &str, key: &str| {
+        if parent.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", parent, key)
+        }
+    };
+    for (key, value) in table {
+        let path = join(section, key);
+        let field = join(prefix, &path);
+        if !known.contains(&key.as_str()) {
+            let message = match suggest(key, known) {
+                Some(candidate) => format!("unknown key (did you mean {:?}?)", candidate),
+                None => "unknown key".to_string(),
+            };
+            errors.push(ConfigError::new(&field, message));
+        } else if path == "profile" {
+            if !prefix.is_empty() {
+                errors.push(ConfigError::new(&field, "profiles can't be nested"));
+                continue;
+            }
+            // [profile.<name>] sections take the top-level keys
+            let profiles = value.as_table().into_iter().flatten();
+            for (name, inner) in profiles.filter_map(|(n, v)| Some((n, v.as_table()?))) {
+                collect_unknown(inner, "", &join(&field, name), errors);
+            }
+        } else if let toml::Value::Table(inner) = value {
+            collect_unknown(inner, &path, prefix, errors);
+        } else if let toml::Value::Array(items) = value {
+            // [[section]]: every table of the array has the section's keys
+            for inner in items.iter().filter_map(toml::Value::as_table) {
+                collect_unknown(inner, &path, prefix, errors);
+            }
+        }
+    }
+}
+
+/// Closest known key to a misspelled one. Underscores are ignored, so
+/// "side_tone_gain" finds "sidetone_gain"; a shared stem of five letters
+/// ("stall_timeout", "side_tone_volume") also counts as close.
+fn suggest(key: &str, known: &[&'static str]) -> Option<&'static str> {
+    let squash = |name: &str| name.replace('_', "").to_ascii_lowercase();
+    let key = squash(key);
+    known
+        .iter()
+        .filter_map(|candidate| {
+            let name = squash(candidate);
+            let distance = edit_distance(&key, &name);
+            let stem = key
+                .chars()
+                .zip(name.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+            (distance <= 2 || stem >= 5).then_some((distance, *candidate))
+        })
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitute.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// 1-based line defining `field` (or its nearest enclosing key or table)
+fn locate(source: &str, field: &str) -> Option<usize> {
+    let parts: Vec<&str> = field.split('.').filter(|part| !part.is_empty()).collect();
+    for depth in (1..=parts.len()).rev() {
+        let table = parts[..depth - 1].join(".");
+        let key = parts[depth - 1];
+        let full = parts[..depth].join(".");
+        let mut current = String::new();
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            let header = line
+                .strip_prefix('[')
+                .map(|l| l.trim_start_matches('['))
+                .and_then(|l| l.split(']').next());
+            if let Some(header) = header {
+                current = header.trim().to_string();
+                if current == full {
+                    return Some(index + 1);
+                }
+            } else if current == table
+                && line
+                    .strip_prefix(key)
+                    .is_some_and(|rest| rest.trim_start().starts_with('='))
+            {
+                return Some(index + 1);
+            }
+        }
+    }
+    None
+}
+
+/// Whether the card named by an ALSA device string ("hw:CARD=MS2109,DEV=0",
+/// "plughw:1,0") exists under `asound_root`; None for names that aren't bound
+/// to a card, like "default"
+pub fn alsa_card_present(asound_root: &Path, device: &str) -> Option<bool> {
+    let (_, args) = device.split_once(':')?;
+    let card = args.split(',').next()?.trim();
+    let card = card.strip_prefix("CARD=").unwrap_or(card);
+    if card.is_empty() {
+        return None;
+    }
+    let entry = if card.bytes().all(|b| b.is_ascii_digit()) {
+        format!("card{}", card)
+    } else {
+        card.to_string()
+    };
+    Some(asound_root.join(entry).exists())
+}
+
+fn gpio_chip_path(chip: &str) -> std::path::PathBuf {
+    if chip.starts_with('/') {
+        std::path::PathBuf::from(chip)
+    } else {
+        Path::new("/dev").join(chip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+    use v4l::FourCC;
+
+    #[test]
+    fn test_config_default_values() {
+        let config = Config::default();
+        assert!(!config.strict);
+        assert_eq!(config.hostname, "camera-box");
+        assert_eq!(config.ndi_name, "usb");
+        assert_eq!(config.device, "auto");
+        assert_eq!(config.capture.deinterlace, "off");
+        assert_eq!(config.capture.stall_timeout_secs, 5);
+        assert!(!config.capture.usb_reset);
+        assert_eq!(
+            config.capture.controls_file,
+            "/var/lib/camera-box/controls.toml"
+        );
+        assert_eq!(config.capture.range, "auto");
+        assert_eq!(config.capture.io_mode, "mmap");
+        assert!(!config.capture.timing);
+        assert!(config.capture.audio.is_none());
+        assert!(config.capture.crop.is_none());
+        assert!(config.capture.replay.is_none());
+        assert_eq!(
+            (config.capture.image.width, config.capture.image.height),
+            (1920, 1080)
+        );
+        assert_eq!(config.capture.image.frame_rate_n, 30);
+        assert_eq!(config.capture.image.frame_rate_d, 1);
+        assert_eq!(config.capture.file, FileSourceConfig::default());
+        assert_eq!(config.capture.file.format, "UYVY");
+        assert!(config.capture.file.loop_at_eof);
+        assert_eq!(config.ndi.pacing, "off");
+        assert!(config.ndi.frame_rate_n.is_none());
+        assert_eq!(config.ndi.on_conflict, "suffix");
+        assert!(!config.ndi.timestamp_burn_in);
+        assert!(!config.ndi.audio_only);
+        assert!(!config.ndi.zero_copy_uyvy);
+        assert_eq!(config.ndi.aspect, AspectValue::Name("auto".to_string()));
+        assert!(config.ndi.overlay.is_none());
+        assert!(config.ndi.frame_rate_d.is_none());
+        assert!(config.ndi_groups.is_none());
+        assert!(!config.ndi_reload_library);
+        assert_eq!(config.control_socket, "/run/camera-box.sock");
+        assert!(!config.announce);
+        assert_eq!(config.status_port, 8080);
+        assert!(config.display.is_none());
+        assert!(config.intercom.is_none());
+        assert!(config.network.is_none());
+    }
+
+    /// The default template with every setting uncommented
+    fn uncommented_template() -> String {
+        DEFAULT_CONFIG
+            .lines()
+            .map(|line| match line.strip_prefix('#') {
+                Some(setting) if !setting.is_empty() && !setting.starts_with(' ') => setting,
+                _ => line,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_default_config_template() {
+        // As written, everything is commented out
+        let (config, errors) = check_source(DEFAULT_CONFIG);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.unwrap().hostname, "camera-box");
+
+        // Uncommented, every value matches the serde default
+        let (config, errors) = check_source(&uncommented_template());
+        assert!(errors.is_empty(), "{:?}", errors);
+        let config = config.unwrap();
+        let defaults = Config::default();
+        assert_eq!(config.hostname, defaults.hostname);
+        assert_eq!(config.device, defaults.device);
+        assert_eq!(config.control_socket, defaults.control_socket);
+        assert_eq!(config.status_port, defaults.status_port);
+        assert_eq!(config.stats_interval_secs, defaults.stats_interval_secs);
+        assert_eq!(config.state_file, defaults.state_file);
+        assert_eq!(
+            config.capture.stall_timeout_secs,
+            defaults.capture.stall_timeout_secs
+        );
+        assert_eq!(config.capture.controls_file, defaults.capture.controls_file);
+        assert_eq!(
+            config.capture.format_priority,
+            defaults.capture.format_priority
+        );
+        let replay = config.capture.replay.unwrap();
+        assert_eq!(replay.frames, default_replay_frames());
+        assert_eq!(replay.dir, default_replay_dir());
+        let audio = config.capture.audio.unwrap();
+        assert_eq!(audio.channels, default_capture_audio_channels());
+        assert_eq!(audio.sample_rate, default_capture_audio_sample_rate());
+        assert_eq!(config.ndi.pacing, defaults.ndi.pacing);
+        assert_eq!(config.ndi.on_conflict, defaults.ndi.on_conflict);
+        assert_eq!(config.ndi.proxy, defaults.ndi.proxy);
+        let display = config.display.unwrap();
+        assert_eq!(display.fb_device, default_fb_device());
+        assert_eq!(display.idle_exit_mins, 0);
+        let intercom = config.intercom.unwrap();
+        assert_eq!(intercom.stream, default_intercom_stream());
+        assert_eq!(intercom.sample_rate, default_intercom_sample_rate());
+        assert_eq!(intercom.mic_gain, default_mic_gain());
+        assert_eq!(intercom.recv_buffer_size, default_recv_buffer_size());
+        assert_eq!(intercom.listen, default_intercom_listen());
+        assert_eq!(intercom.mute_key, default_mute_key());
+        assert_eq!(intercom.mute_led, "auto");
+        assert_eq!(intercom.echo.release_ms, default_echo_release_ms());
+        assert_eq!(config.outputs.len(), 1);
+        assert_eq!(config.outputs[0].port, default_output_port());
+        assert_eq!(config.outputs[0].quality, default_output_quality());
+        assert_eq!(config.status, defaults.status);
+    }
+
+    #[test]
+    fn test_check_source_reports_broken_values_with_lines() {
+        let source = r#"hostname = "cam"
+
+[capture]
+deinterlace = "weave"
+
+[ndi]
+frame_rate_n = 60000
+
+[intercom]
+sample_rate = 44000
+dscp = 70
+button_gpio = "gpio17"
+tally_led_gpio = { chip = "gpiochip0", red = 5, green = 5 }
+
+[intercom.echo]
+far_threshold = 1.5
+"#;
+        let (config, errors) = check_source(source);
+        assert!(config.is_some());
+        let found: Vec<(String, Option<usize>)> = errors
+            .iter()
+            .map(|error| (error.field.clone(), error.line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("capture.deinterlace".to_string(), Some(4)),
+                ("ndi.frame_rate_d".to_string(), Some(6)),
+                ("intercom.sample_rate".to_string(), Some(10)),
+                ("intercom.dscp".to_string(), Some(11)),
+                ("intercom.button_gpio".to_string(), Some(12)),
+                ("intercom.tally_led_gpio.green".to_string(), Some(13)),
+                ("intercom.echo.far_threshold".to_string(), Some(16)),
+            ]
+        );
+        assert_eq!(
+            errors[2].to_string(),
+            "line 10: intercom.sample_rate: 44000 Hz is not a VBAN sample rate"
+        );
+    }
+
+    #[test]
+    fn test_check_source_unknown_keys() {
+        let source = "hostname = \"cam\"\nndi_nmae = \"usb\"\n\n[capture]\nstall_timeout = 3\n";
+        let (_, errors) = check_source(source);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].field, "capture.stall_timeout");
+        assert_eq!(errors[0].line, Some(5));
+        assert_eq!(
+            errors[0].message,
+            "unknown key (did you mean \"stall_timeout_secs\"?)"
+        );
+        assert_eq!(
+            errors[1].to_string(),
+            "line 2: ndi_nmae: unknown key (did you mean \"ndi_name\"?)"
+        );
+    }
+
+    #[test]
+    fn test_known_keys_match_template() {
+        // Every section and key in the table is documented in the template
+        let table: toml::Table = toml::from_str(&uncommented_template()).unwrap();
+        fn walk(table: &toml::Table, section: &str, found: &mut Vec<(String, Vec<String>)>) {
+            let mut keys: Vec<String> = table.keys().cloned().collect();
+            keys.sort();
+            found.push((section.to_string(), keys));
+            for (key, value) in table {
+                let path = if section.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", section, key)
+                };
+                match value {
+                    // Profiles take the top-level keys again
+                    toml::Value::Table(_) if path == "profile" => {}
+                    toml::Value::Table(inner) => walk(inner, &path, found),
+                    toml::Value::Array(items) => {
+                        for inner in items.iter().filter_map(toml::Value::as_table) {
+                            walk(inner, &path, found);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        let mut found = Vec::new();
+        walk(&table, "", &mut found);
+        found.sort();
+        let mut expected: Vec<(String, Vec<String>)> = KNOWN_KEYS
+            .iter()
+            .map(|(section, keys)| {
+                let mut keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+                keys.sort();
+                (section.to_string(), keys)
+            })
+            .collect();
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn test_unknown_keys_nested() {
+        let source = r#"
+[capture.audio]
+device = "hw:CARD=MS2109"
+gain = 2
+
+[intercom]
+side_tone_volume = 50.0
+tally_led_gpio = { chip = "gpiochip0", red = 1, gren = 2 }
+
+[intercom.echo]
+enable = true
+
+[intercom.extra]
+anything = 1
+"#;
+        let errors = unknown_keys(source);
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "capture.audio.gain",
+                "intercom.echo.enable",
+                "intercom.extra",
+                "intercom.side_tone_volume",
+                "intercom.tally_led_gpio.gren",
+            ]
+        );
+        // Unknown tables are reported once, not per key inside them
+        assert_eq!(errors[2].line, Some(13));
+        assert_eq!(errors[3].line, Some(7));
+        assert!(errors[3].message.contains("\"sidetone_gain\""));
+        assert!(errors[4].message.contains("\"green\""));
+        assert!(errors[1].message.contains("\"enabled\""));
+        assert_eq!(errors[0].message, "unknown key");
+    }
+
+    const PROFILES: &str = r#"ndi_name = "cam"
+device = "/dev/video0"
+
+[capture]
+fps = "59.94"
+format_priority = ["YUYV", "NV12"]
+
+[intercom]
+stream = "cam1"
+mic_gain = 12.0
+
+[profile.studio.intercom]
+mic_gain = 20.0
+
+[profile.streaming]
+device = "/dev/video2"
+intercom = false
+
+[profile.streaming.capture]
+format_priority = ["MJPG"]
+"#;
+
+    #[test]
+    fn test_merge_tables() {
+        let mut base: toml::Table = toml::from_str(
+            "a = 1\nlist = [1, 2, 3]\n[t]\nx = 1\ny = 2\n[t.deep]\nz = 3\nkeep = true\n",
+        )
+        .unwrap();
+        let overlay: toml::Table = toml::from_str(
+            "list = [9]\nnew = \"n\"\n[t]\ny = 20\n[t.deep]\nz = 30\n[other]\nk = 1\n",
+        )
+        .unwrap();
+        merge_tables(&mut base, overlay);
+        let expected: toml::Table = toml::from_str(
+            "a = 1\nlist = [9]\nnew = \"n\"\n[t]\nx = 1\ny = 20\n[t.deep]\nz = 30\nkeep = true\n[other]\nk = 1\n",
+        )
+        .unwrap();
+        assert_eq!(base, expected);
+
+        // A value of another kind replaces a table, and the other way round
+        let mut base: toml::Table = toml::from_str("t = { x = 1 }\ns = 1\n").unwrap();
+        merge_tables(&mut base, toml::from_str("t = 5\ns = { y = 2 }\n").unwrap());
+        assert_eq!(base, toml::from_str("t = 5\ns = { y = 2 }\n").unwrap());
+
+        // false removes a table but is an ordinary value elsewhere
+        let mut base: toml::Table = toml::from_str("t = { x = 1 }\nflag = true\n").unwrap();
+        merge_tables(
+            &mut base,
+            toml::from_str("t = false\nflag = false\n").unwrap(),
+        );
+        assert_eq!(base, toml::from_str("flag = false\n").unwrap());
+    }
+
+    #[test]
+    fn test_profile_table() {
+        let base = profile_table(PROFILES, None).unwrap();
+        assert!(!base.contains_key("profile"));
+        assert_eq!(base["device"].as_str(), Some("/dev/video0"));
+
+        let studio = profile_table(PROFILES, Some("studio")).unwrap();
+        assert_eq!(studio["intercom"]["mic_gain"].as_float(), Some(20.0));
+        assert_eq!(studio["intercom"]["stream"].as_str(), Some("cam1"));
+        assert_eq!(studio["device"], base["device"]);
+
+        // Arrays and scalars replace the base value whole
+        let streaming = profile_table(PROFILES, Some("streaming")).unwrap();
+        assert_eq!(streaming["device"].as_str(), Some("/dev/video2"));
+        assert!(!streaming.contains_key("intercom"));
+        let priority = streaming["capture"]["format_priority"].as_array().unwrap();
+        assert_eq!(priority.len(), 1);
+        assert_eq!(streaming["capture"]["fps"].as_str(), Some("59.94"));
+
+        let e = profile_table(PROFILES, Some("field")).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "unknown profile \"field\", available: streaming, studio"
+        );
+        let e = profile_table("device = \"auto\"\n", Some("studio")).unwrap_err();
+        assert!(e.to_string().contains("none are defined"), "{}", e);
+    }
+
+    #[test]
+    fn test_check_profile_source() {
+        let source = r#"[intercom]
+dscp = 70
+
+[profile.studio]
+ndi_name = ""
+
+[profile.studio.intercom]
+dscp = 10
+side_tone_gain = 50.0
+"#;
+        // Unknown keys inside profiles are found without selecting one
+        let (_, errors) = check_source(source);
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["profile.studio.intercom.side_tone_gain", "intercom.dscp"]
+        );
+        assert_eq!(errors[0].line, Some(9));
+        assert!(errors[0].message.contains("\"sidetone_gain\""));
+        assert_eq!(errors[1].line, Some(2));
+
+        // The profile fixes dscp but breaks ndi_name, reported in its section
+        let (config, errors) = check_profile_source(source, Some("studio"));
+        assert_eq!(config.unwrap().intercom.unwrap().dscp, 10);
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec!["profile.studio.intercom.side_tone_gain", "ndi_name"]
+        );
+        assert_eq!(errors[1].line, Some(5));
+
+        let (config, errors) = check_profile_source(source, Some("streaming"));
+        assert!(config.is_none());
+        assert_eq!(errors[0].field, "profile");
+        assert!(errors[0].message.contains("available: studio"));
+
+        let (_, errors) = check_source("[profile.studio.profile.other]\nndi_name = \"x\"\n");
+        assert_eq!(errors[0].field, "profile.studio.profile");
+    }
+
+    #[test]
+    fn test_load_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, PROFILES).unwrap();
+
+        let base = Config::load(&path).unwrap();
+        assert_eq!(base.intercom.as_ref().unwrap().mic_gain, 12.0);
+        let studio = Config::load_profile(&path, Some("studio")).unwrap();
+        assert_eq!(studio.intercom.as_ref().unwrap().mic_gain, 20.0);
+        assert_eq!(studio.ndi_name, "cam");
+        assert!(Config::load_profile(&path, Some("field")).is_err());
+
+        // Without a file only the base defaults exist
+        let missing = dir.path().join("missing.toml");
+        assert_eq!(Config::load(&missing).unwrap(), Config::default());
+        assert!(Config::load_profile(&missing, Some("studio")).is_err());
+    }
+
+    #[test]
+    fn test_suggestion_ranking() {
+        let intercom = section_keys("intercom").unwrap();
+        assert_eq!(suggest("mic_gian", intercom), Some("mic_gain"));
+        assert_eq!(suggest("side_tone_gain", intercom), Some("sidetone_gain"));
+        // The closest of several keys sharing a stem wins
+        assert_eq!(suggest("record_keeps", intercom), Some("record_keep"));
+        assert_eq!(suggest("recorddir", intercom), Some("record_dir"));
+        assert_eq!(suggest("volume", intercom), None);
+    }
+
+    #[test]
+    fn test_strict_rejects_unknown_keys() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "hostname = \"cam\"\nndi_nmae = \"x\"").unwrap();
+        assert!(Config::load(file.path()).is_ok());
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "strict = true\nndi_nmae = \"x\"").unwrap();
+        let err = Config::load(file.path()).unwrap_err().to_string();
+        assert!(err.contains("line 2: ndi_nmae: unknown key"), "{}", err);
+    }
+
+    #[test]
+    fn test_check_source_syntax_and_type_errors() {
+        let (config, errors) = check_source("hostname = \"cam\"\nstatus_port = \"http\"\n");
+        assert!(config.is_none());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, Some(2));
+
+        let (config, errors) = check_source("[intercom\nstream = 1\n");
+        assert!(config.is_none());
+        assert_eq!(errors[0].line, Some(1));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
+    #[test]
+    fn test_network_config() {
+        let (config, errors) = check_source(
+            "[network]\nmode = \"static\"\naddress = \"192.168.1.100/24\"\ngateway = \"192.168.1.1\"\ndns = \"1.1.1.1, 8.8.8.8\"\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        let network = config.unwrap().network.unwrap();
+        assert_eq!(network.interface, default_network_interface());
+        assert_eq!(network.address.as_deref(), Some("192.168.1.100/24"));
+        assert!(network.dhcp_client.is_none());
+
+        let (_, errors) = check_source(
+            "[network]\nmode = \"static\"\ngateway = \"10.0.0.1\"\ndns = \"resolver\"\n",
+        );
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["network.address", "network.dns"]);
+
+        let (_, errors) = check_source(
+            "[network]\nmode = \"static\"\naddress = \"192.168.1.100/24\"\ngateway = \"10.0.0.1\"\n",
+        );
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "network.gateway");
+        assert_eq!(errors[0].line, Some(4));
+    }
 
     #[test]
-    fn test_config_default_values() {
-        let config = Config::default();
-        assert_eq!(config.hostname, "camera-box");
-        assert_eq!(config.ndi_name, "usb");
-        assert_eq!(config.device, "auto");
-        assert!(config.display.is_none());
-        assert!(config.intercom.is_none());
+    fn test_alsa_card_present() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join("MS2109")).unwrap();
+        std::fs::create_dir(root.path().join("card1")).unwrap();
+        let present = |device| alsa_card_present(root.path(), device);
+        assert_eq!(present("hw:CARD=MS2109,DEV=0"), Some(true));
+        assert_eq!(present("plughw:1,0"), Some(true));
+        assert_eq!(present("hw:CARD=HID,DEV=0"), Some(false));
+        assert_eq!(present("hw:2"), Some(false));
+        assert_eq!(present("default"), None);
     }
 
     #[test]
@@ -230,6 +3077,8 @@ mod tests {
 hostname = "CAM1"
 ndi_name = "camera"
 device = "/dev/video0"
+announce = true
+status_port = 9000
 
 [display]
 source = "STRIH-SNV"
@@ -249,6 +3098,8 @@ sidetone_gain = 15.0
         assert_eq!(config.hostname, "CAM1");
         assert_eq!(config.ndi_name, "camera");
         assert_eq!(config.device, "/dev/video0");
+        assert!(config.announce);
+        assert_eq!(config.status_port, 9000);
 
         let display = config.display.unwrap();
         assert_eq!(display.source, "STRIH-SNV");
@@ -325,6 +3176,410 @@ stream = "test"
         assert!((intercom.headphone_gain - 15.0).abs() < 0.001);
         assert!(intercom.limiter_enabled);
         assert!((intercom.limiter_threshold - 0.5).abs() < 0.001);
+        assert!(intercom.allowed_sources.is_empty());
+        assert!(!intercom.auto_lock);
+        assert_eq!(intercom.link_timeout_ms, 2000);
+        assert_eq!(intercom.tx_codec, "pcm16");
+        assert!(!intercom.ping_announce);
+        assert_eq!(intercom.dscp, 46);
+        assert_eq!(intercom.recv_buffer_size, 262144);
+        assert!(intercom.interface.is_none());
+        assert_eq!(intercom.port, 6980);
+        assert_eq!(intercom.listen, "[::]:6980");
+        assert!(intercom.bind_address.is_none());
+        assert!(!intercom.echo.enabled);
+        assert!((intercom.echo.duck_db - 20.0).abs() < 0.001);
+        assert!(intercom.record_dir.is_none());
+        assert_eq!(intercom.record_segment_secs, 60);
+        assert_eq!(intercom.record_keep, 10);
+        assert_eq!(intercom.mute_key, "KEY_POWER");
+        assert!(intercom.button_gpio.is_none());
+        assert!(intercom.tally_led_gpio.is_none());
+        assert_eq!(intercom.auto_unmute, "never");
+        assert!(intercom.call_tone);
+        assert_eq!(intercom.mic_channel, "mix");
+        assert_eq!(intercom.period_frames, 256);
+        assert_eq!(intercom.periods, 4);
+        assert!(!intercom.stream_ignore_case);
+    }
+
+    #[test]
+    fn test_intercom_config_allowed_sources() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+[intercom]
+stream = "cam1"
+allowed_sources = ["10.0.0.5", "strih.lan"]
+auto_lock = true
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(file.path()).unwrap();
+        let intercom = config.intercom.unwrap();
+        assert_eq!(intercom.allowed_sources, vec!["10.0.0.5", "strih.lan"]);
+        assert!(intercom.auto_lock);
+    }
+
+    #[test]
+    fn test_intercom_config_port_and_listen() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+[intercom]
+stream = "cam2"
+port = 6990
+listen = "127.0.0.1:0"
+bind_address = "[fd00::5]"
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(file.path()).unwrap();
+        let intercom = config.intercom.unwrap();
+        assert_eq!(intercom.port, 6990);
+        assert_eq!(intercom.listen, "127.0.0.1:0");
+        assert_eq!(intercom.bind_address.as_deref(), Some("[fd00::5]"));
+
+        let (_, errors) =
+            check_source("[intercom]\nlisten = \"[::]:6980\"\nbind_address = \"fd00::5\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let (_, errors) = check_source("[intercom]\nbind_address = \"strih.lan\"\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "intercom.bind_address");
+        assert_eq!(errors[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_intercom_gpio_config() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+[intercom]
+stream = "cam1"
+mute_key = "KEY_F13"
+button_gpio = "gpiochip0:17"
+tally_led_gpio = {{ chip = "gpiochip0", red = 22, green = 23 }}
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(file.path()).unwrap();
+        let intercom = config.intercom.unwrap();
+        assert_eq!(intercom.mute_key, "KEY_F13");
+        assert_eq!(intercom.button_gpio.as_deref(), Some("gpiochip0:17"));
+        let led = intercom.tally_led_gpio.unwrap();
+        assert_eq!(led.chip, "gpiochip0");
+        assert_eq!(led.red, 22);
+        assert_eq!(led.green, 23);
+    }
+
+    #[test]
+    fn test_intercom_echo_section() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+[intercom]
+stream = "cam1"
+
+[intercom.echo]
+enabled = true
+duck_db = 12.0
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(file.path()).unwrap();
+        let echo = config.intercom.unwrap().echo;
+        assert!(echo.enabled);
+        assert!((echo.duck_db - 12.0).abs() < 0.001);
+        // Unset fields keep their defaults
+        assert!((echo.far_threshold - 0.02).abs() < 0.001);
+        assert!((echo.release_ms - 200.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_intercom_silence_section() {
+        let source = r#"
+[intercom]
+stream = "cam1"
+
+[intercom.silence]
+secs = 20
+notify = true
+"#;
+        let (config, errors) = check_source(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let silence = config.unwrap().intercom.unwrap().silence;
+        assert!(silence.enabled);
+        assert_eq!(silence.secs, 20);
+        assert!(silence.notify);
+        assert!((silence.threshold_db + 60.0).abs() < 0.001);
+
+        let (_, errors) = check_source("[intercom.silence]\nthreshold_db = 6.0\nsecs = 0\n");
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            ["intercom.silence.threshold_db", "intercom.silence.secs"]
+        );
+        assert_eq!(errors[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_intercom_stream_ignore_case() {
+        let (config, errors) = check_source("[intercom]\nstream_ignore_case = true\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(config.unwrap().intercom.unwrap().stream_ignore_case);
+    }
+
+    #[test]
+    fn test_intercom_auto_unmute() {
+        let (config, errors) = check_source("[intercom]\nauto_unmute = \"program\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.unwrap().intercom.unwrap().auto_unmute, "program");
+
+        let (_, errors) = check_source("[intercom]\nauto_unmute = \"always\"\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "intercom.auto_unmute");
+        assert_eq!(errors[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_intercom_mic_channel() {
+        let (config, errors) = check_source("[intercom]\nmic_channel = \"right\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.unwrap().intercom.unwrap().mic_channel, "right");
+
+        let (_, errors) = check_source("[intercom]\nmic_channel = \"both\"\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "intercom.mic_channel");
+        assert_eq!(errors[0].line, Some(2));
+    }
+
+    #[test]
+    fn test_intercom_periods() {
+        let (config, errors) = check_source("[intercom]\nperiod_frames = 64\nperiods = 2\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let intercom = config.unwrap().intercom.unwrap();
+        assert_eq!((intercom.period_frames, intercom.periods), (64, 2));
+
+        let (_, errors) = check_source("[intercom]\nperiod_frames = 8\nperiods = 1\n");
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["intercom.period_frames", "intercom.periods"]);
+        assert_eq!(errors[1].line, Some(3));
+    }
+
+    #[test]
+    fn test_capture_config_deinterlace() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+[capture]
+deinterlace = "blend"
+stall_timeout_secs = 0
+range = "full"
+
+[capture.crop]
+x = 960
+width = 1920
+height = 1080
+
+[capture.image]
+width = 1280
+height = 720
+frame_rate_n = 25
+
+[capture.replay]
+frames = 60
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(file.path()).unwrap();
+        assert_eq!(config.capture.deinterlace, "blend");
+        assert_eq!(config.capture.stall_timeout_secs, 0);
+        assert_eq!(config.capture.range, "full");
+        let crop = config.capture.crop.unwrap();
+        assert_eq!(
+            (crop.x, crop.y, crop.width, crop.height),
+            (960, 0, 1920, 1080)
+        );
+        let image = &config.capture.image;
+        assert_eq!((image.width, image.height), (1280, 720));
+        assert_eq!((image.frame_rate_n, image.frame_rate_d), (25, 1));
+        let replay = config.capture.replay.unwrap();
+        assert_eq!(replay.frames, 60);
+        assert_eq!(replay.dir, default_replay_dir());
+    }
+
+    #[test]
+    fn test_capture_fps() {
+        let rate = |source: &str| {
+            check_source(source)
+                .0
+                .unwrap()
+                .capture
+                .frame_rate()
+                .unwrap()
+        };
+        assert_eq!(rate(""), crate::probe::TARGET_RATE);
+        let ntsc = crate::capture::FrameRate {
+            numerator: 60000,
+            denominator: 1001,
+        };
+        assert_eq!(rate("[capture]\nfps = \"59.94\"\n"), ntsc);
+        assert_eq!(rate("[capture]\nfps = \"60000/1001\"\n"), ntsc);
+
+        let (_, errors) = check_source("[capture]\nfps = \"fast\"\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].to_string().starts_with("line 2: capture.fps: "));
+        let (_, errors) = check_source("[capture]\nfps = \"300\"\n");
+        assert_eq!(errors[0].to_string(), "line 2: capture.fps: above 240 fps");
+    }
+
+    #[test]
+    fn test_capture_format_priority() {
+        let formats = |source: &str| check_source(source).0.unwrap().capture.formats().unwrap();
+        assert_eq!(formats(""), crate::probe::default_format_priority());
+        assert_eq!(
+            formats("[capture]\nformat_priority = [\"nv12\", \"UYVY\", \"YUYV\", \"MJPG\"]\n"),
+            vec![
+                FourCC::new(b"NV12"),
+                FourCC::new(b"UYVY"),
+                FourCC::new(b"YUYV"),
+                FourCC::new(b"MJPG"),
+            ]
+        );
+
+        let (_, errors) = check_source("[capture]\nformat_priority = [\"NV12\", \"H264\"]\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .starts_with("line 2: capture.format_priority: \"H264\" is not a convertible format"));
+        let (_, errors) = check_source("[capture]\nformat_priority = []\n");
+        assert_eq!(
+            errors[0].to_string(),
+            "line 2: capture.format_priority: must list at least one format"
+        );
+    }
+
+    #[test]
+    fn test_capture_io_mode() {
+        let (config, errors) = check_source("[capture]\nio_mode = \"dmabuf\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.unwrap().capture.io_mode, "dmabuf");
+
+        let (_, errors) = check_source("[capture]\nrange = \"full\"\nio_mode = \"userptr\"\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "capture.io_mode");
+        assert_eq!(errors[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_ndi_aspect() {
+        use crate::aspect::AspectRatio;
+
+        let (config, errors) = check_source("[ndi]\naspect = \"4:3\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let aspect = config.unwrap().ndi.aspect.aspect().unwrap();
+        assert_eq!(aspect, AspectRatio::Fixed(4.0 / 3.0));
+
+        // A bare number is a ratio too
+        let (config, errors) = check_source("[ndi]\naspect = 2.39\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.unwrap().ndi.aspect, AspectValue::Ratio(2.39));
+        assert_eq!(AspectValue::default().aspect().unwrap(), AspectRatio::Auto);
+
+        let (_, errors) = check_source("[ndi]\naspect = \"wide\"\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "ndi.aspect");
+        let (_, errors) = check_source("[ndi]\naspect = 0\n");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_ndi_pacing_config() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+[ndi]
+pacing = "software"
+frame_rate_n = 60000
+frame_rate_d = 1001
+on_conflict = "fail"
+timestamp_burn_in = true
+zero_copy_uyvy = true
+
+[ndi.overlay]
+image = "/etc/camera-box/logo.bgra"
+width = 200
+height = 80
+x = 1700
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(file.path()).unwrap();
+        assert_eq!(config.ndi.pacing, "software");
+        assert_eq!(config.ndi.frame_rate_n, Some(60000));
+        assert_eq!(config.ndi.frame_rate_d, Some(1001));
+        assert_eq!(config.ndi.on_conflict, "fail");
+        assert!(config.ndi.timestamp_burn_in);
+        assert!(config.ndi.zero_copy_uyvy);
+        let overlay = config.ndi.overlay.unwrap();
+        assert_eq!(overlay.image, "/etc/camera-box/logo.bgra");
+        assert_eq!(
+            (overlay.width, overlay.height, overlay.x, overlay.y),
+            (200, 80, 1700, 0)
+        );
+        assert_eq!(overlay.alpha, 1.0);
+    }
+
+    #[test]
+    fn test_ndi_audio_only_validation() {
+        let (config, errors) = check_source("[ndi]\naudio_only = true\n\n[intercom]\n");
+        assert!(config.unwrap().ndi.audio_only);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // A camera device and no intercom are both rejected
+        let (_, errors) = check_source("device = \"/dev/video0\"\n\n[ndi]\naudio_only = true\n");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "line 4: ndi.audio_only: cannot be combined with device = \"/dev/video0\"",
+                "line 4: ndi.audio_only: requires an [intercom] section",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_capture_audio_config() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            r#"
+[capture.audio]
+device = "hw:CARD=MS2109"
+quirk = "ms2109"
+"#
+        )
+        .unwrap();
+
+        let config = Config::load(file.path()).unwrap();
+        let audio = config.capture.audio.unwrap();
+        assert_eq!(audio.device, "hw:CARD=MS2109");
+        assert_eq!(audio.channels, 2);
+        assert_eq!(audio.sample_rate, 48000);
+        assert_eq!(audio.quirk, "ms2109");
+        assert_eq!(config.capture.deinterlace, "off");
     }
 
     #[test]
@@ -343,6 +3598,15 @@ source = "NDI Source"
         let display = config.display.unwrap();
         assert_eq!(display.source, "NDI Source");
         assert_eq!(display.fb_device, "/dev/fb0"); // Default
+        assert_eq!(display.color_format, "uyvy");
+        assert_eq!(display.max_age_ms, 100);
+        assert_eq!(display.max_fps, 30);
+        assert!(!display.histogram);
+        assert!(!display.zebra);
+        assert_eq!(display.zebra_percent, 95);
+        assert_eq!(display.standby_dim, 0.3);
+        assert_eq!(display.standby_dim_secs, 600);
+        assert_eq!(display.idle_exit_mins, 0);
     }
 
     #[test]
@@ -350,7 +3614,35 @@ source = "NDI Source"
         assert_eq!(default_hostname(), "camera-box");
         assert_eq!(default_ndi_name(), "usb");
         assert_eq!(default_device(), "auto");
+        assert_eq!(default_format_priority().len(), 8);
         assert_eq!(default_fb_device(), "/dev/fb0");
+        assert_eq!(default_display_max_age_ms(), 100);
+        assert_eq!(default_display_max_fps(), 30);
+        assert_eq!(default_zebra_percent(), 95);
+        assert_eq!(default_standby_dim(), 0.3);
+        assert_eq!(default_standby_dim_secs(), 600);
+        assert_eq!(default_deinterlace(), "off");
+        assert_eq!(default_stall_timeout_secs(), 5);
+        assert_eq!(default_controls_file(), "/var/lib/camera-box/controls.toml");
+        assert_eq!(default_range(), "auto");
+        assert_eq!(default_io_mode(), "mmap");
+        assert_eq!(default_capture_audio_channels(), 2);
+        assert_eq!(default_capture_audio_sample_rate(), 48000);
+        assert_eq!(default_pacing(), "off");
+        assert_eq!(default_on_conflict(), "suffix");
+        assert_eq!(default_overlay_alpha(), 1.0);
+        assert_eq!(default_image_width(), 1920);
+        assert_eq!(default_image_height(), 1080);
+        assert_eq!(default_image_frame_rate_n(), 30);
+        assert_eq!(default_image_frame_rate_d(), 1);
+        assert_eq!(default_control_socket(), "/run/camera-box.sock");
+        assert_eq!(default_status_port(), 8080);
+        assert_eq!(default_stats_interval_secs(), 10);
+        assert_eq!(default_state_file(), "/var/lib/camera-box/state.toml");
+        assert_eq!(default_network_timeout_secs(), 30);
+        assert_eq!(default_device_timeout_secs(), 10);
+        assert_eq!(default_audio_timeout_secs(), 10);
+        assert_eq!(default_ndi_timeout_secs(), 10);
         assert_eq!(default_intercom_stream(), "cam1");
         assert_eq!(default_intercom_target(), "strih.lan");
         assert_eq!(default_intercom_sample_rate(), 48000);
@@ -360,6 +3652,40 @@ source = "NDI Source"
         assert!((default_headphone_gain() - 15.0).abs() < 0.001);
         assert!(default_limiter_enabled());
         assert!((default_limiter_threshold() - 0.5).abs() < 0.001);
+        assert_eq!(default_link_timeout_ms(), 2000);
+        assert_eq!(default_tx_codec(), "pcm16");
+        assert_eq!(default_dscp(), 46);
+        assert_eq!(default_recv_buffer_size(), 262144);
+        assert_eq!(default_intercom_port(), 6980);
+        assert_eq!(default_intercom_listen(), "[::]:6980");
+        assert!((default_echo_far_threshold() - 0.02).abs() < 0.001);
+        assert!((default_echo_near_threshold() - 0.05).abs() < 0.001);
+        assert!((default_echo_duck_db() - 20.0).abs() < 0.001);
+        assert!((default_echo_attack_ms() - 10.0).abs() < 0.001);
+        assert!((default_echo_release_ms() - 200.0).abs() < 0.001);
+        assert!(default_silence_enabled());
+        assert!((default_silence_threshold_db() + 60.0).abs() < 0.001);
+        assert_eq!(default_silence_secs(), 60);
+        assert_eq!(default_record_segment_secs(), 60);
+        assert_eq!(default_record_keep(), 10);
+        assert_eq!(default_mute_key(), "KEY_POWER");
+        assert_eq!(default_auto_unmute(), "never");
+        assert!(default_call_tone());
+        assert_eq!(default_mic_channel(), "mix");
+        assert_eq!(default_period_frames(), 256);
+        assert_eq!(default_periods(), 4);
+        assert_eq!(default_serial_device(), "/dev/ttyUSB0");
+        assert_eq!(default_serial_baud(), 9600);
+        assert_eq!(default_serial_stream(), "serial");
+        assert_eq!(default_serial_listen(), "[::]:6981");
+        assert_eq!(default_record_path(), "/var/lib/camera-box/record");
+        assert_eq!(default_output_port(), 8081);
+        assert_eq!(default_output_width(), 640);
+        assert_eq!(default_output_height(), 360);
+        assert_eq!(default_output_fps(), 5);
+        assert_eq!(default_output_quality(), 70);
+        assert_eq!(default_proxy_name_suffix(), "-proxy");
+        assert_eq!(default_proxy_fps_divisor(), 2);
     }
 
     #[test]
@@ -372,21 +3698,429 @@ source = "NDI Source"
         assert_eq!(config.device, "auto");
     }
 
+    #[test]
+    fn test_display_color_format_validation() {
+        let (config, errors) =
+            check_source("[display]\nsource = \"STRIH\"\ncolor_format = \"BGRA\"\n");
+        assert_eq!(config.unwrap().display.unwrap().color_format, "BGRA");
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let (_, errors) = check_source("[display]\nsource = \"STRIH\"\ncolor_format = \"rgb\"\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .starts_with("line 3: display.color_format:"));
+    }
+
+    #[test]
+    fn test_serial_config() {
+        let (config, errors) = check_source("[serial]\ntarget = \"ptz.lan\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let serial = config.unwrap().serial.unwrap();
+        assert_eq!(serial.device, "/dev/ttyUSB0");
+        assert_eq!(serial.baud, 9600);
+        assert_eq!(serial.stream, "serial");
+        assert_eq!(serial.channel, 0);
+        assert_eq!(serial.target, "ptz.lan");
+        assert_eq!(serial.port, 6980);
+        assert_eq!(serial.listen, "[::]:6981");
+
+        // The target has no default
+        let (config, errors) = check_source("[serial]\n");
+        assert!(config.is_none());
+        assert_eq!(errors.len(), 1);
+
+        let (_, errors) = check_source(
+            "[serial]\ntarget = \"ptz.lan\"\nbaud = 12345\nstream = \"\"\nlisten = \"6981\"\nspeed = 1\n",
+        );
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "line 6: serial.speed: unknown key",
+                "line 3: serial.baud: unsupported baud rate 12345",
+                "line 4: serial.stream: must not be empty",
+                "line 5: serial.listen: \"6981\" is not an address:port",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_record_config() {
+        let (config, errors) = check_source("hostname = \"cam\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let record = config.unwrap().record;
+        assert!(!record.enable);
+        assert_eq!(record.path, "/var/lib/camera-box/record");
+        assert_eq!(record.max_disk_mb, 0);
+
+        let (config, errors) =
+            check_source("[record]\nenable = true\npath = \"/mnt/usb\"\nmax_disk_mb = 20000\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let record = config.unwrap().record;
+        assert!(record.enable);
+        assert_eq!(record.path, "/mnt/usb");
+        assert_eq!(record.max_disk_mb, 20000);
+
+        let (_, errors) = check_source("[record]\nenable = true\npath = \"\"\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "line 3: record.path: must not be empty"
+        );
+    }
+
+    #[test]
+    fn test_proxy_config() {
+        let (config, errors) = check_source("hostname = \"cam\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let proxy = config.unwrap().ndi.proxy;
+        assert!(!proxy.enable);
+        assert_eq!(proxy.name_suffix, "-proxy");
+        assert_eq!((proxy.width, proxy.height), (640, 360));
+        assert_eq!(proxy.fps_divisor, 2);
+
+        let source = "[ndi]\nproxy = { enable = true, name_suffix = \"-mv\", fps_divisor = 3 }\n";
+        let (config, errors) = check_source(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let proxy = config.unwrap().ndi.proxy;
+        assert!(proxy.enable);
+        assert_eq!(proxy.name_suffix, "-mv");
+        assert_eq!(proxy.fps_divisor, 3);
+
+        let source = r#"
+[ndi.proxy]
+enable = true
+name_suffix = ""
+width = 641
+fps_divisor = 0
+"#;
+        let (_, errors) = check_source(source);
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(messages.len(), 3, "{:?}", messages);
+        assert!(messages[0].contains("ndi.proxy.name_suffix: must not be empty"));
+        assert!(messages[1].contains("ndi.proxy.width: must be even"));
+        assert!(messages[2].contains("ndi.proxy.fps_divisor: 0 is outside 1..=60"));
+
+        // Only checked when enabled
+        let (_, errors) = check_source("[ndi.proxy]\nfps_divisor = 0\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn test_output_config() {
+        let (config, errors) = check_source("hostname = \"cam\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(config.unwrap().outputs.is_empty());
+
+        let source = r#"
+[[output]]
+type = "mjpeg_http"
+
+[[output]]
+type = "mjpeg_http"
+port = 9000
+width = 320
+height = 180
+fps = 2
+quality = 50
+"#;
+        let (config, errors) = check_source(source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        let outputs = config.unwrap().outputs;
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[0].port, 8081);
+        assert_eq!((outputs[0].width, outputs[0].height), (640, 360));
+        assert_eq!(outputs[0].fps, 5);
+        assert_eq!(outputs[1].port, 9000);
+        assert_eq!((outputs[1].width, outputs[1].height), (320, 180));
+        assert_eq!((outputs[1].fps, outputs[1].quality), (2, 50));
+
+        let source = r#"
+[[output]]
+type = "mjpeg_http"
+
+[[output]]
+type = "rtsp"
+port = 8081
+width = 321
+fps = 0
+"#;
+        let (_, errors) = check_source(source);
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(messages.len(), 4, "{:?}", messages);
+        assert!(messages[0].contains("output.type: Unsupported output type: rtsp"));
+        assert!(messages[1].contains("output.port: 8081 is used by another output"));
+        assert!(messages[2].contains("output.width: must be even"));
+        assert!(messages[3].contains("output.fps: 0 is outside 1..=30"));
+
+        // NDI outputs use no port and need a name of their own
+        let source = r#"
+[ndi.proxy]
+enable = true
+
+[[output]]
+type = "mjpeg_http"
+
+[[output]]
+type = "ndi"
+
+[[output]]
+type = "ndi"
+name_suffix = "-proxy"
+"#;
+        let (config, errors) = check_source(source);
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(messages.len(), 1, "{:?}", messages);
+        assert!(messages[0].contains("output.name_suffix: \"-proxy\" names another NDI sender"));
+        let outputs = config.unwrap().outputs;
+        assert_eq!(outputs[1].kind, "ndi");
+        assert_eq!(outputs[1].name_suffix, "-preview");
+    }
+
+    #[test]
+    fn test_unknown_keys_in_array_of_tables() {
+        let source =
+            "[[output]]\ntype = \"mjpeg_http\"\n\n[[output]]\ntype = \"mjpeg_http\"\nprot = 9000\n";
+        let errors = unknown_keys(source);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "line 6: output.prot: unknown key (did you mean \"port\"?)"
+        );
+    }
+
+    #[test]
+    fn test_status_token() {
+        let (config, errors) = check_source("status_port = 8090\n\n[status]\ntoken = \"s3cret\"\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let config = config.unwrap();
+        assert_eq!(config.status_port, 8090);
+        assert_eq!(config.status.token, "s3cret");
+        assert_eq!(Config::default().status.token, "");
+    }
+
+    #[test]
+    fn test_startup_timeouts() {
+        let (config, errors) = check_source("[startup]\nnetwork_timeout_secs = 90\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        let timeouts = config.unwrap().startup.timeouts();
+        assert_eq!(timeouts.network, std::time::Duration::from_secs(90));
+        assert_eq!(timeouts.device, std::time::Duration::from_secs(10));
+
+        let (_, errors) =
+            check_source("[startup]\nndi_timeout_secs = 0\naudio_timeout_secs = 601\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].to_string(),
+            "line 3: startup.audio_timeout_secs: 601 is outside 0..=600"
+        );
+    }
+
+    #[test]
+    fn test_display_max_age_validation() {
+        let (config, errors) = check_source("[display]\nsource = \"STRIH\"\nmax_age_ms = 0\n");
+        assert_eq!(config.unwrap().display.unwrap().max_age_ms, 0);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let (_, errors) = check_source("[display]\nsource = \"STRIH\"\nmax_age_ms = 60000\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .starts_with("line 3: display.max_age_ms:"));
+    }
+
+    #[test]
+    fn test_display_max_fps_validation() {
+        let (config, errors) = check_source("[display]\nsource = \"STRIH\"\nmax_fps = 0\n");
+        assert_eq!(config.unwrap().display.unwrap().max_fps, 0);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let (_, errors) = check_source("[display]\nsource = \"STRIH\"\nmax_fps = 1000\n");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "display.max_fps");
+        assert_eq!(errors[0].line, Some(3));
+    }
+
+    #[test]
+    fn test_display_zebra_validation() {
+        let (config, errors) = check_source(
+            "[display]\nsource = \"STRIH\"\nzebra = true\nhistogram = true\nzebra_percent = 100\n",
+        );
+        let display = config.unwrap().display.unwrap();
+        assert!(display.zebra && display.histogram);
+        assert_eq!(display.zebra_percent, 100);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let (_, errors) = check_source("[display]\nsource = \"STRIH\"\nzebra_percent = 0\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .starts_with("line 3: display.zebra_percent:"));
+    }
+
+    #[test]
+    fn test_display_standby_validation() {
+        let (config, errors) = check_source(
+            "[display]\nsource = \"STRIH\"\nstandby_dim = 0.5\nstandby_dim_secs = 0\n",
+        );
+        assert!(errors.is_empty(), "{:?}", errors);
+        let display = config.unwrap().display.unwrap();
+        assert_eq!(display.standby_dim, 0.5);
+        assert_eq!(display.standby_dim_secs, 0);
+
+        let (_, errors) = check_source("[display]\nsource = \"STRIH\"\nstandby_dim = 1.5\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .starts_with("line 3: display.standby_dim:"));
+
+        let display_only = "device = \"none\"\n\n[display]\nsource = \"STRIH\"\n";
+        let (config, errors) = check_source(&format!("{}idle_exit_mins = 30\n", display_only));
+        assert!(errors.is_empty(), "{:?}", errors);
+        let config = config.unwrap();
+        assert!(config.is_display_only());
+        assert_eq!(config.display.unwrap().idle_exit_mins, 30);
+
+        let (_, errors) = check_source(&format!("{}idle_exit_mins = 20000\n", display_only));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .starts_with("line 5: display.idle_exit_mins:"));
+
+        // Exiting would take a camera or the intercom off air
+        for source in [
+            "[display]\nsource = \"STRIH\"\nidle_exit_mins = 30\n".to_string(),
+            format!("{}idle_exit_mins = 30\n\n[intercom]\n", display_only),
+        ] {
+            let (config, errors) = check_source(&source);
+            assert!(!config.unwrap().is_display_only());
+            assert_eq!(errors.len(), 1, "{:?}", errors);
+            assert!(errors[0]
+                .to_string()
+                .ends_with("display.idle_exit_mins: needs a display-only box (device = \"none\", no [intercom])"));
+        }
+    }
+
+    #[test]
+    fn test_stats_interval_validation() {
+        let (config, errors) = check_source("stats_interval_secs = 30\n");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(config.unwrap().stats_interval_secs, 30);
+
+        let (_, errors) = check_source("hostname = \"cam\"\nstats_interval_secs = 0\n");
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0]
+            .to_string()
+            .starts_with("line 2: stats_interval_secs:"));
+    }
+
+    #[test]
+    fn test_placeholders_expand_on_load() {
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "ndi_name = \"{{hostname}}-cam\"\n\n[intercom]\nstream = \"{{{{x}}}}\""
+        )
+        .unwrap();
+        let config = Config::load(file.path()).unwrap();
+        let hostname = crate::ndi_conflict::system_hostname().unwrap();
+        assert_eq!(config.ndi_name, format!("{}-cam", hostname));
+        assert_eq!(config.intercom.unwrap().stream, "{x}");
+
+        let mut file = NamedTempFile::new().unwrap();
+        writeln!(file, "ndi_name = \"{{host}}-cam\"").unwrap();
+        let error = format!("{:#}", Config::load(file.path()).unwrap_err());
+        assert!(
+            error.ends_with(
+                "ndi_name: unknown placeholder {host} (supported: {hostname}, {serial}, {mac})"
+            ),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_placeholders_checked_without_expanding() {
+        let (config, errors) =
+            check_source("ndi_name = \"{mac}\"\n\n[intercom]\nstream = \"cam{\"\n");
+        // The check keeps the template as written
+        assert_eq!(config.unwrap().ndi_name, "{mac}");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            ["line 4: intercom.stream: unclosed '{' (write '{{' for a literal brace)"]
+        );
+    }
+
+    #[test]
+    fn test_intercom_routing_table() {
+        use crate::intercom::{Route, Routing};
+
+        let config: Config = toml::from_str(
+            "[intercom]\nrouting = { vban = \"left\", sidetone = \"right\", tones = 0.5 }\n",
+        )
+        .unwrap();
+        let routing = config.intercom.unwrap().routing.routing().unwrap();
+        assert_eq!(
+            routing,
+            Routing {
+                vban: Route::Pan(-1.0),
+                sidetone: Route::Pan(1.0),
+                tones: Route::Pan(0.5),
+            }
+        );
+
+        // Omitted sources stay in both ears; integer pans are accepted
+        let config: Config = toml::from_str("[intercom.routing]\nvban = -1\n").unwrap();
+        let routing = config.intercom.unwrap().routing.routing().unwrap();
+        assert_eq!(routing.vban, Route::Pan(-1.0));
+        assert_eq!(routing.sidetone, Route::Both);
+        assert_eq!(routing.tones, Route::Both);
+    }
+
+    #[test]
+    fn test_intercom_routing_errors() {
+        let (_, errors) = check_source(
+            "[intercom]\nrouting = { vban = \"middle\", sidetone = 2.0, dir = \"right\" }\n",
+        );
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "line 2: intercom.routing.dir: unknown key",
+                "line 2: intercom.routing.vban: Unsupported route: middle. Supported: left, right, both or a pan from -1.0 to 1.0",
+                "line 2: intercom.routing.sidetone: pan 2 is outside -1.0..1.0",
+            ]
+        );
+    }
+
     #[test]
     fn test_display_config_clone() {
         let display = DisplayConfig {
             source: "test".to_string(),
             fb_device: "/dev/fb0".to_string(),
+            color_format: "bgra".to_string(),
+            max_age_ms: 100,
+            max_fps: 30,
+            histogram: true,
+            zebra: false,
+            zebra_percent: 90,
+            standby_dim: 0.3,
+            standby_dim_secs: 600,
+            idle_exit_mins: 0,
         };
         let cloned = display.clone();
         assert_eq!(display.source, cloned.source);
         assert_eq!(display.fb_device, cloned.fb_device);
+        assert_eq!(display.color_format, cloned.color_format);
     }
 
     #[test]
     fn test_intercom_config_clone() {
         let intercom = IntercomConfig {
             stream: "test".to_string(),
+            stream_ignore_case: false,
             target: "host.lan".to_string(),
             sample_rate: 48000,
             channels: 2,
@@ -395,6 +4129,32 @@ source = "NDI Source"
             headphone_gain: 15.0,
             limiter_enabled: true,
             limiter_threshold: 0.5,
+            allowed_sources: vec!["10.0.0.5".to_string()],
+            auto_lock: false,
+            link_timeout_ms: 2000,
+            tx_codec: "pcm24".to_string(),
+            ping_announce: true,
+            dscp: 34,
+            recv_buffer_size: 1024,
+            interface: Some("eth0".to_string()),
+            port: 6990,
+            listen: "0.0.0.0:0".to_string(),
+            bind_address: Some("fd00::5".to_string()),
+            record_dir: Some("/tmp/rec".to_string()),
+            record_segment_secs: 30,
+            record_keep: 5,
+            echo: EchoConfig::default(),
+            silence: SilenceConfig::default(),
+            routing: RoutingConfig::default(),
+            mute_key: "KEY_F13".to_string(),
+            mute_led: "scrolllock".to_string(),
+            button_gpio: Some("gpiochip0:17".to_string()),
+            tally_led_gpio: None,
+            auto_unmute: "program".to_string(),
+            call_tone: false,
+            mic_channel: "left".to_string(),
+            period_frames: 64,
+            periods: 3,
         };
         let cloned = intercom.clone();
         assert_eq!(intercom.stream, cloned.stream);
@@ -406,5 +4166,27 @@ source = "NDI Source"
         assert!((intercom.headphone_gain - cloned.headphone_gain).abs() < 0.001);
         assert_eq!(intercom.limiter_enabled, cloned.limiter_enabled);
         assert!((intercom.limiter_threshold - cloned.limiter_threshold).abs() < 0.001);
+        assert_eq!(intercom.allowed_sources, cloned.allowed_sources);
+        assert_eq!(intercom.auto_lock, cloned.auto_lock);
+        assert_eq!(intercom.link_timeout_ms, cloned.link_timeout_ms);
+        assert_eq!(intercom.tx_codec, cloned.tx_codec);
+        assert_eq!(intercom.ping_announce, cloned.ping_announce);
+        assert_eq!(intercom.dscp, cloned.dscp);
+        assert_eq!(intercom.recv_buffer_size, cloned.recv_buffer_size);
+        assert_eq!(intercom.interface, cloned.interface);
+        assert_eq!(intercom.port, cloned.port);
+        assert_eq!(intercom.listen, cloned.listen);
+        assert_eq!(intercom.bind_address, cloned.bind_address);
+        assert_eq!(intercom.echo.enabled, cloned.echo.enabled);
+        assert_eq!(intercom.record_dir, cloned.record_dir);
+        assert_eq!(intercom.record_segment_secs, cloned.record_segment_secs);
+        assert_eq!(intercom.record_keep, cloned.record_keep);
+        assert_eq!(intercom.mute_key, cloned.mute_key);
+        assert_eq!(intercom.mute_led, cloned.mute_led);
+        assert_eq!(intercom.button_gpio, cloned.button_gpio);
+        assert_eq!(intercom.auto_unmute, cloned.auto_unmute);
+        assert_eq!(intercom.mic_channel, cloned.mic_channel);
+        assert_eq!(intercom.period_frames, cloned.period_frames);
+        assert_eq!(intercom.periods, cloned.periods);
     }
 }
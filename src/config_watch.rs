@@ -0,0 +1,508 @@
+//! Config file reloads
+//!
+//! SIGHUP, or the config file being written or renamed into place, rereads
+//! the configuration. Settings the control socket can change at runtime are
+//! applied as control commands; other changes are logged and wait for a
+//! restart. A file that doesn't load or validate, such as one caught halfway
+//! through an edit, leaves the last good config in place.
+//!
+//! The inotify watch is on the parent directory. Fleet tools and editors
+//! replace the file by renaming a temp file over it, which a watch on the old
+//! inode would never see. Events for other names, such as vim's `.swp` or a
+//! `~` backup, are ignored. Reloads from the watch are debounced to one per
+//! [`DEBOUNCE`].
+
+use anyhow::{anyhow, Context, Result};
+use std::ffi::OsString;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::control::{Command, ControlHandles};
+
+/// Shortest time between two reloads triggered by the watch
+pub const DEBOUNCE: Duration = Duration::from_secs(2);
+
+/// poll timeout, bounds how long shutdown takes to be noticed
+const WAIT_TIMEOUT: Duration = Duration::from_millis(250);
+
+// =============================================================================
+// Reload
+// =============================================================================
+
+/// What a reload changed
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    /// Control commands applying the settings that change at runtime
+    pub commands: Vec<Command>,
+    /// Other settings changed too and take effect after a restart
+    pub needs_restart: bool,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty() && !self.needs_restart
+    }
+}
+
+/// Differences between two configs: the display source, histogram and zebra
+/// apply at runtime; anything else needs a restart
+pub fn changes(old: &Config, new: &Config) -> Changes {
+    let mut commands = Vec::new();
+    let mut rest = new.clone();
+    if let (Some(old), Some(new), Some(rest)) = (&old.display, &new.display, &mut rest.display) {
+        if new.source != old.source {
+            commands.push(Command::DisplaySource(new.source.clone()));
+        }
+        if new.histogram != old.histogram {
+            commands.push(Command::DisplayHistogram(new.histogram));
+        }
+        if (new.zebra, new.zebra_percent) != (old.zebra, old.zebra_percent) {
+            commands.push(Command::DisplayZebra(new.zebra, Some(new.zebra_percent)));
+        }
+        rest.source = old.source.clone();
+        rest.histogram = old.histogram;
+        rest.zebra = old.zebra;
+        rest.zebra_percent = old.zebra_percent;
+    }
+    Changes {
+        commands,
+        needs_restart: rest != *old,
+    }
+}
+
+/// The config file and the last good config read from it
+pub struct Reloader {
+    path: PathBuf,
+    profile: Option<String>,
+    current: Config,
+}
+
+impl Reloader {
+    /// Reload `path`, which `current` was loaded from
+    pub fn new(path: impl Into<PathBuf>, current: Config) -> Self {
+        Self {
+            path: path.into(),
+            profile: None,
+            current,
+        }
+    }
+
+    /// Keep applying the `[profile.<name>]` section `current` was loaded with
+    pub fn profile(mut self, profile: Option<String>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn current(&self) -> &Config {
+        &self.current
+    }
+
+    /// Reread the file and return what changed. A file that doesn't load or
+    /// validate is an error and keeps the last good config.
+    pub fn reload(&mut self) -> Result<Changes> {
+        let config = Config::load_profile(&self.path, self.profile.as_deref())?;
+        let errors: Vec<String> = config.validate().iter().map(ToString::to_string).collect();
+        if !errors.is_empty() {
+            return Err(anyhow!("{}", errors.join("; ")));
+        }
+        let changes = changes(&self.current, &config);
+        self.current = config;
+        Ok(changes)
+    }
+
+    /// Reload and apply the runtime settings through `handles`, logging the
+    /// outcome
+    pub fn apply(&mut self, handles: &ControlHandles) {
+        let changes = match self.reload() {
+            Ok(changes) => changes,
+            Err(e) => {
+                tracing::warn!(
+                    "{} not reloaded, keeping the last good config: {:#}",
+                    self.path.display(),
+                    e
+                );
+                return;
+            }
+        };
+        if changes.is_empty() {
+            tracing::info!("{} reloaded, nothing changed", self.path.display());
+            return;
+        }
+        tracing::info!("{} reloaded", self.path.display());
+        for command in changes.commands {
+            let name = format!("{:?}", command);
+            if let Err(e) = handles.execute(command) {
+                tracing::warn!("Config: {} not applied: {}", name, e);
+            }
+        }
+        if changes.needs_restart {
+            tracing::warn!(
+                "{}: some changes take effect after a restart",
+                self.path.display()
+            );
+        }
+    }
+}
+
+// =============================================================================
+// Debounce
+// =============================================================================
+
+/// Passes changes on at most once per interval; changes inside the interval
+/// are folded into one at its end
+#[derive(Debug)]
+pub struct Debounce {
+    interval: Duration,
+    last: Option<Instant>,
+    pending: bool,
+}
+
+impl Debounce {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: None,
+            pending: false,
+        }
+    }
+
+    /// Note a change
+    pub fn trigger(&mut self) {
+        self.pending = true;
+    }
+
+    /// True if a noted change is due at `now`
+    pub fn poll(&mut self, now: Instant) -> bool {
+        if !self.pending || self.last.is_some_and(|last| now < last + self.interval) {
+            return false;
+        }
+        self.pending = false;
+        self.last = Some(now);
+        true
+    }
+
+    /// How long until the noted change is due, None without one
+    pub fn due_in(&self, now: Instant) -> Option<Duration> {
+        if !self.pending {
+            return None;
+        }
+        Some(self.last.map_or(Duration::ZERO, |last| {
+            (last + self.interval).saturating_duration_since(now)
+        }))
+    }
+}
+
+// =============================================================================
+// inotify
+// =============================================================================
+
+fn cvt(ret: libc::c_int) -> std::io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+/// inotify watch for writes to and renames onto one file
+pub struct ConfigWatcher {
+    fd: OwnedFd,
+    name: OsString,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} is not a file", path.display()))?
+            .to_os_string();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let fd = cvt(unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) })
+            .map_err(|e| anyhow!("inotify_init1 failed: {}", e))?;
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let c_dir = std::ffi::CString::new(dir.as_os_str().as_encoded_bytes())?;
+        let mask = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+        cvt(unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_dir.as_ptr(), mask) })
+            .map_err(|e| anyhow!("Cannot watch {}: {}", dir.display(), e))?;
+        Ok(Self { fd, name })
+    }
+
+    /// Wait up to `timeout` for events; true if one wrote the file or
+    /// renamed another over it
+    pub fn wait(&self, timeout: Duration) -> Result<bool> {
+        let mut pfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        match unsafe { libc::poll(&mut pfd, 1, timeout_ms) } {
+            0 => return Ok(false),
+            n if n < 0 => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    return Ok(false);
+                }
+                return Err(anyhow!("poll failed: {}", err));
+            }
+            _ => {}
+        }
+        self.read_events()
+    }
+
+    /// Drain pending events, true if any was for the file
+    fn read_events(&self) -> Result<bool> {
+        let mut buf = [0u8; 4096];
+        let mut matched = false;
+        loop {
+            let n = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if n < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::WouldBlock {
+                    return Ok(matched);
+                }
+                return Err(anyhow!("Failed to read inotify events: {}", err));
+            }
+            if n == 0 {
+                return Ok(matched);
+            }
+            matched |= names_file(&buf[..n as usize], &self.name);
+        }
+    }
+}
+
+/// True if an event in an inotify read names `name`; a queue overflow may
+/// have lost one, so it counts too
+fn names_file(buf: &[u8], name: &std::ffi::OsStr) -> bool {
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+    let mut found = false;
+    let mut offset = 0;
+    while offset + HEADER <= buf.len() {
+        let event: libc::inotify_event =
+            unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr() as *const _) };
+        let start = offset + HEADER;
+        let end = (start + event.len as usize).min(buf.len());
+        let event_name = buf[start..end]
+            .split(|&b| b == 0)
+            .next()
+            .unwrap_or_default();
+        if event.mask & libc::IN_Q_OVERFLOW != 0 || event_name == name.as_encoded_bytes() {
+            found = true;
+        }
+        offset = end;
+    }
+    found
+}
+
+/// Call `reload` whenever `path` is written or replaced, at most once per
+/// [`DEBOUNCE`], until `running` is cleared
+pub fn watch(path: &Path, running: &AtomicBool, mut reload: impl FnMut()) -> Result<()> {
+    let watcher =
+        ConfigWatcher::new(path).with_context(|| format!("Cannot watch {}", path.display()))?;
+    tracing::info!("Watching {} for changes", path.display());
+    let mut debounce = Debounce::new(DEBOUNCE);
+    while running.load(Ordering::Relaxed) {
+        let timeout = debounce
+            .due_in(Instant::now())
+            .map_or(WAIT_TIMEOUT, |due| due.min(WAIT_TIMEOUT));
+        if watcher.wait(timeout)? {
+            debounce.trigger();
+        }
+        if debounce.poll(Instant::now()) {
+            reload();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISPLAY: &str = "[display]\nsource = \"PGM\"\n";
+
+    fn load(source: &str) -> Config {
+        toml::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn test_changes_apply_display_settings_at_runtime() {
+        let old = load(DISPLAY);
+        assert!(changes(&old, &old).is_empty());
+
+        let new = load("[display]\nsource = \"CAM2\"\nzebra = true\nzebra_percent = 90\n");
+        assert_eq!(
+            changes(&old, &new),
+            Changes {
+                commands: vec![
+                    Command::DisplaySource("CAM2".to_string()),
+                    Command::DisplayZebra(true, Some(90)),
+                ],
+                needs_restart: false,
+            }
+        );
+
+        let new = load("ndi_name = \"cam2\"\n[display]\nsource = \"PGM\"\nhistogram = true\n");
+        assert_eq!(
+            changes(&old, &new),
+            Changes {
+                commands: vec![Command::DisplayHistogram(true)],
+                needs_restart: true,
+            }
+        );
+
+        // Adding or removing the display needs a restart
+        let none = load("");
+        assert_eq!(
+            changes(&none, &old),
+            Changes {
+                commands: Vec::new(),
+                needs_restart: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_reload_keeps_last_good_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, DISPLAY).unwrap();
+        let mut reloader = Reloader::new(&path, Config::load(&path).unwrap());
+
+        std::fs::write(&path, "[display]\nsource = \"PGM\"\nhistogram = true\n").unwrap();
+        let changes = reloader.reload().unwrap();
+        assert_eq!(changes.commands, vec![Command::DisplayHistogram(true)]);
+        assert!(reloader.current().display.as_ref().unwrap().histogram);
+
+        // Halfway through an edit: neither a parse error nor an invalid
+        // value replaces the config
+        std::fs::write(&path, "[display]\nsource = \"PG").unwrap();
+        assert!(reloader.reload().is_err());
+        std::fs::write(&path, "[display]\nsource = \"PGM\"\nzebra_percent = 0\n").unwrap();
+        let error = reloader.reload().unwrap_err().to_string();
+        assert!(error.contains("display.zebra_percent"), "{}", error);
+        assert!(reloader.current().display.as_ref().unwrap().histogram);
+
+        // Finished: compared with the last good config
+        std::fs::write(
+            &path,
+            "[display]\nsource = \"PGM\"\nhistogram = true\nzebra = true\n",
+        )
+        .unwrap();
+        let changes = reloader.reload().unwrap();
+        assert_eq!(
+            changes.commands,
+            vec![Command::DisplayZebra(true, Some(95))]
+        );
+        assert!(!changes.needs_restart);
+    }
+
+    #[test]
+    fn test_reload_keeps_the_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let source = |histogram| {
+            format!(
+                "[display]\nsource = \"PGM\"\n[profile.studio.display]\nhistogram = {}\n",
+                histogram
+            )
+        };
+        std::fs::write(&path, source(true)).unwrap();
+        let current = Config::load_profile(&path, Some("studio")).unwrap();
+        let mut reloader = Reloader::new(&path, current).profile(Some("studio".to_string()));
+        assert!(reloader.current().display.as_ref().unwrap().histogram);
+
+        std::fs::write(&path, source(false)).unwrap();
+        let changes = reloader.reload().unwrap();
+        assert_eq!(changes.commands, vec![Command::DisplayHistogram(false)]);
+    }
+
+    #[test]
+    fn test_debounce_folds_changes_within_interval() {
+        let t0 = Instant::now();
+        let mut debounce = Debounce::new(DEBOUNCE);
+        assert!(!debounce.poll(t0));
+        assert_eq!(debounce.due_in(t0), None);
+
+        // The first change is due at once
+        debounce.trigger();
+        assert_eq!(debounce.due_in(t0), Some(Duration::ZERO));
+        assert!(debounce.poll(t0));
+        assert!(!debounce.poll(t0));
+
+        // Two more within the interval make one reload at its end
+        debounce.trigger();
+        let t1 = t0 + Duration::from_millis(500);
+        assert!(!debounce.poll(t1));
+        debounce.trigger();
+        assert_eq!(debounce.due_in(t1), Some(Duration::from_millis(1500)));
+        assert!(!debounce.poll(t0 + Duration::from_millis(1999)));
+        assert!(debounce.poll(t0 + DEBOUNCE));
+        assert!(!debounce.poll(t0 + DEBOUNCE * 3));
+    }
+
+    #[test]
+    fn test_watcher_sees_writes_and_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, DISPLAY).unwrap();
+        let watcher = ConfigWatcher::new(&path).unwrap();
+        assert!(!watcher.wait(Duration::from_millis(10)).unwrap());
+
+        // Writing in place
+        std::fs::write(&path, "hostname = \"a\"\n").unwrap();
+        assert!(watcher.wait(Duration::from_secs(1)).unwrap());
+        assert!(!watcher.wait(Duration::from_millis(10)).unwrap());
+
+        // The editor's swap file and a temp file don't count until the temp
+        // file is renamed over the config
+        let swap = dir.path().join(".config.toml.swp");
+        let tmp = dir.path().join("config.toml.tmp");
+        std::fs::write(&swap, "swap").unwrap();
+        std::fs::write(&tmp, "hostname = \"b\"\n").unwrap();
+        assert!(!watcher.wait(Duration::from_millis(50)).unwrap());
+        std::fs::rename(&tmp, &path).unwrap();
+        std::fs::remove_file(&swap).unwrap();
+        assert!(watcher.wait(Duration::from_secs(1)).unwrap());
+
+        // Still watched after the file was replaced
+        std::fs::write(&path, "hostname = \"c\"\n").unwrap();
+        assert!(watcher.wait(Duration::from_secs(1)).unwrap());
+    }
+
+    #[test]
+    fn test_watch_debounces_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, DISPLAY).unwrap();
+        let running = AtomicBool::new(true);
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| watch(&path, &running, || tx.send(Instant::now()).unwrap()).unwrap());
+            // Let the watch start before writing
+            std::thread::sleep(Duration::from_millis(100));
+            std::fs::write(&path, DISPLAY).unwrap();
+            let first = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+            // Two more writes right after make one more reload
+            std::fs::write(&path, DISPLAY).unwrap();
+            std::fs::write(&path, DISPLAY).unwrap();
+            let second = rx.recv_timeout(DEBOUNCE * 2).unwrap();
+            assert!(second - first >= DEBOUNCE);
+            assert!(rx.recv_timeout(Duration::from_millis(500)).is_err());
+            running.store(false, Ordering::Relaxed);
+        });
+        assert!(rx.try_recv().is_err());
+    }
+}
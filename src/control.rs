@@ -0,0 +1,986 @@
+//! Runtime control socket
+//!
+//! A tiny line-based protocol on a unix socket (default `/run/camera-box.sock`)
+//! for changing settings during a show. One command per line, one response
+//! line per command: `ok [details]` or `err <message>`.
+//!
+//! Commands:
+//! - `display.source <name>` - switch the HDMI display to another NDI source
+//! - `display.overlay on|off` - toggle the display overlay
+//! - `display.histogram on|off` - toggle the luma histogram on the display
+//! - `display.zebra on|off|<percent>` - toggle zebra stripes, or set their threshold
+//! - `intercom.mute on|off` - mute or unmute the intercom microphone
+//! - `intercom.headphone_gain <gain>` - set the gain of the incoming stream
+//! - `intercom.sidetone_gain <gain>` - set the sidetone gain
+//! - `camera [<control> [<value>]]` - list, read or set (and save) a V4L2 control
+//! - `dump-ring` - write the replay thumbnails as PNGs
+//! - `log-level <filter>` - replace the log filter, e.g. `camera_box=debug`
+//! - `status` - report current state as `key=value` pairs
+//! - `stats` - the last periodic stats report: counters and their rates
+//! - `wake` - nothing; with socket activation the connection itself starts a
+//!   stopped camera-box (see [`crate::socket_activation`])
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use crate::camera_controls;
+use crate::exposure::{ExposureSettings, MAX_ZEBRA_PERCENT};
+use crate::intercom::{IntercomStats, Tally};
+use crate::log_level::LogLevel;
+use crate::ndi::{self, NdiFeature, NdiReceiverStats};
+use crate::replay::ReplayHandle;
+use crate::stats::StatsRegistry;
+use crate::threads;
+
+/// Default control socket path
+pub const DEFAULT_SOCKET_PATH: &str = "/run/camera-box.sock";
+
+/// Mode of a bound control socket: owner and group only, since any client
+/// can mute the intercom or change the log level
+pub const SOCKET_MODE: u32 = 0o660;
+
+/// Client read timeout, so a wedged server doesn't hang `camera-box ctl`
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Highest intercom gain, as `[intercom]` allows
+const MAX_GAIN: f32 = 1000.0;
+
+// =============================================================================
+// Commands
+// =============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    DisplaySource(String),
+    DisplayOverlay(bool),
+    DisplayHistogram(bool),
+    /// Zebra on or off, optionally with a new threshold in percent
+    DisplayZebra(bool, Option<u8>),
+    IntercomMute(bool),
+    /// Gain multiplier for the headphone or sidetone signal
+    IntercomGain(Gain, f32),
+    /// List controls, read one, or set one: `(control, value)`
+    Camera(Option<String>, Option<i64>),
+    DumpRing,
+    /// Replace the log filter
+    LogLevel(String),
+    Status,
+    Stats,
+    Wake,
+}
+
+/// Intercom signal whose gain can change at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gain {
+    /// Incoming VBAN stream in the headphones
+    Headphone,
+    Sidetone,
+}
+
+impl Gain {
+    pub fn name(self) -> &'static str {
+        match self {
+            Gain::Headphone => "headphone_gain",
+            Gain::Sidetone => "sidetone_gain",
+        }
+    }
+}
+
+fn parse_gain(gain: Gain, value: &str) -> Result<Command> {
+    let parsed = value
+        .parse()
+        .map_err(|_| anyhow!("Invalid gain: {:?}", value))?;
+    Command::intercom_gain(gain, parsed)
+}
+
+fn parse_on_off(value: &str) -> Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        other => bail!("Expected on|off, got {:?}", other),
+    }
+}
+
+/// `on`, `off` or a threshold in percent, which also turns the zebra on
+fn parse_zebra(value: &str) -> Result<Command> {
+    if let Ok(percent) = value.parse::<u8>() {
+        if !(1..=MAX_ZEBRA_PERCENT).contains(&percent) {
+            bail!(
+                "Zebra threshold must be 1-{}%, got {}",
+                MAX_ZEBRA_PERCENT,
+                percent
+            );
+        }
+        return Ok(Command::DisplayZebra(true, Some(percent)));
+    }
+    Ok(Command::DisplayZebra(parse_on_off(value)?, None))
+}
+
+impl Command {
+    /// Set an intercom gain, checked against the range `[intercom]` allows
+    pub fn intercom_gain(gain: Gain, value: f32) -> Result<Self> {
+        if !(0.0..=MAX_GAIN).contains(&value) {
+            bail!("Gain must be 0-{}, got {}", MAX_GAIN, value);
+        }
+        Ok(Command::IntercomGain(gain, value))
+    }
+
+    /// Parse one protocol line
+    pub fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (name, arg) = match line.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (line, ""),
+        };
+        match (name, arg) {
+            ("", _) => bail!("Empty command"),
+            ("display.source", "") => bail!("display.source requires a source name"),
+            ("display.source", source) => Ok(Command::DisplaySource(source.to_string())),
+            ("display.overlay", value) => Ok(Command::DisplayOverlay(parse_on_off(value)?)),
+            ("display.histogram", value) => Ok(Command::DisplayHistogram(parse_on_off(value)?)),
+            ("display.zebra", value) => parse_zebra(value),
+            ("intercom.mute", value) => Ok(Command::IntercomMute(parse_on_off(value)?)),
+            ("intercom.headphone_gain", value) => parse_gain(Gain::Headphone, value),
+            ("intercom.sidetone_gain", value) => parse_gain(Gain::Sidetone, value),
+            ("camera", "") => Ok(Command::Camera(None, None)),
+            ("camera", args) => match args.split_once(char::is_whitespace) {
+                Some((key, value)) => {
+                    let value = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid control value: {:?}", value.trim()))?;
+                    Ok(Command::Camera(Some(key.to_string()), Some(value)))
+                }
+                None => Ok(Command::Camera(Some(args.to_string()), None)),
+            },
+            ("dump-ring", "") => Ok(Command::DumpRing),
+            ("dump-ring", _) => bail!("dump-ring takes no arguments"),
+            ("log-level", "") => bail!("log-level requires a filter"),
+            ("log-level", filter) => Ok(Command::LogLevel(filter.to_string())),
+            ("status", "") => Ok(Command::Status),
+            ("status", _) => bail!("status takes no arguments"),
+            ("stats", "") => Ok(Command::Stats),
+            ("stats", _) => bail!("stats takes no arguments"),
+            ("wake", "") => Ok(Command::Wake),
+            ("wake", _) => bail!("wake takes no arguments"),
+            (other, _) => bail!("Unknown command: {}", other),
+        }
+    }
+}
+
+// =============================================================================
+// Channels
+// =============================================================================
+
+/// Sending side of the control channels, owned by the control server
+pub struct ControlHandles {
+    display_source: watch::Sender<String>,
+    display_overlay: watch::Sender<bool>,
+    display_exposure: watch::Sender<ExposureSettings>,
+    intercom_mute: watch::Sender<bool>,
+    intercom_stats: Option<Arc<IntercomStats>>,
+    display_stats: Arc<NdiReceiverStats>,
+    camera: Option<CameraDevice>,
+    replay: Option<ReplayHandle>,
+    log_level: Option<LogLevel>,
+    stats: Option<Arc<StatsRegistry>>,
+}
+
+/// Capture device whose controls the `camera` command adjusts
+#[derive(Debug, Clone)]
+pub struct CameraDevice {
+    pub device_path: String,
+    /// Store for changed values, empty to not persist
+    pub controls_file: String,
+}
+
+/// Receiving side for the display loop
+pub struct DisplayControl {
+    source: watch::Receiver<String>,
+    overlay: watch::Receiver<bool>,
+    exposure: watch::Receiver<ExposureSettings>,
+    stats: Arc<NdiReceiverStats>,
+    intercom_stats: Option<Arc<IntercomStats>>,
+}
+
+impl DisplayControl {
+    /// True if a different source was requested since the last `source()`
+    pub fn source_changed(&self) -> bool {
+        self.source.has_changed().unwrap_or(false)
+    }
+
+    /// Current source name, marking it as seen
+    pub fn source(&mut self) -> String {
+        self.source.borrow_and_update().clone()
+    }
+
+    /// Receiver counters reported by `status`, kept across reconnections
+    pub fn stats(&self) -> Arc<NdiReceiverStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// New overlay state, if it was changed since the last call
+    pub fn overlay_update(&mut self) -> Option<bool> {
+        if self.overlay.has_changed().unwrap_or(false) {
+            Some(*self.overlay.borrow_and_update())
+        } else {
+            None
+        }
+    }
+
+    /// Blink phase of an incoming intercom call at `now`, None without one
+    pub fn call_blink(&self, now: Instant) -> Option<bool> {
+        self.intercom_stats.as_ref()?.call_blink(now)
+    }
+
+    /// New histogram and zebra settings, if they were changed since the last call
+    pub fn exposure_update(&mut self) -> Option<ExposureSettings> {
+        if self.exposure.has_changed().unwrap_or(false) {
+            Some(*self.exposure.borrow_and_update())
+        } else {
+            None
+        }
+    }
+}
+
+/// Create control channels. The display starts on `display_source`; the
+/// intercom receives mute requests on the returned receiver.
+pub fn channels(
+    display_source: &str,
+    intercom_stats: Option<Arc<IntercomStats>>,
+) -> (ControlHandles, DisplayControl, watch::Receiver<bool>) {
+    let (source_tx, source_rx) = watch::channel(display_source.to_string());
+    let (overlay_tx, overlay_rx) = watch::channel(false);
+    let (exposure_tx, exposure_rx) = watch::channel(ExposureSettings::default());
+    let (mute_tx, mute_rx) = watch::channel(true);
+    let display_stats = Arc::new(NdiReceiverStats::new());
+    let handles = ControlHandles {
+        display_source: source_tx,
+        display_overlay: overlay_tx,
+        display_exposure: exposure_tx,
+        intercom_mute: mute_tx,
+        intercom_stats: intercom_stats.clone(),
+        display_stats: Arc::clone(&display_stats),
+        camera: None,
+        replay: None,
+        log_level: None,
+        stats: None,
+    };
+    let display = DisplayControl {
+        source: source_rx,
+        overlay: overlay_rx,
+        exposure: exposure_rx,
+        stats: display_stats,
+        intercom_stats,
+    };
+    (handles, display, mute_rx)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+impl ControlHandles {
+    /// Enable the `camera` command for a capture device
+    pub fn with_camera(mut self, camera: CameraDevice) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    /// Enable the `dump-ring` command
+    pub fn with_replay(mut self, replay: ReplayHandle) -> Self {
+        self.replay = Some(replay);
+        self
+    }
+
+    /// Enable the `log-level` command and report the filter in `status`
+    pub fn with_log_level(mut self, log_level: LogLevel) -> Self {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    /// Enable the `stats` command
+    pub fn with_stats(mut self, registry: Arc<StatsRegistry>) -> Self {
+        self.stats = Some(registry);
+        self
+    }
+
+    /// Start the display with these exposure aids (from `[display]`)
+    pub fn with_exposure(self, settings: ExposureSettings) -> Self {
+        self.display_exposure.send_replace(settings);
+        self
+    }
+
+    fn display_enabled(&self) -> bool {
+        self.display_source.receiver_count() > 0
+    }
+
+    /// Apply a command, returning the details for the `ok` response
+    pub fn execute(&self, command: Command) -> Result<String> {
+        match command {
+            Command::DisplaySource(source) => {
+                if !self.display_enabled() {
+                    bail!("Display is not enabled");
+                }
+                tracing::info!("Control: display source -> {}", source);
+                self.display_source.send_replace(source);
+                Ok(String::new())
+            }
+            Command::DisplayOverlay(enabled) => {
+                if !self.display_enabled() {
+                    bail!("Display is not enabled");
+                }
+                self.display_overlay.send_replace(enabled);
+                Ok(String::new())
+            }
+            Command::DisplayHistogram(enabled) => {
+                if !self.display_enabled() {
+                    bail!("Display is not enabled");
+                }
+                self.display_exposure
+                    .send_modify(|settings| settings.histogram = enabled);
+                Ok(String::new())
+            }
+            Command::DisplayZebra(enabled, percent) => {
+                if !self.display_enabled() {
+                    bail!("Display is not enabled");
+                }
+                self.display_exposure.send_modify(|settings| {
+                    settings.zebra = enabled;
+                    if let Some(percent) = percent {
+                        settings.zebra_percent = percent;
+                    }
+                });
+                Ok(String::new())
+            }
+            Command::IntercomMute(muted) => {
+                if self.intercom_mute.receiver_count() == 0 {
+                    bail!("Intercom is not enabled");
+                }
+                self.intercom_mute.send_replace(muted);
+                Ok(String::new())
+            }
+            Command::IntercomGain(gain, value) => {
+                let Some(stats) = self.intercom_stats.as_ref() else {
+                    bail!("Intercom is not enabled");
+                };
+                if self.intercom_mute.receiver_count() == 0 {
+                    bail!("Intercom is not enabled");
+                }
+                match gain {
+                    Gain::Headphone => stats.set_headphone_gain(value),
+                    Gain::Sidetone => stats.set_sidetone_gain(value),
+                }
+                tracing::info!("Control: intercom {} -> {:.1}", gain.name(), value);
+                Ok(String::new())
+            }
+            Command::Camera(key, value) => self.camera(key, value),
+            Command::DumpRing => {
+                let Some(replay) = &self.replay else {
+                    bail!("Replay ring is not enabled");
+                };
+                let (dir, frames) = replay.dump()?;
+                Ok(format!("{} frames={}", dir.display(), frames))
+            }
+            Command::LogLevel(filter) => {
+                let Some(log_level) = &self.log_level else {
+                    bail!("Log filter is not reloadable");
+                };
+                log_level.set(&filter)?;
+                tracing::info!("Control: log filter -> {}", filter);
+                Ok(String::new())
+            }
+            Command::Status => Ok(self.status_line()),
+            Command::Stats => {
+                let Some(registry) = &self.stats else {
+                    bail!("Stats are not enabled");
+                };
+                let Some(report) = registry.latest() else {
+                    bail!("No stats report yet");
+                };
+                Ok(report.line())
+            }
+            Command::Wake => Ok(String::new()),
+        }
+    }
+
+    fn camera(&self, key: Option<String>, value: Option<i64>) -> Result<String> {
+        let Some(camera) = &self.camera else {
+            bail!("Camera controls are not available");
+        };
+        match (key, value) {
+            (None, _) => {
+                let controls = camera_controls::device_controls(&camera.device_path)?;
+                Ok(controls
+                    .iter()
+                    .map(|c| format!("{}={}", c.key, c.value))
+                    .collect::<Vec<_>>()
+                    .join(" "))
+            }
+            (Some(key), None) => {
+                let control = camera_controls::get_control(&camera.device_path, &key)?;
+                Ok(format!(
+                    "{}={} min={} max={} default={}",
+                    control.key, control.value, control.min, control.max, control.default
+                ))
+            }
+            (Some(key), Some(value)) => {
+                let applied = camera_controls::set_and_save(
+                    &camera.device_path,
+                    &camera.controls_file,
+                    &key,
+                    value,
+                )?;
+                tracing::info!("Control: camera {} -> {}", key, applied);
+                Ok(format!("{}={}", key, applied))
+            }
+        }
+    }
+
+    fn status_line(&self) -> String {
+        let mut fields = Vec::new();
+        if self.display_enabled() {
+            fields.push(format!(
+                "display.source={:?}",
+                *self.display_source.borrow()
+            ));
+            fields.push(format!(
+                "display.overlay={}",
+                on_off(*self.display_overlay.borrow())
+            ));
+            let exposure = *self.display_exposure.borrow();
+            fields.push(format!("display.histogram={}", on_off(exposure.histogram)));
+            if exposure.zebra {
+                fields.push(format!("display.zebra={}%", exposure.zebra_percent));
+            } else {
+                fields.push("display.zebra=off".to_string());
+            }
+            let stats = self.display_stats.snapshot(Instant::now());
+            fields.push(format!("display.rx={}", stats.video_frames));
+            fields.push(format!("display.rx_bytes={}", stats.bytes_received));
+            fields.push(format!("display.late_drops={}", stats.late_drops));
+            fields.push(format!("display.displayed={}", stats.displayed));
+            if let Some(interval) = stats.average_interval {
+                fields.push(format!("display.interval_ms={:.1}", millis(interval)));
+            }
+            if let Some(age) = stats.last_frame_age {
+                fields.push(format!("display.age_ms={:.0}", millis(age)));
+            }
+        } else {
+            fields.push("display=off".to_string());
+        }
+        match &self.intercom_stats {
+            Some(stats) if self.intercom_mute.receiver_count() > 0 => {
+                let snapshot = stats.snapshot();
+                let tally = match snapshot.tally {
+                    Tally::Off => "off",
+                    Tally::Preview => "preview",
+                    Tally::Program => "program",
+                };
+                fields.push(format!("intercom.muted={}", on_off(snapshot.muted)));
+                fields.push(format!(
+                    "intercom.link={}",
+                    if snapshot.link_up { "up" } else { "down" }
+                ));
+                fields.push(format!("intercom.tally={}", tally));
+                fields.push(format!("intercom.mute_mode={}", snapshot.mute_mode.name()));
+                fields.push(format!("intercom.tx={}", snapshot.packets_sent));
+                fields.push(format!("intercom.rx={}", snapshot.packets_received));
+                fields.push(format!("intercom.buffer={}", snapshot.buffer_depth));
+                fields.push(format!(
+                    "intercom.headphone_gain={:.1}",
+                    snapshot.headphone_gain
+                ));
+                fields.push(format!(
+                    "intercom.sidetone_gain={:.1}",
+                    snapshot.sidetone_gain
+                ));
+            }
+            _ => fields.push("intercom=off".to_string()),
+        }
+        if let Some(runtime) = ndi::capabilities() {
+            fields.push(format!(
+                "ndi.version={}",
+                runtime.version_number().unwrap_or("unknown")
+            ));
+            for feature in NdiFeature::ALL {
+                fields.push(format!(
+                    "ndi.{}={}",
+                    feature.name(),
+                    on_off(runtime.supports(feature))
+                ));
+            }
+        }
+        if let Some(log_level) = &self.log_level {
+            fields.push(format!("log.filter={:?}", log_level.current()));
+        }
+        fields.join(" ")
+    }
+
+    fn respond(&self, line: &str) -> String {
+        match Command::parse(line).and_then(|command| self.execute(command)) {
+            Ok(details) if details.is_empty() => "ok".to_string(),
+            Ok(details) => format!("ok {}", details),
+            Err(e) => format!("err {}", e),
+        }
+    }
+}
+
+// =============================================================================
+// Server
+// =============================================================================
+
+/// Unix socket listener applying control commands
+pub struct ControlServer {
+    listener: UnixListener,
+    /// Socket file removed on drop; None for a socket systemd owns
+    path: Option<PathBuf>,
+    handles: Arc<ControlHandles>,
+}
+
+impl ControlServer {
+    /// Bind the control socket with `SOCKET_MODE`, replacing a stale socket
+    /// file. Fails if another instance answers on the socket. `handles` are
+    /// shared with config reloads.
+    pub fn bind<P: AsRef<Path>>(path: P, handles: Arc<ControlHandles>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        match std::fs::symlink_metadata(&path) {
+            Ok(metadata) if !metadata.file_type().is_socket() => {
+                bail!("{} exists and is not a socket", path.display())
+            }
+            Ok(_) if UnixStream::connect(&path).is_ok() => {
+                bail!("Another instance is listening on {}", path.display())
+            }
+            Ok(_) => std::fs::remove_file(&path)
+                .map_err(|e| anyhow!("Cannot remove stale socket {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow!("Cannot check socket {}: {}", path.display(), e)),
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to bind control socket {}", path.display()))?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(SOCKET_MODE))
+            .with_context(|| format!("Failed to restrict control socket {}", path.display()))?;
+        tracing::info!("Control socket listening on {}", path.display());
+        Ok(Self {
+            listener,
+            path: Some(path),
+            handles,
+        })
+    }
+
+    /// Serve a listening socket passed by systemd socket activation. Its
+    /// file belongs to the socket unit and is left in place on drop.
+    pub fn from_listener(listener: UnixListener, handles: Arc<ControlHandles>) -> Self {
+        Self {
+            listener,
+            path: None,
+            handles,
+        }
+    }
+
+    /// Serve clients on a background thread, one at a time
+    pub fn spawn(self) -> std::thread::JoinHandle<()> {
+        threads::spawn(threads::CONTROL, move || self.run())
+    }
+
+    fn run(&self) {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = self.handle_client(stream) {
+                        tracing::debug!("Control client error: {}", e);
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("Control socket accept failed: {}", e);
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+    }
+
+    fn handle_client(&self, stream: UnixStream) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = self.handles.respond(&line);
+            writeln!(writer, "{}", response)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// =============================================================================
+// Client
+// =============================================================================
+
+/// Send one command to a running camera-box and return its response line
+pub fn send_command<P: AsRef<Path>>(path: P, command: &str) -> Result<String> {
+    let path = path.as_ref();
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("Cannot connect to control socket {}", path.display()))?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    writeln!(stream, "{}", command.trim())?;
+    stream.shutdown(std::net::Shutdown::Write)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let response = response.trim_end().to_string();
+    if response.is_empty() {
+        bail!("No response from {}", path.display());
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            Command::parse("display.source STRIH-SNV (interkom)").unwrap(),
+            Command::DisplaySource("STRIH-SNV (interkom)".to_string())
+        );
+        assert_eq!(
+            Command::parse("display.overlay on").unwrap(),
+            Command::DisplayOverlay(true)
+        );
+        assert_eq!(
+            Command::parse("display.histogram on").unwrap(),
+            Command::DisplayHistogram(true)
+        );
+        assert_eq!(
+            Command::parse("display.zebra off").unwrap(),
+            Command::DisplayZebra(false, None)
+        );
+        assert_eq!(
+            Command::parse("display.zebra 90").unwrap(),
+            Command::DisplayZebra(true, Some(90))
+        );
+        assert_eq!(
+            Command::parse("  intercom.mute OFF \n").unwrap(),
+            Command::IntercomMute(false)
+        );
+        assert_eq!(
+            Command::parse("intercom.sidetone_gain 80").unwrap(),
+            Command::IntercomGain(Gain::Sidetone, 80.0)
+        );
+        assert_eq!(
+            Command::parse("intercom.headphone_gain 7.5").unwrap(),
+            Command::IntercomGain(Gain::Headphone, 7.5)
+        );
+        assert_eq!(Command::parse("status").unwrap(), Command::Status);
+        assert_eq!(Command::parse("stats").unwrap(), Command::Stats);
+        assert_eq!(Command::parse("wake").unwrap(), Command::Wake);
+        assert_eq!(Command::parse("dump-ring").unwrap(), Command::DumpRing);
+        assert_eq!(
+            Command::parse("log-level camera_box=debug,grafton_ndi=info").unwrap(),
+            Command::LogLevel("camera_box=debug,grafton_ndi=info".to_string())
+        );
+        assert_eq!(
+            Command::parse("camera").unwrap(),
+            Command::Camera(None, None)
+        );
+        assert_eq!(
+            Command::parse("camera brightness").unwrap(),
+            Command::Camera(Some("brightness".to_string()), None)
+        );
+        assert_eq!(
+            Command::parse("camera exposure_absolute  -250").unwrap(),
+            Command::Camera(Some("exposure_absolute".to_string()), Some(-250))
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_commands() {
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("display.source").is_err());
+        assert!(Command::parse("display.overlay maybe").is_err());
+        assert!(Command::parse("display.histogram").is_err());
+        assert!(Command::parse("display.zebra 0").is_err());
+        assert!(Command::parse("display.zebra 120").is_err());
+        assert!(Command::parse("intercom.mute").is_err());
+        assert!(Command::parse("wake up").is_err());
+        assert!(Command::parse("intercom.sidetone_gain").is_err());
+        assert!(Command::parse("intercom.sidetone_gain loud").is_err());
+        assert!(Command::parse("intercom.headphone_gain -1").is_err());
+        assert!(Command::parse("intercom.headphone_gain 1001").is_err());
+        assert!(Command::parse("status now").is_err());
+        assert!(Command::parse("stats all").is_err());
+        assert!(Command::parse("dump-ring 10").is_err());
+        assert!(Command::parse("log-level").is_err());
+        assert!(Command::parse("reboot").is_err());
+        assert!(Command::parse("camera gain loud").is_err());
+    }
+
+    #[test]
+    fn test_display_sees_intercom_calls() {
+        let (_handles, display, _mute) = channels("PROGRAM", None);
+        assert_eq!(display.call_blink(Instant::now()), None);
+
+        let stats = Arc::new(IntercomStats::new());
+        let (_handles, display, _mute) = channels("PROGRAM", Some(Arc::clone(&stats)));
+        let now = Instant::now();
+        assert_eq!(display.call_blink(now), None);
+        stats.record_call(now);
+        assert_eq!(display.call_blink(now), Some(true));
+    }
+
+    #[test]
+    fn test_execute_forwards_to_receivers() {
+        let (handles, mut display, mut mute) = channels("PROGRAM", None);
+        assert!(!display.source_changed());
+        assert_eq!(display.source(), "PROGRAM");
+
+        handles
+            .execute(Command::DisplaySource("RETURN".to_string()))
+            .unwrap();
+        assert!(display.source_changed());
+        assert_eq!(display.source(), "RETURN");
+        assert!(!display.source_changed());
+
+        handles.execute(Command::DisplayOverlay(true)).unwrap();
+        assert_eq!(display.overlay_update(), Some(true));
+        assert_eq!(display.overlay_update(), None);
+
+        handles
+            .execute(Command::DisplayZebra(true, Some(90)))
+            .unwrap();
+        handles.execute(Command::DisplayHistogram(true)).unwrap();
+        let expected = ExposureSettings {
+            histogram: true,
+            zebra: true,
+            zebra_percent: 90,
+        };
+        assert_eq!(display.exposure_update(), Some(expected));
+        assert_eq!(display.exposure_update(), None);
+        handles.execute(Command::DisplayZebra(false, None)).unwrap();
+        let settings = display.exposure_update().unwrap();
+        assert!(!settings.zebra);
+        assert_eq!(settings.zebra_percent, 90);
+
+        handles.execute(Command::IntercomMute(false)).unwrap();
+        assert!(mute.has_changed().unwrap());
+        assert!(!*mute.borrow_and_update());
+    }
+
+    #[test]
+    fn test_execute_without_receivers() {
+        let (handles, display, mute) = channels("PROGRAM", None);
+        drop(display);
+        drop(mute);
+        assert!(handles
+            .execute(Command::DisplaySource("X".to_string()))
+            .is_err());
+        assert!(handles.execute(Command::IntercomMute(true)).is_err());
+        assert!(handles
+            .execute(Command::IntercomGain(Gain::Sidetone, 1.0))
+            .is_err());
+        assert!(handles.execute(Command::Camera(None, None)).is_err());
+        assert!(handles.execute(Command::DumpRing).is_err());
+        assert!(handles.execute(Command::Stats).is_err());
+        assert!(handles
+            .execute(Command::LogLevel("debug".to_string()))
+            .is_err());
+        assert_eq!(
+            handles.execute(Command::Status).unwrap(),
+            "display=off intercom=off"
+        );
+    }
+
+    #[test]
+    fn test_dump_ring() {
+        use crate::processing::FrameProcessor;
+        use crate::replay::ReplayRecorder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (mut recorder, replay) = ReplayRecorder::new(8, dir.path());
+        let (handles, _display, _mute) = channels("PROGRAM", None);
+        let handles = handles.with_replay(replay);
+        recorder.process(&mut [128, 16, 128, 16], 2, 1);
+        recorder.process(&mut [128, 235, 128, 235], 2, 1);
+
+        let response = handles.respond("dump-ring");
+        let dump = dir.path().to_str().unwrap();
+        assert!(
+            response.starts_with(&format!("ok {}/replay-", dump)),
+            "{}",
+            response
+        );
+        assert!(response.ends_with(" frames=2"), "{}", response);
+        assert_eq!(handles.respond("dump-ring"), "err Replay ring is empty");
+    }
+
+    #[test]
+    fn test_status_line() {
+        let stats = Arc::new(IntercomStats::new());
+        stats.set_tally(Tally::Program);
+        let (handles, _display, _mute) = channels("PROGRAM", Some(stats));
+        let status = handles.execute(Command::Status).unwrap();
+        assert!(status.starts_with(
+            "display.source=\"PROGRAM\" display.overlay=off display.histogram=off display.zebra=off"
+        ));
+        assert!(status.contains(
+            "display.rx=0 display.rx_bytes=0 display.late_drops=0 display.displayed=0 intercom"
+        ));
+        assert!(status.contains("intercom.muted=on"));
+        assert!(status.contains("intercom.link=down"));
+        assert!(status.contains("intercom.tally=program"));
+        assert!(status.contains("intercom.mute_mode=manual_muted"));
+
+        handles
+            .execute(Command::IntercomGain(Gain::Sidetone, 40.0))
+            .unwrap();
+        handles
+            .execute(Command::IntercomGain(Gain::Headphone, 8.0))
+            .unwrap();
+        let status = handles.execute(Command::Status).unwrap();
+        assert!(status.contains("intercom.headphone_gain=8.0 intercom.sidetone_gain=40.0"));
+
+        let handles = handles.with_exposure(ExposureSettings {
+            zebra: true,
+            ..Default::default()
+        });
+        let status = handles.execute(Command::Status).unwrap();
+        assert!(status.contains("display.histogram=off display.zebra=95%"));
+    }
+
+    #[test]
+    fn test_stats() {
+        use crate::stats::{Component, StatsTicker};
+
+        let registry = Arc::new(StatsRegistry::new());
+        registry.register(Component::new("display").gauge("fps", || 25.0));
+        let (handles, _display, _mute) = channels("PROGRAM", None);
+        let handles = handles.with_stats(Arc::clone(&registry));
+        assert_eq!(handles.respond("stats"), "err No stats report yet");
+
+        let start = Instant::now();
+        let mut ticker = StatsTicker::new(Arc::clone(&registry), start);
+        ticker.tick(start + Duration::from_secs(10));
+        assert_eq!(handles.respond("stats"), "ok display.fps=25");
+    }
+
+    #[test]
+    fn test_log_level() {
+        use crate::log_level::DEFAULT_FILTER;
+
+        let (log_level, _layer) = LogLevel::new(DEFAULT_FILTER).unwrap();
+        let (handles, _display, _mute) = channels("PROGRAM", None);
+        let handles = handles.with_log_level(log_level.clone());
+        assert!(handles
+            .respond("status")
+            .ends_with("intercom=off log.filter=\"camera_box=info\""));
+
+        assert_eq!(handles.respond("log-level camera_box=debug"), "ok");
+        assert_eq!(log_level.current(), "camera_box=debug");
+        assert!(handles
+            .respond("status")
+            .ends_with("log.filter=\"camera_box=debug\""));
+        assert!(handles
+            .respond("log-level camera_box=loud")
+            .starts_with("err Invalid log filter"));
+        assert_eq!(log_level.current(), "camera_box=debug");
+    }
+
+    #[test]
+    fn test_socket_loopback() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let (handles, mut display, _mute) = channels("PROGRAM", None);
+        let _server = ControlServer::bind(&path, Arc::new(handles))
+            .unwrap()
+            .spawn();
+
+        assert_eq!(
+            send_command(&path, "display.source CAM2 RETURN").unwrap(),
+            "ok"
+        );
+        assert_eq!(display.source(), "CAM2 RETURN");
+
+        let status = send_command(&path, "status").unwrap();
+        assert!(status.starts_with("ok display.source=\"CAM2 RETURN\""));
+
+        let err = send_command(&path, "intercom.mute sideways").unwrap();
+        assert!(err.starts_with("err "));
+    }
+
+    #[test]
+    fn test_bind_replaces_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let (handles, _display, _mute) = channels("", None);
+        let server = ControlServer::bind(&path, Arc::new(handles)).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, SOCKET_MODE);
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_bind_refuses_running_instance() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let (handles, _display, _mute) = channels("", None);
+        let handles = Arc::new(handles);
+        let _running = ControlServer::bind(&path, Arc::clone(&handles)).unwrap();
+
+        let err = ControlServer::bind(&path, Arc::clone(&handles)).unwrap_err();
+        assert!(err.to_string().contains("Another instance"), "{}", err);
+        assert!(path.exists());
+
+        // Nor is a file that isn't a socket replaced
+        let file = dir.path().join("config.toml");
+        std::fs::write(&file, "").unwrap();
+        assert!(ControlServer::bind(&file, handles).is_err());
+        assert!(file.exists());
+    }
+
+    #[test]
+    fn test_passed_listener_keeps_socket_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let (handles, _display, _mute) = channels("", None);
+        let server = ControlServer::from_listener(listener, Arc::new(handles));
+        let _server = server.spawn();
+        assert_eq!(send_command(&path, "wake").unwrap(), "ok");
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn test_send_command_without_server() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(send_command(dir.path().join("missing.sock"), "status").is_err());
+    }
+}
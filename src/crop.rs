@@ -0,0 +1,296 @@
+//! Region-of-interest crop before NDI send
+//!
+//! `[capture.crop]` sends only part of the captured frame, e.g. the centre
+//! 1920x1080 of a 4K source as a digital punch-in. The driver crops through
+//! the V4L2 selection API when it supports it; otherwise packed frames are
+//! cropped in software with one row copy per line.
+
+use anyhow::{anyhow, bail, Result};
+use std::ffi::c_void;
+use std::io;
+use std::os::unix::io::RawFd;
+use v4l::v4l2::{self, vidioc};
+use v4l::FourCC;
+
+use crate::config::CropConfig;
+
+// =============================================================================
+// Crop Rectangle
+// =============================================================================
+
+/// Crop rectangle in pixels of the negotiated capture frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropRect {
+    pub fn from_config(config: &CropConfig) -> Self {
+        Self {
+            x: config.x,
+            y: config.y,
+            width: config.width,
+            height: config.height,
+        }
+    }
+
+    /// The whole `width`x`height` frame
+    pub fn full(width: u32, height: u32) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    /// Check the rectangle against a `frame_width`x`frame_height` format.
+    /// `x` and the width are rounded down to even so UYVY chroma pairs stay
+    /// intact.
+    pub fn fit(self, frame_width: u32, frame_height: u32) -> Result<Self> {
+        let rect = Self {
+            x: self.x & !1,
+            width: self.width & !1,
+            ..self
+        };
+        if rect.width == 0 || rect.height == 0 {
+            bail!("Crop {}x{} is empty", self.width, self.height);
+        }
+        let right = rect.x.checked_add(rect.width);
+        let bottom = rect.y.checked_add(rect.height);
+        if right.is_none_or(|right| right > frame_width)
+            || bottom.is_none_or(|bottom| bottom > frame_height)
+        {
+            bail!(
+                "Crop {} exceeds the {}x{} capture format",
+                rect,
+                frame_width,
+                frame_height
+            );
+        }
+        if rect != self {
+            tracing::info!("Crop {} rounded to {} for UYVY chroma pairs", self, rect);
+        }
+        Ok(rect)
+    }
+}
+
+impl std::fmt::Display for CropRect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+// =============================================================================
+// Software Crop
+// =============================================================================
+
+/// Bytes per pixel of the packed formats the software crop handles
+pub fn packed_bytes_per_pixel(fourcc: FourCC) -> Option<usize> {
+    match &fourcc.repr {
+        b"UYVY" | b"YUYV" => Some(2),
+        b"BGRA" | b"BGR4" | b"RX24" => Some(4),
+        _ => None,
+    }
+}
+
+/// Copy `rect` out of a packed frame with line stride `stride` into `dst`,
+/// tightly packed. Rows past the end of `src` are zero-filled.
+pub fn crop_packed(
+    src: &[u8],
+    stride: usize,
+    bytes_per_pixel: usize,
+    rect: CropRect,
+    dst: &mut Vec<u8>,
+) {
+    let row_bytes = rect.width as usize * bytes_per_pixel;
+    let offset = rect.x as usize * bytes_per_pixel;
+    dst.resize(row_bytes * rect.height as usize, 0);
+    if row_bytes == 0 {
+        return;
+    }
+    for (row, out) in dst.chunks_exact_mut(row_bytes).enumerate() {
+        let start = ((rect.y as usize + row) * stride + offset).min(src.len());
+        let end = (start + row_bytes).min(src.len());
+        let (copied, rest) = out.split_at_mut(end - start);
+        copied.copy_from_slice(&src[start..end]);
+        rest.fill(0);
+    }
+}
+
+/// Crops each captured frame into a reused buffer
+pub struct SoftwareCrop {
+    rect: CropRect,
+    source_stride: usize,
+    bytes_per_pixel: usize,
+    buffer: Vec<u8>,
+}
+
+impl SoftwareCrop {
+    /// Fails for planar and compressed formats, which can't be row-copied
+    pub fn new(rect: CropRect, fourcc: FourCC, source_stride: u32) -> Result<Self> {
+        let bytes_per_pixel = packed_bytes_per_pixel(fourcc).ok_or_else(|| {
+            anyhow!(
+                "Unsupported crop format: {}. Supported: UYVY, YUYV, BGRA",
+                fourcc
+            )
+        })?;
+        Ok(Self {
+            rect,
+            source_stride: source_stride as usize,
+            bytes_per_pixel,
+            buffer: Vec::new(),
+        })
+    }
+
+    pub fn rect(&self) -> CropRect {
+        self.rect
+    }
+
+    /// Line stride of the cropped frames
+    pub fn stride(&self) -> u32 {
+        self.rect.width * self.bytes_per_pixel as u32
+    }
+
+    /// Crop one frame; the result is valid until the next call
+    pub fn apply(&mut self, frame: &[u8]) -> &[u8] {
+        crop_packed(
+            frame,
+            self.source_stride,
+            self.bytes_per_pixel,
+            self.rect,
+            &mut self.buffer,
+        );
+        &self.buffer
+    }
+}
+
+// =============================================================================
+// V4L2 Selection
+// =============================================================================
+
+const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const V4L2_SEL_TGT_CROP: u32 = 0;
+
+/// `struct v4l2_rect`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct Rect {
+    left: i32,
+    top: i32,
+    width: u32,
+    height: u32,
+}
+
+/// `struct v4l2_selection`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct Selection {
+    type_: u32,
+    target: u32,
+    flags: u32,
+    r: Rect,
+    reserved: [u32; 9],
+}
+
+/// `_IOWR('V', 95, struct v4l2_selection)`
+const VIDIOC_S_SELECTION: vidioc::_IOC_TYPE =
+    ((3u32 << 30) | ((std::mem::size_of::<Selection>() as u32) << 16) | ((b'V' as u32) << 8) | 95)
+        as vidioc::_IOC_TYPE;
+
+/// Ask the driver to crop single-planar capture to `rect`. Returns the
+/// rectangle it applied, which it may have adjusted.
+pub fn set_selection(fd: RawFd, rect: CropRect) -> io::Result<CropRect> {
+    let mut selection = Selection {
+        type_: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+        target: V4L2_SEL_TGT_CROP,
+        r: Rect {
+            left: rect.x as i32,
+            top: rect.y as i32,
+            width: rect.width,
+            height: rect.height,
+        },
+        ..Default::default()
+    };
+    unsafe {
+        v4l2::ioctl(
+            fd,
+            VIDIOC_S_SELECTION,
+            &mut selection as *mut Selection as *mut c_void,
+        )
+    }?;
+    Ok(CropRect {
+        x: selection.r.left.max(0) as u32,
+        y: selection.r.top.max(0) as u32,
+        width: selection.r.width,
+        height: selection.r.height,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> CropRect {
+        CropRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_fit_rounds_to_chroma_pairs() {
+        let fitted = rect(961, 540, 1921, 1080).fit(3840, 2160).unwrap();
+        assert_eq!(fitted, rect(960, 540, 1920, 1080));
+        assert_eq!(fitted.to_string(), "1920x1080+960+540");
+        // Already even rectangles are unchanged, the full frame fits
+        assert_eq!(rect(2, 3, 4, 5).fit(8, 8).unwrap(), rect(2, 3, 4, 5));
+        assert_eq!(
+            CropRect::full(64, 8).fit(64, 8).unwrap(),
+            CropRect::full(64, 8)
+        );
+    }
+
+    #[test]
+    fn test_fit_rejects_bad_rectangles() {
+        assert!(rect(0, 0, 1, 10).fit(64, 64).is_err());
+        assert!(rect(0, 0, 10, 0).fit(64, 64).is_err());
+        assert!(rect(2000, 0, 1920, 1080).fit(3840, 2160).is_err());
+        assert!(rect(0, 1100, 1920, 1080).fit(3840, 2160).is_err());
+        assert!(rect(u32::MAX - 1, 0, 2, 2).fit(64, 64).is_err());
+    }
+
+    #[test]
+    fn test_crop_packed_row_math() {
+        // 4x3 UYVY frame with a 12 byte stride (4 bytes of padding per row),
+        // each byte holds its own offset
+        let frame: Vec<u8> = (0..36).collect();
+        let mut out = Vec::new();
+        crop_packed(&frame, 12, 2, rect(2, 1, 2, 2), &mut out);
+        assert_eq!(out, [16, 17, 18, 19, 28, 29, 30, 31]);
+
+        // A short source zero-fills the missing rows
+        crop_packed(&frame[..20], 12, 2, rect(2, 1, 2, 2), &mut out);
+        assert_eq!(out, [16, 17, 18, 19, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_software_crop() {
+        let mut crop = SoftwareCrop::new(rect(0, 0, 2, 1), FourCC::new(b"UYVY"), 8).unwrap();
+        assert_eq!(crop.stride(), 4);
+        assert_eq!(crop.apply(&[1, 2, 3, 4, 5, 6, 7, 8]), [1, 2, 3, 4]);
+        assert!(SoftwareCrop::new(rect(0, 0, 2, 2), FourCC::new(b"NV12"), 8).is_err());
+        assert!(SoftwareCrop::new(rect(0, 0, 2, 2), FourCC::new(b"MJPG"), 8).is_err());
+    }
+
+    #[test]
+    fn test_selection_layout() {
+        assert_eq!(std::mem::size_of::<Selection>(), 64);
+        assert_eq!(VIDIOC_S_SELECTION as u32, 0xc040_565f);
+    }
+}
@@ -0,0 +1,272 @@
+//! Deinterlacing for interlaced YUYV capture sources
+//!
+//! Analog-to-USB sticks deliver 576i/480i as two interleaved fields per
+//! frame. Sent as progressive, motion shows combing; these filters turn each
+//! interlaced frame into one progressive frame before UYVY conversion.
+
+use anyhow::{anyhow, Result};
+
+/// Temporal order of the two fields in an interlaced frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldOrder {
+    /// Not interlaced
+    Progressive,
+    /// Top (even lines) field is the earlier one
+    TopFirst,
+    /// Bottom (odd lines) field is the earlier one
+    BottomFirst,
+}
+
+impl FieldOrder {
+    /// Map a V4L2 field order. Plain `Interlaced` follows the video standard:
+    /// bottom first for 525-line (480i) and top first otherwise.
+    /// Field-per-buffer layouts (top/bottom/alternate/sequential) are not
+    /// interleaved and are treated as progressive.
+    pub fn from_v4l(field: v4l::format::FieldOrder, height: u32) -> Self {
+        use v4l::format::FieldOrder as V4l;
+        match field {
+            V4l::InterlacedTB => FieldOrder::TopFirst,
+            V4l::InterlacedBT => FieldOrder::BottomFirst,
+            V4l::Interlaced if height == 480 || height == 486 => FieldOrder::BottomFirst,
+            V4l::Interlaced => FieldOrder::TopFirst,
+            _ => FieldOrder::Progressive,
+        }
+    }
+
+    pub fn is_interlaced(self) -> bool {
+        self != FieldOrder::Progressive
+    }
+}
+
+/// How interlaced frames are handled (`capture.deinterlace`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeinterlaceMode {
+    /// Send frames as captured, flagged progressive
+    Off,
+    /// Keep the first field and double its lines
+    Bob,
+    /// Linear blend of both fields ([1 2 1] vertical filter)
+    Blend,
+    /// Send frames untouched, flagged as interleaved fields for the receiver
+    Interlaced,
+}
+
+impl DeinterlaceMode {
+    /// Parse a mode name from configuration ("off", "bob", "blend", "interlaced")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Ok(DeinterlaceMode::Off),
+            "bob" => Ok(DeinterlaceMode::Bob),
+            "blend" => Ok(DeinterlaceMode::Blend),
+            "interlaced" => Ok(DeinterlaceMode::Interlaced),
+            other => Err(anyhow!(
+                "Unsupported deinterlace mode: {}. Supported: off, bob, blend, interlaced",
+                other
+            )),
+        }
+    }
+
+    /// True if this mode rewrites pixel data for the given source
+    pub fn filters(self, field_order: FieldOrder) -> bool {
+        field_order.is_interlaced() && matches!(self, DeinterlaceMode::Bob | DeinterlaceMode::Blend)
+    }
+}
+
+/// Deinterlace a packed YUYV frame (stride = width * 2) into `out`.
+/// Progressive sources and the off/interlaced modes copy the frame unchanged.
+pub fn deinterlace_yuyv(
+    frame: &[u8],
+    width: usize,
+    height: usize,
+    field_order: FieldOrder,
+    mode: DeinterlaceMode,
+    out: &mut Vec<u8>,
+) {
+    let stride = width * 2;
+    let size = (stride * height).min(frame.len());
+    let height = size / stride.max(1);
+    out.clear();
+    out.extend_from_slice(&frame[..size]);
+
+    if !mode.filters(field_order) || height < 2 {
+        return;
+    }
+
+    let line = |y: usize| &frame[y * stride..(y + 1) * stride];
+    match mode {
+        DeinterlaceMode::Bob => {
+            // Lines of the later field are replaced by the neighbouring line
+            // of the earlier field
+            let keep_parity = match field_order {
+                FieldOrder::BottomFirst => 1,
+                _ => 0,
+            };
+            for y in (0..height).filter(|y| y % 2 != keep_parity) {
+                let src = if keep_parity == 0 || y + 1 >= height {
+                    y - 1
+                } else {
+                    y + 1
+                };
+                out[y * stride..(y + 1) * stride].copy_from_slice(line(src));
+            }
+        }
+        DeinterlaceMode::Blend => {
+            for y in 0..height {
+                let above = line(y.saturating_sub(1));
+                let center = line(y);
+                let below = line((y + 1).min(height - 1));
+                let dst = &mut out[y * stride..(y + 1) * stride];
+                for (i, d) in dst.iter_mut().enumerate() {
+                    let sum = above[i] as u16 + 2 * center[i] as u16 + below[i] as u16;
+                    *d = ((sum + 2) / 4) as u8;
+                }
+            }
+        }
+        DeinterlaceMode::Off | DeinterlaceMode::Interlaced => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 4;
+
+    /// Two-field test pattern: top field lines are 200, bottom field lines 40
+    fn two_field_frame(height: usize) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| {
+                let value = if y % 2 == 0 { 200 } else { 40 };
+                std::iter::repeat_n(value, WIDTH * 2)
+            })
+            .collect()
+    }
+
+    fn line_values(frame: &[u8]) -> Vec<u8> {
+        frame.chunks_exact(WIDTH * 2).map(|l| l[0]).collect()
+    }
+
+    fn run(frame: &[u8], height: usize, order: FieldOrder, mode: DeinterlaceMode) -> Vec<u8> {
+        let mut out = Vec::new();
+        deinterlace_yuyv(frame, WIDTH, height, order, mode, &mut out);
+        out
+    }
+
+    #[test]
+    fn test_bob_top_first_keeps_top_field() {
+        let frame = two_field_frame(6);
+        let out = run(&frame, 6, FieldOrder::TopFirst, DeinterlaceMode::Bob);
+        assert_eq!(line_values(&out), vec![200; 6]);
+    }
+
+    #[test]
+    fn test_bob_bottom_first_keeps_bottom_field() {
+        let frame = two_field_frame(6);
+        let out = run(&frame, 6, FieldOrder::BottomFirst, DeinterlaceMode::Bob);
+        assert_eq!(line_values(&out), vec![40; 6]);
+    }
+
+    #[test]
+    fn test_bob_odd_height() {
+        let frame = two_field_frame(5);
+        let out = run(&frame, 5, FieldOrder::BottomFirst, DeinterlaceMode::Bob);
+        // Last (top field) line has no line below and copies the one above
+        assert_eq!(line_values(&out), vec![40; 5]);
+    }
+
+    #[test]
+    fn test_blend_mixes_fields() {
+        let frame = two_field_frame(4);
+        let out = run(&frame, 4, FieldOrder::TopFirst, DeinterlaceMode::Blend);
+        // Interior lines: (40 + 2*200 + 40)/4 = 120 and (200 + 2*40 + 200)/4 = 120
+        // Edges repeat their own line: (200*3 + 40)/4 = 160, (40*3 + 200)/4 = 80
+        assert_eq!(line_values(&out), vec![160, 120, 120, 80]);
+    }
+
+    #[test]
+    fn test_blend_flat_image_unchanged() {
+        let frame = vec![77u8; WIDTH * 2 * 6];
+        let out = run(&frame, 6, FieldOrder::TopFirst, DeinterlaceMode::Blend);
+        assert_eq!(out, frame);
+    }
+
+    #[test]
+    fn test_progressive_and_passthrough_copy() {
+        let frame = two_field_frame(4);
+        assert_eq!(
+            run(&frame, 4, FieldOrder::Progressive, DeinterlaceMode::Bob),
+            frame
+        );
+        assert_eq!(
+            run(&frame, 4, FieldOrder::TopFirst, DeinterlaceMode::Off),
+            frame
+        );
+        assert_eq!(
+            run(&frame, 4, FieldOrder::TopFirst, DeinterlaceMode::Interlaced),
+            frame
+        );
+    }
+
+    #[test]
+    fn test_short_buffer_is_truncated_to_whole_lines() {
+        let frame = two_field_frame(4);
+        let out = run(
+            &frame[..WIDTH * 2 * 3 + 3],
+            4,
+            FieldOrder::TopFirst,
+            DeinterlaceMode::Bob,
+        );
+        assert_eq!(line_values(&out), vec![200; 3]);
+    }
+
+    #[test]
+    fn test_mode_from_name() {
+        assert_eq!(
+            DeinterlaceMode::from_name("off").unwrap(),
+            DeinterlaceMode::Off
+        );
+        assert_eq!(
+            DeinterlaceMode::from_name("BOB").unwrap(),
+            DeinterlaceMode::Bob
+        );
+        assert_eq!(
+            DeinterlaceMode::from_name("blend").unwrap(),
+            DeinterlaceMode::Blend
+        );
+        assert_eq!(
+            DeinterlaceMode::from_name("interlaced").unwrap(),
+            DeinterlaceMode::Interlaced
+        );
+        assert!(DeinterlaceMode::from_name("yadif").is_err());
+    }
+
+    #[test]
+    fn test_field_order_from_v4l() {
+        use v4l::format::FieldOrder as V4l;
+        assert_eq!(
+            FieldOrder::from_v4l(V4l::Progressive, 1080),
+            FieldOrder::Progressive
+        );
+        assert_eq!(FieldOrder::from_v4l(V4l::Any, 576), FieldOrder::Progressive);
+        assert_eq!(
+            FieldOrder::from_v4l(V4l::InterlacedTB, 480),
+            FieldOrder::TopFirst
+        );
+        assert_eq!(
+            FieldOrder::from_v4l(V4l::InterlacedBT, 576),
+            FieldOrder::BottomFirst
+        );
+        assert_eq!(
+            FieldOrder::from_v4l(V4l::Interlaced, 576),
+            FieldOrder::TopFirst
+        );
+        assert_eq!(
+            FieldOrder::from_v4l(V4l::Interlaced, 480),
+            FieldOrder::BottomFirst
+        );
+        assert_eq!(
+            FieldOrder::from_v4l(V4l::Alternate, 288),
+            FieldOrder::Progressive
+        );
+    }
+}
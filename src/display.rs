@@ -3,16 +3,65 @@
 //! Simple framebuffer-based display that writes directly to /dev/fb0.
 //! Used for displaying NDI streams on the local HDMI output.
 
-use anyhow::{Context, Result};
 use std::fs::{File, OpenOptions};
-use std::io::{Seek, SeekFrom, Write};
-use std::os::unix::fs::FileExt;
+use std::io::{self, Seek, SeekFrom};
 use std::os::unix::io::AsRawFd;
 
+use crate::aspect;
+use crate::audio_meter::{ChannelLevel, MeterOverlay};
+use crate::color_range::ColorRange;
+use crate::exposure::{ExposureOverlay, ExposureSettings};
+use crate::image_source::Letterbox;
+use crate::io_util;
+use crate::splash::SplashScreen;
+use crate::standby::StandbyScreen;
+
 // Framebuffer ioctl constants
 const FBIOGET_VSCREENINFO: libc::c_ulong = 0x4600;
 const FBIOGET_FSCREENINFO: libc::c_ulong = 0x4602;
 
+/// Why the framebuffer could not be opened or written
+#[derive(Debug, thiserror::Error)]
+pub enum DisplayError {
+    /// No framebuffer device, e.g. no monitor driver loaded
+    #[error("Framebuffer not found: {path}")]
+    DeviceNotFound { path: String },
+    #[error("Permission denied opening framebuffer {path}")]
+    PermissionDenied { path: String },
+    #[error("Failed to open framebuffer: {path}")]
+    Open {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The device is not a framebuffer or the driver rejected the query
+    #[error("Failed to get framebuffer {what} info")]
+    ScreenInfo {
+        what: &'static str,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Framebuffer write failed")]
+    Write(#[from] io::Error),
+}
+
+impl DisplayError {
+    /// Classify a failure to open the framebuffer at `path`
+    pub fn open_failed(path: &str, source: io::Error) -> Self {
+        let path = path.to_string();
+        match source.kind() {
+            io::ErrorKind::NotFound => DisplayError::DeviceNotFound { path },
+            io::ErrorKind::PermissionDenied => DisplayError::PermissionDenied { path },
+            _ => DisplayError::Open { path, source },
+        }
+    }
+
+    /// A write that kept being interrupted by signals, not a lost monitor
+    pub fn is_interrupted(&self) -> bool {
+        matches!(self, DisplayError::Write(e) if io_util::is_interrupted(e))
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Default, Clone, Copy)]
 struct FbVarScreenInfo {
@@ -76,51 +125,44 @@ struct FbFixScreenInfo {
     reserved: [u16; 2],
 }
 
-/// Framebuffer display wrapper
-pub struct FramebufferDisplay {
-    file: File,
-    width: u32,
-    height: u32,
-    #[allow(dead_code)]
-    bits_per_pixel: u32,
-    line_length: u32,
+/// Video mode of the framebuffer, as reported by FBIOGET_VSCREENINFO and
+/// FBIOGET_FSCREENINFO. It changes when a monitor with a different
+/// resolution is plugged in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FbMode {
+    pub width: u32,
+    pub height: u32,
+    pub bits_per_pixel: u32,
+    /// Bytes per framebuffer line, including padding
+    pub line_length: u32,
 }
 
-impl FramebufferDisplay {
-    /// Open the framebuffer device
-    pub fn open(device: &str) -> Result<Self> {
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(device)
-            .with_context(|| format!("Failed to open framebuffer: {}", device))?;
-
+impl FbMode {
+    /// Query the current mode of an open framebuffer
+    fn query(file: &File) -> Result<Self, DisplayError> {
         let fd = file.as_raw_fd();
 
         // Get variable screen info
         let mut vinfo = FbVarScreenInfo::default();
         let ret = unsafe { libc::ioctl(fd, FBIOGET_VSCREENINFO, &mut vinfo) };
         if ret < 0 {
-            anyhow::bail!("Failed to get framebuffer variable info");
+            return Err(DisplayError::ScreenInfo {
+                what: "variable",
+                source: io::Error::last_os_error(),
+            });
         }
 
         // Get fixed screen info
         let mut finfo = FbFixScreenInfo::default();
         let ret = unsafe { libc::ioctl(fd, FBIOGET_FSCREENINFO, &mut finfo) };
         if ret < 0 {
-            anyhow::bail!("Failed to get framebuffer fixed info");
+            return Err(DisplayError::ScreenInfo {
+                what: "fixed",
+                source: io::Error::last_os_error(),
+            });
         }
 
-        tracing::info!(
-            "Framebuffer: {}x{} {}bpp (line_length: {})",
-            vinfo.xres,
-            vinfo.yres,
-            vinfo.bits_per_pixel,
-            finfo.line_length
-        );
-
         Ok(Self {
-            file,
             width: vinfo.xres,
             height: vinfo.yres,
             bits_per_pixel: vinfo.bits_per_pixel,
@@ -128,176 +170,427 @@ impl FramebufferDisplay {
         })
     }
 
+    /// Bytes of BGRA pixels per line
+    fn row_bytes(&self) -> usize {
+        self.width as usize * 4
+    }
+
+    /// Whether frames can be written: a disconnected output may report an
+    /// empty mode or lines shorter than the pixels
+    fn is_usable(&self) -> bool {
+        self.width > 0 && self.height > 0 && self.line_length as usize >= self.row_bytes()
+    }
+}
+
+/// Framebuffer display wrapper
+pub struct FramebufferDisplay {
+    file: File,
+    mode: FbMode,
+    /// Zeros written after each line when `line_length` is padded
+    padding: Vec<u8>,
+    /// Scaled frame, reused while the source and mode stay the same
+    scaled: Vec<u8>,
+    /// UYVY frame downscaled to the mode before conversion
+    scaled_uyvy: Vec<u8>,
+    /// Letterboxed frame: the scaled picture between black bars
+    boxed: Vec<u8>,
+    /// Last unsupported fourcc that was logged, to warn once per format
+    unsupported_fourcc: Option<u32>,
+    /// Histogram and zebra, drawn on UYVY frames
+    exposure: ExposureOverlay,
+    /// "PGM" meter of the program audio, drawn on every frame
+    program: MeterOverlay,
+    /// Frame video with the call border (an incoming intercom call)
+    call_flash: bool,
+}
+
+/// Width of the call border in pixels
+const CALL_BORDER: usize = 24;
+
+/// Call border color (amber, BGRA)
+const CALL_COLOR: [u8; 4] = [0, 0xBF, 0xFF, 0xFF];
+
+/// Paint the call border around the edges of a BGRA image
+fn draw_call_border(bgra: &mut [u8], width: u32, height: u32) {
+    let (width, height) = (width as usize, height as usize);
+    let border = CALL_BORDER.min(width / 2).min(height / 2);
+    for (y, row) in bgra.chunks_exact_mut(width * 4).take(height).enumerate() {
+        let span = if y < border || y >= height - border {
+            0..width
+        } else {
+            0..border
+        };
+        for x in span.clone().chain(width - border..width) {
+            row[x * 4..x * 4 + 4].copy_from_slice(&CALL_COLOR);
+        }
+    }
+}
+
+impl FramebufferDisplay {
+    /// Open the framebuffer device
+    pub fn open(device: &str) -> Result<Self, DisplayError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(device)
+            .map_err(|e| DisplayError::open_failed(device, e))?;
+        let mode = FbMode::query(&file)?;
+
+        tracing::info!(
+            "Framebuffer: {}x{} {}bpp (line_length: {})",
+            mode.width,
+            mode.height,
+            mode.bits_per_pixel,
+            mode.line_length
+        );
+
+        Ok(Self::with_mode(file, mode))
+    }
+
+    fn with_mode(file: File, mode: FbMode) -> Self {
+        let mut display = Self {
+            file,
+            mode,
+            padding: Vec::new(),
+            scaled: Vec::new(),
+            scaled_uyvy: Vec::new(),
+            boxed: Vec::new(),
+            unsupported_fourcc: None,
+            exposure: ExposureOverlay::new(ExposureSettings::default()),
+            program: MeterOverlay::new(),
+            call_flash: false,
+        };
+        display.apply_mode(mode);
+        display
+    }
+
     /// Get display dimensions
     pub fn dimensions(&self) -> (u32, u32) {
-        (self.width, self.height)
+        (self.mode.width, self.mode.height)
     }
 
-    /// Display a frame (handles format conversion and scaling)
+    /// Current video mode
+    pub fn mode(&self) -> FbMode {
+        self.mode
+    }
+
+    /// Exposure aids currently shown
+    pub fn exposure(&self) -> ExposureSettings {
+        self.exposure.settings()
+    }
+
+    /// Show or hide the histogram and zebra
+    pub fn set_exposure(&mut self, settings: ExposureSettings) {
+        self.exposure.set_settings(settings);
+    }
+
+    /// Show program audio levels on the following video frames, e.g. from
+    /// [`crate::audio_meter::ProgramMeter::levels`]; none hides the meter
+    pub fn set_program_levels(&mut self, levels: &[ChannelLevel]) {
+        self.program.set_levels(levels);
+    }
+
+    /// Draw the call border on the following video frames or not; the
+    /// caller blinks it
+    pub fn set_call_flash(&mut self, on: bool) {
+        self.call_flash = on;
+    }
+
+    /// Re-read the video mode, e.g. after a write failed or a monitor was
+    /// replugged. Returns true if it changed; the scratch buffers then
+    /// follow the new mode. Fails if the device itself is gone.
+    pub fn refresh_mode(&mut self) -> Result<bool, DisplayError> {
+        let mode = FbMode::query(&self.file)?;
+        Ok(self.apply_mode(mode))
+    }
+
+    /// Switch to `mode`, returning true if it differs from the cached one
+    fn apply_mode(&mut self, mode: FbMode) -> bool {
+        let changed = mode != self.mode;
+        if changed {
+            tracing::info!(
+                "Framebuffer mode changed: {}x{} -> {}x{} {}bpp (line_length: {})",
+                self.mode.width,
+                self.mode.height,
+                mode.width,
+                mode.height,
+                mode.bits_per_pixel,
+                mode.line_length
+            );
+        }
+        self.mode = mode;
+        let padding = (mode.line_length as usize).saturating_sub(mode.row_bytes());
+        self.padding.resize(padding, 0);
+        self.scaled.clear();
+        self.scaled_uyvy.clear();
+        self.boxed.clear();
+        changed
+    }
+
+    /// Display a frame stretched over the whole screen (handles format
+    /// conversion and scaling). `stride` is the source line stride in
+    /// bytes; 0 means tightly packed. Returns false without writing
+    /// anything if the fourcc is unsupported.
     pub fn display_frame(
         &mut self,
         data: &[u8],
         width: u32,
         height: u32,
+        stride: u32,
         fourcc: u32,
-    ) -> Result<()> {
-        // Convert to BGRA for framebuffer
-        let bgra_data = self.convert_to_bgra(data, width, height, fourcc)?;
-
-        // Scale if needed
-        let final_data = if width != self.width || height != self.height {
-            self.scale_nearest(&bgra_data, width, height, self.width, self.height)
-        } else {
-            bgra_data
+    ) -> Result<bool, DisplayError> {
+        let (fb_width, fb_height) = self.dimensions();
+        let screen = Letterbox {
+            x: 0,
+            y: 0,
+            width: fb_width,
+            height: fb_height,
         };
-
-        // Write to framebuffer using pwrite (atomic position + write)
-        let src_stride = self.width as usize * 4;
-        if self.line_length as usize == src_stride {
-            // No padding needed - write entire frame at once at offset 0
-            self.file.write_all_at(&final_data, 0)?;
-        } else {
-            // Write line by line with padding
-            self.file.seek(SeekFrom::Start(0))?;
-            for y in 0..self.height as usize {
-                let src_offset = y * src_stride;
-                let src_end = src_offset + src_stride;
-                if src_end <= final_data.len() {
-                    self.file.write_all(&final_data[src_offset..src_end])?;
-                    let padding = self.line_length as usize - src_stride;
-                    if padding > 0 {
-                        self.file.write_all(&vec![0u8; padding])?;
-                    }
-                }
-            }
-        }
-
-        Ok(())
+        self.draw_frame(data, width, height, stride, fourcc, screen)
     }
 
-    /// Convert various formats to BGRA
-    fn convert_to_bgra(
+    /// Display a frame shown at `aspect` (display width over height),
+    /// letterboxed or pillarboxed to fit the screen, like
+    /// [`Self::display_frame`] otherwise
+    pub fn display_frame_with_aspect(
         &mut self,
         data: &[u8],
         width: u32,
         height: u32,
+        stride: u32,
         fourcc: u32,
-    ) -> Result<Vec<u8>> {
-        let fourcc_bytes = fourcc.to_le_bytes();
-        let fourcc_str = std::str::from_utf8(&fourcc_bytes).unwrap_or("????");
+        aspect: f32,
+    ) -> Result<bool, DisplayError> {
+        let (fb_width, fb_height) = self.dimensions();
+        let place = aspect::fit(aspect, fb_width, fb_height);
+        self.draw_frame(data, width, height, stride, fourcc, place)
+    }
 
-        match fourcc_str {
-            "UYVY" => Ok(self.uyvy_to_bgra(data, width, height)),
-            "BGRA" | "BGRX" => Ok(data.to_vec()),
-            "RGBA" => Ok(self.rgba_to_bgra(data)),
-            _ => {
-                tracing::warn!(
-                    "Unknown fourcc: {} (0x{:08x}), treating as UYVY",
-                    fourcc_str,
-                    fourcc
-                );
-                Ok(self.uyvy_to_bgra(data, width, height))
-            }
+    /// Scale the frame into `place` on the screen, black around it
+    fn draw_frame(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        stride: u32,
+        fourcc: u32,
+        place: Letterbox,
+    ) -> Result<bool, DisplayError> {
+        if !self.mode.is_usable() {
+            return Ok(true);
         }
-    }
 
-    /// Convert UYVY to BGRA
-    fn uyvy_to_bgra(&self, uyvy: &[u8], width: u32, height: u32) -> Vec<u8> {
-        let mut bgra = Vec::with_capacity((width * height * 4) as usize);
+        let (fb_width, fb_height) = self.dimensions();
+        let (pic_width, pic_height) = (place.width, place.height);
+        let uyvy = fourcc == u32::from_le_bytes(*b"UYVY");
+        let path = scale_path(uyvy, width, height, pic_width, pic_height);
+
+        // Convert to BGRA for framebuffer; unsupported formats are left to
+        // the caller's standby screen.
+        // Downscaled UYVY is converted at the mode's size, which is cheaper.
+        let mut bgra_data = if path == ScalePath::ScaleThenConvert {
+            scale_uyvy_into(
+                data,
+                width,
+                height,
+                stride,
+                pic_width,
+                pic_height,
+                &mut self.scaled_uyvy,
+            );
+            convert_uyvy_to_bgra(
+                &self.scaled_uyvy,
+                pic_width,
+                pic_height,
+                ColorRange::Limited,
+            )
+        } else {
+            match self.convert_to_bgra(data, width, height, stride, fourcc) {
+                Some(bgra) => bgra,
+                None => return Ok(false),
+            }
+        };
 
-        for y in 0..height as usize {
-            for x in (0..width as usize).step_by(2) {
-                let idx = (y * width as usize + x) * 2;
-                if idx + 3 >= uyvy.len() {
-                    break;
-                }
+        // Exposure aids measure the source luma, so only UYVY frames get them
+        let exposure = self.exposure.is_active() && uyvy;
+        if exposure {
+            let (source, width, height, stride) = if path == ScalePath::ScaleThenConvert {
+                (&self.scaled_uyvy[..], pic_width, pic_height, 0)
+            } else {
+                (data, width, height, stride)
+            };
+            self.exposure
+                .mark_source(&mut bgra_data, source, width, height, stride);
+        }
 
-                let u = uyvy[idx] as i32 - 128;
-                let y0 = uyvy[idx + 1] as i32;
-                let v = uyvy[idx + 2] as i32 - 128;
-                let y1 = uyvy[idx + 3] as i32;
-
-                // YUV to RGB (BT.601)
-                let r0 = (y0 + (359 * v) / 256).clamp(0, 255) as u8;
-                let g0 = (y0 - (88 * u) / 256 - (183 * v) / 256).clamp(0, 255) as u8;
-                let b0 = (y0 + (454 * u) / 256).clamp(0, 255) as u8;
-
-                let r1 = (y1 + (359 * v) / 256).clamp(0, 255) as u8;
-                let g1 = (y1 - (88 * u) / 256 - (183 * v) / 256).clamp(0, 255) as u8;
-                let b1 = (y1 + (454 * u) / 256).clamp(0, 255) as u8;
-
-                // BGRA format
-                bgra.push(b0);
-                bgra.push(g0);
-                bgra.push(r0);
-                bgra.push(255);
-
-                bgra.push(b1);
-                bgra.push(g1);
-                bgra.push(r1);
-                bgra.push(255);
-            }
+        let picture = if path == ScalePath::ConvertThenScale {
+            scale_nearest_into(
+                &bgra_data,
+                width,
+                height,
+                pic_width,
+                pic_height,
+                &mut self.scaled,
+            );
+            &mut self.scaled
+        } else {
+            &mut bgra_data
+        };
+        let final_data = if (pic_width, pic_height) == (fb_width, fb_height) {
+            picture
+        } else {
+            place_into(picture, place, fb_width, fb_height, &mut self.boxed);
+            &mut self.boxed
+        };
+        if exposure {
+            self.exposure.draw_output(final_data, fb_width, fb_height);
+        }
+        self.program.draw_output(final_data, fb_width, fb_height);
+        if self.call_flash {
+            draw_call_border(final_data, fb_width, fb_height);
         }
 
-        bgra
+        write_bgra(&mut self.file, self.mode, &self.padding, final_data)?;
+        Ok(true)
     }
 
-    /// Convert RGBA to BGRA (swap R and B)
-    fn rgba_to_bgra(&self, rgba: &[u8]) -> Vec<u8> {
-        let mut bgra = Vec::with_capacity(rgba.len());
-        for chunk in rgba.chunks_exact(4) {
-            bgra.push(chunk[2]); // B
-            bgra.push(chunk[1]); // G
-            bgra.push(chunk[0]); // R
-            bgra.push(chunk[3]); // A
+    /// Show the standby screen; only written when it changed since the
+    /// last call
+    pub fn show_standby(
+        &mut self,
+        standby: &mut StandbyScreen,
+        now: std::time::Instant,
+        clock: &str,
+    ) -> Result<(), DisplayError> {
+        if !self.mode.is_usable() {
+            return Ok(());
+        }
+        let (fb_width, fb_height) = self.dimensions();
+        match standby.render(fb_width, fb_height, now, clock) {
+            Some(frame) => write_bgra(&mut self.file, self.mode, &self.padding, frame),
+            None => Ok(()),
         }
-        bgra
     }
 
-    /// Simple nearest-neighbor scaling
-    fn scale_nearest(&self, src: &[u8], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Vec<u8> {
-        let mut dst = vec![0u8; (dst_w * dst_h * 4) as usize];
-
-        for dst_y in 0..dst_h {
-            let src_y = (dst_y * src_h / dst_h).min(src_h - 1);
-            for dst_x in 0..dst_w {
-                let src_x = (dst_x * src_w / dst_w).min(src_w - 1);
+    /// Draw the boot splash with `message`; like the standby screen, only
+    /// when it changed
+    pub fn show_splash(
+        &mut self,
+        splash: &mut SplashScreen,
+        message: &str,
+    ) -> Result<(), DisplayError> {
+        if !self.mode.is_usable() {
+            return Ok(());
+        }
+        let (fb_width, fb_height) = self.dimensions();
+        match splash.render(fb_width, fb_height, message) {
+            Some(frame) => write_bgra(&mut self.file, self.mode, &self.padding, frame),
+            None => Ok(()),
+        }
+    }
 
-                let src_idx = ((src_y * src_w + src_x) * 4) as usize;
-                let dst_idx = ((dst_y * dst_w + dst_x) * 4) as usize;
+    /// Convert various formats to BGRA, or None for an unsupported fourcc
+    fn convert_to_bgra(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        stride: u32,
+        fourcc: u32,
+    ) -> Option<Vec<u8>> {
+        let fourcc_bytes = fourcc.to_le_bytes();
+        let fourcc_str = std::str::from_utf8(&fourcc_bytes).unwrap_or("????");
 
-                if src_idx + 3 < src.len() && dst_idx + 3 < dst.len() {
-                    dst[dst_idx] = src[src_idx];
-                    dst[dst_idx + 1] = src[src_idx + 1];
-                    dst[dst_idx + 2] = src[src_idx + 2];
-                    dst[dst_idx + 3] = src[src_idx + 3];
-                }
-            }
+        if let Some(bgra) = frame_to_bgra(data, width, height, stride, fourcc_str) {
+            return Some(bgra);
         }
-
-        dst
+        if self.unsupported_fourcc != Some(fourcc) {
+            tracing::warn!(
+                "Unsupported fourcc: {} (0x{:08x}), showing standby screen",
+                fourcc_str,
+                fourcc
+            );
+            self.unsupported_fourcc = Some(fourcc);
+        }
+        None
     }
 
     /// Clear the display to black
-    #[allow(dead_code)]
-    pub fn clear(&mut self) -> Result<()> {
-        let black = vec![0u8; (self.line_length * self.height) as usize];
-        self.file.seek(SeekFrom::Start(0))?;
-        self.file.write_all(&black)?;
+    pub fn clear(&mut self) -> Result<(), DisplayError> {
+        let black = vec![0u8; (self.mode.line_length * self.mode.height) as usize];
+        io_util::write_all_at(&self.file, &black, 0)?;
         Ok(())
     }
 }
 
+/// Copy the BGRA `picture` of `place`'s size to `place` in a black
+/// `width`x`height` image
+fn place_into(picture: &[u8], place: Letterbox, width: u32, height: u32, dst: &mut Vec<u8>) {
+    dst.resize(width as usize * height as usize * 4, 0);
+    for px in dst.chunks_exact_mut(4) {
+        px.copy_from_slice(&[0, 0, 0, 255]);
+    }
+    let row_bytes = place.width as usize * 4;
+    for (y, row) in picture
+        .chunks_exact(row_bytes)
+        .take(place.height as usize)
+        .enumerate()
+    {
+        let start = ((place.y as usize + y) * width as usize + place.x as usize) * 4;
+        dst[start..start + row_bytes].copy_from_slice(row);
+    }
+}
+
+/// Write a BGRA image of the framebuffer's size, adding `padding` after
+/// each line when `line_length` is padded
+fn write_bgra(
+    file: &mut File,
+    mode: FbMode,
+    padding: &[u8],
+    bgra: &[u8],
+) -> Result<(), DisplayError> {
+    if padding.is_empty() {
+        // No padding needed - write entire frame at once at offset 0
+        // (pwrite: atomic position + write)
+        io_util::write_all_at(file, bgra, 0)?;
+    } else {
+        // Write line by line with padding
+        io_util::retry_eintr(|| file.seek(SeekFrom::Start(0)))?;
+        for row in bgra
+            .chunks_exact(mode.row_bytes())
+            .take(mode.height as usize)
+        {
+            io_util::write_all(file, row)?;
+            io_util::write_all(file, padding)?;
+        }
+    }
+    Ok(())
+}
+
 // Standalone conversion functions for testing and potential reuse
 // These mirror the FramebufferDisplay methods but don't require a framebuffer
 
-/// Convert UYVY to BGRA (standalone version for testing)
-pub fn convert_uyvy_to_bgra(uyvy: &[u8], width: u32, height: u32) -> Vec<u8> {
+/// Convert tightly packed UYVY in the given range to BGRA (standalone
+/// version for testing)
+pub fn convert_uyvy_to_bgra(uyvy: &[u8], width: u32, height: u32, range: ColorRange) -> Vec<u8> {
+    convert_uyvy_to_bgra_strided(uyvy, width, height, 0, range)
+}
+
+/// Convert UYVY to BGRA reading each row at `row * stride` (0 for tightly
+/// packed), so senders that pad lines aren't sheared
+pub fn convert_uyvy_to_bgra_strided(
+    uyvy: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    range: ColorRange,
+) -> Vec<u8> {
     let mut bgra = Vec::with_capacity((width * height * 4) as usize);
+    let stride = (stride as usize).max(width as usize * 2);
 
     for y in 0..height as usize {
         for x in (0..width as usize).step_by(2) {
-            let idx = (y * width as usize + x) * 2;
+            let idx = y * stride + x * 2;
             if idx + 3 >= uyvy.len() {
                 break;
             }
@@ -307,28 +600,189 @@ pub fn convert_uyvy_to_bgra(uyvy: &[u8], width: u32, height: u32) -> Vec<u8> {
             let v = uyvy[idx + 2] as i32 - 128;
             let y1 = uyvy[idx + 3] as i32;
 
-            // YUV to RGB (BT.601)
-            let r0 = (y0 + (359 * v) / 256).clamp(0, 255) as u8;
-            let g0 = (y0 - (88 * u) / 256 - (183 * v) / 256).clamp(0, 255) as u8;
-            let b0 = (y0 + (454 * u) / 256).clamp(0, 255) as u8;
+            for luma in [y0, y1] {
+                let [b, g, r] = yuv_to_bgr(luma, u, v, range);
+                bgra.extend_from_slice(&[b, g, r, 255]);
+            }
+        }
+    }
 
-            let r1 = (y1 + (359 * v) / 256).clamp(0, 255) as u8;
-            let g1 = (y1 - (88 * u) / 256 - (183 * v) / 256).clamp(0, 255) as u8;
-            let b1 = (y1 + (454 * u) / 256).clamp(0, 255) as u8;
+    bgra
+}
+
+/// One pixel of YUV (chroma centred on 0) to BGR (BT.601)
+#[inline]
+pub(crate) fn yuv_to_bgr(y: i32, u: i32, v: i32, range: ColorRange) -> [u8; 3] {
+    let (r, g, b) = match range {
+        // Expand 16-235 luma and 16-240 chroma to 0-255
+        ColorRange::Limited => {
+            let c = 298 * (y - 16);
+            (
+                (c + 409 * v + 128) >> 8,
+                (c - 100 * u - 208 * v + 128) >> 8,
+                (c + 516 * u + 128) >> 8,
+            )
+        }
+        ColorRange::Full => (
+            y + (359 * v) / 256,
+            y - (88 * u) / 256 - (183 * v) / 256,
+            y + (454 * u) / 256,
+        ),
+    };
+    [
+        b.clamp(0, 255) as u8,
+        g.clamp(0, 255) as u8,
+        r.clamp(0, 255) as u8,
+    ]
+}
+
+/// Byte order of the 32-bit NDI video formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackedFormat {
+    Bgra,
+    /// BGRA with an undefined fourth byte
+    Bgrx,
+    Rgba,
+    /// RGBA with an undefined fourth byte
+    Rgbx,
+}
+
+impl PackedFormat {
+    pub fn from_fourcc(fourcc: &str) -> Option<Self> {
+        match fourcc {
+            "BGRA" => Some(PackedFormat::Bgra),
+            "BGRX" => Some(PackedFormat::Bgrx),
+            "RGBA" => Some(PackedFormat::Rgba),
+            "RGBX" => Some(PackedFormat::Rgbx),
+            _ => None,
+        }
+    }
+}
+
+/// Convert a 32-bit format to BGRA, reading `stride` bytes per row (0 for
+/// tightly packed). The X byte of BGRX/RGBX becomes opaque alpha.
+pub fn convert_packed_to_bgra(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    format: PackedFormat,
+) -> Vec<u8> {
+    let row_bytes = width as usize * 4;
+    let stride = (stride as usize).max(row_bytes);
+    let mut bgra = Vec::with_capacity(row_bytes * height as usize);
+    if row_bytes == 0 {
+        return bgra;
+    }
+
+    for row in data.chunks(stride).take(height as usize) {
+        let Some(row) = row.get(..row_bytes) else {
+            break;
+        };
+        match format {
+            PackedFormat::Bgra => bgra.extend_from_slice(row),
+            PackedFormat::Bgrx => {
+                for px in row.chunks_exact(4) {
+                    bgra.extend_from_slice(&[px[0], px[1], px[2], 255]);
+                }
+            }
+            PackedFormat::Rgba => {
+                for px in row.chunks_exact(4) {
+                    bgra.extend_from_slice(&[px[2], px[1], px[0], px[3]]);
+                }
+            }
+            PackedFormat::Rgbx => {
+                for px in row.chunks_exact(4) {
+                    bgra.extend_from_slice(&[px[2], px[1], px[0], 255]);
+                }
+            }
+        }
+    }
+
+    bgra
+}
 
-            // BGRA format
-            bgra.push(b0);
-            bgra.push(g0);
-            bgra.push(r0);
-            bgra.push(255);
+/// Framebuffer BGRA for one received frame, or None for an unsupported
+/// fourcc. BGRA and BGRX (`display.color_format = "bgra"`) are only copied;
+/// UYVY is converted as limited range, like all NDI video, and so are
+/// P216 and PA16 once cut down to 8 bits.
+pub fn frame_to_bgra(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    fourcc: &str,
+) -> Option<Vec<u8>> {
+    if fourcc == "UYVY" {
+        return Some(convert_uyvy_to_bgra_strided(
+            data,
+            width,
+            height,
+            stride,
+            ColorRange::Limited,
+        ));
+    }
+    if matches!(fourcc, "P216" | "PA16") {
+        let uyvy = convert_p216_to_uyvy(data, width, height, stride);
+        return Some(convert_uyvy_to_bgra(
+            &uyvy,
+            width,
+            height,
+            ColorRange::Limited,
+        ));
+    }
+    match PackedFormat::from_fourcc(fourcc)? {
+        // Already in framebuffer order (the X byte is ignored)
+        PackedFormat::Bgra | PackedFormat::Bgrx => {
+            Some(copy_bgra_rows(data, width, height, stride))
+        }
+        format => Some(convert_packed_to_bgra(data, width, height, stride, format)),
+    }
+}
 
-            bgra.push(b1);
-            bgra.push(g1);
-            bgra.push(r1);
-            bgra.push(255);
+/// UYVY from the 16-bit 4:2:2 NDI formats: P216 is a plane of Y samples
+/// followed by a plane of interleaved Cb,Cr pairs, PA16 adds an alpha plane
+/// after those (ignored here). Both planes are read at `stride` bytes per
+/// row (0 for tightly packed) and each sample keeps its high byte; without
+/// dither, which is plenty for a confidence monitor. Samples missing from
+/// `data` are black.
+pub fn convert_p216_to_uyvy(data: &[u8], width: u32, height: u32, stride: u32) -> Vec<u8> {
+    let (pairs, height) = (width as usize / 2, height as usize);
+    let stride = (stride as usize).max(width as usize * 2);
+    let chroma_plane = stride * height;
+    // High byte of the little-endian sample at `at`
+    let high = |at: usize, black: u8| data.get(at + 1).copied().unwrap_or(black);
+    let mut uyvy = Vec::with_capacity(pairs * 4 * height);
+    for row in 0..height {
+        let luma = row * stride;
+        let chroma = chroma_plane + row * stride;
+        for pair in (0..pairs).map(|pair| pair * 4) {
+            uyvy.extend_from_slice(&[
+                high(chroma + pair, 128),
+                high(luma + pair, 16),
+                high(chroma + pair + 2, 128),
+                high(luma + pair + 2, 16),
+            ]);
         }
     }
+    uyvy
+}
 
+/// Copy BGRA or BGRX rows as they are into a tightly packed buffer, reading
+/// `stride` bytes per row (0 for tightly packed)
+pub fn copy_bgra_rows(data: &[u8], width: u32, height: u32, stride: u32) -> Vec<u8> {
+    let row_bytes = width as usize * 4;
+    let stride = (stride as usize).max(row_bytes);
+    let mut bgra = Vec::with_capacity(row_bytes * height as usize);
+    if row_bytes == 0 {
+        return bgra;
+    }
+    for row in data.chunks(stride).take(height as usize) {
+        let Some(row) = row.get(..row_bytes) else {
+            break;
+        };
+        bgra.extend_from_slice(row);
+    }
     bgra
 }
 
@@ -352,7 +806,21 @@ pub fn scale_nearest_neighbor(
     dst_w: u32,
     dst_h: u32,
 ) -> Vec<u8> {
-    let mut dst = vec![0u8; (dst_w * dst_h * 4) as usize];
+    let mut dst = Vec::new();
+    scale_nearest_into(src, src_w, src_h, dst_w, dst_h, &mut dst);
+    dst
+}
+
+/// Nearest-neighbor scaling into `dst`, which is resized to fit
+fn scale_nearest_into(
+    src: &[u8],
+    src_w: u32,
+    src_h: u32,
+    dst_w: u32,
+    dst_h: u32,
+    dst: &mut Vec<u8>,
+) {
+    dst.resize((dst_w * dst_h * 4) as usize, 0);
 
     for dst_y in 0..dst_h {
         let src_y = (dst_y * src_h / dst_h).min(src_h - 1);
@@ -363,27 +831,428 @@ pub fn scale_nearest_neighbor(
             let dst_idx = ((dst_y * dst_w + dst_x) * 4) as usize;
 
             if src_idx + 3 < src.len() && dst_idx + 3 < dst.len() {
-                dst[dst_idx] = src[src_idx];
-                dst[dst_idx + 1] = src[src_idx + 1];
-                dst[dst_idx + 2] = src[src_idx + 2];
-                dst[dst_idx + 3] = src[src_idx + 3];
+                dst[dst_idx..dst_idx + 4].copy_from_slice(&src[src_idx..src_idx + 4]);
+            } else if dst_idx + 3 < dst.len() {
+                dst[dst_idx..dst_idx + 4].fill(0);
             }
         }
     }
+}
+
+/// How a frame is brought to the framebuffer's size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalePath {
+    /// Already the right size: convert only
+    ConvertOnly,
+    /// Scale the UYVY source down, then convert fewer pixels
+    ScaleThenConvert,
+    /// Convert at the source size, then scale the BGRA (upscaling keeps
+    /// every source pixel's own chroma)
+    ConvertThenScale,
+}
+
+/// Pick the cheaper order for a `src_w`x`src_h` frame shown at
+/// `dst_w`x`dst_h`. Only UYVY can be scaled before conversion, and only to
+/// an even width (whole macropixels).
+pub fn scale_path(uyvy: bool, src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> ScalePath {
+    if (src_w, src_h) == (dst_w, dst_h) {
+        ScalePath::ConvertOnly
+    } else if uyvy
+        && dst_w.is_multiple_of(2)
+        && (dst_w as u64 * dst_h as u64) < (src_w as u64 * src_h as u64)
+    {
+        ScalePath::ScaleThenConvert
+    } else {
+        ScalePath::ConvertThenScale
+    }
+}
+
+/// A UYVY macropixel of limited-range black
+const UYVY_BLACK: [u8; 4] = [128, 16, 128, 16];
 
+/// Nearest-neighbor scaling of tightly packed UYVY in whole macropixels
+/// (two pixels sharing one chroma pair), so chroma stays paired with its
+/// luma. The output width is `dst_w` rounded down to even.
+pub fn scale_uyvy_nearest(src: &[u8], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Vec<u8> {
+    let mut dst = Vec::new();
+    scale_uyvy_into(src, src_w, src_h, 0, dst_w, dst_h, &mut dst);
     dst
 }
 
+/// Macropixel scaling into `dst`, reading source rows at `stride` bytes
+/// (0 for tightly packed); pixels missing from `src` are black
+fn scale_uyvy_into(
+    src: &[u8],
+    src_w: u32,
+    src_h: u32,
+    stride: u32,
+    dst_w: u32,
+    dst_h: u32,
+    dst: &mut Vec<u8>,
+) {
+    let src_pairs = src_w as usize / 2;
+    let dst_pairs = dst_w as usize / 2;
+    let (src_h, dst_h) = (src_h as usize, dst_h as usize);
+    let stride = (stride as usize).max(src_pairs * 4);
+    dst.resize(dst_pairs * 4 * dst_h, 0);
+    if src_pairs == 0 || src_h == 0 || dst_pairs == 0 {
+        for out in dst.chunks_exact_mut(4) {
+            out.copy_from_slice(&UYVY_BLACK);
+        }
+        return;
+    }
+
+    for (dst_y, row) in dst.chunks_exact_mut(dst_pairs * 4).enumerate() {
+        let src_y = (dst_y * src_h / dst_h).min(src_h - 1);
+        for (pair, out) in row.chunks_exact_mut(4).enumerate() {
+            let src_pair = (pair * src_pairs / dst_pairs).min(src_pairs - 1);
+            let idx = src_y * stride + src_pair * 4;
+            match src.get(idx..idx + 4) {
+                Some(macropixel) => out.copy_from_slice(macropixel),
+                None => out.copy_from_slice(&UYVY_BLACK),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_interrupted_write_is_not_a_lost_monitor() {
+        let eintr = DisplayError::Write(io::Error::from_raw_os_error(libc::EINTR));
+        assert!(eintr.is_interrupted());
+        let gone = DisplayError::Write(io::Error::from_raw_os_error(libc::ENODEV));
+        assert!(!gone.is_interrupted());
+        assert!(!DisplayError::DeviceNotFound {
+            path: "/dev/fb0".into()
+        }
+        .is_interrupted());
+    }
+
+    #[test]
+    fn test_open_error_variants() {
+        assert!(matches!(
+            FramebufferDisplay::open("/dev/does-not-exist/fb9"),
+            Err(DisplayError::DeviceNotFound { .. })
+        ));
+        // A regular file opens but is no framebuffer
+        let file = tempfile::NamedTempFile::new().unwrap();
+        assert!(matches!(
+            FramebufferDisplay::open(file.path().to_str().unwrap()),
+            Err(DisplayError::ScreenInfo {
+                what: "variable",
+                ..
+            })
+        ));
+    }
+
+    const BGRA: u32 = u32::from_le_bytes(*b"BGRA");
+
+    fn mode(width: u32, height: u32, line_length: u32) -> FbMode {
+        FbMode {
+            width,
+            height,
+            bits_per_pixel: 32,
+            line_length,
+        }
+    }
+
+    fn written(display: &FramebufferDisplay) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut file = display.file.try_clone().unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        std::io::Read::read_to_end(&mut file, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_mode_refresh_resizes_scratch_buffers() {
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(4, 2, 16));
+        assert!(display.padding.is_empty());
+        let frame: Vec<u8> = (0..32).collect();
+        display.display_frame(&frame, 4, 2, 0, BGRA).unwrap();
+        assert_eq!(written(&display), frame);
+
+        // Replugged at a smaller mode with padded lines
+        assert!(display.apply_mode(mode(2, 2, 12)));
+        assert!(!display.apply_mode(mode(2, 2, 12)));
+        assert_eq!(display.dimensions(), (2, 2));
+        assert_eq!(display.padding, [0; 4]);
+        display.file.set_len(0).unwrap();
+        display.display_frame(&frame, 4, 2, 0, BGRA).unwrap();
+        // The 4x2 frame is scaled to 2x2, each line followed by padding
+        let mut expected = Vec::new();
+        for row in [
+            &frame[0..4],
+            &frame[8..12],
+            &[0; 4],
+            &frame[16..20],
+            &frame[24..28],
+            &[0; 4],
+        ] {
+            expected.extend_from_slice(row);
+        }
+        assert_eq!(written(&display), expected);
+        assert_eq!(display.scaled.len(), 2 * 2 * 4);
+
+        // Back to a mode matching the source: no scaling, no padding
+        assert!(display.apply_mode(mode(4, 2, 16)));
+        assert!(display.padding.is_empty());
+        assert!(display.scaled.is_empty());
+        display.file.set_len(0).unwrap();
+        display.display_frame(&frame, 4, 2, 0, BGRA).unwrap();
+        assert_eq!(written(&display), frame);
+    }
+
+    #[test]
+    fn test_aspect_letterboxes_the_picture() {
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(8, 4, 32));
+        let white = vec![255u8; 4 * 4 * 4];
+
+        // A square picture is pillarboxed: 4 columns of white, centred
+        display
+            .display_frame_with_aspect(&white, 4, 4, 0, BGRA, 1.0)
+            .unwrap();
+        let out = written(&display);
+        let pixel = |out: &[u8], x: usize, y: usize| out[(y * 8 + x) * 4..][..4].to_vec();
+        for y in 0..4 {
+            for x in 0..8 {
+                let expected = if (2..6).contains(&x) {
+                    [255; 4]
+                } else {
+                    [0, 0, 0, 255]
+                };
+                assert_eq!(pixel(&out, x, y), expected, "({}, {})", x, y);
+            }
+        }
+
+        // Anamorphic 4x4 shown at 2:1 fills the 2:1 screen
+        display.file.set_len(0).unwrap();
+        display
+            .display_frame_with_aspect(&white, 4, 4, 0, BGRA, 2.0)
+            .unwrap();
+        assert_eq!(written(&display), [255u8; 8 * 4 * 4]);
+
+        // Wider than the screen: bars top and bottom
+        display.file.set_len(0).unwrap();
+        display
+            .display_frame_with_aspect(&white, 4, 4, 0, BGRA, 4.0)
+            .unwrap();
+        let out = written(&display);
+        assert_eq!(pixel(&out, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&out, 0, 1), [255; 4]);
+        assert_eq!(pixel(&out, 7, 2), [255; 4]);
+        assert_eq!(pixel(&out, 7, 3), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_unsupported_fourcc_writes_nothing() {
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(4, 2, 16));
+        let y416 = u32::from_le_bytes(*b"Y416");
+        assert!(!display.display_frame(&[0xAB; 64], 4, 2, 0, y416).unwrap());
+        assert!(written(&display).is_empty());
+        assert!(display.display_frame(&[0; 32], 4, 2, 0, BGRA).unwrap());
+        assert_eq!(written(&display), [0; 32]);
+    }
+
+    #[test]
+    fn test_p216_matches_uyvy() {
+        // Padded rows, with detail below the top byte of every sample
+        let (width, height, stride) = (8u32, 4u32, 24usize);
+        let chroma_plane = stride * height as usize;
+        let mut p216 = vec![0u8; chroma_plane * 2];
+        let mut uyvy = Vec::new();
+        for row in 0..height as usize {
+            for pair in 0..width as usize / 2 {
+                let y0 = (16 + row * 50 + pair * 20) as u8;
+                let (y1, cb, cr) = (y0 + 10, (64 + pair * 30) as u8, (200 - row * 30) as u8);
+                uyvy.extend_from_slice(&[cb, y0, cr, y1]);
+                let luma = row * stride + pair * 4;
+                let chroma = chroma_plane + row * stride + pair * 4;
+                for (at, value) in [(luma, y0), (luma + 2, y1), (chroma, cb), (chroma + 2, cr)] {
+                    let sample = (value as u16) << 8 | 0xC0;
+                    p216[at..at + 2].copy_from_slice(&sample.to_le_bytes());
+                }
+            }
+        }
+        assert_eq!(
+            convert_p216_to_uyvy(&p216, width, height, stride as u32),
+            uyvy
+        );
+
+        let expected = convert_uyvy_to_bgra(&uyvy, width, height, ColorRange::Limited);
+        for fourcc in ["P216", "PA16"] {
+            let bgra = frame_to_bgra(&p216, width, height, stride as u32, fourcc).unwrap();
+            assert_eq!(bgra.len(), expected.len());
+            for (got, want) in bgra.iter().zip(&expected) {
+                assert!(got.abs_diff(*want) <= 1, "{} {} vs {}", fourcc, got, want);
+            }
+        }
+        // Other high bit depth formats are left to the standby screen
+        assert_eq!(
+            frame_to_bgra(&p216, width, height, stride as u32, "P210"),
+            None
+        );
+
+        // A truncated frame is black where samples are missing
+        let short = convert_p216_to_uyvy(&p216[..chroma_plane], width, height, stride as u32);
+        assert_eq!(short[..4], [128, uyvy[1], 128, uyvy[3]]);
+    }
+
+    #[test]
+    fn test_unusable_mode_skips_writes() {
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(0, 0, 0));
+        display.display_frame(&[0; 32], 4, 2, 0, BGRA).unwrap();
+        assert!(written(&display).is_empty());
+
+        // Lines shorter than the pixels can't be written either
+        display.apply_mode(mode(4, 2, 8));
+        display.display_frame(&[0; 32], 4, 2, 0, BGRA).unwrap();
+        assert!(written(&display).is_empty());
+    }
+
+    #[test]
+    fn test_zebra_only_on_uyvy_frames() {
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(4, 4, 16));
+        display.set_exposure(ExposureSettings {
+            zebra: true,
+            ..Default::default()
+        });
+        assert!(display.exposure().zebra);
+
+        // Full white UYVY: the stripe crosses the top-left corner
+        let white = [128, 235, 128, 235].repeat(2 * 4);
+        display
+            .display_frame(&white, 4, 4, 0, u32::from_le_bytes(*b"UYVY"))
+            .unwrap();
+        let out = written(&display);
+        assert_eq!(out[0..4], [0, 0, 0, 255]);
+        let last = (4 * 4 - 1) * 4;
+        assert!(out[last..last + 3].iter().all(|&c| c > 250));
+
+        // BGRA frames pass through untouched
+        display.file.set_len(0).unwrap();
+        let frame = vec![255u8; 4 * 4 * 4];
+        display.display_frame(&frame, 4, 4, 0, BGRA).unwrap();
+        assert_eq!(written(&display), frame);
+    }
+
+    #[test]
+    fn test_call_border_frames_the_picture() {
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(64, 64, 256));
+        display.set_call_flash(true);
+        let frame = vec![0u8; 64 * 64 * 4];
+        display.display_frame(&frame, 64, 64, 0, BGRA).unwrap();
+        let out = written(&display);
+        let pixel = |x: usize, y: usize| &out[(y * 64 + x) * 4..(y * 64 + x) * 4 + 4];
+        for (x, y) in [
+            (0, 0),
+            (63, 0),
+            (0, 63),
+            (63, 63),
+            (23, 32),
+            (40, 32),
+            (32, 23),
+        ] {
+            assert_eq!(pixel(x, y), CALL_COLOR, "({}, {})", x, y);
+        }
+        for (x, y) in [(24, 24), (32, 32), (39, 39)] {
+            assert_eq!(pixel(x, y), [0, 0, 0, 0], "({}, {})", x, y);
+        }
+
+        // Off again: the frame is written as it came
+        display.set_call_flash(false);
+        display.display_frame(&frame, 64, 64, 0, BGRA).unwrap();
+        assert_eq!(written(&display), frame);
+    }
+
+    #[test]
+    fn test_program_meter_on_video() {
+        use crate::audio_meter::ProgramMeter;
+
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(128, 128, 512));
+        let frame = vec![0u8; 128 * 128 * 4];
+        let now = std::time::Instant::now();
+        let mut meter = ProgramMeter::new();
+
+        // No audio metered yet: the frame is written as it came
+        display.set_program_levels(&meter.levels(now));
+        display.display_frame(&frame, 128, 128, 0, BGRA).unwrap();
+        assert_eq!(written(&display), frame);
+
+        // A full-scale stereo frame lights the bars in the bottom-right corner
+        meter.feed(&[1.0, -1.0].repeat(960), 2, 960, 960, 48_000, now);
+        display.set_program_levels(&meter.levels(now));
+        display.file.set_len(0).unwrap();
+        display.display_frame(&frame, 128, 128, 0, BGRA).unwrap();
+        let out = written(&display);
+        let pixel = |x: usize, y: usize| &out[(y * 128 + x) * 4..(y * 128 + x) * 4 + 4];
+        for x in [85, 100] {
+            assert!(pixel(x, 100)[1] > 128, "bar at x {}", x);
+        }
+        assert_eq!(pixel(8, 100), [0, 0, 0, 0]);
+        assert_eq!(pixel(100, 8), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_call_border_on_a_tiny_mode() {
+        let mut bgra = vec![0u8; 4 * 2 * 4];
+        draw_call_border(&mut bgra, 4, 2);
+        assert!(bgra.chunks_exact(4).all(|pixel| pixel == CALL_COLOR));
+    }
+
+    #[test]
+    fn test_standby_written_only_when_changed() {
+        use crate::standby::{StandbySettings, DRIFT_INTERVAL};
+
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(64, 32, 260));
+        let mut standby = StandbyScreen::new(StandbySettings::default());
+        let start = std::time::Instant::now();
+        display.show_standby(&mut standby, start, "12:34").unwrap();
+        let out = written(&display);
+        assert_eq!(out.len(), 260 * 32);
+        // Padding after each line stays zero
+        assert!(out[256..260].iter().all(|&b| b == 0));
+
+        display.file.set_len(0).unwrap();
+        display.show_standby(&mut standby, start, "12:34").unwrap();
+        assert!(written(&display).is_empty());
+        display
+            .show_standby(&mut standby, start + DRIFT_INTERVAL, "12:34")
+            .unwrap();
+        assert_eq!(written(&display).len(), 260 * 32);
+    }
+
+    #[test]
+    fn test_refresh_mode_of_a_non_framebuffer() {
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(4, 2, 16));
+        assert!(matches!(
+            display.refresh_mode(),
+            Err(DisplayError::ScreenInfo {
+                what: "variable",
+                ..
+            })
+        ));
+        assert_eq!(display.mode(), mode(4, 2, 16));
+    }
+
     #[test]
     fn test_uyvy_to_bgra_black() {
         // Black in UYVY: Y=16 (video black), U=128, V=128
         // UYVY format: U Y0 V Y1
         let uyvy = vec![128, 16, 128, 16]; // 2 black pixels
-        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1);
+        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Limited);
 
         // Should produce near-black pixels
         assert_eq!(bgra.len(), 8); // 2 pixels * 4 bytes
@@ -398,7 +1267,7 @@ mod tests {
     fn test_uyvy_to_bgra_white() {
         // White in UYVY: Y=235 (video white), U=128, V=128
         let uyvy = vec![128, 235, 128, 235]; // 2 white pixels
-        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1);
+        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Limited);
 
         assert_eq!(bgra.len(), 8);
         // First pixel should be near-white
@@ -412,7 +1281,7 @@ mod tests {
     fn test_uyvy_to_bgra_red() {
         // Red in UYVY: Y=81, U=90, V=240 (approximate)
         let uyvy = vec![90, 81, 240, 81];
-        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1);
+        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Limited);
 
         assert_eq!(bgra.len(), 8);
         // Red channel should be high, blue/green low
@@ -424,7 +1293,7 @@ mod tests {
     fn test_uyvy_to_bgra_green() {
         // Green in UYVY: Y=145, U=54, V=34 (approximate)
         let uyvy = vec![54, 145, 34, 145];
-        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1);
+        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Limited);
 
         assert_eq!(bgra.len(), 8);
         // Green channel should be highest
@@ -436,7 +1305,7 @@ mod tests {
     fn test_uyvy_to_bgra_blue() {
         // Blue in UYVY: Y=41, U=240, V=110 (approximate)
         let uyvy = vec![240, 41, 110, 41];
-        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1);
+        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Limited);
 
         assert_eq!(bgra.len(), 8);
         // Blue channel should be highest
@@ -444,11 +1313,28 @@ mod tests {
         assert!(bgra[0] > bgra[2], "Blue > Red for blue pixel");
     }
 
+    #[test]
+    fn test_uyvy_to_bgra_range() {
+        // Limited range expands 16-235 to 0-255; full range takes Y as is
+        let uyvy = vec![128, 16, 128, 235];
+        let limited = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Limited);
+        let full = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Full);
+        assert_eq!(limited, [0, 0, 0, 255, 255, 255, 255, 255]);
+        assert_eq!(full, [16, 16, 16, 255, 235, 235, 235, 255]);
+
+        // Mid grey with a red cast
+        let uyvy = vec![128, 128, 192, 128];
+        let limited = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Limited);
+        let full = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Full);
+        assert_eq!(&limited[..4], [130, 78, 233, 255]);
+        assert_eq!(&full[..4], [128, 83, 217, 255]);
+    }
+
     #[test]
     fn test_uyvy_to_bgra_output_size() {
         // 4x2 image in UYVY = 4*2*2 = 16 bytes
         let uyvy = vec![128u8; 16];
-        let bgra = convert_uyvy_to_bgra(&uyvy, 4, 2);
+        let bgra = convert_uyvy_to_bgra(&uyvy, 4, 2, ColorRange::Limited);
 
         // 4x2 in BGRA = 4*2*4 = 32 bytes
         assert_eq!(bgra.len(), 32);
@@ -490,6 +1376,110 @@ mod tests {
         assert_eq!(bgra[7], 255); // A
     }
 
+    #[test]
+    fn test_packed_formats_to_bgra() {
+        let px = [10, 20, 30, 40];
+        let convert = |format| convert_packed_to_bgra(&px, 1, 1, 0, format);
+        assert_eq!(convert(PackedFormat::Bgra), [10, 20, 30, 40]);
+        assert_eq!(convert(PackedFormat::Bgrx), [10, 20, 30, 255]);
+        assert_eq!(convert(PackedFormat::Rgba), [30, 20, 10, 40]);
+        assert_eq!(convert(PackedFormat::Rgbx), [30, 20, 10, 255]);
+        assert_eq!(
+            convert_packed_to_bgra(&px, 1, 1, 0, PackedFormat::Rgba),
+            convert_rgba_to_bgra(&px)
+        );
+    }
+
+    #[test]
+    fn test_packed_fourccs() {
+        assert_eq!(PackedFormat::from_fourcc("RGBX"), Some(PackedFormat::Rgbx));
+        assert_eq!(PackedFormat::from_fourcc("BGRX"), Some(PackedFormat::Bgrx));
+        assert_eq!(PackedFormat::from_fourcc("UYVY"), None);
+        assert_eq!(PackedFormat::from_fourcc("NV12"), None);
+    }
+
+    #[test]
+    fn test_packed_to_bgra_strided() {
+        // 2x2 RGBX with 4 bytes of padding per row (stride 12)
+        let data = [
+            1, 2, 3, 0, 4, 5, 6, 0, 99, 99, 99, 99, // row 0 + padding
+            7, 8, 9, 0, 10, 11, 12, 0, 99, 99, 99, 99, // row 1 + padding
+        ];
+        let bgra = convert_packed_to_bgra(&data, 2, 2, 12, PackedFormat::Rgbx);
+        assert_eq!(
+            bgra,
+            [3, 2, 1, 255, 6, 5, 4, 255, 9, 8, 7, 255, 12, 11, 10, 255]
+        );
+
+        // A stride below width*4 is treated as tightly packed
+        let tight = convert_packed_to_bgra(&data[..8], 2, 1, 4, PackedFormat::Bgra);
+        assert_eq!(tight, data[..8]);
+
+        // Short last row stops the conversion instead of panicking
+        let short = convert_packed_to_bgra(&data[..18], 2, 2, 12, PackedFormat::Bgra);
+        assert_eq!(short.len(), 8);
+    }
+
+    #[test]
+    fn test_bgra_frames_are_copied_not_converted() {
+        // BGRX whose X bytes aren't 255: a conversion would rewrite them
+        let bgrx = [
+            1, 2, 3, 7, 4, 5, 6, 8, 99, 99, 9, 10, 11, 12, 13, 14, 15, 16, 99, 99,
+        ];
+        let copied = frame_to_bgra(&bgrx, 2, 2, 10, "BGRX").unwrap();
+        assert_eq!(
+            copied,
+            [1, 2, 3, 7, 4, 5, 6, 8, 9, 10, 11, 12, 13, 14, 15, 16]
+        );
+        assert_ne!(
+            copied,
+            convert_packed_to_bgra(&bgrx, 2, 2, 10, PackedFormat::Bgrx)
+        );
+
+        // Tightly packed BGRA comes out byte for byte
+        let bgra: Vec<u8> = (0..32).collect();
+        assert_eq!(frame_to_bgra(&bgra, 4, 2, 0, "BGRA").unwrap(), bgra);
+
+        // Everything else still goes through a conversion
+        let rgba = frame_to_bgra(&[1, 2, 3, 4], 1, 1, 0, "RGBA").unwrap();
+        assert_eq!(rgba, [3, 2, 1, 4]);
+        assert_eq!(
+            frame_to_bgra(&[128, 16, 128, 16], 2, 1, 0, "UYVY").unwrap()[3],
+            255
+        );
+        assert_eq!(frame_to_bgra(&[0; 4], 1, 1, 0, "NV12"), None);
+    }
+
+    #[test]
+    fn test_uyvy_to_bgra_padded_rows() {
+        // 4x3 UYVY ramp (8 bytes per row) padded to a 12 byte stride; each
+        // row is a single grey level, so shear would mix levels within a row
+        let levels = [16u8, 128, 235];
+        let mut padded = Vec::new();
+        let mut tight = Vec::new();
+        for &luma in &levels {
+            let row = [128, luma, 128, luma, 128, luma, 128, luma];
+            padded.extend_from_slice(&row);
+            padded.extend_from_slice(&[0xAA; 4]);
+            tight.extend_from_slice(&row);
+        }
+
+        let bgra = convert_uyvy_to_bgra_strided(&padded, 4, 3, 12, ColorRange::Limited);
+        assert_eq!(
+            bgra,
+            convert_uyvy_to_bgra(&tight, 4, 3, ColorRange::Limited)
+        );
+        for (row, expected) in bgra.chunks(16).zip([0u8, 130, 255]) {
+            assert!(row
+                .chunks(4)
+                .all(|px| px == [expected, expected, expected, 255]));
+        }
+
+        // Read without the stride the padding shifts rows 1 and 2
+        let sheared = convert_uyvy_to_bgra(&padded, 4, 3, ColorRange::Limited);
+        assert_ne!(sheared, bgra);
+    }
+
     #[test]
     fn test_scale_nearest_passthrough() {
         // Same size should be identity (but creates new buffer)
@@ -557,10 +1547,145 @@ mod tests {
         }
     }
 
+    /// Tightly packed UYVY whose macropixel `i` (row-major) is [i, i, i, i]
+    fn numbered_uyvy(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height / 2).flat_map(|i| [i as u8; 4]).collect()
+    }
+
+    #[test]
+    fn test_scale_uyvy_downscale_keeps_macropixels() {
+        // 8x4 (4 macropixels per row) → 4x2 (2 per row)
+        let src = numbered_uyvy(8, 4);
+        let dst = scale_uyvy_nearest(&src, 8, 4, 4, 2);
+        assert_eq!(dst.len(), 4 * 2 * 2);
+        // Rows 0 and 2, macropixels 0 and 2 of each
+        let picked: Vec<u8> = dst.chunks_exact(4).map(|m| m[0]).collect();
+        assert_eq!(picked, [0, 2, 8, 10]);
+        // Each output macropixel is a whole source one
+        assert!(dst.chunks_exact(4).all(|m| m.iter().all(|&b| b == m[0])));
+    }
+
+    #[test]
+    fn test_scale_uyvy_upscale_repeats_macropixels() {
+        let src = numbered_uyvy(4, 1);
+        let dst = scale_uyvy_nearest(&src, 4, 1, 8, 2);
+        let picked: Vec<u8> = dst.chunks_exact(4).map(|m| m[0]).collect();
+        assert_eq!(picked, [0, 0, 1, 1, 0, 0, 1, 1]);
+
+        // Odd widths round down to whole macropixels
+        assert_eq!(scale_uyvy_nearest(&src, 4, 1, 5, 1).len(), 2 * 4);
+    }
+
+    #[test]
+    fn test_scale_uyvy_strided_and_short_input() {
+        // 4x2 with 4 bytes of padding per row
+        let mut padded = Vec::new();
+        for row in numbered_uyvy(4, 2).chunks_exact(8) {
+            padded.extend_from_slice(row);
+            padded.extend_from_slice(&[99; 4]);
+        }
+        let mut dst = Vec::new();
+        scale_uyvy_into(&padded, 4, 2, 12, 2, 2, &mut dst);
+        assert_eq!(dst, [0, 0, 0, 0, 2, 2, 2, 2]);
+
+        // Rows missing from the source are black
+        scale_uyvy_into(&padded[..12], 4, 2, 12, 2, 2, &mut dst);
+        assert_eq!(dst, [0, 0, 0, 0, 128, 16, 128, 16]);
+    }
+
+    #[test]
+    fn test_scale_path() {
+        assert_eq!(
+            scale_path(true, 1920, 1080, 1920, 1080),
+            ScalePath::ConvertOnly
+        );
+        assert_eq!(
+            scale_path(true, 1920, 1080, 1280, 720),
+            ScalePath::ScaleThenConvert
+        );
+        // Upscaling converts first for quality
+        assert_eq!(
+            scale_path(true, 1280, 720, 1920, 1080),
+            ScalePath::ConvertThenScale
+        );
+        // Only UYVY, and only to whole macropixels
+        assert_eq!(
+            scale_path(false, 1920, 1080, 1280, 720),
+            ScalePath::ConvertThenScale
+        );
+        assert_eq!(
+            scale_path(true, 1920, 1080, 1279, 720),
+            ScalePath::ConvertThenScale
+        );
+        // 4K sources on HD monitors scale first
+        assert_eq!(
+            scale_path(true, 3840, 2160, 1920, 1080),
+            ScalePath::ScaleThenConvert
+        );
+        assert_eq!(
+            scale_path(true, 3840, 2160, 1280, 720),
+            ScalePath::ScaleThenConvert
+        );
+    }
+
+    #[test]
+    fn test_scale_uyvy_4k_padded_rows() {
+        // 4K UYVY rows are 7680 bytes; the driver pads these to 7936
+        let (width, height, stride) = (3840u32, 2160u32, 7936usize);
+        let mut src = vec![0u8; stride * height as usize];
+        for (row, line) in src.chunks_exact_mut(stride).enumerate() {
+            for (pair, macropixel) in line[..7680].chunks_exact_mut(4).enumerate() {
+                macropixel.fill((row + pair) as u8);
+            }
+            line[7680..].fill(0xEE);
+        }
+        let mut dst = Vec::new();
+        scale_uyvy_into(&src, width, height, stride as u32, 1920, 1080, &mut dst);
+        assert_eq!(dst.len(), 1920 * 1080 * 2);
+        // Every other row and macropixel, never the padding
+        for (row, line) in dst.chunks_exact(1920 * 2).enumerate() {
+            for (pair, macropixel) in line.chunks_exact(4).enumerate() {
+                let expected = (row * 2 + pair * 2) as u8;
+                assert!(
+                    macropixel.iter().all(|&b| b == expected),
+                    "{} {}",
+                    row,
+                    pair
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_uyvy_downscale_converts_at_mode_size() {
+        const UYVY: u32 = u32::from_le_bytes(*b"UYVY");
+        let mut display =
+            FramebufferDisplay::with_mode(tempfile::tempfile().unwrap(), mode(4, 2, 16));
+        let frame: Vec<u8> = (0..8 * 4 * 2).map(|i| (i * 7 % 256) as u8).collect();
+        display.display_frame(&frame, 8, 4, 0, UYVY).unwrap();
+
+        let expected = convert_uyvy_to_bgra(
+            &scale_uyvy_nearest(&frame, 8, 4, 4, 2),
+            4,
+            2,
+            ColorRange::Limited,
+        );
+        assert_eq!(written(&display), expected);
+        assert_eq!(display.scaled_uyvy.len(), 4 * 2 * 2);
+        assert!(display.scaled.is_empty());
+
+        // Upscaled frames are converted first
+        display.file.set_len(0).unwrap();
+        display
+            .display_frame(&frame[..2 * 2 * 2], 2, 2, 0, UYVY)
+            .unwrap();
+        assert_eq!(display.scaled.len(), 4 * 2 * 4);
+    }
+
     #[test]
     fn test_uyvy_to_bgra_empty_input() {
         let uyvy: Vec<u8> = vec![];
-        let bgra = convert_uyvy_to_bgra(&uyvy, 0, 0);
+        let bgra = convert_uyvy_to_bgra(&uyvy, 0, 0, ColorRange::Limited);
         assert!(bgra.is_empty());
     }
 
@@ -577,7 +1702,7 @@ mod tests {
         let width = 1920u32;
         let height = 1080u32;
         let uyvy = vec![128u8; (width * height * 2) as usize];
-        let bgra = convert_uyvy_to_bgra(&uyvy, width, height);
+        let bgra = convert_uyvy_to_bgra(&uyvy, width, height, ColorRange::Limited);
 
         assert_eq!(bgra.len(), (width * height * 4) as usize);
     }
@@ -587,7 +1712,7 @@ mod tests {
         // Test that extreme YUV values clamp properly and don't overflow
         // Max Y, extreme U/V that would cause overflow without clamping
         let uyvy = vec![255, 255, 255, 255];
-        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1);
+        let bgra = convert_uyvy_to_bgra(&uyvy, 2, 1, ColorRange::Limited);
 
         // Should produce 2 pixels (8 bytes) without panicking
         assert_eq!(bgra.len(), 8);
@@ -0,0 +1,334 @@
+//! Exposure aids for shading cameras from the HDMI monitor
+//!
+//! A luma histogram graph and zebra stripes over near-white areas, drawn
+//! on the displayed frame. Both look at every [`GRID_STEP`]th pixel of
+//! every [`GRID_STEP`]th line of the received UYVY frame, a sixteenth of
+//! the pixels, so they stay cheap on the low-priority display thread.
+
+use crate::compositor::{blend_over, Surface};
+
+/// Sampling grid spacing in pixels, horizontally and vertically
+pub const GRID_STEP: usize = 4;
+
+/// Default zebra threshold in percent of the video range
+pub const DEFAULT_ZEBRA_PERCENT: u8 = 95;
+
+/// Highest zebra threshold: 109% is the top of the superwhite range
+pub const MAX_ZEBRA_PERCENT: u8 = 109;
+
+/// Histogram graph size in framebuffer pixels, one column per luma code
+const GRAPH_WIDTH: u32 = 256;
+const GRAPH_HEIGHT: u32 = 96;
+/// Gap between the graph and the bottom-left corner of the screen
+const GRAPH_MARGIN: u32 = 16;
+/// Straight BGRA colors of the graph
+const GRAPH_BACKGROUND: [u8; 4] = [0, 0, 0, 160];
+const GRAPH_BAR: [u8; 4] = [230, 230, 230, 255];
+
+/// Zebra stripes are this many pixels wide, repeating every twice that
+const STRIPE_WIDTH: u32 = 4;
+const STRIPE: [u8; 4] = [0, 0, 0, 255];
+
+/// Luma code of `percent` of the limited (video) range: 0% is 16, 100% is 235
+pub fn zebra_level(percent: u8) -> u8 {
+    let percent = percent.min(MAX_ZEBRA_PERCENT) as u32;
+    (16 + (percent * 219 + 50) / 100) as u8
+}
+
+/// Bytes per UYVY row: `stride`, or tightly packed when that is 0
+fn row_stride(width: u32, stride: u32) -> usize {
+    (stride as usize).max(width as usize * 2)
+}
+
+/// Counts of each luma code over the sampling grid of a UYVY frame.
+/// `stride` is the row stride in bytes, 0 for tightly packed.
+pub fn luma_histogram(uyvy: &[u8], width: u32, height: u32, stride: u32) -> [u32; 256] {
+    let mut histogram = [0u32; 256];
+    let stride = row_stride(width, stride);
+    for y in (0..height as usize).step_by(GRID_STEP) {
+        let Some(row) = uyvy.get(y * stride..) else {
+            break;
+        };
+        for x in (0..width as usize).step_by(GRID_STEP) {
+            if let Some(&luma) = row.get(x * 2 + 1) {
+                histogram[luma as usize] += 1;
+            }
+        }
+    }
+    histogram
+}
+
+/// Paint diagonal zebra stripes on `bgra`, the conversion of the UYVY frame
+/// `uyvy`, where the source luma is above `threshold`. Each grid sample
+/// decides for the [`GRID_STEP`]-square cell it starts. `phase` shifts the
+/// stripes; advancing it every frame makes them crawl. Returns the number
+/// of cells marked.
+pub fn apply_zebra(
+    bgra: &mut [u8],
+    uyvy: &[u8],
+    width: u32,
+    height: u32,
+    stride: u32,
+    threshold: u8,
+    phase: u32,
+) -> usize {
+    let stride = row_stride(width, stride);
+    let mut marked = 0;
+    for cell_y in (0..height as usize).step_by(GRID_STEP) {
+        for cell_x in (0..width as usize).step_by(GRID_STEP) {
+            let hot = uyvy
+                .get(cell_y * stride + cell_x * 2 + 1)
+                .is_some_and(|&luma| luma > threshold);
+            if !hot {
+                continue;
+            }
+            marked += 1;
+            for y in cell_y..(cell_y + GRID_STEP).min(height as usize) {
+                for x in cell_x..(cell_x + GRID_STEP).min(width as usize) {
+                    if (x as u32 + y as u32 + phase) % (STRIPE_WIDTH * 2) >= STRIPE_WIDTH {
+                        continue;
+                    }
+                    let i = (y * width as usize + x) * 4;
+                    if let Some(pixel) = bgra.get_mut(i..i + 4) {
+                        pixel.copy_from_slice(&STRIPE);
+                    }
+                }
+            }
+        }
+    }
+    marked
+}
+
+/// Draw `histogram` as bars scaled to its tallest bin on a translucent
+/// background
+fn render_graph(histogram: &[u32; 256], graph: &mut Surface) {
+    graph.fill(GRAPH_BACKGROUND);
+    let peak = histogram.iter().copied().max().unwrap_or(0).max(1) as u64;
+    let (width, height) = (graph.width() as usize, graph.height() as usize);
+    let data = graph.data_mut();
+    for (x, &count) in histogram.iter().enumerate().take(width) {
+        let bar = (count as u64 * height as u64).div_ceil(peak) as usize;
+        for y in height - bar..height {
+            let i = (y * width + x) * 4;
+            data[i..i + 4].copy_from_slice(&GRAPH_BAR);
+        }
+    }
+}
+
+/// Which exposure aids are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExposureSettings {
+    pub histogram: bool,
+    pub zebra: bool,
+    /// Zebra threshold in percent of the video range
+    pub zebra_percent: u8,
+}
+
+impl Default for ExposureSettings {
+    fn default() -> Self {
+        Self {
+            histogram: false,
+            zebra: false,
+            zebra_percent: DEFAULT_ZEBRA_PERCENT,
+        }
+    }
+}
+
+/// Exposure aids for the display, applied to each received UYVY frame
+pub struct ExposureOverlay {
+    settings: ExposureSettings,
+    phase: u32,
+    graph: Surface,
+}
+
+impl ExposureOverlay {
+    pub fn new(settings: ExposureSettings) -> Self {
+        Self {
+            settings,
+            phase: 0,
+            graph: Surface::new(GRAPH_WIDTH, GRAPH_HEIGHT),
+        }
+    }
+
+    pub fn settings(&self) -> ExposureSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ExposureSettings) {
+        self.settings = settings;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.settings.histogram || self.settings.zebra
+    }
+
+    /// Measure the source frame and draw the zebra on its BGRA conversion
+    /// (same size, before any scaling)
+    pub fn mark_source(
+        &mut self,
+        bgra: &mut [u8],
+        uyvy: &[u8],
+        width: u32,
+        height: u32,
+        stride: u32,
+    ) {
+        if self.settings.histogram {
+            render_graph(
+                &luma_histogram(uyvy, width, height, stride),
+                &mut self.graph,
+            );
+        }
+        if self.settings.zebra {
+            let threshold = zebra_level(self.settings.zebra_percent);
+            apply_zebra(bgra, uyvy, width, height, stride, threshold, self.phase);
+            self.phase = self.phase.wrapping_add(1);
+        }
+    }
+
+    /// Draw the histogram graph in the bottom-left corner of the frame as
+    /// it goes to the screen
+    pub fn draw_output(&self, bgra: &mut [u8], width: u32, height: u32) {
+        if !self.settings.histogram {
+            return;
+        }
+        let y = height as i32 - (GRAPH_HEIGHT + GRAPH_MARGIN) as i32;
+        blend_over(
+            bgra,
+            self.graph.data(),
+            GRAPH_WIDTH,
+            GRAPH_HEIGHT,
+            width,
+            height,
+            GRAPH_MARGIN as i32,
+            y,
+            1.0,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// UYVY frame with luma `left` in the left half and `right` in the right
+    fn split_frame(width: u32, height: u32, left: u8, right: u8) -> Vec<u8> {
+        let mut frame = Vec::new();
+        for _ in 0..height {
+            for x in (0..width).step_by(2) {
+                let luma = if x < width / 2 { left } else { right };
+                frame.extend_from_slice(&[128, luma, 128, luma]);
+            }
+        }
+        frame
+    }
+
+    #[test]
+    fn test_zebra_level() {
+        assert_eq!(zebra_level(0), 16);
+        assert_eq!(zebra_level(100), 235);
+        assert_eq!(zebra_level(95), 224);
+        assert_eq!(zebra_level(109), 255);
+        assert_eq!(zebra_level(200), 255);
+    }
+
+    #[test]
+    fn test_luma_histogram_samples_the_grid() {
+        let frame = split_frame(16, 8, 16, 235);
+        let histogram = luma_histogram(&frame, 16, 8, 0);
+        // 4 columns x 2 rows of samples, half black and half white
+        assert_eq!(histogram[16], 4);
+        assert_eq!(histogram[235], 4);
+        assert_eq!(histogram.iter().sum::<u32>(), 8);
+
+        // A bright pixel between grid points isn't seen
+        let mut frame = split_frame(16, 8, 16, 16);
+        frame[16 * 2 + 3] = 255;
+        assert_eq!(luma_histogram(&frame, 16, 8, 0)[255], 0);
+    }
+
+    #[test]
+    fn test_luma_histogram_with_padded_rows() {
+        // Rows padded to 40 bytes with bytes that must not be counted
+        let packed = split_frame(16, 8, 100, 100);
+        let mut padded = Vec::new();
+        for row in packed.chunks(32) {
+            padded.extend_from_slice(row);
+            padded.extend_from_slice(&[255; 8]);
+        }
+        let histogram = luma_histogram(&padded, 16, 8, 40);
+        assert_eq!(histogram[100], 8);
+        assert_eq!(histogram[255], 0);
+
+        // Short frames are counted as far as they go
+        assert_eq!(luma_histogram(&padded[..40], 16, 8, 40)[100], 4);
+    }
+
+    #[test]
+    fn test_zebra_marks_hot_cells() {
+        let (width, height) = (8u32, 8u32);
+        let mut frame = split_frame(width, height, 100, 100);
+        // Top-left cell is at 100%, above the 95% threshold
+        frame[1] = 235;
+        let mut bgra = vec![200u8; (width * height * 4) as usize];
+
+        let marked = apply_zebra(&mut bgra, &frame, width, height, 0, zebra_level(95), 0);
+        assert_eq!(marked, 1);
+        let pixel = |bgra: &[u8], x: u32, y: u32| {
+            let i = ((y * width + x) * 4) as usize;
+            [bgra[i], bgra[i + 1], bgra[i + 2], bgra[i + 3]]
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let striped = x < 4 && y < 4 && (x + y) % 8 < 4;
+                let expected = if striped { STRIPE } else { [200; 4] };
+                assert_eq!(pixel(&bgra, x, y), expected, "{},{}", x, y);
+            }
+        }
+
+        // The phase moves the stripes
+        let mut shifted = vec![200u8; bgra.len()];
+        apply_zebra(&mut shifted, &frame, width, height, 0, zebra_level(95), 4);
+        assert_eq!(pixel(&shifted, 0, 0), [200; 4]);
+        assert_eq!(pixel(&shifted, 3, 3), STRIPE);
+
+        // At exactly the threshold nothing is marked
+        frame[1] = zebra_level(95);
+        assert_eq!(
+            apply_zebra(&mut bgra, &frame, width, height, 0, zebra_level(95), 0),
+            0
+        );
+    }
+
+    #[test]
+    fn test_histogram_graph_is_drawn_bottom_left() {
+        let (width, height) = (320u32, 180u32);
+        let frame = split_frame(width, height, 16, 235);
+        let mut bgra = vec![128u8; (width * height * 4) as usize];
+        let pixel = |bgra: &[u8], x: u32, y: u32| {
+            let i = ((y * width + x) * 4) as usize;
+            [bgra[i], bgra[i + 1], bgra[i + 2], bgra[i + 3]]
+        };
+
+        // Disabled aids leave the frame alone
+        let mut overlay = ExposureOverlay::new(ExposureSettings::default());
+        assert!(!overlay.is_active());
+        overlay.mark_source(&mut bgra, &frame, width, height, 0);
+        overlay.draw_output(&mut bgra, width, height);
+        assert!(bgra.iter().all(|&b| b == 128));
+
+        overlay.set_settings(ExposureSettings {
+            histogram: true,
+            ..Default::default()
+        });
+        overlay.mark_source(&mut bgra, &frame, width, height, 0);
+        overlay.draw_output(&mut bgra, width, height);
+        let bottom = height - GRAPH_MARGIN - 1;
+        // Full-height bars at the black and white codes, dimmed background elsewhere
+        assert_eq!(pixel(&bgra, GRAPH_MARGIN + 16, bottom - 95), GRAPH_BAR);
+        assert_eq!(pixel(&bgra, GRAPH_MARGIN + 235, bottom), GRAPH_BAR);
+        assert!(pixel(&bgra, GRAPH_MARGIN + 128, bottom)[0] < 128);
+        // Outside the graph the frame is untouched
+        assert_eq!(pixel(&bgra, GRAPH_MARGIN - 1, bottom), [128; 4]);
+        assert_eq!(pixel(&bgra, GRAPH_MARGIN, bottom - GRAPH_HEIGHT), [128; 4]);
+    }
+}
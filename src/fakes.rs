@@ -0,0 +1,516 @@
+//! In-memory frame sources and sinks
+//!
+//! Drive the pipeline without a capture device or the NDI runtime: a
+//! scripted source plays back frames, timeouts and capture errors, and a
+//! recording sink keeps every frame sent to it and fails on request. Used by
+//! the pipeline tests; embedding applications can use them the same way.
+
+use anyhow::{bail, Result};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use v4l::FourCC;
+
+use crate::capture::{FrameInfo, FrameRate};
+use crate::deinterlace::FieldOrder;
+use crate::intercom::Tally;
+use crate::ndi_supervisor::VideoSender;
+use crate::pipeline::FrameSource;
+use crate::zero_copy::HeldFrame;
+
+// =============================================================================
+// Scripted Source
+// =============================================================================
+
+/// One entry of a [`ScriptedSource`] script
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    /// Deliver this many frames back to back
+    Frames(u64),
+    /// Report no frame within the timeout
+    Timeout,
+    /// Fail the capture call
+    Error(String),
+}
+
+/// UYVY source that plays a script of frames, timeouts and errors. Frames
+/// are delivered without pacing; each frame is filled with its sequence
+/// number (mod 256). Like a capture stream, the source alternates between
+/// two buffers and holds the last frame's buffer until the next frame's
+/// callback has returned. Once the script is exhausted the source stalls.
+pub struct ScriptedSource {
+    width: u32,
+    height: u32,
+    frame_rate: FrameRate,
+    script: VecDeque<Step>,
+    buffers: [Vec<u8>; 2],
+    /// Buffer and sequence byte of the last frame
+    held: Option<(usize, u8)>,
+    sequence: u64,
+    trace: Option<Arc<SinkLog>>,
+}
+
+impl ScriptedSource {
+    pub fn new(width: u32, height: u32, frame_rate: FrameRate) -> Self {
+        Self {
+            width,
+            height,
+            frame_rate,
+            script: VecDeque::new(),
+            buffers: std::array::from_fn(|_| {
+                vec![0; width.div_ceil(2) as usize * 4 * height as usize]
+            }),
+            held: None,
+            sequence: 0,
+            trace: None,
+        }
+    }
+
+    /// Log dequeues and requeues to `log`'s [`BufferEvent`]s
+    pub fn traced(mut self, log: &Arc<SinkLog>) -> Self {
+        self.trace = Some(Arc::clone(log));
+        self
+    }
+
+    fn trace(&self, event: BufferEvent) {
+        if let Some(log) = &self.trace {
+            log.trace(event);
+        }
+    }
+
+    /// Deliver `count` frames
+    pub fn with_frames(mut self, count: u64) -> Self {
+        self.script.push_back(Step::Frames(count));
+        self
+    }
+
+    /// Time out `count` times, like a device that briefly stops delivering
+    pub fn with_timeouts(mut self, count: u32) -> Self {
+        self.script
+            .extend(std::iter::repeat_n(Step::Timeout, count as usize));
+        self
+    }
+
+    /// Fail one capture call with `message`
+    pub fn with_error(mut self, message: &str) -> Self {
+        self.script.push_back(Step::Error(message.to_string()));
+        self
+    }
+
+    /// Frames delivered so far
+    pub fn frames_delivered(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn frame_info(&self) -> FrameInfo {
+        FrameInfo::new(
+            self.width,
+            self.height,
+            FourCC::new(b"UYVY"),
+            self.width.div_ceil(2) * 4,
+        )
+        // Refilled only after the next frame's callback has returned
+        .with_held(true)
+    }
+}
+
+impl FrameSource for ScriptedSource {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn frame_rate(&self) -> FrameRate {
+        self.frame_rate
+    }
+
+    fn field_order(&self) -> FieldOrder {
+        FieldOrder::Progressive
+    }
+
+    fn process_frame(
+        &mut self,
+        timeout: Duration,
+        callback: &mut dyn FnMut(&[u8], FrameInfo),
+    ) -> Result<bool> {
+        let Some(step) = self.script.front_mut() else {
+            std::thread::sleep(timeout);
+            return Ok(false);
+        };
+        match step {
+            Step::Frames(left) => {
+                *left -= 1;
+                if *left == 0 {
+                    self.script.pop_front();
+                }
+                let index = self.held.map_or(0, |(index, _)| 1 - index);
+                let sequence = self.sequence as u8;
+                self.buffers[index].fill(sequence);
+                self.trace(BufferEvent::Dequeued(sequence));
+                callback(&self.buffers[index], self.frame_info());
+                if let Some((_, previous)) = self.held.replace((index, sequence)) {
+                    self.trace(BufferEvent::Requeued(previous));
+                }
+                self.sequence += 1;
+                Ok(true)
+            }
+            Step::Timeout => {
+                self.script.pop_front();
+                std::thread::sleep(timeout);
+                Ok(false)
+            }
+            Step::Error(message) => {
+                let message = std::mem::take(message);
+                self.script.pop_front();
+                bail!("{}", message)
+            }
+        }
+    }
+
+    fn skip_frame(&mut self) -> Result<bool> {
+        // Only frames that are already due can be skipped; the held buffer
+        // stays held
+        let Some(Step::Frames(left)) = self.script.front_mut() else {
+            return Ok(false);
+        };
+        *left -= 1;
+        if *left == 0 {
+            self.script.pop_front();
+        }
+        self.sequence += 1;
+        Ok(true)
+    }
+}
+
+// =============================================================================
+// Recording Sink
+// =============================================================================
+
+/// A frame as it reached a [`RecordingSink`]
+#[derive(Clone)]
+pub struct SentFrame {
+    pub data: Vec<u8>,
+    pub info: FrameInfo,
+}
+
+/// A step in the life of a frame buffer, by the frame's sequence byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferEvent {
+    /// A traced source dequeued the frame and passes it on
+    Dequeued(u8),
+    /// The frame reached the sink
+    Sent(u8),
+    /// A holding sink gave the lent frame back
+    Released(u8),
+    /// A traced source requeued the frame's buffer
+    Requeued(u8),
+}
+
+/// What a [`RecordingSink`] received, shared with the test that created it.
+/// It outlives sink restarts, so it also covers recreated senders.
+#[derive(Default)]
+pub struct SinkLog {
+    frames: Mutex<Vec<SentFrame>>,
+    audio_samples: AtomicU64,
+    fail_sends: AtomicU32,
+    fail_recreates: AtomicU32,
+    recreations: AtomicU32,
+    connections: AtomicU32,
+    tally: AtomicU8,
+    send_delay_us: AtomicU64,
+    frames_held: AtomicU64,
+    torn_frames: AtomicU64,
+    events: Mutex<Vec<BufferEvent>>,
+}
+
+impl SinkLog {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Fail the next `count` frame sends
+    pub fn fail_next_sends(&self, count: u32) {
+        self.fail_sends.store(count, Ordering::Relaxed);
+    }
+
+    /// Fail the next `count` recreate attempts
+    pub fn fail_next_recreates(&self, count: u32) {
+        self.fail_recreates.store(count, Ordering::Relaxed);
+    }
+
+    /// Receiver count reported by every sink of this log
+    pub fn set_connections(&self, connections: u32) {
+        self.connections.store(connections, Ordering::Relaxed);
+    }
+
+    /// Tally reported by every sink of this log
+    pub fn set_tally(&self, tally: Tally) {
+        self.tally.store(tally as u8, Ordering::Relaxed);
+    }
+
+    /// Take `delay` over every frame send, like a slow conversion
+    pub fn set_send_delay(&self, delay: Duration) {
+        self.send_delay_us
+            .store(delay.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Frames received so far
+    pub fn frame_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Copy of the received frames
+    pub fn frames(&self) -> Vec<SentFrame> {
+        self.lock().clone()
+    }
+
+    pub fn audio_samples(&self) -> u64 {
+        self.audio_samples.load(Ordering::Relaxed)
+    }
+
+    pub fn recreations(&self) -> u32 {
+        self.recreations.load(Ordering::Relaxed)
+    }
+
+    /// Frames lent by holding sinks
+    pub fn frames_held(&self) -> u64 {
+        self.frames_held.load(Ordering::Relaxed)
+    }
+
+    /// Lent frames whose buffer changed before they were given back
+    pub fn torn_frames(&self) -> u64 {
+        self.torn_frames.load(Ordering::Relaxed)
+    }
+
+    /// Buffer events so far, in order
+    pub fn buffer_events(&self) -> Vec<BufferEvent> {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn trace(&self, event: BufferEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<SentFrame>> {
+        self.frames.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Take one injected failure, if any are left
+fn take_failure(counter: &AtomicU32) -> bool {
+    counter
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+            left.checked_sub(1)
+        })
+        .is_ok()
+}
+
+/// Sender that records frames into a [`SinkLog`]
+pub struct RecordingSink {
+    log: Arc<SinkLog>,
+    hold: bool,
+    /// Lent frame and its sequence byte
+    held: Option<(HeldFrame, u8)>,
+}
+
+impl RecordingSink {
+    pub fn new(log: &Arc<SinkLog>) -> Self {
+        Self {
+            log: Arc::clone(log),
+            hold: false,
+            held: None,
+        }
+    }
+
+    /// Keep held frames lent until the next send or `release_frame`, like
+    /// NDI's asynchronous zero-copy send, and log those whose buffer changed
+    /// meanwhile. The source must keep them allocated until then, as it
+    /// must for NDI.
+    pub fn holding(mut self, hold: bool) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    /// Give the lent frame back, logging it if its buffer changed
+    fn take_held(&mut self) -> bool {
+        let Some((held, sequence)) = self.held.take() else {
+            return true;
+        };
+        self.log.trace(BufferEvent::Released(sequence));
+        let intact = unsafe { held.is_intact() };
+        if !intact {
+            self.log.torn_frames.fetch_add(1, Ordering::Relaxed);
+        }
+        intact
+    }
+}
+
+impl VideoSender for RecordingSink {
+    fn send_frame(&mut self, data: &[u8], info: FrameInfo) -> Result<()> {
+        if take_failure(&self.log.fail_sends) {
+            bail!("Injected send failure");
+        }
+        let delay = self.log.send_delay_us.load(Ordering::Relaxed);
+        if delay > 0 {
+            std::thread::sleep(Duration::from_micros(delay));
+        }
+        self.log.lock().push(SentFrame {
+            data: data.to_vec(),
+            info,
+        });
+        let sequence = data.first().copied().unwrap_or_default();
+        self.log.trace(BufferEvent::Sent(sequence));
+        self.take_held();
+        if self.hold && info.held {
+            self.held = Some((HeldFrame::new(data), sequence));
+            self.log.frames_held.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn recreate(&mut self) -> Result<()> {
+        self.held = None;
+        self.log.recreations.fetch_add(1, Ordering::Relaxed);
+        if take_failure(&self.log.fail_recreates) {
+            bail!("Injected recreate failure");
+        }
+        Ok(())
+    }
+
+    fn send_audio(
+        &mut self,
+        samples: &[i16],
+        _channels: u32,
+        _sample_rate: u32,
+        _captured: Option<Instant>,
+    ) -> Result<()> {
+        self.log
+            .audio_samples
+            .fetch_add(samples.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn release_frame(&mut self) -> Result<bool> {
+        Ok(self.take_held())
+    }
+
+    fn lends(&self, data: &[u8]) -> bool {
+        self.held
+            .as_ref()
+            .is_some_and(|(held, _)| held.is_buffer(data))
+    }
+
+    fn connections(&self) -> Option<u32> {
+        Some(self.log.connections.load(Ordering::Relaxed))
+    }
+
+    fn tally(&self) -> Option<Tally> {
+        Some(match self.log.tally.load(Ordering::Relaxed) {
+            2 => Tally::Program,
+            1 => Tally::Preview,
+            _ => Tally::Off,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: FrameRate = FrameRate {
+        numerator: 60,
+        denominator: 1,
+    };
+
+    fn poll(source: &mut ScriptedSource) -> Result<Option<u8>> {
+        let mut first = None;
+        let delivered = source.process_frame(Duration::ZERO, &mut |data, info| {
+            assert_eq!(data.len(), (info.stride * info.height) as usize);
+            first = Some(data[0]);
+        })?;
+        assert_eq!(delivered, first.is_some());
+        Ok(first)
+    }
+
+    #[test]
+    fn test_scripted_source_plays_script() {
+        let mut source = ScriptedSource::new(4, 2, RATE)
+            .with_frames(2)
+            .with_timeouts(1)
+            .with_error("unplugged")
+            .with_frames(1);
+        assert_eq!(poll(&mut source).unwrap(), Some(0));
+        assert_eq!(poll(&mut source).unwrap(), Some(1));
+        assert_eq!(poll(&mut source).unwrap(), None);
+        assert_eq!(poll(&mut source).unwrap_err().to_string(), "unplugged");
+        assert_eq!(poll(&mut source).unwrap(), Some(2));
+        // Exhausted: stalls
+        assert_eq!(poll(&mut source).unwrap(), None);
+        assert_eq!(source.frames_delivered(), 3);
+    }
+
+    #[test]
+    fn test_recording_sink_injects_failures() {
+        let log = SinkLog::new();
+        let mut sink = RecordingSink::new(&log);
+        let info = FrameInfo::new(2, 1, FourCC::new(b"UYVY"), 4);
+
+        log.fail_next_sends(1);
+        assert!(sink.send_frame(&[1; 4], info).is_err());
+        assert!(sink.send_frame(&[2; 4], info).is_ok());
+        assert_eq!(log.frame_count(), 1);
+        assert_eq!(log.frames()[0].data, [2; 4]);
+
+        log.fail_next_recreates(1);
+        assert!(sink.recreate().is_err());
+        assert!(sink.recreate().is_ok());
+        assert_eq!(log.recreations(), 2);
+
+        sink.send_audio(&[0; 6], 2, 48000, None).unwrap();
+        assert_eq!(log.audio_samples(), 6);
+        log.set_connections(3);
+        assert_eq!(sink.connections(), Some(3));
+        assert_eq!(sink.tally(), Some(Tally::Off));
+        log.set_tally(Tally::Preview);
+        assert_eq!(sink.tally(), Some(Tally::Preview));
+    }
+
+    #[test]
+    fn test_holding_sink_detects_reused_buffers() {
+        let log = SinkLog::new();
+        let mut sink = RecordingSink::new(&log).holding(true);
+        let info = FrameInfo::new(2, 1, FourCC::new(b"UYVY"), 4).with_held(true);
+        let mut buffer = [1u8; 4];
+
+        // Released before the buffer is reused: intact
+        sink.send_frame(&buffer, info).unwrap();
+        assert!(sink.lends(&buffer));
+        assert!(sink.release_frame().unwrap());
+        assert!(!sink.lends(&buffer));
+        buffer.fill(2);
+
+        // Reused while lent: torn
+        sink.send_frame(&buffer, info).unwrap();
+        buffer.fill(3);
+        assert!(!sink.release_frame().unwrap());
+        assert_eq!(log.frames_held(), 2);
+        assert_eq!(log.torn_frames(), 1);
+
+        // Frames the source doesn't hold are never lent
+        sink.send_frame(
+            &buffer,
+            FrameInfo {
+                held: false,
+                ..info
+            },
+        )
+        .unwrap();
+        assert_eq!(log.frames_held(), 2);
+    }
+}
@@ -0,0 +1,568 @@
+//! Raw clip source for simulation and QA
+//!
+//! `device = "file:/var/lib/camera-box/clip.uyvy"` replays raw UYVY or YUYV
+//! frames through the whole pipeline instead of a camera, e.g. problem
+//! footage from a show or the output of a V4L2 loopback; `file:-` reads
+//! them from stdin. Size, format and rate come from `[capture.file]`, and a
+//! `clip.uyvy.toml` sidecar next to the clip overrides any of them. Frames
+//! go out on an absolute [`FrameSchedule`]: a slow disk drops frames rather
+//! than slowing the clock. A file starts over at its end unless
+//! `loop_at_eof` is off; stdin stops sending at its end.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use v4l::FourCC;
+
+use crate::capture::{FrameInfo, FrameRate};
+use crate::config::FileSourceConfig;
+use crate::ndi::uyvy_frame_size;
+
+/// Device prefix selecting a raw clip instead of a V4L2 device
+pub const DEVICE_PREFIX: &str = "file:";
+
+/// Clip path that reads frames from stdin
+pub const STDIN: &str = "-";
+
+/// The clip path of a "file:PATH" device, None for other devices
+pub fn file_path(device: &str) -> Option<&str> {
+    device.strip_prefix(DEVICE_PREFIX)
+}
+
+/// Sidecar holding a clip's frame format: the clip path plus ".toml"
+pub fn sidecar_path(clip: &Path) -> PathBuf {
+    let mut path = clip.as_os_str().to_owned();
+    path.push(".toml");
+    PathBuf::from(path)
+}
+
+// =============================================================================
+// Format
+// =============================================================================
+
+/// Pixel format of the raw frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawFormat {
+    Uyvy,
+    Yuyv,
+}
+
+impl RawFormat {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "UYVY" => Ok(RawFormat::Uyvy),
+            "YUYV" | "YUY2" => Ok(RawFormat::Yuyv),
+            _ => bail!("Unknown raw format: {}. Use \"UYVY\" or \"YUYV\"", name),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RawFormat::Uyvy => "UYVY",
+            RawFormat::Yuyv => "YUYV",
+        }
+    }
+
+    pub fn fourcc(self) -> FourCC {
+        match self {
+            RawFormat::Uyvy => FourCC::new(b"UYVY"),
+            RawFormat::Yuyv => FourCC::new(b"YUYV"),
+        }
+    }
+}
+
+/// Frame format of a clip
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClipFormat {
+    pub width: u32,
+    pub height: u32,
+    pub format: RawFormat,
+    pub frame_rate: FrameRate,
+    pub loop_at_eof: bool,
+}
+
+/// A clip's sidecar; each key set overrides `[capture.file]`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Sidecar {
+    width: Option<u32>,
+    height: Option<u32>,
+    format: Option<String>,
+    frame_rate_n: Option<u32>,
+    frame_rate_d: Option<u32>,
+    loop_at_eof: Option<bool>,
+}
+
+impl ClipFormat {
+    pub fn from_config(config: &FileSourceConfig) -> Result<Self> {
+        Ok(Self {
+            width: config.width,
+            height: config.height,
+            format: RawFormat::from_name(&config.format)?,
+            frame_rate: FrameRate {
+                numerator: config.frame_rate_n,
+                denominator: config.frame_rate_d,
+            },
+            loop_at_eof: config.loop_at_eof,
+        })
+    }
+
+    /// This format with the values of the sidecar `text` over it
+    pub fn with_sidecar(mut self, text: &str) -> Result<Self> {
+        let sidecar: Sidecar = toml::from_str(text)?;
+        self.width = sidecar.width.unwrap_or(self.width);
+        self.height = sidecar.height.unwrap_or(self.height);
+        if let Some(format) = &sidecar.format {
+            self.format = RawFormat::from_name(format)?;
+        }
+        self.frame_rate.numerator = sidecar.frame_rate_n.unwrap_or(self.frame_rate.numerator);
+        self.frame_rate.denominator = sidecar.frame_rate_d.unwrap_or(self.frame_rate.denominator);
+        self.loop_at_eof = sidecar.loop_at_eof.unwrap_or(self.loop_at_eof);
+        if self.width < 2 || self.width % 2 != 0 || self.height == 0 {
+            bail!("Invalid frame size {}x{}", self.width, self.height);
+        }
+        if self.frame_rate.numerator == 0 || self.frame_rate.denominator == 0 {
+            bail!("Invalid frame rate");
+        }
+        Ok(self)
+    }
+
+    /// Bytes per frame, 2 per pixel in both formats
+    pub fn frame_size(&self) -> usize {
+        uyvy_frame_size(self.width, self.height)
+    }
+}
+
+// =============================================================================
+// Pacing
+// =============================================================================
+
+/// Absolute schedule of a fixed-rate stream: frame n is due n periods after
+/// the start, computed from the rate rather than summed from sleeps so
+/// neither rounding nor late wakeups accumulate
+#[derive(Debug, Clone, Copy)]
+pub struct FrameSchedule {
+    start: Instant,
+    rate: FrameRate,
+}
+
+impl FrameSchedule {
+    pub fn new(start: Instant, rate: FrameRate) -> Self {
+        Self { start, rate }
+    }
+
+    /// When frame `index` is due
+    pub fn due(&self, index: u64) -> Instant {
+        let nanos = index as u128 * 1_000_000_000 * self.rate.denominator.max(1) as u128
+            / self.rate.numerator.max(1) as u128;
+        self.start + Duration::from_nanos(nanos as u64)
+    }
+
+    /// The last frame due at or before `now`
+    pub fn index_at(&self, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.start).as_nanos();
+        (elapsed * self.rate.numerator.max(1) as u128
+            / (1_000_000_000 * self.rate.denominator.max(1) as u128)) as u64
+    }
+}
+
+// =============================================================================
+// File Source
+// =============================================================================
+
+enum Input {
+    File(File),
+    Stdin(io::Stdin),
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::File(file) => file.read(buf),
+            Input::Stdin(stdin) => stdin.read(buf),
+        }
+    }
+}
+
+/// Read into `buf` until it is full or the input ends; returns the bytes
+/// read
+fn fill(input: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Replays raw frames from a file or stdin at a fixed rate
+pub struct FileSource {
+    name: String,
+    input: Input,
+    format: ClipFormat,
+    frame: Vec<u8>,
+    /// Started by the first frame
+    schedule: Option<FrameSchedule>,
+    /// Index of the next frame on the schedule
+    next: u64,
+    ended: bool,
+    dropped: u64,
+}
+
+impl FileSource {
+    /// Open the clip at `path`, or stdin for [`STDIN`]
+    pub fn open(path: &str, format: ClipFormat) -> Result<Self> {
+        let input = if path == STDIN {
+            Input::Stdin(io::stdin())
+        } else {
+            Input::File(File::open(path).with_context(|| format!("Failed to open {}", path))?)
+        };
+        let name = if path == STDIN { "stdin" } else { path };
+        tracing::info!(
+            "Replaying {} as {}x{} {}, {}/{} fps{}",
+            name,
+            format.width,
+            format.height,
+            format.format.name(),
+            format.frame_rate.numerator,
+            format.frame_rate.denominator,
+            if format.loop_at_eof { ", looping" } else { "" }
+        );
+        Ok(Self::with_input(name, input, format))
+    }
+
+    /// Open a clip with the `[capture.file]` format, overridden by the
+    /// clip's sidecar if it has one
+    pub fn from_config(path: &str, config: &FileSourceConfig) -> Result<Self> {
+        let mut format = ClipFormat::from_config(config)?;
+        if path != STDIN {
+            let sidecar = sidecar_path(Path::new(path));
+            match std::fs::read_to_string(&sidecar) {
+                Ok(text) => {
+                    format = format
+                        .with_sidecar(&text)
+                        .with_context(|| format!("Invalid sidecar {}", sidecar.display()))?;
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to read {}", sidecar.display()))
+                }
+            }
+        }
+        Self::open(path, format)
+    }
+
+    fn with_input(name: &str, input: Input, format: ClipFormat) -> Self {
+        Self {
+            name: name.to_string(),
+            input,
+            format,
+            frame: vec![0; format.frame_size()],
+            schedule: None,
+            next: 0,
+            ended: false,
+            dropped: 0,
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.format.width, self.format.height)
+    }
+
+    pub fn frame_rate(&self) -> FrameRate {
+        self.format.frame_rate
+    }
+
+    pub fn frame_info(&self) -> FrameInfo {
+        FrameInfo::new(
+            self.format.width,
+            self.format.height,
+            self.format.format.fourcc(),
+            self.format.width * 2,
+        )
+    }
+
+    /// Frames dropped because they were read too late to send on time
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Back to the first frame; false for stdin
+    fn rewind(&mut self) -> io::Result<bool> {
+        match &mut self.input {
+            Input::File(file) => file.seek(SeekFrom::Start(0)).map(|_| true),
+            Input::Stdin(_) => Ok(false),
+        }
+    }
+
+    /// Read the next whole frame, starting over at the end when looping. A
+    /// partial frame at the end is left out. Returns false at the end of a
+    /// clip that doesn't loop.
+    fn read_frame(&mut self) -> io::Result<bool> {
+        let size = self.frame.len();
+        if fill(&mut self.input, &mut self.frame)? == size {
+            return Ok(true);
+        }
+        if !(self.format.loop_at_eof && self.rewind()?) {
+            return Ok(false);
+        }
+        if fill(&mut self.input, &mut self.frame)? < size {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "clip is shorter than one frame",
+            ));
+        }
+        Ok(true)
+    }
+
+    /// Pass over `count` frames: a seek in a file, read and thrown away
+    /// from stdin. Returns false if the clip ended.
+    fn skip_frames(&mut self, count: u64) -> io::Result<bool> {
+        let size = self.frame.len() as u64;
+        let file = match &mut self.input {
+            Input::File(file) => file,
+            Input::Stdin(_) => {
+                for _ in 0..count {
+                    if !self.read_frame()? {
+                        return Ok(false);
+                    }
+                }
+                return Ok(true);
+            }
+        };
+        let frames = file.metadata()?.len() / size;
+        let mut target = file.stream_position()? / size + count;
+        if target >= frames {
+            if !self.format.loop_at_eof || frames == 0 {
+                file.seek(SeekFrom::End(0))?;
+                return Ok(false);
+            }
+            target %= frames;
+        }
+        file.seek(SeekFrom::Start(target * size))?;
+        Ok(true)
+    }
+
+    /// Wait for the next frame (up to `timeout`) and pass it to `callback`.
+    /// Frames whose time passed while the previous one was read or sent are
+    /// skipped. Returns false if no frame was due within the timeout or the
+    /// clip has ended.
+    pub fn process_frame_timeout<F>(&mut self, timeout: Duration, mut callback: F) -> Result<bool>
+    where
+        F: FnMut(&[u8], FrameInfo),
+    {
+        let now = Instant::now();
+        if self.ended {
+            std::thread::sleep(timeout);
+            return Ok(false);
+        }
+        let schedule = *self
+            .schedule
+            .get_or_insert_with(|| FrameSchedule::new(now, self.format.frame_rate));
+        let due = schedule.due(self.next);
+        if due > now + timeout {
+            std::thread::sleep(timeout);
+            return Ok(false);
+        }
+        std::thread::sleep(due.saturating_duration_since(now));
+
+        let current = schedule.index_at(Instant::now()).max(self.next);
+        let late = current - self.next;
+        self.next = current + 1;
+        let more = if late > 0 {
+            self.dropped += late;
+            tracing::debug!("{}: dropped {} late frame(s)", self.name, late);
+            self.skip_frames(late)? && self.read_frame()?
+        } else {
+            self.read_frame()?
+        };
+        if !more {
+            self.ended = true;
+            tracing::info!("{}: end of clip", self.name);
+            return Ok(false);
+        }
+        callback(&self.frame, self.frame_info());
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const WIDTH: u32 = 4;
+    const HEIGHT: u32 = 2;
+
+    fn clip_format(loop_at_eof: bool, rate: u32) -> ClipFormat {
+        ClipFormat {
+            width: WIDTH,
+            height: HEIGHT,
+            format: RawFormat::Uyvy,
+            frame_rate: FrameRate {
+                numerator: rate,
+                denominator: 1,
+            },
+            loop_at_eof,
+        }
+    }
+
+    /// `frames` 4x2 frames, each filled with its index, then half a frame
+    fn fixture(frames: u8) -> tempfile::NamedTempFile {
+        let size = uyvy_frame_size(WIDTH, HEIGHT);
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for index in 0..frames {
+            file.write_all(&vec![index; size]).unwrap();
+        }
+        file.write_all(&vec![0xee; size / 2]).unwrap();
+        file
+    }
+
+    fn source(clip: &tempfile::NamedTempFile, loop_at_eof: bool, rate: u32) -> FileSource {
+        let file = File::open(clip.path()).unwrap();
+        FileSource::with_input("clip", Input::File(file), clip_format(loop_at_eof, rate))
+    }
+
+    /// The index filling each frame read, until the clip ends
+    fn read_all(source: &mut FileSource, limit: usize) -> Vec<u8> {
+        let mut frames = Vec::new();
+        while frames.len() < limit && source.read_frame().unwrap() {
+            assert!(source.frame.iter().all(|&b| b == source.frame[0]));
+            frames.push(source.frame[0]);
+        }
+        frames
+    }
+
+    #[test]
+    fn test_file_path() {
+        assert_eq!(file_path("file:/tmp/clip.uyvy"), Some("/tmp/clip.uyvy"));
+        assert_eq!(file_path("file:-"), Some(STDIN));
+        assert_eq!(file_path("/dev/video0"), None);
+        assert_eq!(
+            sidecar_path(Path::new("/tmp/clip.uyvy")),
+            Path::new("/tmp/clip.uyvy.toml")
+        );
+    }
+
+    #[test]
+    fn test_schedule_is_absolute() {
+        let start = Instant::now();
+        let ntsc = FrameSchedule::new(
+            start,
+            FrameRate {
+                numerator: 30000,
+                denominator: 1001,
+            },
+        );
+        assert_eq!(ntsc.due(0), start);
+        assert_eq!(ntsc.due(1), start + Duration::from_nanos(33_366_666));
+        // An hour of frames lands exactly, where summed periods would be
+        // 72 us early
+        assert_eq!(
+            ntsc.due(107_892),
+            start + Duration::from_micros(3_599_996_400)
+        );
+        assert_eq!(ntsc.due(30_000), start + Duration::from_secs(1001));
+        for index in [0, 1, 2, 29, 30, 1000, 107_892] {
+            assert_eq!(ntsc.index_at(ntsc.due(index)), index);
+            if index > 0 {
+                assert_eq!(
+                    ntsc.index_at(ntsc.due(index) - Duration::from_nanos(1)),
+                    index - 1
+                );
+            }
+        }
+        assert_eq!(ntsc.index_at(start - Duration::from_secs(1)), 0);
+
+        // 100 ms late at 30 fps: frames 1 and 2 are past, 3 is due
+        let thirty = FrameSchedule::new(
+            start,
+            FrameRate {
+                numerator: 30,
+                denominator: 1,
+            },
+        );
+        assert_eq!(thirty.index_at(start + Duration::from_millis(100)), 3);
+    }
+
+    #[test]
+    fn test_clip_loops_at_eof() {
+        let clip = fixture(3);
+        let mut looping = source(&clip, true, 30);
+        // The half frame at the end is never sent
+        assert_eq!(read_all(&mut looping, 8), [0, 1, 2, 0, 1, 2, 0, 1]);
+
+        let mut once = source(&clip, false, 30);
+        assert_eq!(read_all(&mut once, 8), [0, 1, 2]);
+        assert!(!once.read_frame().unwrap());
+
+        // Skips wrap around a looping clip and end one that doesn't
+        let mut looping = source(&clip, true, 30);
+        assert!(looping.skip_frames(4).unwrap());
+        assert_eq!(read_all(&mut looping, 2), [1, 2]);
+        let mut once = source(&clip, false, 30);
+        assert!(!once.skip_frames(3).unwrap());
+        assert!(!once.read_frame().unwrap());
+
+        // Too short for a single frame
+        let mut empty = source(&fixture(0), true, 30);
+        assert_eq!(
+            empty.read_frame().unwrap_err().kind(),
+            ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_late_frames_are_dropped() {
+        let clip = fixture(5);
+        let mut source = source(&clip, false, 1);
+        // 3.5 s behind at 1 fps: frames 0 to 2 are past, frame 3 goes out
+        // now and frame 4 stays on the schedule
+        let start = Instant::now() - Duration::from_millis(3500);
+        source.schedule = Some(FrameSchedule::new(start, source.format.frame_rate));
+        let mut sent = Vec::new();
+        let mut record = |frame: &[u8], info: FrameInfo| {
+            assert_eq!((info.width, info.height), (WIDTH, HEIGHT));
+            sent.push(frame[0]);
+        };
+        assert!(source
+            .process_frame_timeout(Duration::ZERO, &mut record)
+            .unwrap());
+        assert!(!source
+            .process_frame_timeout(Duration::from_millis(10), &mut record)
+            .unwrap());
+        assert_eq!(sent, [3]);
+        assert_eq!(source.dropped(), 3);
+        assert_eq!(source.next, 4);
+    }
+
+    #[test]
+    fn test_sidecar_overrides_config() {
+        let config = FileSourceConfig::default();
+        let format = ClipFormat::from_config(&config).unwrap();
+        assert_eq!(format.format, RawFormat::Uyvy);
+        assert!(format.loop_at_eof);
+
+        let format = format
+            .with_sidecar("width = 1280\nheight = 720\nformat = \"yuyv\"\nframe_rate_n = 50\n")
+            .unwrap();
+        assert_eq!((format.width, format.height), (1280, 720));
+        assert_eq!(format.format, RawFormat::Yuyv);
+        assert_eq!(
+            (format.frame_rate.numerator, format.frame_rate.denominator),
+            (50, 1)
+        );
+        assert_eq!(format.frame_size(), 1280 * 720 * 2);
+
+        assert!(format.with_sidecar("width = 1281").is_err());
+        assert!(format.with_sidecar("format = \"NV12\"").is_err());
+        assert!(format.with_sidecar("fps = 30").is_err());
+    }
+}
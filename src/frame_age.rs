@@ -0,0 +1,218 @@
+//! Stale frame guard for the NDI display
+//!
+//! When the framebuffer write blocks (some drivers wait for vsync) the
+//! display loop falls behind and shows frames that are long gone on air.
+//! NDI stamps every frame with the sender's clock; the guard compares that
+//! against the local monotonic clock. The two clocks share no epoch, so the
+//! smallest difference seen over the first frames is taken as the offset
+//! (the frames that arrived fastest), and a frame's age is how much later
+//! than that it arrived. Frames older than the limit are dropped.
+//!
+//! Senders that leave the timestamp unset (zero, or NDI's "undefined")
+//! disable the guard for the connection.
+
+use std::time::{Duration, Instant};
+
+/// NDI's `NDIlib_recv_timestamp_undefined`
+pub const TIMESTAMP_UNDEFINED: i64 = i64::MAX;
+
+/// Frames used to estimate the clock offset before any is judged
+pub const CALIBRATION_FRAMES: u32 = 30;
+
+/// Consecutive stale frames after which the offset is estimated again; the
+/// sender's clock most likely stepped
+pub const RECALIBRATE_AFTER: u32 = 30;
+
+/// NDI timestamps count 100ns ticks
+const TICK_NANOS: i64 = 100;
+
+/// What to do with a received frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameVerdict {
+    Show,
+    /// Older than the limit by this age
+    Drop(Duration),
+}
+
+/// Per-connection age check of received frames
+#[derive(Debug)]
+pub struct StaleFrameGuard {
+    max_age: Duration,
+    epoch: Instant,
+    /// Smallest (local - sender) clock difference seen, in ticks
+    offset: Option<i64>,
+    calibrated: u32,
+    stale_run: u32,
+    disabled: bool,
+}
+
+impl StaleFrameGuard {
+    /// Guard dropping frames older than `max_age`; zero disables it
+    pub fn new(max_age: Duration, now: Instant) -> Self {
+        Self {
+            max_age,
+            epoch: now,
+            offset: None,
+            calibrated: 0,
+            stale_run: 0,
+            disabled: max_age.is_zero(),
+        }
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled
+    }
+
+    /// Estimated (local - sender) clock offset in 100ns ticks
+    pub fn offset(&self) -> Option<i64> {
+        self.offset
+    }
+
+    /// Judge a frame stamped `timestamp` (sender clock, 100ns ticks) that
+    /// was received at `now`
+    pub fn check(&mut self, timestamp: i64, now: Instant) -> FrameVerdict {
+        if self.disabled {
+            return FrameVerdict::Show;
+        }
+        if timestamp == 0 || timestamp == TIMESTAMP_UNDEFINED {
+            tracing::info!("NDI display: sender has no frame timestamps, age guard off");
+            self.disabled = true;
+            return FrameVerdict::Show;
+        }
+
+        let local = (now.saturating_duration_since(self.epoch).as_nanos() as i64) / TICK_NANOS;
+        let difference = local.saturating_sub(timestamp);
+        let offset = match self.offset {
+            Some(offset) if difference >= offset => offset,
+            // Arrived faster than any frame before: the new baseline
+            _ => {
+                self.offset = Some(difference);
+                difference
+            }
+        };
+        if self.calibrated < CALIBRATION_FRAMES {
+            self.calibrated += 1;
+            return FrameVerdict::Show;
+        }
+
+        let age = Duration::from_nanos(((difference - offset) * TICK_NANOS) as u64);
+        if age <= self.max_age {
+            self.stale_run = 0;
+            return FrameVerdict::Show;
+        }
+        self.stale_run += 1;
+        if self.stale_run >= RECALIBRATE_AFTER {
+            tracing::warn!(
+                "NDI display: {} stale frames in a row, re-estimating the sender clock offset",
+                self.stale_run
+            );
+            self.offset = None;
+            self.calibrated = 0;
+            self.stale_run = 0;
+        }
+        FrameVerdict::Drop(age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_AGE: Duration = Duration::from_millis(100);
+
+    /// Sender clock 10s ahead of ours, in ticks
+    const SENDER_AHEAD: i64 = 100_000_000;
+
+    fn ticks(duration: Duration) -> i64 {
+        duration.as_nanos() as i64 / TICK_NANOS
+    }
+
+    /// Feed frames sent every 20ms, each received `delay(i)` after it was sent
+    fn run(
+        guard: &mut StaleFrameGuard,
+        start: Instant,
+        frames: std::ops::Range<u32>,
+        delay: impl Fn(u32) -> Duration,
+    ) -> Vec<FrameVerdict> {
+        frames
+            .map(|i| {
+                let sent = Duration::from_millis(20 * i as u64);
+                guard.check(SENDER_AHEAD + ticks(sent), start + sent + delay(i))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_offset_is_the_fastest_arrival() {
+        let start = Instant::now();
+        let mut guard = StaleFrameGuard::new(MAX_AGE, start);
+        // Network jitter of 5-14ms; the 5ms frames set the baseline
+        let verdicts = run(&mut guard, start, 0..CALIBRATION_FRAMES, |i| {
+            Duration::from_millis(5 + (i as u64 * 7) % 10)
+        });
+        assert!(verdicts.iter().all(|v| *v == FrameVerdict::Show));
+        assert_eq!(
+            guard.offset(),
+            Some(ticks(Duration::from_millis(5)) - SENDER_AHEAD)
+        );
+    }
+
+    #[test]
+    fn test_late_frames_are_dropped() {
+        let start = Instant::now();
+        let mut guard = StaleFrameGuard::new(MAX_AGE, start);
+        run(&mut guard, start, 0..CALIBRATION_FRAMES, |_| {
+            Duration::from_millis(5)
+        });
+
+        // 90ms late is within the limit, 250ms late is not
+        let verdicts = run(&mut guard, start, 30..33, |i| match i {
+            30 => Duration::from_millis(95),
+            31 => Duration::from_millis(255),
+            _ => Duration::from_millis(5),
+        });
+        assert_eq!(verdicts[0], FrameVerdict::Show);
+        assert_eq!(verdicts[1], FrameVerdict::Drop(Duration::from_millis(250)));
+        assert_eq!(verdicts[2], FrameVerdict::Show);
+    }
+
+    #[test]
+    fn test_calibration_frames_are_never_dropped() {
+        let start = Instant::now();
+        let mut guard = StaleFrameGuard::new(MAX_AGE, start);
+        let verdicts = run(&mut guard, start, 0..CALIBRATION_FRAMES, |i| {
+            Duration::from_millis(if i == 0 { 500 } else { 5 })
+        });
+        assert!(verdicts.iter().all(|v| *v == FrameVerdict::Show));
+    }
+
+    #[test]
+    fn test_zero_or_undefined_timestamps_disable_the_guard() {
+        for bogus in [0, TIMESTAMP_UNDEFINED] {
+            let start = Instant::now();
+            let mut guard = StaleFrameGuard::new(MAX_AGE, start);
+            assert_eq!(guard.check(bogus, start), FrameVerdict::Show);
+            assert!(guard.is_disabled());
+            // Even real timestamps later on aren't judged
+            let verdicts = run(&mut guard, start, 0..100, |_| Duration::from_secs(5));
+            assert!(verdicts.iter().all(|v| *v == FrameVerdict::Show));
+        }
+        assert!(StaleFrameGuard::new(Duration::ZERO, Instant::now()).is_disabled());
+    }
+
+    #[test]
+    fn test_clock_step_recalibrates() {
+        let start = Instant::now();
+        let mut guard = StaleFrameGuard::new(MAX_AGE, start);
+        run(&mut guard, start, 0..CALIBRATION_FRAMES, |_| {
+            Duration::from_millis(5)
+        });
+        // The sender's clock jumps back a second: every frame looks old
+        let stepped = run(&mut guard, start, 30..30 + RECALIBRATE_AFTER, |_| {
+            Duration::from_millis(1005)
+        });
+        assert!(stepped.iter().all(|v| matches!(v, FrameVerdict::Drop(_))));
+        let after = run(&mut guard, start, 60..200, |_| Duration::from_millis(1005));
+        assert!(after.iter().all(|v| *v == FrameVerdict::Show));
+    }
+}
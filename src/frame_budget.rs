@@ -0,0 +1,185 @@
+//! Capture backlog guard
+//!
+//! When conversion and sending take longer than a frame interval (on the
+//! dual-core boxes this happens while the intercom and display are busy),
+//! V4L2 keeps filling its queue and every later frame goes out that much
+//! older; the latency never recovers on its own. The capture loop times
+//! each frame, estimates how many frames are waiting in the queue and, once
+//! more than one is, dequeues the oldest without converting them so the
+//! newest is the next one sent.
+//!
+//! V4L2 can't be asked how many buffers are ready, so the depth is derived:
+//! a frame that was ready immediately came from the queue, and frames keep
+//! arriving at the frame rate while the previous one was being processed.
+
+use std::time::Duration;
+
+use crate::capture::FrameRate;
+
+/// A dequeue that returned within this long found the frame already queued
+pub const READY_THRESHOLD: Duration = Duration::from_millis(1);
+
+/// Most frames skipped in one go; one less than the capture buffer count
+pub const MAX_SKIP: u32 = 3;
+
+/// Queue depth estimate of the capture loop
+#[derive(Debug)]
+pub struct FrameBudget {
+    interval: Duration,
+    /// Estimated frames waiting in the queue
+    backlog: f64,
+}
+
+impl FrameBudget {
+    pub fn new(frame_rate: FrameRate) -> Self {
+        let interval = if frame_rate.numerator == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(frame_rate.denominator as f64 / frame_rate.numerator as f64)
+        };
+        Self {
+            interval,
+            backlog: 0.0,
+        }
+    }
+
+    /// Time available for converting and sending one frame
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Estimated frames waiting in the queue
+    pub fn backlog(&self) -> f64 {
+        self.backlog
+    }
+
+    /// Record a processed frame: `wait` is the time the dequeue spent waiting
+    /// for it and `work` the time its conversion and send took. Returns true
+    /// if the frame went over budget.
+    pub fn record(&mut self, wait: Duration, work: Duration) -> bool {
+        if self.interval.is_zero() {
+            return false;
+        }
+        // A frame we had to wait for means the queue was empty
+        let left = if wait < READY_THRESHOLD {
+            (self.backlog - 1.0).max(0.0)
+        } else {
+            0.0
+        };
+        self.backlog = left + work.as_secs_f64() / self.interval.as_secs_f64();
+        work > self.interval
+    }
+
+    /// Frames to dequeue without processing before the next one, leaving the
+    /// newest queued frame to be sent
+    pub fn frames_to_skip(&self) -> u32 {
+        let depth = self.backlog.floor() as u32;
+        if depth > 1 {
+            (depth - 1).min(MAX_SKIP)
+        } else {
+            0
+        }
+    }
+
+    /// Record `count` frames dequeued without processing
+    pub fn skipped(&mut self, count: u32) {
+        self.backlog = (self.backlog - count as f64).max(0.0);
+    }
+
+    /// A skip found no frame ready: the queue is empty
+    pub fn drained(&mut self) {
+        self.backlog = 0.0;
+    }
+
+    /// Forget the estimate, e.g. after the source was reopened
+    pub fn reset(&mut self, frame_rate: FrameRate) {
+        *self = Self::new(frame_rate);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: FrameRate = FrameRate {
+        numerator: 60,
+        denominator: 1,
+    };
+
+    fn ms(ms: f64) -> Duration {
+        Duration::from_secs_f64(ms / 1000.0)
+    }
+
+    #[test]
+    fn test_fast_frames_never_skip() {
+        let mut budget = FrameBudget::new(RATE);
+        // Waited for every frame, 5 ms of work each
+        for _ in 0..100 {
+            assert!(!budget.record(ms(11.0), ms(5.0)));
+            assert_eq!(budget.frames_to_skip(), 0);
+        }
+        // A burst of queued frames processed quickly drains by itself
+        for _ in 0..10 {
+            budget.record(Duration::ZERO, ms(2.0));
+            assert_eq!(budget.frames_to_skip(), 0);
+        }
+    }
+
+    #[test]
+    fn test_single_overrun_does_not_skip() {
+        let mut budget = FrameBudget::new(RATE);
+        budget.record(ms(10.0), ms(5.0));
+        // 25 ms: the next frame is queued, nothing behind it yet
+        assert!(budget.record(ms(10.0), ms(25.0)));
+        assert_eq!(budget.frames_to_skip(), 0);
+        budget.record(Duration::ZERO, ms(5.0));
+        assert_eq!(budget.frames_to_skip(), 0);
+    }
+
+    #[test]
+    fn test_sustained_overrun_skips_oldest() {
+        let mut budget = FrameBudget::new(RATE);
+        let mut skipped = 0;
+        // 20 ms per frame at 60 fps: the queue grows by 0.2 frames each frame
+        for i in 0..50 {
+            let wait = if i == 0 { ms(10.0) } else { Duration::ZERO };
+            assert!(budget.record(wait, ms(20.0)));
+            let skip = budget.frames_to_skip();
+            if skip > 0 {
+                skipped += skip;
+                budget.skipped(skip);
+            }
+            assert!(budget.backlog() < 2.0, "backlog {}", budget.backlog());
+        }
+        // About one frame in six has to go
+        assert!((6..=10).contains(&skipped), "skipped {}", skipped);
+    }
+
+    #[test]
+    fn test_long_stall_skip_is_capped() {
+        let mut budget = FrameBudget::new(RATE);
+        // Blocked for just over six frames: six queued behind this one
+        budget.record(ms(10.0), ms(101.0));
+        assert_eq!(budget.frames_to_skip(), MAX_SKIP);
+        budget.skipped(MAX_SKIP);
+        assert_eq!(budget.frames_to_skip(), 2);
+        // Fewer were queued than estimated
+        budget.drained();
+        assert_eq!(budget.frames_to_skip(), 0);
+    }
+
+    #[test]
+    fn test_reset_and_unknown_rate() {
+        let mut budget = FrameBudget::new(RATE);
+        budget.record(ms(10.0), ms(100.0));
+        budget.reset(RATE);
+        assert_eq!(budget.frames_to_skip(), 0);
+
+        let mut budget = FrameBudget::new(FrameRate {
+            numerator: 0,
+            denominator: 1,
+        });
+        assert!(!budget.record(Duration::ZERO, ms(100.0)));
+        assert_eq!(budget.frames_to_skip(), 0);
+    }
+}
@@ -0,0 +1,470 @@
+//! GPIO button and LED support
+//!
+//! Uses the Linux GPIO character device (/dev/gpiochipN) line handle and
+//! line event ioctls directly, so no sysfs export or helper daemon is needed.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::time::Duration;
+
+// GPIO character device uAPI (linux/gpio.h, v1 line handle/event interface)
+const GPIOHANDLES_MAX: usize = 64;
+const GPIOHANDLE_REQUEST_INPUT: u32 = 1 << 0;
+const GPIOHANDLE_REQUEST_OUTPUT: u32 = 1 << 1;
+const GPIOHANDLE_REQUEST_ACTIVE_LOW: u32 = 1 << 2;
+const GPIOEVENT_REQUEST_BOTH_EDGES: u32 = 0b11;
+const GPIOEVENT_EVENT_RISING_EDGE: u32 = 0x01;
+
+const CONSUMER_LABEL: &[u8] = b"camera-box";
+
+#[repr(C)]
+struct GpioHandleRequest {
+    lineoffsets: [u32; GPIOHANDLES_MAX],
+    flags: u32,
+    default_values: [u8; GPIOHANDLES_MAX],
+    consumer_label: [u8; 32],
+    lines: u32,
+    fd: libc::c_int,
+}
+
+#[repr(C)]
+struct GpioHandleData {
+    values: [u8; GPIOHANDLES_MAX],
+}
+
+#[repr(C)]
+struct GpioEventRequest {
+    lineoffset: u32,
+    handleflags: u32,
+    eventflags: u32,
+    consumer_label: [u8; 32],
+    fd: libc::c_int,
+}
+
+#[repr(C)]
+struct GpioEventData {
+    timestamp: u64,
+    id: u32,
+}
+
+/// Linux _IOWR(type, nr, T)
+const fn iowr<T>(nr: u8) -> libc::c_ulong {
+    ((3u32 << 30) | ((std::mem::size_of::<T>() as u32) << 16) | (0xB4 << 8) | nr as u32)
+        as libc::c_ulong
+}
+
+const GPIO_GET_LINEHANDLE_IOCTL: libc::c_ulong = iowr::<GpioHandleRequest>(0x03);
+const GPIO_GET_LINEEVENT_IOCTL: libc::c_ulong = iowr::<GpioEventRequest>(0x04);
+const GPIOHANDLE_SET_LINE_VALUES_IOCTL: libc::c_ulong = iowr::<GpioHandleData>(0x09);
+
+fn consumer_label() -> [u8; 32] {
+    let mut label = [0u8; 32];
+    label[..CONSUMER_LABEL.len()].copy_from_slice(CONSUMER_LABEL);
+    label
+}
+
+/// A GPIO line reference such as "gpiochip0:17"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpioLine {
+    /// Chip name ("gpiochip0") or device path ("/dev/gpiochip0")
+    pub chip: String,
+    /// Line offset within the chip
+    pub offset: u32,
+}
+
+impl GpioLine {
+    /// Parse "<chip>:<offset>", e.g. "gpiochip0:17" or "/dev/gpiochip1:4"
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (chip, offset) = spec.rsplit_once(':').ok_or_else(|| {
+            anyhow!(
+                "Invalid GPIO line {:?}, expected \"gpiochipN:offset\"",
+                spec
+            )
+        })?;
+        if chip.is_empty() {
+            bail!("Invalid GPIO line {:?}: missing chip name", spec);
+        }
+        let offset = offset
+            .trim()
+            .parse::<u32>()
+            .map_err(|_| anyhow!("Invalid GPIO line {:?}: bad offset {:?}", spec, offset))?;
+        Ok(Self {
+            chip: chip.trim().to_string(),
+            offset,
+        })
+    }
+}
+
+/// A red/green bi-color LED on two lines of one chip
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TallyLedPins {
+    pub chip: String,
+    pub red: u32,
+    pub green: u32,
+}
+
+/// Resolve a chip name to its character device path
+pub fn chip_path(chip: &str) -> String {
+    if chip.starts_with('/') {
+        chip.to_string()
+    } else {
+        format!("/dev/{}", chip)
+    }
+}
+
+fn open_chip(chip: &str) -> Result<File> {
+    let path = chip_path(chip);
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open GPIO chip {}", path))
+}
+
+// =============================================================================
+// Button (edge events with debounce)
+// =============================================================================
+
+/// Accepts a press only if the previous accepted press is at least `interval` old.
+/// Timestamps are the kernel event times, so bursts from contact bounce collapse.
+#[derive(Debug)]
+pub struct Debouncer {
+    interval: Duration,
+    last_accepted: Option<Duration>,
+}
+
+impl Debouncer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_accepted: None,
+        }
+    }
+
+    /// Feed a press at `timestamp`. Returns true if it counts as a new press.
+    pub fn accept(&mut self, timestamp: Duration) -> bool {
+        match self.last_accepted {
+            Some(last) if timestamp.saturating_sub(last) < self.interval => false,
+            _ => {
+                self.last_accepted = Some(timestamp);
+                true
+            }
+        }
+    }
+}
+
+/// Default debounce interval for mechanical buttons
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// A debounced change of a button's state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEdge {
+    Pressed,
+    Released,
+}
+
+/// Push button on a GPIO line (active low, pressed = line pulled to ground)
+pub struct GpioButton {
+    events: File,
+    /// Shared by both edges, so the bounce after a release can't count as
+    /// a new press
+    debouncer: Debouncer,
+    pressed: bool,
+}
+
+impl GpioButton {
+    pub fn open(line: &GpioLine) -> Result<Self> {
+        let chip = open_chip(&line.chip)?;
+        let mut request = GpioEventRequest {
+            lineoffset: line.offset,
+            handleflags: GPIOHANDLE_REQUEST_INPUT | GPIOHANDLE_REQUEST_ACTIVE_LOW,
+            eventflags: GPIOEVENT_REQUEST_BOTH_EDGES,
+            consumer_label: consumer_label(),
+            fd: -1,
+        };
+        let ret = unsafe { libc::ioctl(chip.as_raw_fd(), GPIO_GET_LINEEVENT_IOCTL, &mut request) };
+        if ret < 0 || request.fd < 0 {
+            return Err(anyhow!(
+                "Failed to request GPIO line {}:{} for events: {}",
+                line.chip,
+                line.offset,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(Self {
+            events: unsafe { File::from_raw_fd(request.fd as RawFd) },
+            debouncer: Debouncer::new(DEFAULT_DEBOUNCE),
+            pressed: false,
+        })
+    }
+
+    /// Wait up to `timeout` for a debounced press or release. Returns None
+    /// on timeout or when the edge was contact bounce.
+    pub fn wait_edge(&mut self, timeout: Duration) -> Result<Option<ButtonEdge>> {
+        let mut pfd = libc::pollfd {
+            fd: self.events.as_raw_fd(),
+            events: libc::POLLIN | libc::POLLPRI,
+            revents: 0,
+        };
+        let ret = unsafe { libc::poll(&mut pfd, 1, timeout.as_millis() as libc::c_int) };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                return Ok(None);
+            }
+            return Err(anyhow!("GPIO poll failed: {}", err));
+        }
+        if ret == 0 {
+            return Ok(None);
+        }
+
+        let mut event = GpioEventData {
+            timestamp: 0,
+            id: 0,
+        };
+        let n = unsafe {
+            libc::read(
+                self.events.as_raw_fd(),
+                &mut event as *mut GpioEventData as *mut libc::c_void,
+                std::mem::size_of::<GpioEventData>(),
+            )
+        };
+        if n != std::mem::size_of::<GpioEventData>() as isize {
+            return Err(anyhow!(
+                "GPIO event read failed: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        // Active-low line: logical rising edge = button pressed
+        let pressed = event.id == GPIOEVENT_EVENT_RISING_EDGE;
+        Ok(debounce_edge(
+            &mut self.debouncer,
+            &mut self.pressed,
+            pressed,
+            Duration::from_nanos(event.timestamp),
+        ))
+    }
+}
+
+/// Turn a raw edge at `timestamp` into a state change of a button that is
+/// currently `pressed`; edges that repeat the state or bounce are dropped
+fn debounce_edge(
+    debouncer: &mut Debouncer,
+    pressed: &mut bool,
+    edge_pressed: bool,
+    timestamp: Duration,
+) -> Option<ButtonEdge> {
+    if edge_pressed == *pressed || !debouncer.accept(timestamp) {
+        return None;
+    }
+    *pressed = edge_pressed;
+    Some(if edge_pressed {
+        ButtonEdge::Pressed
+    } else {
+        ButtonEdge::Released
+    })
+}
+
+// =============================================================================
+// Bi-color LED
+// =============================================================================
+
+/// Colors of a red/green bi-color LED
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedColor {
+    Off,
+    Red,
+    Green,
+    /// Both elements lit
+    Amber,
+}
+
+impl LedColor {
+    /// (red, green) line values
+    fn levels(self) -> (u8, u8) {
+        match self {
+            LedColor::Off => (0, 0),
+            LedColor::Red => (1, 0),
+            LedColor::Green => (0, 1),
+            LedColor::Amber => (1, 1),
+        }
+    }
+}
+
+/// Bi-color LED driven by two output lines on one chip
+pub struct GpioLed {
+    handle: File,
+    color: LedColor,
+}
+
+impl GpioLed {
+    pub fn open(chip: &str, red: u32, green: u32) -> Result<Self> {
+        let chip_file = open_chip(chip)?;
+        let mut request = GpioHandleRequest {
+            lineoffsets: [0; GPIOHANDLES_MAX],
+            flags: GPIOHANDLE_REQUEST_OUTPUT,
+            default_values: [0; GPIOHANDLES_MAX],
+            consumer_label: consumer_label(),
+            lines: 2,
+            fd: -1,
+        };
+        request.lineoffsets[0] = red;
+        request.lineoffsets[1] = green;
+        let ret = unsafe {
+            libc::ioctl(
+                chip_file.as_raw_fd(),
+                GPIO_GET_LINEHANDLE_IOCTL,
+                &mut request,
+            )
+        };
+        if ret < 0 || request.fd < 0 {
+            return Err(anyhow!(
+                "Failed to request GPIO lines {}:{},{} for LED: {}",
+                chip,
+                red,
+                green,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(Self {
+            handle: unsafe { File::from_raw_fd(request.fd as RawFd) },
+            color: LedColor::Off,
+        })
+    }
+
+    /// Set the LED color (no-op if unchanged)
+    pub fn set(&mut self, color: LedColor) -> Result<()> {
+        if color == self.color {
+            return Ok(());
+        }
+        let (red, green) = color.levels();
+        let mut data = GpioHandleData {
+            values: [0; GPIOHANDLES_MAX],
+        };
+        data.values[0] = red;
+        data.values[1] = green;
+        let ret = unsafe {
+            libc::ioctl(
+                self.handle.as_raw_fd(),
+                GPIOHANDLE_SET_LINE_VALUES_IOCTL,
+                &mut data,
+            )
+        };
+        if ret < 0 {
+            return Err(anyhow!(
+                "Failed to set LED: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        self.color = color;
+        Ok(())
+    }
+}
+
+impl Drop for GpioLed {
+    fn drop(&mut self) {
+        let _ = self.set(LedColor::Off);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line() {
+        let line = GpioLine::parse("gpiochip0:17").unwrap();
+        assert_eq!(line.chip, "gpiochip0");
+        assert_eq!(line.offset, 17);
+    }
+
+    #[test]
+    fn test_parse_line_with_path() {
+        let line = GpioLine::parse("/dev/gpiochip1:4").unwrap();
+        assert_eq!(line.chip, "/dev/gpiochip1");
+        assert_eq!(line.offset, 4);
+    }
+
+    #[test]
+    fn test_parse_line_invalid() {
+        assert!(GpioLine::parse("gpiochip0").is_err());
+        assert!(GpioLine::parse(":17").is_err());
+        assert!(GpioLine::parse("gpiochip0:abc").is_err());
+        assert!(GpioLine::parse("gpiochip0:-1").is_err());
+    }
+
+    #[test]
+    fn test_chip_path() {
+        assert_eq!(chip_path("gpiochip0"), "/dev/gpiochip0");
+        assert_eq!(chip_path("/dev/gpiochip2"), "/dev/gpiochip2");
+    }
+
+    #[test]
+    fn test_debounce_rejects_bounce() {
+        let mut d = Debouncer::new(Duration::from_millis(50));
+        assert!(d.accept(Duration::from_millis(1000)));
+        // Contact bounce within the window
+        assert!(!d.accept(Duration::from_millis(1002)));
+        assert!(!d.accept(Duration::from_millis(1030)));
+        // Next real press
+        assert!(d.accept(Duration::from_millis(1200)));
+    }
+
+    #[test]
+    fn test_debounce_window_measured_from_accepted_press() {
+        let mut d = Debouncer::new(Duration::from_millis(50));
+        assert!(d.accept(Duration::from_millis(0)));
+        assert!(!d.accept(Duration::from_millis(40)));
+        // 50ms after the accepted press, even though a bounce came at 40ms
+        assert!(d.accept(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn test_debounce_out_of_order_timestamp() {
+        let mut d = Debouncer::new(Duration::from_millis(50));
+        assert!(d.accept(Duration::from_millis(100)));
+        assert!(!d.accept(Duration::from_millis(90)));
+    }
+
+    #[test]
+    fn test_debounce_edges_of_a_press_and_release() {
+        let mut d = Debouncer::new(Duration::from_millis(50));
+        let mut pressed = false;
+        let mut edge = |down: bool, ms: u64| {
+            debounce_edge(&mut d, &mut pressed, down, Duration::from_millis(ms))
+        };
+        assert_eq!(edge(true, 0), Some(ButtonEdge::Pressed));
+        // Bounce on the way down
+        assert_eq!(edge(false, 3), None);
+        assert_eq!(edge(true, 6), None);
+        // Held for a while, then bouncing on the way up
+        assert_eq!(edge(false, 1500), Some(ButtonEdge::Released));
+        assert_eq!(edge(true, 1504), None);
+        assert_eq!(edge(false, 1508), None);
+        assert_eq!(edge(true, 1800), Some(ButtonEdge::Pressed));
+    }
+
+    #[test]
+    fn test_ioctl_numbers() {
+        // Values from linux/gpio.h on 64-bit targets
+        assert_eq!(GPIO_GET_LINEHANDLE_IOCTL, 0xC16C_B403);
+        assert_eq!(GPIO_GET_LINEEVENT_IOCTL, 0xC030_B404);
+        assert_eq!(GPIOHANDLE_SET_LINE_VALUES_IOCTL, 0xC040_B409);
+    }
+
+    #[test]
+    fn test_led_levels() {
+        assert_eq!(LedColor::Off.levels(), (0, 0));
+        assert_eq!(LedColor::Red.levels(), (1, 0));
+        assert_eq!(LedColor::Green.levels(), (0, 1));
+        assert_eq!(LedColor::Amber.levels(), (1, 1));
+    }
+
+    #[test]
+    fn test_open_missing_chip() {
+        let line = GpioLine::parse("gpiochip-missing:1").unwrap();
+        let err = GpioButton::open(&line).err().unwrap();
+        assert!(err.to_string().contains("/dev/gpiochip-missing"));
+    }
+}
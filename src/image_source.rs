@@ -0,0 +1,411 @@
+//! Still-image slate source
+//!
+//! `device = "image:/etc/camera-box/slate.png"` publishes a static slate
+//! instead of a camera, e.g. "CAMERA 3 - OFFLINE" during rehearsals. The PNG
+//! is decoded once (through ffmpeg, like MJPEG), letterboxed into the
+//! `[capture.image]` resolution and sent at its frame rate. The file's mtime
+//! is checked every few seconds and a changed slate is swapped in live.
+
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime};
+use v4l::FourCC;
+
+use crate::capture::{FrameInfo, FrameRate};
+use crate::color_range::ColorRange;
+use crate::config::ImageSourceConfig;
+use crate::ndi::{convert_bgra_to_uyvy_into, uyvy_frame_size};
+
+/// Device prefix selecting an image file instead of a V4L2 device
+pub const DEVICE_PREFIX: &str = "image:";
+
+/// How often the image file is checked for changes
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Black in limited range UYVY, for the letterbox bars
+const BLACK_PAIR: [u8; 4] = [128, 16, 128, 16];
+
+/// The image path of an "image:PATH" device, None for other devices
+pub fn image_path(device: &str) -> Option<&str> {
+    device.strip_prefix(DEVICE_PREFIX)
+}
+
+// =============================================================================
+// Decoding and Letterboxing
+// =============================================================================
+
+/// Width and height from a PNG's IHDR chunk
+pub fn png_dimensions(data: &[u8]) -> Result<(u32, u32)> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if data.len() < 24 || !data.starts_with(SIGNATURE) || &data[12..16] != b"IHDR" {
+        bail!("Not a PNG file");
+    }
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+    if width == 0 || height == 0 {
+        bail!("PNG has no pixels ({}x{})", width, height);
+    }
+    Ok((width, height))
+}
+
+/// Decode a PNG file to BGRA. Returns the pixels, width and height.
+fn decode_png(path: &Path) -> Result<(Vec<u8>, u32, u32)> {
+    let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let (width, height) =
+        png_dimensions(&data).with_context(|| format!("Invalid slate {}", path.display()))?;
+
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-i"])
+        .arg(path)
+        .args([
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "bgra",
+            "-frames:v",
+            "1",
+            "pipe:1",
+        ])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .context("PNG decode requires ffmpeg. Install with: apt install ffmpeg")?;
+    let expected = width as usize * height as usize * 4;
+    if !output.status.success() || output.stdout.len() != expected {
+        bail!("ffmpeg PNG decode of {} failed", path.display());
+    }
+    Ok((output.stdout, width, height))
+}
+
+/// Where an image lands inside the output frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Letterbox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Largest `image_width`x`image_height` aspect rectangle centred in the
+/// output frame. `x` and the width are even so UYVY pairs stay whole.
+pub fn letterbox(image_width: u32, image_height: u32, width: u32, height: u32) -> Letterbox {
+    let (iw, ih) = (image_width.max(1) as u64, image_height.max(1) as u64);
+    let (w, h) = (width as u64, height as u64);
+    let (fit_w, fit_h) = if iw * h >= ih * w {
+        // Wider than the frame: bars top and bottom
+        (w, (ih * w / iw).max(1))
+    } else {
+        // Taller than the frame: bars left and right
+        ((iw * h / ih).max(2) & !1, h)
+    };
+    Letterbox {
+        x: ((w - fit_w) / 2) as u32 & !1,
+        y: ((h - fit_h) / 2) as u32,
+        width: fit_w as u32,
+        height: fit_h as u32,
+    }
+}
+
+/// Bilinear resize of a BGRA image
+fn scale_bgra(src: &[u8], src_w: u32, src_h: u32, dst_w: u32, dst_h: u32) -> Vec<u8> {
+    let mut dst = vec![0; dst_w as usize * dst_h as usize * 4];
+    let sample = |x: u32, y: u32, c: usize| src[(y * src_w + x) as usize * 4 + c] as f32;
+    for y in 0..dst_h {
+        let fy = ((y as f32 + 0.5) * src_h as f32 / dst_h as f32 - 0.5).max(0.0);
+        let (y0, ty) = (fy as u32, fy.fract());
+        let y1 = (y0 + 1).min(src_h - 1);
+        let y0 = y0.min(src_h - 1);
+        for x in 0..dst_w {
+            let fx = ((x as f32 + 0.5) * src_w as f32 / dst_w as f32 - 0.5).max(0.0);
+            let (x0, tx) = (fx as u32, fx.fract());
+            let x1 = (x0 + 1).min(src_w - 1);
+            let x0 = x0.min(src_w - 1);
+            let out = (y * dst_w + x) as usize * 4;
+            for c in 0..4 {
+                let top = sample(x0, y0, c) * (1.0 - tx) + sample(x1, y0, c) * tx;
+                let bottom = sample(x0, y1, c) * (1.0 - tx) + sample(x1, y1, c) * tx;
+                dst[out + c] = (top * (1.0 - ty) + bottom * ty).round() as u8;
+            }
+        }
+    }
+    dst
+}
+
+/// Letterbox a BGRA image into a black `width`x`height` UYVY frame
+pub fn render_slate(
+    bgra: &[u8],
+    image_width: u32,
+    image_height: u32,
+    width: u32,
+    height: u32,
+) -> Vec<u8> {
+    let mut frame = BLACK_PAIR.repeat(uyvy_frame_size(width as usize, height as usize) / 4);
+    let place = letterbox(image_width, image_height, width, height);
+    let scaled = scale_bgra(bgra, image_width, image_height, place.width, place.height);
+    let mut boxed = vec![0; uyvy_frame_size(place.width as usize, place.height as usize)];
+    convert_bgra_to_uyvy_into(
+        &scaled,
+        place.width as usize,
+        place.height as usize,
+        place.width as usize * 4,
+        ColorRange::Limited,
+        &mut boxed,
+    );
+
+    let frame_row = uyvy_frame_size(width as usize, 1);
+    let box_row = uyvy_frame_size(place.width as usize, 1);
+    for (row, src) in boxed.chunks_exact(box_row).enumerate() {
+        let start = (place.y as usize + row) * frame_row + place.x as usize * 2;
+        frame[start..start + box_row].copy_from_slice(src);
+    }
+    frame
+}
+
+// =============================================================================
+// Pacing
+// =============================================================================
+
+/// Frame period of `rate`
+pub fn frame_period(rate: FrameRate) -> Duration {
+    Duration::from_nanos(
+        1_000_000_000 * rate.denominator.max(1) as u64 / rate.numerator.max(1) as u64,
+    )
+}
+
+/// Fixed-rate frame schedule. Due times advance by whole periods so the
+/// average rate is exact; after a hiccup of more than a period it resyncs
+/// to now instead of sending a burst of catch-up frames.
+#[derive(Debug)]
+pub struct FrameClock {
+    period: Duration,
+    next_due: Option<Instant>,
+}
+
+impl FrameClock {
+    pub fn new(rate: FrameRate) -> Self {
+        Self {
+            period: frame_period(rate),
+            next_due: None,
+        }
+    }
+
+    /// The due time of the next frame if it falls within `timeout` of `now`;
+    /// the schedule moves on to the following frame
+    pub fn next_frame(&mut self, now: Instant, timeout: Duration) -> Option<Instant> {
+        let due = match self.next_due {
+            Some(due) if now.saturating_duration_since(due) <= self.period => due,
+            _ => now,
+        };
+        if due > now + timeout {
+            return None;
+        }
+        self.next_due = Some(due + self.period);
+        Some(due)
+    }
+}
+
+// =============================================================================
+// Image Source
+// =============================================================================
+
+/// Publishes a still image at a fixed rate
+pub struct ImageSource {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    frame_rate: FrameRate,
+    clock: FrameClock,
+    frame: Vec<u8>,
+    modified: Option<SystemTime>,
+    next_check: Instant,
+}
+
+impl ImageSource {
+    /// Decode `path` and render it at `width`x`height` (width rounded up to even)
+    pub fn open(path: &Path, width: u32, height: u32, frame_rate: FrameRate) -> Result<Self> {
+        let width = width.div_ceil(2) * 2;
+        let mut source = Self {
+            path: path.to_path_buf(),
+            width,
+            height,
+            frame_rate,
+            clock: FrameClock::new(frame_rate),
+            frame: Vec::new(),
+            modified: None,
+            next_check: Instant::now() + RELOAD_CHECK_INTERVAL,
+        };
+        source.load()?;
+        tracing::info!(
+            "Slate {} at {}x{}, {}/{} fps",
+            path.display(),
+            width,
+            height,
+            frame_rate.numerator,
+            frame_rate.denominator
+        );
+        Ok(source)
+    }
+
+    pub fn from_config(path: &str, config: &ImageSourceConfig) -> Result<Self> {
+        let rate = FrameRate {
+            numerator: config.frame_rate_n,
+            denominator: config.frame_rate_d,
+        };
+        Self::open(Path::new(path), config.width, config.height, rate)
+    }
+
+    fn modified_time(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    fn load(&mut self) -> Result<()> {
+        let modified = self.modified_time();
+        let (bgra, image_width, image_height) = decode_png(&self.path)?;
+        self.frame = render_slate(&bgra, image_width, image_height, self.width, self.height);
+        self.modified = modified;
+        Ok(())
+    }
+
+    /// Reload the slate if the file changed; a broken file keeps the old one
+    fn check_reload(&mut self, now: Instant) {
+        if now < self.next_check {
+            return;
+        }
+        self.next_check = now + RELOAD_CHECK_INTERVAL;
+        let modified = self.modified_time();
+        if modified.is_none() || modified == self.modified {
+            return;
+        }
+        match self.load() {
+            Ok(()) => tracing::info!("Slate {} reloaded", self.path.display()),
+            Err(e) => {
+                // Don't retry the same broken file every check
+                self.modified = modified;
+                tracing::warn!("Keeping previous slate: {:#}", e);
+            }
+        }
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn frame_rate(&self) -> FrameRate {
+        self.frame_rate
+    }
+
+    pub fn frame_info(&self) -> FrameInfo {
+        FrameInfo::new(
+            self.width,
+            self.height,
+            FourCC::new(b"UYVY"),
+            self.width * 2,
+        )
+    }
+
+    /// Wait for the next frame (up to `timeout`) and pass it to `callback`.
+    /// Returns false if no frame was due within the timeout.
+    pub fn process_frame_timeout<F>(&mut self, timeout: Duration, mut callback: F) -> bool
+    where
+        F: FnMut(&[u8], FrameInfo),
+    {
+        let now = Instant::now();
+        self.check_reload(now);
+        let Some(due) = self.clock.next_frame(now, timeout) else {
+            std::thread::sleep(timeout);
+            return false;
+        };
+        std::thread::sleep(due.saturating_duration_since(now));
+        callback(&self.frame, self.frame_info());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_path() {
+        assert_eq!(image_path("image:/etc/slate.png"), Some("/etc/slate.png"));
+        assert_eq!(image_path("/dev/video0"), None);
+    }
+
+    #[test]
+    fn test_png_dimensions() {
+        let mut header = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        header.extend_from_slice(&1920u32.to_be_bytes());
+        header.extend_from_slice(&1080u32.to_be_bytes());
+        assert_eq!(png_dimensions(&header).unwrap(), (1920, 1080));
+        assert!(png_dimensions(b"GIF89a").is_err());
+        assert!(png_dimensions(&header[..20]).is_err());
+    }
+
+    #[test]
+    fn test_letterbox_placement() {
+        let boxed = |x, y, width, height| Letterbox {
+            x,
+            y,
+            width,
+            height,
+        };
+        // Same aspect fills the frame
+        assert_eq!(letterbox(1280, 720, 1920, 1080), boxed(0, 0, 1920, 1080));
+        // 4:3 into 16:9: pillarbox
+        assert_eq!(letterbox(640, 480, 1920, 1080), boxed(240, 0, 1440, 1080));
+        // 2.35:1 into 16:9: letterbox
+        assert_eq!(letterbox(2350, 1000, 1920, 1080), boxed(0, 131, 1920, 817));
+        // Odd pillar widths stay on pixel pairs
+        let tall = letterbox(3, 10, 64, 10);
+        assert_eq!((tall.x % 2, tall.width % 2), (0, 0));
+        assert_eq!(tall, boxed(30, 0, 2, 10));
+    }
+
+    #[test]
+    fn test_render_slate_fills_bars_black() {
+        // White 1x2 image into a 6x2 frame: pillarbox with the image in the middle pair
+        let frame = render_slate(&[255; 8], 1, 2, 6, 2);
+        assert_eq!(frame.len(), 24);
+        for row in frame.chunks_exact(12) {
+            assert_eq!(&row[..4], BLACK_PAIR);
+            assert_eq!(&row[4..8], [128, 235, 128, 235]);
+            assert_eq!(&row[8..], BLACK_PAIR);
+        }
+    }
+
+    #[test]
+    fn test_frame_period() {
+        let rate = |numerator, denominator| FrameRate {
+            numerator,
+            denominator,
+        };
+        assert_eq!(frame_period(rate(25, 1)), Duration::from_millis(40));
+        assert_eq!(
+            frame_period(rate(30000, 1001)),
+            Duration::from_nanos(33_366_666)
+        );
+        assert_eq!(frame_period(rate(0, 0)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_frame_clock_schedule() {
+        let mut clock = FrameClock::new(FrameRate {
+            numerator: 25,
+            denominator: 1,
+        });
+        let t0 = Instant::now();
+        let ms = Duration::from_millis;
+        assert_eq!(clock.next_frame(t0, ms(10)), Some(t0));
+        // Next frame at 40 ms is beyond a 10 ms timeout
+        assert_eq!(clock.next_frame(t0 + ms(5), ms(10)), None);
+        assert_eq!(clock.next_frame(t0 + ms(35), ms(10)), Some(t0 + ms(40)));
+        // Slightly late: keep the schedule
+        assert_eq!(clock.next_frame(t0 + ms(90), ms(10)), Some(t0 + ms(80)));
+        // More than a period behind: resync instead of bursting
+        assert_eq!(clock.next_frame(t0 + ms(500), ms(10)), Some(t0 + ms(500)));
+        assert_eq!(clock.next_frame(t0 + ms(500), ms(50)), Some(t0 + ms(540)));
+    }
+}
@@ -0,0 +1,901 @@
+//! Input device mute key monitor
+//!
+//! Watches every `/dev/input/event*` device that reports a configurable key
+//! (power button, USB keypad, footswitch). A short press toggles a shared
+//! mute flag, holding the key for a second pages the director instead (see
+//! [`PressGesture`]). Devices are waited on with epoll, unplugged devices are
+//! dropped on EPOLLERR/EPOLLHUP and new ones are picked up via inotify on
+//! /dev/input. The mute state is written back to the same devices as an
+//! LED (see [`MuteLed`]), so a headset's inline mute light follows it.
+
+use anyhow::{anyhow, Result};
+use evdev::{Device, Key, LedType};
+use std::collections::HashMap;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::gpio::ButtonEdge;
+use crate::io_util;
+
+const INPUT_DIR: &str = "/dev/input";
+
+/// epoll token of the inotify watch (device tokens are their fds)
+const INOTIFY_TOKEN: u64 = u64::MAX;
+
+/// epoll_wait timeout, bounds how long shutdown takes to be noticed
+const WAIT_TIMEOUT_MS: libc::c_int = 100;
+
+const EV_SYN: u16 = 0x00;
+const SYN_REPORT: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_LED: u16 = 0x11;
+const KEY_RELEASED: i32 = 0;
+const KEY_PRESSED: i32 = 1;
+
+/// Hold time that turns a press into a call
+pub const LONG_PRESS: Duration = Duration::from_secs(1);
+
+/// Parse an evdev key name such as "KEY_POWER" or "KEY_F13"
+pub fn parse_key_name(name: &str) -> Result<Key> {
+    name.trim()
+        .parse::<Key>()
+        .map_err(|_| anyhow!("Unknown key name: {:?} (expected e.g. \"KEY_F13\")", name))
+}
+
+/// True for a key-down event of `key` (repeats and releases are ignored)
+pub fn is_key_press(event: &libc::input_event, key: Key) -> bool {
+    event.type_ == EV_KEY && event.code == key.code() && event.value == KEY_PRESSED
+}
+
+/// The press or release of `key` an event reports; autorepeats are None
+pub fn key_edge(event: &libc::input_event, key: Key) -> Option<ButtonEdge> {
+    if event.type_ != EV_KEY || event.code != key.code() {
+        return None;
+    }
+    match event.value {
+        KEY_PRESSED => Some(ButtonEdge::Pressed),
+        KEY_RELEASED => Some(ButtonEdge::Released),
+        _ => None,
+    }
+}
+
+// =============================================================================
+// Press Gestures
+// =============================================================================
+
+/// What a press of the mute button meant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    /// Released before the long press time: toggle mute
+    Short,
+    /// Held for the long press time: page the director
+    Long,
+}
+
+/// Tells short presses from long ones. A long press is reported as soon
+/// as the hold time is reached (by [`PressGesture::poll`]), so the operator
+/// doesn't have to guess when to let go; its release is then ignored.
+/// Driven by an explicit clock like `SilenceDetector`.
+#[derive(Debug)]
+pub struct PressGesture {
+    hold: Duration,
+    pressed_at: Option<Instant>,
+    /// The current press was already reported as long
+    fired: bool,
+}
+
+impl PressGesture {
+    pub fn new(hold: Duration) -> Self {
+        Self {
+            hold,
+            pressed_at: None,
+            fired: false,
+        }
+    }
+
+    /// Feed a debounced edge at `now`
+    pub fn edge(&mut self, edge: ButtonEdge, now: Instant) -> Option<Gesture> {
+        match edge {
+            ButtonEdge::Pressed => {
+                self.pressed_at = Some(now);
+                self.fired = false;
+                None
+            }
+            ButtonEdge::Released => {
+                let pressed_at = self.pressed_at.take()?;
+                if self.fired {
+                    None
+                } else if now.saturating_duration_since(pressed_at) >= self.hold {
+                    Some(Gesture::Long)
+                } else {
+                    Some(Gesture::Short)
+                }
+            }
+        }
+    }
+
+    /// Check a held button; call regularly while waiting for edges
+    pub fn poll(&mut self, now: Instant) -> Option<Gesture> {
+        let pressed_at = self.pressed_at?;
+        if self.fired || now.saturating_duration_since(pressed_at) < self.hold {
+            return None;
+        }
+        self.fired = true;
+        Some(Gesture::Long)
+    }
+
+    /// Forget a press whose release will never come (device unplugged)
+    pub fn cancel(&mut self) {
+        self.pressed_at = None;
+    }
+}
+
+/// Act on a gesture of the mute button or key: a short press toggles
+/// `muted`, a long one raises `call` for the intercom to send
+pub fn apply_gesture(gesture: Gesture, muted: &AtomicBool, call: &AtomicBool, via: &str) {
+    match gesture {
+        Gesture::Short => {
+            let now_muted = !muted.fetch_xor(true, Ordering::Relaxed);
+            tracing::info!(
+                "🎤 Microphone {} (via {})",
+                if now_muted { "MUTED" } else { "UNMUTED" },
+                via
+            );
+        }
+        Gesture::Long => {
+            call.store(true, Ordering::Relaxed);
+            tracing::info!("📣 Calling the director (via {})", via);
+        }
+    }
+}
+
+// =============================================================================
+// Mute LEDs
+// =============================================================================
+
+/// Which LED of the mute key devices shows the mute state
+/// (`intercom.mute_led`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MuteLed {
+    /// A headset's mute LED, else a keyboard's scroll lock
+    #[default]
+    Auto,
+    /// Leave device LEDs alone
+    None,
+    /// Scroll lock only
+    ScrollLock,
+}
+
+impl MuteLed {
+    /// Parse a mode name from configuration ("auto", "none", "scrolllock")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "auto" => Ok(MuteLed::Auto),
+            "none" => Ok(MuteLed::None),
+            "scrolllock" => Ok(MuteLed::ScrollLock),
+            other => Err(anyhow!(
+                "Unsupported mute LED: {}. Supported: auto, none, scrolllock",
+                other
+            )),
+        }
+    }
+
+    /// The LED that shows mute on a device with the LEDs `has` accepts,
+    /// None if it has no suitable one
+    pub fn pick(self, has: impl Fn(LedType) -> bool) -> Option<LedType> {
+        let candidates: &[LedType] = match self {
+            MuteLed::Auto => &[LedType::LED_MUTE, LedType::LED_SCROLLL],
+            MuteLed::None => &[],
+            MuteLed::ScrollLock => &[LedType::LED_SCROLLL],
+        };
+        candidates.iter().copied().find(|&led| has(led))
+    }
+}
+
+fn input_event(type_: u16, code: u16, value: i32) -> libc::input_event {
+    libc::input_event {
+        time: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        type_,
+        code,
+        value,
+    }
+}
+
+/// The events that turn `led` on or off: the EV_LED change, then a
+/// SYN_REPORT so the device applies it
+pub fn led_events(led: LedType, on: bool) -> [libc::input_event; 2] {
+    [
+        input_event(EV_LED, led.0, on as i32),
+        input_event(EV_SYN, SYN_REPORT, 0),
+    ]
+}
+
+/// `events` as the bytes written to an evdev node
+pub fn event_bytes(events: &[libc::input_event]) -> &[u8] {
+    // SAFETY: input_event is plain data without padding
+    unsafe {
+        std::slice::from_raw_parts(events.as_ptr() as *const u8, std::mem::size_of_val(events))
+    }
+}
+
+/// Turn `led` of the device at `fd` on or off
+fn write_led(fd: RawFd, led: LedType, on: bool) -> std::io::Result<()> {
+    let events = led_events(led, on);
+    let bytes = event_bytes(&events);
+    let written = io_util::retry_eintr(|| {
+        let n = unsafe { libc::write(fd, bytes.as_ptr() as *const libc::c_void, bytes.len()) };
+        if n < 0 {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(n as usize)
+        }
+    })?;
+    // evdev takes whole events or none
+    if written < bytes.len() {
+        return Err(std::io::ErrorKind::WriteZero.into());
+    }
+    Ok(())
+}
+
+/// Show `muted` on every device with a suitable LED. Devices without one
+/// are skipped and failed writes (e.g. a node opened read-only) ignored.
+fn show_mute(table: &DeviceTable<Device>, mode: MuteLed, muted: bool) {
+    for (path, device) in table.iter() {
+        let supported = device.supported_leds();
+        let Some(led) = mode.pick(|led| supported.is_some_and(|leds| leds.contains(led))) else {
+            continue;
+        };
+        if let Err(e) = write_led(device.as_raw_fd(), led, muted) {
+            tracing::debug!("Cannot set {:?} on {}: {}", led, path.display(), e);
+        }
+    }
+}
+
+fn is_event_node(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with("event"))
+}
+
+/// List `event*` nodes in an input directory, sorted
+pub fn list_event_devices(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| is_event_node(p))
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Cannot list {}: {}", dir.display(), e);
+            Vec::new()
+        }
+    };
+    paths.sort();
+    paths
+}
+
+// =============================================================================
+// Device Bookkeeping
+// =============================================================================
+
+/// Known input devices by path. Devices without the mute key are remembered
+/// as `None` so rescans don't reopen them; nodes that fail to open (e.g.
+/// before udev has fixed their permissions) are not recorded and get retried.
+#[derive(Debug)]
+pub struct DeviceTable<T> {
+    devices: HashMap<PathBuf, Option<T>>,
+}
+
+impl<T> Default for DeviceTable<T> {
+    fn default() -> Self {
+        Self {
+            devices: HashMap::new(),
+        }
+    }
+}
+
+impl<T> DeviceTable<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reconcile against the nodes currently present. Entries whose node is
+    /// gone are removed and returned; `open` is called for each new node.
+    pub fn rescan<F>(&mut self, present: &[PathBuf], mut open: F) -> Vec<(PathBuf, T)>
+    where
+        F: FnMut(&Path) -> std::io::Result<Option<T>>,
+    {
+        let gone: Vec<PathBuf> = self
+            .devices
+            .keys()
+            .filter(|p| !present.contains(p))
+            .cloned()
+            .collect();
+        let mut removed = Vec::new();
+        for path in gone {
+            if let Some(Some(device)) = self.devices.remove(&path) {
+                removed.push((path, device));
+            }
+        }
+        for path in present {
+            if !self.devices.contains_key(path) {
+                if let Ok(device) = open(path) {
+                    self.devices.insert(path.clone(), device);
+                }
+            }
+        }
+        removed
+    }
+
+    /// Forget a device (e.g. after EPOLLERR). It is reopened on the next
+    /// rescan if its node reappears.
+    pub fn remove(&mut self, path: &Path) -> Option<T> {
+        self.devices.remove(path).flatten()
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&T> {
+        self.devices.get(path).and_then(|d| d.as_ref())
+    }
+
+    /// Opened devices
+    pub fn iter(&self) -> impl Iterator<Item = (&PathBuf, &T)> {
+        self.devices
+            .iter()
+            .filter_map(|(p, d)| d.as_ref().map(|d| (p, d)))
+    }
+
+    /// Number of opened devices
+    pub fn len(&self) -> usize {
+        self.devices.values().filter(|d| d.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// =============================================================================
+// epoll / inotify Plumbing
+// =============================================================================
+
+fn cvt(ret: libc::c_int) -> std::io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+fn epoll_add(epoll: &OwnedFd, fd: RawFd, token: u64) -> std::io::Result<()> {
+    let mut event = libc::epoll_event {
+        events: (libc::EPOLLIN | libc::EPOLLERR | libc::EPOLLHUP) as u32,
+        u64: token,
+    };
+    cvt(unsafe { libc::epoll_ctl(epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) })?;
+    Ok(())
+}
+
+fn epoll_del(epoll: &OwnedFd, fd: RawFd) {
+    unsafe {
+        libc::epoll_ctl(
+            epoll.as_raw_fd(),
+            libc::EPOLL_CTL_DEL,
+            fd,
+            std::ptr::null_mut(),
+        );
+    }
+}
+
+fn open_inotify(dir: &Path) -> Result<OwnedFd> {
+    let fd = cvt(unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) })
+        .map_err(|e| anyhow!("inotify_init1 failed: {}", e))?;
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let c_dir = std::ffi::CString::new(dir.as_os_str().as_encoded_bytes())?;
+    // IN_ATTRIB catches udev fixing node permissions after creation
+    let mask = libc::IN_CREATE | libc::IN_DELETE | libc::IN_ATTRIB;
+    cvt(unsafe { libc::inotify_add_watch(fd.as_raw_fd(), c_dir.as_ptr(), mask) })
+        .map_err(|e| anyhow!("Cannot watch {}: {}", dir.display(), e))?;
+    Ok(fd)
+}
+
+/// Drain pending inotify events; only whether any arrived matters
+fn drain_fd(fd: RawFd) {
+    let mut buf = [0u8; 4096];
+    while unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+}
+
+fn open_key_device(path: &Path, key: Key) -> std::io::Result<Option<Device>> {
+    let device = Device::open(path)?;
+    if !device
+        .supported_keys()
+        .is_some_and(|keys| keys.contains(key))
+    {
+        return Ok(None);
+    }
+    let fd = device.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+    }
+    tracing::info!(
+        "Found {:?} device: {} ({})",
+        key,
+        device.name().unwrap_or("unknown"),
+        path.display()
+    );
+    Ok(Some(device))
+}
+
+/// Read all pending events from a device into `edges` (presses and
+/// releases of `key`), failing once the device is gone
+fn read_key_edges(fd: RawFd, key: Key, edges: &mut Vec<ButtonEdge>) -> std::io::Result<()> {
+    const EVENT_SIZE: usize = std::mem::size_of::<libc::input_event>();
+    let mut events: [libc::input_event; 16] = unsafe { std::mem::zeroed() };
+    loop {
+        let n = unsafe {
+            libc::read(
+                fd,
+                events.as_mut_ptr() as *mut libc::c_void,
+                EVENT_SIZE * events.len(),
+            )
+        };
+        if n < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::WouldBlock {
+                return Ok(());
+            }
+            return Err(err);
+        }
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        let count = n as usize / EVENT_SIZE;
+        edges.extend(events[..count].iter().filter_map(|e| key_edge(e, key)));
+    }
+}
+
+// =============================================================================
+// Mute Key Monitor
+// =============================================================================
+
+/// Toggle `muted` on short presses of `key` on any input device and raise
+/// `call` on long ones, until `running` is cleared. The `led` of those
+/// devices follows `muted`, whatever toggled it.
+pub fn run_mute_key_monitor(
+    key: Key,
+    led: MuteLed,
+    muted: Arc<AtomicBool>,
+    call: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+) {
+    if let Err(e) = monitor_mute_key(key, led, &muted, &call, &running) {
+        tracing::warn!("Mute key monitor stopped: {}", e);
+    }
+}
+
+fn monitor_mute_key(
+    key: Key,
+    led: MuteLed,
+    muted: &AtomicBool,
+    call: &AtomicBool,
+    running: &AtomicBool,
+) -> Result<()> {
+    let dir = Path::new(INPUT_DIR);
+    let epoll = cvt(unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) })
+        .map_err(|e| anyhow!("epoll_create1 failed: {}", e))?;
+    let epoll = unsafe { OwnedFd::from_raw_fd(epoll) };
+
+    let inotify = match open_inotify(dir) {
+        Ok(fd) => {
+            epoll_add(&epoll, fd.as_raw_fd(), INOTIFY_TOKEN)?;
+            Some(fd)
+        }
+        Err(e) => {
+            tracing::warn!("Input hotplug disabled: {}", e);
+            None
+        }
+    };
+
+    let mut table: DeviceTable<Device> = DeviceTable::new();
+    let mut paths_by_fd: HashMap<RawFd, PathBuf> = HashMap::new();
+    let rescan = |table: &mut DeviceTable<Device>, paths_by_fd: &mut HashMap<RawFd, PathBuf>| {
+        let present = list_event_devices(dir);
+        for (path, device) in table.rescan(&present, |p| open_key_device(p, key)) {
+            tracing::info!("Input device removed: {}", path.display());
+            paths_by_fd.remove(&device.as_raw_fd());
+            epoll_del(&epoll, device.as_raw_fd());
+        }
+        for (path, device) in table.iter() {
+            let fd = device.as_raw_fd();
+            if paths_by_fd.contains_key(&fd) {
+                continue;
+            }
+            match epoll_add(&epoll, fd, fd as u64) {
+                Ok(()) => {
+                    paths_by_fd.insert(fd, path.clone());
+                }
+                Err(e) => tracing::warn!("Cannot watch {}: {}", path.display(), e),
+            }
+        }
+    };
+
+    rescan(&mut table, &mut paths_by_fd);
+    if table.is_empty() {
+        tracing::warn!("No {:?} device found yet - waiting for hotplug", key);
+    } else {
+        tracing::info!("Mute key {:?} enabled ({} devices)", key, table.len());
+    }
+
+    // One gesture for all devices: a single key is held at a time
+    let mut gesture = PressGesture::new(LONG_PRESS);
+    let mut edges = Vec::new();
+    let mut events: [libc::epoll_event; 8] = unsafe { std::mem::zeroed() };
+    // Mute state the LEDs show; None writes it again, e.g. after a hotplug
+    let mut shown = None;
+    while running.load(Ordering::Relaxed) {
+        let now_muted = muted.load(Ordering::Relaxed);
+        if led != MuteLed::None && shown != Some(now_muted) {
+            show_mute(&table, led, now_muted);
+            shown = Some(now_muted);
+        }
+        if let Some(long) = gesture.poll(Instant::now()) {
+            apply_gesture(long, muted, call, &format!("{:?}", key));
+        }
+        let n = unsafe {
+            libc::epoll_wait(
+                epoll.as_raw_fd(),
+                events.as_mut_ptr(),
+                events.len() as libc::c_int,
+                WAIT_TIMEOUT_MS,
+            )
+        };
+        if n < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(anyhow!("epoll_wait failed: {}", err));
+        }
+
+        let mut needs_rescan = false;
+        for event in &events[..n as usize] {
+            let token = event.u64;
+            if token == INOTIFY_TOKEN {
+                if let Some(fd) = &inotify {
+                    drain_fd(fd.as_raw_fd());
+                }
+                needs_rescan = true;
+                continue;
+            }
+
+            let fd = token as RawFd;
+            let Some(path) = paths_by_fd.get(&fd).cloned() else {
+                continue;
+            };
+            let failed = event.events & (libc::EPOLLERR | libc::EPOLLHUP) as u32 != 0;
+            edges.clear();
+            let result = if failed {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            } else {
+                read_key_edges(fd, key, &mut edges)
+            };
+            match result {
+                Ok(()) => {
+                    let now = Instant::now();
+                    for &edge in &edges {
+                        if let Some(done) = gesture.edge(edge, now) {
+                            apply_gesture(done, muted, call, &path.display().to_string());
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::info!("Input device lost: {} ({})", path.display(), e);
+                    gesture.cancel();
+                    epoll_del(&epoll, fd);
+                    paths_by_fd.remove(&fd);
+                    table.remove(&path);
+                }
+            }
+        }
+
+        if needs_rescan {
+            rescan(&mut table, &mut paths_by_fd);
+            shown = None;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names
+            .iter()
+            .map(|n| PathBuf::from(format!("/dev/input/{}", n)))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_key_name() {
+        assert_eq!(parse_key_name("KEY_POWER").unwrap(), Key::KEY_POWER);
+        assert_eq!(parse_key_name("KEY_F13").unwrap(), Key::KEY_F13);
+        assert_eq!(parse_key_name(" KEY_MUTE ").unwrap(), Key::KEY_MUTE);
+    }
+
+    #[test]
+    fn test_parse_key_name_rejects_unknown() {
+        assert!(parse_key_name("KEY_NOPE").is_err());
+        assert!(parse_key_name("key_power").is_err());
+        assert!(parse_key_name("").is_err());
+    }
+
+    #[test]
+    fn test_is_key_press() {
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        event.type_ = EV_KEY;
+        event.code = Key::KEY_F13.code();
+        event.value = KEY_PRESSED;
+        assert!(is_key_press(&event, Key::KEY_F13));
+        assert!(!is_key_press(&event, Key::KEY_POWER));
+
+        event.value = 0; // release
+        assert!(!is_key_press(&event, Key::KEY_F13));
+        event.value = 2; // autorepeat
+        assert!(!is_key_press(&event, Key::KEY_F13));
+    }
+
+    #[test]
+    fn test_key_edge() {
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        event.type_ = EV_KEY;
+        event.code = Key::KEY_F13.code();
+        event.value = KEY_PRESSED;
+        assert_eq!(key_edge(&event, Key::KEY_F13), Some(ButtonEdge::Pressed));
+        assert_eq!(key_edge(&event, Key::KEY_POWER), None);
+        event.value = KEY_RELEASED;
+        assert_eq!(key_edge(&event, Key::KEY_F13), Some(ButtonEdge::Released));
+        event.value = 2; // autorepeat
+        assert_eq!(key_edge(&event, Key::KEY_F13), None);
+    }
+
+    #[test]
+    fn test_led_event_encoding() {
+        let [led, syn] = led_events(LedType::LED_MUTE, true);
+        assert_eq!((led.type_, led.code, led.value), (0x11, 0x07, 1));
+        assert_eq!((syn.type_, syn.code, syn.value), (0, 0, 0));
+        let [off, _] = led_events(LedType::LED_SCROLLL, false);
+        assert_eq!((off.type_, off.code, off.value), (0x11, 0x02, 0));
+
+        // The kernel reads type, code and value after the timestamp
+        let events = led_events(LedType::LED_MUTE, true);
+        let bytes = event_bytes(&events);
+        let size = std::mem::size_of::<libc::input_event>();
+        assert_eq!(bytes.len(), 2 * size);
+        let at = std::mem::size_of::<libc::timeval>();
+        assert_eq!(&bytes[at..at + 2], &0x11u16.to_ne_bytes());
+        assert_eq!(&bytes[at + 2..at + 4], &0x07u16.to_ne_bytes());
+        assert_eq!(&bytes[at + 4..at + 8], &1i32.to_ne_bytes());
+        assert!(bytes[size..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_write_led_sends_whole_events() {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (read_end, write_end) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+        write_led(write_end.as_raw_fd(), LedType::LED_MUTE, true).unwrap();
+
+        let expected = led_events(LedType::LED_MUTE, true);
+        let mut buf = vec![0u8; 64];
+        let n = unsafe {
+            libc::read(
+                read_end.as_raw_fd(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+        assert_eq!(&buf[..n as usize], event_bytes(&expected));
+
+        // A node that can't take the write is an error for the caller to
+        // ignore, not a panic
+        assert!(write_led(read_end.as_raw_fd(), LedType::LED_MUTE, false).is_err());
+    }
+
+    #[test]
+    fn test_mute_led_picks_a_supported_led() {
+        assert_eq!(MuteLed::from_name("auto").unwrap(), MuteLed::Auto);
+        assert_eq!(MuteLed::from_name("None").unwrap(), MuteLed::None);
+        assert_eq!(
+            MuteLed::from_name("scrolllock").unwrap(),
+            MuteLed::ScrollLock
+        );
+        assert!(MuteLed::from_name("capslock").is_err());
+
+        let headset = |led: LedType| led == LedType::LED_MUTE;
+        let keyboard = |led: LedType| {
+            [LedType::LED_NUML, LedType::LED_CAPSL, LedType::LED_SCROLLL].contains(&led)
+        };
+        let no_leds = |_: LedType| false;
+        assert_eq!(MuteLed::Auto.pick(headset), Some(LedType::LED_MUTE));
+        assert_eq!(MuteLed::Auto.pick(keyboard), Some(LedType::LED_SCROLLL));
+        assert_eq!(MuteLed::Auto.pick(no_leds), None);
+        assert_eq!(MuteLed::ScrollLock.pick(headset), None);
+        assert_eq!(
+            MuteLed::ScrollLock.pick(keyboard),
+            Some(LedType::LED_SCROLLL)
+        );
+        assert_eq!(MuteLed::None.pick(keyboard), None);
+    }
+
+    #[test]
+    fn test_short_press_toggles_on_release() {
+        let start = Instant::now();
+        let mut gesture = PressGesture::new(LONG_PRESS);
+        assert_eq!(gesture.edge(ButtonEdge::Pressed, start), None);
+        assert_eq!(gesture.poll(start + Duration::from_millis(500)), None);
+        let release = start + Duration::from_millis(900);
+        assert_eq!(
+            gesture.edge(ButtonEdge::Released, release),
+            Some(Gesture::Short)
+        );
+        // Nothing held any more
+        assert_eq!(gesture.poll(release + Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn test_long_press_fires_while_held() {
+        let start = Instant::now();
+        let mut gesture = PressGesture::new(LONG_PRESS);
+        gesture.edge(ButtonEdge::Pressed, start);
+        assert_eq!(gesture.poll(start + Duration::from_millis(999)), None);
+        assert_eq!(gesture.poll(start + LONG_PRESS), Some(Gesture::Long));
+        // Reported once, and the release doesn't toggle mute
+        assert_eq!(gesture.poll(start + Duration::from_secs(2)), None);
+        assert_eq!(
+            gesture.edge(ButtonEdge::Released, start + Duration::from_secs(3)),
+            None
+        );
+
+        // The next short press is a short press again
+        let next = start + Duration::from_secs(4);
+        gesture.edge(ButtonEdge::Pressed, next);
+        assert_eq!(
+            gesture.edge(ButtonEdge::Released, next + Duration::from_millis(100)),
+            Some(Gesture::Short)
+        );
+    }
+
+    #[test]
+    fn test_long_press_released_between_polls() {
+        // The release can arrive before a poll saw the hold time pass
+        let start = Instant::now();
+        let mut gesture = PressGesture::new(LONG_PRESS);
+        gesture.edge(ButtonEdge::Pressed, start);
+        assert_eq!(
+            gesture.edge(ButtonEdge::Released, start + Duration::from_millis(1050)),
+            Some(Gesture::Long)
+        );
+    }
+
+    #[test]
+    fn test_release_without_press_and_cancel() {
+        let start = Instant::now();
+        let mut gesture = PressGesture::new(LONG_PRESS);
+        assert_eq!(gesture.edge(ButtonEdge::Released, start), None);
+
+        gesture.edge(ButtonEdge::Pressed, start);
+        gesture.cancel();
+        assert_eq!(gesture.poll(start + Duration::from_secs(2)), None);
+    }
+
+    #[test]
+    fn test_apply_gesture() {
+        let muted = AtomicBool::new(true);
+        let call = AtomicBool::new(false);
+        apply_gesture(Gesture::Short, &muted, &call, "test");
+        assert!(!muted.load(Ordering::Relaxed));
+        assert!(!call.load(Ordering::Relaxed));
+
+        apply_gesture(Gesture::Long, &muted, &call, "test");
+        assert!(!muted.load(Ordering::Relaxed));
+        assert!(call.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_list_event_devices_filters_and_sorts() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["event12", "event3", "mouse0", "by-id"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let found = list_event_devices(dir.path());
+        let names: Vec<_> = found
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["event12", "event3"]);
+    }
+
+    #[test]
+    fn test_rescan_opens_only_new_devices() {
+        let mut table = DeviceTable::new();
+        let mut opened = Vec::new();
+        table.rescan(&paths(&["event0", "event1"]), |p| {
+            opened.push(p.to_path_buf());
+            Ok(Some(1))
+        });
+        assert_eq!(opened.len(), 2);
+        assert_eq!(table.len(), 2);
+
+        opened.clear();
+        table.rescan(&paths(&["event0", "event1", "event15"]), |p| {
+            opened.push(p.to_path_buf());
+            Ok(Some(2))
+        });
+        assert_eq!(opened, paths(&["event15"]));
+        assert_eq!(table.len(), 3);
+    }
+
+    #[test]
+    fn test_rescan_remembers_devices_without_key() {
+        let mut table: DeviceTable<u32> = DeviceTable::new();
+        let mut calls = 0;
+        for _ in 0..2 {
+            table.rescan(&paths(&["event0"]), |_| {
+                calls += 1;
+                Ok(None)
+            });
+        }
+        assert_eq!(calls, 1);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_rescan_retries_failed_opens() {
+        let mut table = DeviceTable::new();
+        let present = paths(&["event9"]);
+        table.rescan(&present, |_| {
+            Err(std::io::ErrorKind::PermissionDenied.into())
+        });
+        assert!(table.is_empty());
+
+        // udev fixed the permissions - the next rescan opens it
+        table.rescan(&present, |_| Ok(Some(7)));
+        assert_eq!(table.get(&present[0]), Some(&7));
+    }
+
+    #[test]
+    fn test_rescan_removes_unplugged_devices() {
+        let mut table = DeviceTable::new();
+        table.rescan(&paths(&["event0", "event4", "event5"]), |p| {
+            Ok((!p.ends_with("event5")).then(|| p.to_path_buf()))
+        });
+        assert_eq!(table.len(), 2);
+
+        let removed = table.rescan(&paths(&["event0"]), |_| Ok(None));
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].0, paths(&["event4"])[0]);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_allows_reopen() {
+        let mut table = DeviceTable::new();
+        let present = paths(&["event2"]);
+        table.rescan(&present, |_| Ok(Some(1)));
+        assert_eq!(table.remove(&present[0]), Some(1));
+        assert!(table.is_empty());
+
+        // Node reappeared (replug) - opened again
+        table.rescan(&present, |_| Ok(Some(2)));
+        assert_eq!(table.get(&present[0]), Some(&2));
+    }
+}
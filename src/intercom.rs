@@ -7,102 +7,217 @@
 
 use alsa::pcm::{Access, Format, HwParams, PCM};
 use alsa::{Direction, ValueOr};
-use anyhow::{anyhow, Context, Result};
-use evdev::{Device, Key};
-use std::collections::VecDeque;
-use std::net::UdpSocket;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use anyhow::{anyhow, Result};
+use evdev::Key;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender};
 use std::sync::Arc;
 use std::sync::Mutex;
-
-use crate::vban::{VbanCodec, VbanHeader, MAX_VBAN_PACKET_SIZE, VBAN_HEADER_SIZE, VBAN_PORT};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use crate::gpio::{GpioButton, GpioLed, GpioLine, LedColor, TallyLedPins};
+use crate::input::{self, MuteLed, PressGesture, LONG_PRESS};
+use crate::io_util::{self, SendOutcome};
+use crate::net;
+use crate::stats::{Component, StatsRegistry};
+use crate::threads;
+use crate::vban::{
+    decode_samples, decode_text_packet, encode_text_packet, is_ping_request, VbanCodec, VbanHeader,
+    VbanPacketWriter, VbanPing, MAX_VBAN_PACKET_SIZE, VBAN_HEADER_SIZE, VBAN_PING_FEATURE_AUDIO,
+    VBAN_PING_FEATURE_VOIP, VBAN_PING_TYPE_RECEPTOR, VBAN_PING_TYPE_TRANSMITTER, VBAN_PORT,
+};
+use crate::wav::SegmentedWavWriter;
 
 // ALSA configuration - optimized for low latency
-const ALSA_DEVICE: &str = "hw:CARD=HID,DEV=0";
-const SAMPLE_RATE: u32 = 48000;
+pub const ALSA_DEVICE: &str = "hw:CARD=HID,DEV=0";
+pub const SAMPLE_RATE: u32 = 48000;
 const PERIOD_SIZE: u32 = 256; // ~5.3ms at 48kHz - low latency
 const BUFFER_PERIODS: u32 = 4; // 4 periods = ~21ms total buffer
 
+/// Period sizes tried in turn, above the configured one, when a device
+/// refuses it
+const PERIOD_FALLBACKS: [u32; 4] = [128, 256, 512, 1024];
+
+/// Longest wait for a PCM period, bounds how long shutdown takes to be noticed
+const ALSA_WAIT_MS: u32 = 100;
+
+/// Granularity of interruptible sleeps (retry delays, LED blink)
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+// Link monitoring
+const DEFAULT_LINK_TIMEOUT_MS: u64 = 2000;
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1); // While muted
+const STALL_CHECK_INTERVAL: Duration = Duration::from_secs(10); // No samples: restart
+
+/// Why the intercom could not open an audio device, socket or recording
+#[derive(Debug, thiserror::Error)]
+pub enum IntercomError {
+    /// Opening or configuring a PCM failed; `errno` tells a missing device
+    /// (ENOENT) from one another process holds (EBUSY)
+    #[error("Failed to open ALSA {direction} device {device}")]
+    AlsaDevice {
+        device: String,
+        direction: &'static str,
+        errno: i32,
+        #[source]
+        source: alsa::Error,
+    },
+    #[error("Failed to bind VBAN socket to {addr}")]
+    Bind {
+        addr: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Failed to connect VBAN sender to {target}")]
+    Connect {
+        target: String,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The configured interface can't be bound to
+    #[error(transparent)]
+    Interface(anyhow::Error),
+    /// The recording directory or its first segments can't be written
+    #[error(transparent)]
+    Recording(anyhow::Error),
+}
+
+impl IntercomError {
+    fn alsa(device: &str, direction: Direction, source: alsa::Error) -> Self {
+        IntercomError::AlsaDevice {
+            device: device.to_string(),
+            direction: match direction {
+                Direction::Capture => "capture",
+                Direction::Playback => "playback",
+            },
+            errno: source.errno(),
+            source,
+        }
+    }
+}
+
 // =============================================================================
-// Power Button Mute Toggle
+// GPIO Mute Button and Tally LED
 // =============================================================================
 
-fn find_power_buttons() -> Vec<(String, i32)> {
-    let mut devices = Vec::new();
-    for i in 0..10 {
-        let path = format!("/dev/input/event{}", i);
-        if let Ok(device) = Device::open(&path) {
-            if let Some(keys) = device.supported_keys() {
-                if keys.contains(Key::KEY_POWER) {
-                    let name = device.name().unwrap_or("unknown").to_string();
-                    tracing::info!("Found power button: {} ({})", name, path);
-                    use std::os::unix::io::AsRawFd;
-                    let fd = device.as_raw_fd();
-                    let dup_fd = unsafe { libc::dup(fd) };
-                    if dup_fd >= 0 {
-                        devices.push((path, dup_fd));
-                    }
+/// LED blink half-period while muted
+const LED_BLINK_INTERVAL: Duration = Duration::from_millis(400);
+
+/// How often the LED is updated; short enough for the call blink
+const LED_TICK: Duration = Duration::from_millis(50);
+
+/// Short presses toggle `muted`, long presses raise `call`
+fn run_gpio_button_monitor(
+    line: GpioLine,
+    muted: Arc<AtomicBool>,
+    call: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+) {
+    let mut button = match GpioButton::open(&line) {
+        Ok(b) => b,
+        Err(e) => {
+            tracing::warn!("GPIO mute button disabled: {}", e);
+            return;
+        }
+    };
+    tracing::info!("GPIO mute button enabled ({}:{})", line.chip, line.offset);
+
+    let via = format!("GPIO {}:{}", line.chip, line.offset);
+    let mut gesture = PressGesture::new(LONG_PRESS);
+    while running.load(Ordering::Relaxed) {
+        let edge = button.wait_edge(Duration::from_millis(100));
+        let now = Instant::now();
+        match edge {
+            Ok(Some(edge)) => {
+                if let Some(done) = gesture.edge(edge, now) {
+                    input::apply_gesture(done, &muted, &call, &via);
                 }
             }
+            Ok(None) => {
+                if let Some(long) = gesture.poll(now) {
+                    input::apply_gesture(long, &muted, &call, &via);
+                }
+            }
+            Err(e) => {
+                tracing::warn!("GPIO mute button stopped: {}", e);
+                return;
+            }
         }
     }
-    devices
 }
 
-fn run_power_button_monitor(muted: Arc<AtomicBool>, running: Arc<AtomicBool>) {
-    let devices = find_power_buttons();
-    if devices.is_empty() {
-        tracing::warn!("No power button found - mute toggle disabled");
-        return;
+/// LED color for the current tally and mute state.
+/// Program = red, preview = green; muted blinks (amber when there is no tally).
+pub fn tally_led_color(tally: Tally, muted: bool, blink_on: bool) -> LedColor {
+    let base = match tally {
+        Tally::Program => LedColor::Red,
+        Tally::Preview => LedColor::Green,
+        Tally::Off => LedColor::Off,
+    };
+    match (muted, blink_on) {
+        (false, _) => base,
+        (true, false) => LedColor::Off,
+        (true, true) if base == LedColor::Off => LedColor::Amber,
+        (true, true) => base,
     }
+}
 
-    for (_path, fd) in &devices {
-        unsafe {
-            let flags = libc::fcntl(*fd, libc::F_GETFL);
-            libc::fcntl(*fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
-        }
+/// LED color with an incoming call on top: while `call_blink` is Some the
+/// LED flashes amber, otherwise it shows the tally and mute state
+pub fn led_color(tally: Tally, muted: bool, call_blink: Option<bool>, blink_on: bool) -> LedColor {
+    match call_blink {
+        Some(true) => LedColor::Amber,
+        Some(false) => LedColor::Off,
+        None => tally_led_color(tally, muted, blink_on),
     }
+}
 
+fn run_tally_led(pins: TallyLedPins, stats: Arc<IntercomStats>, running: Arc<AtomicBool>) {
+    let mut led = match GpioLed::open(&pins.chip, pins.red, pins.green) {
+        Ok(led) => led,
+        Err(e) => {
+            tracing::warn!("Tally LED disabled: {}", e);
+            return;
+        }
+    };
     tracing::info!(
-        "Power button mute toggle enabled ({} devices)",
-        devices.len()
+        "Tally LED enabled ({}: red={}, green={})",
+        pins.chip,
+        pins.red,
+        pins.green
     );
-    let mut event_buf = [0u8; 24];
 
+    let start = Instant::now();
     while running.load(Ordering::Relaxed) {
-        for (path, fd) in &devices {
-            let n = unsafe {
-                libc::read(
-                    *fd,
-                    event_buf.as_mut_ptr() as *mut libc::c_void,
-                    event_buf.len(),
-                )
-            };
-            if n == 24 {
-                let event_type = u16::from_ne_bytes([event_buf[16], event_buf[17]]);
-                let event_code = u16::from_ne_bytes([event_buf[18], event_buf[19]]);
-                let event_value = i32::from_ne_bytes([
-                    event_buf[20],
-                    event_buf[21],
-                    event_buf[22],
-                    event_buf[23],
-                ]);
-                if event_type == 1 && event_code == 116 && event_value == 1 {
-                    let was_muted = muted.fetch_xor(true, Ordering::Relaxed);
-                    let now_muted = !was_muted;
-                    tracing::info!(
-                        "🎤 Microphone {} (via {})",
-                        if now_muted { "MUTED" } else { "UNMUTED" },
-                        path
-                    );
-                }
-            }
+        let now = Instant::now();
+        let phase = now.duration_since(start).as_millis() / LED_BLINK_INTERVAL.as_millis();
+        let color = led_color(
+            stats.tally(),
+            stats.muted.load(Ordering::Relaxed),
+            stats.call_blink(now),
+            phase % 2 == 0,
+        );
+        if let Err(e) = led.set(color) {
+            tracing::warn!("Tally LED stopped: {}", e);
+            return;
         }
-        std::thread::sleep(std::time::Duration::from_millis(20));
+        sleep_while_running(&running, LED_TICK);
     }
+}
 
-    for (_path, fd) in devices {
-        unsafe { libc::close(fd) };
+/// Sleep for `duration`, returning early once `running` is cleared
+fn sleep_while_running(running: &AtomicBool, duration: Duration) {
+    let deadline = Instant::now() + duration;
+    while running.load(Ordering::Relaxed) {
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+            return;
+        };
+        std::thread::sleep(left.min(SHUTDOWN_POLL));
     }
 }
 
@@ -113,6 +228,8 @@ fn run_power_button_monitor(muted: Arc<AtomicBool>, running: Arc<AtomicBool>) {
 #[derive(Debug, Clone)]
 pub struct IntercomConfig {
     pub stream_name: String,
+    /// Match incoming stream names ignoring ASCII case
+    pub stream_ignore_case: bool,
     pub target_host: String,
     #[allow(dead_code)] // Config API, uses SAMPLE_RATE constant internally
     pub sample_rate: u32,
@@ -127,12 +244,123 @@ pub struct IntercomConfig {
     pub limiter_enabled: bool,
     /// Limiter threshold as fraction of max (0.5 = -6dB)
     pub limiter_threshold: f32,
+    /// Hosts allowed to send VBAN audio (IPs or hostnames, empty = any)
+    pub allowed_sources: Vec<String>,
+    /// Latch onto the first source seen and reject others until it goes silent
+    pub auto_lock: bool,
+    /// Time without VBAN packets before the link is reported down
+    pub link_timeout_ms: u64,
+    /// Codec for the outbound VBAN stream (PCM16, PCM24 or Float32)
+    pub tx_codec: VbanCodec,
+    /// Host name reported in VBAN ping replies
+    pub hostname: String,
+    /// Send an unsolicited VBAN identification to the target periodically
+    pub ping_announce: bool,
+    /// DSCP value marked on VBAN packets (46 = Expedited Forwarding)
+    pub dscp: u8,
+    /// Receive socket buffer size in bytes
+    pub recv_buffer_size: usize,
+    /// Network interface to pin the intercom sockets to (None = any)
+    pub interface: Option<String>,
+    /// UDP port of the target's VBAN receiver
+    pub port: u16,
+    /// Local address the VBAN receiver binds to (port 0 = ephemeral);
+    /// `[::]` listens dual-stack
+    pub listen: String,
+    /// Local IP the VBAN sender binds to (None = wildcard of the target's family)
+    pub bind_address: Option<IpAddr>,
+    /// Directory for rolling rx/tx WAV recordings (None = off)
+    pub record_dir: Option<String>,
+    /// Length of each recording segment in seconds
+    pub record_segment_secs: u64,
+    /// Recording segments kept per direction
+    pub record_keep: usize,
+    /// Echo suppressor settings
+    pub echo: EchoConfig,
+    /// Dead mic detection settings
+    pub silence: SilenceConfig,
+    /// Ear placement of each headphone source
+    pub routing: Routing,
+    /// Input device key that toggles mute (power button, keypad, footswitch)
+    pub mute_key: Key,
+    /// LED of the mute key devices that shows mute
+    pub mute_led: MuteLed,
+    /// GPIO line of a physical mute button (None = mute key only)
+    pub button_gpio: Option<GpioLine>,
+    /// GPIO lines of a red/green tally LED
+    pub tally_led: Option<TallyLedPins>,
+    /// Tally state that opens the mic by itself
+    pub auto_unmute: AutoUnmute,
+    /// Send a short tone burst with a call, so the director hears it too
+    pub call_tone: bool,
+    /// Channel used as the mic when the capture device is stereo
+    pub mic_channel: MicChannel,
+    /// ALSA PCM of the headset, for both capture and playback
+    pub alsa_device: String,
+    /// Requested period size and count of both PCMs; larger periods are
+    /// tried when the device refuses them
+    pub periods: AlsaPeriods,
+    /// Receives a copy of the transmitted mic audio (mono, after gain and
+    /// processing); chunks are dropped while the receiver lags behind
+    pub mic_tap: Option<SyncSender<Vec<i16>>>,
+}
+
+/// Echo suppressor parameters (see `EchoSuppressor`)
+#[derive(Debug, Clone)]
+pub struct EchoConfig {
+    pub enabled: bool,
+    /// Far-end (incoming) RMS as fraction of full scale that triggers ducking
+    pub far_threshold: f32,
+    /// Mic RMS as fraction of full scale below which the mic is treated as echo
+    pub near_threshold: f32,
+    /// Mic attenuation while ducking, in dB
+    pub duck_db: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for EchoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            far_threshold: 0.02,
+            near_threshold: 0.05,
+            duck_db: 20.0,
+            attack_ms: 10.0,
+            release_ms: 200.0,
+        }
+    }
+}
+
+/// Dead mic detection parameters (see `SilenceDetector`)
+#[derive(Debug, Clone)]
+pub struct SilenceConfig {
+    pub enabled: bool,
+    /// Captured RMS in dBFS, before the mic gain, below which the mic is silent
+    pub threshold_db: f32,
+    /// Time below the threshold while unmuted before the mic is reported silent
+    pub secs: u64,
+    /// Send a VBAN TEXT notification to the target when the mic goes silent
+    /// and when it comes back
+    pub notify: bool,
+}
+
+impl Default for SilenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold_db: -60.0,
+            secs: 60,
+            notify: false,
+        }
+    }
 }
 
 impl Default for IntercomConfig {
     fn default() -> Self {
         Self {
             stream_name: "cam1".to_string(),
+            stream_ignore_case: false,
             target_host: "strih.lan".to_string(),
             sample_rate: SAMPLE_RATE,
             channels: 2,
@@ -141,6 +369,541 @@ impl Default for IntercomConfig {
             headphone_gain: 15.0,
             limiter_enabled: true,
             limiter_threshold: 0.5,
+            allowed_sources: Vec::new(),
+            auto_lock: false,
+            link_timeout_ms: DEFAULT_LINK_TIMEOUT_MS,
+            tx_codec: VbanCodec::Pcm16,
+            hostname: "camera-box".to_string(),
+            ping_announce: false,
+            dscp: net::DSCP_EF,
+            recv_buffer_size: net::DEFAULT_RECV_BUFFER_SIZE,
+            interface: None,
+            port: VBAN_PORT,
+            listen: format!("[::]:{}", VBAN_PORT),
+            bind_address: None,
+            record_dir: None,
+            record_segment_secs: 60,
+            record_keep: 10,
+            echo: EchoConfig::default(),
+            silence: SilenceConfig::default(),
+            routing: Routing::default(),
+            mute_key: Key::KEY_POWER,
+            mute_led: MuteLed::Auto,
+            button_gpio: None,
+            tally_led: None,
+            auto_unmute: AutoUnmute::Never,
+            call_tone: true,
+            mic_channel: MicChannel::Mix,
+            alsa_device: ALSA_DEVICE.to_string(),
+            periods: AlsaPeriods::default(),
+            mic_tap: None,
+        }
+    }
+}
+
+// =============================================================================
+// Intercom Statistics (shared with status consumers)
+// =============================================================================
+
+/// Tally state of this camera on the production switcher
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Tally {
+    Off = 0,
+    Preview = 1,
+    Program = 2,
+}
+
+/// Live intercom counters and state. Created by the caller and shared via `Arc`,
+/// so values survive intercom restarts and can be read from other threads.
+#[derive(Debug)]
+pub struct IntercomStats {
+    pub packets_sent: AtomicU64,
+    /// Packets dropped because the socket send buffer was full
+    pub send_dropped: AtomicU64,
+    pub packets_received: AtomicU64,
+    pub packets_rejected: AtomicU64,
+    pub samples_captured: AtomicU64,
+    /// ALSA capture/playback errors that required recovery
+    pub xruns: AtomicU64,
+    /// Samples dropped by the troubleshooting recorder because it fell behind
+    pub record_dropped: AtomicU64,
+    /// Samples waiting in the playback buffer
+    pub buffer_depth: AtomicUsize,
+    pub link_up: AtomicBool,
+    pub muted: AtomicBool,
+    /// Mic below the silence threshold for the configured time while unmuted
+    pub mic_silent: AtomicBool,
+    /// Calls sent to the director with a long press
+    pub calls_sent: AtomicU64,
+    /// Calls received from the director
+    pub calls_received: AtomicU64,
+    // Time of the last received call as nanoseconds since `epoch`, plus
+    // one (0 = none yet)
+    last_call: AtomicU64,
+    epoch: Instant,
+    tally: AtomicU8,
+    mute_mode: AtomicU8,
+    // Gains stored as f32 bit patterns
+    mic_gain: AtomicU32,
+    headphone_gain: AtomicU32,
+    sidetone_gain: AtomicU32,
+    // Measured far-end clock drift as f32 bits, NaN until measured
+    drift_ppm: AtomicU32,
+    // Captured mic level in dBFS as f32 bits, NaN until measured
+    mic_level_db: AtomicU32,
+    // Round-trip ALSA buffer latency in ms as f32 bits, NaN until the
+    // devices are open
+    latency_ms: AtomicU32,
+}
+
+/// Point-in-time copy of `IntercomStats`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct IntercomStatsSnapshot {
+    pub packets_sent: u64,
+    pub send_dropped: u64,
+    pub packets_received: u64,
+    pub packets_rejected: u64,
+    pub samples_captured: u64,
+    pub xruns: u64,
+    pub record_dropped: u64,
+    pub buffer_depth: usize,
+    pub link_up: bool,
+    pub muted: bool,
+    pub mic_silent: bool,
+    pub calls_sent: u64,
+    pub calls_received: u64,
+    pub tally: Tally,
+    pub mute_mode: MuteMode,
+    pub mic_gain: f32,
+    pub headphone_gain: f32,
+    pub sidetone_gain: f32,
+    /// Far-end clock rate relative to the headset, once measured
+    pub drift_ppm: Option<f32>,
+    /// Captured mic level in dBFS before the mic gain, once measured
+    pub mic_level_db: Option<f32>,
+    /// Round-trip ALSA buffer latency in ms, once the devices are open
+    pub latency_ms: Option<f32>,
+}
+
+impl Default for IntercomStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IntercomStats {
+    /// Create zeroed stats; the microphone starts muted and the link down
+    pub fn new() -> Self {
+        Self {
+            packets_sent: AtomicU64::new(0),
+            send_dropped: AtomicU64::new(0),
+            packets_received: AtomicU64::new(0),
+            packets_rejected: AtomicU64::new(0),
+            samples_captured: AtomicU64::new(0),
+            xruns: AtomicU64::new(0),
+            record_dropped: AtomicU64::new(0),
+            buffer_depth: AtomicUsize::new(0),
+            link_up: AtomicBool::new(false),
+            muted: AtomicBool::new(true),
+            mic_silent: AtomicBool::new(false),
+            calls_sent: AtomicU64::new(0),
+            calls_received: AtomicU64::new(0),
+            last_call: AtomicU64::new(0),
+            epoch: Instant::now(),
+            tally: AtomicU8::new(Tally::Off as u8),
+            mute_mode: AtomicU8::new(MuteMode::ManualMuted as u8),
+            mic_gain: AtomicU32::new(0),
+            headphone_gain: AtomicU32::new(0),
+            sidetone_gain: AtomicU32::new(0),
+            drift_ppm: AtomicU32::new(f32::NAN.to_bits()),
+            mic_level_db: AtomicU32::new(f32::NAN.to_bits()),
+            latency_ms: AtomicU32::new(f32::NAN.to_bits()),
+        }
+    }
+
+    /// Set the gains the audio loop starts with
+    pub fn set_gains(&self, mic: f32, headphone: f32, sidetone: f32) {
+        self.mic_gain.store(mic.to_bits(), Ordering::Relaxed);
+        self.headphone_gain
+            .store(headphone.to_bits(), Ordering::Relaxed);
+        self.sidetone_gain
+            .store(sidetone.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Headphone gain the audio loop applies to the incoming stream
+    pub fn headphone_gain(&self) -> f32 {
+        f32::from_bits(self.headphone_gain.load(Ordering::Relaxed))
+    }
+
+    /// Sidetone gain the audio loop applies
+    pub fn sidetone_gain(&self) -> f32 {
+        f32::from_bits(self.sidetone_gain.load(Ordering::Relaxed))
+    }
+
+    /// Change the headphone gain of the running audio loop
+    pub fn set_headphone_gain(&self, gain: f32) {
+        self.headphone_gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Change the sidetone gain of the running audio loop
+    pub fn set_sidetone_gain(&self, gain: f32) {
+        self.sidetone_gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Record the measured clock drift (None while unknown)
+    pub fn set_drift_ppm(&self, drift: Option<f32>) {
+        self.drift_ppm
+            .store(drift.unwrap_or(f32::NAN).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Record the captured mic level (None while unknown)
+    pub fn set_mic_level_db(&self, level: Option<f32>) {
+        self.mic_level_db
+            .store(level.unwrap_or(f32::NAN).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Record the buffer latency of the opened devices (None while unknown)
+    pub fn set_latency_ms(&self, latency: Option<f32>) {
+        self.latency_ms
+            .store(latency.unwrap_or(f32::NAN).to_bits(), Ordering::Relaxed);
+    }
+
+    /// Record the current tally state
+    pub fn set_tally(&self, tally: Tally) {
+        self.tally.store(tally as u8, Ordering::Relaxed);
+    }
+
+    /// Count a call from the director received at `now`, starting the
+    /// alert on the LED and display
+    pub fn record_call(&self, now: Instant) {
+        self.calls_received.fetch_add(1, Ordering::Relaxed);
+        let since = now.saturating_duration_since(self.epoch).as_nanos() as u64 + 1;
+        self.last_call.store(since, Ordering::Relaxed);
+    }
+
+    /// Blink phase of the call alert at `now` (true = lit), None once the
+    /// alert is over or without a call
+    pub fn call_blink(&self, now: Instant) -> Option<bool> {
+        let since = self.last_call.load(Ordering::Relaxed).checked_sub(1)?;
+        let age = now
+            .saturating_duration_since(self.epoch)
+            .checked_sub(Duration::from_nanos(since))?;
+        call_blink_at(age)
+    }
+
+    /// Current tally state
+    pub fn tally(&self) -> Tally {
+        match self.tally.load(Ordering::Relaxed) {
+            2 => Tally::Program,
+            1 => Tally::Preview,
+            _ => Tally::Off,
+        }
+    }
+
+    /// Record who decides the mute state
+    pub fn set_mute_mode(&self, mode: MuteMode) {
+        self.mute_mode.store(mode as u8, Ordering::Relaxed);
+    }
+
+    /// Who decides the mute state
+    pub fn mute_mode(&self) -> MuteMode {
+        match self.mute_mode.load(Ordering::Relaxed) {
+            2 => MuteMode::AutoFollow,
+            1 => MuteMode::ManualOpen,
+            _ => MuteMode::ManualMuted,
+        }
+    }
+
+    /// Report the counters and link state as "intercom"
+    pub fn register(self: &Arc<Self>, registry: &StatsRegistry) {
+        let state = Arc::clone(self);
+        let link = Arc::clone(self);
+        let muted = Arc::clone(self);
+        let drift = Arc::clone(self);
+        let level = Arc::clone(self);
+        let silent = Arc::clone(self);
+        let mode = Arc::clone(self);
+        let latency = Arc::clone(self);
+        registry.register(
+            Component::new("intercom")
+                .counter("received", self, |s| &s.packets_received)
+                .counter("sent", self, |s| &s.packets_sent)
+                .counter("send_dropped", self, |s| &s.send_dropped)
+                .counter("rejected", self, |s| &s.packets_rejected)
+                .counter("samples", self, |s| &s.samples_captured)
+                .counter("xruns", self, |s| &s.xruns)
+                .counter("record_dropped", self, |s| &s.record_dropped)
+                .counter("calls_sent", self, |s| &s.calls_sent)
+                .counter("calls_received", self, |s| &s.calls_received)
+                .gauge("buffer", move || {
+                    state.buffer_depth.load(Ordering::Relaxed) as f64
+                })
+                .gauge("link", move || {
+                    link.link_up.load(Ordering::Relaxed) as u8 as f64
+                })
+                .gauge("muted", move || {
+                    muted.muted.load(Ordering::Relaxed) as u8 as f64
+                })
+                .gauge("mute_mode", move || {
+                    mode.mute_mode.load(Ordering::Relaxed) as f64
+                })
+                .gauge("drift_ppm", move || {
+                    f32::from_bits(drift.drift_ppm.load(Ordering::Relaxed)) as f64
+                })
+                .gauge("mic_db", move || {
+                    f32::from_bits(level.mic_level_db.load(Ordering::Relaxed)) as f64
+                })
+                .gauge("mic_silent", move || {
+                    silent.mic_silent.load(Ordering::Relaxed) as u8 as f64
+                })
+                .gauge("latency_ms", move || {
+                    f32::from_bits(latency.latency_ms.load(Ordering::Relaxed)) as f64
+                }),
+        );
+    }
+
+    /// Copy all values into a plain struct
+    pub fn snapshot(&self) -> IntercomStatsSnapshot {
+        IntercomStatsSnapshot {
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            send_dropped: self.send_dropped.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            packets_rejected: self.packets_rejected.load(Ordering::Relaxed),
+            samples_captured: self.samples_captured.load(Ordering::Relaxed),
+            xruns: self.xruns.load(Ordering::Relaxed),
+            record_dropped: self.record_dropped.load(Ordering::Relaxed),
+            buffer_depth: self.buffer_depth.load(Ordering::Relaxed),
+            link_up: self.link_up.load(Ordering::Relaxed),
+            muted: self.muted.load(Ordering::Relaxed),
+            mic_silent: self.mic_silent.load(Ordering::Relaxed),
+            calls_sent: self.calls_sent.load(Ordering::Relaxed),
+            calls_received: self.calls_received.load(Ordering::Relaxed),
+            tally: self.tally(),
+            mute_mode: self.mute_mode(),
+            mic_gain: f32::from_bits(self.mic_gain.load(Ordering::Relaxed)),
+            headphone_gain: f32::from_bits(self.headphone_gain.load(Ordering::Relaxed)),
+            sidetone_gain: f32::from_bits(self.sidetone_gain.load(Ordering::Relaxed)),
+            drift_ppm: Some(f32::from_bits(self.drift_ppm.load(Ordering::Relaxed)))
+                .filter(|drift| !drift.is_nan()),
+            mic_level_db: Some(f32::from_bits(self.mic_level_db.load(Ordering::Relaxed)))
+                .filter(|level| !level.is_nan()),
+            latency_ms: Some(f32::from_bits(self.latency_ms.load(Ordering::Relaxed)))
+                .filter(|latency| !latency.is_nan()),
+        }
+    }
+}
+
+// =============================================================================
+// Source Filter (VBAN sender allowlist)
+// =============================================================================
+
+/// Silence after which an auto-locked source is released
+const AUTO_LOCK_RELEASE: Duration = Duration::from_secs(10);
+
+/// Minimum interval between hostname re-resolution attempts
+const RESOLVE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the resolver thread checks for requests and shutdown
+const RESOLVER_POLL: Duration = Duration::from_millis(100);
+
+/// Last good addresses of each allowed hostname. A failed lookup keeps the
+/// host's previous addresses, so a DNS hiccup doesn't cut off a source that
+/// was working.
+#[derive(Debug, Default)]
+struct HostAddrs {
+    hosts: HashMap<String, HashSet<IpAddr>>,
+}
+
+impl HostAddrs {
+    fn update(&mut self, host: &str, result: std::io::Result<Vec<SocketAddr>>) {
+        match result {
+            Ok(addrs) => {
+                let addrs = addrs.iter().map(|a| a.ip().to_canonical()).collect();
+                self.hosts.insert(host.to_string(), addrs);
+            }
+            Err(e) => match self.hosts.get(host) {
+                Some(last) => tracing::warn!(
+                    "Could not resolve allowed VBAN source {}: {}, keeping {:?}",
+                    host,
+                    e,
+                    last
+                ),
+                None => tracing::warn!("Could not resolve allowed VBAN source {}: {}", host, e),
+            },
+        }
+    }
+
+    fn resolve_all(&mut self, hostnames: &[String]) {
+        for host in hostnames {
+            self.update(host, net::resolve(host, 0));
+        }
+    }
+
+    fn all(&self) -> HashSet<IpAddr> {
+        self.hosts.values().flatten().copied().collect()
+    }
+}
+
+/// Hostname addresses shared between a [`SourceFilter`] and its resolver
+/// thread
+#[derive(Debug, Default)]
+struct ResolvedHosts {
+    /// Addresses of all allowed hostnames
+    addrs: Mutex<HashSet<IpAddr>>,
+    /// Bumped with each new set, so the packet path copies it only when it
+    /// changed
+    generation: AtomicU64,
+    /// An unknown address was seen: look the hostnames up again
+    wanted: AtomicBool,
+    /// The filter was dropped
+    stopped: AtomicBool,
+}
+
+impl ResolvedHosts {
+    fn publish(&self, addrs: HashSet<IpAddr>) {
+        *self.addrs.lock().unwrap() = addrs;
+        self.generation.fetch_add(1, Ordering::Release);
+    }
+}
+
+/// Look the hostnames up again when the filter asks, at most every
+/// [`RESOLVE_RETRY_INTERVAL`], until the filter is dropped
+fn run_resolver(hostnames: Vec<String>, mut hosts: HostAddrs, shared: Arc<ResolvedHosts>) {
+    let mut last_resolve = Instant::now();
+    while !shared.stopped.load(Ordering::Relaxed) {
+        std::thread::sleep(RESOLVER_POLL);
+        if !shared.wanted.load(Ordering::Relaxed) || last_resolve.elapsed() < RESOLVE_RETRY_INTERVAL
+        {
+            continue;
+        }
+        shared.wanted.store(false, Ordering::Relaxed);
+        last_resolve = Instant::now();
+        hosts.resolve_all(&hostnames);
+        shared.publish(hosts.all());
+    }
+}
+
+/// Decides which hosts may inject audio into the operator's headset.
+/// Runs per packet, so the hot path is a lookup in a pre-resolved `IpAddr` set.
+/// Hostnames are looked up again on a background thread, never on the
+/// packet path, so unknown senders can't stall playback on DNS.
+#[derive(Debug)]
+pub struct SourceFilter {
+    /// Addresses currently allowed (literal IPs plus resolved hostnames)
+    allowed: HashSet<IpAddr>,
+    /// Configured entries that are literal IP addresses
+    literal: HashSet<IpAddr>,
+    /// Hostname addresses from the resolver thread; None without hostnames
+    resolved: Option<Arc<ResolvedHosts>>,
+    /// Generation of `resolved` merged into `allowed`
+    generation: u64,
+    /// Auto-lock mode enabled
+    auto_lock: bool,
+    /// Currently locked source and when it was last heard from
+    locked: Option<(IpAddr, Instant)>,
+}
+
+impl SourceFilter {
+    /// Create a filter from configured sources (IPs or hostnames).
+    /// Hostnames are resolved immediately; failures are retried later.
+    pub fn new(allowed_sources: &[String], auto_lock: bool) -> Self {
+        let mut literal = HashSet::new();
+        let mut hostnames = Vec::new();
+        for source in allowed_sources {
+            match source.parse::<IpAddr>() {
+                Ok(ip) => {
+                    literal.insert(ip);
+                }
+                Err(_) => hostnames.push(source.clone()),
+            }
+        }
+
+        let mut allowed = literal.clone();
+        let resolved = (!hostnames.is_empty()).then(|| {
+            let mut hosts = HostAddrs::default();
+            hosts.resolve_all(&hostnames);
+            allowed.extend(hosts.all());
+            let shared = Arc::new(ResolvedHosts::default());
+            let resolver = Arc::clone(&shared);
+            threads::spawn(threads::INTERCOM_RESOLVE, move || {
+                run_resolver(hostnames, hosts, resolver)
+            });
+            shared
+        });
+
+        Self {
+            allowed,
+            literal,
+            resolved,
+            generation: 0,
+            auto_lock,
+            locked: None,
+        }
+    }
+
+    /// Whether any allowlist is configured
+    pub fn is_restricted(&self) -> bool {
+        !self.literal.is_empty() || self.resolved.is_some()
+    }
+
+    /// Currently locked source (auto-lock mode only)
+    pub fn locked_source(&self) -> Option<IpAddr> {
+        self.locked.map(|(ip, _)| ip)
+    }
+
+    /// Check a packet's source address. Returns true if the packet should be accepted.
+    pub fn accept(&mut self, addr: IpAddr, now: Instant) -> bool {
+        // A dual-stack receiver sees IPv4 sources as IPv4-mapped addresses
+        let addr = addr.to_canonical();
+        if !self.is_allowed(addr) {
+            return false;
+        }
+
+        if self.auto_lock {
+            match self.locked {
+                Some((ip, last_seen))
+                    if ip != addr && now.duration_since(last_seen) < AUTO_LOCK_RELEASE =>
+                {
+                    return false;
+                }
+                Some((ip, _)) if ip == addr => {}
+                _ => tracing::info!("VBAN receiver locked to source {}", addr),
+            }
+            self.locked = Some((addr, now));
+        }
+
+        true
+    }
+
+    fn is_allowed(&mut self, addr: IpAddr) -> bool {
+        if !self.is_restricted() || self.allowed.contains(&addr) {
+            return true;
+        }
+        let Some(resolved) = &self.resolved else {
+            return false;
+        };
+
+        // Pick up addresses the resolver found since the last miss
+        let generation = resolved.generation.load(Ordering::Acquire);
+        if generation != self.generation {
+            self.generation = generation;
+            self.allowed = &self.literal | &*resolved.addrs.lock().unwrap();
+            if self.allowed.contains(&addr) {
+                return true;
+            }
+        }
+
+        // Unknown address - the allowed host may have changed IP
+        resolved.wanted.store(true, Ordering::Relaxed);
+        false
+    }
+}
+
+impl Drop for SourceFilter {
+    fn drop(&mut self) {
+        if let Some(resolved) = &self.resolved {
+            resolved.stopped.store(true, Ordering::Relaxed);
         }
     }
 }
@@ -152,6 +915,8 @@ impl Default for IntercomConfig {
 struct AudioBuffer {
     samples: VecDeque<i16>,
     capacity: usize,
+    /// Samples pushed so far, including ones dropped on overflow
+    received: u64,
 }
 
 impl AudioBuffer {
@@ -159,10 +924,12 @@ impl AudioBuffer {
         Self {
             samples: VecDeque::with_capacity(capacity),
             capacity,
+            received: 0,
         }
     }
 
     fn push_samples(&mut self, data: &[i16]) {
+        self.received += data.len() as u64;
         while self.samples.len() + data.len() > self.capacity {
             self.samples.pop_front();
         }
@@ -173,6 +940,175 @@ impl AudioBuffer {
         let available = count.min(self.samples.len());
         self.samples.drain(..available).collect()
     }
+
+    fn len(&self) -> usize {
+        self.samples.len()
+    }
+}
+
+// =============================================================================
+// Clock Drift Compensation
+// =============================================================================
+
+/// Window over which the far end's sample rate is compared with the headset's
+const DRIFT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Shortest span a drift estimate is made from
+const DRIFT_MIN_SPAN: Duration = Duration::from_secs(10);
+
+/// Spacing of the points in the drift window
+const DRIFT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Playback buffer level the corrections steer towards, in periods
+const DRIFT_TARGET_PERIODS: f64 = 2.0;
+
+/// Extra correction per frame the buffer level is off target, in ppm
+const DRIFT_CENTER_GAIN: f64 = 2.0;
+
+/// Largest correction applied, in ppm
+const DRIFT_MAX_CORRECTION: f64 = 2000.0;
+
+/// Smoothing of the buffer level per playback period
+const DRIFT_LEVEL_SMOOTHING: f64 = 0.01;
+
+/// Keeps the playback buffer from draining or overflowing when the far end's
+/// VBAN clock runs at a slightly different rate than the headset.
+///
+/// Samples received are compared with samples requested by playback over a
+/// sliding window; the measured drift plus a small term pulling the buffer
+/// level back to its target is corrected by dropping or repeating one frame
+/// every so many periods.
+#[derive(Debug)]
+pub struct DriftCompensator {
+    channels: usize,
+    /// Buffer level the corrections steer towards, in frames
+    target: f64,
+    /// (time, samples received, samples played) once per sample interval
+    history: VecDeque<(Instant, u64, u64)>,
+    played: u64,
+    drift_ppm: Option<f64>,
+    /// Smoothed buffer level in frames
+    level: f64,
+    /// Frames owed: positive to drop, negative to repeat
+    owed: f64,
+}
+
+impl DriftCompensator {
+    /// Compensator for playback periods of `period_frames` frames
+    pub fn new(channels: usize, period_frames: usize) -> Self {
+        let target = DRIFT_TARGET_PERIODS * period_frames as f64;
+        Self {
+            channels: channels.max(1),
+            target,
+            history: VecDeque::new(),
+            played: 0,
+            drift_ppm: None,
+            level: target,
+            owed: 0.0,
+        }
+    }
+
+    /// Measured far-end rate relative to playback, in ppm (positive = faster)
+    pub fn drift_ppm(&self) -> Option<f64> {
+        self.drift_ppm
+    }
+
+    /// Forget the measurement, e.g. after the link went down
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.drift_ppm = None;
+        self.owed = 0.0;
+    }
+
+    /// Record the counters at `now` and update the drift estimate
+    fn measure(&mut self, received: u64, now: Instant) {
+        let due = self
+            .history
+            .back()
+            .is_none_or(|&(at, _, _)| now.saturating_duration_since(at) >= DRIFT_SAMPLE_INTERVAL);
+        if !due {
+            return;
+        }
+        self.history.push_back((now, received, self.played));
+        while self
+            .history
+            .front()
+            .is_some_and(|&(at, _, _)| now.saturating_duration_since(at) > DRIFT_WINDOW)
+        {
+            self.history.pop_front();
+        }
+        let (&(since, _, played_then), &(_, _, played_now)) =
+            (self.history.front().unwrap(), self.history.back().unwrap());
+        let span = now.saturating_duration_since(since).as_secs_f64();
+        let played_rate = played_now.saturating_sub(played_then) as f64 / span;
+        if now.saturating_duration_since(since) < DRIFT_MIN_SPAN || played_rate <= 0.0 {
+            return;
+        }
+        // Least-squares slope of (received - played) over the window, so a
+        // packet landing either side of one point barely moves the estimate
+        let points: Vec<(f64, f64)> = self
+            .history
+            .iter()
+            .map(|&(at, received, played)| {
+                let t = at.saturating_duration_since(since).as_secs_f64();
+                (t, received as f64 - played as f64)
+            })
+            .collect();
+        let n = points.len() as f64;
+        let mean_t = points.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_d = points.iter().map(|p| p.1).sum::<f64>() / n;
+        let (cov, var) = points.iter().fold((0.0, 0.0), |(cov, var), &(t, d)| {
+            (
+                cov + (t - mean_t) * (d - mean_d),
+                var + (t - mean_t).powi(2),
+            )
+        });
+        if var > 0.0 {
+            self.drift_ppm = Some(cov / var / played_rate * 1e6);
+        }
+    }
+
+    /// Frames to drop (1), repeat (-1) or neither (0) in a period of
+    /// `frames` with the buffer holding `level` frames
+    fn correction(&mut self, frames: usize, level: usize) -> i32 {
+        self.level += (level as f64 - self.level) * DRIFT_LEVEL_SMOOTHING;
+        let center = (self.level - self.target) * DRIFT_CENTER_GAIN;
+        let ppm = (self.drift_ppm.unwrap_or(0.0) + center)
+            .clamp(-DRIFT_MAX_CORRECTION, DRIFT_MAX_CORRECTION);
+        self.owed = (self.owed + frames as f64 * ppm / 1e6).clamp(-1.0, 1.0);
+        if self.owed >= 1.0 {
+            1
+        } else if self.owed <= -1.0 {
+            -1
+        } else {
+            0
+        }
+    }
+
+    /// Take one playback period of `count` samples from `buf`, dropping or
+    /// repeating a frame when a correction is due
+    fn pop(&mut self, buf: &mut AudioBuffer, count: usize, now: Instant) -> Vec<i16> {
+        self.measure(buf.received, now);
+        self.played += count as u64;
+        let frame = self.channels;
+        match self.correction(count / frame, buf.len() / frame) {
+            1 if buf.len() >= count + frame => {
+                self.owed -= 1.0;
+                let mut samples = buf.pop_samples(count + frame);
+                samples.truncate(count);
+                samples
+            }
+            -1 if buf.len() >= count - frame && count >= 2 * frame => {
+                self.owed += 1.0;
+                let mut samples = buf.pop_samples(count - frame);
+                let last = samples[samples.len() - frame..].to_vec();
+                samples.extend_from_slice(&last);
+                samples
+            }
+            // Not enough audio to correct with; the correction stays owed
+            _ => buf.pop_samples(count),
+        }
+    }
 }
 
 // =============================================================================
@@ -286,377 +1222,1910 @@ impl PeakLimiter {
 }
 
 // =============================================================================
-// Direct ALSA Audio
+// Gain Ramp (click-free muting)
 // =============================================================================
 
-fn open_alsa_capture() -> Result<PCM> {
-    let pcm = PCM::new(ALSA_DEVICE, Direction::Capture, false)
-        .context("Failed to open ALSA capture device")?;
+/// Length of the fade when the mic is muted or unmuted
+const MUTE_RAMP_MS: u32 = 5;
+
+/// Per-sample linear gain envelope. Muting by switching samples off makes
+/// a step in the waveform, heard as a click; ramping the gain over a few
+/// milliseconds instead is inaudible.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GainRamp {
+    current: f32,
+    target: f32,
+    /// Gain change per sample
+    step: f32,
+}
 
-    {
-        let hwp = HwParams::any(&pcm)?;
-        hwp.set_channels(1)?; // Mono microphone
-        hwp.set_rate(SAMPLE_RATE, ValueOr::Nearest)?;
-        hwp.set_format(Format::s16())?;
-        hwp.set_access(Access::RWInterleaved)?;
-        hwp.set_period_size(PERIOD_SIZE as i64, ValueOr::Nearest)?;
-        hwp.set_buffer_size((PERIOD_SIZE * BUFFER_PERIODS) as i64)?;
-        pcm.hw_params(&hwp)?;
+impl GainRamp {
+    /// Start settled at `gain`; a full swing between 0 and 1 takes
+    /// `ramp_samples`
+    pub fn new(gain: f32, ramp_samples: u32) -> Self {
+        Self {
+            current: gain,
+            target: gain,
+            step: 1.0 / ramp_samples.max(1) as f32,
+        }
     }
 
-    {
-        let swp = pcm.sw_params_current()?;
-        swp.set_start_threshold(1)?;
-        swp.set_avail_min(PERIOD_SIZE as i64)?;
-        pcm.sw_params(&swp)?;
+    /// Ramp towards `target` from wherever the gain is now
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
     }
 
-    tracing::info!(
-        "ALSA capture: hw:CARD=HID, {}Hz mono, period={} frames",
-        SAMPLE_RATE,
-        PERIOD_SIZE
-    );
-    Ok(pcm)
-}
+    pub fn target(&self) -> f32 {
+        self.target
+    }
 
-fn open_alsa_playback() -> Result<PCM> {
-    let pcm = PCM::new(ALSA_DEVICE, Direction::Playback, false)
-        .context("Failed to open ALSA playback device")?;
+    /// Whether the gain has arrived at the target
+    pub fn is_settled(&self) -> bool {
+        self.current == self.target
+    }
 
-    {
-        let hwp = HwParams::any(&pcm)?;
-        hwp.set_channels(2)?; // Stereo output
-        hwp.set_rate(SAMPLE_RATE, ValueOr::Nearest)?;
-        hwp.set_format(Format::s16())?;
-        hwp.set_access(Access::RWInterleaved)?;
-        hwp.set_period_size(PERIOD_SIZE as i64, ValueOr::Nearest)?;
-        hwp.set_buffer_size((PERIOD_SIZE * BUFFER_PERIODS) as i64)?;
-        pcm.hw_params(&hwp)?;
+    /// Gain for the next sample, landing exactly on the target
+    pub fn next_gain(&mut self) -> f32 {
+        let gain = self.current;
+        if self.current < self.target {
+            self.current = (self.current + self.step).min(self.target);
+        } else if self.current > self.target {
+            self.current = (self.current - self.step).max(self.target);
+        }
+        gain
     }
 
-    {
-        let swp = pcm.sw_params_current()?;
-        swp.set_start_threshold(PERIOD_SIZE as i64)?;
-        swp.set_avail_min(PERIOD_SIZE as i64)?;
-        pcm.sw_params(&swp)?;
+    /// Apply the envelope to a buffer in place
+    pub fn process_buffer(&mut self, buffer: &mut [i16]) {
+        for sample in buffer.iter_mut() {
+            *sample = (*sample as f32 * self.next_gain()).clamp(-32768.0, 32767.0) as i16;
+        }
     }
+}
 
-    tracing::info!(
-        "ALSA playback: hw:CARD=HID, {}Hz stereo, period={} frames",
-        SAMPLE_RATE,
-        PERIOD_SIZE
-    );
-    Ok(pcm)
+// =============================================================================
+// Echo Suppressor (half-duplex ducking for open-ear headsets)
+// =============================================================================
+
+/// RMS level of a buffer as a fraction of full scale
+pub fn rms_level(samples: &[i16]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f32 = samples
+        .iter()
+        .map(|&s| {
+            let v = s as f32 / 32768.0;
+            v * v
+        })
+        .sum();
+    (sum / samples.len() as f32).sqrt()
 }
 
-fn recover_alsa(pcm: &PCM, err: i32) -> bool {
-    match pcm.recover(err, true) {
-        Ok(_) => true,
-        Err(e) => {
-            tracing::error!("ALSA recovery failed: {}", e);
-            false
+/// Level as a fraction of full scale in dBFS, floored at -120
+pub fn level_db(level: f32) -> f32 {
+    20.0 * level.max(1e-6).log10()
+}
+
+/// Linear gain of an attenuation in dB (the sign is ignored)
+fn attenuation_gain(db: f32) -> f32 {
+    10f32.powf(-db.abs() / 20.0)
+}
+
+/// One-pole gain smoother stepped once per sample: `attack_ms` towards a
+/// lower target, `release_ms` back up
+#[derive(Debug, Clone)]
+pub struct GainFollower {
+    attack_coeff: f32,
+    release_coeff: f32,
+    gain: f32,
+}
+
+impl GainFollower {
+    /// A follower starting at unity gain
+    pub fn new(attack_ms: f32, release_ms: f32, sample_rate: u32) -> Self {
+        let coeff = |ms: f32| (-1.0 / (ms.max(0.1) / 1000.0 * sample_rate as f32)).exp();
+        Self {
+            attack_coeff: coeff(attack_ms),
+            release_coeff: coeff(release_ms),
+            gain: 1.0,
         }
     }
-}
 
-// =============================================================================
-// VBAN Receiver
-// =============================================================================
+    /// Smoothing coefficient for moving towards `target`
+    pub fn coeff(&self, target: f32) -> f32 {
+        if target < self.gain {
+            self.attack_coeff
+        } else {
+            self.release_coeff
+        }
+    }
 
-fn run_receiver(
-    config: &IntercomConfig,
-    playback_buffer: Arc<Mutex<AudioBuffer>>,
-    running: Arc<AtomicBool>,
-    frames_received: Arc<AtomicU64>,
-) -> Result<()> {
-    let socket = UdpSocket::bind(format!("0.0.0.0:{}", VBAN_PORT))?;
-    socket
-        .set_read_timeout(Some(std::time::Duration::from_millis(100)))
-        .ok();
+    /// Step one sample towards `target` with `coeff`, returning the gain
+    pub fn step(&mut self, target: f32, coeff: f32) -> f32 {
+        self.gain = self.gain * coeff + target * (1.0 - coeff);
+        self.gain
+    }
 
-    tracing::info!(
-        "VBAN receiver listening on port {}, stream: {}",
-        VBAN_PORT,
-        config.stream_name
-    );
-    let mut packet_buf = [0u8; MAX_VBAN_PACKET_SIZE];
+    /// Current smoothed gain
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+}
 
-    while running.load(Ordering::Relaxed) {
-        match socket.recv_from(&mut packet_buf) {
-            Ok((len, _addr)) => {
-                if len < VBAN_HEADER_SIZE {
-                    continue;
-                }
-                let header = match VbanHeader::decode(&packet_buf[..len]) {
-                    Ok(h) => h,
-                    Err(_) => continue,
-                };
-                if header.stream_name_str() != config.stream_name {
-                    continue;
-                }
+/// Ducks the outbound mic while the far end is talking and the local
+/// talker is quiet, so earpiece leakage does not echo back to the mixer.
+/// Gain changes are smoothed per sample with attack/release coefficients.
+pub struct EchoSuppressor {
+    far_threshold: f32,
+    near_threshold: f32,
+    /// Linear gain applied while ducking
+    duck_gain: f32,
+    follower: GainFollower,
+}
 
-                let audio_data = &packet_buf[VBAN_HEADER_SIZE..len];
-                let samples: Vec<i16> = match header.codec {
-                    c if c == VbanCodec::Pcm16 as u8 => audio_data
-                        .chunks_exact(2)
-                        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
-                        .collect(),
-                    c if c == VbanCodec::Float32 as u8 => audio_data
-                        .chunks_exact(4)
-                        .map(|chunk| {
-                            let f = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
-                            (f * 32767.0).clamp(-32768.0, 32767.0) as i16
-                        })
-                        .collect(),
-                    _ => audio_data
-                        .chunks_exact(2)
-                        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
-                        .collect(),
-                };
+impl EchoSuppressor {
+    pub fn new(config: &EchoConfig, sample_rate: u32) -> Self {
+        Self {
+            far_threshold: config.far_threshold,
+            near_threshold: config.near_threshold,
+            duck_gain: attenuation_gain(config.duck_db),
+            follower: GainFollower::new(config.attack_ms, config.release_ms, sample_rate),
+        }
+    }
 
-                if let Ok(mut buf) = playback_buffer.lock() {
-                    buf.push_samples(&samples);
-                }
-                frames_received.fetch_add(1, Ordering::Relaxed);
-            }
-            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
-            Err(e) => tracing::warn!("VBAN receive error: {}", e),
+    /// Process one period of mic samples in-place.
+    /// `far_level` is the RMS of the incoming audio (post-gain) for the current period.
+    pub fn process_buffer(&mut self, mic: &mut [i16], far_level: f32) {
+        let near_level = rms_level(mic);
+        let target = if far_level > self.far_threshold && near_level < self.near_threshold {
+            self.duck_gain
+        } else {
+            1.0
+        };
+        let coeff = self.follower.coeff(target);
+        for sample in mic.iter_mut() {
+            let gain = self.follower.step(target, coeff);
+            *sample = (*sample as f32 * gain).clamp(-32768.0, 32767.0) as i16;
         }
     }
-    Ok(())
+
+    /// Current smoothed gain (1.0 = not ducking)
+    pub fn gain(&self) -> f32 {
+        self.follower.gain()
+    }
 }
 
 // =============================================================================
-// Main Intercom Loop
+// Program Ducker (program monitor under the director's voice)
 // =============================================================================
 
-fn apply_intercom_priority() {
-    unsafe {
-        libc::nice(10);
-        let mut cpuset: libc::cpu_set_t = std::mem::zeroed();
-        libc::CPU_SET(1, &mut cpuset);
-        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &cpuset);
+/// Program monitor ducking parameters (see `ProgramDucker`). There is no
+/// `[intercom.ducking]` section until a program monitor is mixed into the
+/// headphones to duck.
+#[derive(Debug, Clone)]
+pub struct DuckingConfig {
+    pub enable: bool,
+    /// Intercom (rx) RMS in dBFS above which the program is ducked
+    pub threshold_db: f32,
+    /// Program attenuation while ducking, in dB
+    pub amount_db: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+impl Default for DuckingConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            threshold_db: -40.0,
+            amount_db: 12.0,
+            attack_ms: 20.0,
+            release_ms: 500.0,
+        }
     }
 }
 
-pub fn run_intercom(config: IntercomConfig, running: Arc<AtomicBool>) -> Result<()> {
-    apply_intercom_priority();
+/// Sidechain ducker for a program monitor mixed into the headphones: while
+/// the incoming intercom is above the threshold, the program gain falls by
+/// the configured amount, and it comes back once the intercom goes quiet.
+/// The gain steps once per frame, so interleaved channels share it.
+pub struct ProgramDucker {
+    threshold: f32,
+    /// Linear gain applied while ducking
+    duck_gain: f32,
+    channels: usize,
+    follower: GainFollower,
+}
 
-    while running.load(Ordering::Relaxed) {
-        tracing::info!(
-            "Starting VBAN intercom with direct ALSA: stream={}, target={}",
-            config.stream_name,
-            config.target_host
-        );
+impl ProgramDucker {
+    pub fn new(config: &DuckingConfig, sample_rate: u32, channels: usize) -> Self {
+        Self {
+            threshold: 10f32.powf(config.threshold_db / 20.0),
+            duck_gain: attenuation_gain(config.amount_db),
+            channels: channels.max(1),
+            follower: GainFollower::new(config.attack_ms, config.release_ms, sample_rate),
+        }
+    }
 
-        match run_intercom_inner(&config, Arc::clone(&running)) {
-            Ok(()) => {
-                tracing::info!("Intercom stopped normally");
-                break;
-            }
-            Err(e) => {
-                tracing::error!("Intercom error: {} - restarting in 2 seconds", e);
-                std::thread::sleep(std::time::Duration::from_secs(2));
+    /// Duck one period of interleaved program samples in place.
+    /// `intercom_level` is the RMS of the received intercom for the period.
+    pub fn process_buffer(&mut self, program: &mut [i16], intercom_level: f32) {
+        let target = if intercom_level > self.threshold {
+            self.duck_gain
+        } else {
+            1.0
+        };
+        let coeff = self.follower.coeff(target);
+        for frame in program.chunks_mut(self.channels) {
+            let gain = self.follower.step(target, coeff);
+            for sample in frame {
+                *sample = (*sample as f32 * gain).clamp(-32768.0, 32767.0) as i16;
             }
         }
     }
-    Ok(())
+
+    /// Current program gain (1.0 = not ducking)
+    pub fn gain(&self) -> f32 {
+        self.follower.gain()
+    }
 }
 
 // =============================================================================
-// Testable Audio Buffer (public for testing)
+// Silence Detection (dead or unplugged mic while unmuted)
 // =============================================================================
 
-/// Ring buffer for audio samples (exposed for testing)
+/// Mic silence transition reported by `SilenceDetector`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SilenceEvent {
+    /// Below the threshold for the hold time while unmuted
+    Silent,
+    /// Signal above the threshold again
+    Restored,
+}
+
+/// Watches the captured mic level for a dead mic, e.g. an unplugged lav.
+/// Time only counts while unmuted; a level above the threshold clears the
+/// state at once, muted or not. Driven by an explicit clock like `LinkMonitor`.
 #[derive(Debug)]
-pub struct TestableAudioBuffer {
-    samples: VecDeque<i16>,
-    capacity: usize,
+pub struct SilenceDetector {
+    /// Linear RMS threshold
+    threshold: f32,
+    hold: Duration,
+    quiet_since: Option<Instant>,
+    silent: bool,
 }
 
-impl TestableAudioBuffer {
-    /// Create a new audio buffer with the given capacity
-    pub fn new(capacity: usize) -> Self {
+impl SilenceDetector {
+    pub fn new(threshold_db: f32, hold: Duration) -> Self {
         Self {
-            samples: VecDeque::with_capacity(capacity),
-            capacity,
+            threshold: 10f32.powf(threshold_db / 20.0),
+            hold,
+            quiet_since: None,
+            silent: false,
         }
     }
 
-    /// Push samples into the buffer, dropping oldest if at capacity
-    pub fn push_samples(&mut self, data: &[i16]) {
-        while self.samples.len() + data.len() > self.capacity {
-            self.samples.pop_front();
+    /// Feed the RMS `level` of one capture period
+    pub fn update(&mut self, level: f32, muted: bool, now: Instant) -> Option<SilenceEvent> {
+        if level >= self.threshold {
+            self.quiet_since = None;
+            if self.silent {
+                self.silent = false;
+                return Some(SilenceEvent::Restored);
+            }
+            return None;
         }
-        self.samples.extend(data.iter().copied());
-    }
-
-    /// Pop up to `count` samples from the buffer
-    pub fn pop_samples(&mut self, count: usize) -> Vec<i16> {
-        let available = count.min(self.samples.len());
-        self.samples.drain(..available).collect()
+        if muted {
+            // A muted talker is expected to be quiet; count again from unmute
+            self.quiet_since = None;
+            return None;
+        }
+        let since = *self.quiet_since.get_or_insert(now);
+        if !self.silent && now.saturating_duration_since(since) >= self.hold {
+            self.silent = true;
+            return Some(SilenceEvent::Silent);
+        }
+        None
     }
 
-    /// Get current number of samples in buffer
-    pub fn len(&self) -> usize {
-        self.samples.len()
+    /// Whether the mic is currently reported silent
+    pub fn is_silent(&self) -> bool {
+        self.silent
     }
+}
 
-    /// Check if buffer is empty
-    pub fn is_empty(&self) -> bool {
-        self.samples.is_empty()
+/// VBAN TEXT notification of a silence change for the mixer operator
+pub fn silence_notice(stream_name: &str, event: SilenceEvent) -> String {
+    match event {
+        SilenceEvent::Silent => format!("{}: mic silent", stream_name),
+        SilenceEvent::Restored => format!("{}: mic signal restored", stream_name),
     }
+}
 
-    /// Get buffer capacity
-    pub fn capacity(&self) -> usize {
-        self.capacity
-    }
+/// VBAN TEXT notification sent once when the intercom stops
+pub fn offline_notice(stream_name: &str) -> String {
+    format!("{}: offline", stream_name)
 }
 
-fn run_intercom_inner(config: &IntercomConfig, running: Arc<AtomicBool>) -> Result<()> {
-    // Open ALSA devices with retry
-    let capture = loop {
-        match open_alsa_capture() {
-            Ok(c) => break c,
-            Err(e) => {
-                if !running.load(Ordering::Relaxed) {
-                    return Ok(());
-                }
-                tracing::warn!("Waiting for audio capture device: {} - retrying...", e);
-                std::thread::sleep(std::time::Duration::from_secs(2));
-            }
-        }
-    };
+// =============================================================================
+// Director Call (long press on the mute button)
+// =============================================================================
 
-    let playback = loop {
-        match open_alsa_playback() {
-            Ok(p) => break p,
-            Err(e) => {
-                if !running.load(Ordering::Relaxed) {
-                    return Ok(());
-                }
-                tracing::warn!("Waiting for audio playback device: {} - retrying...", e);
-                std::thread::sleep(std::time::Duration::from_secs(2));
-            }
-        }
-    };
+/// VBAN TEXT message of a call, sent on our stream and accepted on it
+pub const CALL_MESSAGE: &str = "call=1";
 
-    // Mute state
-    let muted = Arc::new(AtomicBool::new(true));
-    tracing::info!("🎤 Microphone starts MUTED - press power button to unmute");
+/// How long an incoming call flashes the LED and display
+pub const CALL_ALERT: Duration = Duration::from_secs(5);
 
-    // Start power button monitor
-    let muted_btn = Arc::clone(&muted);
-    let running_btn = Arc::clone(&running);
-    std::thread::spawn(move || run_power_button_monitor(muted_btn, running_btn));
+/// Half-period of the call flash
+const CALL_BLINK_INTERVAL: Duration = Duration::from_millis(250);
 
-    // VBAN sender
-    let vban_socket = UdpSocket::bind("0.0.0.0:0")?;
-    let target_addr = format!("{}:{}", config.target_host, VBAN_PORT);
-    vban_socket.connect(&target_addr)?;
-    tracing::info!(
-        "VBAN sender targeting {}, stream: {}",
-        target_addr,
-        config.stream_name
-    );
+/// Whether a VBAN TEXT message is a call
+pub fn is_call(text: &str) -> bool {
+    text.trim() == CALL_MESSAGE
+}
 
-    // Playback buffer for VBAN receive
-    let playback_buffer = Arc::new(Mutex::new(AudioBuffer::new(SAMPLE_RATE as usize)));
+/// Blink phase `age` into a call alert (true = lit), None once it is over
+pub fn call_blink_at(age: Duration) -> Option<bool> {
+    if age >= CALL_ALERT {
+        return None;
+    }
+    Some((age.as_millis() / CALL_BLINK_INTERVAL.as_millis()) % 2 == 0)
+}
+
+// =============================================================================
+// Tally Auto Unmute (mic follows the camera's tally)
+// =============================================================================
 
-    // Stats
-    let frames_received = Arc::new(AtomicU64::new(0));
-    let frames_sent = Arc::new(AtomicU64::new(0));
-    let samples_captured = Arc::new(AtomicU64::new(0));
+/// How often the follower checks the tally and the mute flag
+const AUTO_UNMUTE_POLL: Duration = Duration::from_millis(50);
+
+/// Tally state in which the mic opens by itself (`intercom.auto_unmute`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AutoUnmute {
+    /// Mute only follows the buttons
+    #[default]
+    Never,
+    /// Open on preview or program
+    Preview,
+    /// Open on program only
+    Program,
+}
 
-    // Start VBAN receiver thread
-    let recv_config = config.clone();
-    let recv_buf = Arc::clone(&playback_buffer);
-    let recv_running = Arc::clone(&running);
-    let recv_frames = Arc::clone(&frames_received);
-    std::thread::spawn(move || {
-        if let Err(e) = run_receiver(&recv_config, recv_buf, recv_running, recv_frames) {
-            tracing::error!("VBAN receiver error: {}", e);
+impl AutoUnmute {
+    /// Parse a policy name from configuration ("never", "preview", "program")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "never" => Ok(AutoUnmute::Never),
+            "preview" => Ok(AutoUnmute::Preview),
+            "program" => Ok(AutoUnmute::Program),
+            other => Err(anyhow!(
+                "Unsupported auto unmute mode: {}. Supported: never, preview, program",
+                other
+            )),
         }
-    });
+    }
 
-    // Audio gains
-    let sidetone_gain = config.sidetone_gain;
-    let headphone_gain = config.headphone_gain;
-    let mic_gain = config.mic_gain;
+    /// Whether `tally` opens the mic
+    pub fn opens_on(self, tally: Tally) -> bool {
+        match self {
+            AutoUnmute::Never => false,
+            AutoUnmute::Preview => tally != Tally::Off,
+            AutoUnmute::Program => tally == Tally::Program,
+        }
+    }
+}
 
-    // Peak limiter for microphone output (prevents spikes from plug/unplug)
-    let mut limiter = if config.limiter_enabled {
-        Some(PeakLimiter::new(config.limiter_threshold, SAMPLE_RATE))
-    } else {
-        None
-    };
-    tracing::info!(
-        "Audio gains: mic={:.1}x, headphone={:.1}x, sidetone={:.1}x, limiter={}",
-        mic_gain,
-        headphone_gain,
-        sidetone_gain,
-        if config.limiter_enabled {
-            format!("on (threshold={:.0}%)", config.limiter_threshold * 100.0)
-        } else {
-            "off".to_string()
+/// Who decides the mute state right now
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum MuteMode {
+    /// Muted by a button, key or control command
+    ManualMuted = 0,
+    /// Opened by a button, key or control command
+    ManualOpen = 1,
+    /// Following the tally
+    AutoFollow = 2,
+}
+
+impl MuteMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            MuteMode::ManualMuted => "manual_muted",
+            MuteMode::ManualOpen => "manual_open",
+            MuteMode::AutoFollow => "auto",
         }
-    );
+    }
+}
 
-    // VBAN packet state
-    let mut frame_counter: u32 = 0;
-    let stream_name_bytes: [u8; 16] = {
-        let mut buf = [0u8; 16];
-        let name = config.stream_name.as_bytes();
-        let len = name.len().min(16);
-        buf[..len].copy_from_slice(&name[..len]);
-        buf
-    };
+/// Couples the mute state to the tally. Entering the configured tally state
+/// opens the mic and leaving it mutes again; a manual toggle wins until the
+/// next such transition. Tally changes that don't cross the configured
+/// state (e.g. off to preview under `Program`) leave a manual choice alone.
+#[derive(Debug)]
+pub struct TallyFollower {
+    policy: AutoUnmute,
+    mode: MuteMode,
+    /// In the tally state that opens the mic
+    open: bool,
+}
 
-    // Buffers
-    let mut capture_buf = vec![0i16; PERIOD_SIZE as usize];
-    let mut playback_buf = vec![0i16; (PERIOD_SIZE * 2) as usize]; // Stereo
-    let mut sidetone_buf = VecDeque::<i16>::with_capacity(1024);
+impl TallyFollower {
+    /// Start off air with the mic muted
+    pub fn new(policy: AutoUnmute) -> Self {
+        Self {
+            policy,
+            mode: match policy {
+                AutoUnmute::Never => MuteMode::ManualMuted,
+                _ => MuteMode::AutoFollow,
+            },
+            open: false,
+        }
+    }
 
-    // Stats timing
-    let mut last_report = std::time::Instant::now();
-    let report_interval = std::time::Duration::from_secs(10);
-    let mut last_received = 0u64;
-    let mut last_sent = 0u64;
+    pub fn mode(&self) -> MuteMode {
+        self.mode
+    }
 
-    // Capture watchdog - detect if capture stops producing samples
-    let mut last_capture_samples = 0u64;
-    let mut capture_stall_count = 0u32;
+    /// A button, key or control command left the mic `muted`
+    pub fn manual(&mut self, muted: bool) {
+        self.mode = if muted {
+            MuteMode::ManualMuted
+        } else {
+            MuteMode::ManualOpen
+        };
+    }
 
-    tracing::info!(
-        "Audio streams started with direct ALSA, period={}frames (~{:.1}ms)",
-        PERIOD_SIZE,
-        PERIOD_SIZE as f32 / SAMPLE_RATE as f32 * 1000.0
-    );
+    /// The current tally; returns the mute state to apply when it entered
+    /// or left the configured state
+    pub fn tally(&mut self, tally: Tally) -> Option<bool> {
+        if self.policy == AutoUnmute::Never {
+            return None;
+        }
+        let open = self.policy.opens_on(tally);
+        if open == self.open {
+            return None;
+        }
+        self.open = open;
+        self.mode = MuteMode::AutoFollow;
+        Some(!open)
+    }
+}
 
+/// Apply tally transitions to `muted` and tell manual toggles apart from
+/// our own writes, until `running` is cleared
+fn run_tally_follower(
+    policy: AutoUnmute,
+    muted: Arc<AtomicBool>,
+    stats: Arc<IntercomStats>,
+    running: Arc<AtomicBool>,
+) {
+    let mut follower = TallyFollower::new(policy);
+    let mut expected = muted.load(Ordering::Relaxed);
+    stats.set_mute_mode(follower.mode());
     while running.load(Ordering::Relaxed) {
-        let is_muted = muted.load(Ordering::Relaxed);
+        let current = muted.load(Ordering::Relaxed);
+        if current != expected {
+            follower.manual(current);
+            expected = current;
+        }
+        if let Some(now_muted) = follower.tally(stats.tally()) {
+            // A toggle racing this write wins and is seen on the next poll
+            if muted
+                .compare_exchange(expected, now_muted, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                if now_muted != expected {
+                    tracing::info!(
+                        "🎤 Microphone {} (via tally: {:?})",
+                        if now_muted { "MUTED" } else { "UNMUTED" },
+                        stats.tally()
+                    );
+                }
+                expected = now_muted;
+            }
+        }
+        stats.set_mute_mode(follower.mode());
+        sleep_while_running(&running, AUTO_UNMUTE_POLL);
+    }
+}
 
-        // === CAPTURE ===
-        let io_cap = capture.io_i16()?;
-        match io_cap.readi(&mut capture_buf) {
-            Ok(frames) if frames > 0 => {
-                samples_captured.fetch_add(frames as u64, Ordering::Relaxed);
-                capture_stall_count = 0; // Reset stall counter on successful capture
+// =============================================================================
+// Headphone Routing (per-source ear placement)
+// =============================================================================
 
-                if !is_muted {
-                    // Add RAW samples to sidetone buffer (no gain/limiter for minimum latency)
-                    for &sample in &capture_buf[..frames] {
-                        if sidetone_buf.len() < 512 {
-                            sidetone_buf.push_back(sample);
-                        }
-                    }
+/// Where one source of the headphone mix is heard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Route {
+    /// Full level in both ears, stereo sources keep their channels
+    Both,
+    /// Mono, panned from -1.0 (left) to 1.0 (right) with constant power
+    Pan(f32),
+}
 
-                    // Apply mic gain and limiter for VBAN output (separate from sidetone)
-                    // Pre-clip: catch ALSA garbage from plug/unplug BEFORE gain amplification
-                    // Any sample near max likely indicates a transient glitch
-                    const PRE_CLIP_THRESHOLD: i16 = 30000; // ~91% of max
-                    let mut vban_samples: Vec<i16> = capture_buf[..frames]
-                        .iter()
+impl Route {
+    /// Parse a route name from configuration ("left", "right", "both")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "both" => Ok(Route::Both),
+            "left" => Ok(Route::Pan(-1.0)),
+            "right" => Ok(Route::Pan(1.0)),
+            other => Err(anyhow!(
+                "Unsupported route: {}. Supported: left, right, both or a pan from -1.0 to 1.0",
+                other
+            )),
+        }
+    }
+
+    /// Pan position from configuration, -1.0 (left) to 1.0 (right)
+    pub fn from_pan(pan: f32) -> Result<Self> {
+        if !(-1.0..=1.0).contains(&pan) {
+            return Err(anyhow!("pan {} is outside -1.0..1.0", pan));
+        }
+        Ok(Route::Pan(pan))
+    }
+
+    /// (left, right) gains; a centered pan is -3dB in each ear
+    pub fn gains(self) -> (f32, f32) {
+        match self {
+            Route::Both => (1.0, 1.0),
+            Route::Pan(pan) => {
+                let angle = (pan + 1.0) * std::f32::consts::FRAC_PI_4;
+                (angle.cos(), angle.sin())
+            }
+        }
+    }
+
+    /// Place one stereo frame of a source; panned sources are mixed to mono first
+    pub fn place(self, left: f32, right: f32) -> (f32, f32) {
+        match self {
+            Route::Both => (left, right),
+            Route::Pan(_) => {
+                let mono = (left + right) / 2.0;
+                let (left_gain, right_gain) = self.gains();
+                (mono * left_gain, mono * right_gain)
+            }
+        }
+    }
+}
+
+/// Ear placement of each source mixed into the headphones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Routing {
+    /// Incoming VBAN audio
+    pub vban: Route,
+    /// Own microphone
+    pub sidetone: Route,
+    /// Link up/down notification tones
+    pub tones: Route,
+}
+
+impl Default for Routing {
+    fn default() -> Self {
+        Self {
+            vban: Route::Both,
+            sidetone: Route::Both,
+            tones: Route::Both,
+        }
+    }
+}
+
+impl Routing {
+    /// Mix one headphone frame from the (gained) source samples; left and
+    /// right are summed independently
+    pub fn mix(&self, vban: (f32, f32), sidetone: f32, tone: f32) -> [i16; 2] {
+        let sources = [
+            self.vban.place(vban.0, vban.1),
+            self.sidetone.place(sidetone, sidetone),
+            self.tones.place(tone, tone),
+        ];
+        let (left, right) = sources
+            .iter()
+            .fold((0.0, 0.0), |(left, right), (l, r)| (left + l, right + r));
+        [
+            left.round().clamp(-32768.0, 32767.0) as i16,
+            right.round().clamp(-32768.0, 32767.0) as i16,
+        ]
+    }
+}
+
+// =============================================================================
+// Troubleshooting Recorder (rolling WAV files of rx/tx audio)
+// =============================================================================
+
+/// Chunks queued for the recorder thread before samples are dropped (~2.7s of periods)
+const RECORD_QUEUE_DEPTH: usize = 512;
+
+enum RecordChunk {
+    /// Received audio after the jitter buffer (stereo interleaved)
+    Rx(Vec<i16>),
+    /// Transmitted mic audio after gain and processing (mono)
+    Tx(Vec<i16>),
+}
+
+/// Hands audio to a writer thread over a bounded channel, so disk I/O never
+/// blocks the audio loop. If the writer falls behind, chunks are dropped and
+/// counted in `IntercomStats::record_dropped`.
+pub struct Recorder {
+    sender: Option<SyncSender<RecordChunk>>,
+    handle: Option<std::thread::JoinHandle<()>>,
+    stats: Arc<IntercomStats>,
+}
+
+impl Recorder {
+    /// Start recording into `dir` as `rx-*.wav` and `tx-*.wav` segments
+    pub fn start<P: AsRef<Path>>(
+        dir: P,
+        segment: Duration,
+        keep: usize,
+        stats: Arc<IntercomStats>,
+    ) -> Result<Self, IntercomError> {
+        let dir = dir.as_ref();
+        let mut rx = SegmentedWavWriter::new(dir, "rx", SAMPLE_RATE, 2, segment, keep)
+            .map_err(IntercomError::Recording)?;
+        let mut tx = SegmentedWavWriter::new(dir, "tx", SAMPLE_RATE, 1, segment, keep)
+            .map_err(IntercomError::Recording)?;
+        let (sender, receiver) = sync_channel::<RecordChunk>(RECORD_QUEUE_DEPTH);
+
+        let handle = threads::spawn(threads::INTERCOM_RECORD, move || {
+            // Runs until the sender is dropped, then finalizes the open segments
+            for chunk in receiver {
+                let result = match chunk {
+                    RecordChunk::Rx(samples) => rx.write_samples(&samples),
+                    RecordChunk::Tx(samples) => tx.write_samples(&samples),
+                };
+                if let Err(e) = result {
+                    tracing::warn!("Recording stopped: {}", e);
+                    break;
+                }
+            }
+            let _ = rx.finish();
+            let _ = tx.finish();
+        });
+
+        tracing::info!("Recording intercom audio to {}", dir.display());
+        Ok(Self {
+            sender: Some(sender),
+            handle: Some(handle),
+            stats,
+        })
+    }
+
+    /// Queue received (stereo) audio
+    pub fn record_rx(&self, samples: &[i16]) {
+        self.send(RecordChunk::Rx(samples.to_vec()), samples.len());
+    }
+
+    /// Queue transmitted (mono) audio
+    pub fn record_tx(&self, samples: &[i16]) {
+        self.send(RecordChunk::Tx(samples.to_vec()), samples.len());
+    }
+
+    fn send(&self, chunk: RecordChunk, len: usize) {
+        if let Some(ref sender) = self.sender {
+            queue_record_chunk(sender, chunk, len, &self.stats);
+        }
+    }
+}
+
+/// Queue a chunk without blocking; count its samples as dropped if the queue is full
+fn queue_record_chunk(
+    sender: &SyncSender<RecordChunk>,
+    chunk: RecordChunk,
+    len: usize,
+    stats: &IntercomStats,
+) {
+    if sender.try_send(chunk).is_err() {
+        stats
+            .record_dropped
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer thread drain and finalize files
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// =============================================================================
+// Link Monitor (VBAN receive keepalive)
+// =============================================================================
+
+/// Link state transition reported by `LinkMonitor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkEvent {
+    /// No packets received within the timeout
+    Down,
+    /// Packets resumed after the link was down
+    Up,
+}
+
+/// Tracks time since the last accepted VBAN packet.
+/// Time is passed in explicitly so the state machine can be driven by a fake clock.
+#[derive(Debug)]
+pub struct LinkMonitor {
+    timeout: Duration,
+    last_packet: Instant,
+    link_down: bool,
+}
+
+impl LinkMonitor {
+    /// Create a monitor; the link is considered up until `timeout` passes without packets
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            last_packet: now,
+            link_down: false,
+        }
+    }
+
+    /// Record an accepted packet. Returns `Some(LinkEvent::Up)` if the link was down.
+    pub fn packet_received(&mut self, now: Instant) -> Option<LinkEvent> {
+        self.last_packet = now;
+        if self.link_down {
+            self.link_down = false;
+            Some(LinkEvent::Up)
+        } else {
+            None
+        }
+    }
+
+    /// Check for timeout. Returns `Some(LinkEvent::Down)` once when the link goes down.
+    pub fn poll(&mut self, now: Instant) -> Option<LinkEvent> {
+        if !self.link_down && now.saturating_duration_since(self.last_packet) >= self.timeout {
+            self.link_down = true;
+            Some(LinkEvent::Down)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the link is currently down
+    pub fn is_down(&self) -> bool {
+        self.link_down
+    }
+}
+
+// =============================================================================
+// Tone Generator (operator notification beeps)
+// =============================================================================
+
+/// Beep level as fraction of full scale (-14dB - audible over program audio)
+const TONE_LEVEL: f32 = 0.2;
+
+/// Segment length that never runs out (see [`ToneGenerator::play_continuous`])
+const ENDLESS: u32 = u32::MAX;
+
+/// Sine beep sequencer mixed into the headphone output
+pub struct ToneGenerator {
+    sample_rate: u32,
+    /// Peak level as fraction of full scale
+    level: f32,
+    phase: f32,
+    /// Pending segments: (frequency Hz, remaining samples). Frequency 0 = gap.
+    segments: VecDeque<(f32, u32)>,
+}
+
+impl ToneGenerator {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            level: TONE_LEVEL,
+            phase: 0.0,
+            segments: VecDeque::new(),
+        }
+    }
+
+    /// Play at `level` (fraction of full scale) instead of the beep level
+    pub fn with_level(mut self, level: f32) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Queue segments of (frequency Hz, duration ms); frequency 0 is a silent gap
+    pub fn play(&mut self, pattern: &[(f32, u32)]) {
+        for &(freq, ms) in pattern {
+            let samples = (self.sample_rate as u64 * ms as u64 / 1000) as u32;
+            self.segments.push_back((freq, samples.min(ENDLESS - 1)));
+        }
+    }
+
+    /// Queue a steady tone that plays until [`Self::stop`]
+    pub fn play_continuous(&mut self, frequency: f32) {
+        self.segments.push_back((frequency, ENDLESS));
+    }
+
+    /// Drop everything playing or queued
+    pub fn stop(&mut self) {
+        self.segments.clear();
+        self.phase = 0.0;
+    }
+
+    /// Double beep - link lost
+    pub fn play_link_down(&mut self) {
+        self.play(&[(880.0, 120), (0.0, 80), (880.0, 120)]);
+    }
+
+    /// Single beep - link restored
+    pub fn play_link_up(&mut self) {
+        self.play(&[(1320.0, 150)]);
+    }
+
+    /// Triple beep - a call to the director, mixed into the tx audio
+    pub fn play_call(&mut self) {
+        self.play(&[
+            (1000.0, 100),
+            (0.0, 60),
+            (1000.0, 100),
+            (0.0, 60),
+            (1000.0, 100),
+        ]);
+    }
+
+    /// Whether a tone is currently playing or queued
+    pub fn is_active(&self) -> bool {
+        !self.segments.is_empty()
+    }
+
+    /// Produce the next mono sample (0 when idle)
+    pub fn next_sample(&mut self) -> i16 {
+        let Some((freq, remaining)) = self.segments.front_mut() else {
+            return 0;
+        };
+
+        let sample = if *freq > 0.0 {
+            let value = (self.phase * std::f32::consts::TAU).sin() * self.level * 32767.0;
+            self.phase = (self.phase + *freq / self.sample_rate as f32).fract();
+            value as i16
+        } else {
+            0
+        };
+
+        if *remaining != ENDLESS {
+            *remaining = remaining.saturating_sub(1);
+            if *remaining == 0 {
+                self.segments.pop_front();
+                self.phase = 0.0;
+            }
+        }
+        sample
+    }
+}
+
+// =============================================================================
+// Mic Channel Selection (stereo capture devices)
+// =============================================================================
+
+/// Capture channel counts in order of preference: mono where the device
+/// offers it, else stereo (dual-mic headsets, XLR interfaces)
+const CAPTURE_CHANNELS: [u32; 2] = [1, 2];
+
+/// Which part of a stereo capture is the mic (`intercom.mic_channel`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MicChannel {
+    Left,
+    Right,
+    /// Average of both channels
+    #[default]
+    Mix,
+}
+
+impl MicChannel {
+    /// Parse a channel name from configuration ("left", "right", "mix")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "left" => Ok(MicChannel::Left),
+            "right" => Ok(MicChannel::Right),
+            "mix" => Ok(MicChannel::Mix),
+            other => Err(anyhow!(
+                "Unsupported mic channel: {}. Supported: left, right, mix",
+                other
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MicChannel::Left => "left",
+            MicChannel::Right => "right",
+            MicChannel::Mix => "mix",
+        }
+    }
+
+    /// Reduce one stereo frame to the mic sample
+    pub fn select(self, left: i16, right: i16) -> i16 {
+        match self {
+            MicChannel::Left => left,
+            MicChannel::Right => right,
+            MicChannel::Mix => ((left as i32 + right as i32) / 2) as i16,
+        }
+    }
+}
+
+/// First of `CAPTURE_CHANNELS` the device accepts
+fn negotiate_capture_channels(supports: impl Fn(u32) -> bool) -> Option<u32> {
+    CAPTURE_CHANNELS
+        .into_iter()
+        .find(|&channels| supports(channels))
+}
+
+/// Channel layout the capture device was opened with, and how its
+/// interleaved periods become the mono mic signal used for VBAN and
+/// sidetone alike
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CaptureLayout {
+    channels: usize,
+    mic: MicChannel,
+}
+
+impl CaptureLayout {
+    fn new(channels: u32, mic: MicChannel) -> Self {
+        Self {
+            channels: (channels as usize).max(1),
+            mic,
+        }
+    }
+
+    /// Interleaved samples in a capture buffer of `frames` frames
+    fn buffer_len(&self, frames: usize) -> usize {
+        frames * self.channels
+    }
+
+    /// Write the mic sample of each of the first `frames` frames of
+    /// `interleaved` to `mono`
+    fn extract_mic(&self, interleaved: &[i16], frames: usize, mono: &mut [i16]) {
+        let frames = frames.min(mono.len());
+        match self.channels {
+            1 => mono[..frames].copy_from_slice(&interleaved[..frames]),
+            channels => {
+                for (out, frame) in mono[..frames]
+                    .iter_mut()
+                    .zip(interleaved.chunks_exact(channels))
+                {
+                    *out = self.mic.select(frame[0], frame[1]);
+                }
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.channels {
+            1 => "mono".to_string(),
+            channels => format!("{} channels, mic={}", channels, self.mic.name()),
+        }
+    }
+}
+
+// =============================================================================
+// ALSA Period Negotiation (fallback to larger periods)
+// =============================================================================
+
+/// Period size and count of both PCMs (`intercom.period_frames`,
+/// `intercom.periods`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlsaPeriods {
+    /// Frames per period, also the frames the audio loop moves per pass
+    pub frames: u32,
+    /// Periods in the hardware buffer
+    pub count: u32,
+}
+
+impl Default for AlsaPeriods {
+    fn default() -> Self {
+        Self {
+            frames: PERIOD_SIZE,
+            count: BUFFER_PERIODS,
+        }
+    }
+}
+
+impl AlsaPeriods {
+    pub fn buffer_frames(self) -> u32 {
+        self.frames * self.count
+    }
+
+    /// Round-trip buffer latency in ms: a capture period waiting to be read
+    /// plus a full playback buffer
+    pub fn latency_ms(self) -> f32 {
+        (self.frames + self.buffer_frames()) as f32 * 1000.0 / SAMPLE_RATE as f32
+    }
+
+    /// Configurations to try: this one, then each larger fallback period
+    /// with the same count
+    fn ladder(self) -> Vec<AlsaPeriods> {
+        let larger = PERIOD_FALLBACKS
+            .into_iter()
+            .filter(|&frames| frames > self.frames)
+            .map(|frames| AlsaPeriods { frames, ..self });
+        std::iter::once(self).chain(larger).collect()
+    }
+}
+
+/// Open with the first configuration of `requested.ladder()` that `open`
+/// accepts. An error `retry` rejects (a missing or busy device) ends the
+/// search at once; otherwise the error of the last configuration is returned.
+fn open_with_fallback<T, E: std::fmt::Display>(
+    requested: AlsaPeriods,
+    mut open: impl FnMut(AlsaPeriods) -> std::result::Result<T, E>,
+    retry: impl Fn(&E) -> bool,
+) -> std::result::Result<(T, AlsaPeriods), E> {
+    let mut ladder = requested.ladder().into_iter().peekable();
+    loop {
+        let periods = ladder.next().expect("ladder starts with the request");
+        match open(periods) {
+            Ok(opened) => return Ok((opened, periods)),
+            Err(e) if ladder.peek().is_none() || !retry(&e) => return Err(e),
+            Err(e) => tracing::debug!(
+                "ALSA refused {} frames x {} periods: {}",
+                periods.frames,
+                periods.count,
+                e
+            ),
+        }
+    }
+}
+
+/// Whether another period size might help: not for missing or busy devices
+fn period_may_help(error: &IntercomError) -> bool {
+    match error {
+        IntercomError::AlsaDevice { errno, .. } => {
+            ![libc::ENOENT, libc::ENODEV, libc::EBUSY].contains(errno)
+        }
+        _ => false,
+    }
+}
+
+/// Capture and playback PCMs of the headset, opened with the same periods
+struct AlsaDevices {
+    capture: PCM,
+    layout: CaptureLayout,
+    playback: PCM,
+    periods: AlsaPeriods,
+}
+
+fn open_alsa(
+    device: &str,
+    mic: MicChannel,
+    requested: AlsaPeriods,
+) -> Result<AlsaDevices, IntercomError> {
+    let ((capture, layout, playback), periods) = open_with_fallback(
+        requested,
+        |periods| {
+            let (capture, layout) = open_alsa_capture(device, mic, periods)?;
+            let playback = open_alsa_playback(device, periods)?;
+            Ok((capture, layout, playback))
+        },
+        period_may_help,
+    )?;
+    if periods != requested {
+        tracing::warn!(
+            "ALSA refused {} frames x {} periods, using {} frames",
+            requested.frames,
+            requested.count,
+            periods.frames
+        );
+    }
+    tracing::info!(
+        "ALSA periods: {} frames x {}, round-trip buffer latency {:.1}ms",
+        periods.frames,
+        periods.count,
+        periods.latency_ms()
+    );
+    Ok(AlsaDevices {
+        capture,
+        layout,
+        playback,
+        periods,
+    })
+}
+
+// =============================================================================
+// Direct ALSA Audio
+// =============================================================================
+
+fn open_alsa_capture(
+    device: &str,
+    mic: MicChannel,
+    periods: AlsaPeriods,
+) -> Result<(PCM, CaptureLayout), IntercomError> {
+    let (pcm, channels) = configure_alsa_capture(device, periods)
+        .map_err(|e| IntercomError::alsa(device, Direction::Capture, e))?;
+    let layout = CaptureLayout::new(channels, mic);
+    tracing::info!(
+        "ALSA capture: {}, {}Hz {}, period={} frames",
+        device,
+        SAMPLE_RATE,
+        layout.describe(),
+        periods.frames
+    );
+    Ok((pcm, layout))
+}
+
+/// PCMs are opened non-blocking; the audio loop waits for each period with
+/// a timeout so it keeps checking for shutdown while a device is silent.
+/// Returns the PCM with its negotiated channel count.
+fn configure_alsa_capture(device: &str, periods: AlsaPeriods) -> alsa::Result<(PCM, u32)> {
+    let pcm = PCM::new(device, Direction::Capture, true)?;
+
+    let channels;
+    {
+        let hwp = HwParams::any(&pcm)?;
+        // A device without mono or stereo fails on the mono request
+        channels = negotiate_capture_channels(|c| hwp.test_channels(c).is_ok())
+            .unwrap_or(CAPTURE_CHANNELS[0]);
+        hwp.set_channels(channels)?;
+        hwp.set_rate(SAMPLE_RATE, ValueOr::Nearest)?;
+        hwp.set_format(Format::s16())?;
+        hwp.set_access(Access::RWInterleaved)?;
+        hwp.set_period_size(periods.frames as i64, ValueOr::Nearest)?;
+        hwp.set_buffer_size(periods.buffer_frames() as i64)?;
+        pcm.hw_params(&hwp)?;
+    }
+
+    {
+        let swp = pcm.sw_params_current()?;
+        swp.set_start_threshold(1)?;
+        swp.set_avail_min(periods.frames as i64)?;
+        pcm.sw_params(&swp)?;
+    }
+    Ok((pcm, channels))
+}
+
+fn open_alsa_playback(device: &str, periods: AlsaPeriods) -> Result<PCM, IntercomError> {
+    let pcm = configure_alsa_playback(device, periods)
+        .map_err(|e| IntercomError::alsa(device, Direction::Playback, e))?;
+    tracing::info!(
+        "ALSA playback: {}, {}Hz stereo, period={} frames",
+        device,
+        SAMPLE_RATE,
+        periods.frames
+    );
+    Ok(pcm)
+}
+
+fn configure_alsa_playback(device: &str, periods: AlsaPeriods) -> alsa::Result<PCM> {
+    let pcm = PCM::new(device, Direction::Playback, true)?;
+
+    {
+        let hwp = HwParams::any(&pcm)?;
+        hwp.set_channels(2)?; // Stereo output
+        hwp.set_rate(SAMPLE_RATE, ValueOr::Nearest)?;
+        hwp.set_format(Format::s16())?;
+        hwp.set_access(Access::RWInterleaved)?;
+        hwp.set_period_size(periods.frames as i64, ValueOr::Nearest)?;
+        hwp.set_buffer_size(periods.buffer_frames() as i64)?;
+        pcm.hw_params(&hwp)?;
+    }
+
+    {
+        let swp = pcm.sw_params_current()?;
+        swp.set_start_threshold(periods.frames as i64)?;
+        swp.set_avail_min(periods.frames as i64)?;
+        pcm.sw_params(&swp)?;
+    }
+    Ok(pcm)
+}
+
+fn recover_alsa(pcm: &PCM, err: i32) -> bool {
+    match pcm.recover(err, true) {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::error!("ALSA recovery failed: {}", e);
+            false
+        }
+    }
+}
+
+// =============================================================================
+// VBAN Receiver
+// =============================================================================
+
+/// Interval between unsolicited VBAN identification packets
+const PING_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Build the VBAN identification advertised to VoiceMeeter's VBAN dialog
+fn identification(config: &IntercomConfig) -> VbanPing {
+    let mut version = [0u8; 4];
+    for (slot, part) in version.iter_mut().zip(env!("CARGO_PKG_VERSION").split('.')) {
+        *slot = part.parse().unwrap_or(0);
+    }
+    VbanPing {
+        device_type: VBAN_PING_TYPE_RECEPTOR | VBAN_PING_TYPE_TRANSMITTER,
+        features: VBAN_PING_FEATURE_AUDIO | VBAN_PING_FEATURE_VOIP,
+        preferred_rate: SAMPLE_RATE,
+        min_rate: SAMPLE_RATE,
+        max_rate: SAMPLE_RATE,
+        version,
+        device_name: env!("CARGO_PKG_NAME").to_string(),
+        manufacturer_name: env!("CARGO_PKG_NAME").to_string(),
+        application_name: format!("{} intercom", env!("CARGO_PKG_NAME")),
+        host_name: config.hostname.clone(),
+        user_name: config.stream_name.clone(),
+    }
+}
+
+/// Apply QoS marking and optional interface pinning to an intercom socket.
+/// DSCP failures only degrade prioritization, so they are logged; a requested
+/// interface that cannot be bound is an error.
+fn configure_socket(socket: &UdpSocket, config: &IntercomConfig) -> Result<(), IntercomError> {
+    if let Err(e) = net::set_dscp(socket, config.dscp) {
+        tracing::warn!("VBAN socket: {}", e);
+    }
+    if let Some(ref interface) = config.interface {
+        net::bind_to_device(socket, interface).map_err(IntercomError::Interface)?;
+    }
+    Ok(())
+}
+
+/// Bind the VBAN receive socket to `config.listen`
+fn bind_receiver_socket(config: &IntercomConfig) -> Result<UdpSocket, IntercomError> {
+    let bind_error = |source| IntercomError::Bind {
+        addr: config.listen.clone(),
+        source,
+    };
+    let addr = config
+        .listen
+        .parse::<SocketAddr>()
+        .map_err(|e| bind_error(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+    let socket = net::bind_udp(addr).map_err(bind_error)?;
+    socket
+        .set_read_timeout(Some(std::time::Duration::from_millis(100)))
+        .ok();
+    configure_socket(&socket, config)?;
+    if let Err(e) = net::set_recv_buffer_size(&socket, config.recv_buffer_size) {
+        tracing::warn!("VBAN receiver: {}", e);
+    }
+    Ok(socket)
+}
+
+/// Create the VBAN send socket connected to `config.target_host:config.port`,
+/// trying each resolved address of the bind address's family in turn
+fn connect_sender_socket(config: &IntercomConfig) -> Result<UdpSocket, IntercomError> {
+    let target = net::host_port(&config.target_host, config.port);
+    let connect_error = |source| IntercomError::Connect {
+        target: target.clone(),
+        source,
+    };
+    let addrs = net::resolve(&config.target_host, config.port).map_err(connect_error)?;
+    let mut last_error = std::io::Error::new(
+        std::io::ErrorKind::AddrNotAvailable,
+        "no address of the bind_address family",
+    );
+    for addr in addrs {
+        let local = match config.bind_address {
+            Some(ip) if ip.is_ipv4() != addr.is_ipv4() => continue,
+            Some(ip) => SocketAddr::new(ip, 0),
+            None => net::unspecified_for(&addr),
+        };
+        let socket = UdpSocket::bind(local).map_err(|source| IntercomError::Bind {
+            addr: local.to_string(),
+            source,
+        })?;
+        configure_socket(&socket, config)?;
+        // The audio loop must never block on a full send buffer
+        match socket
+            .connect(addr)
+            .and_then(|()| socket.set_nonblocking(true))
+        {
+            Ok(()) => return Ok(socket),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(connect_error(last_error))
+}
+
+/// Send one packet on the non-blocking VBAN socket. A full send buffer
+/// drops the packet and counts it; other errors, like a far end that isn't
+/// listening yet, are left to the link monitor. Returns whether it went out.
+fn send_packet(socket: &UdpSocket, packet: &[u8], stats: &IntercomStats) -> bool {
+    match io_util::send_or_drop(|| socket.send(packet)) {
+        Ok(SendOutcome::Sent) => true,
+        Ok(SendOutcome::Dropped) => {
+            stats.send_dropped.fetch_add(1, Ordering::Relaxed);
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+fn run_receiver(
+    config: &IntercomConfig,
+    playback_buffer: Arc<Mutex<AudioBuffer>>,
+    running: Arc<AtomicBool>,
+    stats: Arc<IntercomStats>,
+) -> Result<()> {
+    let socket = bind_receiver_socket(config)?;
+
+    tracing::info!(
+        "VBAN receiver listening on {}, stream: {}",
+        socket.local_addr()?,
+        config.stream_name
+    );
+    let mut source_filter = SourceFilter::new(&config.allowed_sources, config.auto_lock);
+    if source_filter.is_restricted() || config.auto_lock {
+        tracing::info!(
+            "VBAN source filter: allowed={:?}, auto_lock={}",
+            config.allowed_sources,
+            config.auto_lock
+        );
+    }
+    let mut packet_buf = [0u8; MAX_VBAN_PACKET_SIZE];
+    let ping = identification(config);
+    let mut ping_counter: u32 = 0;
+    let mut last_announce: Option<Instant> = None;
+
+    while running.load(Ordering::Relaxed) {
+        if config.ping_announce
+            && last_announce.is_none_or(|t| t.elapsed() >= PING_ANNOUNCE_INTERVAL)
+        {
+            last_announce = Some(Instant::now());
+            match net::resolve(&config.target_host, config.port) {
+                Ok(addrs) => {
+                    let local = socket.local_addr()?;
+                    ping_counter = ping_counter.wrapping_add(1);
+                    let target = net::peer_for(&local, addrs[0]);
+                    let packet = ping.encode(false, ping_counter);
+                    let _ = io_util::retry_eintr(|| socket.send_to(&packet, target));
+                }
+                Err(e) => tracing::debug!("VBAN announce: cannot resolve target: {}", e),
+            }
+        }
+
+        match socket.recv_from(&mut packet_buf) {
+            Ok((len, addr)) => {
+                if len < VBAN_HEADER_SIZE {
+                    continue;
+                }
+                if is_ping_request(&packet_buf[..len]) {
+                    tracing::debug!("VBAN ping from {}", addr);
+                    ping_counter = ping_counter.wrapping_add(1);
+                    let packet = ping.encode(true, ping_counter);
+                    let _ = io_util::retry_eintr(|| socket.send_to(&packet, addr));
+                    continue;
+                }
+                if let Ok(text) = decode_text_packet(&packet_buf[..len]) {
+                    let ours = if config.stream_ignore_case {
+                        text.matches_name_ignore_case(&config.stream_name)
+                    } else {
+                        text.matches_name(&config.stream_name)
+                    };
+                    let now = Instant::now();
+                    if ours && is_call(&text.text) && source_filter.accept(addr.ip(), now) {
+                        tracing::info!("📣 Call from the director ({})", addr);
+                        stats.record_call(now);
+                    }
+                    continue;
+                }
+                let header = match VbanHeader::decode(&packet_buf[..len]) {
+                    Ok(h) => h,
+                    Err(_) => continue,
+                };
+                let matches = if config.stream_ignore_case {
+                    header.matches_name_ignore_case(&config.stream_name)
+                } else {
+                    header.matches_name(&config.stream_name)
+                };
+                if !matches {
+                    continue;
+                }
+                if !source_filter.accept(addr.ip(), Instant::now()) {
+                    stats.packets_rejected.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+
+                let audio_data = &packet_buf[VBAN_HEADER_SIZE..len];
+                let codec = VbanCodec::from_format(header.codec).unwrap_or(VbanCodec::Pcm16);
+                let samples = decode_samples(audio_data, codec);
+
+                if let Ok(mut buf) = playback_buffer.lock() {
+                    buf.push_samples(&samples);
+                }
+                stats.packets_received.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => tracing::warn!("VBAN receive error: {}", e),
+        }
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Main Intercom Loop
+// =============================================================================
+
+fn apply_intercom_priority() {
+    unsafe {
+        libc::nice(10);
+        let mut cpuset: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(1, &mut cpuset);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &cpuset);
+    }
+}
+
+/// Apply mute requests from the control socket until shutdown or until its
+/// sender goes away
+async fn run_mute_control(
+    mut control: watch::Receiver<bool>,
+    muted: Arc<AtomicBool>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            changed = control.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                let now_muted = *control.borrow_and_update();
+                muted.store(now_muted, Ordering::Relaxed);
+                tracing::info!(
+                    "🎤 Microphone {} (via control socket)",
+                    if now_muted { "MUTED" } else { "UNMUTED" }
+                );
+            }
+            _ = wait_for_shutdown(&mut shutdown) => return,
+        }
+    }
+}
+
+/// Resolve once `shutdown` reads true or its sender is dropped
+async fn wait_for_shutdown(shutdown: &mut watch::Receiver<bool>) {
+    while !*shutdown.borrow_and_update() {
+        if shutdown.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Start the intercom and its mute/tally inputs. ALSA and evdev still run
+/// on blocking threads; the returned task resolves once `shutdown` turns
+/// true (or its sender is dropped) and every intercom thread has been joined.
+/// Must be called from within a tokio runtime.
+pub fn spawn_intercom(
+    config: IntercomConfig,
+    stats: Arc<IntercomStats>,
+    mute_control: watch::Receiver<bool>,
+    mut shutdown: watch::Receiver<bool>,
+) -> tokio::task::JoinHandle<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    // Gains changed at runtime outlive intercom restarts too
+    stats.set_gains(config.mic_gain, config.headphone_gain, config.sidetone_gain);
+    // Mute state and its inputs outlive intercom restarts, so buttons and
+    // GPIO lines are only claimed once
+    let muted = Arc::new(AtomicBool::new(true));
+    // Raised by a long press, taken by the audio loop which sends the call
+    let call = Arc::new(AtomicBool::new(false));
+    let mut threads = Vec::new();
+
+    let mute_key = config.mute_key;
+    let mute_led = config.mute_led;
+    let muted_btn = Arc::clone(&muted);
+    let call_btn = Arc::clone(&call);
+    let running_btn = Arc::clone(&running);
+    threads.push(threads::spawn(threads::MUTE_KEY, move || {
+        input::run_mute_key_monitor(mute_key, mute_led, muted_btn, call_btn, running_btn)
+    }));
+
+    if let Some(line) = config.button_gpio.clone() {
+        let muted_gpio = Arc::clone(&muted);
+        let call_gpio = Arc::clone(&call);
+        let running_gpio = Arc::clone(&running);
+        threads.push(threads::spawn(threads::MUTE_BUTTON, move || {
+            run_gpio_button_monitor(line, muted_gpio, call_gpio, running_gpio)
+        }));
+    }
+
+    let policy = config.auto_unmute;
+    let muted_tally = Arc::clone(&muted);
+    let stats_tally = Arc::clone(&stats);
+    let running_tally = Arc::clone(&running);
+    threads.push(threads::spawn(threads::AUTO_UNMUTE, move || {
+        run_tally_follower(policy, muted_tally, stats_tally, running_tally)
+    }));
+
+    if let Some(pins) = config.tally_led.clone() {
+        let stats_led = Arc::clone(&stats);
+        let running_led = Arc::clone(&running);
+        threads.push(threads::spawn(threads::TALLY_LED, move || {
+            run_tally_led(pins, stats_led, running_led)
+        }));
+    }
+
+    let mute_task = tokio::spawn(run_mute_control(
+        mute_control,
+        Arc::clone(&muted),
+        shutdown.clone(),
+    ));
+
+    let running_audio = Arc::clone(&running);
+    threads.push(threads::spawn(threads::INTERCOM_AUDIO, move || {
+        apply_intercom_priority();
+        run_intercom(&config, &running_audio, &muted, &call, &stats);
+    }));
+
+    tokio::spawn(async move {
+        wait_for_shutdown(&mut shutdown).await;
+        running.store(false, Ordering::Relaxed);
+        let _ = mute_task.await;
+        let joined = tokio::task::spawn_blocking(move || {
+            threads::run_named(threads::INTERCOM_STOP, || {
+                for thread in threads {
+                    let _ = thread.join();
+                }
+            })
+        });
+        let _ = joined.await;
+        tracing::info!("Intercom stopped");
+    })
+}
+
+/// Run the intercom, restarting it after errors, until `running` is cleared
+fn run_intercom(
+    config: &IntercomConfig,
+    running: &Arc<AtomicBool>,
+    muted: &Arc<AtomicBool>,
+    call: &AtomicBool,
+    stats: &Arc<IntercomStats>,
+) {
+    while running.load(Ordering::Relaxed) {
+        tracing::info!(
+            "Starting VBAN intercom with direct ALSA: stream={}, target={}",
+            config.stream_name,
+            config.target_host
+        );
+
+        match run_intercom_inner(
+            config,
+            Arc::clone(running),
+            Arc::clone(muted),
+            call,
+            Arc::clone(stats),
+        ) {
+            Ok(()) => break,
+            Err(e) => {
+                tracing::error!("Intercom error: {} - restarting in 2 seconds", e);
+                sleep_while_running(running, Duration::from_secs(2));
+            }
+        }
+    }
+}
+
+/// VBAN receiver thread of one intercom session, stopped and joined on drop
+struct ReceiverThread {
+    running: Arc<AtomicBool>,
+    handle: Option<std::thread::JoinHandle<()>>,
+}
+
+impl ReceiverThread {
+    fn spawn(
+        config: &IntercomConfig,
+        playback_buffer: Arc<Mutex<AudioBuffer>>,
+        stats: Arc<IntercomStats>,
+    ) -> Self {
+        let config = config.clone();
+        let running = Arc::new(AtomicBool::new(true));
+        let running_recv = Arc::clone(&running);
+        let handle = threads::spawn(threads::INTERCOM_RECEIVE, move || {
+            if let Err(e) = run_receiver(&config, playback_buffer, running_recv, stats) {
+                tracing::error!("VBAN receiver error: {}", e);
+            }
+        });
+        Self {
+            running,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for ReceiverThread {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// =============================================================================
+// Testable Audio Buffer (public for testing)
+// =============================================================================
+
+/// Ring buffer for audio samples (exposed for testing)
+#[derive(Debug)]
+pub struct TestableAudioBuffer {
+    samples: VecDeque<i16>,
+    capacity: usize,
+}
+
+impl TestableAudioBuffer {
+    /// Create a new audio buffer with the given capacity
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Push samples into the buffer, dropping oldest if at capacity
+    pub fn push_samples(&mut self, data: &[i16]) {
+        while self.samples.len() + data.len() > self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.extend(data.iter().copied());
+    }
+
+    /// Pop up to `count` samples from the buffer
+    pub fn pop_samples(&mut self, count: usize) -> Vec<i16> {
+        let available = count.min(self.samples.len());
+        self.samples.drain(..available).collect()
+    }
+
+    /// Get current number of samples in buffer
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Check if buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Get buffer capacity
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+fn run_intercom_inner(
+    config: &IntercomConfig,
+    running: Arc<AtomicBool>,
+    muted: Arc<AtomicBool>,
+    call: &AtomicBool,
+    stats: Arc<IntercomStats>,
+) -> Result<()> {
+    // Open ALSA devices with retry
+    let AlsaDevices {
+        capture,
+        layout,
+        playback,
+        periods,
+    } = loop {
+        match open_alsa(&config.alsa_device, config.mic_channel, config.periods) {
+            Ok(devices) => break devices,
+            Err(e) => {
+                if !running.load(Ordering::Relaxed) {
+                    return Ok(());
+                }
+                tracing::warn!("Waiting for audio device: {} - retrying...", e);
+                sleep_while_running(&running, Duration::from_secs(2));
+            }
+        }
+    };
+    stats.set_latency_ms(Some(periods.latency_ms()));
+    let period = periods.frames as usize;
+
+    // Mute state
+    muted.store(true, Ordering::Relaxed);
+    tracing::info!("🎤 Microphone starts MUTED - press power button to unmute");
+
+    // VBAN sender
+    let vban_socket = connect_sender_socket(config)?;
+    tracing::info!(
+        "VBAN sender targeting {}:{}, stream: {}, codec: {:?}",
+        config.target_host,
+        config.port,
+        config.stream_name,
+        config.tx_codec
+    );
+
+    // Playback buffer for VBAN receive
+    let playback_buffer = Arc::new(Mutex::new(AudioBuffer::new(SAMPLE_RATE as usize)));
+
+    // Start VBAN receiver thread, joined when this session ends
+    let _receiver = ReceiverThread::spawn(config, Arc::clone(&playback_buffer), Arc::clone(&stats));
+
+    // Audio gains; headphone and sidetone gains follow runtime changes
+    // through the stats
+    let mic_gain = config.mic_gain;
+
+    // Peak limiter for microphone output (prevents spikes from plug/unplug)
+    let mut limiter = if config.limiter_enabled {
+        Some(PeakLimiter::new(config.limiter_threshold, SAMPLE_RATE))
+    } else {
+        None
+    };
+    let mut echo_suppressor = if config.echo.enabled {
+        tracing::info!(
+            "Echo suppression on: far>{:.3}, near<{:.3}, duck {:.0}dB",
+            config.echo.far_threshold,
+            config.echo.near_threshold,
+            config.echo.duck_db
+        );
+        Some(EchoSuppressor::new(&config.echo, SAMPLE_RATE))
+    } else {
+        None
+    };
+    // Optional troubleshooting recorder; failing to start it must not stop the intercom
+    let recorder = config.record_dir.as_ref().and_then(|dir| {
+        Recorder::start(
+            dir,
+            Duration::from_secs(config.record_segment_secs.max(1)),
+            config.record_keep,
+            Arc::clone(&stats),
+        )
+        .map_err(|e| tracing::warn!("Intercom recording disabled: {}", e))
+        .ok()
+    });
+
+    // Dead mic detection; a new session starts from a clear flag
+    let mut silence = config.silence.enabled.then(|| {
+        SilenceDetector::new(
+            config.silence.threshold_db,
+            Duration::from_secs(config.silence.secs),
+        )
+    });
+    stats.mic_silent.store(false, Ordering::Relaxed);
+    let mut text_packet = Vec::new();
+    let mut text_counter: u32 = 0;
+
+    // Incoming level of the last playback period, drives the echo suppressor
+    let mut far_level = 0.0f32;
+    tracing::info!(
+        "Audio gains: mic={:.1}x, headphone={:.1}x, sidetone={:.1}x, limiter={}",
+        mic_gain,
+        stats.headphone_gain(),
+        stats.sidetone_gain(),
+        if config.limiter_enabled {
+            format!("on (threshold={:.0}%)", config.limiter_threshold * 100.0)
+        } else {
+            "off".to_string()
+        }
+    );
+
+    // Link monitor and notification tones
+    let mut link_monitor = LinkMonitor::new(
+        Duration::from_millis(config.link_timeout_ms),
+        Instant::now(),
+    );
+    let mut tone = ToneGenerator::new(SAMPLE_RATE);
+    // Call beeps for the director, mixed into the tx audio
+    let mut call_tone = ToneGenerator::new(SAMPLE_RATE);
+    let mut link_frames_seen = stats.packets_received.load(Ordering::Relaxed);
+
+    // VBAN packet state (one scratch buffer shared by audio and keepalive packets)
+    let mut packet_writer = VbanPacketWriter::new(VbanHeader::new(
+        &config.stream_name,
+        SAMPLE_RATE,
+        2,
+        config.tx_codec,
+    )?);
+    let mut frame_counter: u32 = 0;
+    let mut last_keepalive = Instant::now();
+
+    // Buffers
+    let mut capture_buf = vec![0i16; layout.buffer_len(period)];
+    let mut mic_buf = vec![0i16; period];
+    let mut playback_buf = vec![0i16; period * 2]; // Stereo
+    let mut drift = DriftCompensator::new(2, period);
+    let mut sidetone_buf = VecDeque::<i16>::with_capacity(1024);
+
+    // Mute fades: the mic path runs on the capture clock, sidetone on the
+    // playback clock. Both start muted.
+    let ramp_samples = SAMPLE_RATE * MUTE_RAMP_MS / 1000;
+    let mut tx_ramp = GainRamp::new(0.0, ramp_samples);
+    let mut sidetone_ramp = GainRamp::new(0.0, ramp_samples);
+
+    // Watchdog timing; the counters are reported by the stats ticker
+    let mut last_stall_check = Instant::now();
+    let mut last_samples = stats.samples_captured.load(Ordering::Relaxed);
+
+    // Capture watchdog - detect if capture stops producing samples
+    let mut capture_stall_count = 0u32;
+
+    tracing::info!(
+        "Audio streams started with direct ALSA, period={}frames (~{:.1}ms)",
+        period,
+        period as f32 / SAMPLE_RATE as f32 * 1000.0
+    );
+
+    while running.load(Ordering::Relaxed) {
+        let is_muted = muted.load(Ordering::Relaxed);
+        stats.muted.store(is_muted, Ordering::Relaxed);
+        let gain = if is_muted { 0.0 } else { 1.0 };
+        tx_ramp.set_target(gain);
+        sidetone_ramp.set_target(gain);
+
+        // === CALL ===
+        if call.swap(false, Ordering::Relaxed) {
+            if encode_text_packet(
+                &config.stream_name,
+                text_counter,
+                CALL_MESSAGE,
+                &mut text_packet,
+            )
+            .is_ok()
+            {
+                send_packet(&vban_socket, &text_packet, &stats);
+                text_counter = text_counter.wrapping_add(1);
+                stats.calls_sent.fetch_add(1, Ordering::Relaxed);
+            }
+            if config.call_tone {
+                call_tone.play_call();
+            }
+        }
+
+        // Still sending while the fade out or a call tone plays
+        let sending = !is_muted || !tx_ramp.is_settled() || call_tone.is_active();
+
+        // === CAPTURE ===
+        // A period arrives every ~5ms; a timed out wait counts for the
+        // iterations it stood in for (errors surface from readi below)
+        if !capture.wait(Some(ALSA_WAIT_MS)).unwrap_or(true) {
+            capture_stall_count += ALSA_WAIT_MS / 5;
+        }
+        let io_cap = capture.io_i16()?;
+        match io_cap.readi(&mut capture_buf) {
+            Ok(frames) if frames > 0 => {
+                stats
+                    .samples_captured
+                    .fetch_add(frames as u64, Ordering::Relaxed);
+                capture_stall_count = 0; // Reset stall counter on successful capture
+                layout.extract_mic(&capture_buf, frames, &mut mic_buf);
+
+                // Raw level, before gain and muting, for the stats and the dead mic check
+                let mic_level = rms_level(&mic_buf[..frames]);
+                stats.set_mic_level_db(Some(level_db(mic_level)));
+                let event = silence
+                    .as_mut()
+                    .and_then(|detector| detector.update(mic_level, is_muted, Instant::now()));
+                if let Some(event) = event {
+                    match event {
+                        SilenceEvent::Silent => tracing::warn!(
+                            "Mic SILENT - below {:.0}dBFS for {}s while unmuted, check the mic",
+                            config.silence.threshold_db,
+                            config.silence.secs
+                        ),
+                        SilenceEvent::Restored => tracing::info!("Mic signal restored"),
+                    }
+                    stats
+                        .mic_silent
+                        .store(event == SilenceEvent::Silent, Ordering::Relaxed);
+                    if config.silence.notify {
+                        let notice = silence_notice(&config.stream_name, event);
+                        if encode_text_packet(
+                            &config.stream_name,
+                            text_counter,
+                            &notice,
+                            &mut text_packet,
+                        )
+                        .is_ok()
+                        {
+                            send_packet(&vban_socket, &text_packet, &stats);
+                            text_counter = text_counter.wrapping_add(1);
+                        }
+                    }
+                }
+
+                // Add RAW samples to sidetone buffer (no gain/limiter for minimum
+                // latency); the sidetone fade mutes them at playback
+                for &sample in &mic_buf[..frames] {
+                    if sidetone_buf.len() < 2 * period {
+                        sidetone_buf.push_back(sample);
+                    }
+                }
+
+                if sending {
+                    // Apply mic gain and limiter for VBAN output (separate from sidetone)
+                    // Pre-clip: catch ALSA garbage from plug/unplug BEFORE gain amplification
+                    // Any sample near max likely indicates a transient glitch
+                    const PRE_CLIP_THRESHOLD: i16 = 30000; // ~91% of max
+                    let mut vban_samples: Vec<i16> = mic_buf[..frames]
+                        .iter()
                         .map(|&s| {
                             // Pre-clip extreme values before applying gain
                             let clipped = s.clamp(-PRE_CLIP_THRESHOLD, PRE_CLIP_THRESHOLD);
@@ -664,172 +3133,1368 @@ fn run_intercom_inner(config: &IntercomConfig, running: Arc<AtomicBool>) -> Resu
                         })
                         .collect();
 
-                    // Apply limiter if enabled (prevents spikes from plug/unplug)
-                    if let Some(ref mut lim) = limiter {
-                        lim.process_buffer(&mut vban_samples);
+                    // Duck mic while only the far end is talking (echo protection)
+                    if let Some(ref mut echo) = echo_suppressor {
+                        echo.process_buffer(&mut vban_samples, far_level);
+                    }
+
+                    // Apply limiter if enabled (prevents spikes from plug/unplug)
+                    if let Some(ref mut lim) = limiter {
+                        lim.process_buffer(&mut vban_samples);
+                    }
+
+                    tx_ramp.process_buffer(&mut vban_samples);
+                    // The call tone goes out muted or not
+                    if call_tone.is_active() {
+                        for sample in vban_samples.iter_mut() {
+                            *sample = sample.saturating_add(call_tone.next_sample());
+                        }
+                    }
+
+                    if let Some(ref rec) = recorder {
+                        rec.record_tx(&vban_samples);
+                    }
+                    if let Some(ref tap) = config.mic_tap {
+                        let _ = tap.try_send(vban_samples.clone());
+                    }
+
+                    // Send VBAN packets
+                    const CHUNK_SIZE: usize = 128;
+                    for chunk in vban_samples.chunks(CHUNK_SIZE) {
+                        let packet = packet_writer.write_mono_as_stereo(chunk, frame_counter);
+                        if send_packet(&vban_socket, packet, &stats) {
+                            stats.packets_sent.fetch_add(1, Ordering::Relaxed);
+                        }
+                        frame_counter = frame_counter.wrapping_add(1);
+                    }
+                }
+            }
+            Ok(_) => {
+                // Zero frames - capture might be stalled
+                capture_stall_count += 1;
+            }
+            // Nothing ready after the wait, already counted above
+            Err(e) if e.errno() == libc::EAGAIN => {}
+            Err(e) => {
+                capture_stall_count += 1;
+                stats.xruns.fetch_add(1, Ordering::Relaxed);
+                if !recover_alsa(&capture, e.errno()) {
+                    return Err(anyhow!("ALSA capture error: {}", e));
+                }
+            }
+        }
+
+        // Quick stall detection: if 500+ consecutive iterations without capture
+        // (about 2.5 seconds at 5ms/iteration), force restart
+        if capture_stall_count > 500 {
+            tracing::warn!(
+                "Capture device unresponsive ({} consecutive failures), forcing restart...",
+                capture_stall_count
+            );
+            return Err(anyhow!("Capture device unresponsive"));
+        }
+
+        // === KEEPALIVE ===
+        // While muted, send one minimal silent packet per second
+        if !sending && last_keepalive.elapsed() >= KEEPALIVE_INTERVAL {
+            let packet = packet_writer.write_mono_as_stereo(&[0], frame_counter);
+            if send_packet(&vban_socket, packet, &stats) {
+                stats.packets_sent.fetch_add(1, Ordering::Relaxed);
+            }
+            frame_counter = frame_counter.wrapping_add(1);
+            last_keepalive = Instant::now();
+        }
+
+        // === LINK STATUS ===
+        let now = Instant::now();
+        let received_total = stats.packets_received.load(Ordering::Relaxed);
+        let link_event = if received_total != link_frames_seen {
+            link_frames_seen = received_total;
+            link_monitor.packet_received(now)
+        } else {
+            link_monitor.poll(now)
+        };
+        match link_event {
+            Some(LinkEvent::Down) => {
+                tracing::warn!(
+                    "VBAN link DOWN - no packets for {}ms",
+                    config.link_timeout_ms
+                );
+                tone.play_link_down();
+            }
+            Some(LinkEvent::Up) => {
+                tracing::info!("VBAN link UP - packets resumed");
+                tone.play_link_up();
+            }
+            None => {}
+        }
+        stats
+            .link_up
+            .store(!link_monitor.is_down(), Ordering::Relaxed);
+
+        // === PLAYBACK ===
+        // Mix VBAN + sidetone + notification tones. Drift is only measured
+        // while packets arrive.
+        if link_monitor.is_down() {
+            drift.reset();
+        }
+        let vban_samples = if let Ok(mut buf) = playback_buffer.lock() {
+            let samples = drift.pop(&mut buf, playback_buf.len(), now);
+            stats.buffer_depth.store(buf.len(), Ordering::Relaxed);
+            samples
+        } else {
+            vec![]
+        };
+        stats.set_drift_ppm(drift.drift_ppm().map(|ppm| ppm as f32));
+        if let Some(ref rec) = recorder {
+            rec.record_rx(&vban_samples);
+        }
+
+        let headphone_gain = stats.headphone_gain();
+        let sidetone_gain = stats.sidetone_gain();
+        let mut far_energy = 0.0f32;
+        for (i, frame) in playback_buf.chunks_exact_mut(2).enumerate() {
+            let vban = |channel: usize| {
+                vban_samples.get(2 * i + channel).copied().unwrap_or(0) as f32 * headphone_gain
+            };
+            let (left, right) = (vban(0), vban(1));
+            for far in [left, right] {
+                let far = far.clamp(-32768.0, 32767.0) / 32768.0;
+                far_energy += far * far;
+            }
+            let sidetone = sidetone_buf.pop_front().unwrap_or(0) as f32
+                * sidetone_gain
+                * sidetone_ramp.next_gain();
+            let tone = tone.next_sample() as f32;
+            frame.copy_from_slice(&config.routing.mix((left, right), sidetone, tone));
+        }
+        far_level = (far_energy / playback_buf.len() as f32).sqrt();
+
+        // Write to ALSA; a period that finds the buffer still full is dropped
+        let _ = playback.wait(Some(ALSA_WAIT_MS));
+        let io_play = playback.io_i16()?;
+        match io_play.writei(&playback_buf) {
+            Ok(_) => {}
+            Err(e) if e.errno() == libc::EAGAIN => {}
+            Err(e) => {
+                stats.xruns.fetch_add(1, Ordering::Relaxed);
+                if !recover_alsa(&playback, e.errno()) {
+                    return Err(anyhow!("ALSA playback error: {}", e));
+                }
+            }
+        }
+
+        // Watchdog: if no samples captured in this period, something is wrong
+        if last_stall_check.elapsed() >= STALL_CHECK_INTERVAL {
+            let samples = stats.samples_captured.load(Ordering::Relaxed);
+            if samples == last_samples {
+                tracing::warn!(
+                    "Capture stalled! No samples in {}s (stall_count={}), forcing restart...",
+                    STALL_CHECK_INTERVAL.as_secs(),
+                    capture_stall_count
+                );
+                return Err(anyhow!("Capture device stalled - forcing restart"));
+            }
+
+            last_samples = samples;
+            last_stall_check = Instant::now();
+        }
+    }
+
+    // Stopped on purpose: the operator hears about it now, not from a link
+    // timeout on the mixer
+    let notice = offline_notice(&config.stream_name);
+    if encode_text_packet(&config.stream_name, text_counter, &notice, &mut text_packet).is_ok() {
+        send_packet(&vban_socket, &text_packet, &stats);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intercom_config_default() {
+        let config = IntercomConfig::default();
+        assert_eq!(config.stream_name, "cam1");
+        assert_eq!(config.target_host, "strih.lan");
+        assert_eq!(config.sample_rate, 48000);
+        assert_eq!(config.channels, 2);
+        assert!((config.sidetone_gain - 100.0).abs() < 0.001);
+        assert!((config.mic_gain - 12.0).abs() < 0.001);
+        assert!((config.headphone_gain - 15.0).abs() < 0.001);
+        assert!(config.limiter_enabled);
+        assert!((config.limiter_threshold - 0.5).abs() < 0.001);
+        assert!(config.allowed_sources.is_empty());
+        assert!(!config.auto_lock);
+        assert_eq!(config.link_timeout_ms, 2000);
+        assert_eq!(config.tx_codec, VbanCodec::Pcm16);
+        assert_eq!(config.hostname, "camera-box");
+        assert!(!config.ping_announce);
+        assert_eq!(config.dscp, 46);
+        assert_eq!(config.recv_buffer_size, 256 * 1024);
+        assert!(config.interface.is_none());
+        assert_eq!(config.mute_key, Key::KEY_POWER);
+        assert_eq!(config.mute_led, MuteLed::Auto);
+        assert!(config.button_gpio.is_none());
+        assert!(config.tally_led.is_none());
+        assert_eq!(config.port, 6980);
+        assert_eq!(config.listen, "[::]:6980");
+        assert!(config.bind_address.is_none());
+        assert_eq!(config.alsa_device, ALSA_DEVICE);
+        assert!(config.silence.enabled);
+        assert_eq!(config.silence.secs, 60);
+        assert!(!config.silence.notify);
+    }
+
+    #[test]
+    fn test_spawn_intercom_shuts_down_promptly() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        // ALSA's null PCM runs the audio loop; a missing card keeps it retrying
+        for device in ["null", "hw:CARD=CameraBoxMissing"] {
+            let config = IntercomConfig {
+                target_host: "127.0.0.1".to_string(),
+                listen: "127.0.0.1:0".to_string(),
+                alsa_device: device.to_string(),
+                ..Default::default()
+            };
+            let (_mute, mute_control) = watch::channel(true);
+            let (shutdown, shutdown_rx) = watch::channel(false);
+            runtime.block_on(async {
+                let handle = spawn_intercom(
+                    config,
+                    Arc::new(IntercomStats::new()),
+                    mute_control,
+                    shutdown_rx,
+                );
+                tokio::time::sleep(Duration::from_millis(300)).await;
+                let started = Instant::now();
+                shutdown.send(true).unwrap();
+                tokio::time::timeout(Duration::from_secs(2), handle)
+                    .await
+                    .unwrap_or_else(|_| panic!("intercom on {} did not stop", device))
+                    .unwrap();
+                assert!(
+                    started.elapsed() < Duration::from_millis(500),
+                    "{} took {:?}",
+                    device,
+                    started.elapsed()
+                );
+            });
+        }
+    }
+
+    #[test]
+    fn test_sleep_while_running_returns_on_stop() {
+        let running = AtomicBool::new(false);
+        let started = Instant::now();
+        sleep_while_running(&running, Duration::from_secs(5));
+        assert!(started.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_intercom_config_clone() {
+        let config = IntercomConfig {
+            stream_name: "test".to_string(),
+            stream_ignore_case: true,
+            target_host: "host.lan".to_string(),
+            sample_rate: 44100,
+            channels: 1,
+            sidetone_gain: 15.0,
+            mic_gain: 2.0,
+            headphone_gain: 8.0,
+            limiter_enabled: false,
+            limiter_threshold: 0.8,
+            allowed_sources: vec!["10.0.0.5".to_string()],
+            auto_lock: true,
+            link_timeout_ms: 500,
+            tx_codec: VbanCodec::Float32,
+            hostname: "CAM1".to_string(),
+            ping_announce: true,
+            dscp: 0,
+            recv_buffer_size: 4096,
+            interface: Some("eth0".to_string()),
+            port: 6990,
+            listen: "127.0.0.1:6990".to_string(),
+            bind_address: Some(IpAddr::from([127, 0, 0, 1])),
+            record_dir: Some("/tmp/rec".to_string()),
+            record_segment_secs: 30,
+            record_keep: 3,
+            echo: EchoConfig {
+                enabled: true,
+                ..Default::default()
+            },
+            silence: SilenceConfig {
+                notify: true,
+                ..Default::default()
+            },
+            routing: Routing {
+                vban: Route::Pan(1.0),
+                ..Default::default()
+            },
+            mute_key: Key::KEY_F13,
+            mute_led: MuteLed::ScrollLock,
+            button_gpio: Some(GpioLine {
+                chip: "gpiochip0".to_string(),
+                offset: 17,
+            }),
+            tally_led: Some(TallyLedPins {
+                chip: "gpiochip0".to_string(),
+                red: 22,
+                green: 23,
+            }),
+            auto_unmute: AutoUnmute::Program,
+            call_tone: false,
+            mic_channel: MicChannel::Right,
+            alsa_device: "null".to_string(),
+            periods: AlsaPeriods {
+                frames: 64,
+                count: 3,
+            },
+            mic_tap: None,
+        };
+        let cloned = config.clone();
+        assert_eq!(config.stream_name, cloned.stream_name);
+        assert_eq!(config.target_host, cloned.target_host);
+        assert_eq!(config.sample_rate, cloned.sample_rate);
+        assert_eq!(config.channels, cloned.channels);
+        assert!((config.mic_gain - cloned.mic_gain).abs() < 0.001);
+        assert!((config.headphone_gain - cloned.headphone_gain).abs() < 0.001);
+        assert_eq!(config.limiter_enabled, cloned.limiter_enabled);
+        assert!((config.limiter_threshold - cloned.limiter_threshold).abs() < 0.001);
+        assert_eq!(config.allowed_sources, cloned.allowed_sources);
+        assert_eq!(config.auto_lock, cloned.auto_lock);
+        assert_eq!(config.link_timeout_ms, cloned.link_timeout_ms);
+        assert_eq!(config.tx_codec, cloned.tx_codec);
+        assert_eq!(config.hostname, cloned.hostname);
+        assert_eq!(config.ping_announce, cloned.ping_announce);
+        assert_eq!(config.dscp, cloned.dscp);
+        assert_eq!(config.recv_buffer_size, cloned.recv_buffer_size);
+        assert_eq!(config.interface, cloned.interface);
+        assert_eq!(config.port, cloned.port);
+        assert_eq!(config.listen, cloned.listen);
+        assert_eq!(config.bind_address, cloned.bind_address);
+        assert_eq!(config.echo.enabled, cloned.echo.enabled);
+        assert_eq!(config.silence.notify, cloned.silence.notify);
+        assert_eq!(config.routing, cloned.routing);
+        assert_eq!(config.record_dir, cloned.record_dir);
+        assert_eq!(config.record_segment_secs, cloned.record_segment_secs);
+        assert_eq!(config.record_keep, cloned.record_keep);
+        assert_eq!(config.mute_key, cloned.mute_key);
+        assert_eq!(config.mute_led, cloned.mute_led);
+        assert_eq!(config.button_gpio, cloned.button_gpio);
+        assert_eq!(config.tally_led, cloned.tally_led);
+        assert_eq!(config.auto_unmute, cloned.auto_unmute);
+        assert_eq!(config.call_tone, cloned.call_tone);
+        assert_eq!(config.mic_channel, cloned.mic_channel);
+        assert_eq!(config.periods, cloned.periods);
+    }
+
+    fn sine(amplitude: f32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|i| (amplitude * 32767.0 * (i as f32 * 0.13).sin()) as i16)
+            .collect()
+    }
+
+    /// Run the suppressor for `periods` periods with constant far/near signals
+    fn run_echo(echo: &mut EchoSuppressor, far: &[i16], near_amplitude: f32, periods: usize) {
+        let far_level = rms_level(far);
+        for _ in 0..periods {
+            let mut mic = sine(near_amplitude, 256);
+            echo.process_buffer(&mut mic, far_level);
+        }
+    }
+
+    #[test]
+    fn test_rms_level() {
+        assert_eq!(rms_level(&[]), 0.0);
+        assert_eq!(rms_level(&[0; 64]), 0.0);
+        assert!((rms_level(&[16384; 64]) - 0.5).abs() < 0.001);
+        // Sine RMS is amplitude / sqrt(2)
+        assert!((rms_level(&sine(1.0, 4800)) - 0.707).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_echo_ducks_when_far_talks_and_mic_quiet() {
+        let mut echo = EchoSuppressor::new(&EchoConfig::default(), 48000);
+        // Loud far end, mic only picks up faint leakage
+        run_echo(&mut echo, &sine(0.3, 256), 0.01, 40);
+        // 20dB duck = 0.1 linear
+        assert!((echo.gain() - 0.1).abs() < 0.01, "gain {}", echo.gain());
+    }
+
+    #[test]
+    fn test_echo_no_duck_when_near_talks() {
+        let mut echo = EchoSuppressor::new(&EchoConfig::default(), 48000);
+        // Double talk: local speech is above near threshold
+        run_echo(&mut echo, &sine(0.3, 256), 0.3, 40);
+        assert!((echo.gain() - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_echo_no_duck_when_far_silent() {
+        let mut echo = EchoSuppressor::new(&EchoConfig::default(), 48000);
+        run_echo(&mut echo, &[0; 256], 0.01, 40);
+        assert!((echo.gain() - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_echo_attack_is_smoothed() {
+        let mut echo = EchoSuppressor::new(&EchoConfig::default(), 48000);
+        let far_level = rms_level(&sine(0.3, 256));
+        let mut mic = sine(0.01, 256);
+        echo.process_buffer(&mut mic, far_level);
+        // 256 samples (~5ms) into a 10ms attack: partially ducked, not instant
+        assert!(echo.gain() < 1.0);
+        assert!(echo.gain() > 0.3, "gain {}", echo.gain());
+    }
+
+    #[test]
+    fn test_echo_release_is_slower_than_attack() {
+        let mut echo = EchoSuppressor::new(&EchoConfig::default(), 48000);
+        run_echo(&mut echo, &sine(0.3, 256), 0.01, 40);
+        let ducked = echo.gain();
+        // One period of local speech after ducking
+        run_echo(&mut echo, &sine(0.3, 256), 0.3, 1);
+        let after_one = echo.gain();
+        assert!(after_one > ducked);
+        // 200ms release: still well below unity after ~5ms
+        assert!(after_one < 0.3, "gain {}", after_one);
+        // Recovers fully after ~1s
+        run_echo(&mut echo, &sine(0.3, 256), 0.3, 200);
+        assert!(echo.gain() > 0.99);
+    }
+
+    #[test]
+    fn test_echo_applies_gain_to_samples() {
+        let mut echo = EchoSuppressor::new(&EchoConfig::default(), 48000);
+        run_echo(&mut echo, &sine(0.3, 256), 0.01, 40);
+        let mut mic = vec![1000i16; 256];
+        echo.process_buffer(&mut mic, rms_level(&sine(0.3, 256)));
+        assert!(mic.iter().all(|&s| (90..=110).contains(&s)));
+    }
+
+    /// Program monitor gain after each of `periods` stereo periods, with
+    /// the intercom talking in the periods `talking` picks
+    fn run_ducker(
+        ducker: &mut ProgramDucker,
+        periods: usize,
+        talking: impl Fn(usize) -> bool,
+    ) -> Vec<f32> {
+        let speech = rms_level(&sine(0.3, 256));
+        (0..periods)
+            .map(|i| {
+                let mut program = vec![8000i16; 512];
+                ducker.process_buffer(&mut program, if talking(i) { speech } else { 0.0 });
+                ducker.gain()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ducker_follows_speech_bursts() {
+        let mut ducker = ProgramDucker::new(&DuckingConfig::default(), 48000, 2);
+        // Two 0.5s bursts (~94 periods of 256 frames) with 2s of quiet between
+        let burst = |i: usize| i < 94 || (469..563).contains(&i);
+        let gains = run_ducker(&mut ducker, 1000, burst);
+        // 12dB duck = 0.251 linear
+        let ducked = attenuation_gain(12.0);
+
+        // 20ms attack: a quarter of the way down after one period,
+        // settled within the burst
+        assert!(gains[0] < 1.0 && gains[0] > 0.6, "gain {}", gains[0]);
+        assert!((gains[20] - ducked).abs() < 0.01, "gain {}", gains[20]);
+        assert!((gains[93] - ducked).abs() < 0.001);
+        // Never below the configured amount
+        assert!(gains.iter().all(|&g| g > ducked - 0.001));
+
+        // 500ms release: rising but still ducked 100ms after the burst,
+        // back to unity before the next one
+        assert!(
+            gains[113] > gains[93] && gains[113] < 0.5,
+            "gain {}",
+            gains[113]
+        );
+        assert!(gains[468] > 0.95, "gain {}", gains[468]);
+        // The gain only falls while talking and only rises while quiet
+        for (i, pair) in gains.windows(2).enumerate() {
+            if burst(i + 1) {
+                assert!(pair[1] <= pair[0] + 1e-6, "period {}", i + 1);
+            } else {
+                assert!(pair[1] >= pair[0] - 1e-6, "period {}", i + 1);
+            }
+        }
+        // The second burst ducks as deep as the first
+        assert!((gains[562] - ducked).abs() < 0.001);
+        assert!(gains[999] > 0.99);
+    }
+
+    #[test]
+    fn test_ducker_ignores_intercom_below_threshold() {
+        let mut ducker = ProgramDucker::new(&DuckingConfig::default(), 48000, 2);
+        // Line noise at -50dBFS stays under the -40dBFS threshold
+        let noise = 10f32.powf(-50.0 / 20.0);
+        for _ in 0..100 {
+            let mut program = vec![8000i16; 512];
+            ducker.process_buffer(&mut program, noise);
+            assert!(program.iter().all(|&s| (7999..=8000).contains(&s)));
+        }
+        assert!(ducker.gain() > 0.9999);
+    }
+
+    #[test]
+    fn test_ducker_scales_both_channels_alike() {
+        let mut ducker = ProgramDucker::new(&DuckingConfig::default(), 48000, 2);
+        run_ducker(&mut ducker, 100, |_| true);
+        let mut program: Vec<i16> = [8000i16, -4000].repeat(256);
+        ducker.process_buffer(&mut program, rms_level(&sine(0.3, 256)));
+        for frame in program.chunks(2) {
+            assert!((1990..=2020).contains(&frame[0]), "left {}", frame[0]);
+            assert!((-1010..=-995).contains(&frame[1]), "right {}", frame[1]);
+        }
+    }
+
+    /// Capture period length at 48kHz
+    const PERIOD: Duration = Duration::from_micros(5333);
+
+    /// Feed `periods` periods of `signal(period index)` from `start`,
+    /// returning the events and the time after the last period
+    fn run_silence(
+        detector: &mut SilenceDetector,
+        start: Instant,
+        periods: u32,
+        muted: bool,
+        signal: impl Fn(u32) -> Vec<i16>,
+    ) -> (Vec<SilenceEvent>, Instant) {
+        let mut events = Vec::new();
+        let mut now = start;
+        for i in 0..periods {
+            if let Some(event) = detector.update(rms_level(&signal(i)), muted, now) {
+                events.push(event);
+            }
+            now += PERIOD;
+        }
+        (events, now)
+    }
+
+    /// Syllables of ~150ms with ~250ms pauses of dead air, like speech
+    fn speech(period: u32) -> Vec<i16> {
+        if period % 75 < 28 {
+            sine(0.2, 256)
+        } else {
+            vec![0; 256]
+        }
+    }
+
+    /// An unplugged input: hum and hiss far below any voice
+    fn dead_mic(_period: u32) -> Vec<i16> {
+        sine(0.0001, 256)
+    }
+
+    #[test]
+    fn test_level_db() {
+        assert!((level_db(1.0) - 0.0).abs() < 0.001);
+        assert!((level_db(0.5) + 6.02).abs() < 0.01);
+        assert!((level_db(0.0) + 120.0).abs() < 0.001);
+        assert!(level_db(rms_level(&dead_mic(0))) < -80.0);
+    }
+
+    #[test]
+    fn test_silence_detected_after_hold() {
+        let mut detector = SilenceDetector::new(-60.0, Duration::from_secs(2));
+        let t0 = Instant::now();
+        // 1.9s of a dead mic is not enough
+        let (events, now) = run_silence(&mut detector, t0, 356, false, dead_mic);
+        assert!(events.is_empty());
+        assert!(!detector.is_silent());
+        let (events, now) = run_silence(&mut detector, now, 30, false, dead_mic);
+        assert_eq!(events, vec![SilenceEvent::Silent]);
+        assert!(detector.is_silent());
+        // Reported once, however long it lasts
+        let (events, now) = run_silence(&mut detector, now, 2000, false, dead_mic);
+        assert!(events.is_empty());
+
+        // The first syllable clears it
+        let (events, _) = run_silence(&mut detector, now, 75, false, speech);
+        assert_eq!(events, vec![SilenceEvent::Restored]);
+        assert!(!detector.is_silent());
+    }
+
+    #[test]
+    fn test_silence_not_reported_for_speech_pauses() {
+        let mut detector = SilenceDetector::new(-60.0, Duration::from_secs(2));
+        let (events, _) = run_silence(&mut detector, Instant::now(), 6000, false, speech);
+        assert!(events.is_empty());
+        assert!(!detector.is_silent());
+    }
+
+    #[test]
+    fn test_silence_only_counts_while_unmuted() {
+        let mut detector = SilenceDetector::new(-60.0, Duration::from_secs(2));
+        let t0 = Instant::now();
+        let (events, now) = run_silence(&mut detector, t0, 2000, true, dead_mic);
+        assert!(events.is_empty());
+        // Unmuting starts the count afresh
+        let (events, now) = run_silence(&mut detector, now, 300, false, dead_mic);
+        assert!(events.is_empty());
+        let (events, now) = run_silence(&mut detector, now, 100, false, dead_mic);
+        assert_eq!(events, vec![SilenceEvent::Silent]);
+
+        // Muting keeps the flag; signal while muted clears it
+        let (events, now) = run_silence(&mut detector, now, 500, true, dead_mic);
+        assert!(events.is_empty());
+        assert!(detector.is_silent());
+        let (events, _) = run_silence(&mut detector, now, 10, true, |_| sine(0.2, 256));
+        assert_eq!(events, vec![SilenceEvent::Restored]);
+    }
+
+    #[test]
+    fn test_silence_notice_text() {
+        assert_eq!(
+            silence_notice("cam1", SilenceEvent::Silent),
+            "cam1: mic silent"
+        );
+        assert_eq!(
+            silence_notice("cam1", SilenceEvent::Restored),
+            "cam1: mic signal restored"
+        );
+        assert_eq!(offline_notice("cam1"), "cam1: offline");
+    }
+
+    #[test]
+    fn test_route_pan_law_is_constant_power() {
+        for pan in [-1.0, -0.6, -0.25, 0.0, 0.3, 0.75, 1.0] {
+            let (left, right) = Route::from_pan(pan).unwrap().gains();
+            assert!(
+                (left * left + right * right - 1.0).abs() < 1e-6,
+                "pan {}",
+                pan
+            );
+        }
+        let (left, right) = Route::Pan(0.0).gains();
+        assert!((left - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!((left - right).abs() < 1e-6);
+        let (left, right) = Route::from_name("left").unwrap().gains();
+        assert!((left - 1.0).abs() < 1e-6 && right.abs() < 1e-6);
+        let (left, right) = Route::from_name("RIGHT").unwrap().gains();
+        assert!(left.abs() < 1e-6 && (right - 1.0).abs() < 1e-6);
+        assert_eq!(Route::from_name("both").unwrap().gains(), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_route_rejects_bad_values() {
+        assert!(Route::from_name("center").is_err());
+        assert!(Route::from_pan(1.5).is_err());
+        assert!(Route::from_pan(f32::NAN).is_err());
+    }
+
+    #[test]
+    fn test_routing_mixes_ears_independently() {
+        // Default: everything in both ears, VBAN keeps its stereo image
+        let routing = Routing::default();
+        assert_eq!(routing.mix((100.0, -50.0), 10.0, 1.0), [111, -39]);
+
+        // Program in the left ear, own mic and tones in the right
+        let routing = Routing {
+            vban: Route::Pan(-1.0),
+            sidetone: Route::Pan(1.0),
+            tones: Route::Pan(1.0),
+        };
+        assert_eq!(routing.mix((100.0, 300.0), 10.0, 1.0), [200, 11]);
+
+        // Sums clip per ear
+        assert_eq!(routing.mix((40000.0, 40000.0), 0.0, 0.0), [32767, 0]);
+    }
+
+    #[test]
+    fn test_recorder_writes_rx_and_tx_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let stats = Arc::new(IntercomStats::new());
+        {
+            let recorder =
+                Recorder::start(dir.path(), Duration::from_secs(60), 2, Arc::clone(&stats))
+                    .unwrap();
+            recorder.record_rx(&[1, 2, 3, 4]);
+            recorder.record_tx(&[5, 6]);
+        } // Drop finalizes the files
+
+        let mut names: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names.len(), 2);
+        assert!(names[0].starts_with("rx-"));
+        assert!(names[1].starts_with("tx-"));
+
+        let rx = std::fs::read(dir.path().join(&names[0])).unwrap();
+        let tx = std::fs::read(dir.path().join(&names[1])).unwrap();
+        assert_eq!(rx.len(), crate::wav::WAV_HEADER_SIZE + 8);
+        assert_eq!(rx[22], 2); // stereo
+        assert_eq!(tx.len(), crate::wav::WAV_HEADER_SIZE + 4);
+        assert_eq!(tx[22], 1); // mono
+        assert_eq!(stats.snapshot().record_dropped, 0);
+    }
+
+    #[test]
+    fn test_recorder_counts_dropped_samples() {
+        let stats = IntercomStats::new();
+        // Nobody drains this queue, like a writer thread stuck on slow storage
+        let (sender, _receiver) = sync_channel(2);
+        for _ in 0..5 {
+            queue_record_chunk(&sender, RecordChunk::Tx(vec![0; 256]), 256, &stats);
+        }
+        assert_eq!(stats.snapshot().record_dropped, 3 * 256);
+    }
+
+    #[test]
+    fn test_tally_led_color_unmuted() {
+        assert_eq!(tally_led_color(Tally::Program, false, true), LedColor::Red);
+        assert_eq!(tally_led_color(Tally::Program, false, false), LedColor::Red);
+        assert_eq!(
+            tally_led_color(Tally::Preview, false, true),
+            LedColor::Green
+        );
+        assert_eq!(tally_led_color(Tally::Off, false, true), LedColor::Off);
+    }
+
+    #[test]
+    fn test_tally_led_color_muted_blinks() {
+        assert_eq!(tally_led_color(Tally::Program, true, true), LedColor::Red);
+        assert_eq!(tally_led_color(Tally::Program, true, false), LedColor::Off);
+        assert_eq!(tally_led_color(Tally::Preview, true, true), LedColor::Green);
+        assert_eq!(tally_led_color(Tally::Off, true, true), LedColor::Amber);
+        assert_eq!(tally_led_color(Tally::Off, true, false), LedColor::Off);
+    }
+
+    #[test]
+    fn test_led_color_flashes_for_a_call() {
+        assert_eq!(
+            led_color(Tally::Program, false, Some(true), true),
+            LedColor::Amber
+        );
+        assert_eq!(
+            led_color(Tally::Program, false, Some(false), true),
+            LedColor::Off
+        );
+        assert_eq!(led_color(Tally::Program, false, None, true), LedColor::Red);
+    }
+
+    #[test]
+    fn test_call_blink_timing() {
+        assert_eq!(call_blink_at(Duration::ZERO), Some(true));
+        assert_eq!(call_blink_at(Duration::from_millis(249)), Some(true));
+        assert_eq!(call_blink_at(Duration::from_millis(250)), Some(false));
+        assert_eq!(call_blink_at(Duration::from_millis(500)), Some(true));
+        assert_eq!(call_blink_at(Duration::from_millis(4999)), Some(false));
+        assert_eq!(call_blink_at(CALL_ALERT), None);
+    }
+
+    #[test]
+    fn test_stats_call_alert() {
+        let stats = IntercomStats::new();
+        let start = Instant::now();
+        assert_eq!(stats.call_blink(start), None);
+
+        stats.record_call(start);
+        assert_eq!(stats.call_blink(start), Some(true));
+        assert_eq!(
+            stats.call_blink(start + Duration::from_millis(300)),
+            Some(false)
+        );
+        assert_eq!(stats.call_blink(start + CALL_ALERT), None);
+        assert_eq!(stats.snapshot().calls_received, 1);
+
+        // A second call restarts the alert
+        let again = start + Duration::from_secs(10);
+        stats.record_call(again);
+        assert_eq!(stats.call_blink(again + Duration::from_secs(1)), Some(true));
+        assert_eq!(stats.snapshot().calls_received, 2);
+    }
+
+    #[test]
+    fn test_is_call() {
+        assert!(is_call("call=1"));
+        assert!(is_call("call=1\n"));
+        assert!(!is_call("call=0"));
+        assert!(!is_call("cam1: mic silent"));
+    }
+
+    #[test]
+    fn test_call_tone_is_short() {
+        let mut tone = ToneGenerator::new(48000);
+        tone.play_call();
+        let mut samples = 0;
+        while tone.is_active() {
+            tone.next_sample();
+            samples += 1;
+        }
+        // 3 x 100 ms beeps with 2 x 60 ms gaps
+        assert_eq!(samples, 48 * 420);
+    }
+
+    #[test]
+    fn test_stats_tally() {
+        let stats = IntercomStats::new();
+        assert_eq!(stats.tally(), Tally::Off);
+        stats.set_tally(Tally::Program);
+        assert_eq!(stats.snapshot().tally, Tally::Program);
+        stats.set_tally(Tally::Preview);
+        assert_eq!(stats.tally(), Tally::Preview);
+    }
+
+    #[test]
+    fn test_auto_unmute_from_name() {
+        assert_eq!(AutoUnmute::from_name("never").unwrap(), AutoUnmute::Never);
+        assert_eq!(
+            AutoUnmute::from_name("Preview").unwrap(),
+            AutoUnmute::Preview
+        );
+        assert_eq!(
+            AutoUnmute::from_name("program").unwrap(),
+            AutoUnmute::Program
+        );
+        assert!(AutoUnmute::from_name("always").is_err());
+
+        for tally in [Tally::Off, Tally::Preview, Tally::Program] {
+            assert!(!AutoUnmute::Never.opens_on(tally));
+            assert_eq!(AutoUnmute::Preview.opens_on(tally), tally != Tally::Off);
+            assert_eq!(AutoUnmute::Program.opens_on(tally), tally == Tally::Program);
+        }
+    }
+
+    #[test]
+    fn test_mic_channel_from_name() {
+        assert_eq!(MicChannel::from_name("left").unwrap(), MicChannel::Left);
+        assert_eq!(MicChannel::from_name("Right").unwrap(), MicChannel::Right);
+        assert_eq!(MicChannel::from_name("mix").unwrap(), MicChannel::Mix);
+        assert!(MicChannel::from_name("both").is_err());
+        for mic in [MicChannel::Left, MicChannel::Right, MicChannel::Mix] {
+            assert_eq!(MicChannel::from_name(mic.name()).unwrap(), mic);
+        }
+    }
+
+    #[test]
+    fn test_mic_channel_select() {
+        assert_eq!(MicChannel::Left.select(100, -300), 100);
+        assert_eq!(MicChannel::Right.select(100, -300), -300);
+        assert_eq!(MicChannel::Mix.select(100, -300), -100);
+        // Averaging must not overflow at full scale
+        assert_eq!(MicChannel::Mix.select(i16::MAX, i16::MAX), i16::MAX);
+        assert_eq!(MicChannel::Mix.select(i16::MIN, i16::MIN), i16::MIN);
+        assert_eq!(MicChannel::Mix.select(i16::MAX, i16::MIN), 0);
+    }
+
+    #[test]
+    fn test_negotiate_capture_channels() {
+        assert_eq!(negotiate_capture_channels(|_| true), Some(1));
+        assert_eq!(negotiate_capture_channels(|c| c == 2), Some(2));
+        assert_eq!(negotiate_capture_channels(|c| c >= 2), Some(2));
+        assert_eq!(negotiate_capture_channels(|c| c == 4), None);
+    }
+
+    #[test]
+    fn test_capture_layout_mono_passes_through() {
+        let layout = CaptureLayout::new(1, MicChannel::Left);
+        assert_eq!(layout.buffer_len(256), 256);
+        assert_eq!(layout.describe(), "mono");
+
+        let mut mono = [0i16; 4];
+        layout.extract_mic(&[1, 2, 3, 4], 3, &mut mono);
+        assert_eq!(mono, [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_capture_layout_stereo_selects_the_mic() {
+        let interleaved = [10, -10, 20, 40, 30, 0, 99, 99];
+        let mut mono = [0i16; 4];
+
+        let layout = CaptureLayout::new(2, MicChannel::Left);
+        assert_eq!(layout.buffer_len(256), 512);
+        assert_eq!(layout.describe(), "2 channels, mic=left");
+        layout.extract_mic(&interleaved, 3, &mut mono);
+        assert_eq!(mono, [10, 20, 30, 0]);
+
+        CaptureLayout::new(2, MicChannel::Right).extract_mic(&interleaved, 3, &mut mono);
+        assert_eq!(mono, [-10, 40, 0, 0]);
+
+        CaptureLayout::new(2, MicChannel::Mix).extract_mic(&interleaved, 3, &mut mono);
+        assert_eq!(mono, [0, 30, 15, 0]);
+
+        // Only the frames read this period are converted
+        let mut mono = [7i16; 4];
+        CaptureLayout::new(2, MicChannel::Mix).extract_mic(&interleaved, 1, &mut mono);
+        assert_eq!(mono, [0, 7, 7, 7]);
+    }
+
+    #[test]
+    fn test_tally_follower_transitions() {
+        use MuteMode::*;
+        let tallies = [Tally::Off, Tally::Preview, Tally::Program];
+        for policy in [AutoUnmute::Preview, AutoUnmute::Program] {
+            for start in [AutoFollow, ManualMuted, ManualOpen] {
+                for from in tallies {
+                    for to in tallies {
+                        let mut follower = TallyFollower::new(policy);
+                        follower.tally(from);
+                        match start {
+                            ManualMuted => follower.manual(true),
+                            ManualOpen => follower.manual(false),
+                            AutoFollow => {}
+                        }
+                        assert_eq!(follower.mode(), start);
+
+                        let applied = follower.tally(to);
+                        let crossed = policy.opens_on(from) != policy.opens_on(to);
+                        let case = (policy, start, from, to);
+                        if crossed {
+                            // Entering opens, leaving mutes, and auto takes over
+                            assert_eq!(applied, Some(!policy.opens_on(to)), "{:?}", case);
+                            assert_eq!(follower.mode(), AutoFollow, "{:?}", case);
+                        } else {
+                            // Not a transition: a manual choice stays
+                            assert_eq!(applied, None, "{:?}", case);
+                            assert_eq!(follower.mode(), start, "{:?}", case);
+                        }
                     }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tally_follower_manual_override() {
+        let mut follower = TallyFollower::new(AutoUnmute::Program);
+        assert_eq!(follower.mode(), MuteMode::AutoFollow);
+        // Already off air and muted
+        assert_eq!(follower.tally(Tally::Off), None);
+        assert_eq!(follower.tally(Tally::Program), Some(false));
+
+        // On air, the operator mutes to cough; the tally doesn't reopen
+        follower.manual(true);
+        assert_eq!(follower.mode(), MuteMode::ManualMuted);
+        assert_eq!(follower.tally(Tally::Program), None);
+        // Off air mutes (already muted) and hands back to the tally
+        assert_eq!(follower.tally(Tally::Off), Some(true));
+        assert_eq!(follower.mode(), MuteMode::AutoFollow);
+
+        // Opened by hand off air, closed at the end of the next take
+        follower.manual(false);
+        assert_eq!(follower.mode(), MuteMode::ManualOpen);
+        assert_eq!(follower.tally(Tally::Preview), None);
+        assert_eq!(follower.tally(Tally::Program), Some(false));
+        assert_eq!(follower.tally(Tally::Preview), Some(true));
+    }
+
+    #[test]
+    fn test_tally_follower_never_ignores_tally() {
+        let mut follower = TallyFollower::new(AutoUnmute::Never);
+        assert_eq!(follower.mode(), MuteMode::ManualMuted);
+        for tally in [Tally::Program, Tally::Off, Tally::Preview] {
+            assert_eq!(follower.tally(tally), None);
+        }
+        follower.manual(false);
+        assert_eq!(follower.tally(Tally::Program), None);
+        assert_eq!(follower.mode(), MuteMode::ManualOpen);
+    }
+
+    #[test]
+    fn test_tally_follower_thread_drives_mute_flag() {
+        let muted = Arc::new(AtomicBool::new(true));
+        let stats = Arc::new(IntercomStats::new());
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let (muted, stats, running) =
+                (Arc::clone(&muted), Arc::clone(&stats), Arc::clone(&running));
+            std::thread::spawn(move || {
+                run_tally_follower(AutoUnmute::Preview, muted, stats, running)
+            })
+        };
+        let wait_until = |done: &dyn Fn() -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !done() && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            done()
+        };
+
+        stats.set_tally(Tally::Preview);
+        assert!(wait_until(&|| !muted.load(Ordering::Relaxed)));
+        assert!(wait_until(&|| stats.mute_mode() == MuteMode::AutoFollow));
+
+        // A button press mutes and sticks while the tally stays on
+        muted.store(true, Ordering::Relaxed);
+        assert!(wait_until(&|| stats.mute_mode() == MuteMode::ManualMuted));
+        stats.set_tally(Tally::Program);
+        std::thread::sleep(AUTO_UNMUTE_POLL * 3);
+        assert!(muted.load(Ordering::Relaxed));
+
+        stats.set_tally(Tally::Off);
+        assert!(wait_until(&|| stats.mute_mode() == MuteMode::AutoFollow));
+        assert!(muted.load(Ordering::Relaxed));
 
-                    // Send VBAN packets
-                    const CHUNK_SIZE: usize = 128;
-                    for chunk in vban_samples.chunks(CHUNK_SIZE) {
-                        let stereo_data: Vec<i16> = chunk.iter().flat_map(|&s| [s, s]).collect();
-                        let samples_per_frame = chunk.len();
-                        let mut packet = vec![0u8; VBAN_HEADER_SIZE + stereo_data.len() * 2];
-
-                        packet[0..4].copy_from_slice(b"VBAN");
-                        packet[4] = 3; // 48kHz
-                        packet[5] = (samples_per_frame.saturating_sub(1) & 0xFF) as u8;
-                        packet[6] = 1; // 2 channels - 1
-                        packet[7] = 0x01; // PCM16
-                        packet[8..24].copy_from_slice(&stream_name_bytes);
-                        packet[24..28].copy_from_slice(&frame_counter.to_le_bytes());
-
-                        for (i, &sample) in stereo_data.iter().enumerate() {
-                            let bytes = sample.to_le_bytes();
-                            packet[VBAN_HEADER_SIZE + i * 2] = bytes[0];
-                            packet[VBAN_HEADER_SIZE + i * 2 + 1] = bytes[1];
-                        }
+        running.store(false, Ordering::Relaxed);
+        thread.join().unwrap();
+    }
 
-                        let _ = vban_socket.send(&packet);
-                        frame_counter = frame_counter.wrapping_add(1);
-                        frames_sent.fetch_add(1, Ordering::Relaxed);
-                    }
-                }
+    #[test]
+    fn test_stats_new_defaults() {
+        let snapshot = IntercomStats::new().snapshot();
+        assert_eq!(snapshot.packets_sent, 0);
+        assert_eq!(snapshot.packets_received, 0);
+        assert_eq!(snapshot.xruns, 0);
+        assert!(snapshot.muted);
+        assert!(!snapshot.link_up);
+    }
+
+    #[test]
+    fn test_stats_snapshot_reflects_updates() {
+        let stats = IntercomStats::new();
+        stats.packets_sent.fetch_add(3, Ordering::Relaxed);
+        stats.packets_received.fetch_add(5, Ordering::Relaxed);
+        stats.packets_rejected.fetch_add(1, Ordering::Relaxed);
+        stats.samples_captured.fetch_add(256, Ordering::Relaxed);
+        stats.xruns.fetch_add(2, Ordering::Relaxed);
+        stats.buffer_depth.store(512, Ordering::Relaxed);
+        stats.link_up.store(true, Ordering::Relaxed);
+        stats.muted.store(false, Ordering::Relaxed);
+        stats.set_gains(12.0, 15.0, 100.0);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.packets_sent, 3);
+        assert_eq!(snapshot.packets_received, 5);
+        assert_eq!(snapshot.packets_rejected, 1);
+        assert_eq!(snapshot.samples_captured, 256);
+        assert_eq!(snapshot.xruns, 2);
+        assert_eq!(snapshot.buffer_depth, 512);
+        assert!(snapshot.link_up);
+        assert!(!snapshot.muted);
+        assert_eq!(snapshot.mic_gain, 12.0);
+        assert_eq!(snapshot.headphone_gain, 15.0);
+        assert_eq!(snapshot.sidetone_gain, 100.0);
+        assert!(!snapshot.mic_silent);
+        assert_eq!(snapshot.mic_level_db, None);
+
+        stats.mic_silent.store(true, Ordering::Relaxed);
+        stats.set_mic_level_db(Some(-72.5));
+        let snapshot = stats.snapshot();
+        assert!(snapshot.mic_silent);
+        assert_eq!(snapshot.mic_level_db, Some(-72.5));
+    }
+
+    #[test]
+    fn test_stats_shared_across_threads() {
+        let stats = Arc::new(IntercomStats::new());
+        let writer = Arc::clone(&stats);
+        std::thread::spawn(move || {
+            for _ in 0..1000 {
+                writer.packets_received.fetch_add(1, Ordering::Relaxed);
             }
-            Ok(_) => {
-                // Zero frames - capture might be stalled
-                capture_stall_count += 1;
+        })
+        .join()
+        .unwrap();
+        assert_eq!(stats.snapshot().packets_received, 1000);
+    }
+
+    /// Run `minutes` of playback against a far end `ppm` faster than the
+    /// headset, sending 128-frame stereo packets. Returns the compensator,
+    /// the min and max buffer level in frames and the number of short
+    /// periods, all counted after the first minute.
+    fn simulate_drift(ppm: f64, minutes: u64) -> (DriftCompensator, usize, usize, u64) {
+        let period = PERIOD_SIZE as f64 / SAMPLE_RATE as f64;
+        let packet = 128.0 / (SAMPLE_RATE as f64 * (1.0 + ppm / 1e6));
+        let t0 = Instant::now();
+        let mut buf = AudioBuffer::new(SAMPLE_RATE as usize);
+        let mut drift = DriftCompensator::new(2, PERIOD_SIZE as usize);
+        let (mut min, mut max, mut underruns) = (usize::MAX, 0, 0);
+        // Half a packet out of phase with playback so rounding never moves a
+        // packet across a period boundary
+        let mut next_packet = packet / 2.0;
+        let periods = (minutes as f64 * 60.0 / period) as u64;
+        for i in 0..periods {
+            let t = i as f64 * period;
+            while next_packet <= t {
+                buf.push_samples(&[1000; 256]);
+                next_packet += packet;
             }
-            Err(e) => {
-                capture_stall_count += 1;
-                if !recover_alsa(&capture, e.errno()) {
-                    return Err(anyhow!("ALSA capture error: {}", e));
+            let out = drift.pop(
+                &mut buf,
+                2 * PERIOD_SIZE as usize,
+                t0 + Duration::from_secs_f64(t),
+            );
+            if t >= 60.0 {
+                let level = buf.len() / 2;
+                min = min.min(level);
+                max = max.max(level);
+                if out.len() < 2 * PERIOD_SIZE as usize {
+                    underruns += 1;
                 }
             }
         }
+        (drift, min, max, underruns)
+    }
 
-        // Quick stall detection: if 500+ consecutive iterations without capture
-        // (about 2.5 seconds at 5ms/iteration), force restart
-        if capture_stall_count > 500 {
-            tracing::warn!(
-                "Capture device unresponsive ({} consecutive failures), forcing restart...",
-                capture_stall_count
+    #[test]
+    fn test_drift_compensation_keeps_buffer_bounded() {
+        // Uncorrected, +200 ppm grows the buffer by ~10 frames/s (~0.1 s over
+        // 10 minutes) and -200 ppm drains it into an underrun every ~50 s
+        for ppm in [200.0, -200.0] {
+            let (drift, min, max, underruns) = simulate_drift(ppm, 10);
+            let measured = drift.drift_ppm().unwrap();
+            assert!(
+                (measured - ppm).abs() < 20.0,
+                "{} ppm measured as {}",
+                ppm,
+                measured
             );
-            return Err(anyhow!("Capture device unresponsive"));
+            assert!(
+                max < 3 * DRIFT_TARGET_FRAMES as usize,
+                "{} ppm: max {}",
+                ppm,
+                max
+            );
+            assert!(min > 0, "{} ppm: buffer drained", ppm);
+            assert_eq!(underruns, 0, "{} ppm", ppm);
         }
+    }
 
-        // === PLAYBACK ===
-        // Mix VBAN + sidetone
-        let vban_samples = if let Ok(mut buf) = playback_buffer.lock() {
-            buf.pop_samples(playback_buf.len())
-        } else {
-            vec![]
-        };
+    #[test]
+    fn test_drift_compensation_without_drift() {
+        let (drift, min, max, underruns) = simulate_drift(0.0, 5);
+        assert!(drift.drift_ppm().unwrap().abs() < 20.0);
+        assert!(min > 0 && max < 3 * DRIFT_TARGET_FRAMES as usize);
+        assert_eq!(underruns, 0);
+    }
 
-        for (i, sample) in playback_buf.iter_mut().enumerate() {
-            let vban = (vban_samples.get(i).copied().unwrap_or(0) as f32 * headphone_gain) as i32;
-            let sidetone = if is_muted {
-                0
-            } else {
-                // Get mono sample and duplicate for stereo
-                let mono = if i % 2 == 0 {
-                    sidetone_buf.pop_front().unwrap_or(0)
-                } else {
-                    sidetone_buf.front().copied().unwrap_or(0)
-                };
-                (mono as f32 * sidetone_gain) as i32
-            };
-            *sample = (vban + sidetone).clamp(-32768, 32767) as i16;
+    #[test]
+    fn test_drift_measurement_needs_min_span() {
+        let t0 = Instant::now();
+        let mut drift = DriftCompensator::new(2, PERIOD_SIZE as usize);
+        let mut buf = AudioBuffer::new(4096);
+        for second in 0..10 {
+            buf.push_samples(&[0; 512]);
+            drift.pop(&mut buf, 512, t0 + Duration::from_secs(second));
         }
+        // Ten points but only nine seconds apart
+        assert_eq!(drift.drift_ppm(), None);
+        buf.push_samples(&[0; 512]);
+        drift.pop(&mut buf, 512, t0 + Duration::from_secs(10));
+        assert_eq!(drift.drift_ppm(), Some(0.0));
+
+        drift.reset();
+        assert_eq!(drift.drift_ppm(), None);
+    }
 
-        // Write to ALSA
-        let io_play = playback.io_i16()?;
-        match io_play.writei(&playback_buf) {
-            Ok(_) => {}
-            Err(e) => {
-                if !recover_alsa(&playback, e.errno()) {
-                    return Err(anyhow!("ALSA playback error: {}", e));
-                }
-            }
+    #[test]
+    fn test_drift_correction_drops_and_repeats_whole_frames() {
+        let t0 = Instant::now();
+        let mut buf = AudioBuffer::new(4096);
+        let mut drift = DriftCompensator::new(2, PERIOD_SIZE as usize);
+        // A frame is due to be dropped
+        drift.owed = 1.5;
+        drift.level = DRIFT_TARGET_FRAMES;
+        buf.push_samples(&[1, 1, 2, 2, 3, 3, 4, 4]);
+        assert_eq!(drift.pop(&mut buf, 6, t0), [1, 1, 2, 2, 3, 3]);
+        assert_eq!(buf.len(), 0);
+
+        // Repeat: one frame fewer is taken and the last is doubled
+        drift.owed = -1.5;
+        buf.push_samples(&[5, 5, 6, 6, 7, 7]);
+        assert_eq!(drift.pop(&mut buf, 6, t0), [5, 5, 6, 6, 6, 6]);
+        assert_eq!(buf.len(), 2);
+    }
+
+    #[test]
+    fn test_stats_registered_for_the_report() {
+        use crate::stats::{StatsTicker, Value};
+
+        let stats = Arc::new(IntercomStats::new());
+        let registry = Arc::new(StatsRegistry::new());
+        stats.register(&registry);
+        let start = Instant::now();
+        let mut ticker = StatsTicker::new(Arc::clone(&registry), start);
+        stats.packets_received.fetch_add(3750, Ordering::Relaxed);
+        stats.packets_sent.fetch_add(1875, Ordering::Relaxed);
+        stats.samples_captured.fetch_add(480_000, Ordering::Relaxed);
+        let report = ticker.tick(start + Duration::from_secs(10));
+        assert_eq!(report.rate("intercom.received"), Some(375.0));
+        assert_eq!(report.rate("intercom.sent"), Some(187.5));
+        assert_eq!(report.rate("intercom.samples"), Some(48000.0));
+        assert_eq!(
+            report.reading("intercom.link").unwrap().value,
+            Value::Gauge(0.0)
+        );
+        assert_eq!(
+            report.reading("intercom.muted").unwrap().value,
+            Value::Gauge(1.0)
+        );
+        assert!(report.line().contains("intercom.drift_ppm=n/a"));
+        assert!(report.line().contains("intercom.mic_db=n/a"));
+        assert_eq!(
+            report.reading("intercom.mic_silent").unwrap().value,
+            Value::Gauge(0.0)
+        );
+
+        stats.set_drift_ppm(Some(-187.5));
+        let report = ticker.tick(start + Duration::from_secs(20));
+        assert!(report.line().contains("intercom.drift_ppm=-187.5"));
+    }
+
+    fn loopback_config() -> IntercomConfig {
+        IntercomConfig {
+            target_host: "127.0.0.1".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            ..Default::default()
         }
+    }
 
-        // Stats and watchdog
-        if last_report.elapsed() >= report_interval {
-            let received = frames_received.load(Ordering::Relaxed);
-            let sent = frames_sent.load(Ordering::Relaxed);
-            let recv_rate = (received - last_received) as f64 / report_interval.as_secs_f64();
-            let send_rate = (sent - last_sent) as f64 / report_interval.as_secs_f64();
-            let captured = samples_captured.load(Ordering::Relaxed);
-            let capture_rate =
-                (captured - last_capture_samples) as f64 / report_interval.as_secs_f64();
-
-            tracing::info!(
-                "Intercom: recv {:.1} pkt/s, send {:.1} pkt/s, capture {:.0} samp/s",
-                recv_rate,
-                send_rate,
-                capture_rate
-            );
+    #[test]
+    fn test_receiver_socket_ephemeral_port() {
+        let socket = bind_receiver_socket(&loopback_config()).unwrap();
+        assert_ne!(socket.local_addr().unwrap().port(), 0);
+    }
 
-            // Watchdog: if no samples captured in this period, something is wrong
-            if captured == last_capture_samples && capture_rate < 1000.0 {
-                tracing::warn!(
-                    "Capture stalled! No samples in {}s (stall_count={}), forcing restart...",
-                    report_interval.as_secs(),
-                    capture_stall_count
-                );
-                return Err(anyhow!("Capture device stalled - forcing restart"));
+    #[test]
+    fn test_receiver_bind_error_includes_address() {
+        let first = bind_receiver_socket(&loopback_config()).unwrap();
+        let taken = first.local_addr().unwrap().to_string();
+        let config = IntercomConfig {
+            listen: taken.clone(),
+            ..loopback_config()
+        };
+        let err = bind_receiver_socket(&config).unwrap_err();
+        assert!(format!("{:#}", err).contains(&taken));
+        match err {
+            IntercomError::Bind { addr, source } => {
+                assert_eq!(addr, taken);
+                assert_eq!(source.kind(), std::io::ErrorKind::AddrInUse);
             }
-
-            last_capture_samples = captured;
-            last_received = received;
-            last_sent = sent;
-            last_report = std::time::Instant::now();
+            other => panic!("unexpected error: {:?}", other),
         }
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_sender_and_receiver_over_ipv6_loopback() {
+        let config = IntercomConfig {
+            target_host: "[::1]".to_string(),
+            listen: "[::1]:0".to_string(),
+            ..Default::default()
+        };
+        // Skip on hosts without IPv6 loopback
+        let Ok(receiver) = bind_receiver_socket(&config) else {
+            return;
+        };
+        let config = IntercomConfig {
+            port: receiver.local_addr().unwrap().port(),
+            ..config
+        };
+        let sender = connect_sender_socket(&config).unwrap();
+        assert!(sender.local_addr().unwrap().is_ipv6());
+        sender.send(b"VBAN").unwrap();
+        let mut buf = [0u8; 16];
+        let (len, from) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"VBAN");
+        assert_eq!(from, sender.local_addr().unwrap());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_dual_stack_receiver_hears_ipv4_sender() {
+        let config = IntercomConfig {
+            listen: "[::]:0".to_string(),
+            ..loopback_config()
+        };
+        let receiver = bind_receiver_socket(&config).unwrap();
+        let config = IntercomConfig {
+            port: receiver.local_addr().unwrap().port(),
+            ..config
+        };
+        let sender = connect_sender_socket(&config).unwrap();
+        sender.send(b"VBAN").unwrap();
+        let mut buf = [0u8; 16];
+        let (len, from) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"VBAN");
+        let mut filter = SourceFilter::new(&["127.0.0.1".to_string()], false);
+        assert!(filter.accept(from.ip(), Instant::now()));
+    }
 
     #[test]
-    fn test_intercom_config_default() {
-        let config = IntercomConfig::default();
-        assert_eq!(config.stream_name, "cam1");
-        assert_eq!(config.target_host, "strih.lan");
-        assert_eq!(config.sample_rate, 48000);
-        assert_eq!(config.channels, 2);
-        assert!((config.sidetone_gain - 100.0).abs() < 0.001);
-        assert!((config.mic_gain - 12.0).abs() < 0.001);
-        assert!((config.headphone_gain - 15.0).abs() < 0.001);
-        assert!(config.limiter_enabled);
-        assert!((config.limiter_threshold - 0.5).abs() < 0.001);
+    fn test_sender_honors_bind_address_family() {
+        let config = IntercomConfig {
+            bind_address: Some(IpAddr::from([127, 0, 0, 1])),
+            ..loopback_config()
+        };
+        let sender = connect_sender_socket(&config).unwrap();
+        assert_eq!(
+            sender.local_addr().unwrap().ip(),
+            IpAddr::from([127, 0, 0, 1])
+        );
+
+        let config = IntercomConfig {
+            target_host: "::1".to_string(),
+            ..config
+        };
+        match connect_sender_socket(&config) {
+            Err(IntercomError::Connect { target, .. }) => assert_eq!(target, "[::1]:6980"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
     }
 
     #[test]
-    fn test_intercom_config_clone() {
+    fn test_sender_reaches_receiver_on_configured_port() {
+        let receiver = bind_receiver_socket(&loopback_config()).unwrap();
         let config = IntercomConfig {
-            stream_name: "test".to_string(),
-            target_host: "host.lan".to_string(),
-            sample_rate: 44100,
-            channels: 1,
-            sidetone_gain: 15.0,
-            mic_gain: 2.0,
-            headphone_gain: 8.0,
-            limiter_enabled: false,
-            limiter_threshold: 0.8,
+            port: receiver.local_addr().unwrap().port(),
+            ..loopback_config()
         };
-        let cloned = config.clone();
-        assert_eq!(config.stream_name, cloned.stream_name);
-        assert_eq!(config.target_host, cloned.target_host);
-        assert_eq!(config.sample_rate, cloned.sample_rate);
-        assert_eq!(config.channels, cloned.channels);
-        assert!((config.mic_gain - cloned.mic_gain).abs() < 0.001);
-        assert!((config.headphone_gain - cloned.headphone_gain).abs() < 0.001);
-        assert_eq!(config.limiter_enabled, cloned.limiter_enabled);
-        assert!((config.limiter_threshold - cloned.limiter_threshold).abs() < 0.001);
+        let sender = connect_sender_socket(&config).unwrap();
+        sender.send(b"VBAN").unwrap();
+        let mut buf = [0u8; 16];
+        let (len, _) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"VBAN");
+    }
+
+    #[test]
+    fn test_send_packet_on_non_blocking_socket() {
+        let receiver = bind_receiver_socket(&loopback_config()).unwrap();
+        let config = IntercomConfig {
+            port: receiver.local_addr().unwrap().port(),
+            ..loopback_config()
+        };
+        let sender = connect_sender_socket(&config).unwrap();
+        // Non-blocking: a read with nothing queued returns at once
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            sender.recv(&mut buf).unwrap_err().kind(),
+            std::io::ErrorKind::WouldBlock
+        );
+
+        let stats = IntercomStats::new();
+        assert!(send_packet(&sender, b"VBAN", &stats));
+        let (len, _) = receiver.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"VBAN");
+        assert_eq!(stats.snapshot().send_dropped, 0);
+    }
+
+    #[test]
+    fn test_identification_reports_host_and_version() {
+        let config = IntercomConfig {
+            hostname: "CAM2".to_string(),
+            stream_name: "cam2".to_string(),
+            ..Default::default()
+        };
+        let ping = identification(&config);
+        assert_eq!(ping.host_name, "CAM2");
+        assert_eq!(ping.user_name, "cam2");
+        assert_eq!(ping.preferred_rate, 48000);
+        let expected: Vec<u8> = env!("CARGO_PKG_VERSION")
+            .split('.')
+            .map(|p| p.parse().unwrap())
+            .collect();
+        assert_eq!(&ping.version[..expected.len()], &expected[..]);
     }
 
     #[test]
@@ -921,6 +4586,113 @@ mod tests {
         assert_eq!(buf.len(), 48000);
     }
 
+    #[test]
+    fn test_alsa_periods_latency() {
+        let periods = AlsaPeriods::default();
+        assert_eq!(
+            periods,
+            AlsaPeriods {
+                frames: 256,
+                count: 4
+            }
+        );
+        assert_eq!(periods.buffer_frames(), 1024);
+        // 256 frames of capture and 1024 of playback at 48 kHz
+        assert!((periods.latency_ms() - 26.667).abs() < 0.01);
+        let small = AlsaPeriods {
+            frames: 64,
+            count: 2,
+        };
+        assert!((small.latency_ms() - 4.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_alsa_period_ladder() {
+        let frames = |periods: AlsaPeriods| -> Vec<u32> {
+            periods.ladder().iter().map(|p| p.frames).collect()
+        };
+        let request = |frames| AlsaPeriods { frames, count: 3 };
+        assert_eq!(frames(request(64)), [64, 128, 256, 512, 1024]);
+        assert_eq!(frames(request(256)), [256, 512, 1024]);
+        assert_eq!(frames(request(300)), [300, 512, 1024]);
+        assert_eq!(frames(request(2048)), [2048]);
+        assert!(request(64).ladder().iter().all(|p| p.count == 3));
+    }
+
+    /// Fallback against a device that accepts only `accepted` period sizes
+    fn fallback(
+        requested: u32,
+        accepted: &[u32],
+        errno: i32,
+    ) -> (std::result::Result<u32, i32>, Vec<u32>) {
+        let mut tried = Vec::new();
+        let result = open_with_fallback(
+            AlsaPeriods {
+                frames: requested,
+                count: 4,
+            },
+            |periods| {
+                tried.push(periods.frames);
+                if accepted.contains(&periods.frames) {
+                    Ok(periods.frames)
+                } else {
+                    Err(errno)
+                }
+            },
+            |&errno| errno == libc::EINVAL,
+        )
+        .map(|(frames, periods)| {
+            assert_eq!(frames, periods.frames);
+            frames
+        });
+        (result, tried)
+    }
+
+    #[test]
+    fn test_alsa_period_fallback() {
+        // Accepted at once
+        assert_eq!(fallback(64, &[64, 256], libc::EINVAL), (Ok(64), vec![64]));
+        // Climbs until a size sticks
+        assert_eq!(
+            fallback(64, &[256, 512], libc::EINVAL),
+            (Ok(256), vec![64, 128, 256])
+        );
+        assert_eq!(
+            fallback(256, &[1024], libc::EINVAL),
+            (Ok(1024), vec![256, 512, 1024])
+        );
+        // Nothing fits: the last error
+        assert_eq!(
+            fallback(256, &[], libc::EINVAL),
+            (Err(libc::EINVAL), vec![256, 512, 1024])
+        );
+        // A missing device isn't retried with other sizes
+        assert_eq!(
+            fallback(64, &[], libc::ENOENT),
+            (Err(libc::ENOENT), vec![64])
+        );
+    }
+
+    #[test]
+    fn test_period_may_help() {
+        let error = |errno| IntercomError::AlsaDevice {
+            device: "hw:0".to_string(),
+            direction: "capture",
+            errno,
+            source: alsa::Error::new("snd_pcm_hw_params", errno),
+        };
+        assert!(period_may_help(&error(libc::EINVAL)));
+        assert!(period_may_help(&error(libc::EIO)));
+        assert!(!period_may_help(&error(libc::ENOENT)));
+        assert!(!period_may_help(&error(libc::EBUSY)));
+    }
+
+    #[test]
+    fn test_drift_target_follows_the_period() {
+        assert_eq!(DriftCompensator::new(2, 256).target, 512.0);
+        assert_eq!(DriftCompensator::new(2, 1024).target, 2048.0);
+    }
+
     #[test]
     fn test_alsa_constants() {
         assert_eq!(SAMPLE_RATE, 48000);
@@ -941,6 +4713,51 @@ mod tests {
     // PeakLimiter Tests
     // =============================================================================
 
+    #[test]
+    fn test_gain_ramp_fades_in_monotonically() {
+        let mut ramp = GainRamp::new(0.0, 240);
+        assert!(ramp.is_settled());
+        ramp.set_target(1.0);
+        let gains: Vec<f32> =
+            std::iter::from_fn(|| (!ramp.is_settled()).then(|| ramp.next_gain())).collect();
+        assert_eq!(gains[0], 0.0);
+        assert!(gains.windows(2).all(|pair| pair[1] > pair[0]));
+        assert!((240..=241).contains(&gains.len()), "{}", gains.len());
+        // Exactly on target, not a float step short or past it
+        assert_eq!(ramp.next_gain(), 1.0);
+        assert_eq!(ramp.next_gain(), 1.0);
+    }
+
+    #[test]
+    fn test_gain_ramp_fades_out_and_reverses() {
+        let mut ramp = GainRamp::new(1.0, 100);
+        ramp.set_target(0.0);
+        let down: Vec<f32> = (0..50).map(|_| ramp.next_gain()).collect();
+        assert!(down.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(!ramp.is_settled());
+
+        // Unmuted halfway: back up from where it got to, no jump
+        ramp.set_target(1.0);
+        let turn = ramp.next_gain();
+        assert!((turn - 0.5).abs() < 1e-4, "{}", turn);
+        let up: Vec<f32> =
+            std::iter::from_fn(|| (!ramp.is_settled()).then(|| ramp.next_gain())).collect();
+        assert!(up.windows(2).all(|pair| pair[1] > pair[0]));
+        assert!(up.len() <= 51);
+        assert_eq!(ramp.next_gain(), 1.0);
+    }
+
+    #[test]
+    fn test_gain_ramp_process_buffer() {
+        let mut ramp = GainRamp::new(1.0, 4);
+        ramp.set_target(0.0);
+        let mut buffer = [1000i16; 6];
+        ramp.process_buffer(&mut buffer);
+        assert_eq!(buffer, [1000, 750, 500, 250, 0, 0]);
+        assert_eq!(ramp.target(), 0.0);
+        assert!(ramp.is_settled());
+    }
+
     #[test]
     fn test_limiter_new() {
         let limiter = PeakLimiter::new(0.5, 48000);
@@ -1153,4 +4970,235 @@ mod tests {
             max_spike
         );
     }
+
+    // =============================================================================
+    // SourceFilter Tests
+    // =============================================================================
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_source_filter_unrestricted_accepts_all() {
+        let mut filter = SourceFilter::new(&[], false);
+        let now = Instant::now();
+        assert!(!filter.is_restricted());
+        assert!(filter.accept(ip("10.0.0.5"), now));
+        assert!(filter.accept(ip("192.168.1.1"), now));
+    }
+
+    #[test]
+    fn test_source_filter_allowlist_matching() {
+        let sources = vec!["10.0.0.5".to_string(), "10.0.0.6".to_string()];
+        let mut filter = SourceFilter::new(&sources, false);
+        let now = Instant::now();
+        assert!(filter.is_restricted());
+        assert!(filter.accept(ip("10.0.0.5"), now));
+        assert!(filter.accept(ip("10.0.0.6"), now));
+        assert!(!filter.accept(ip("10.0.0.7"), now));
+    }
+
+    #[test]
+    fn test_source_filter_resolves_hostnames() {
+        let sources = vec!["localhost".to_string()];
+        let mut filter = SourceFilter::new(&sources, false);
+        assert!(filter.accept(ip("127.0.0.1"), Instant::now()));
+        assert!(!filter.accept(ip("10.0.0.7"), Instant::now()));
+    }
+
+    #[test]
+    fn test_source_filter_unresolvable_hostname_rejects() {
+        let sources = vec!["no-such-host.invalid".to_string()];
+        let mut filter = SourceFilter::new(&sources, false);
+        assert!(filter.is_restricted());
+        assert!(!filter.accept(ip("10.0.0.5"), Instant::now()));
+    }
+
+    #[test]
+    fn test_source_filter_picks_up_resolver_results() {
+        let sources = vec!["10.0.0.5".to_string(), "no-such-host.invalid".to_string()];
+        let mut filter = SourceFilter::new(&sources, false);
+        let resolved = Arc::clone(filter.resolved.as_ref().unwrap());
+
+        // A miss only asks the resolver thread to look again
+        assert!(!filter.accept(ip("10.0.0.9"), Instant::now()));
+        assert!(resolved.wanted.load(Ordering::Relaxed));
+
+        // Its next set is merged with the literal addresses
+        resolved.publish(HashSet::from([ip("10.0.0.9")]));
+        assert!(filter.accept(ip("10.0.0.9"), Instant::now()));
+        assert!(filter.accept(ip("10.0.0.5"), Instant::now()));
+        assert!(!filter.accept(ip("10.0.0.7"), Instant::now()));
+
+        drop(filter);
+        assert!(resolved.stopped.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_host_addrs_keep_last_good_lookup() {
+        let addr = |s: &str| SocketAddr::new(ip(s), 0);
+        let failed = || Err(std::io::Error::other("temporary failure"));
+        let mut hosts = HostAddrs::default();
+        hosts.update("mixer", Ok(vec![addr("10.0.0.5")]));
+        hosts.update("talkback", Ok(vec![addr("::ffff:10.0.0.6")]));
+
+        // A failed lookup keeps the host's previous addresses
+        hosts.update("mixer", failed());
+        assert_eq!(hosts.all(), HashSet::from([ip("10.0.0.5"), ip("10.0.0.6")]));
+
+        // A successful one replaces them
+        hosts.update("mixer", Ok(vec![addr("10.0.0.8")]));
+        assert_eq!(hosts.all(), HashSet::from([ip("10.0.0.8"), ip("10.0.0.6")]));
+
+        // A host that never resolved adds nothing
+        hosts.update("intercom", failed());
+        assert_eq!(hosts.all().len(), 2);
+    }
+
+    #[test]
+    fn test_auto_lock_latches_first_source() {
+        let mut filter = SourceFilter::new(&[], true);
+        let t0 = Instant::now();
+        assert!(filter.accept(ip("10.0.0.5"), t0));
+        assert_eq!(filter.locked_source(), Some(ip("10.0.0.5")));
+
+        // Other sources rejected while locked source is active
+        assert!(!filter.accept(ip("10.0.0.6"), t0 + Duration::from_secs(1)));
+        assert!(filter.accept(ip("10.0.0.5"), t0 + Duration::from_secs(5)));
+        assert!(!filter.accept(ip("10.0.0.6"), t0 + Duration::from_secs(14)));
+    }
+
+    #[test]
+    fn test_auto_lock_releases_after_silence() {
+        let mut filter = SourceFilter::new(&[], true);
+        let t0 = Instant::now();
+        assert!(filter.accept(ip("10.0.0.5"), t0));
+
+        // 10s of silence from locked source - new source takes over
+        let later = t0 + AUTO_LOCK_RELEASE;
+        assert!(filter.accept(ip("10.0.0.6"), later));
+        assert_eq!(filter.locked_source(), Some(ip("10.0.0.6")));
+        assert!(!filter.accept(ip("10.0.0.5"), later + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_auto_lock_respects_allowlist() {
+        let sources = vec!["10.0.0.5".to_string()];
+        let mut filter = SourceFilter::new(&sources, true);
+        let t0 = Instant::now();
+        // Disallowed source must not take the lock
+        assert!(!filter.accept(ip("10.0.0.9"), t0));
+        assert_eq!(filter.locked_source(), None);
+        assert!(filter.accept(ip("10.0.0.5"), t0));
+        assert_eq!(filter.locked_source(), Some(ip("10.0.0.5")));
+    }
+
+    // =============================================================================
+    // LinkMonitor / ToneGenerator Tests
+    // =============================================================================
+
+    #[test]
+    fn test_link_monitor_starts_up() {
+        let t0 = Instant::now();
+        let mut monitor = LinkMonitor::new(Duration::from_millis(2000), t0);
+        assert!(!monitor.is_down());
+        assert_eq!(monitor.poll(t0 + Duration::from_millis(1999)), None);
+        assert!(!monitor.is_down());
+    }
+
+    #[test]
+    fn test_link_monitor_goes_down_after_timeout() {
+        let t0 = Instant::now();
+        let mut monitor = LinkMonitor::new(Duration::from_millis(2000), t0);
+        assert_eq!(monitor.packet_received(t0), None);
+        assert_eq!(
+            monitor.poll(t0 + Duration::from_millis(2000)),
+            Some(LinkEvent::Down)
+        );
+        assert!(monitor.is_down());
+        // Down event fires only once
+        assert_eq!(monitor.poll(t0 + Duration::from_millis(5000)), None);
+    }
+
+    #[test]
+    fn test_link_monitor_packets_keep_link_up() {
+        let t0 = Instant::now();
+        let mut monitor = LinkMonitor::new(Duration::from_millis(2000), t0);
+        for i in 1..10 {
+            let now = t0 + Duration::from_millis(i * 1500);
+            assert_eq!(monitor.packet_received(now), None);
+            assert_eq!(monitor.poll(now + Duration::from_millis(1000)), None);
+        }
+        assert!(!monitor.is_down());
+    }
+
+    #[test]
+    fn test_link_monitor_recovers_on_packet() {
+        let t0 = Instant::now();
+        let mut monitor = LinkMonitor::new(Duration::from_millis(2000), t0);
+        assert_eq!(
+            monitor.poll(t0 + Duration::from_secs(3)),
+            Some(LinkEvent::Down)
+        );
+        assert_eq!(
+            monitor.packet_received(t0 + Duration::from_secs(4)),
+            Some(LinkEvent::Up)
+        );
+        assert!(!monitor.is_down());
+        assert_eq!(monitor.packet_received(t0 + Duration::from_secs(4)), None);
+    }
+
+    #[test]
+    fn test_tone_generator_idle_is_silent() {
+        let mut tone = ToneGenerator::new(48000);
+        assert!(!tone.is_active());
+        assert_eq!(tone.next_sample(), 0);
+    }
+
+    #[test]
+    fn test_tone_generator_double_beep_length() {
+        let mut tone = ToneGenerator::new(48000);
+        tone.play_link_down();
+        assert!(tone.is_active());
+
+        // 120ms + 80ms + 120ms = 320ms = 15360 samples at 48kHz
+        let samples: Vec<i16> = (0..15360).map(|_| tone.next_sample()).collect();
+        assert!(!tone.is_active());
+
+        // Gap between beeps is silent
+        assert!(samples[5760..9600].iter().all(|&s| s == 0));
+        // Beeps are audible but below full scale
+        let peak = samples.iter().map(|&s| s.unsigned_abs()).max().unwrap();
+        assert!(peak > 1000 && peak <= (TONE_LEVEL * 32767.0) as u16 + 1);
+    }
+
+    #[test]
+    fn test_tone_generator_continuous_until_stopped() {
+        let mut tone = ToneGenerator::new(48000).with_level(0.5);
+        tone.play_continuous(1000.0);
+        let samples: Vec<i16> = (0..96000).map(|_| tone.next_sample()).collect();
+        assert!(tone.is_active());
+        // Still full level at the end, one period is 48 samples
+        let peak = samples[95952..]
+            .iter()
+            .map(|&s| s.unsigned_abs())
+            .max()
+            .unwrap();
+        assert!(peak > 16000 && peak <= 16384);
+
+        tone.stop();
+        assert!(!tone.is_active());
+        assert_eq!(tone.next_sample(), 0);
+    }
+
+    #[test]
+    fn test_tone_generator_single_beep_length() {
+        let mut tone = ToneGenerator::new(48000);
+        tone.play_link_up();
+        for _ in 0..7200 {
+            tone.next_sample();
+        }
+        assert!(!tone.is_active());
+    }
 }
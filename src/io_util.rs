@@ -0,0 +1,209 @@
+//! I/O that survives signals
+//!
+//! The capture and intercom threads run under SCHED_FIFO while SIGUSR1,
+//! SIGUSR2 and SIGHUP arrive at any time, so a write or send may fail with
+//! EINTR without anything being wrong. These helpers retry interrupted
+//! calls, finish short writes, and tell a full socket buffer (EAGAIN on a
+//! non-blocking socket) apart from a real error.
+
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::fs::FileExt;
+
+/// Interrupted calls in a row before giving up and returning the error
+pub const MAX_INTERRUPTS: u32 = 16;
+
+/// Whether `error` is EINTR
+pub fn is_interrupted(error: &io::Error) -> bool {
+    error.kind() == ErrorKind::Interrupted
+}
+
+/// Run `op` again while it fails with EINTR, up to [`MAX_INTERRUPTS`] times
+pub fn retry_eintr<T>(mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut interrupts = 0;
+    loop {
+        match op() {
+            Err(e) if is_interrupted(&e) && interrupts < MAX_INTERRUPTS => interrupts += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Write all of `buf` at the current position, continuing after short
+/// writes and retrying EINTR
+pub fn write_all(writer: &mut impl Write, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match retry_eintr(|| writer.write(buf))? {
+            0 => return Err(ErrorKind::WriteZero.into()),
+            n => buf = &buf[n..],
+        }
+    }
+    Ok(())
+}
+
+/// Write all of `buf` at `offset` (pwrite), continuing after short writes
+/// and retrying EINTR
+pub fn write_all_at(file: &impl FileExt, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match retry_eintr(|| file.write_at(buf, offset))? {
+            0 => return Err(ErrorKind::WriteZero.into()),
+            n => {
+                buf = &buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What became of a datagram sent on a non-blocking socket
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendOutcome {
+    Sent,
+    /// The socket buffer was full; the datagram is lost
+    Dropped,
+}
+
+/// Send one datagram with `send`, retrying EINTR; a full socket buffer is
+/// a [`SendOutcome::Dropped`] rather than an error
+pub fn send_or_drop(send: impl FnMut() -> io::Result<usize>) -> io::Result<SendOutcome> {
+    match retry_eintr(send) {
+        Ok(_) => Ok(SendOutcome::Sent),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(SendOutcome::Dropped),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// Returns the scripted results in turn, then accepts everything. A
+    /// result of Ok(n) takes at most n bytes.
+    #[derive(Default)]
+    struct Scripted {
+        script: RefCell<VecDeque<io::Result<usize>>>,
+        written: RefCell<Vec<(u64, Vec<u8>)>>,
+    }
+
+    impl Scripted {
+        fn new(script: Vec<io::Result<usize>>) -> Self {
+            Self {
+                script: RefCell::new(script.into()),
+                ..Default::default()
+            }
+        }
+
+        fn take(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+            let n = match self.script.borrow_mut().pop_front() {
+                Some(Ok(n)) => n.min(buf.len()),
+                Some(Err(e)) => return Err(e),
+                None => buf.len(),
+            };
+            self.written.borrow_mut().push((offset, buf[..n].to_vec()));
+            Ok(n)
+        }
+
+        fn bytes(&self) -> Vec<u8> {
+            self.written
+                .borrow()
+                .iter()
+                .flat_map(|(_, bytes)| bytes.clone())
+                .collect()
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.take(buf, 0)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl FileExt for Scripted {
+        fn read_at(&self, _buf: &mut [u8], _offset: u64) -> io::Result<usize> {
+            Ok(0)
+        }
+
+        fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+            self.take(buf, offset)
+        }
+    }
+
+    fn eintr() -> io::Result<usize> {
+        Err(io::Error::from_raw_os_error(libc::EINTR))
+    }
+
+    #[test]
+    fn test_write_all_survives_eintr_and_short_writes() {
+        let mut writer = Scripted::new(vec![eintr(), Ok(3), eintr(), eintr(), Ok(2)]);
+        write_all(&mut writer, b"0123456789").unwrap();
+        assert_eq!(writer.bytes(), b"0123456789");
+        assert_eq!(writer.written.borrow().len(), 3);
+
+        let mut stuck = Scripted::new(vec![Ok(4), Ok(0)]);
+        let error = write_all(&mut stuck, b"0123456789").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::WriteZero);
+    }
+
+    #[test]
+    fn test_write_all_at_advances_the_offset() {
+        let file = Scripted::new(vec![Ok(4), eintr(), Ok(4)]);
+        write_all_at(&file, b"0123456789", 100).unwrap();
+        assert_eq!(file.bytes(), b"0123456789");
+        let offsets: Vec<u64> = file.written.borrow().iter().map(|(at, _)| *at).collect();
+        assert_eq!(offsets, [100, 104, 108]);
+
+        // Other errors are not retried
+        let failing = Scripted::new(vec![Err(ErrorKind::BrokenPipe.into())]);
+        let error = write_all_at(&failing, b"x", 0).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+        assert!(failing.written.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_retry_eintr_gives_up() {
+        let mut calls = 0;
+        let error = retry_eintr(|| {
+            calls += 1;
+            eintr()
+        })
+        .unwrap_err();
+        assert!(is_interrupted(&error));
+        assert_eq!(calls, MAX_INTERRUPTS + 1);
+
+        let mut script = vec![eintr(), eintr(), Ok(7)].into_iter();
+        assert_eq!(retry_eintr(|| script.next().unwrap()).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_send_or_drop() {
+        let mut script = vec![eintr(), Ok(28)].into_iter();
+        assert_eq!(
+            send_or_drop(|| script.next().unwrap()).unwrap(),
+            SendOutcome::Sent
+        );
+        let full = || -> io::Result<usize> { Err(io::Error::from_raw_os_error(libc::EAGAIN)) };
+        assert_eq!(send_or_drop(full).unwrap(), SendOutcome::Dropped);
+        let refused =
+            || -> io::Result<usize> { Err(io::Error::from_raw_os_error(libc::ECONNREFUSED)) };
+        assert_eq!(
+            send_or_drop(refused).unwrap_err().kind(),
+            ErrorKind::ConnectionRefused
+        );
+
+        // A real non-blocking socket
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_nonblocking(true).unwrap();
+        let target = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = target.local_addr().unwrap();
+        assert_eq!(
+            send_or_drop(|| socket.send_to(b"VBAN", addr)).unwrap(),
+            SendOutcome::Sent
+        );
+    }
+}
@@ -0,0 +1,619 @@
+//! Baseline JPEG encoding of UYVY frames
+//!
+//! Enough of JPEG for a preview stream: baseline DCT with the example
+//! quantization and Huffman tables of the standard (Annex K), scaled by a
+//! quality setting the way libjpeg does. UYVY is already 4:2:2, so each
+//! 16x8 MCU takes two luma blocks and one block of each chroma plane
+//! straight from the frame without resampling. Limited-range video levels
+//! are stretched to the full range JFIF viewers expect. Decoding is left
+//! to ffmpeg; [`jpeg_dimensions`] only reads a frame's size from its header.
+
+use crate::ndi::uyvy_frame_size;
+
+/// Natural (row-major) index of each coefficient in zigzag order
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Luminance quantization at quality 50, in natural order
+const LUMA_QUANT: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// Chrominance quantization at quality 50, in natural order
+const CHROMA_QUANT: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// Codes per length (1-16 bits) and symbols of a Huffman table
+struct HuffmanSpec {
+    bits: [u8; 16],
+    values: &'static [u8],
+}
+
+const DC_LUMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+    values: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+};
+
+const DC_CHROMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0],
+    values: &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
+};
+
+const AC_LUMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d],
+    values: &[
+        0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61,
+        0x07, 0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52,
+        0xd1, 0xf0, 0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25,
+        0x26, 0x27, 0x28, 0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45,
+        0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64,
+        0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83,
+        0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99,
+        0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6,
+        0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3,
+        0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8,
+        0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+    ],
+};
+
+const AC_CHROMA: HuffmanSpec = HuffmanSpec {
+    bits: [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77],
+    values: &[
+        0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61,
+        0x71, 0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33,
+        0x52, 0xf0, 0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18,
+        0x19, 0x1a, 0x26, 0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44,
+        0x45, 0x46, 0x47, 0x48, 0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63,
+        0x64, 0x65, 0x66, 0x67, 0x68, 0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a,
+        0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97,
+        0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4,
+        0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca,
+        0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7,
+        0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa,
+    ],
+};
+
+/// Code and length in bits per symbol
+struct HuffmanTable {
+    codes: [(u16, u8); 256],
+}
+
+impl HuffmanTable {
+    /// Canonical codes of a table, assigned as in Annex C
+    fn new(spec: &HuffmanSpec) -> Self {
+        let mut codes = [(0, 0); 256];
+        let mut values = spec.values.iter();
+        let mut code = 0u16;
+        for (index, &count) in spec.bits.iter().enumerate() {
+            for _ in 0..count {
+                if let Some(&value) = values.next() {
+                    codes[value as usize] = (code, index as u8 + 1);
+                }
+                code += 1;
+            }
+            code <<= 1;
+        }
+        Self { codes }
+    }
+}
+
+/// Quantization table for `quality` (1-100, 50 is the table as given)
+fn scaled_quant(base: &[u8; 64], quality: u8) -> [u8; 64] {
+    let quality = quality.clamp(1, 100) as u32;
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - 2 * quality
+    };
+    let mut table = [0; 64];
+    for (out, &value) in table.iter_mut().zip(base) {
+        *out = ((value as u32 * scale + 50) / 100).clamp(1, 255) as u8;
+    }
+    table
+}
+
+/// Entropy-coded data with 0xFF bytes stuffed
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new(out: Vec<u8>) -> Self {
+        Self {
+            out,
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    /// Append the low `len` (at most 16) bits of `value`
+    fn put(&mut self, value: u32, len: u32) {
+        if len == 0 {
+            return;
+        }
+        self.acc = (self.acc << len) | (value & ((1 << len) - 1));
+        self.bits += len;
+        while self.bits >= 8 {
+            let byte = (self.acc >> (self.bits - 8)) as u8;
+            self.out.push(byte);
+            if byte == 0xFF {
+                self.out.push(0);
+            }
+            self.bits -= 8;
+        }
+        self.acc &= (1 << self.bits) - 1;
+    }
+
+    /// Pad the last byte with ones
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.put(0xFF, 8 - self.bits);
+        }
+        self.out
+    }
+}
+
+/// Bit count of a coefficient and the bits that encode it
+fn magnitude(value: i32) -> (u32, u32) {
+    let size = 32 - value.unsigned_abs().leading_zeros();
+    let bits = if value < 0 {
+        (value - 1) as u32
+    } else {
+        value as u32
+    };
+    (size, bits & ((1 << size) - 1))
+}
+
+/// Encoder with the tables for one quality, reused across frames
+pub struct JpegEncoder {
+    luma_quant: [u8; 64],
+    chroma_quant: [u8; 64],
+    /// `cos[u][x]`: DCT basis including the normalization
+    cos: [[f32; 8]; 8],
+    /// Limited-range video levels to full range, shifted to center on 0
+    luma_levels: [f32; 256],
+    chroma_levels: [f32; 256],
+    dc_luma: HuffmanTable,
+    ac_luma: HuffmanTable,
+    dc_chroma: HuffmanTable,
+    ac_chroma: HuffmanTable,
+}
+
+impl JpegEncoder {
+    /// Encoder for `quality` 1-100
+    pub fn new(quality: u8) -> Self {
+        let mut cos = [[0.0; 8]; 8];
+        for (u, row) in cos.iter_mut().enumerate() {
+            let scale = if u == 0 { 0.5 / 2f32.sqrt() } else { 0.5 };
+            for (x, value) in row.iter_mut().enumerate() {
+                let angle = (2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / 16.0;
+                *value = scale * angle.cos();
+            }
+        }
+        let mut luma_levels = [0.0; 256];
+        let mut chroma_levels = [0.0; 256];
+        for (level, (luma, chroma)) in luma_levels.iter_mut().zip(&mut chroma_levels).enumerate() {
+            *luma = ((level as f32 - 16.0) * 255.0 / 219.0).clamp(0.0, 255.0) - 128.0;
+            *chroma = ((level as f32 - 128.0) * 255.0 / 224.0).clamp(-128.0, 127.0);
+        }
+        Self {
+            luma_quant: scaled_quant(&LUMA_QUANT, quality),
+            chroma_quant: scaled_quant(&CHROMA_QUANT, quality),
+            cos,
+            luma_levels,
+            chroma_levels,
+            dc_luma: HuffmanTable::new(&DC_LUMA),
+            ac_luma: HuffmanTable::new(&AC_LUMA),
+            dc_chroma: HuffmanTable::new(&DC_CHROMA),
+            ac_chroma: HuffmanTable::new(&AC_CHROMA),
+        }
+    }
+
+    /// Encode a tightly packed UYVY frame as a baseline JFIF image. Sizes
+    /// beyond the 16 bits of the header are not supported.
+    pub fn encode(&self, uyvy: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let width = width.min(u16::MAX as u32) as usize;
+        let height = height.min(u16::MAX as u32) as usize;
+        let stride = uyvy_frame_size(width, 1);
+        let mut out = Vec::with_capacity(stride * height / 8 + 1024);
+        self.write_headers(&mut out, width as u16, height as u16);
+        if width == 0 || height == 0 || uyvy.len() < stride * height {
+            out.extend_from_slice(&[0xFF, 0xD9]);
+            return out;
+        }
+
+        let mut bits = BitWriter::new(out);
+        let mut predictors = [0i32; 3];
+        let mut blocks = [[0.0f32; 64]; 4];
+        for mcu_y in (0..height).step_by(8) {
+            for mcu_x in (0..width).step_by(16) {
+                for y in 0..8 {
+                    let row = &uyvy[(mcu_y + y).min(height - 1) * stride..][..stride];
+                    for x in 0..16 {
+                        let px = (mcu_x + x).min(width - 1);
+                        let luma = row[px / 2 * 4 + 1 + (px & 1) * 2];
+                        blocks[x / 8][y * 8 + x % 8] = self.luma_levels[luma as usize];
+                    }
+                    for x in 0..8 {
+                        let pair = (mcu_x + 2 * x).min(width - 1) / 2 * 4;
+                        blocks[2][y * 8 + x] = self.chroma_levels[row[pair] as usize];
+                        blocks[3][y * 8 + x] = self.chroma_levels[row[pair + 2] as usize];
+                    }
+                }
+                for (index, block) in blocks.iter().enumerate() {
+                    let (component, quant, dc, ac) = match index {
+                        0 | 1 => (0, &self.luma_quant, &self.dc_luma, &self.ac_luma),
+                        _ => (
+                            index - 1,
+                            &self.chroma_quant,
+                            &self.dc_chroma,
+                            &self.ac_chroma,
+                        ),
+                    };
+                    let coefficients = self.quantized_dct(block, quant);
+                    encode_block(&mut bits, &coefficients, &mut predictors[component], dc, ac);
+                }
+            }
+        }
+        let mut out = bits.finish();
+        out.extend_from_slice(&[0xFF, 0xD9]);
+        out
+    }
+
+    /// Forward DCT of one block, quantized, in natural order
+    fn quantized_dct(&self, block: &[f32; 64], quant: &[u8; 64]) -> [i32; 64] {
+        let mut rows = [0.0f32; 64];
+        for (row, samples) in rows.chunks_exact_mut(8).zip(block.chunks_exact(8)) {
+            for (out, basis) in row.iter_mut().zip(&self.cos) {
+                *out = samples.iter().zip(basis).map(|(s, c)| s * c).sum();
+            }
+        }
+        let mut out = [0; 64];
+        for (v, basis) in self.cos.iter().enumerate() {
+            for u in 0..8 {
+                let value: f32 = basis
+                    .iter()
+                    .enumerate()
+                    .map(|(y, c)| rows[y * 8 + u] * c)
+                    .sum();
+                // The AC symbols of the tables go up to 10 bits
+                out[v * 8 + u] =
+                    ((value / quant[v * 8 + u] as f32).round() as i32).clamp(-1023, 1023);
+            }
+        }
+        out
+    }
+
+    fn write_headers(&self, out: &mut Vec<u8>, width: u16, height: u16) {
+        out.extend_from_slice(&[0xFF, 0xD8]);
+        // JFIF 1.01, no density, no thumbnail
+        write_segment(
+            out,
+            0xE0,
+            &[b'J', b'F', b'I', b'F', 0, 1, 1, 0, 0, 1, 0, 1, 0, 0],
+        );
+
+        let mut dqt = Vec::with_capacity(130);
+        for (id, table) in [&self.luma_quant, &self.chroma_quant]
+            .into_iter()
+            .enumerate()
+        {
+            dqt.push(id as u8);
+            dqt.extend(ZIGZAG.iter().map(|&natural| table[natural]));
+        }
+        write_segment(out, 0xDB, &dqt);
+
+        // 8-bit samples; Y sampled 2x1, Cb and Cr 1x1
+        let mut sof = vec![8];
+        sof.extend_from_slice(&height.to_be_bytes());
+        sof.extend_from_slice(&width.to_be_bytes());
+        sof.extend_from_slice(&[3, 1, 0x21, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        write_segment(out, 0xC0, &sof);
+
+        let mut dht = Vec::new();
+        for (class_id, spec) in [
+            (0x00, &DC_LUMA),
+            (0x10, &AC_LUMA),
+            (0x01, &DC_CHROMA),
+            (0x11, &AC_CHROMA),
+        ] {
+            dht.push(class_id);
+            dht.extend_from_slice(&spec.bits);
+            dht.extend_from_slice(spec.values);
+        }
+        write_segment(out, 0xC4, &dht);
+
+        // All three components, full spectral range, no approximation
+        write_segment(out, 0xDA, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+    }
+}
+
+/// Width and height from the frame header (SOFn) of a JPEG, None if there
+/// is none before the scan. Cameras' MJPEG frames often leave out the
+/// Huffman tables, but never the frame header.
+pub fn jpeg_dimensions(jpeg: &[u8]) -> Option<(u32, u32)> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut at = 2;
+    loop {
+        // Markers may be padded with any number of 0xFF fill bytes
+        while jpeg.get(at) == Some(&0xFF) && jpeg.get(at + 1) == Some(&0xFF) {
+            at += 1;
+        }
+        let (&0xFF, &kind) = (jpeg.get(at)?, jpeg.get(at + 1)?) else {
+            return None;
+        };
+        // TEM and RSTn stand alone, without a length
+        if kind == 0x01 || (0xD0..=0xD7).contains(&kind) {
+            at += 2;
+            continue;
+        }
+        let len = u16::from_be_bytes([*jpeg.get(at + 2)?, *jpeg.get(at + 3)?]) as usize;
+        match kind {
+            // SOF0-SOF15 except DHT, JPG and DAC
+            0xC0..=0xCF if !matches!(kind, 0xC4 | 0xC8 | 0xCC) => {
+                let sof = jpeg.get(at + 4..at + 9)?;
+                let height = u16::from_be_bytes([sof[1], sof[2]]) as u32;
+                let width = u16::from_be_bytes([sof[3], sof[4]]) as u32;
+                return (width > 0 && height > 0).then_some((width, height));
+            }
+            0xDA | 0xD9 => return None,
+            _ => at += 2 + len,
+        }
+    }
+}
+
+/// Marker `0xFF kind` and a segment with its length
+fn write_segment(out: &mut Vec<u8>, kind: u8, data: &[u8]) {
+    out.extend_from_slice(&[0xFF, kind]);
+    out.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn encode_block(
+    bits: &mut BitWriter,
+    coefficients: &[i32; 64],
+    predictor: &mut i32,
+    dc: &HuffmanTable,
+    ac: &HuffmanTable,
+) {
+    let dc_value = coefficients[0];
+    let (size, value) = magnitude(dc_value - *predictor);
+    *predictor = dc_value;
+    let (code, len) = dc.codes[size as usize];
+    bits.put(code as u32, len as u32);
+    bits.put(value, size);
+
+    let mut zeros = 0;
+    for &natural in &ZIGZAG[1..] {
+        let coefficient = coefficients[natural];
+        if coefficient == 0 {
+            zeros += 1;
+            continue;
+        }
+        while zeros >= 16 {
+            let (code, len) = ac.codes[0xF0];
+            bits.put(code as u32, len as u32);
+            zeros -= 16;
+        }
+        let (size, value) = magnitude(coefficient);
+        let (code, len) = ac.codes[(zeros << 4 | size) as usize];
+        bits.put(code as u32, len as u32);
+        bits.put(value, size);
+        zeros = 0;
+    }
+    if zeros > 0 {
+        let (code, len) = ac.codes[0x00];
+        bits.put(code as u32, len as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (marker, segment data) up to the start of scan
+    fn segments(jpeg: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut found = Vec::new();
+        let mut at = 2;
+        while at + 4 <= jpeg.len() && jpeg[at] == 0xFF {
+            let kind = jpeg[at + 1];
+            let len = u16::from_be_bytes([jpeg[at + 2], jpeg[at + 3]]) as usize;
+            found.push((kind, &jpeg[at + 4..at + 2 + len]));
+            at += 2 + len;
+            if kind == 0xDA {
+                break;
+            }
+        }
+        found
+    }
+
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        let mut uyvy = Vec::new();
+        for y in 0..height {
+            for x in 0..width / 2 {
+                let luma = (16 + (x * 4 + y) % 220) as u8;
+                uyvy.extend_from_slice(&[(x * 7 % 256) as u8, luma, (y * 5 % 256) as u8, luma]);
+            }
+        }
+        uyvy
+    }
+
+    #[test]
+    fn test_markers_and_dimensions() {
+        let jpeg = JpegEncoder::new(75).encode(&gradient(100, 30), 100, 30);
+        assert_eq!(&jpeg[..2], &[0xFF, 0xD8]);
+        assert_eq!(&jpeg[jpeg.len() - 2..], &[0xFF, 0xD9]);
+
+        let segments = segments(&jpeg);
+        let kinds: Vec<u8> = segments.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, vec![0xE0, 0xDB, 0xC0, 0xC4, 0xDA]);
+        let (_, sof) = segments[2];
+        assert_eq!(sof[0], 8);
+        assert_eq!(u16::from_be_bytes([sof[1], sof[2]]), 30);
+        assert_eq!(u16::from_be_bytes([sof[3], sof[4]]), 100);
+        assert_eq!(&sof[5..], &[3, 1, 0x21, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    }
+
+    /// RGB a JFIF decoder should produce for a limited-range UYVY sample
+    fn expected_rgb((y, cb, cr): (u8, u8, u8)) -> [f32; 3] {
+        let y = (y as f32 - 16.0) * 255.0 / 219.0;
+        let cb = (cb as f32 - 128.0) * 255.0 / 224.0;
+        let cr = (cr as f32 - 128.0) * 255.0 / 224.0;
+        [
+            y + 1.402 * cr,
+            y - 0.344_136 * cb - 0.714_136 * cr,
+            y + 1.772 * cb,
+        ]
+        .map(|c| c.clamp(0.0, 255.0))
+    }
+
+    fn decode(jpeg: &[u8]) -> (u16, u16, Vec<u8>) {
+        let mut decoder = jpeg_decoder::Decoder::new(jpeg);
+        let pixels = decoder.decode().expect("encoder output must decode");
+        let info = decoder.info().unwrap();
+        assert_eq!(info.pixel_format, jpeg_decoder::PixelFormat::RGB24);
+        (info.width, info.height, pixels)
+    }
+
+    #[test]
+    fn test_decode_round_trip() {
+        // Bars 32 pixels wide, so every MCU is flat
+        let bars = [
+            (235, 128, 128),
+            (16, 128, 128),
+            (145, 147, 44),
+            (63, 193, 204),
+            (51, 109, 212),
+            (28, 212, 120),
+            (126, 128, 128),
+        ];
+        let (width, height) = (32 * bars.len() as u32, 16);
+        let line: Vec<u8> = bars
+            .iter()
+            .flat_map(|&(y, cb, cr)| [cb, y, cr, y].repeat(16))
+            .collect();
+        let uyvy = line.repeat(height as usize);
+
+        let jpeg = JpegEncoder::new(95).encode(&uyvy, width, height);
+        let (decoded_width, decoded_height, rgb) = decode(&jpeg);
+        assert_eq!(
+            (decoded_width as u32, decoded_height as u32),
+            (width, height)
+        );
+        assert_eq!(rgb.len(), (width * height * 3) as usize);
+        for (index, &bar) in bars.iter().enumerate() {
+            let expected = expected_rgb(bar);
+            for y in [0, 7, 15] {
+                let x = index * 32 + 16;
+                let pixel = &rgb[(y * width as usize + x) * 3..][..3];
+                for (channel, (&got, want)) in pixel.iter().zip(expected).enumerate() {
+                    assert!(
+                        (got as f32 - want).abs() <= 4.0,
+                        "bar {} channel {}: {} != {:.1}",
+                        index,
+                        channel,
+                        got,
+                        want
+                    );
+                }
+            }
+        }
+
+        // Sizes that don't fill the last MCU still decode to the full size
+        let jpeg = JpegEncoder::new(50).encode(&gradient(100, 30), 100, 30);
+        let (decoded_width, decoded_height, rgb) = decode(&jpeg);
+        assert_eq!((decoded_width, decoded_height), (100, 30));
+        assert_eq!(rgb.len(), 100 * 30 * 3);
+    }
+
+    #[test]
+    fn test_jpeg_dimensions() {
+        let jpeg = JpegEncoder::new(75).encode(&gradient(100, 30), 100, 30);
+        assert_eq!(jpeg_dimensions(&jpeg), Some((100, 30)));
+
+        // A camera frame: fill bytes, a restart interval, no Huffman
+        // tables and a progressive frame header
+        let mut frame = vec![0xFF, 0xD8, 0xFF];
+        write_segment(&mut frame, 0xDD, &[0, 4]);
+        write_segment(
+            &mut frame,
+            0xC2,
+            &[8, 0x02, 0xD0, 0x05, 0x00, 1, 1, 0x11, 0],
+        );
+        assert_eq!(jpeg_dimensions(&frame), Some((1280, 720)));
+
+        // No frame header before the scan, or cut short
+        let mut no_sof = vec![0xFF, 0xD8];
+        write_segment(&mut no_sof, 0xC4, &[0; 17]);
+        write_segment(&mut no_sof, 0xDA, &[0; 10]);
+        assert_eq!(jpeg_dimensions(&no_sof), None);
+        assert_eq!(jpeg_dimensions(&jpeg[..40]), None);
+        assert_eq!(jpeg_dimensions(b"not a jpeg"), None);
+        assert_eq!(jpeg_dimensions(&[]), None);
+    }
+
+    #[test]
+    fn test_entropy_data_has_no_markers() {
+        // A busy frame at high quality produces plenty of 0xFF bytes
+        let jpeg = JpegEncoder::new(100).encode(&gradient(64, 64), 64, 64);
+        let header_len: usize = segments(&jpeg).iter().map(|(_, data)| data.len() + 4).sum();
+        let scan = &jpeg[2 + header_len..jpeg.len() - 2];
+        assert!(!scan.is_empty());
+        for pair in scan.windows(2) {
+            if pair[0] == 0xFF {
+                assert_eq!(pair[1], 0, "unstuffed 0xFF in the scan");
+            }
+        }
+    }
+
+    #[test]
+    fn test_bit_writer_stuffs_and_pads() {
+        let mut bits = BitWriter::new(Vec::new());
+        bits.put(0xFF, 8);
+        bits.put(0b101, 3);
+        assert_eq!(bits.finish(), vec![0xFF, 0x00, 0b1011_1111]);
+    }
+
+    #[test]
+    fn test_magnitude() {
+        assert_eq!(magnitude(0), (0, 0));
+        assert_eq!(magnitude(1), (1, 1));
+        assert_eq!(magnitude(-1), (1, 0));
+        assert_eq!(magnitude(5), (3, 0b101));
+        assert_eq!(magnitude(-5), (3, 0b010));
+    }
+
+    #[test]
+    fn test_quality_scaling() {
+        assert_eq!(scaled_quant(&LUMA_QUANT, 50), LUMA_QUANT);
+        assert!(scaled_quant(&LUMA_QUANT, 100).iter().all(|&q| q == 1));
+        assert_eq!(scaled_quant(&LUMA_QUANT, 1)[0], 255);
+    }
+
+    #[test]
+    fn test_ac_tables_cover_every_symbol() {
+        // Run 0-15 with sizes 1-10, plus end of block and the 16-zero run
+        for spec in [&AC_LUMA, &AC_CHROMA] {
+            let table = HuffmanTable::new(spec);
+            for run in 0..16 {
+                for size in 1..=10 {
+                    assert_ne!(table.codes[run << 4 | size].1, 0);
+                }
+            }
+            assert_ne!(table.codes[0x00].1, 0);
+            assert_ne!(table.codes[0xF0].1, 0);
+        }
+    }
+}
@@ -1,11 +1,72 @@
 //! camera-box library
 //!
-//! This module exports the public APIs for testing and benchmarking.
+//! This module exports the public APIs for testing and benchmarking, and the
+//! [`pipeline`] API for embedding the capture → NDI pipeline.
 
+pub mod aspect;
+pub mod audio_meter;
+pub mod audio_only;
+pub mod av_clock;
+pub mod camera_controls;
 pub mod capture;
+pub mod capture_audio;
+pub mod capture_dmabuf;
+pub mod capture_mmap;
+pub mod capture_mplane;
+pub mod color_range;
+pub mod compositor;
 pub mod config;
+pub mod config_watch;
+pub mod control;
+pub mod crop;
+pub mod deinterlace;
 pub mod display;
+pub mod exposure;
+pub mod fakes;
+pub mod file_source;
+pub mod frame_age;
+pub mod frame_budget;
+pub mod gpio;
+pub mod image_source;
+pub mod input;
 pub mod intercom;
+pub mod io_util;
+pub mod jpeg;
+pub mod lineup;
+pub mod log_level;
+pub mod mdns;
 pub mod ndi;
+pub mod ndi_conflict;
 pub mod ndi_display;
+pub mod ndi_proxy;
+pub mod ndi_supervisor;
+pub mod net;
+pub mod netcfg;
+pub mod netwatch;
+pub mod output;
+pub mod pacing;
+pub mod pipeline;
+pub mod placeholders;
+pub mod probe;
+pub mod processing;
+pub mod realtime;
+pub mod recorder;
+pub mod replay;
+pub mod sd_notify;
+pub mod serial_bridge;
+pub mod session;
+pub mod shutdown;
+pub mod socket_activation;
+pub mod splash;
+pub mod standby;
+pub mod startup;
+pub mod stats;
+pub mod status_server;
+pub mod test_pattern;
+pub mod threads;
+pub mod timing;
+pub mod usb_reset;
 pub mod vban;
+pub mod watchdog;
+pub mod wav;
+pub mod zero_copy;
@@ -0,0 +1,282 @@
+//! Bars and tone for line-up (`camera-box lineup`)
+//!
+//! Streams SMPTE bars at the slate resolution (`[capture.image]`) as
+//! "<ndi_name>-BARS", with a steady sine tone (1kHz at -18dBFS by default)
+//! as NDI audio and, when `[intercom]` is configured, as VBAN to the
+//! intercom target. Levels are peak dBFS: a full-scale sine is 0dBFS.
+
+use anyhow::{bail, Context, Result};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::capture::{FrameRate, FRAME_TIMEOUT};
+use crate::config::Config;
+use crate::intercom::{ToneGenerator, SAMPLE_RATE};
+use crate::io_util;
+use crate::ndi::NdiSender;
+use crate::net;
+use crate::test_pattern::TestPattern;
+use crate::vban::{VbanCodec, VbanHeader, VbanPacketWriter, VBAN_MAX_SAMPLES_PER_FRAME};
+
+/// Appended to the NDI name so bars are never mistaken for the camera
+pub const NAME_SUFFIX: &str = "-BARS";
+
+/// Line-up tone frequency in Hz
+pub const DEFAULT_FREQUENCY: f32 = 1000.0;
+
+/// Line-up tone level in dBFS (EBU R68 alignment level)
+pub const DEFAULT_LEVEL_DBFS: f32 = -18.0;
+
+/// Quietest tone level accepted
+const MIN_LEVEL_DBFS: f32 = -60.0;
+
+/// Fraction of full scale of a peak level in dBFS
+pub fn dbfs_to_level(dbfs: f32) -> f32 {
+    10f32.powf(dbfs / 20.0)
+}
+
+/// Peak level of `samples` in dBFS (-inf for silence)
+pub fn peak_dbfs(samples: &[i16]) -> f32 {
+    let peak = samples.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0);
+    20.0 * (peak as f32 / 32767.0).log10()
+}
+
+/// Where the tone goes over VBAN
+#[derive(Debug, Clone)]
+pub struct VbanTarget {
+    pub stream: String,
+    pub host: String,
+    pub port: u16,
+    pub codec: VbanCodec,
+}
+
+/// Resolved `camera-box lineup` settings
+#[derive(Debug, Clone)]
+pub struct LineupSettings {
+    pub ndi_name: String,
+    pub ndi_groups: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: FrameRate,
+    pub frequency: f32,
+    pub level_dbfs: f32,
+    pub vban: Option<VbanTarget>,
+}
+
+impl LineupSettings {
+    /// Names, resolution and VBAN target from `config`
+    pub fn from_config(config: &Config, frequency: f32, level_dbfs: f32) -> Result<Self> {
+        let nyquist = SAMPLE_RATE as f32 / 2.0;
+        if !(frequency > 0.0 && frequency < nyquist) {
+            bail!(
+                "Tone frequency must be between 0 and {}Hz, got {}",
+                nyquist,
+                frequency
+            );
+        }
+        if !(MIN_LEVEL_DBFS..=0.0).contains(&level_dbfs) {
+            bail!(
+                "Tone level must be between {} and 0 dBFS, got {}",
+                MIN_LEVEL_DBFS,
+                level_dbfs
+            );
+        }
+        let image = &config.capture.image;
+        let vban = config
+            .intercom
+            .as_ref()
+            .map(|ic| -> Result<VbanTarget> {
+                Ok(VbanTarget {
+                    stream: ic.stream.clone(),
+                    host: ic.target.clone(),
+                    port: ic.port,
+                    codec: VbanCodec::from_name(&ic.tx_codec)?,
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            ndi_name: format!("{}{}", config.ndi_name, NAME_SUFFIX),
+            ndi_groups: config.ndi_groups.clone(),
+            width: image.width,
+            height: image.height,
+            frame_rate: FrameRate {
+                numerator: image.frame_rate_n,
+                denominator: image.frame_rate_d,
+            },
+            frequency,
+            level_dbfs,
+            vban,
+        })
+    }
+
+    /// Tone generator playing the line-up tone
+    pub fn tone(&self) -> ToneGenerator {
+        let mut tone = ToneGenerator::new(SAMPLE_RATE).with_level(dbfs_to_level(self.level_dbfs));
+        tone.play_continuous(self.frequency);
+        tone
+    }
+}
+
+/// Tone sender over VBAN
+struct VbanTone {
+    socket: UdpSocket,
+    target: SocketAddr,
+    writer: VbanPacketWriter,
+    frame_counter: u32,
+}
+
+impl VbanTone {
+    fn open(target: &VbanTarget) -> Result<Self> {
+        let address = net::resolve(&target.host, target.port)
+            .with_context(|| format!("Cannot resolve VBAN target {}", target.host))?[0];
+        let socket = UdpSocket::bind(net::unspecified_for(&address))
+            .context("Failed to bind VBAN socket")?;
+        let header = VbanHeader::new(&target.stream, SAMPLE_RATE, 2, target.codec)?;
+        Ok(Self {
+            socket,
+            target: address,
+            writer: VbanPacketWriter::new(header),
+            frame_counter: 0,
+        })
+    }
+
+    fn send(&mut self, samples: &[i16]) {
+        for chunk in samples.chunks(VBAN_MAX_SAMPLES_PER_FRAME) {
+            let packet = self.writer.write_mono_as_stereo(chunk, self.frame_counter);
+            self.frame_counter = self.frame_counter.wrapping_add(1);
+            if let Err(e) = io_util::retry_eintr(|| self.socket.send_to(packet, self.target)) {
+                tracing::debug!("VBAN tone send failed: {}", e);
+            }
+        }
+    }
+}
+
+/// Stream bars and tone until `running` is cleared
+pub fn run(settings: LineupSettings, running: Arc<AtomicBool>) -> Result<()> {
+    let mut sender = NdiSender::with_groups(
+        &settings.ndi_name,
+        settings.frame_rate,
+        settings.ndi_groups.as_deref(),
+    )?;
+    let mut vban = match &settings.vban {
+        Some(target) => match VbanTone::open(target) {
+            Ok(vban) => {
+                tracing::info!(
+                    "Line-up tone over VBAN to {} ({})",
+                    vban.target,
+                    target.stream
+                );
+                Some(vban)
+            }
+            Err(e) => {
+                tracing::warn!("Line-up tone over NDI only: {:#}", e);
+                None
+            }
+        },
+        None => None,
+    };
+    let mut pattern = TestPattern::smpte(settings.width, settings.height, settings.frame_rate);
+    let mut tone = settings.tone();
+    tracing::info!(
+        "Line-up: {}x{} bars as '{}', {}Hz tone at {}dBFS",
+        settings.width,
+        settings.height,
+        settings.ndi_name,
+        settings.frequency,
+        settings.level_dbfs
+    );
+
+    let start = Instant::now();
+    let mut samples_sent: u64 = 0;
+    let mut mono = Vec::new();
+    let mut stereo = Vec::new();
+    while running.load(Ordering::Relaxed) {
+        pattern.process_frame_timeout(FRAME_TIMEOUT, |data, info| {
+            if let Err(e) = sender.send_frame_zero_copy(data, info) {
+                tracing::debug!("Failed to send bars: {:#}", e);
+            }
+        });
+
+        // Tone for the time since the last frame, kept in step with the clock
+        let due = (start.elapsed().as_secs_f64() * SAMPLE_RATE as f64) as u64;
+        let count = due.saturating_sub(samples_sent) as usize;
+        if count == 0 {
+            continue;
+        }
+        samples_sent += count as u64;
+        mono.clear();
+        mono.extend((0..count).map(|_| tone.next_sample()));
+        stereo.clear();
+        stereo.extend(mono.iter().flat_map(|&sample| [sample, sample]));
+        if let Err(e) = sender.send_audio(&stereo, 2, SAMPLE_RATE, None) {
+            tracing::debug!("Failed to send tone: {}", e);
+        }
+        if let Some(vban) = &mut vban {
+            vban.send(&mono);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone_samples(settings: &LineupSettings, count: usize) -> Vec<i16> {
+        let mut tone = settings.tone();
+        (0..count).map(|_| tone.next_sample()).collect()
+    }
+
+    #[test]
+    fn test_tone_level_in_dbfs() {
+        let config = Config::default();
+        for level in [DEFAULT_LEVEL_DBFS, -20.0, -6.0, 0.0] {
+            let settings = LineupSettings::from_config(&config, 1000.0, level).unwrap();
+            let samples = tone_samples(&settings, SAMPLE_RATE as usize);
+            let peak = peak_dbfs(&samples);
+            assert!(
+                (peak - level).abs() < 0.05,
+                "{} dBFS came out at {}",
+                level,
+                peak
+            );
+        }
+        assert!((dbfs_to_level(-18.0) - 0.1259).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_tone_frequency() {
+        let settings =
+            LineupSettings::from_config(&Config::default(), 1000.0, DEFAULT_LEVEL_DBFS).unwrap();
+        let samples = tone_samples(&settings, SAMPLE_RATE as usize);
+        // A 1kHz sine crosses zero upwards 1000 times a second
+        let rising = samples.windows(2).filter(|w| w[0] < 0 && w[1] >= 0).count();
+        assert!((999..=1000).contains(&rising), "{}", rising);
+    }
+
+    #[test]
+    fn test_settings_from_config() {
+        let mut config = Config::default();
+        config.ndi_name = "cam1".to_string();
+        let settings =
+            LineupSettings::from_config(&config, DEFAULT_FREQUENCY, DEFAULT_LEVEL_DBFS).unwrap();
+        assert_eq!(settings.ndi_name, "cam1-BARS");
+        assert_eq!((settings.width, settings.height), (1920, 1080));
+        assert!(settings.vban.is_none());
+
+        config.intercom = toml::from_str::<Config>("[intercom]\n").unwrap().intercom;
+        let settings =
+            LineupSettings::from_config(&config, DEFAULT_FREQUENCY, DEFAULT_LEVEL_DBFS).unwrap();
+        let vban = settings.vban.unwrap();
+        assert_eq!(
+            (vban.stream.as_str(), vban.host.as_str()),
+            ("cam1", "strih.lan")
+        );
+
+        assert!(LineupSettings::from_config(&config, 0.0, -18.0).is_err());
+        assert!(LineupSettings::from_config(&config, 30000.0, -18.0).is_err());
+        assert!(LineupSettings::from_config(&config, 1000.0, 3.0).is_err());
+    }
+}
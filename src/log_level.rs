@@ -0,0 +1,164 @@
+//! Runtime log filter
+//!
+//! The subscriber's `EnvFilter` sits behind a reload layer, so the filter can
+//! be swapped while running: `log-level <filter>` on the control socket sets
+//! any filter, and SIGUSR1 toggles between the configured filter and
+//! [`DEBUG_PRESET`]. The current filter is reported in `status`.
+
+use anyhow::{anyhow, Result};
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Filter used without `--debug`
+pub const DEFAULT_FILTER: &str = "camera_box=info";
+
+/// Filter used with `--debug`, and the SIGUSR1 toggle target
+pub const DEBUG_PRESET: &str = "camera_box=debug,grafton_ndi=debug";
+
+/// Filter layer that [`LogLevel`] reloads
+pub type FilterLayer = reload::Layer<EnvFilter, Registry>;
+
+/// Handle for changing the log filter at runtime
+#[derive(Clone)]
+pub struct LogLevel {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// Filter set at startup, restored by [`LogLevel::toggle_debug`]
+    configured: String,
+    current: Arc<Mutex<String>>,
+}
+
+impl LogLevel {
+    /// Reloadable filter layer starting at `filter`, with its handle
+    pub fn new(filter: &str) -> Result<(Self, FilterLayer)> {
+        let (layer, handle) = reload::Layer::new(parse(filter)?);
+        let level = Self {
+            handle,
+            configured: filter.to_string(),
+            current: Arc::new(Mutex::new(filter.to_string())),
+        };
+        Ok((level, layer))
+    }
+
+    /// Install the global subscriber: formatted output through a reloadable filter
+    pub fn init(filter: &str) -> Result<Self> {
+        let (level, layer) = Self::new(filter)?;
+        tracing_subscriber::registry()
+            .with(layer)
+            .with(tracing_subscriber::fmt::layer())
+            .init();
+        Ok(level)
+    }
+
+    /// Current filter string
+    pub fn current(&self) -> String {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Replace the filter, e.g. "camera_box=debug,camera_box::intercom=trace"
+    pub fn set(&self, filter: &str) -> Result<()> {
+        let parsed = parse(filter)?;
+        self.handle
+            .reload(parsed)
+            .map_err(|e| anyhow!("Failed to reload log filter: {}", e))?;
+        *self.current.lock().unwrap() = filter.to_string();
+        Ok(())
+    }
+
+    /// Switch to [`DEBUG_PRESET`], or back to the configured filter if it is
+    /// already active (to [`DEFAULT_FILTER`] when started with `--debug`).
+    /// Returns the new filter.
+    pub fn toggle_debug(&self) -> Result<String> {
+        let next = if self.current() != DEBUG_PRESET {
+            DEBUG_PRESET
+        } else if self.configured != DEBUG_PRESET {
+            self.configured.as_str()
+        } else {
+            DEFAULT_FILTER
+        }
+        .to_string();
+        self.set(&next)?;
+        Ok(next)
+    }
+}
+
+fn parse(filter: &str) -> Result<EnvFilter> {
+    let filter = filter.trim();
+    if filter.is_empty() {
+        return Err(anyhow!("Empty log filter"));
+    }
+    EnvFilter::try_new(filter).map_err(|e| anyhow!("Invalid log filter {:?}: {}", filter, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer};
+
+    /// Records the level of every event that passes the filter
+    struct Capture(Arc<Mutex<Vec<Level>>>);
+
+    impl<S: Subscriber> Layer<S> for Capture {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            self.0.lock().unwrap().push(*event.metadata().level());
+        }
+    }
+
+    #[test]
+    fn test_reload_enables_and_suppresses_debug_events() {
+        let (level, layer) = LogLevel::new(DEFAULT_FILTER).unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry()
+            .with(layer)
+            .with(Capture(Arc::clone(&events)));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let emit = || {
+                tracing::debug!("debug detail");
+                tracing::info!("info line");
+            };
+            emit();
+            assert_eq!(*events.lock().unwrap(), vec![Level::INFO]);
+
+            level.set("camera_box=debug").unwrap();
+            events.lock().unwrap().clear();
+            emit();
+            assert_eq!(*events.lock().unwrap(), vec![Level::DEBUG, Level::INFO]);
+
+            level.set("camera_box=warn").unwrap();
+            events.lock().unwrap().clear();
+            emit();
+            assert!(events.lock().unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_set_rejects_bad_filters() {
+        let (level, _layer) = LogLevel::new(DEFAULT_FILTER).unwrap();
+        assert!(level.set("").is_err());
+        assert!(level.set("camera_box=loud").is_err());
+        assert_eq!(level.current(), DEFAULT_FILTER);
+        level.set("camera_box::intercom=trace").unwrap();
+        assert_eq!(level.current(), "camera_box::intercom=trace");
+    }
+
+    #[test]
+    fn test_toggle_debug() {
+        let (level, _layer) = LogLevel::new(DEFAULT_FILTER).unwrap();
+        assert_eq!(level.toggle_debug().unwrap(), DEBUG_PRESET);
+        assert_eq!(level.current(), DEBUG_PRESET);
+        assert_eq!(level.toggle_debug().unwrap(), DEFAULT_FILTER);
+
+        // From a custom filter, the toggle goes to debug and back to the configured one
+        level.set("camera_box=warn").unwrap();
+        assert_eq!(level.toggle_debug().unwrap(), DEBUG_PRESET);
+        assert_eq!(level.toggle_debug().unwrap(), DEFAULT_FILTER);
+
+        // Started with --debug, the toggle falls back to the default filter
+        let (level, _layer) = LogLevel::new(DEBUG_PRESET).unwrap();
+        assert_eq!(level.toggle_debug().unwrap(), DEFAULT_FILTER);
+        assert_eq!(level.toggle_debug().unwrap(), DEBUG_PRESET);
+    }
+}
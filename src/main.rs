@@ -1,83 +1,42 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::signal;
-use tracing_subscriber::EnvFilter;
 
+use camera_box::audio_only::{self, AudioOnlyStream};
 use camera_box::capture::VideoCapture;
-use camera_box::config::Config;
+use camera_box::config::{self, Config};
+use camera_box::config_watch::{self, Reloader};
+use camera_box::control::{self, CameraDevice, ControlHandles, ControlServer};
+use camera_box::exposure::ExposureSettings;
+use camera_box::file_source;
+use camera_box::gpio;
+use camera_box::image_source;
+use camera_box::input;
 use camera_box::intercom;
-use camera_box::ndi::NdiSender;
-use camera_box::ndi_display::{self, NdiDisplayConfig};
-
-/// Apply real-time optimizations to the current thread for lowest latency
-/// Based on media-bridge's extreme low-latency settings
-fn apply_realtime_optimizations() {
-    // 1. Set real-time SCHED_FIFO scheduling with high priority
-    apply_realtime_scheduling();
-
-    // 2. Lock all memory to prevent page faults
-    apply_memory_locking();
-
-    // 3. Set CPU affinity (optional - pin to core 1)
-    apply_cpu_affinity();
-}
-
-/// Set SCHED_FIFO real-time scheduling with priority 90
-fn apply_realtime_scheduling() {
-    unsafe {
-        let param = libc::sched_param { sched_priority: 90 };
-        let result = libc::sched_setscheduler(0, libc::SCHED_FIFO, &param);
-
-        if result == 0 {
-            tracing::info!("Real-time SCHED_FIFO priority 90 enabled");
-        } else {
-            tracing::warn!(
-                "Could not set real-time priority (need CAP_SYS_NICE). \
-                Run: sudo setcap 'cap_sys_nice,cap_ipc_lock+ep' /usr/local/bin/camera-box"
-            );
-        }
-    }
-}
-
-/// Lock all memory to prevent page faults during capture
-fn apply_memory_locking() {
-    unsafe {
-        // MCL_CURRENT: Lock all pages currently mapped
-        // MCL_FUTURE: Lock all pages that will be mapped in the future
-        let result = libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE);
-
-        if result == 0 {
-            tracing::info!("Memory locked (mlockall) - no page faults possible");
-        } else {
-            tracing::warn!(
-                "Could not lock memory (need CAP_IPC_LOCK). \
-                Run: sudo setcap 'cap_sys_nice,cap_ipc_lock+ep' /usr/local/bin/camera-box"
-            );
-        }
-    }
-}
-
-/// Set CPU affinity to pin capture thread to a specific core
-fn apply_cpu_affinity() {
-    unsafe {
-        let mut cpuset: libc::cpu_set_t = std::mem::zeroed();
-
-        // Pin to CPU core 1 (leave core 0 for system tasks)
-        libc::CPU_SET(1, &mut cpuset);
-
-        let result = libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &cpuset);
-
-        if result == 0 {
-            tracing::info!("CPU affinity set to core 1");
-        } else {
-            // Not critical - just a hint to the scheduler
-            tracing::debug!("Could not set CPU affinity (non-critical)");
-        }
-    }
-}
+use camera_box::lineup::{self, LineupSettings};
+use camera_box::log_level::{self, LogLevel};
+use camera_box::mdns::{self, ServiceInfo};
+use camera_box::ndi::RecvColorFormat;
+use camera_box::ndi_display::{self, DisplayExit, NdiDisplayConfig};
+use camera_box::netcfg;
+use camera_box::pipeline::{Pipeline, PipelineEvent};
+use camera_box::replay::ReplayHandle;
+use camera_box::sd_notify;
+use camera_box::serial_bridge::{SerialBridge, SerialBridgeConfig, SerialBridgeStats};
+use camera_box::session::{Session, SessionState};
+use camera_box::shutdown::{self, Outcome, Shutdown};
+use camera_box::socket_activation;
+use camera_box::splash::{self, SplashHandle};
+use camera_box::standby::StandbySettings;
+use camera_box::startup::{self, Prerequisite, Startup, Step, Timeline};
+use camera_box::stats::{Report, StatsRegistry, StatsTicker, Value};
+use camera_box::status_server::StatusServer;
+use camera_box::threads::{self, ThreadCpu};
+use camera_box::timing::PipelineTiming;
+use camera_box::vban::VbanCodec;
 
 /// Simple USB video capture to NDI streaming appliance
 #[derive(Parser, Debug)]
@@ -87,6 +46,10 @@ struct Args {
     #[arg(short, long, default_value = "/etc/camera-box/config.toml")]
     config: PathBuf,
 
+    /// Apply the config's [profile.<name>] section over the base settings
+    #[arg(long, global = true, env = config::PROFILE_ENV)]
+    profile: Option<String>,
+
     /// Override video device path
     #[arg(short, long)]
     device: Option<String>,
@@ -103,6 +66,18 @@ struct Args {
     #[arg(long)]
     debug: bool,
 
+    /// List the capture device's formats and the one that would be picked, then exit
+    #[arg(long)]
+    probe: bool,
+
+    /// Print the --probe report as JSON
+    #[arg(long, requires = "probe")]
+    json: bool,
+
+    /// Report per-stage capture timing (overrides capture.timing)
+    #[arg(long)]
+    timing: bool,
+
     /// Enable VBAN intercom (stream name, e.g., "cam1")
     #[arg(long = "intercom")]
     intercom_stream: Option<String>,
@@ -110,46 +85,269 @@ struct Args {
     /// VBAN intercom target host (default: strih.lan)
     #[arg(long, default_value = "strih.lan")]
     intercom_target: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Send a control command to a running camera-box (e.g. "status")
+    Ctl {
+        /// Control socket path
+        #[arg(long, default_value = control::DEFAULT_SOCKET_PATH)]
+        socket: PathBuf,
+
+        /// Command and arguments, e.g. display.source "CAM2 (return)"
+        #[arg(required = true, trailing_var_arg = true)]
+        command: Vec<String>,
+    },
+
+    /// Write or check a configuration file
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+
+    /// Network provisioning from the [network] config section
+    Netcfg {
+        #[command(subcommand)]
+        action: NetcfgCommand,
+    },
+
+    /// Stream SMPTE bars and a line-up tone as "<ndi_name>-BARS" until Ctrl+C
+    Lineup {
+        /// Tone frequency in Hz
+        #[arg(long, default_value_t = lineup::DEFAULT_FREQUENCY)]
+        frequency: f32,
+
+        /// Tone level in dBFS
+        #[arg(long, default_value_t = lineup::DEFAULT_LEVEL_DBFS, allow_negative_numbers = true)]
+        level: f32,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum NetcfgCommand {
+    /// Configure the interface, routes, DNS and hostname (needs CAP_NET_ADMIN)
+    Apply,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Write a commented config with every default (path defaults to --config)
+    Init {
+        path: Option<PathBuf>,
+
+        /// Replace an existing file
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Check a config file and the devices it names, exiting non-zero on errors
+    Validate { path: Option<PathBuf> },
+
+    /// Print the settings a config file sets, with --profile merged in
+    Show { path: Option<PathBuf> },
+}
+
+/// Run `camera-box config init|validate|show`
+fn run_config(
+    action: &ConfigCommand,
+    default_path: &std::path::Path,
+    profile: Option<&str>,
+) -> Result<()> {
+    match action {
+        ConfigCommand::Init { path, force } => {
+            let path = path.as_deref().unwrap_or(default_path);
+            if path.exists() && !force {
+                anyhow::bail!("{} exists (use --force to replace it)", path.display());
+            }
+            if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, config::DEFAULT_CONFIG)?;
+            println!("Wrote {}", path.display());
+            Ok(())
+        }
+        ConfigCommand::Validate { path } => {
+            let path = path.as_deref().unwrap_or(default_path);
+            if !path.exists() {
+                anyhow::bail!("{} does not exist", path.display());
+            }
+            let (config, mut errors) = config::check_profile_file(path, profile)?;
+            if let Some(config) = config {
+                errors.extend(config.check_devices());
+            }
+            for error in &errors {
+                println!("{}: {}", path.display(), error);
+            }
+            if !errors.is_empty() {
+                std::process::exit(1);
+            }
+            println!("{}: OK", path.display());
+            Ok(())
+        }
+        ConfigCommand::Show { path } => {
+            let path = path.as_deref().unwrap_or(default_path);
+            print!("{}", show_config(path, profile)?);
+            Ok(())
+        }
+    }
+}
+
+/// The settings `path` sets under `profile`, as TOML; unset ones take their
+/// defaults
+fn show_config(path: &std::path::Path, profile: Option<&str>) -> Result<String> {
+    let source =
+        std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+    let table = config::profile_table(&source, profile)?;
+    let header = match profile {
+        Some(name) => format!("# {} with profile {:?}\n", path.display(), name),
+        None => format!("# {}\n", path.display()),
+    };
+    Ok(header + &toml::to_string(&table)?)
+}
+
+/// Run `camera-box lineup` until Ctrl+C
+async fn run_lineup(settings: LineupSettings) -> Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+    let worker = {
+        let running = running.clone();
+        tokio::task::spawn_blocking(move || {
+            threads::run_named(threads::LINEUP, || lineup::run(settings, running))
+        })
+    };
+    tokio::pin!(worker);
+    tokio::select! {
+        result = &mut worker => return result?,
+        _ = signal::ctrl_c() => tracing::info!("Stopping line-up..."),
+    }
+    running.store(false, Ordering::SeqCst);
+    worker.await?
+}
+
+/// Run `camera-box ctl`: print the response, failing on `err` replies
+fn run_ctl(socket: &std::path::Path, command: &[String]) -> Result<()> {
+    let response = control::send_command(socket, &command.join(" "))?;
+    println!("{}", response);
+    if response.starts_with("err") {
+        std::process::exit(1);
+    }
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
 
-    // Initialize logging
-    let filter = if args.debug {
-        EnvFilter::new("camera_box=debug,grafton_ndi=debug")
+    if let Some(Command::Ctl { socket, command }) = &args.command {
+        return run_ctl(socket, command);
+    }
+    if let Some(Command::Config { action }) = &args.command {
+        return run_config(action, &args.config, args.profile.as_deref());
+    }
+
+    // Initialize logging; the filter can be changed later at runtime
+    let log_level = LogLevel::init(if args.debug {
+        log_level::DEBUG_PRESET
     } else {
-        EnvFilter::new("camera_box=info")
-    };
-    tracing_subscriber::fmt().with_env_filter(filter).init();
+        log_level::DEFAULT_FILTER
+    })?;
+
+    if let Some(Command::Netcfg {
+        action: NetcfgCommand::Apply,
+    }) = &args.command
+    {
+        let config = Config::load_profile(&args.config, args.profile.as_deref())?;
+        let network = config
+            .network
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No [network] section in {}", args.config.display()))?;
+        return netcfg::apply(network, &config.hostname);
+    }
+
+    if let Some(Command::Lineup { frequency, level }) = &args.command {
+        let config = Config::load_profile(&args.config, args.profile.as_deref())?;
+        return run_lineup(LineupSettings::from_config(&config, *frequency, *level)?).await;
+    }
 
     tracing::info!("camera-box starting...");
 
     // Load configuration
-    let config = Config::load(&args.config)?;
+    let mut config = Config::load_profile(&args.config, args.profile.as_deref())?;
+    config.capture.timing |= args.timing;
+    if let Some(ref profile) = args.profile {
+        tracing::info!("Config profile: {}", profile);
+    }
+    for error in config.validate() {
+        tracing::warn!("{}: {}", args.config.display(), error);
+    }
     tracing::info!("Hostname: {}", config.hostname);
 
-    // Determine device path
-    let device_path = if let Some(ref device) = args.device {
-        device.clone()
+    // Determine device path; audio-only and display-only boxes have no camera
+    let device_path = if config.ndi.audio_only {
+        if args.device.is_some() {
+            anyhow::bail!("--device cannot be used with ndi.audio_only");
+        }
+        None
+    } else if let Some(ref device) = args.device {
+        Some(device.clone())
+    } else if config.device == config::NO_DEVICE {
+        None
     } else {
-        config.device_path()?
+        Some(config.device_path()?)
     };
 
+    if args.probe {
+        let device_path = device_path
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("No capture device to probe"))?;
+        let report = VideoCapture::probe(
+            device_path,
+            config.capture.frame_rate()?,
+            &config.capture.formats()?,
+        )?;
+        if args.json {
+            println!("{}", report.to_json());
+        } else {
+            print!("{}", report.to_table());
+        }
+        return Ok(());
+    }
+
     // Determine display source (CLI overrides config)
-    let display_config = if let Some(ref source) = args.display_source {
+    let mut display_config = if let Some(ref source) = args.display_source {
         Some(NdiDisplayConfig {
             source_name: source.clone(),
             fb_device: args.fb_device.clone(),
-            find_timeout_secs: 30,
+            ..Default::default()
         })
     } else {
-        config.display.as_ref().map(|display| NdiDisplayConfig {
-            source_name: display.source.clone(),
-            fb_device: display.fb_device.clone(),
-            find_timeout_secs: 30,
-        })
+        config
+            .display
+            .as_ref()
+            .map(|display| -> Result<NdiDisplayConfig> {
+                Ok(NdiDisplayConfig {
+                    source_name: display.source.clone(),
+                    fb_device: display.fb_device.clone(),
+                    color_format: RecvColorFormat::from_name(&display.color_format)?,
+                    max_age: std::time::Duration::from_millis(display.max_age_ms),
+                    max_fps: display.max_fps,
+                    exposure: ExposureSettings {
+                        histogram: display.histogram,
+                        zebra: display.zebra,
+                        zebra_percent: display.zebra_percent,
+                    },
+                    standby: StandbySettings {
+                        dim: display.standby_dim,
+                        dim_after: std::time::Duration::from_secs(display.standby_dim_secs),
+                    },
+                    idle_exit: std::time::Duration::from_secs(display.idle_exit_mins * 60),
+                    ..Default::default()
+                })
+            })
+            .transpose()?
     };
 
     // Determine intercom config (CLI overrides config)
@@ -164,150 +362,730 @@ async fn main() -> Result<()> {
             headphone_gain: 15.0, // Headphone volume from network
             limiter_enabled: true,
             limiter_threshold: 0.5, // -6dB ceiling
+            hostname: config.hostname.clone(),
+            ..Default::default()
         })
     } else {
-        config.intercom.as_ref().map(|ic| intercom::IntercomConfig {
-            stream_name: ic.stream.clone(),
-            target_host: ic.target.clone(),
-            sample_rate: ic.sample_rate,
-            channels: ic.channels,
-            sidetone_gain: ic.sidetone_gain,
-            mic_gain: ic.mic_gain,
-            headphone_gain: ic.headphone_gain,
-            limiter_enabled: ic.limiter_enabled,
-            limiter_threshold: ic.limiter_threshold,
-        })
+        config
+            .intercom
+            .as_ref()
+            .map(|ic| -> Result<intercom::IntercomConfig> {
+                Ok(intercom::IntercomConfig {
+                    stream_name: ic.stream.clone(),
+                    stream_ignore_case: ic.stream_ignore_case,
+                    target_host: ic.target.clone(),
+                    sample_rate: ic.sample_rate,
+                    channels: ic.channels,
+                    sidetone_gain: ic.sidetone_gain,
+                    mic_gain: ic.mic_gain,
+                    headphone_gain: ic.headphone_gain,
+                    limiter_enabled: ic.limiter_enabled,
+                    limiter_threshold: ic.limiter_threshold,
+                    allowed_sources: ic.allowed_sources.clone(),
+                    auto_lock: ic.auto_lock,
+                    link_timeout_ms: ic.link_timeout_ms,
+                    tx_codec: VbanCodec::from_name(&ic.tx_codec)?,
+                    hostname: config.hostname.clone(),
+                    ping_announce: ic.ping_announce,
+                    dscp: ic.dscp,
+                    recv_buffer_size: ic.recv_buffer_size,
+                    interface: ic.interface.clone(),
+                    port: ic.port,
+                    listen: ic.listen.clone(),
+                    bind_address: ic
+                        .bind_address
+                        .as_deref()
+                        .map(|ip| camera_box::net::unbracket(ip).parse())
+                        .transpose()?,
+                    record_dir: ic.record_dir.clone(),
+                    record_segment_secs: ic.record_segment_secs,
+                    record_keep: ic.record_keep,
+                    mute_key: input::parse_key_name(&ic.mute_key)?,
+                    mute_led: input::MuteLed::from_name(&ic.mute_led)?,
+                    button_gpio: ic
+                        .button_gpio
+                        .as_deref()
+                        .map(gpio::GpioLine::parse)
+                        .transpose()?,
+                    tally_led: ic.tally_led_gpio.as_ref().map(|led| gpio::TallyLedPins {
+                        chip: led.chip.clone(),
+                        red: led.red,
+                        green: led.green,
+                    }),
+                    auto_unmute: intercom::AutoUnmute::from_name(&ic.auto_unmute)?,
+                    call_tone: ic.call_tone,
+                    mic_channel: intercom::MicChannel::from_name(&ic.mic_channel)?,
+                    periods: intercom::AlsaPeriods {
+                        frames: ic.period_frames,
+                        count: ic.periods,
+                    },
+                    echo: intercom::EchoConfig {
+                        enabled: ic.echo.enabled,
+                        far_threshold: ic.echo.far_threshold,
+                        near_threshold: ic.echo.near_threshold,
+                        duck_db: ic.echo.duck_db,
+                        attack_ms: ic.echo.attack_ms,
+                        release_ms: ic.echo.release_ms,
+                    },
+                    silence: intercom::SilenceConfig {
+                        enabled: ic.silence.enabled,
+                        threshold_db: ic.silence.threshold_db,
+                        secs: ic.silence.secs,
+                        notify: ic.silence.notify,
+                    },
+                    routing: ic.routing.routing()?,
+                    alsa_device: intercom::ALSA_DEVICE.to_string(),
+                    mic_tap: None,
+                })
+            })
+            .transpose()?
     };
 
+    // An idle exit would take a camera or the intercom off air too, e.g.
+    // with --device or --intercom-stream on a display-only config
+    if let Some(display) = display_config.as_mut() {
+        if !display.idle_exit.is_zero() && (device_path.is_some() || intercom_config.is_some()) {
+            tracing::warn!("display.idle_exit_mins ignored: the box is not display-only");
+            display.idle_exit = std::time::Duration::ZERO;
+        }
+    }
+
     // Run the capture loop with optional display and intercom
     run_capture_loop(
-        &device_path,
-        &config.ndi_name,
+        device_path.as_deref(),
+        &config,
+        &args.config,
+        args.profile.as_deref(),
         display_config,
         intercom_config,
+        log_level,
     )
     .await
 }
 
+/// Streams the capture device, only the intercom mic without one
+/// (audio-only), or nothing besides the display (`device = "none"`)
 async fn run_capture_loop(
-    device_path: &str,
-    ndi_name: &str,
+    device_path: Option<&str>,
+    config: &Config,
+    config_path: &std::path::Path,
+    profile: Option<&str>,
     display_config: Option<NdiDisplayConfig>,
-    intercom_config: Option<intercom::IntercomConfig>,
+    mut intercom_config: Option<intercom::IntercomConfig>,
+    log_level: LogLevel,
 ) -> Result<()> {
+    // Validate the capture pipeline before starting anything else
+    let mut pipeline = match device_path {
+        Some(device_path) => Some(
+            Pipeline::builder(config.clone())
+                .device(device_path)
+                .realtime(true)
+                .build()?,
+        ),
+        None => None,
+    };
+    let mic = if pipeline.is_none() && config.ndi.audio_only {
+        let intercom_config = intercom_config
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("ndi.audio_only needs the intercom"))?;
+        let (tap, mic) = audio_only::mic_tap();
+        intercom_config.mic_tap = Some(tap);
+        Some(mic)
+    } else {
+        None
+    };
+
     // Shared flag for graceful shutdown
     let running = Arc::new(AtomicBool::new(true));
 
-    // Start display thread if configured (LOW PRIORITY - different core)
-    let display_handle = if let Some(config) = display_config {
-        let running_clone = Arc::clone(&running);
-        tracing::info!("Starting NDI display for source: {}", config.source_name);
+    // Intercom stats outlive intercom restarts and are shared with status consumers
+    let intercom_stats = Arc::new(intercom::IntercomStats::new());
 
-        Some(std::thread::spawn(move || {
-            // Apply low priority settings BEFORE doing anything
-            ndi_display::apply_low_priority();
+    // Control socket channels; receivers of disabled features are dropped
+    let initial_source = display_config
+        .as_ref()
+        .map(|d| d.source_name.as_str())
+        .unwrap_or_default();
+    let stats_for_control = intercom_config
+        .as_ref()
+        .map(|_| Arc::clone(&intercom_stats));
+    let (mut control_handles, display_control, mute_control) =
+        control::channels(initial_source, stats_for_control);
 
-            if let Err(e) = ndi_display::run_display_loop(config, running_clone) {
-                tracing::error!("NDI display error: {}", e);
-            }
-        }))
-    } else {
-        None
+    // Every component's counters, logged together by the stats ticker
+    let stats_registry = Arc::new(StatsRegistry::new());
+    control_handles = control_handles.with_stats(Arc::clone(&stats_registry));
+    if display_config.is_some() {
+        display_control.stats().register(&stats_registry, "display");
+    }
+    if intercom_config.is_some() {
+        intercom_stats.register(&stats_registry);
+    }
+
+    // Frame totals over all runs, carried on from the last clean stop
+    let session = Arc::new(Session::new(
+        load_session_state(&config.state_file),
+        std::time::Instant::now(),
+    ));
+    let captured = pipeline.as_ref().map(Pipeline::stats);
+    let frames_captured = move || {
+        captured
+            .as_ref()
+            .map_or(0, |stats| stats.frames_captured.load(Ordering::Relaxed))
     };
+    session.register(&stats_registry, frames_captured.clone());
+    if let Some(device_path) = device_path {
+        control_handles = control_handles.with_camera(CameraDevice {
+            device_path: device_path.to_string(),
+            controls_file: config.capture.controls_file.clone(),
+        });
+    }
+    if let Some(display_config) = &display_config {
+        control_handles = control_handles.with_exposure(display_config.exposure);
+    }
+    toggle_debug_on_sigusr1(log_level.clone())?;
+    control_handles = control_handles.with_log_level(log_level);
+    if let Some(replay) = pipeline.as_ref().and_then(Pipeline::replay) {
+        control_handles = control_handles.with_replay(replay.clone());
+        dump_replay_on_sigusr2(replay)?;
+    }
+    if let Some(timing) = pipeline.as_ref().and_then(Pipeline::timing) {
+        dump_timing_on_sigusr2(timing)?;
+    }
 
-    // Start intercom thread if configured
-    let intercom_handle = if let Some(config) = intercom_config {
-        let running_clone = Arc::clone(&running);
-        tracing::info!(
-            "Starting VBAN intercom: stream={}, target={}",
-            config.stream_name,
-            config.target_host
-        );
+    // Start the components in dependency order, each once its prerequisites
+    // are there (or have timed out), so a slow boot doesn't race them
+    let steps = startup_steps(
+        config,
+        device_path,
+        display_config.as_ref(),
+        intercom_config.as_ref(),
+    );
+    let order = startup::resolve(&steps)?;
+    let mut startup = Startup::new(config.startup.timeouts());
+    // Progress on the monitor meanwhile; the display takes over its framebuffer
+    let splash_device = display_config
+        .as_ref()
+        .map_or(splash::DEFAULT_FB_DEVICE, |d| d.fb_device.as_str());
+    let mut splash = Some(splash::spawn_splash(
+        splash_device.to_string(),
+        startup.subscribe(),
+        Arc::clone(&running),
+    ));
+    let mut display_config = display_config;
+    let mut display_control = display_config.as_ref().map(|_| display_control);
+    let mut mute_control = intercom_config.as_ref().map(|_| mute_control);
+    let control_handles = Arc::new(control_handles);
+    reload_config_on_change(
+        config_path,
+        profile,
+        config.clone(),
+        Arc::clone(&control_handles),
+        Arc::clone(&running),
+    )?;
+    let mut mic = mic;
 
-        Some(std::thread::spawn(move || {
-            if let Err(e) = intercom::run_intercom(config, running_clone) {
-                tracing::error!("Intercom error: {}", e);
+    let (shutdown, shutdown_rx) = tokio::sync::watch::channel(false);
+    let serial_stats = Arc::new(SerialBridgeStats::new());
+    // Raised by the display after `display.idle_exit_mins` without video
+    let display_idle = Arc::new(tokio::sync::Notify::new());
+    let mut display_handle = None;
+    let mut intercom_handle = None;
+    let mut serial_handle = None;
+    let mut audio_stream = None;
+    let mut pipeline_events = None;
+    for name in order {
+        let step = steps
+            .iter()
+            .find(|step| step.name == name)
+            .expect("resolved steps come from the list");
+        startup.wait(step).await;
+        match name {
+            // Control socket and status page (keep running until the process exits)
+            STEP_CONTROL => {
+                // Under socket activation systemd already listens for us
+                if let Some(listener) = socket_activation::control_listener() {
+                    tracing::info!("Control socket passed by systemd");
+                    ControlServer::from_listener(listener, Arc::clone(&control_handles)).spawn();
+                } else if !config.control_socket.is_empty() {
+                    match ControlServer::bind(&config.control_socket, Arc::clone(&control_handles))
+                    {
+                        Ok(server) => {
+                            server.spawn();
+                        }
+                        Err(e) => tracing::warn!("Control socket disabled: {:#}", e),
+                    }
+                }
+                if config.status_port != 0 {
+                    match StatusServer::bind(
+                        config.status_port,
+                        &config.status.token,
+                        Arc::clone(&control_handles),
+                    ) {
+                        Ok(server) => {
+                            server.spawn();
+                        }
+                        Err(e) => tracing::warn!("Status page disabled: {:#}", e),
+                    }
+                }
+            }
+
+            // Intercom; it stops and joins its threads on `shutdown`
+            STEP_INTERCOM => {
+                let config = intercom_config.take().expect("intercom starts once");
+                tracing::info!(
+                    "Starting VBAN intercom: stream={}, target={}",
+                    config.stream_name,
+                    config.target_host
+                );
+                intercom_handle = Some(intercom::spawn_intercom(
+                    config,
+                    Arc::clone(&intercom_stats),
+                    mute_control.take().expect("intercom starts once"),
+                    shutdown_rx.clone(),
+                ));
             }
-        }))
-    } else {
-        None
-    };
 
-    // Open capture device at 1920x1080 @ 60fps
-    let mut capture = VideoCapture::open(device_path)?;
-    let (width, height) = capture.dimensions();
-    let frame_rate = capture.frame_rate();
-    tracing::info!("Capturing at {}x{}", width, height);
-
-    // Create NDI sender with configured name and detected frame rate
-    let mut sender = NdiSender::new(ndi_name, frame_rate)?;
-    tracing::info!("NDI sender ready, streaming as '{}'", ndi_name);
-    tracing::info!("ZERO-COPY mode: AVX2 SIMD + sync send for lowest latency");
-
-    // Spawn capture loop in blocking task - minimal overhead for lowest latency
-    let running_capture = Arc::clone(&running);
-    let capture_handle = tokio::task::spawn_blocking(move || {
-        // Apply real-time optimizations BEFORE entering the capture loop
-        apply_realtime_optimizations();
-
-        let mut frame_count: u64 = 0;
-        let mut last_report = std::time::Instant::now();
-
-        while running_capture.load(Ordering::Relaxed) {
-            // ZERO-COPY: Process frame directly from mmap buffer without copying
-            let result = capture.process_frame(|data, info| {
-                if let Err(e) = sender.send_frame_zero_copy(data, info) {
-                    tracing::error!("Failed to send frame: {}", e);
+            // Open the capture device and stream it to NDI on the pipeline's
+            // threads, or stream the intercom mic alone
+            STEP_CAPTURE => {
+                if let Some(pipeline) = &mut pipeline {
+                    let events = pipeline.subscribe();
+                    pipeline.stats().register(&stats_registry);
+                    if let Some(recording) = pipeline.recording() {
+                        recording.register(&stats_registry);
+                    }
+                    if let Some(timing) = pipeline.timing() {
+                        timing.register(&stats_registry);
+                    }
+                    for (name, stats) in pipeline.outputs() {
+                        stats.register(&stats_registry, name);
+                    }
+                    pipeline.start()?;
+                    tracing::info!("ZERO-COPY mode: AVX2 SIMD + sync send for lowest latency");
+                    pipeline_events = Some(events);
+                } else if let Some(mic) = mic.take() {
+                    tracing::info!("Audio-only mode: streaming the intercom mic as NDI audio");
+                    let stream =
+                        AudioOnlyStream::start(&config.ndi_name, config.ndi_groups.as_deref(), mic);
+                    stream.stats().register(&stats_registry);
+                    audio_stream = Some(stream);
                 }
-            });
-
-            match result {
-                Ok(()) => {
-                    frame_count += 1;
-
-                    // Report fps every 5 seconds
-                    let elapsed = last_report.elapsed();
-                    if elapsed.as_secs() >= 5 {
-                        let fps = frame_count as f64 / elapsed.as_secs_f64();
-                        tracing::info!("Streaming: {:.1} fps ({} frames)", fps, frame_count);
-                        frame_count = 0;
-                        last_report = std::time::Instant::now();
+            }
+
+            // NDI display (LOW PRIORITY - different core)
+            STEP_DISPLAY => {
+                let config = display_config.take().expect("display starts once");
+                let display_control = display_control.take().expect("display starts once");
+                let splash = splash.take();
+                let running_clone = Arc::clone(&running);
+                let display_idle = Arc::clone(&display_idle);
+                tracing::info!("Starting NDI display for source: {}", config.source_name);
+
+                display_handle = Some(threads::spawn(threads::DISPLAY, move || {
+                    // Apply low priority settings BEFORE doing anything
+                    ndi_display::apply_low_priority();
+
+                    let opened = splash.and_then(SplashHandle::finish);
+                    match ndi_display::run_display_loop(
+                        config,
+                        running_clone,
+                        display_control,
+                        opened,
+                    ) {
+                        Ok(DisplayExit::Idle) => display_idle.notify_one(),
+                        Ok(DisplayExit::Stopped) => {}
+                        Err(e) => tracing::error!("NDI display error: {}", e),
                     }
+                }));
+            }
+
+            // VBAN serial bridge
+            STEP_SERIAL => {
+                let serial = config.serial.as_ref().expect("serial step is configured");
+                let bridge_config = SerialBridgeConfig {
+                    device: serial.device.clone(),
+                    baud: serial.baud,
+                    stream_name: serial.stream.clone(),
+                    channel: serial.channel,
+                    target_host: serial.target.clone(),
+                    target_port: serial.port,
+                    listen: serial.listen.clone(),
+                };
+                match SerialBridge::bind(bridge_config, Arc::clone(&serial_stats)) {
+                    Ok(bridge) => {
+                        serial_stats.register(&stats_registry);
+                        let running_clone = Arc::clone(&running);
+                        serial_handle = Some(threads::spawn(threads::SERIAL, move || {
+                            bridge.run(&running_clone)
+                        }));
+                    }
+                    Err(e) => tracing::error!("Serial bridge disabled: {:#}", e),
                 }
-                Err(e) => {
-                    tracing::error!("Failed to capture frame: {}", e);
-                    std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+
+            // mDNS announcement
+            STEP_MDNS => {
+                let service =
+                    ServiceInfo::new(&config.hostname, &config.ndi_name, config.status_port);
+                let running_clone = Arc::clone(&running);
+                threads::spawn(threads::MDNS, move || {
+                    mdns::run_responder(service, running_clone)
+                });
+            }
+
+            _ => unreachable!("no start for step {}", name),
+        }
+        startup.started(name);
+
+        // READY once the first frame made it through; audio-only and
+        // display-only boxes have no frames
+        if name == STEP_CAPTURE {
+            let timeline = startup.timeline().clone();
+            match pipeline_events.take() {
+                Some(events) => {
+                    let tally = Arc::clone(&intercom_stats);
+                    threads::spawn(threads::PIPELINE_EVENTS, move || {
+                        log_pipeline_events(events, timeline, tally)
+                    });
+                }
+                None => {
+                    timeline.log();
+                    sd_notify::ready();
                 }
             }
         }
-    });
+    }
 
-    // Wait for shutdown signal
+    // Without a display the splash ends here, leaving "ready" on screen
+    startup.finished();
+
+    // Log the stats every interval until the shutdown signal
     tracing::info!("Streaming started. Press Ctrl+C to stop.");
-    signal::ctrl_c().await?;
-    tracing::info!("Shutdown signal received");
+    let mut report_tick =
+        tokio::time::interval(std::time::Duration::from_secs(config.stats_interval_secs));
+    report_tick.tick().await;
+    let mut ticker = StatsTicker::new(Arc::clone(&stats_registry), std::time::Instant::now());
+    // CPU per thread, sampled just before each report
+    let mut thread_cpu = ThreadCpu::new();
+    thread_cpu.sample(std::time::Instant::now(), &stats_registry);
+    // systemd stops the service with SIGTERM
+    let mut terminate = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+    let mut exit_code = 0;
+    loop {
+        tokio::select! {
+            result = signal::ctrl_c() => {
+                result?;
+                break;
+            }
+            _ = terminate.recv() => break,
+            _ = display_idle.notified() => {
+                exit_code = ndi_display::IDLE_EXIT_CODE;
+                break;
+            }
+            _ = report_tick.tick() => {
+                let now = std::time::Instant::now();
+                thread_cpu.sample(now, &stats_registry);
+                notify_status(&ticker.tick(now));
+            }
+        }
+    }
+    if exit_code == ndi_display::IDLE_EXIT_CODE {
+        tracing::info!("Display idle, stopping");
+    } else {
+        tracing::info!("Shutdown signal received");
+    }
 
-    // Signal all threads to stop
+    // Signal all threads to stop, then wait for them in order, but not
+    // past the deadline
     running.store(false, Ordering::Relaxed);
+    let _ = shutdown.send(true);
+    let mut stop = Shutdown::new();
+    if let Some(pipeline) = pipeline.take() {
+        // Dropping the senders takes the NDI source off the network
+        stop = stop.step("capture", move || drop(pipeline));
+    }
+    if let Some(mut stream) = audio_stream.take() {
+        stop = stop.step("audio-only", move || stream.stop());
+    }
+    if !config.state_file.is_empty() {
+        let state_file = config.state_file.clone();
+        stop = stop.step("session", move || {
+            let state = session.finish(
+                frames_captured(),
+                std::time::Instant::now(),
+                std::time::SystemTime::now(),
+            );
+            if let Err(e) = state.save(&state_file) {
+                tracing::warn!("Session totals not saved: {:#}", e);
+            }
+        });
+    }
+    // The intercom sends its offline notice and releases the ALSA device
+    if let Some(handle) = intercom_handle {
+        let runtime = tokio::runtime::Handle::current();
+        stop = stop.step("intercom", move || {
+            let _ = runtime.block_on(handle);
+        });
+    }
+    if let Some(handle) = serial_handle {
+        stop = stop.step("serial", move || {
+            let _ = handle.join();
+        });
+    }
+    // The display blanks the screen on its way out; it may be
+    // sleeping between reconnects, so it goes last
+    if let Some(handle) = display_handle {
+        stop = stop.step("display", move || {
+            let _ = handle.join();
+        });
+    }
 
-    // Wait for capture loop (with timeout)
-    let _ = tokio::time::timeout(std::time::Duration::from_secs(2), capture_handle).await;
+    match tokio::task::spawn_blocking(move || stop.run(shutdown::DEADLINE)).await? {
+        Outcome::Completed => tracing::info!("camera-box stopped"),
+        Outcome::Unfinished(step) => {
+            tracing::warn!(
+                "{} did not stop within {:?}, exiting anyway",
+                step,
+                shutdown::DEADLINE
+            );
+            std::process::exit(exit_code);
+        }
+    }
 
-    // Wait for display thread if running
-    if let Some(handle) = display_handle {
-        let _ = handle.join();
+    if exit_code != 0 {
+        std::process::exit(exit_code);
     }
+    Ok(())
+}
 
-    // Wait for intercom thread if running
-    if let Some(handle) = intercom_handle {
-        let _ = handle.join();
+/// The state the last clean stop left, logged as the previous session; a
+/// missing or unreadable file starts new totals
+fn load_session_state(path: &str) -> SessionState {
+    if path.is_empty() {
+        return SessionState::default();
+    }
+    let state = SessionState::load(path).unwrap_or_else(|e| {
+        tracing::warn!("Previous session unknown: {:#}", e);
+        SessionState::default()
+    });
+    if let Some(summary) = state.summary(std::time::SystemTime::now()) {
+        tracing::info!("{}", summary);
     }
+    state
+}
+
+const STEP_CONTROL: &str = "control";
+const STEP_INTERCOM: &str = "intercom";
+const STEP_CAPTURE: &str = "capture";
+const STEP_DISPLAY: &str = "display";
+const STEP_SERIAL: &str = "serial";
+const STEP_MDNS: &str = "mdns";
 
-    tracing::info!("camera-box stopped");
+/// The configured components and what each needs before it starts
+fn startup_steps(
+    config: &Config,
+    device_path: Option<&str>,
+    display_config: Option<&NdiDisplayConfig>,
+    intercom_config: Option<&intercom::IntercomConfig>,
+) -> Vec<Step> {
+    let mut steps = vec![Step::new(STEP_CONTROL)];
 
+    let mut capture = Step::new(STEP_CAPTURE).requires(Prerequisite::NdiLibrary);
+    match device_path {
+        Some(device_path) => {
+            let node = image_source::image_path(device_path)
+                .or_else(|| file_source::file_path(device_path))
+                .unwrap_or(device_path);
+            // Nothing to wait for when frames come from stdin
+            if node != file_source::STDIN {
+                capture = capture.requires(Prerequisite::DeviceNode(node.into()));
+            }
+            if let Some(audio) = &config.capture.audio {
+                capture = capture.requires(Prerequisite::AlsaCard(audio.device.clone()));
+            }
+        }
+        // Audio-only streams the intercom mic
+        None if config.ndi.audio_only => capture = capture.after(STEP_INTERCOM),
+        // Display-only: nothing to capture
+        None => {}
+    }
+    steps.push(capture.requires(Prerequisite::NetworkRoute));
+
+    if let Some(intercom) = intercom_config {
+        steps.push(
+            Step::new(STEP_INTERCOM)
+                .requires(Prerequisite::AlsaCard(intercom.alsa_device.clone()))
+                .requires(Prerequisite::NetworkRoute),
+        );
+    }
+    // The display and announcement wait for the camera, which matters more
+    if let Some(display) = display_config {
+        steps.push(
+            Step::new(STEP_DISPLAY)
+                .requires(Prerequisite::NdiLibrary)
+                .requires(Prerequisite::NetworkRoute)
+                .requires(Prerequisite::DeviceNode(display.fb_device.clone().into()))
+                .after(STEP_CAPTURE),
+        );
+    }
+    if let Some(serial) = &config.serial {
+        steps.push(
+            Step::new(STEP_SERIAL)
+                .requires(Prerequisite::DeviceNode(serial.device.clone().into()))
+                .requires(Prerequisite::NetworkRoute),
+        );
+    }
+    if config.announce {
+        steps.push(
+            Step::new(STEP_MDNS)
+                .requires(Prerequisite::NetworkRoute)
+                .after(STEP_CAPTURE),
+        );
+    }
+    steps
+}
+
+/// Summarize the stats report in the systemd status line
+fn notify_status(report: &Report) {
+    if let Some(fps) = report.rate("capture.frames") {
+        let recoveries = match report.reading("capture.stall_recoveries").map(|r| r.value) {
+            Some(Value::Counter(count)) => count,
+            _ => 0,
+        };
+        sd_notify::status(&format!(
+            "Streaming {:.1} fps, stall recoveries: {}",
+            fps, recoveries
+        ));
+    } else if let Some(Value::Counter(frames)) =
+        report.reading("audio_only.frames").map(|r| r.value)
+    {
+        sd_notify::status(&format!("Streaming audio only, {} frames", frames));
+    }
+}
+
+/// Toggle debug logging whenever SIGUSR1 arrives
+fn toggle_debug_on_sigusr1(log_level: LogLevel) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+    tokio::spawn(async move {
+        while usr1.recv().await.is_some() {
+            match log_level.toggle_debug() {
+                Ok(filter) => tracing::info!("Log filter: {}", filter),
+                Err(e) => tracing::warn!("Log filter toggle failed: {:#}", e),
+            }
+        }
+    });
     Ok(())
 }
 
+/// Reload the config whenever SIGHUP arrives or the file is written or
+/// replaced, applying what changes at runtime through the control handles
+fn reload_config_on_change(
+    path: &std::path::Path,
+    profile: Option<&str>,
+    config: Config,
+    handles: Arc<ControlHandles>,
+    running: Arc<AtomicBool>,
+) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (reload, mut requests) = tokio::sync::mpsc::unbounded_channel();
+    let mut hup = signal(SignalKind::hangup())?;
+    let on_signal = reload.clone();
+    tokio::spawn(async move {
+        while hup.recv().await.is_some() {
+            let _ = on_signal.send(());
+        }
+    });
+
+    let watched = path.to_path_buf();
+    threads::spawn(threads::CONFIG_WATCH, move || {
+        let on_change = || {
+            let _ = reload.send(());
+        };
+        if let Err(e) = config_watch::watch(&watched, &running, on_change) {
+            tracing::warn!("Config file watch disabled: {:#}", e);
+        }
+    });
+
+    let mut reloader = Reloader::new(path, config).profile(profile.map(str::to_string));
+    tokio::spawn(async move {
+        while requests.recv().await.is_some() {
+            reloader.apply(&handles);
+        }
+    });
+    Ok(())
+}
+
+/// Dump the replay ring whenever SIGUSR2 arrives
+fn dump_replay_on_sigusr2(replay: ReplayHandle) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut usr2 = signal(SignalKind::user_defined2())?;
+    tokio::spawn(async move {
+        while usr2.recv().await.is_some() {
+            let replay = replay.clone();
+            let dump = move || threads::run_named(threads::REPLAY_DUMP, || replay.dump());
+            match tokio::task::spawn_blocking(dump).await {
+                Ok(Err(e)) => tracing::warn!("Replay dump failed: {:#}", e),
+                Err(e) => tracing::warn!("Replay dump failed: {}", e),
+                Ok(Ok(_)) => {}
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Log the capture timing percentiles whenever SIGUSR2 arrives, next to
+/// any replay dump
+fn dump_timing_on_sigusr2(timing: Arc<PipelineTiming>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut usr2 = signal(SignalKind::user_defined2())?;
+    tokio::spawn(async move {
+        while usr2.recv().await.is_some() {
+            timing.dump();
+        }
+    });
+    Ok(())
+}
+
+/// Log pipeline events; the first frame completes the startup `timeline`
+/// and tally changes go to the intercom, whose mute may follow them
+fn log_pipeline_events(
+    events: std::sync::mpsc::Receiver<PipelineEvent>,
+    mut timeline: Timeline,
+    intercom_stats: Arc<intercom::IntercomStats>,
+) {
+    for event in events {
+        match event {
+            PipelineEvent::FirstFrame => {
+                timeline.mark(std::time::Instant::now(), "first frame");
+                timeline.log();
+                sd_notify::ready();
+            }
+            PipelineEvent::FrameRateChanged(rate) => {
+                tracing::info!(
+                    "Capture frame rate: {}/{}",
+                    rate.numerator,
+                    rate.denominator
+                )
+            }
+            PipelineEvent::DeviceLost => tracing::warn!("Capture device lost"),
+            PipelineEvent::DeviceRecovered => tracing::info!("Capture device recovered"),
+            PipelineEvent::NdiConnections(count) => {
+                tracing::info!("NDI receivers connected: {}", count)
+            }
+            PipelineEvent::TallyChanged(tally) => {
+                tracing::info!("Tally: {:?}", tally);
+                intercom_stats.set_tally(tally);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -324,6 +1102,9 @@ mod tests {
         assert!(!args.debug);
         assert!(args.intercom_stream.is_none());
         assert_eq!(args.intercom_target, "strih.lan");
+        assert!(args.command.is_none());
+        assert!(!args.probe);
+        assert!(!args.json);
     }
 
     #[test]
@@ -366,6 +1147,22 @@ mod tests {
     fn test_args_parse_debug_flag() {
         let args = Args::try_parse_from(["camera-box", "--debug"]).unwrap();
         assert!(args.debug);
+        assert!(!args.timing);
+    }
+
+    #[test]
+    fn test_args_parse_timing_flag() {
+        let args = Args::try_parse_from(["camera-box", "--timing"]).unwrap();
+        assert!(args.timing);
+    }
+
+    #[test]
+    fn test_args_parse_probe() {
+        let args = Args::try_parse_from(["camera-box", "--probe", "--json"]).unwrap();
+        assert!(args.probe);
+        assert!(args.json);
+        // --json only applies to the probe report
+        assert!(Args::try_parse_from(["camera-box", "--json"]).is_err());
     }
 
     #[test]
@@ -374,6 +1171,149 @@ mod tests {
         assert_eq!(args.fb_device, "/dev/fb1");
     }
 
+    #[test]
+    fn test_args_parse_ctl() {
+        let args =
+            Args::try_parse_from(["camera-box", "ctl", "display.source", "CAM2 (return)"]).unwrap();
+        match args.command {
+            Some(Command::Ctl { socket, command }) => {
+                assert_eq!(socket, PathBuf::from("/run/camera-box.sock"));
+                assert_eq!(command, vec!["display.source", "CAM2 (return)"]);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        assert!(Args::try_parse_from(["camera-box", "ctl"]).is_err());
+    }
+
+    #[test]
+    fn test_args_parse_config_subcommands() {
+        let args = Args::try_parse_from(["camera-box", "config", "init", "/tmp/c.toml"]).unwrap();
+        match args.command {
+            Some(Command::Config {
+                action: ConfigCommand::Init { path, force },
+            }) => {
+                assert_eq!(path, Some(PathBuf::from("/tmp/c.toml")));
+                assert!(!force);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        let args = Args::try_parse_from(["camera-box", "config", "validate"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Config {
+                action: ConfigCommand::Validate { path: None }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_args_parse_netcfg_apply() {
+        let args = Args::try_parse_from(["camera-box", "netcfg", "apply"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(Command::Netcfg {
+                action: NetcfgCommand::Apply
+            })
+        ));
+        assert!(Args::try_parse_from(["camera-box", "netcfg"]).is_err());
+    }
+
+    #[test]
+    fn test_args_parse_lineup() {
+        let args = Args::try_parse_from(["camera-box", "lineup"]).unwrap();
+        match args.command {
+            Some(Command::Lineup { frequency, level }) => {
+                assert_eq!(frequency, 1000.0);
+                assert_eq!(level, -18.0);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+        let args = Args::try_parse_from([
+            "camera-box",
+            "-c",
+            "/tmp/c.toml",
+            "lineup",
+            "--frequency",
+            "440",
+            "--level",
+            "-20",
+        ])
+        .unwrap();
+        assert_eq!(args.config, PathBuf::from("/tmp/c.toml"));
+        assert!(matches!(
+            args.command,
+            Some(Command::Lineup { frequency, level }) if frequency == 440.0 && level == -20.0
+        ));
+    }
+
+    #[test]
+    fn test_run_config_init_writes_template() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("etc/config.toml");
+        let init = |force| ConfigCommand::Init {
+            path: Some(path.clone()),
+            force,
+        };
+        run_config(&init(false), &path, None).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            config::DEFAULT_CONFIG
+        );
+        // Existing files are only replaced with --force
+        assert!(run_config(&init(false), &path, None).is_err());
+        run_config(&init(true), &path, None).unwrap();
+    }
+
+    #[test]
+    fn test_args_parse_config_show_profile() {
+        let args =
+            Args::try_parse_from(["camera-box", "config", "show", "--profile", "studio"]).unwrap();
+        assert_eq!(args.profile.as_deref(), Some("studio"));
+        assert!(matches!(
+            args.command,
+            Some(Command::Config {
+                action: ConfigCommand::Show { path: None }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_show_config_merges_the_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "ndi_name = \"cam\"\n[capture]\nfps = \"60\"\n[profile.streaming.capture]\nfps = \"30\"\n",
+        )
+        .unwrap();
+
+        let shown = show_config(&path, Some("streaming")).unwrap();
+        assert!(shown.starts_with("# "), "{}", shown);
+        let table: toml::Table = toml::from_str(&shown).unwrap();
+        assert_eq!(table["ndi_name"].as_str(), Some("cam"));
+        assert_eq!(table["capture"]["fps"].as_str(), Some("30"));
+        assert!(!table.contains_key("profile"));
+
+        let base: toml::Table = toml::from_str(&show_config(&path, None).unwrap()).unwrap();
+        assert_eq!(base["capture"]["fps"].as_str(), Some("60"));
+        assert!(show_config(&path, Some("studio")).is_err());
+    }
+
+    #[test]
+    fn test_display_only_startup_steps() {
+        // No camera and no intercom for the capture step to wait on
+        let config = Config {
+            device: config::NO_DEVICE.to_string(),
+            ..Default::default()
+        };
+        let display = NdiDisplayConfig::default();
+        let steps = startup_steps(&config, None, Some(&display), None);
+        let order = startup::resolve(&steps).unwrap();
+        assert!(!order.contains(&STEP_INTERCOM));
+        let position = |name| order.iter().position(|&step| step == name).unwrap();
+        assert!(position(STEP_CAPTURE) < position(STEP_DISPLAY));
+    }
+
     #[test]
     fn test_args_command_valid() {
         // Ensure the command can be built
@@ -0,0 +1,625 @@
+//! Minimal mDNS/DNS-SD responder
+//!
+//! Announces the box as `<hostname>._camera-box._tcp.local` so provisioning
+//! tools can find its management address. Answers PTR/SRV/TXT/A queries on
+//! 224.0.0.251:5353 for our own names only; everything else is ignored.
+
+use anyhow::{bail, Context, Result};
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::net;
+
+/// mDNS multicast group and port (RFC 6762)
+pub const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_PORT: u16 = 5353;
+
+/// DNS-SD service type announced by camera-box
+pub const SERVICE_TYPE: &str = "_camera-box._tcp.local";
+const SERVICES_META: &str = "_services._dns-sd._udp.local";
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_SRV: u16 = 33;
+pub const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Class bit: cache-flush in answers, unicast-response requested in questions
+const CLASS_TOP_BIT: u16 = 0x8000;
+
+const FLAGS_RESPONSE: u16 = 0x8400; // QR + authoritative answer
+const HEADER_SIZE: usize = 12;
+const RECORD_TTL: u32 = 120;
+const MAX_NAME_POINTERS: usize = 16;
+
+// =============================================================================
+// Wire Format
+// =============================================================================
+
+/// Append a dotted name as DNS labels (no compression)
+pub fn encode_name(name: &str, out: &mut Vec<u8>) {
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+    {
+        let bytes = &label.as_bytes()[..label.len().min(63)];
+        out.push(bytes.len() as u8);
+        out.extend_from_slice(bytes);
+    }
+    out.push(0);
+}
+
+/// Read a (possibly compressed) name at `offset`. Returns the name and the
+/// offset just past it in the original position.
+pub fn decode_name(packet: &[u8], mut offset: usize) -> Result<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut pointers = 0;
+    loop {
+        let Some(&len) = packet.get(offset) else {
+            bail!("Truncated name");
+        };
+        match len {
+            0 => {
+                let end = end.unwrap_or(offset + 1);
+                return Ok((labels.join("."), end));
+            }
+            l if l & 0xC0 == 0xC0 => {
+                let Some(&low) = packet.get(offset + 1) else {
+                    bail!("Truncated name pointer");
+                };
+                pointers += 1;
+                if pointers > MAX_NAME_POINTERS {
+                    bail!("Name pointer loop");
+                }
+                end.get_or_insert(offset + 2);
+                offset = (((l & 0x3F) as usize) << 8) | low as usize;
+            }
+            l if l & 0xC0 == 0 => {
+                let l = l as usize;
+                let Some(label) = packet.get(offset + 1..offset + 1 + l) else {
+                    bail!("Truncated label");
+                };
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + l;
+            }
+            _ => bail!("Invalid label type"),
+        }
+    }
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Result<u16> {
+    match packet.get(offset..offset + 2) {
+        Some(b) => Ok(u16::from_be_bytes([b[0], b[1]])),
+        None => bail!("Truncated packet"),
+    }
+}
+
+/// A question from an mDNS query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    pub name: String,
+    pub qtype: u16,
+    /// QU bit: the querier asked for a unicast reply
+    pub unicast: bool,
+}
+
+/// Parse an mDNS query, returning its id and questions. Responses yield no
+/// questions.
+pub fn parse_query(packet: &[u8]) -> Result<(u16, Vec<Question>)> {
+    if packet.len() < HEADER_SIZE {
+        bail!("Packet too short");
+    }
+    let id = read_u16(packet, 0)?;
+    let flags = read_u16(packet, 2)?;
+    if flags & 0x8000 != 0 {
+        return Ok((id, Vec::new()));
+    }
+    let count = read_u16(packet, 4)?;
+    let mut offset = HEADER_SIZE;
+    let mut questions = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (name, next) = decode_name(packet, offset)?;
+        let qtype = read_u16(packet, next)?;
+        let qclass = read_u16(packet, next + 2)?;
+        offset = next + 4;
+        questions.push(Question {
+            name,
+            qtype,
+            unicast: qclass & CLASS_TOP_BIT != 0,
+        });
+    }
+    Ok((id, questions))
+}
+
+/// A resource record to send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub rtype: u16,
+    /// Unique record: receivers replace cached entries (not for shared PTRs)
+    pub cache_flush: bool,
+    pub ttl: u32,
+    pub rdata: Vec<u8>,
+}
+
+impl Record {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        encode_name(&self.name, out);
+        out.extend_from_slice(&self.rtype.to_be_bytes());
+        let class = if self.cache_flush {
+            CLASS_IN | CLASS_TOP_BIT
+        } else {
+            CLASS_IN
+        };
+        out.extend_from_slice(&class.to_be_bytes());
+        out.extend_from_slice(&self.ttl.to_be_bytes());
+        out.extend_from_slice(&(self.rdata.len() as u16).to_be_bytes());
+        out.extend_from_slice(&self.rdata);
+    }
+}
+
+/// Encode a response. Legacy unicast replies echo the questions.
+pub fn encode_response(
+    id: u16,
+    questions: &[Question],
+    answers: &[Record],
+    additionals: &[Record],
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(512);
+    out.extend_from_slice(&id.to_be_bytes());
+    out.extend_from_slice(&FLAGS_RESPONSE.to_be_bytes());
+    out.extend_from_slice(&(questions.len() as u16).to_be_bytes());
+    out.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+    out.extend_from_slice(&(additionals.len() as u16).to_be_bytes());
+    for q in questions {
+        encode_name(&q.name, &mut out);
+        out.extend_from_slice(&q.qtype.to_be_bytes());
+        out.extend_from_slice(&CLASS_IN.to_be_bytes());
+    }
+    for record in answers.iter().chain(additionals) {
+        record.encode(&mut out);
+    }
+    out
+}
+
+// =============================================================================
+// Service Records
+// =============================================================================
+
+/// The announced service
+#[derive(Debug, Clone)]
+pub struct ServiceInfo {
+    pub hostname: String,
+    pub port: u16,
+    pub txt: Vec<(String, String)>,
+}
+
+impl ServiceInfo {
+    /// camera-box service with hostname, version, NDI name and status port TXT keys
+    pub fn new(hostname: &str, ndi_name: &str, status_port: u16) -> Self {
+        Self {
+            hostname: hostname.to_string(),
+            port: status_port,
+            txt: vec![
+                ("hostname".to_string(), hostname.to_string()),
+                ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+                ("ndi_name".to_string(), ndi_name.to_string()),
+                ("status_port".to_string(), status_port.to_string()),
+            ],
+        }
+    }
+
+    pub fn instance_name(&self) -> String {
+        format!("{}.{}", self.hostname, SERVICE_TYPE)
+    }
+
+    pub fn host_name(&self) -> String {
+        format!("{}.local", self.hostname)
+    }
+
+    fn ptr_record(&self) -> Record {
+        let mut rdata = Vec::new();
+        encode_name(&self.instance_name(), &mut rdata);
+        Record {
+            name: SERVICE_TYPE.to_string(),
+            rtype: TYPE_PTR,
+            cache_flush: false,
+            ttl: RECORD_TTL,
+            rdata,
+        }
+    }
+
+    fn meta_ptr_record(&self) -> Record {
+        let mut rdata = Vec::new();
+        encode_name(SERVICE_TYPE, &mut rdata);
+        Record {
+            name: SERVICES_META.to_string(),
+            rtype: TYPE_PTR,
+            cache_flush: false,
+            ttl: RECORD_TTL,
+            rdata,
+        }
+    }
+
+    fn srv_record(&self) -> Record {
+        let mut rdata = Vec::new();
+        rdata.extend_from_slice(&0u16.to_be_bytes()); // priority
+        rdata.extend_from_slice(&0u16.to_be_bytes()); // weight
+        rdata.extend_from_slice(&self.port.to_be_bytes());
+        encode_name(&self.host_name(), &mut rdata);
+        Record {
+            name: self.instance_name(),
+            rtype: TYPE_SRV,
+            cache_flush: true,
+            ttl: RECORD_TTL,
+            rdata,
+        }
+    }
+
+    fn txt_record(&self) -> Record {
+        let mut rdata = Vec::new();
+        for (key, value) in &self.txt {
+            let entry = format!("{}={}", key, value);
+            let bytes = &entry.as_bytes()[..entry.len().min(255)];
+            rdata.push(bytes.len() as u8);
+            rdata.extend_from_slice(bytes);
+        }
+        if rdata.is_empty() {
+            rdata.push(0);
+        }
+        Record {
+            name: self.instance_name(),
+            rtype: TYPE_TXT,
+            cache_flush: true,
+            ttl: RECORD_TTL,
+            rdata,
+        }
+    }
+
+    fn a_record(&self, address: Ipv4Addr) -> Record {
+        Record {
+            name: self.host_name(),
+            rtype: TYPE_A,
+            cache_flush: true,
+            ttl: RECORD_TTL,
+            rdata: address.octets().to_vec(),
+        }
+    }
+
+    /// Full announcement: PTR answer with SRV, TXT and A as additionals
+    pub fn announcement(&self, address: Option<Ipv4Addr>) -> (Vec<Record>, Vec<Record>) {
+        let mut additionals = vec![self.srv_record(), self.txt_record()];
+        additionals.extend(address.map(|a| self.a_record(a)));
+        (vec![self.ptr_record()], additionals)
+    }
+
+    /// Records answering `questions`, or None if none of them concern us
+    pub fn answer(
+        &self,
+        questions: &[Question],
+        address: Option<Ipv4Addr>,
+    ) -> Option<(Vec<Record>, Vec<Record>)> {
+        let instance = self.instance_name();
+        let host = self.host_name();
+        let mut answers = Vec::new();
+        let mut wants_service = false;
+        for q in questions {
+            let is = |name: &str| q.name.eq_ignore_ascii_case(name);
+            let any = q.qtype == TYPE_ANY;
+            if is(SERVICE_TYPE) && (q.qtype == TYPE_PTR || any) {
+                answers.push(self.ptr_record());
+                wants_service = true;
+            } else if is(SERVICES_META) && (q.qtype == TYPE_PTR || any) {
+                answers.push(self.meta_ptr_record());
+            } else if is(&instance) {
+                if q.qtype == TYPE_SRV || any {
+                    answers.push(self.srv_record());
+                    wants_service = true;
+                }
+                if q.qtype == TYPE_TXT || any {
+                    answers.push(self.txt_record());
+                }
+            } else if is(&host) && (q.qtype == TYPE_A || any) {
+                answers.extend(address.map(|a| self.a_record(a)));
+            }
+        }
+        if answers.is_empty() {
+            return None;
+        }
+
+        // Save the querier a round trip: include what it will ask next
+        let mut additionals = Vec::new();
+        if wants_service {
+            for record in [self.srv_record(), self.txt_record()] {
+                if !answers.contains(&record) {
+                    additionals.push(record);
+                }
+            }
+            additionals.extend(address.map(|a| self.a_record(a)));
+        }
+        Some((answers, additionals))
+    }
+}
+
+/// IPv4 address the host would use to reach the mDNS group
+pub fn local_ipv4() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((MDNS_ADDR, MDNS_PORT)).ok()?;
+    match socket.local_addr().ok()? {
+        SocketAddr::V4(addr) if !addr.ip().is_unspecified() => Some(*addr.ip()),
+        _ => None,
+    }
+}
+
+// =============================================================================
+// Responder
+// =============================================================================
+
+/// mDNS responder socket bound to the group port
+pub struct MdnsResponder {
+    socket: UdpSocket,
+    port: u16,
+    service: ServiceInfo,
+}
+
+impl MdnsResponder {
+    /// Join the mDNS group on `interface` (UNSPECIFIED = default) at `port`
+    pub fn bind(service: ServiceInfo, port: u16, interface: Ipv4Addr) -> Result<Self> {
+        let socket = net::bind_udp_reuse(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port))
+            .context("Failed to bind mDNS socket")?;
+        socket
+            .join_multicast_v4(&MDNS_ADDR, &interface)
+            .context("Failed to join mDNS multicast group")?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.set_multicast_ttl_v4(255)?;
+        socket.set_read_timeout(Some(Duration::from_millis(500)))?;
+        let port = socket.local_addr()?.port();
+        Ok(Self {
+            socket,
+            port,
+            service,
+        })
+    }
+
+    /// Send an unsolicited announcement to the group
+    pub fn announce(&self) -> Result<()> {
+        let (answers, additionals) = self.service.announcement(local_ipv4());
+        let packet = encode_response(0, &[], &answers, &additionals);
+        self.socket.send_to(&packet, (MDNS_ADDR, self.port))?;
+        Ok(())
+    }
+
+    /// Wait for one packet and answer it if it is a query for our names
+    pub fn handle_one(&self) -> Result<()> {
+        let mut buf = [0u8; 1500];
+        let (len, src) = match self.socket.recv_from(&mut buf) {
+            Ok(r) => r,
+            Err(e)
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                return Ok(())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let Ok((id, questions)) = parse_query(&buf[..len]) else {
+            return Ok(());
+        };
+        let Some((answers, additionals)) = self.service.answer(&questions, local_ipv4()) else {
+            return Ok(());
+        };
+
+        // Legacy unicast (querier not on the mDNS port) gets a direct reply
+        // echoing the id and questions; QU questions get a direct reply too
+        let legacy = src.port() != self.port;
+        let packet = if legacy {
+            encode_response(id, &questions, &answers, &additionals)
+        } else {
+            encode_response(0, &[], &answers, &additionals)
+        };
+        if legacy || questions.iter().any(|q| q.unicast) {
+            self.socket.send_to(&packet, src)?;
+        } else {
+            self.socket.send_to(&packet, (MDNS_ADDR, self.port))?;
+        }
+        Ok(())
+    }
+}
+
+/// Announce the service and answer queries until `running` is cleared
+pub fn run_responder(service: ServiceInfo, running: Arc<AtomicBool>) {
+    let instance = service.instance_name();
+    let responder = match MdnsResponder::bind(service, MDNS_PORT, Ipv4Addr::UNSPECIFIED) {
+        Ok(r) => r,
+        Err(e) => {
+            tracing::warn!("mDNS announcement disabled: {:#}", e);
+            return;
+        }
+    };
+    tracing::info!("mDNS: announcing {}", instance);
+
+    // RFC 6762 8.3: announce at least twice, one second apart
+    for _ in 0..2 {
+        if let Err(e) = responder.announce() {
+            tracing::warn!("mDNS announcement failed: {}", e);
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+
+    while running.load(Ordering::Relaxed) {
+        if let Err(e) = responder.handle_one() {
+            tracing::debug!("mDNS: {}", e);
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> ServiceInfo {
+        ServiceInfo::new("CAM1", "usb", 8080)
+    }
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        encode_name(name, &mut packet);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_encode_name() {
+        let mut out = Vec::new();
+        encode_name("CAM1._camera-box._tcp.local", &mut out);
+        assert_eq!(out, b"\x04CAM1\x0b_camera-box\x04_tcp\x05local\x00");
+    }
+
+    #[test]
+    fn test_decode_name_roundtrip_and_pointer() {
+        let mut packet = vec![0u8; HEADER_SIZE];
+        encode_name("_tcp.local", &mut packet);
+        let pointer_at = packet.len();
+        packet.extend_from_slice(b"\x04CAM1\xC0\x0C");
+
+        let (name, next) = decode_name(&packet, HEADER_SIZE).unwrap();
+        assert_eq!(name, "_tcp.local");
+        assert_eq!(next, pointer_at);
+
+        let (name, next) = decode_name(&packet, pointer_at).unwrap();
+        assert_eq!(name, "CAM1._tcp.local");
+        assert_eq!(next, packet.len());
+    }
+
+    #[test]
+    fn test_decode_name_rejects_pointer_loop() {
+        let packet = [0u8; HEADER_SIZE]
+            .iter()
+            .copied()
+            .chain([0xC0, 0x0C])
+            .collect::<Vec<_>>();
+        assert!(decode_name(&packet, HEADER_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let (id, questions) = parse_query(&query(SERVICE_TYPE, TYPE_PTR)).unwrap();
+        assert_eq!(id, 0x1234);
+        assert_eq!(
+            questions,
+            vec![Question {
+                name: SERVICE_TYPE.to_string(),
+                qtype: TYPE_PTR,
+                unicast: false,
+            }]
+        );
+
+        // Responses carry no questions for us
+        let response = encode_response(0, &[], &service().announcement(None).0, &[]);
+        assert!(parse_query(&response).unwrap().1.is_empty());
+        assert!(parse_query(&[0; 4]).is_err());
+    }
+
+    #[test]
+    fn test_srv_and_txt_rdata() {
+        let service = service();
+        let srv = service.srv_record();
+        assert_eq!(&srv.rdata[..6], &[0, 0, 0, 0, 0x1F, 0x90]);
+        assert_eq!(&srv.rdata[6..], b"\x04CAM1\x05local\x00");
+        assert!(srv.cache_flush);
+
+        let txt = service.txt_record();
+        let mut expected = Vec::new();
+        for entry in [
+            "hostname=CAM1".to_string(),
+            format!("version={}", env!("CARGO_PKG_VERSION")),
+            "ndi_name=usb".to_string(),
+            "status_port=8080".to_string(),
+        ] {
+            expected.push(entry.len() as u8);
+            expected.extend_from_slice(entry.as_bytes());
+        }
+        assert_eq!(txt.rdata, expected);
+    }
+
+    #[test]
+    fn test_record_encoding() {
+        let record = service().a_record(Ipv4Addr::new(10, 0, 0, 5));
+        let mut out = Vec::new();
+        record.encode(&mut out);
+        let mut expected = b"\x04CAM1\x05local\x00".to_vec();
+        expected.extend_from_slice(&[0, 1, 0x80, 1, 0, 0, 0, 120, 0, 4, 10, 0, 0, 5]);
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_answer_ptr_includes_additionals() {
+        let service = service();
+        let (_, questions) = parse_query(&query("_CAMERA-BOX._tcp.local", TYPE_PTR)).unwrap();
+        let (answers, additionals) = service
+            .answer(&questions, Some(Ipv4Addr::new(10, 0, 0, 5)))
+            .unwrap();
+        assert_eq!(answers, vec![service.ptr_record()]);
+        let types: Vec<u16> = additionals.iter().map(|r| r.rtype).collect();
+        assert_eq!(types, vec![TYPE_SRV, TYPE_TXT, TYPE_A]);
+    }
+
+    #[test]
+    fn test_answer_ignores_other_names() {
+        let service = service();
+        let (_, questions) = parse_query(&query("_http._tcp.local", TYPE_PTR)).unwrap();
+        assert!(service.answer(&questions, None).is_none());
+        let (_, questions) = parse_query(&query("CAM2.local", TYPE_A)).unwrap();
+        assert!(service.answer(&questions, None).is_none());
+    }
+
+    #[test]
+    fn test_answer_host_and_meta_queries() {
+        let service = service();
+        let (_, questions) = parse_query(&query("cam1.local", TYPE_A)).unwrap();
+        let (answers, additionals) = service
+            .answer(&questions, Some(Ipv4Addr::new(10, 0, 0, 5)))
+            .unwrap();
+        assert_eq!(answers[0].rdata, vec![10, 0, 0, 5]);
+        assert!(additionals.is_empty());
+
+        let (_, questions) = parse_query(&query(SERVICES_META, TYPE_PTR)).unwrap();
+        let (answers, _) = service.answer(&questions, None).unwrap();
+        assert_eq!(answers, vec![service.meta_ptr_record()]);
+    }
+
+    #[test]
+    #[ignore = "needs multicast routed over loopback; run with --ignored"]
+    fn test_loopback_multicast_query() {
+        let responder = MdnsResponder::bind(service(), 0, Ipv4Addr::LOCALHOST).unwrap();
+        let client = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        client.set_multicast_loop_v4(true).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        client
+            .send_to(&query(SERVICE_TYPE, TYPE_PTR), (MDNS_ADDR, responder.port))
+            .unwrap();
+
+        responder.handle_one().unwrap();
+
+        let mut buf = [0u8; 1500];
+        let (len, _) = client
+            .recv_from(&mut buf)
+            .expect("no mDNS response received");
+        let response = &buf[..len];
+        assert_eq!(&response[..2], &[0x12, 0x34]);
+        assert_eq!(read_u16(response, 2).unwrap(), FLAGS_RESPONSE);
+        assert_eq!(read_u16(response, 4).unwrap(), 1); // question echoed
+        assert_eq!(read_u16(response, 6).unwrap(), 1); // PTR answer
+        let instance = b"\x04CAM1\x0b_camera-box\x04_tcp\x05local\x00";
+        assert!(response.windows(instance.len()).any(|w| w == instance));
+    }
+}
@@ -3,12 +3,28 @@ use libloading::Library;
 use std::ffi::{c_char, c_int, c_void, CStr, CString};
 use std::path::Path;
 use std::ptr;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard};
+use std::time::{Duration, Instant};
 
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
-use crate::capture::{Frame, FrameRate};
+use crate::aspect::AspectRatio;
+use crate::av_clock::NdiClock;
+use crate::capture::{Frame, FrameInfo, FrameRate};
+use crate::capture_audio::interleaved_to_planar_f32;
+use crate::color_range::{uyvy_full_to_limited, ColorRange};
+use crate::deinterlace::{deinterlace_yuyv, DeinterlaceMode, FieldOrder};
+use crate::display::scale_uyvy_nearest;
+use crate::intercom::Tally;
+use crate::jpeg::jpeg_dimensions;
+use crate::processing::{FrameAction, SharedProcessors};
+use crate::stats::{Component, StatsRegistry};
+use crate::zero_copy::HeldFrame;
+
+#[cfg(feature = "ndi_stub")]
+pub mod stub;
 
 // NDI SDK type definitions (minimal subset for video sending and receiving)
 #[repr(C)]
@@ -19,6 +35,13 @@ struct NDIlib_send_create_t {
     clock_audio: bool,
 }
 
+#[repr(C)]
+#[derive(Default)]
+struct NDIlib_tally_t {
+    on_program: bool,
+    on_preview: bool,
+}
+
 #[repr(C)]
 struct NDIlib_video_frame_v2_t {
     xres: c_int,
@@ -35,15 +58,44 @@ struct NDIlib_video_frame_v2_t {
     timestamp: i64,
 }
 
+#[repr(C)]
+struct NDIlib_audio_frame_v2_t {
+    sample_rate: c_int,
+    no_channels: c_int,
+    no_samples: c_int,
+    timecode: i64,
+    p_data: *const f32,
+    channel_stride_in_bytes: c_int,
+    p_metadata: *const c_char,
+    timestamp: i64,
+}
+
 // FourCC codes
 const NDILIBD_FOURCC_UYVY: u32 = u32::from_le_bytes([b'U', b'Y', b'V', b'Y']);
 #[allow(dead_code)]
 const NDILIBD_FOURCC_BGRA: u32 = u32::from_le_bytes([b'B', b'G', b'R', b'A']);
 #[allow(dead_code)]
 const NDILIBD_FOURCC_BGRX: u32 = u32::from_le_bytes([b'B', b'G', b'R', b'X']);
+const NDILIBD_FOURCC_P216: u32 = u32::from_le_bytes([b'P', b'2', b'1', b'6']);
+const NDILIBD_FOURCC_PA16: u32 = u32::from_le_bytes([b'P', b'A', b'1', b'6']);
+
+/// Bytes of a received frame: `stride` * `height` per plane. P216 has a
+/// chroma plane after the luma, PA16 an alpha plane after that.
+fn received_frame_size(fourcc: u32, stride: usize, height: usize) -> usize {
+    let planes = match fourcc {
+        NDILIBD_FOURCC_P216 => 2,
+        NDILIBD_FOURCC_PA16 => 3,
+        _ => 1,
+    };
+    stride * height * planes
+}
+
+// Timecode asking NDI to stamp the frame with the send time
+const NDILIB_SEND_TIMECODE_SYNTHESIZE: i64 = i64::MAX;
 
 // Frame format types
 const NDILIB_FRAME_FORMAT_TYPE_PROGRESSIVE: c_int = 1;
+const NDILIB_FRAME_FORMAT_TYPE_INTERLEAVED: c_int = 0;
 
 // NDI receiver types
 #[repr(C)]
@@ -86,19 +138,15 @@ pub struct NDIlib_video_frame_v2_recv_t {
 }
 
 // Frame types returned by recv_capture
-#[allow(dead_code)]
 const NDILIB_FRAME_TYPE_NONE: c_int = 0;
 const NDILIB_FRAME_TYPE_VIDEO: c_int = 1;
-#[allow(dead_code)]
 const NDILIB_FRAME_TYPE_AUDIO: c_int = 2;
-#[allow(dead_code)]
 const NDILIB_FRAME_TYPE_METADATA: c_int = 3;
-#[allow(dead_code)]
 const NDILIB_FRAME_TYPE_ERROR: c_int = 4;
+const NDILIB_FRAME_TYPE_STATUS_CHANGE: c_int = 100;
 
 // Color formats
 const NDILIB_RECV_COLOR_FORMAT_UYVY_BGRA: c_int = 0;
-#[allow(dead_code)]
 const NDILIB_RECV_COLOR_FORMAT_BGRX_BGRA: c_int = 1;
 
 // Bandwidth
@@ -109,6 +157,8 @@ type NDIlib_initialize_fn = unsafe extern "C" fn() -> bool;
 #[allow(non_camel_case_types)]
 type NDIlib_destroy_fn = unsafe extern "C" fn();
 #[allow(non_camel_case_types)]
+type NDIlib_version_fn = unsafe extern "C" fn() -> *const c_char;
+#[allow(non_camel_case_types)]
 type NDIlib_send_create_fn = unsafe extern "C" fn(*const NDIlib_send_create_t) -> *mut c_void;
 #[allow(non_camel_case_types)]
 type NDIlib_send_destroy_fn = unsafe extern "C" fn(*mut c_void);
@@ -118,6 +168,13 @@ type NDIlib_send_send_video_v2_fn =
 #[allow(non_camel_case_types)]
 type NDIlib_send_send_video_async_v2_fn =
     unsafe extern "C" fn(*mut c_void, *const NDIlib_video_frame_v2_t);
+#[allow(non_camel_case_types)]
+type NDIlib_send_send_audio_v2_fn =
+    unsafe extern "C" fn(*mut c_void, *const NDIlib_audio_frame_v2_t);
+#[allow(non_camel_case_types)]
+type NDIlib_send_get_no_connections_fn = unsafe extern "C" fn(*mut c_void, u32) -> c_int;
+#[allow(non_camel_case_types)]
+type NDIlib_send_get_tally_fn = unsafe extern "C" fn(*mut c_void, *mut NDIlib_tally_t, u32) -> bool;
 
 // Receiver function types
 #[allow(non_camel_case_types)]
@@ -147,155 +204,364 @@ type NDIlib_recv_free_video_v2_fn =
 
 /// NDI library wrapper with dynamic loading
 struct NdiLib {
-    _library: Library,
+    /// None for the in-process stub
+    _library: Option<Library>,
     destroy: NDIlib_destroy_fn,
     // Sender functions
     send_create: NDIlib_send_create_fn,
     send_destroy: NDIlib_send_destroy_fn,
     send_send_video_v2: NDIlib_send_send_video_v2_fn,
-    #[allow(dead_code)] // Keep for potential future async mode
-    send_send_video_async_v2: NDIlib_send_send_video_async_v2_fn,
+    send_send_audio_v2: NDIlib_send_send_audio_v2_fn,
+    send_get_no_connections: NDIlib_send_get_no_connections_fn,
+    // Optional sender functions, see NdiCapabilities
+    send_send_video_async_v2: Option<NDIlib_send_send_video_async_v2_fn>,
+    send_get_tally: Option<NDIlib_send_get_tally_fn>,
     // Receiver functions
     find_create_v2: NDIlib_find_create_v2_fn,
     find_destroy: NDIlib_find_destroy_fn,
     find_wait_for_sources: NDIlib_find_wait_for_sources_fn,
     find_get_current_sources: NDIlib_find_get_current_sources_fn,
-    recv_create_v3: NDIlib_recv_create_v3_fn,
     recv_destroy: NDIlib_recv_destroy_fn,
-    recv_capture_v3: NDIlib_recv_capture_v3_fn,
     recv_free_video_v2: NDIlib_recv_free_video_v2_fn,
+    // Optional receiver functions, see NdiCapabilities
+    recv_v3: Option<RecvV3>,
 }
 
-impl NdiLib {
-    fn load() -> Result<Self> {
-        // Search paths for NDI library
-        let search_paths = [
-            // Environment variable paths
-            std::env::var("NDI_RUNTIME_DIR_V6").ok(),
-            std::env::var("NDI_RUNTIME_DIR_V5").ok(),
-            std::env::var("NDI_RUNTIME_DIR").ok(),
-            // Standard paths
-            Some("/usr/lib/ndi".to_string()),
-            Some("/usr/local/lib/ndi".to_string()),
-            Some("/opt/ndi/lib".to_string()),
-            // Current directory
-            Some(".".to_string()),
-        ];
+/// The v3 receiver API, which the display needs
+#[derive(Clone, Copy)]
+struct RecvV3 {
+    create: NDIlib_recv_create_v3_fn,
+    capture: NDIlib_recv_capture_v3_fn,
+}
 
-        let lib_names = ["libndi.so.6", "libndi.so.5", "libndi.so"];
-
-        let mut last_error = None;
-
-        for path in search_paths.iter().flatten() {
-            for lib_name in &lib_names {
-                let lib_path = Path::new(path).join(lib_name);
-                if lib_path.exists() {
-                    tracing::debug!("Trying NDI library: {:?}", lib_path);
-                    match unsafe { Library::new(&lib_path) } {
-                        Ok(lib) => {
-                            return Self::init_from_library(lib).with_context(|| {
-                                format!("Failed to initialize NDI from {:?}", lib_path)
-                            });
-                        }
-                        Err(e) => {
-                            last_error = Some(e);
-                        }
+/// Open the NDI runtime without initializing it, returning it with the
+/// name it was found under
+fn open_library() -> Result<(Library, String), NdiError> {
+    // Search paths for NDI library
+    let search_paths = [
+        // Environment variable paths
+        std::env::var("NDI_RUNTIME_DIR_V6").ok(),
+        std::env::var("NDI_RUNTIME_DIR_V5").ok(),
+        std::env::var("NDI_RUNTIME_DIR").ok(),
+        // Standard paths
+        Some("/usr/lib/ndi".to_string()),
+        Some("/usr/local/lib/ndi".to_string()),
+        Some("/opt/ndi/lib".to_string()),
+        // Current directory
+        Some(".".to_string()),
+    ];
+
+    let lib_names = ["libndi.so.6", "libndi.so.5", "libndi.so"];
+
+    let mut last_error = None;
+
+    for path in search_paths.iter().flatten() {
+        for lib_name in &lib_names {
+            let lib_path = Path::new(path).join(lib_name);
+            if lib_path.exists() {
+                tracing::debug!("Trying NDI library: {:?}", lib_path);
+                match unsafe { Library::new(&lib_path) } {
+                    Ok(lib) => {
+                        return Ok((lib, lib_path.to_string_lossy().into_owned()));
+                    }
+                    Err(e) => {
+                        last_error = Some(e);
                     }
                 }
             }
         }
+    }
 
-        // Try system-wide library search
-        for lib_name in &lib_names {
-            tracing::debug!("Trying system NDI library: {}", lib_name);
-            match unsafe { Library::new(*lib_name) } {
-                Ok(lib) => {
-                    return Self::init_from_library(lib)
-                        .context("Failed to initialize NDI from system library");
-                }
-                Err(e) => {
-                    last_error = Some(e);
-                }
+    // Try system-wide library search
+    for lib_name in &lib_names {
+        tracing::debug!("Trying system NDI library: {}", lib_name);
+        match unsafe { Library::new(*lib_name) } {
+            Ok(lib) => {
+                return Ok((lib, lib_name.to_string()));
+            }
+            Err(e) => {
+                last_error = Some(e);
             }
         }
+    }
+
+    Err(last_error.map_or(NdiError::LibraryNotFound, NdiError::LibraryLoad))
+}
+
+/// Whether the NDI runtime can be loaded, checked at startup before
+/// anything uses it
+pub fn library_loadable() -> Result<(), NdiError> {
+    if cfg!(feature = "ndi_stub") {
+        return Ok(());
+    }
+    open_library().map(drop)
+}
+
+/// Optional parts of the NDI SDK; older runtimes lack some of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NdiFeature {
+    AsyncSend,
+    RecvV3,
+    AudioV3,
+    Tally,
+}
+
+impl NdiFeature {
+    pub const ALL: [NdiFeature; 4] = [
+        NdiFeature::AsyncSend,
+        NdiFeature::RecvV3,
+        NdiFeature::AudioV3,
+        NdiFeature::Tally,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NdiFeature::AsyncSend => "async_send",
+            NdiFeature::RecvV3 => "recv_v3",
+            NdiFeature::AudioV3 => "audio_v3",
+            NdiFeature::Tally => "tally",
+        }
+    }
+
+    /// The exported function that provides the feature
+    pub fn symbol(self) -> &'static str {
+        match self {
+            NdiFeature::AsyncSend => "NDIlib_send_send_video_async_v2",
+            NdiFeature::RecvV3 => "NDIlib_recv_capture_v3",
+            NdiFeature::AudioV3 => "NDIlib_send_send_audio_v3",
+            NdiFeature::Tally => "NDIlib_send_get_tally",
+        }
+    }
+
+    /// What changes without the feature, if anything uses it
+    pub fn fallback(self) -> Option<&'static str> {
+        match self {
+            NdiFeature::AsyncSend => Some("zero-copy sends fall back to copying sends"),
+            NdiFeature::RecvV3 => Some("the NDI display cannot connect"),
+            NdiFeature::AudioV3 => None,
+            NdiFeature::Tally => Some("tally stays off"),
+        }
+    }
+}
+
+/// Version and optional features of the loaded NDI runtime
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NdiCapabilities {
+    /// `NDIlib_version()`, if the runtime exports it
+    pub version: Option<String>,
+    /// Major version parsed from `version`
+    pub major: Option<u32>,
+    pub async_send: bool,
+    pub recv_v3: bool,
+    pub audio_v3: bool,
+    pub tally: bool,
+}
+
+impl NdiCapabilities {
+    pub fn supports(&self, feature: NdiFeature) -> bool {
+        match feature {
+            NdiFeature::AsyncSend => self.async_send,
+            NdiFeature::RecvV3 => self.recv_v3,
+            NdiFeature::AudioV3 => self.audio_v3,
+            NdiFeature::Tally => self.tally,
+        }
+    }
+
+    /// Features the runtime lacks that something here depends on
+    pub fn degraded(&self) -> Vec<NdiFeature> {
+        NdiFeature::ALL
+            .into_iter()
+            .filter(|&feature| !self.supports(feature) && feature.fallback().is_some())
+            .collect()
+    }
+
+    /// The dotted version number in `version`, e.g. "6.0.1.0"
+    pub fn version_number(&self) -> Option<&str> {
+        self.version
+            .as_deref()
+            .and_then(|version| version.split_whitespace().rev().find(|t| is_version(t)))
+    }
+
+    /// Log the version and one warning per degraded feature
+    fn log(&self, library: &str) {
+        tracing::info!(
+            "NDI library {}: {}",
+            library,
+            self.version.as_deref().unwrap_or("version unknown")
+        );
+        for feature in self.degraded() {
+            tracing::warn!(
+                "NDI runtime lacks {} ({}): {}",
+                feature.name(),
+                feature.symbol(),
+                feature.fallback().unwrap_or_default()
+            );
+        }
+    }
+}
 
-        Err(last_error
-            .map(|e| anyhow::anyhow!("Failed to load NDI library: {}", e))
-            .unwrap_or_else(|| anyhow::anyhow!("NDI library not found")))
+/// Whether `token` is a dotted version number such as "6.0.1.0"
+fn is_version(token: &str) -> bool {
+    let mut parts = token.split('.');
+    let all_digits = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    parts.next().is_some_and(all_digits) && {
+        let rest: Vec<&str> = parts.collect();
+        !rest.is_empty() && rest.into_iter().all(all_digits)
     }
+}
 
-    fn init_from_library(library: Library) -> Result<Self> {
+/// Major version from an `NDIlib_version()` string such as
+/// "NDI SDK LINUX 12:21:33 Jun 25 2024 6.0.1.0"
+pub fn parse_major_version(version: &str) -> Option<u32> {
+    version
+        .split_whitespace()
+        .rev()
+        .find(|token| is_version(token))
+        .and_then(|token| token.split('.').next()?.parse().ok())
+}
+
+/// Capabilities of the first NDI runtime loaded in this process
+static CAPABILITIES: OnceLock<NdiCapabilities> = OnceLock::new();
+
+/// Capabilities of the NDI runtime, once something has loaded it
+pub fn capabilities() -> Option<&'static NdiCapabilities> {
+    CAPABILITIES.get()
+}
+
+impl NdiLib {
+    #[cfg(not(feature = "ndi_stub"))]
+    fn load() -> Result<Self, NdiError> {
+        let (library, name) = open_library()?;
+        Self::init_from_library(library, &name)
+    }
+
+    /// The in-process stub instead of libndi, see [`stub`]
+    #[cfg(feature = "ndi_stub")]
+    fn load() -> Result<Self, NdiError> {
+        Ok(stub::library())
+    }
+
+    #[cfg_attr(feature = "ndi_stub", allow(dead_code))]
+    fn init_from_library(library: Library, name: &str) -> Result<Self, NdiError> {
+        let missing = |symbol: &'static str| NdiError::MissingSymbol {
+            library: name.to_string(),
+            symbol,
+        };
         unsafe {
             // Load required symbols and extract raw function pointers immediately
             let initialize: NDIlib_initialize_fn = *library
                 .get::<NDIlib_initialize_fn>(b"NDIlib_initialize")
-                .context("NDIlib_initialize not found")?;
+                .map_err(|_| missing("NDIlib_initialize"))?;
             let destroy: NDIlib_destroy_fn = *library
                 .get::<NDIlib_destroy_fn>(b"NDIlib_destroy")
-                .context("NDIlib_destroy not found")?;
+                .map_err(|_| missing("NDIlib_destroy"))?;
 
             // Sender functions
             let send_create: NDIlib_send_create_fn = *library
                 .get::<NDIlib_send_create_fn>(b"NDIlib_send_create")
-                .context("NDIlib_send_create not found")?;
+                .map_err(|_| missing("NDIlib_send_create"))?;
             let send_destroy: NDIlib_send_destroy_fn = *library
                 .get::<NDIlib_send_destroy_fn>(b"NDIlib_send_destroy")
-                .context("NDIlib_send_destroy not found")?;
+                .map_err(|_| missing("NDIlib_send_destroy"))?;
             let send_send_video_v2: NDIlib_send_send_video_v2_fn = *library
                 .get::<NDIlib_send_send_video_v2_fn>(b"NDIlib_send_send_video_v2")
-                .context("NDIlib_send_send_video_v2 not found")?;
-            let send_send_video_async_v2: NDIlib_send_send_video_async_v2_fn = *library
-                .get::<NDIlib_send_send_video_async_v2_fn>(b"NDIlib_send_send_video_async_v2")
-                .context("NDIlib_send_send_video_async_v2 not found")?;
+                .map_err(|_| missing("NDIlib_send_send_video_v2"))?;
+            let send_send_audio_v2: NDIlib_send_send_audio_v2_fn = *library
+                .get::<NDIlib_send_send_audio_v2_fn>(b"NDIlib_send_send_audio_v2")
+                .map_err(|_| missing("NDIlib_send_send_audio_v2"))?;
+            let send_get_no_connections: NDIlib_send_get_no_connections_fn = *library
+                .get::<NDIlib_send_get_no_connections_fn>(b"NDIlib_send_get_no_connections")
+                .map_err(|_| missing("NDIlib_send_get_no_connections"))?;
 
             // Receiver functions
             let find_create_v2: NDIlib_find_create_v2_fn = *library
                 .get::<NDIlib_find_create_v2_fn>(b"NDIlib_find_create_v2")
-                .context("NDIlib_find_create_v2 not found")?;
+                .map_err(|_| missing("NDIlib_find_create_v2"))?;
             let find_destroy: NDIlib_find_destroy_fn = *library
                 .get::<NDIlib_find_destroy_fn>(b"NDIlib_find_destroy")
-                .context("NDIlib_find_destroy not found")?;
+                .map_err(|_| missing("NDIlib_find_destroy"))?;
             let find_wait_for_sources: NDIlib_find_wait_for_sources_fn = *library
                 .get::<NDIlib_find_wait_for_sources_fn>(b"NDIlib_find_wait_for_sources")
-                .context("NDIlib_find_wait_for_sources not found")?;
+                .map_err(|_| missing("NDIlib_find_wait_for_sources"))?;
             let find_get_current_sources: NDIlib_find_get_current_sources_fn = *library
                 .get::<NDIlib_find_get_current_sources_fn>(b"NDIlib_find_get_current_sources")
-                .context("NDIlib_find_get_current_sources not found")?;
-            let recv_create_v3: NDIlib_recv_create_v3_fn = *library
-                .get::<NDIlib_recv_create_v3_fn>(b"NDIlib_recv_create_v3")
-                .context("NDIlib_recv_create_v3 not found")?;
+                .map_err(|_| missing("NDIlib_find_get_current_sources"))?;
             let recv_destroy: NDIlib_recv_destroy_fn = *library
                 .get::<NDIlib_recv_destroy_fn>(b"NDIlib_recv_destroy")
-                .context("NDIlib_recv_destroy not found")?;
-            let recv_capture_v3: NDIlib_recv_capture_v3_fn = *library
-                .get::<NDIlib_recv_capture_v3_fn>(b"NDIlib_recv_capture_v3")
-                .context("NDIlib_recv_capture_v3 not found")?;
+                .map_err(|_| missing("NDIlib_recv_destroy"))?;
             let recv_free_video_v2: NDIlib_recv_free_video_v2_fn = *library
                 .get::<NDIlib_recv_free_video_v2_fn>(b"NDIlib_recv_free_video_v2")
-                .context("NDIlib_recv_free_video_v2 not found")?;
+                .map_err(|_| missing("NDIlib_recv_free_video_v2"))?;
+
+            // Optional functions: features that need them degrade without
+            let send_send_video_async_v2 = library
+                .get::<NDIlib_send_send_video_async_v2_fn>(b"NDIlib_send_send_video_async_v2")
+                .ok()
+                .map(|f| *f);
+            let send_get_tally = library
+                .get::<NDIlib_send_get_tally_fn>(b"NDIlib_send_get_tally")
+                .ok()
+                .map(|f| *f);
+            let recv_create_v3 = library
+                .get::<NDIlib_recv_create_v3_fn>(b"NDIlib_recv_create_v3")
+                .ok()
+                .map(|f| *f);
+            let recv_capture_v3 = library
+                .get::<NDIlib_recv_capture_v3_fn>(b"NDIlib_recv_capture_v3")
+                .ok()
+                .map(|f| *f);
+            let recv_v3 = match (recv_create_v3, recv_capture_v3) {
+                (Some(create), Some(capture)) => Some(RecvV3 { create, capture }),
+                _ => None,
+            };
+            let version = library
+                .get::<NDIlib_version_fn>(b"NDIlib_version")
+                .ok()
+                .map(|f| (*f)())
+                .filter(|version| !version.is_null())
+                .map(|version| CStr::from_ptr(version).to_string_lossy().into_owned());
 
             // Initialize NDI
             if !initialize() {
-                anyhow::bail!("NDIlib_initialize failed");
+                return Err(NdiError::InitializeFailed {
+                    library: name.to_string(),
+                });
             }
 
-            tracing::info!("NDI library loaded successfully");
+            let capabilities = NdiCapabilities {
+                major: version.as_deref().and_then(parse_major_version),
+                version,
+                async_send: send_send_video_async_v2.is_some(),
+                recv_v3: recv_v3.is_some(),
+                audio_v3: library
+                    .get::<unsafe extern "C" fn()>(b"NDIlib_send_send_audio_v3")
+                    .is_ok(),
+                tally: send_get_tally.is_some(),
+            };
+            // Senders and receivers load the library each; report it once
+            if CAPABILITIES.set(capabilities.clone()).is_ok() {
+                capabilities.log(name);
+            } else {
+                tracing::debug!("NDI library loaded: {}", name);
+            }
 
             Ok(Self {
-                _library: library,
+                _library: Some(library),
                 destroy,
                 send_create,
                 send_destroy,
                 send_send_video_v2,
+                send_send_audio_v2,
+                send_get_no_connections,
                 send_send_video_async_v2,
+                send_get_tally,
                 find_create_v2,
                 find_destroy,
                 find_wait_for_sources,
                 find_get_current_sources,
-                recv_create_v3,
                 recv_destroy,
-                recv_capture_v3,
                 recv_free_video_v2,
+                recv_v3,
             })
         }
     }
@@ -309,41 +575,333 @@ impl Drop for NdiLib {
     }
 }
 
-/// NDI sender wrapper - optimized for low latency
+/// Why a frame could not be sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendErrorKind {
+    /// May work for the next frame: sender restarting, one corrupt frame
+    Transient,
+    /// The frame format can't be converted; the same format will fail again
+    Format,
+    /// Nothing will send until the system changes, e.g. a missing decoder
+    Fatal,
+}
+
+impl SendErrorKind {
+    /// Kind of a send error; errors from other senders count as transient
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .downcast_ref::<SendError>()
+            .map_or(SendErrorKind::Transient, |e| e.kind)
+    }
+}
+
+/// Where in the send path a frame failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendFailure {
+    /// The capture format has no conversion to UYVY
+    UnsupportedFormat,
+    /// The format is supported but this frame didn't convert, e.g. a
+    /// corrupt MJPEG frame
+    ConversionFailed,
+    /// The frame converted but NDI couldn't take it
+    SendFailed,
+}
+
+impl SendFailure {
+    /// Failure of a send error; errors from other senders count as send
+    /// failures
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .downcast_ref::<SendError>()
+            .map_or(SendFailure::SendFailed, |e| e.failure)
+    }
+}
+
+/// A failed send with its [`SendFailure`] and [`SendErrorKind`]
+#[derive(Debug)]
+pub struct SendError {
+    failure: SendFailure,
+    kind: SendErrorKind,
+    error: anyhow::Error,
+}
+
+impl SendError {
+    pub fn new(failure: SendFailure, kind: SendErrorKind, error: impl Into<anyhow::Error>) -> Self {
+        Self {
+            failure,
+            kind,
+            error: error.into(),
+        }
+    }
+
+    pub fn failure(&self) -> SendFailure {
+        self.failure
+    }
+
+    pub fn kind(&self) -> SendErrorKind {
+        self.kind
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#}", self.error)
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// Why the NDI library, a sender or a receiver could not be set up
+#[derive(Debug, thiserror::Error)]
+pub enum NdiError {
+    /// No libndi in the search paths or on the system library path
+    #[error("NDI library not found")]
+    LibraryNotFound,
+    #[error("Failed to load NDI library: {0}")]
+    LibraryLoad(libloading::Error),
+    /// The library is too old (or not libndi at all)
+    #[error("NDI library {library} lacks {symbol}")]
+    MissingSymbol {
+        library: String,
+        symbol: &'static str,
+    },
+    #[error("NDIlib_initialize failed for {library}")]
+    InitializeFailed { library: String },
+    /// A name or group list handed to the SDK contains a NUL byte
+    #[error("{what} contains a NUL byte")]
+    InvalidName { what: &'static str },
+    #[error("Failed to create NDI sender")]
+    SenderCreate,
+    #[error("NDI sender not initialized")]
+    NotInitialized,
+    #[error("Failed to create NDI finder")]
+    FinderCreate,
+    #[error("NDI source '{name}' not found within timeout")]
+    SourceNotFound { name: String },
+    #[error("Failed to create NDI receiver")]
+    ReceiverCreate,
+    /// The loaded runtime lacks an optional part of the SDK
+    #[error("NDI runtime lacks {} ({})", .0.name(), .0.symbol())]
+    Unsupported(NdiFeature),
+}
+
+/// A raw NDI send instance pointer, null while there is none
+struct RawSender(*mut c_void);
+
+// SAFETY: the NDI SDK documents send instances as thread safe: frames can be
+// sent on one thread while tally and connection counts are queried on
+// others. Only destroying the instance must not race with any other call,
+// which NdiSendHandle ensures by destroying it under the write lock.
+unsafe impl Send for RawSender {}
+unsafe impl Sync for RawSender {}
+
+/// Sender state that can be queried from any thread while another thread
+/// sends frames
+pub trait SenderStatus: Send + Sync {
+    /// Number of receivers currently connected
+    fn connections(&self) -> u32;
+
+    /// Whether a receiver shows this source on program or preview
+    fn tally(&self) -> Tally;
+}
+
+/// Shared handle to an NDI send instance. The capture thread sends through
+/// it; clones poll tally and connection counts from other threads and keep
+/// working across sender recreations. Queries return "nothing connected"
+/// once the sender is gone.
+#[derive(Clone)]
+pub struct NdiSendHandle {
+    lib: Arc<NdiLib>,
+    instance: Arc<RwLock<RawSender>>,
+}
+
+impl NdiSendHandle {
+    fn new(lib: NdiLib, sender: *mut c_void) -> Self {
+        Self {
+            lib: Arc::new(lib),
+            instance: Arc::new(RwLock::new(RawSender(sender))),
+        }
+    }
+
+    /// The instance pointer, borrowed for the duration of an SDK call
+    fn read(&self) -> RwLockReadGuard<'_, RawSender> {
+        self.instance.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Replace the instance with `create()`'s result, destroying the old one
+    /// first. Queries meanwhile wait for the swap; on failure the handle is
+    /// left without an instance.
+    fn replace(
+        &self,
+        create: impl FnOnce(&NdiLib) -> Result<*mut c_void, NdiError>,
+    ) -> Result<(), NdiError> {
+        let mut instance = self.instance.write().unwrap_or_else(|e| e.into_inner());
+        self.destroy_locked(&mut instance);
+        instance.0 = create(&self.lib)?;
+        Ok(())
+    }
+
+    fn destroy(&self) {
+        let mut instance = self.instance.write().unwrap_or_else(|e| e.into_inner());
+        self.destroy_locked(&mut instance);
+    }
+
+    fn destroy_locked(&self, instance: &mut RawSender) {
+        if !instance.0.is_null() {
+            unsafe { (self.lib.send_destroy)(instance.0) };
+            instance.0 = ptr::null_mut();
+        }
+    }
+}
+
+impl SenderStatus for NdiSendHandle {
+    fn connections(&self) -> u32 {
+        let instance = self.read();
+        if instance.0.is_null() {
+            return 0;
+        }
+        let count = unsafe { (self.lib.send_get_no_connections)(instance.0, 0) };
+        count.max(0) as u32
+    }
+
+    fn tally(&self) -> Tally {
+        let instance = self.read();
+        if instance.0.is_null() {
+            return Tally::Off;
+        }
+        // Warned about once at load
+        let Some(get_tally) = self.lib.send_get_tally else {
+            return Tally::Off;
+        };
+        let mut tally = NDIlib_tally_t::default();
+        unsafe { get_tally(instance.0, &mut tally, 0) };
+        if tally.on_program {
+            Tally::Program
+        } else if tally.on_preview {
+            Tally::Preview
+        } else {
+            Tally::Off
+        }
+    }
+}
+
+/// NDI timecode of a frame or audio block captured at `captured`, or the
+/// send time if it's unknown
+fn capture_timecode(captured: Option<Instant>) -> i64 {
+    captured.map_or(NDILIB_SEND_TIMECODE_SYNTHESIZE, |at| {
+        NdiClock::global().timecode(at)
+    })
+}
+
+/// Size of the placeholder frame sent by [`NdiSender::send_keepalive_video`]
+pub const KEEPALIVE_SIZE: u32 = 16;
+
+/// How often the placeholder frame is sent
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Schedule of the keepalive video frame: due immediately, then once per
+/// interval. A late call doesn't cause a burst; the next frame is due one
+/// interval after the late one.
+#[derive(Debug)]
+pub struct KeepalivePacer {
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl KeepalivePacer {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: None,
+        }
+    }
+
+    /// Whether a frame is due at `now`; if so it counts as sent
+    pub fn due(&mut self, now: Instant) -> bool {
+        if self.next.is_some_and(|next| now < next) {
+            return false;
+        }
+        self.next = Some(now + self.interval);
+        true
+    }
+
+    /// Time left until the next frame is due
+    pub fn wait(&self, now: Instant) -> Duration {
+        self.next
+            .map_or(Duration::ZERO, |next| next.saturating_duration_since(now))
+    }
+}
+
+/// NDI sender wrapper - optimized for low latency. The conversion buffers
+/// belong to the sending thread; share [`NdiSender::handle`] to observe the
+/// sender from elsewhere.
 pub struct NdiSender {
-    lib: NdiLib,
-    sender: *mut c_void,
-    #[allow(dead_code)]
+    handle: NdiSendHandle,
     ndi_name: CString, // Keep CString alive while sender exists
+    groups: Option<CString>,
+    clock_video: bool,
     frame_rate: FrameRate,
-    frame_count: u64,
     // Single buffer for sync sending (no double buffer needed)
-    uyvy_buffer: Vec<u8>,
+    uyvy_buffer: UyvyBuffer,
     // AVX2 support flag
     has_avx2: bool,
+    // Interlaced source handling
+    deinterlace: DeinterlaceMode,
+    field_order: FieldOrder,
+    deinterlace_buffer: Vec<u8>,
+    // Planar float scratch for audio (NDI's native audio layout)
+    audio_buffer: Vec<f32>,
+    // User processing on the converted frame
+    processors: Option<SharedProcessors>,
+    // Placeholder video for audio-only senders
+    keepalive: KeepalivePacer,
+    // Asynchronous sends straight from held UYVY capture buffers
+    zero_copy: bool,
+    held: Option<HeldFrame>,
+    // The first frame's conversion time is logged once
+    first_frame_logged: bool,
+    // A JPEG size other than the negotiated one is logged once
+    mjpeg_mismatch_logged: bool,
+    // Conversion and processing time of the last frame sent
+    last_convert: Duration,
+    // Picture aspect ratio sent with each frame
+    aspect: AspectRatio,
 }
 
-// SAFETY: NdiSender uses thread-safe NDI operations
-unsafe impl Send for NdiSender {}
-
 impl NdiSender {
     /// Create a new NDI sender with the specified source name and frame rate
-    pub fn new(name: &str, frame_rate: FrameRate) -> Result<Self> {
-        let lib = NdiLib::load()?;
+    pub fn new(name: &str, frame_rate: FrameRate) -> Result<Self, NdiError> {
+        Self::with_groups(name, frame_rate, None)
+    }
 
-        let ndi_name = CString::new(name).unwrap();
+    /// Create a new NDI sender advertised in the given NDI groups
+    /// (comma-separated, None = default group)
+    pub fn with_groups(
+        name: &str,
+        frame_rate: FrameRate,
+        groups: Option<&str>,
+    ) -> Result<Self, NdiError> {
+        Self::with_options(name, frame_rate, groups, false)
+    }
 
-        let create_settings = NDIlib_send_create_t {
-            p_ndi_name: ndi_name.as_ptr(),
-            p_groups: ptr::null(),
-            clock_video: false, // Disable for lowest latency (no frame pacing)
-            clock_audio: false,
-        };
+    /// Create a new NDI sender; `clock_video` lets the SDK pace sends to
+    /// `frame_rate` at the cost of added latency
+    pub fn with_options(
+        name: &str,
+        frame_rate: FrameRate,
+        groups: Option<&str>,
+        clock_video: bool,
+    ) -> Result<Self, NdiError> {
+        let lib = NdiLib::load()?;
 
-        let sender = unsafe { (lib.send_create)(&create_settings) };
-        if sender.is_null() {
-            anyhow::bail!("Failed to create NDI sender");
-        }
+        let ndi_name =
+            CString::new(name).map_err(|_| NdiError::InvalidName { what: "NDI name" })?;
+        let groups = groups
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| NdiError::InvalidName { what: "NDI groups" })?;
+
+        let sender = Self::create_sender(&lib, &ndi_name, groups.as_ref(), clock_video)?;
 
         // Detect AVX2 support for SIMD optimization
         let has_avx2 = Self::detect_avx2();
@@ -353,143 +911,175 @@ impl NdiSender {
             tracing::info!("NDI sender: Using scalar YUYV→UYVY conversion");
         }
 
-        tracing::info!(
-            "NDI sender created: {} (sync mode, clock_video=false)",
-            name
-        );
+        tracing::info!("NDI sender created: {} (clock_video={})", name, clock_video);
 
         Ok(Self {
-            lib,
-            sender,
+            handle: NdiSendHandle::new(lib, sender),
             ndi_name,
+            groups,
+            clock_video,
             frame_rate,
-            frame_count: 0,
-            uyvy_buffer: Vec::with_capacity(1920 * 1080 * 2), // Pre-allocate for 1080p
+            uyvy_buffer: UyvyBuffer::new(), // Sized by the first frame
             has_avx2,
+            deinterlace: DeinterlaceMode::Off,
+            field_order: FieldOrder::Progressive,
+            deinterlace_buffer: Vec::new(),
+            audio_buffer: Vec::new(),
+            processors: None,
+            keepalive: KeepalivePacer::new(KEEPALIVE_INTERVAL),
+            zero_copy: false,
+            held: None,
+            first_frame_logged: false,
+            mjpeg_mismatch_logged: false,
+            last_convert: Duration::ZERO,
+            aspect: AspectRatio::Auto,
         })
     }
 
-    /// Detect AVX2 CPU support
-    #[cfg(target_arch = "x86_64")]
-    fn detect_avx2() -> bool {
-        is_x86_feature_detected!("avx2")
-    }
+    fn create_sender(
+        lib: &NdiLib,
+        ndi_name: &CString,
+        groups: Option<&CString>,
+        clock_video: bool,
+    ) -> Result<*mut c_void, NdiError> {
+        let create_settings = NDIlib_send_create_t {
+            p_ndi_name: ndi_name.as_ptr(),
+            p_groups: groups.map_or(ptr::null(), |g| g.as_ptr()),
+            clock_video, // Off by default for lowest latency (no frame pacing)
+            clock_audio: false,
+        };
 
-    #[cfg(not(target_arch = "x86_64"))]
-    fn detect_avx2() -> bool {
-        false
+        let sender = unsafe { (lib.send_create)(&create_settings) };
+        if sender.is_null() {
+            return Err(NdiError::SenderCreate);
+        }
+        Ok(sender)
     }
 
-    // --- Format conversion functions ---
-
-    /// Convert YUYV to UYVY - uses AVX2 SIMD when available
-    fn convert_yuyv_to_uyvy(&mut self, yuyv: &[u8]) {
-        self.uyvy_buffer.clear();
-        self.uyvy_buffer.reserve(yuyv.len());
+    /// Destroy and recreate the NDI sender instance, keeping the loaded library
+    pub fn recreate(&mut self) -> Result<(), NdiError> {
+        self.handle.replace(|lib| {
+            Self::create_sender(lib, &self.ndi_name, self.groups.as_ref(), self.clock_video)
+        })?;
+        // Destroying the old instance gave the held frame back
+        self.held = None;
+        tracing::info!("NDI sender recreated: {}", self.ndi_name.to_string_lossy());
+        Ok(())
+    }
 
-        #[cfg(target_arch = "x86_64")]
-        if self.has_avx2 {
-            // SAFETY: We checked for AVX2 support
-            unsafe { self.convert_yuyv_to_uyvy_avx2(yuyv) };
-            return;
+    /// Configure handling of interlaced sources. Bob/blend apply to YUYV
+    /// capture; "interlaced" flags frames as interleaved fields instead.
+    pub fn set_deinterlace(&mut self, mode: DeinterlaceMode, field_order: FieldOrder) {
+        self.deinterlace = mode;
+        self.field_order = field_order;
+        if field_order.is_interlaced() {
+            tracing::info!(
+                "NDI sender: {:?} source, deinterlace={:?}",
+                field_order,
+                mode
+            );
+            if mode == DeinterlaceMode::Interlaced && field_order == FieldOrder::BottomFirst {
+                tracing::warn!("NDI assumes top field first; bottom-first fields may judder");
+            }
         }
-
-        // Scalar fallback
-        self.convert_yuyv_to_uyvy_scalar(yuyv);
     }
 
-    /// Scalar YUYV to UYVY conversion (fallback)
-    #[inline]
-    fn convert_yuyv_to_uyvy_scalar(&mut self, yuyv: &[u8]) {
-        // YUYV: Y0 U0 Y1 V0 -> UYVY: U0 Y0 V0 Y1
-        for chunk in yuyv.chunks_exact(4) {
-            self.uyvy_buffer.push(chunk[1]); // U0
-            self.uyvy_buffer.push(chunk[0]); // Y0
-            self.uyvy_buffer.push(chunk[3]); // V0
-            self.uyvy_buffer.push(chunk[2]); // Y1
+    /// Send UYVY frames that stay valid until the next frame's callback has
+    /// returned (see [`FrameInfo::held`]) asynchronously, without copying
+    /// them. Sending the next frame gives a lent frame back; when that frame
+    /// isn't lent (see [`Self::lends`]), the caller must call
+    /// [`Self::release_frame`] before the buffer is reused.
+    pub fn set_zero_copy(&mut self, enabled: bool) {
+        // Without async sends every frame is copied (warned about at load)
+        self.zero_copy = enabled && self.handle.lib.send_send_video_async_v2.is_some();
+        if self.zero_copy {
+            tracing::info!("NDI sender: zero-copy async send for UYVY capture");
         }
     }
 
-    /// AVX2 SIMD YUYV to UYVY conversion - processes 32 pixels (64 bytes) per iteration
-    /// This is ~16x faster than scalar for 1080p frames
-    #[cfg(target_arch = "x86_64")]
-    #[target_feature(enable = "avx2")]
-    unsafe fn convert_yuyv_to_uyvy_avx2(&mut self, yuyv: &[u8]) {
-        let total_bytes = yuyv.len();
-        let avx_bytes = (total_bytes / 64) * 64;
-
-        // Pre-size buffer
-        self.uyvy_buffer.resize(total_bytes, 0);
-        let dst = self.uyvy_buffer.as_mut_ptr();
-
-        // Shuffle mask to convert YUYV to UYVY
-        // YUYV: Y0 U0 Y1 V0 (indices 0,1,2,3) -> UYVY: U0 Y0 V0 Y1 (indices 1,0,3,2)
-        let shuffle_mask = _mm256_setr_epi8(
-            1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 11, 10, 13, 12, 15, 14, 1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 11,
-            10, 13, 12, 15, 14,
-        );
-
-        let mut i = 0;
-        while i < avx_bytes {
-            // Load 64 bytes (32 pixels in YUYV format)
-            let data0 = _mm256_loadu_si256(yuyv.as_ptr().add(i) as *const __m256i);
-            let data1 = _mm256_loadu_si256(yuyv.as_ptr().add(i + 32) as *const __m256i);
+    /// Picture aspect ratio to send, e.g. 16:9 for anamorphic 1440x1080
+    pub fn set_aspect(&mut self, aspect: AspectRatio) {
+        self.aspect = aspect;
+    }
 
-            // Shuffle to convert YUYV to UYVY
-            let result0 = _mm256_shuffle_epi8(data0, shuffle_mask);
-            let result1 = _mm256_shuffle_epi8(data1, shuffle_mask);
+    /// Wait until NDI is done with the frame lent to the last asynchronous
+    /// send. Returns false if its buffer changed in the meantime.
+    pub fn release_frame(&mut self) -> Result<bool, NdiError> {
+        let Some(held) = self.held.take() else {
+            return Ok(true);
+        };
+        // Frames are only lent with async sends
+        let Some(send_async) = self.handle.lib.send_send_video_async_v2 else {
+            return Ok(true);
+        };
+        let instance = self.handle.read();
+        if instance.0.is_null() {
+            return Err(NdiError::NotInitialized);
+        }
+        // A null frame flushes: it returns once NDI no longer uses the buffer
+        unsafe {
+            send_async(instance.0, ptr::null());
+        }
+        drop(instance);
+        // The capture keeps the buffer until the frame is released, so it
+        // is still mapped
+        Ok(unsafe { held.is_intact() })
+    }
 
-            // Store results
-            _mm256_storeu_si256(dst.add(i) as *mut __m256i, result0);
-            _mm256_storeu_si256(dst.add(i + 32) as *mut __m256i, result1);
+    /// Whether the last send lent `data` to NDI, which still reads it
+    pub fn lends(&self, data: &[u8]) -> bool {
+        self.held.as_ref().is_some_and(|held| held.is_buffer(data))
+    }
 
-            i += 64;
+    /// Size the conversion scratch buffers for the negotiated `width`x`height`
+    /// up front, so a 4K first frame doesn't allocate on the send path
+    pub fn reserve_frame(&mut self, width: u32, height: u32) {
+        let len = uyvy_frame_size(width as usize, height as usize);
+        self.uyvy_buffer.reserve(len);
+        if self.deinterlace.filters(self.field_order) {
+            // YUYV is the same size as UYVY
+            self.deinterlace_buffer
+                .reserve(len.saturating_sub(self.deinterlace_buffer.len()));
         }
+    }
 
-        // Handle remaining bytes with scalar code
-        while i < total_bytes {
-            let y0 = *yuyv.get_unchecked(i);
-            let u = *yuyv.get_unchecked(i + 1);
-            let y1 = *yuyv.get_unchecked(i + 2);
-            let v = *yuyv.get_unchecked(i + 3);
-
-            *dst.add(i) = u;
-            *dst.add(i + 1) = y0;
-            *dst.add(i + 2) = v;
-            *dst.add(i + 3) = y1;
+    /// Run `processors` on every converted frame before it is sent
+    pub fn set_processors(&mut self, processors: SharedProcessors) {
+        let active = !processors
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty();
+        self.processors = active.then_some(processors);
+    }
 
-            i += 4;
+    fn frame_format_type(&self) -> c_int {
+        if self.deinterlace == DeinterlaceMode::Interlaced && self.field_order.is_interlaced() {
+            NDILIB_FRAME_FORMAT_TYPE_INTERLEAVED
+        } else {
+            NDILIB_FRAME_FORMAT_TYPE_PROGRESSIVE
         }
     }
 
-    fn convert_nv12_to_uyvy(&mut self, nv12: &[u8], width: usize, height: usize) {
-        // NV12: Y plane followed by interleaved UV plane
-        let y_size = width * height;
-        self.uyvy_buffer.clear();
-        self.uyvy_buffer.reserve(width * height * 2);
-
-        let y_plane = &nv12[..y_size];
-        let uv_plane = &nv12[y_size..];
-
-        for row in 0..height {
-            let uv_row = row / 2;
-            for col in (0..width).step_by(2) {
-                let y0 = y_plane[row * width + col];
-                let y1 = y_plane[row * width + col + 1];
-                let uv_idx = uv_row * width + col;
-                let u = uv_plane.get(uv_idx).copied().unwrap_or(128);
-                let v = uv_plane.get(uv_idx + 1).copied().unwrap_or(128);
-
-                // UYVY: U Y0 V Y1
-                self.uyvy_buffer.push(u);
-                self.uyvy_buffer.push(y0);
-                self.uyvy_buffer.push(v);
-                self.uyvy_buffer.push(y1);
-            }
-        }
+    /// Detect AVX2 CPU support
+    #[cfg(target_arch = "x86_64")]
+    fn detect_avx2() -> bool {
+        is_x86_feature_detected!("avx2")
     }
 
-    fn decode_mjpeg_to_uyvy(&mut self, mjpeg: &[u8], _width: usize, _height: usize) -> Result<()> {
+    #[cfg(not(target_arch = "x86_64"))]
+    fn detect_avx2() -> bool {
+        false
+    }
+
+    // --- Format conversion functions ---
+
+    /// Decode an MJPEG frame into the UYVY buffer at `width`x`height`
+    fn decode_mjpeg_to_uyvy(
+        &mut self,
+        mjpeg: &[u8],
+        width: u32,
+        height: u32,
+    ) -> std::result::Result<(), SendError> {
         // Simple MJPEG decoder using system libjpeg via turbojpeg would be ideal,
         // but for simplicity we'll use a pure-Rust approach
         // For now, fail gracefully - full MJPEG support would need additional dependency
@@ -515,65 +1105,46 @@ impl NdiSender {
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::null())
             .spawn()
-            .context("MJPEG decode requires ffmpeg. Install with: apt install ffmpeg")?;
+            .context("MJPEG decode requires ffmpeg. Install with: apt install ffmpeg")
+            .map_err(|e| SendError::new(SendFailure::ConversionFailed, SendErrorKind::Fatal, e))?;
 
         {
             let stdin = child.stdin.as_mut().unwrap();
-            stdin.write_all(mjpeg)?;
+            stdin.write_all(mjpeg).map_err(|e| {
+                SendError::new(SendFailure::ConversionFailed, SendErrorKind::Transient, e)
+            })?;
         }
 
-        let output = child.wait_with_output()?;
+        let output = child.wait_with_output().map_err(|e| {
+            SendError::new(SendFailure::ConversionFailed, SendErrorKind::Transient, e)
+        })?;
         if !output.status.success() {
-            anyhow::bail!("ffmpeg MJPEG decode failed");
+            // Usually one corrupt frame
+            return Err(SendError::new(
+                SendFailure::ConversionFailed,
+                SendErrorKind::Transient,
+                anyhow::anyhow!("ffmpeg MJPEG decode failed"),
+            ));
         }
 
-        self.uyvy_buffer = output.stdout;
-        Ok(())
-    }
-
-    fn convert_bgra_to_uyvy(&mut self, bgra: &[u8], width: usize, height: usize) {
-        self.uyvy_buffer.clear();
-        self.uyvy_buffer.reserve(width * height * 2);
-
-        for row in 0..height {
-            for col in (0..width).step_by(2) {
-                let idx0 = (row * width + col) * 4;
-                let idx1 = (row * width + col + 1) * 4;
-
-                // BGRA to YUV conversion (BT.601)
-                let (b0, g0, r0) = (
-                    bgra[idx0] as i32,
-                    bgra[idx0 + 1] as i32,
-                    bgra[idx0 + 2] as i32,
-                );
-                let (b1, g1, r1) = (
-                    bgra.get(idx1).copied().unwrap_or(0) as i32,
-                    bgra.get(idx1 + 1).copied().unwrap_or(0) as i32,
-                    bgra.get(idx1 + 2).copied().unwrap_or(0) as i32,
-                );
-
-                let y0 = ((66 * r0 + 129 * g0 + 25 * b0 + 128) >> 8) + 16;
-                let y1 = ((66 * r1 + 129 * g1 + 25 * b1 + 128) >> 8) + 16;
-
-                // Average for U/V
-                let r = (r0 + r1) / 2;
-                let g = (g0 + g1) / 2;
-                let b = (b0 + b1) / 2;
-                let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
-                let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
-
-                // UYVY: U Y0 V Y1
-                self.uyvy_buffer.push(u.clamp(0, 255) as u8);
-                self.uyvy_buffer.push(y0.clamp(16, 235) as u8);
-                self.uyvy_buffer.push(v.clamp(0, 255) as u8);
-                self.uyvy_buffer.push(y1.clamp(16, 235) as u8);
-            }
+        let decoded =
+            fit_decoded_mjpeg(mjpeg, &output.stdout, width, height, &mut self.uyvy_buffer)?;
+        if decoded != (width, height) && !self.mjpeg_mismatch_logged {
+            self.mjpeg_mismatch_logged = true;
+            tracing::warn!(
+                "Camera sends {}x{} JPEGs for a {}x{} format; scaling them",
+                decoded.0,
+                decoded.1,
+                width,
+                height
+            );
         }
+        Ok(())
     }
 
     /// Send video frame (legacy method with owned data)
     #[allow(dead_code)]
-    pub fn send_frame(&mut self, frame: &Frame) -> Result<()> {
+    pub fn send_frame(&mut self, frame: &Frame) -> std::result::Result<(), SendError> {
         self.send_frame_data(
             &frame.data,
             frame.width,
@@ -593,93 +1164,368 @@ impl NdiSender {
         height: u32,
         fourcc: v4l::FourCC,
         stride: u32,
-    ) -> Result<()> {
-        let fourcc_str = fourcc.str()?;
+    ) -> std::result::Result<(), SendError> {
+        self.send_frame_zero_copy(data, FrameInfo::new(width, height, fourcc, stride))
+    }
+
+    /// Zero-copy send from FrameInfo (callback-compatible). Planar formats
+    /// are read in place at the plane offsets carried in `info`.
+    #[inline]
+    pub fn send_frame_zero_copy(
+        &mut self,
+        data: &[u8],
+        info: FrameInfo,
+    ) -> std::result::Result<(), SendError> {
+        if self.handle.read().0.is_null() {
+            return Err(SendError::new(
+                SendFailure::SendFailed,
+                SendErrorKind::Transient,
+                anyhow::anyhow!("NDI sender is not available"),
+            ));
+        }
+        let FrameInfo {
+            width,
+            height,
+            fourcc,
+            stride,
+            planes,
+            range,
+            captured,
+            held,
+            uncached,
+        } = info;
+        let fourcc_str = fourcc.str().map_err(|e| {
+            SendError::new(SendFailure::UnsupportedFormat, SendErrorKind::Format, e)
+        })?;
+        let convert_start = Instant::now();
 
         // Convert to UYVY, get stride
-        let (uyvy_ptr, uyvy_stride) = match fourcc_str {
+        let (mut uyvy_ptr, mut uyvy_stride) = match fourcc_str {
+            "UYVY" if range == ColorRange::Full => {
+                // Copied so the range can be compressed below
+                self.uyvy_buffer
+                    .copy_uyvy(data, width as usize, height as usize, stride as usize);
+                (self.uyvy_buffer.as_ptr(), width * 2)
+            }
             "UYVY" => {
                 // Direct passthrough - no conversion needed!
                 (data.as_ptr(), stride)
             }
+            "YUYV" if self.deinterlace.filters(self.field_order) => {
+                let mut progressive = std::mem::take(&mut self.deinterlace_buffer);
+                deinterlace_yuyv(
+                    data,
+                    width as usize,
+                    height as usize,
+                    self.field_order,
+                    self.deinterlace,
+                    &mut progressive,
+                );
+                self.uyvy_buffer.convert_yuyv(&progressive, self.has_avx2);
+                self.deinterlace_buffer = progressive;
+                (self.uyvy_buffer.as_ptr(), width * 2)
+            }
+            "YUYV" if uncached => {
+                self.uyvy_buffer.convert_yuyv_uncached(data, self.has_avx2);
+                (self.uyvy_buffer.as_ptr(), width * 2)
+            }
             "YUYV" => {
-                self.convert_yuyv_to_uyvy(data);
+                self.uyvy_buffer.convert_yuyv(data, self.has_avx2);
                 (self.uyvy_buffer.as_ptr(), width * 2)
             }
-            "NV12" => {
-                self.convert_nv12_to_uyvy(data, width as usize, height as usize);
+            "NV12" | "NM12" => {
+                let luma = planes.get(0).unwrap_or(0).min(data.len());
+                let chroma = planes
+                    .get(1)
+                    .unwrap_or(stride as usize * height as usize)
+                    .min(data.len());
+                self.uyvy_buffer.convert_nv12_planes(
+                    &data[luma..],
+                    &data[chroma..],
+                    width as usize,
+                    height as usize,
+                    stride as usize,
+                );
                 (self.uyvy_buffer.as_ptr(), width * 2)
             }
             "MJPG" => {
-                self.decode_mjpeg_to_uyvy(data, width as usize, height as usize)?;
+                self.decode_mjpeg_to_uyvy(data, width, height)?;
                 (self.uyvy_buffer.as_ptr(), width * 2)
             }
             "BGRA" | "BGR4" | "RX24" => {
-                self.convert_bgra_to_uyvy(data, width as usize, height as usize);
+                self.uyvy_buffer.convert_bgra(
+                    data,
+                    width as usize,
+                    height as usize,
+                    stride as usize,
+                );
                 (self.uyvy_buffer.as_ptr(), width * 2)
             }
             format => {
-                anyhow::bail!(
-                    "Unsupported video format: {}. Supported: UYVY, YUYV, NV12, MJPG, BGRA",
-                    format
-                );
+                return Err(SendError::new(
+                    SendFailure::UnsupportedFormat,
+                    SendErrorKind::Format,
+                    anyhow::anyhow!(
+                        "Unsupported video format: {}. Supported: UYVY, YUYV, NV12, MJPG, BGRA",
+                        format
+                    ),
+                ));
             }
         };
 
+        // NDI expects limited range; the MJPEG decoder and the BGRA
+        // conversion already produce it
+        if range == ColorRange::Full && matches!(fourcc_str, "UYVY" | "YUYV" | "NV12" | "NM12") {
+            self.uyvy_buffer.compress_full_range();
+            uyvy_ptr = self.uyvy_buffer.as_ptr();
+            uyvy_stride = width * 2;
+        }
+        if !self.first_frame_logged {
+            self.first_frame_logged = true;
+            tracing::info!(
+                "First frame: {}x{} {} to UYVY in {:.2} ms",
+                width,
+                height,
+                fourcc_str,
+                convert_start.elapsed().as_secs_f64() * 1000.0
+            );
+        }
+
+        if let Some(processors) = &self.processors {
+            if uyvy_ptr == data.as_ptr() {
+                // Passthrough frames live in the capture buffer; process a copy
+                self.uyvy_buffer
+                    .copy_uyvy(data, width as usize, height as usize, stride as usize);
+                uyvy_ptr = self.uyvy_buffer.as_ptr();
+                uyvy_stride = width * 2;
+            }
+            let mut chain = processors.lock().unwrap_or_else(|e| e.into_inner());
+            if chain.run(self.uyvy_buffer.as_mut_slice(), width, height) == FrameAction::Skip {
+                self.last_convert = convert_start.elapsed();
+                return Ok(());
+            }
+        }
+        self.last_convert = convert_start.elapsed();
+
         let video_frame = NDIlib_video_frame_v2_t {
             xres: width as c_int,
             yres: height as c_int,
             fourcc: NDILIBD_FOURCC_UYVY,
             frame_rate_n: self.frame_rate.numerator as c_int,
             frame_rate_d: self.frame_rate.denominator as c_int,
-            picture_aspect_ratio: 0.0, // Use default
-            frame_format_type: NDILIB_FRAME_FORMAT_TYPE_PROGRESSIVE,
-            timecode: i64::MAX, // Use current time
+            picture_aspect_ratio: self.aspect.picture_aspect_ratio(width, height),
+            frame_format_type: self.frame_format_type(),
+            timecode: capture_timecode(captured),
             p_data: uyvy_ptr,
             line_stride_in_bytes: uyvy_stride as c_int,
             p_metadata: ptr::null(),
             timestamp: 0,
         };
 
-        // SYNCHRONOUS send - blocks until NDI accepts frame (lowest latency)
+        // Held passthrough frames are lent to NDI straight from the capture
+        // buffer; everything else is a SYNCHRONOUS send that blocks until
+        // NDI accepts the frame (lowest latency)
+        let send_async = self
+            .handle
+            .lib
+            .send_send_video_async_v2
+            .filter(|_| self.zero_copy && held && uyvy_ptr == data.as_ptr());
+        let lend = send_async.is_some();
+        let instance = self.handle.read();
+        if instance.0.is_null() {
+            return Err(SendError::new(
+                SendFailure::SendFailed,
+                SendErrorKind::Transient,
+                anyhow::anyhow!("NDI sender is not available"),
+            ));
+        }
+        // Any video send gives the previously lent frame back
+        let previous = self.held.take();
         unsafe {
-            (self.lib.send_send_video_v2)(self.sender, &video_frame);
+            match send_async {
+                Some(send_async) => send_async(instance.0, &video_frame),
+                None => (self.handle.lib.send_send_video_v2)(instance.0, &video_frame),
+            }
+        }
+        drop(instance);
+        if let Some(previous) = previous {
+            // The source keeps the previous buffer until this send returns
+            if !unsafe { previous.is_intact() } {
+                tracing::warn!("Zero-copy frame buffer changed while NDI still held it");
+            }
+        }
+        if lend {
+            self.held = Some(HeldFrame::new(data));
         }
 
-        self.frame_count += 1;
+        Ok(())
+    }
 
-        if self.frame_count.is_multiple_of(300) {
-            tracing::debug!("Sent {} frames", self.frame_count);
+    /// Send interleaved 16-bit audio, converted to NDI's planar float layout.
+    /// `captured` is when the first sample was captured.
+    pub fn send_audio(
+        &mut self,
+        samples: &[i16],
+        channels: u32,
+        sample_rate: u32,
+        captured: Option<Instant>,
+    ) -> Result<(), NdiError> {
+        if channels == 0 || samples.is_empty() {
+            return Ok(());
         }
+        let frames = interleaved_to_planar_f32(samples, channels as usize, &mut self.audio_buffer);
+
+        let audio_frame = NDIlib_audio_frame_v2_t {
+            sample_rate: sample_rate as c_int,
+            no_channels: channels as c_int,
+            no_samples: frames as c_int,
+            timecode: capture_timecode(captured),
+            p_data: self.audio_buffer.as_ptr(),
+            channel_stride_in_bytes: (frames * std::mem::size_of::<f32>()) as c_int,
+            p_metadata: ptr::null(),
+            timestamp: 0,
+        };
 
+        let instance = self.handle.read();
+        if instance.0.is_null() {
+            return Err(NdiError::NotInitialized);
+        }
+        unsafe {
+            (self.handle.lib.send_send_audio_v2)(instance.0, &audio_frame);
+        }
         Ok(())
     }
 
-    /// Zero-copy send from FrameInfo (callback-compatible)
-    #[inline]
-    pub fn send_frame_zero_copy(
-        &mut self,
-        data: &[u8],
-        info: crate::capture::FrameInfo,
-    ) -> Result<()> {
-        self.send_frame_data(data, info.width, info.height, info.fourcc, info.stride)
+    /// Send a small black frame if one is due, so receivers that only list
+    /// sources with video show an audio-only sender. Call it regularly; it
+    /// returns whether a frame was sent.
+    pub fn send_keepalive_video(&mut self, now: Instant) -> Result<bool, NdiError> {
+        if !self.keepalive.due(now) {
+            return Ok(false);
+        }
+        // Black UYVY: U=V=128, Y=16
+        let frame = [128u8, 16, 128, 16].repeat((KEEPALIVE_SIZE * KEEPALIVE_SIZE / 2) as usize);
+        let video_frame = NDIlib_video_frame_v2_t {
+            xres: KEEPALIVE_SIZE as c_int,
+            yres: KEEPALIVE_SIZE as c_int,
+            fourcc: NDILIBD_FOURCC_UYVY,
+            // One frame per interval
+            frame_rate_n: 1000,
+            frame_rate_d: KEEPALIVE_INTERVAL.as_millis() as c_int,
+            picture_aspect_ratio: 0.0,
+            frame_format_type: NDILIB_FRAME_FORMAT_TYPE_PROGRESSIVE,
+            timecode: NDILIB_SEND_TIMECODE_SYNTHESIZE,
+            p_data: frame.as_ptr(),
+            line_stride_in_bytes: (KEEPALIVE_SIZE * 2) as c_int,
+            p_metadata: ptr::null(),
+            timestamp: 0,
+        };
+        let instance = self.handle.read();
+        if instance.0.is_null() {
+            return Err(NdiError::NotInitialized);
+        }
+        unsafe {
+            (self.handle.lib.send_send_video_v2)(instance.0, &video_frame);
+        }
+        Ok(true)
     }
 
-    /// Get number of frames sent
-    #[allow(dead_code)]
-    pub fn frame_count(&self) -> u64 {
-        self.frame_count
+    /// Time until [`NdiSender::send_keepalive_video`] sends the next frame
+    pub fn keepalive_wait(&self, now: Instant) -> Duration {
+        self.keepalive.wait(now)
+    }
+
+    /// Number of receivers currently connected (doesn't block)
+    pub fn connections(&self) -> u32 {
+        self.handle.connections()
+    }
+
+    /// Whether a receiver shows this source on program or preview
+    pub fn tally(&self) -> Tally {
+        self.handle.tally()
+    }
+
+    /// Time the last frame spent in conversion to UYVY and the processors
+    pub fn last_convert(&self) -> Duration {
+        self.last_convert
+    }
+
+    /// Handle for polling tally and connections from other threads
+    pub fn handle(&self) -> NdiSendHandle {
+        self.handle.clone()
     }
 }
 
 impl Drop for NdiSender {
     fn drop(&mut self) {
-        if !self.sender.is_null() {
-            unsafe {
-                (self.lib.send_destroy)(self.sender);
-            }
+        // Handles may outlive the sender; the source stops being advertised now
+        self.handle.destroy();
+    }
+}
+
+// ============================================================================
+// NDI Discovery
+// ============================================================================
+
+/// Sources currently known to `finder` with their names. The source structs
+/// point into memory owned by the finder.
+///
+/// # Safety
+/// `finder` must be a live finder created by `lib`.
+unsafe fn current_sources(lib: &NdiLib, finder: *mut c_void) -> Vec<(NDIlib_source_t, String)> {
+    let mut num_sources: u32 = 0;
+    let sources = (lib.find_get_current_sources)(finder, &mut num_sources);
+    if sources.is_null() {
+        return Vec::new();
+    }
+    (0..num_sources as usize)
+        .map(|i| *sources.add(i))
+        .filter(|source| !source.p_ndi_name.is_null())
+        .map(|source| {
+            let name = CStr::from_ptr(source.p_ndi_name)
+                .to_string_lossy()
+                .to_string();
+            (source, name)
+        })
+        .collect()
+}
+
+/// Names of the NDI sources visible in `groups` (None = default group),
+/// including local ones, after waiting up to `timeout` for discovery
+pub fn find_sources(timeout: Duration, groups: Option<&str>) -> Result<Vec<String>, NdiError> {
+    let lib = NdiLib::load()?;
+    let groups = groups
+        .map(CString::new)
+        .transpose()
+        .map_err(|_| NdiError::InvalidName { what: "NDI groups" })?;
+    let find_create = NDIlib_find_create_t {
+        show_local_sources: true,
+        p_groups: groups.as_ref().map_or(ptr::null(), |g| g.as_ptr()),
+        p_extra_ips: ptr::null(),
+    };
+
+    let finder = unsafe { (lib.find_create_v2)(&find_create) };
+    if finder.is_null() {
+        return Err(NdiError::FinderCreate);
+    }
+
+    // Sources trickle in as announcements arrive; keep waiting until the
+    // list stops changing or the timeout runs out
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        if left.is_zero()
+            || !unsafe { (lib.find_wait_for_sources)(finder, left.as_millis() as u32) }
+        {
+            break;
         }
     }
+    let names = unsafe { current_sources(&lib, finder) }
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect();
+    unsafe { (lib.find_destroy)(finder) };
+    Ok(names)
 }
 
 // ============================================================================
@@ -691,26 +1537,252 @@ pub struct ReceivedFrame {
     pub width: u32,
     pub height: u32,
     pub fourcc: u32,
-    #[allow(dead_code)]
     pub stride: u32,
     pub data: Vec<u8>,
+    /// Display width over height the sender set, 0 for square pixels
+    pub picture_aspect_ratio: f32,
+    /// Sender's clock when the frame was sent, in 100ns units (0 or
+    /// `i64::MAX` when the sender doesn't set it)
+    pub timestamp: i64,
+    /// Timecode the sender attached, in 100ns units
+    pub timecode: i64,
+}
+
+/// Live receiver counters. Created by the caller and shared via `Arc`, so
+/// values survive reconnections and can be read from other threads.
+#[derive(Debug)]
+pub struct NdiReceiverStats {
+    pub video_frames: AtomicU64,
+    pub audio_frames: AtomicU64,
+    pub metadata_frames: AtomicU64,
+    pub status_changes: AtomicU64,
+    /// Captures that returned no frame within the timeout
+    pub timeouts: AtomicU64,
+    /// Captures reporting a lost connection
+    pub errors: AtomicU64,
+    /// Video payload bytes copied out of the receiver
+    pub bytes_received: AtomicU64,
+    /// Video frames the display dropped as too old to show
+    pub late_drops: AtomicU64,
+    /// Video frames the display drew
+    pub displayed: AtomicU64,
+    /// Video frames the display skipped for a newer one (`display.max_fps`)
+    pub rate_limited: AtomicU64,
+    /// Video frames the display skipped because signals kept interrupting
+    /// the framebuffer write
+    pub write_interrupted: AtomicU64,
+    /// Connections to the source after the first one
+    pub reconnects: AtomicU64,
+    // Video frame times as nanoseconds since `epoch`, plus one (0 = none yet)
+    first_frame: AtomicU64,
+    last_frame: AtomicU64,
+    epoch: Instant,
+}
+
+/// Point-in-time copy of `NdiReceiverStats`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NdiReceiverStatsSnapshot {
+    pub video_frames: u64,
+    pub audio_frames: u64,
+    pub metadata_frames: u64,
+    pub status_changes: u64,
+    pub timeouts: u64,
+    pub errors: u64,
+    pub bytes_received: u64,
+    pub late_drops: u64,
+    pub displayed: u64,
+    pub rate_limited: u64,
+    pub write_interrupted: u64,
+    pub reconnects: u64,
+    /// Mean time between video frames, once two have arrived
+    pub average_interval: Option<Duration>,
+    /// Time since the last video frame, once one has arrived
+    pub last_frame_age: Option<Duration>,
+}
+
+impl Default for NdiReceiverStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NdiReceiverStats {
+    pub fn new() -> Self {
+        Self {
+            video_frames: AtomicU64::new(0),
+            audio_frames: AtomicU64::new(0),
+            metadata_frames: AtomicU64::new(0),
+            status_changes: AtomicU64::new(0),
+            timeouts: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            bytes_received: AtomicU64::new(0),
+            late_drops: AtomicU64::new(0),
+            displayed: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+            write_interrupted: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+            first_frame: AtomicU64::new(0),
+            last_frame: AtomicU64::new(0),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Report the counters under `name`, with the mean frame interval
+    pub fn register(self: &Arc<Self>, registry: &StatsRegistry, name: &str) {
+        let timing = Arc::clone(self);
+        registry.register(
+            Component::new(name)
+                .counter("frames", self, |s| &s.video_frames)
+                .counter("bytes", self, |s| &s.bytes_received)
+                .counter("late_drops", self, |s| &s.late_drops)
+                .counter("displayed", self, |s| &s.displayed)
+                .counter("rate_limited", self, |s| &s.rate_limited)
+                .counter("write_interrupted", self, |s| &s.write_interrupted)
+                .counter("timeouts", self, |s| &s.timeouts)
+                .counter("errors", self, |s| &s.errors)
+                .counter("reconnects", self, |s| &s.reconnects)
+                .gauge("interval_ms", move || {
+                    timing
+                        .snapshot(Instant::now())
+                        .average_interval
+                        .map_or(f64::NAN, |i| i.as_secs_f64() * 1000.0)
+                }),
+        );
+    }
+
+    fn since_epoch(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.epoch).as_nanos() as u64 + 1
+    }
+
+    /// Count one `recv_capture` result; `bytes` is the video payload size
+    fn record(&self, frame_type: c_int, bytes: usize, now: Instant) {
+        let counter = match frame_type {
+            NDILIB_FRAME_TYPE_NONE => &self.timeouts,
+            NDILIB_FRAME_TYPE_VIDEO => {
+                let at = self.since_epoch(now);
+                let _ =
+                    self.first_frame
+                        .compare_exchange(0, at, Ordering::Relaxed, Ordering::Relaxed);
+                self.last_frame.store(at, Ordering::Relaxed);
+                self.bytes_received
+                    .fetch_add(bytes as u64, Ordering::Relaxed);
+                &self.video_frames
+            }
+            NDILIB_FRAME_TYPE_AUDIO => &self.audio_frames,
+            NDILIB_FRAME_TYPE_METADATA => &self.metadata_frames,
+            NDILIB_FRAME_TYPE_ERROR => &self.errors,
+            NDILIB_FRAME_TYPE_STATUS_CHANGE => &self.status_changes,
+            _ => return,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Copy the counters, with frame timing relative to `now`
+    pub fn snapshot(&self, now: Instant) -> NdiReceiverStatsSnapshot {
+        let video_frames = self.video_frames.load(Ordering::Relaxed);
+        let first = self.first_frame.load(Ordering::Relaxed);
+        let last = self.last_frame.load(Ordering::Relaxed);
+        let average_interval = (video_frames >= 2 && first > 0)
+            .then(|| Duration::from_nanos(last.saturating_sub(first) / (video_frames - 1)));
+        let last_frame_age =
+            (last > 0).then(|| Duration::from_nanos(self.since_epoch(now).saturating_sub(last)));
+        NdiReceiverStatsSnapshot {
+            video_frames,
+            audio_frames: self.audio_frames.load(Ordering::Relaxed),
+            metadata_frames: self.metadata_frames.load(Ordering::Relaxed),
+            status_changes: self.status_changes.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            late_drops: self.late_drops.load(Ordering::Relaxed),
+            displayed: self.displayed.load(Ordering::Relaxed),
+            rate_limited: self.rate_limited.load(Ordering::Relaxed),
+            write_interrupted: self.write_interrupted.load(Ordering::Relaxed),
+            reconnects: self.reconnects.load(Ordering::Relaxed),
+            average_interval,
+            last_frame_age,
+        }
+    }
+}
+
+/// Video format a receiver asks the sender for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecvColorFormat {
+    /// UYVY (BGRA when the source has alpha); converted to BGRA by us
+    #[default]
+    Uyvy,
+    /// BGRX (BGRA with alpha); the NDI library converts while decoding
+    Bgra,
+}
+
+impl RecvColorFormat {
+    /// Parse a format name from configuration ("uyvy", "bgra")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "uyvy" => Ok(RecvColorFormat::Uyvy),
+            "bgra" => Ok(RecvColorFormat::Bgra),
+            other => Err(anyhow::anyhow!(
+                "Unsupported receive color format: {}. Supported: uyvy, bgra",
+                other
+            )),
+        }
+    }
+
+    fn raw(self) -> c_int {
+        match self {
+            RecvColorFormat::Uyvy => NDILIB_RECV_COLOR_FORMAT_UYVY_BGRA,
+            RecvColorFormat::Bgra => NDILIB_RECV_COLOR_FORMAT_BGRX_BGRA,
+        }
+    }
+
+    /// Where the conversion to the framebuffer's BGRA happens, for the log
+    fn cost(self) -> &'static str {
+        match self {
+            RecvColorFormat::Uyvy => "converted to BGRA in software every frame",
+            RecvColorFormat::Bgra => {
+                "converted by the NDI decoder, frames twice the size in memory"
+            }
+        }
+    }
 }
 
 /// NDI receiver wrapper - receives video from an NDI source
 pub struct NdiReceiver {
     lib: Arc<NdiLib>,
+    capture: NDIlib_recv_capture_v3_fn,
     receiver: *mut c_void,
     source_name: String,
+    stats: Arc<NdiReceiverStats>,
+    // Captures so far, to log the first few frame types
+    captures: u64,
 }
 
-// SAFETY: NdiReceiver uses thread-safe NDI operations
+// SAFETY: a receive instance may be moved to another thread; it is only
+// ever used through &mut self, so calls on it never run concurrently
 unsafe impl Send for NdiReceiver {}
 
 impl NdiReceiver {
     /// Find and connect to an NDI source by name
     /// Blocks until the source is found (with timeout)
-    pub fn connect(source_name: &str, timeout_secs: u32) -> Result<Self> {
+    pub fn connect(
+        source_name: &str,
+        timeout_secs: u32,
+        color_format: RecvColorFormat,
+    ) -> Result<Self, NdiError> {
+        Self::connect_with_stats(source_name, timeout_secs, color_format, Arc::default())
+    }
+
+    /// Connect like [`NdiReceiver::connect`], counting into shared `stats`
+    pub fn connect_with_stats(
+        source_name: &str,
+        timeout_secs: u32,
+        color_format: RecvColorFormat,
+        stats: Arc<NdiReceiverStats>,
+    ) -> Result<Self, NdiError> {
         let lib = Arc::new(NdiLib::load()?);
+        let recv_v3 = lib
+            .recv_v3
+            .ok_or(NdiError::Unsupported(NdiFeature::RecvV3))?;
 
         tracing::info!("Searching for NDI source: {}", source_name);
 
@@ -723,7 +1795,7 @@ impl NdiReceiver {
 
         let finder = unsafe { (lib.find_create_v2)(&find_create) };
         if finder.is_null() {
-            anyhow::bail!("Failed to create NDI finder");
+            return Err(NdiError::FinderCreate);
         }
 
         // Search for source with timeout
@@ -735,25 +1807,13 @@ impl NdiReceiver {
             // Wait for sources (1 second intervals)
             unsafe { (lib.find_wait_for_sources)(finder, 1000) };
 
-            // Get current sources
-            let mut num_sources: u32 = 0;
-            let sources = unsafe { (lib.find_get_current_sources)(finder, &mut num_sources) };
-
-            if num_sources > 0 && !sources.is_null() {
-                for i in 0..num_sources {
-                    let source = unsafe { *sources.add(i as usize) };
-                    if !source.p_ndi_name.is_null() {
-                        let name = unsafe { CStr::from_ptr(source.p_ndi_name) }
-                            .to_string_lossy()
-                            .to_string();
-                        tracing::debug!("Found NDI source: {}", name);
-
-                        if name.contains(source_name) {
-                            tracing::info!("Found matching source: {}", name);
-                            found_source = Some(source);
-                            break;
-                        }
-                    }
+            for (source, name) in unsafe { current_sources(&lib, finder) } {
+                tracing::debug!("Found NDI source: {}", name);
+
+                if name.contains(source_name) {
+                    tracing::info!("Found matching source: {}", name);
+                    found_source = Some(source);
+                    break;
                 }
             }
 
@@ -766,7 +1826,9 @@ impl NdiReceiver {
             Some(s) => s,
             None => {
                 unsafe { (lib.find_destroy)(finder) };
-                anyhow::bail!("NDI source '{}' not found within timeout", source_name);
+                return Err(NdiError::SourceNotFound {
+                    name: source_name.to_string(),
+                });
             }
         };
 
@@ -774,38 +1836,45 @@ impl NdiReceiver {
         let recv_name = CString::new("camera-box-display").unwrap();
         let recv_create = NDIlib_recv_create_v3_t {
             source_to_connect_to: source,
-            color_format: NDILIB_RECV_COLOR_FORMAT_UYVY_BGRA,
+            color_format: color_format.raw(),
             bandwidth: NDILIB_RECV_BANDWIDTH_HIGHEST,
             allow_video_fields: false,
             p_ndi_recv_name: recv_name.as_ptr(),
         };
 
-        let receiver = unsafe { (lib.recv_create_v3)(&recv_create) };
+        let receiver = unsafe { (recv_v3.create)(&recv_create) };
         if receiver.is_null() {
             // Cleanup finder before error
             unsafe { (lib.find_destroy)(finder) };
-            anyhow::bail!("Failed to create NDI receiver");
+            return Err(NdiError::ReceiverCreate);
         }
 
         // NOW we can cleanup finder - receiver has copied the source info
         unsafe { (lib.find_destroy)(finder) };
 
-        tracing::info!("NDI receiver connected to source");
+        tracing::info!(
+            "NDI receiver connected to source, color format {:?} ({})",
+            color_format,
+            color_format.cost()
+        );
 
         Ok(Self {
             lib,
+            capture: recv_v3.capture,
             receiver,
             source_name: source_name.to_string(),
+            stats,
+            captures: 0,
         })
     }
 
     /// Capture next video frame (blocking with timeout)
     /// Returns None if no frame available within timeout
-    pub fn capture_frame(&mut self, timeout_ms: u32) -> Result<Option<ReceivedFrame>> {
+    pub fn capture_frame(&mut self, timeout_ms: u32) -> Result<Option<ReceivedFrame>, NdiError> {
         let mut video_frame: NDIlib_video_frame_v2_recv_t = unsafe { std::mem::zeroed() };
 
         let frame_type = unsafe {
-            (self.lib.recv_capture_v3)(
+            (self.capture)(
                 self.receiver,
                 &mut video_frame,
                 ptr::null_mut(), // no audio
@@ -815,94 +1884,285 @@ impl NdiReceiver {
         };
 
         // Debug: log frame type occasionally
-        static mut FRAME_TYPE_LOG_COUNT: u64 = 0;
+        self.captures += 1;
+        if self.captures <= 5 || self.captures.is_multiple_of(100) {
+            tracing::debug!(
+                "NDI recv frame_type={} (0=none, 1=video, 2=audio, 3=meta, 4=error)",
+                frame_type
+            );
+        }
+
+        if frame_type != NDILIB_FRAME_TYPE_VIDEO {
+            self.stats.record(frame_type, 0, Instant::now());
+            return Ok(None);
+        }
+
+        // Copy the frame data out, then give the frame back: the receiver
+        // reuses the buffer, and a frame without data must be freed too
+        let data_size = received_frame_size(
+            video_frame.fourcc,
+            video_frame.line_stride_in_bytes.max(0) as usize,
+            video_frame.yres.max(0) as usize,
+        );
+        let data = (!video_frame.p_data.is_null() && data_size > 0)
+            .then(|| unsafe { std::slice::from_raw_parts(video_frame.p_data, data_size).to_vec() });
         unsafe {
-            FRAME_TYPE_LOG_COUNT += 1;
-            if FRAME_TYPE_LOG_COUNT <= 5 || FRAME_TYPE_LOG_COUNT.is_multiple_of(100) {
-                tracing::debug!(
-                    "NDI recv frame_type={} (0=none, 1=video, 2=audio, 3=meta, 4=error)",
-                    frame_type
-                );
+            (self.lib.recv_free_video_v2)(self.receiver, &video_frame);
+        }
+        let Some(data) = data else {
+            return Ok(None);
+        };
+        self.stats
+            .record(NDILIB_FRAME_TYPE_VIDEO, data_size, Instant::now());
+
+        let frame = ReceivedFrame {
+            width: video_frame.xres as u32,
+            height: video_frame.yres as u32,
+            fourcc: video_frame.fourcc,
+            stride: video_frame.line_stride_in_bytes as u32,
+            data,
+            picture_aspect_ratio: video_frame.picture_aspect_ratio,
+            timestamp: video_frame.timestamp,
+            timecode: video_frame.timecode,
+        };
+        Ok(Some(frame))
+    }
+
+    /// Get source name
+    #[allow(dead_code)]
+    pub fn source_name(&self) -> &str {
+        &self.source_name
+    }
+
+    /// Counters of this receiver (shared if connected with stats)
+    pub fn stats(&self) -> &Arc<NdiReceiverStats> {
+        &self.stats
+    }
+}
+
+impl Drop for NdiReceiver {
+    fn drop(&mut self) {
+        if !self.receiver.is_null() {
+            unsafe {
+                (self.lib.recv_destroy)(self.receiver);
             }
         }
+    }
+}
+
+// ============================================================================
+// UYVY Scratch Buffer
+// ============================================================================
+
+/// Conversion target reused across frames. Sized by the first frame and
+/// only reallocated when the frame size grows, so steady-state capture
+/// (including switching formats at the same resolution) never allocates.
+#[derive(Debug, Default)]
+pub struct UyvyBuffer {
+    data: Vec<u8>,
+    reallocations: u64,
+}
+
+impl UyvyBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resize to `len` bytes and return the writable contents
+    pub fn prepare(&mut self, len: usize) -> &mut [u8] {
+        let sized = self.data.capacity() > 0;
+        let before = self.data.as_ptr();
+        self.data.resize(len, 0);
+        if sized && self.data.as_ptr() != before {
+            self.reallocations += 1;
+            tracing::debug!("UYVY buffer reallocated for {} bytes", len);
+        }
+        &mut self.data
+    }
+
+    /// Reserve room for frames of `len` bytes without resizing
+    pub fn reserve(&mut self, len: usize) {
+        self.data.reserve_exact(len.saturating_sub(self.data.len()));
+    }
+
+    /// Convert YUYV, using AVX2 when `use_avx2` is set and supported
+    pub fn convert_yuyv(&mut self, yuyv: &[u8], use_avx2: bool) {
+        let dst = self.prepare(yuyv.len() / 4 * 4);
+
+        #[cfg(target_arch = "x86_64")]
+        if use_avx2 && has_avx2() {
+            // SAFETY: AVX2 support checked above
+            unsafe { convert_yuyv_to_uyvy_avx2_into(yuyv, dst) };
+            return;
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = use_avx2;
 
-        if frame_type != NDILIB_FRAME_TYPE_VIDEO {
-            return Ok(None);
+        convert_yuyv_to_uyvy_scalar_into(yuyv, dst);
+    }
+
+    /// Convert YUYV from a mapping that may be uncached (a DMA-BUF), using
+    /// AVX2 streaming loads when `use_avx2` is set and supported
+    pub fn convert_yuyv_uncached(&mut self, yuyv: &[u8], use_avx2: bool) {
+        let dst = self.prepare(yuyv.len() / 4 * 4);
+
+        #[cfg(target_arch = "x86_64")]
+        if use_avx2 && has_avx2() {
+            // SAFETY: AVX2 support checked above
+            unsafe { convert_yuyv_to_uyvy_avx2_stream_into(yuyv, dst) };
+            return;
         }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = use_avx2;
 
-        // Copy frame data (receiver may reuse buffer)
-        let data_size = (video_frame.line_stride_in_bytes * video_frame.yres) as usize;
-        let data = if !video_frame.p_data.is_null() && data_size > 0 {
-            unsafe { std::slice::from_raw_parts(video_frame.p_data, data_size).to_vec() }
-        } else {
-            return Ok(None);
-        };
+        convert_yuyv_to_uyvy_scalar_into(yuyv, dst);
+    }
 
-        let frame = ReceivedFrame {
-            width: video_frame.xres as u32,
-            height: video_frame.yres as u32,
-            fourcc: video_frame.fourcc,
-            stride: video_frame.line_stride_in_bytes as u32,
-            data,
-        };
+    pub fn convert_nv12(&mut self, nv12: &[u8], width: usize, height: usize) {
+        let dst = self.prepare(uyvy_frame_size(width, height));
+        convert_nv12_to_uyvy_into(nv12, width, height, dst);
+    }
 
-        // Free the NDI frame
-        unsafe {
-            (self.lib.recv_free_video_v2)(self.receiver, &video_frame);
+    /// Convert NV12 from separate luma and chroma planes with line stride `stride`
+    pub fn convert_nv12_planes(
+        &mut self,
+        y_plane: &[u8],
+        uv_plane: &[u8],
+        width: usize,
+        height: usize,
+        stride: usize,
+    ) {
+        let dst = self.prepare(uyvy_frame_size(width, height));
+        convert_nv12_planes_to_uyvy_into(y_plane, uv_plane, width, height, stride, dst);
+    }
+
+    /// Convert BGRA with line stride `stride`
+    pub fn convert_bgra(&mut self, bgra: &[u8], width: usize, height: usize, stride: usize) {
+        let dst = self.prepare(uyvy_frame_size(width, height));
+        convert_bgra_to_uyvy_into(bgra, width, height, stride, ColorRange::Limited, dst);
+    }
+
+    /// Copy UYVY with line stride `stride`, dropping any row padding
+    pub fn copy_uyvy(&mut self, uyvy: &[u8], width: usize, height: usize, stride: usize) {
+        let row_bytes = uyvy_frame_size(width, 1);
+        let dst = self.prepare(uyvy_frame_size(width, height));
+        for (row, out) in dst.chunks_exact_mut(row_bytes).enumerate() {
+            let start = (row * stride).min(uyvy.len());
+            let src = &uyvy[start..(start + row_bytes).min(uyvy.len())];
+            out[..src.len()].copy_from_slice(src);
         }
+    }
 
-        Ok(Some(frame))
+    /// Compress the converted frame from full to limited range in place
+    pub fn compress_full_range(&mut self) {
+        uyvy_full_to_limited(&mut self.data);
     }
 
-    /// Get source name
-    #[allow(dead_code)]
-    pub fn source_name(&self) -> &str {
-        &self.source_name
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.data.as_ptr()
+    }
+
+    /// Times the storage moved after the initial sizing
+    pub fn reallocations(&self) -> u64 {
+        self.reallocations
     }
 }
 
-impl Drop for NdiReceiver {
-    fn drop(&mut self) {
-        if !self.receiver.is_null() {
-            unsafe {
-                (self.lib.recv_destroy)(self.receiver);
-            }
-        }
+/// UYVY bytes for a frame (pixel pairs of 4 bytes; odd widths round up)
+pub fn uyvy_frame_size(width: usize, height: usize) -> usize {
+    width.div_ceil(2) * 4 * height
+}
+
+/// Put a decoded MJPEG frame into `out` at the negotiated `width`x`height`.
+/// Some dongles send 1280x720 JPEGs while claiming 1920x1080, and ffmpeg
+/// decodes at the JPEG's own size, so the size is taken from the JPEG
+/// header, checked against the decoded bytes and scaled when it differs.
+/// Returns the size the JPEG was decoded at.
+pub fn fit_decoded_mjpeg(
+    mjpeg: &[u8],
+    decoded: &[u8],
+    width: u32,
+    height: u32,
+    out: &mut UyvyBuffer,
+) -> std::result::Result<(u32, u32), SendError> {
+    let corrupt = |message: String| {
+        SendError::new(
+            SendFailure::ConversionFailed,
+            SendErrorKind::Transient,
+            anyhow::anyhow!(message),
+        )
+    };
+    let (jpeg_width, jpeg_height) = jpeg_dimensions(mjpeg)
+        .ok_or_else(|| corrupt("MJPEG frame has no frame header".to_string()))?;
+    let expected = uyvy_frame_size(jpeg_width as usize, jpeg_height as usize);
+    if decoded.len() != expected {
+        return Err(corrupt(format!(
+            "Decoded {} bytes for a {}x{} JPEG, expected {}",
+            decoded.len(),
+            jpeg_width,
+            jpeg_height,
+            expected
+        )));
+    }
+    let len = uyvy_frame_size(width as usize, height as usize);
+    // Copy into the scratch buffer rather than replacing it, so the next
+    // raw frame doesn't reallocate
+    if (jpeg_width, jpeg_height) == (width, height) {
+        out.prepare(len).copy_from_slice(decoded);
+    } else {
+        let scaled = scale_uyvy_nearest(decoded, jpeg_width, jpeg_height, width, height);
+        out.prepare(len).copy_from_slice(&scaled);
     }
+    Ok((jpeg_width, jpeg_height))
 }
 
 // ============================================================================
 // Standalone conversion functions for testing (without NDI library dependency)
 // ============================================================================
 
+/// Convert YUYV to UYVY into `dst` using scalar method
+/// YUYV: Y0 U0 Y1 V0 -> UYVY: U0 Y0 V0 Y1
+pub fn convert_yuyv_to_uyvy_scalar_into(yuyv: &[u8], dst: &mut [u8]) {
+    for (src, out) in yuyv.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+        out[0] = src[1]; // U0
+        out[1] = src[0]; // Y0
+        out[2] = src[3]; // V0
+        out[3] = src[2]; // Y1
+    }
+}
+
 /// Convert YUYV to UYVY using scalar method (standalone for testing)
 /// YUYV: Y0 U0 Y1 V0 -> UYVY: U0 Y0 V0 Y1
 pub fn convert_yuyv_to_uyvy_scalar(yuyv: &[u8]) -> Vec<u8> {
-    let mut uyvy = Vec::with_capacity(yuyv.len());
-    for chunk in yuyv.chunks_exact(4) {
-        uyvy.push(chunk[1]); // U0
-        uyvy.push(chunk[0]); // Y0
-        uyvy.push(chunk[3]); // V0
-        uyvy.push(chunk[2]); // Y1
-    }
+    let mut uyvy = vec![0u8; yuyv.len() / 4 * 4];
+    convert_yuyv_to_uyvy_scalar_into(yuyv, &mut uyvy);
     uyvy
 }
 
-/// Convert YUYV to UYVY using AVX2 SIMD (standalone for testing)
+/// Convert YUYV to UYVY into `dst` using AVX2 SIMD - processes 32 pixels
+/// (64 bytes) per iteration, ~16x faster than scalar for 1080p frames
 ///
 /// # Safety
 /// This function requires AVX2 CPU support. The caller must verify AVX2 is available
 /// using `has_avx2()` before calling. Calling on a CPU without AVX2 is undefined behavior.
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2")]
-pub unsafe fn convert_yuyv_to_uyvy_avx2(yuyv: &[u8]) -> Vec<u8> {
-    let total_bytes = yuyv.len();
+pub unsafe fn convert_yuyv_to_uyvy_avx2_into(yuyv: &[u8], dst: &mut [u8]) {
+    let total_bytes = (yuyv.len() / 4 * 4).min(dst.len() / 4 * 4);
     let avx_bytes = (total_bytes / 64) * 64;
-
-    let mut uyvy = vec![0u8; total_bytes];
-    let dst = uyvy.as_mut_ptr();
+    let src = yuyv.as_ptr();
+    let dst = dst.as_mut_ptr();
 
     // Shuffle mask to convert YUYV to UYVY
+    // YUYV: Y0 U0 Y1 V0 (indices 0,1,2,3) -> UYVY: U0 Y0 V0 Y1 (indices 1,0,3,2)
     let shuffle_mask = _mm256_setr_epi8(
         1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 11, 10, 13, 12, 15, 14, 1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 11, 10,
         13, 12, 15, 14,
@@ -910,8 +2170,8 @@ pub unsafe fn convert_yuyv_to_uyvy_avx2(yuyv: &[u8]) -> Vec<u8> {
 
     let mut i = 0;
     while i < avx_bytes {
-        let data0 = _mm256_loadu_si256(yuyv.as_ptr().add(i) as *const __m256i);
-        let data1 = _mm256_loadu_si256(yuyv.as_ptr().add(i + 32) as *const __m256i);
+        let data0 = _mm256_loadu_si256(src.add(i) as *const __m256i);
+        let data1 = _mm256_loadu_si256(src.add(i + 32) as *const __m256i);
 
         let result0 = _mm256_shuffle_epi8(data0, shuffle_mask);
         let result1 = _mm256_shuffle_epi8(data1, shuffle_mask);
@@ -924,10 +2184,10 @@ pub unsafe fn convert_yuyv_to_uyvy_avx2(yuyv: &[u8]) -> Vec<u8> {
 
     // Handle remaining bytes with scalar code
     while i < total_bytes {
-        let y0 = *yuyv.get_unchecked(i);
-        let u = *yuyv.get_unchecked(i + 1);
-        let y1 = *yuyv.get_unchecked(i + 2);
-        let v = *yuyv.get_unchecked(i + 3);
+        let y0 = *src.add(i);
+        let u = *src.add(i + 1);
+        let y1 = *src.add(i + 2);
+        let v = *src.add(i + 3);
 
         *dst.add(i) = u;
         *dst.add(i + 1) = y0;
@@ -936,50 +2196,150 @@ pub unsafe fn convert_yuyv_to_uyvy_avx2(yuyv: &[u8]) -> Vec<u8> {
 
         i += 4;
     }
+}
+
+/// Convert YUYV to UYVY into `dst` like [`convert_yuyv_to_uyvy_avx2_into`],
+/// reading with non-temporal streaming loads (`_mm256_stream_load_si256`),
+/// which skip the slow uncached reads of a write-combined DMA-BUF mapping.
+/// Bytes before the first 32-byte aligned pixel pair are converted scalar.
+///
+/// # Safety
+/// This function requires AVX2 CPU support. The caller must verify AVX2 is available
+/// using `has_avx2()` before calling. Calling on a CPU without AVX2 is undefined behavior.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub unsafe fn convert_yuyv_to_uyvy_avx2_stream_into(yuyv: &[u8], dst: &mut [u8]) {
+    let total_bytes = (yuyv.len() / 4 * 4).min(dst.len() / 4 * 4);
+    let head = yuyv.as_ptr().align_offset(32);
+    if head % 4 != 0 || head > total_bytes {
+        // Pixel pairs never line up with 32 bytes: plain unaligned loads
+        convert_yuyv_to_uyvy_avx2_into(yuyv, dst);
+        return;
+    }
+    convert_yuyv_to_uyvy_scalar_into(&yuyv[..head], &mut dst[..head]);
+
+    let avx_end = head + (total_bytes - head) / 64 * 64;
+    let src = yuyv.as_ptr();
+    let out = dst.as_mut_ptr();
+    let shuffle_mask = _mm256_setr_epi8(
+        1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 11, 10, 13, 12, 15, 14, 1, 0, 3, 2, 5, 4, 7, 6, 9, 8, 11, 10,
+        13, 12, 15, 14,
+    );
+
+    let mut i = head;
+    while i < avx_end {
+        // Aligned: `head` is the offset to the first 32-byte boundary
+        let data0 = _mm256_stream_load_si256(src.add(i) as *const __m256i);
+        let data1 = _mm256_stream_load_si256(src.add(i + 32) as *const __m256i);
+
+        _mm256_storeu_si256(
+            out.add(i) as *mut __m256i,
+            _mm256_shuffle_epi8(data0, shuffle_mask),
+        );
+        _mm256_storeu_si256(
+            out.add(i + 32) as *mut __m256i,
+            _mm256_shuffle_epi8(data1, shuffle_mask),
+        );
+
+        i += 64;
+    }
+
+    convert_yuyv_to_uyvy_scalar_into(&yuyv[avx_end..total_bytes], &mut dst[avx_end..total_bytes]);
+}
 
+/// Convert YUYV to UYVY using AVX2 SIMD (standalone for testing)
+///
+/// # Safety
+/// This function requires AVX2 CPU support. The caller must verify AVX2 is available
+/// using `has_avx2()` before calling. Calling on a CPU without AVX2 is undefined behavior.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+pub unsafe fn convert_yuyv_to_uyvy_avx2(yuyv: &[u8]) -> Vec<u8> {
+    let mut uyvy = vec![0u8; yuyv.len() / 4 * 4];
+    convert_yuyv_to_uyvy_avx2_into(yuyv, &mut uyvy);
     uyvy
 }
 
-/// Convert NV12 to UYVY (standalone for testing)
-pub fn convert_nv12_to_uyvy(nv12: &[u8], width: usize, height: usize) -> Vec<u8> {
+/// Convert NV12 to UYVY into `dst` (length `uyvy_frame_size(width, height)`)
+pub fn convert_nv12_to_uyvy_into(nv12: &[u8], width: usize, height: usize, dst: &mut [u8]) {
     let y_size = width * height;
-    let mut uyvy = Vec::with_capacity(width * height * 2);
-
     let y_plane = &nv12[..y_size.min(nv12.len())];
     let uv_plane = if nv12.len() > y_size {
         &nv12[y_size..]
     } else {
         &[]
     };
+    convert_nv12_planes_to_uyvy_into(y_plane, uv_plane, width, height, width, dst);
+}
 
+/// Convert NV12 held as separate Y and interleaved UV planes (both with line
+/// stride `stride`) to UYVY into `dst`
+pub fn convert_nv12_planes_to_uyvy_into(
+    y_plane: &[u8],
+    uv_plane: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    dst: &mut [u8],
+) {
+    let stride = stride.max(width);
+    let mut out = dst.chunks_exact_mut(4);
     for row in 0..height {
         let uv_row = row / 2;
         for col in (0..width).step_by(2) {
-            let y0 = y_plane.get(row * width + col).copied().unwrap_or(128);
-            let y1 = y_plane.get(row * width + col + 1).copied().unwrap_or(128);
-            let uv_idx = uv_row * width + col;
+            let Some(px) = out.next() else {
+                return;
+            };
+            let y0 = y_plane.get(row * stride + col).copied().unwrap_or(128);
+            let y1 = y_plane.get(row * stride + col + 1).copied().unwrap_or(128);
+            let uv_idx = uv_row * stride + col;
             let u = uv_plane.get(uv_idx).copied().unwrap_or(128);
             let v = uv_plane.get(uv_idx + 1).copied().unwrap_or(128);
 
-            uyvy.push(u);
-            uyvy.push(y0);
-            uyvy.push(v);
-            uyvy.push(y1);
+            // UYVY: U Y0 V Y1
+            px.copy_from_slice(&[u, y0, v, y1]);
         }
     }
+}
 
+/// Convert NV12 to UYVY (standalone for testing)
+pub fn convert_nv12_to_uyvy(nv12: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut uyvy = vec![0u8; uyvy_frame_size(width, height)];
+    convert_nv12_to_uyvy_into(nv12, width, height, &mut uyvy);
     uyvy
 }
 
-/// Convert BGRA to UYVY (standalone for testing)
-pub fn convert_bgra_to_uyvy(bgra: &[u8], width: usize, height: usize) -> Vec<u8> {
-    let mut uyvy = Vec::with_capacity(width * height * 2);
-
+/// Convert BGRA with line stride `stride` to UYVY in the given output
+/// `range` into `dst` (length `uyvy_frame_size(width, height)`). Only the
+/// first `width * 4` bytes of each row are read.
+pub fn convert_bgra_to_uyvy_into(
+    bgra: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    range: ColorRange,
+    dst: &mut [u8],
+) {
+    let stride = stride.max(width * 4);
+    debug_assert!(
+        bgra.len() >= stride * height,
+        "BGRA frame is {} bytes, expected {} for {}x{} with stride {}",
+        bgra.len(),
+        stride * height,
+        width,
+        height,
+        stride
+    );
+    let mut out = dst.chunks_exact_mut(4);
     for row in 0..height {
         for col in (0..width).step_by(2) {
-            let idx0 = (row * width + col) * 4;
-            let idx1 = (row * width + col + 1) * 4;
+            let Some(px) = out.next() else {
+                return;
+            };
+            let idx0 = row * stride + col * 4;
+            let idx1 = idx0 + 4;
 
+            // BGRA to YUV conversion (BT.601)
             let (b0, g0, r0) = (
                 bgra.get(idx0).copied().unwrap_or(0) as i32,
                 bgra.get(idx0 + 1).copied().unwrap_or(0) as i32,
@@ -991,22 +2351,50 @@ pub fn convert_bgra_to_uyvy(bgra: &[u8], width: usize, height: usize) -> Vec<u8>
                 bgra.get(idx1 + 2).copied().unwrap_or(0) as i32,
             );
 
-            let y0 = ((66 * r0 + 129 * g0 + 25 * b0 + 128) >> 8) + 16;
-            let y1 = ((66 * r1 + 129 * g1 + 25 * b1 + 128) >> 8) + 16;
-
+            // Average for U/V
             let r = (r0 + r1) / 2;
             let g = (g0 + g1) / 2;
             let b = (b0 + b1) / 2;
-            let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
-            let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
 
-            uyvy.push(u.clamp(0, 255) as u8);
-            uyvy.push(y0.clamp(16, 235) as u8);
-            uyvy.push(v.clamp(0, 255) as u8);
-            uyvy.push(y1.clamp(16, 235) as u8);
+            let (y0, y1, u, v, luma) = match range {
+                ColorRange::Limited => (
+                    ((66 * r0 + 129 * g0 + 25 * b0 + 128) >> 8) + 16,
+                    ((66 * r1 + 129 * g1 + 25 * b1 + 128) >> 8) + 16,
+                    ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128,
+                    ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128,
+                    16..=235,
+                ),
+                ColorRange::Full => (
+                    (77 * r0 + 150 * g0 + 29 * b0 + 128) >> 8,
+                    (77 * r1 + 150 * g1 + 29 * b1 + 128) >> 8,
+                    ((-43 * r - 85 * g + 128 * b + 128) >> 8) + 128,
+                    ((128 * r - 107 * g - 21 * b + 128) >> 8) + 128,
+                    0..=255,
+                ),
+            };
+
+            // UYVY: U Y0 V Y1
+            px.copy_from_slice(&[
+                u.clamp(0, 255) as u8,
+                y0.clamp(*luma.start(), *luma.end()) as u8,
+                v.clamp(0, 255) as u8,
+                y1.clamp(*luma.start(), *luma.end()) as u8,
+            ]);
         }
     }
+}
 
+/// Convert BGRA with line stride `stride` to UYVY in the given output
+/// range (standalone for testing)
+pub fn convert_bgra_to_uyvy(
+    bgra: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    range: ColorRange,
+) -> Vec<u8> {
+    let mut uyvy = vec![0u8; uyvy_frame_size(width, height)];
+    convert_bgra_to_uyvy_into(bgra, width, height, stride, range, &mut uyvy);
     uyvy
 }
 
@@ -1024,6 +2412,151 @@ pub fn has_avx2() -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicU32, AtomicU8, Ordering};
+
+    /// Status of a sender switched between program and preview by the test
+    #[derive(Default)]
+    struct StubStatus {
+        tally: AtomicU8,
+        polls: AtomicU32,
+    }
+
+    impl SenderStatus for StubStatus {
+        fn connections(&self) -> u32 {
+            1
+        }
+
+        fn tally(&self) -> Tally {
+            self.polls.fetch_add(1, Ordering::Relaxed);
+            match self.tally.load(Ordering::Relaxed) {
+                2 => Tally::Program,
+                1 => Tally::Preview,
+                _ => Tally::Off,
+            }
+        }
+    }
+
+    #[test]
+    fn test_receiver_stats_accumulate() {
+        let stats = NdiReceiverStats::new();
+        let t0 = Instant::now();
+        let empty = stats.snapshot(t0);
+        assert_eq!(empty.video_frames, 0);
+        assert_eq!(empty.average_interval, None);
+        assert_eq!(empty.last_frame_age, None);
+
+        // Three video frames 20 ms apart, with other frame types between
+        for i in 0..3 {
+            let at = t0 + Duration::from_millis(20 * i);
+            stats.record(NDILIB_FRAME_TYPE_VIDEO, 1000, at);
+            stats.record(NDILIB_FRAME_TYPE_NONE, 0, at);
+        }
+        stats.record(NDILIB_FRAME_TYPE_AUDIO, 0, t0);
+        stats.record(NDILIB_FRAME_TYPE_METADATA, 0, t0);
+        stats.record(NDILIB_FRAME_TYPE_STATUS_CHANGE, 0, t0);
+        stats.record(NDILIB_FRAME_TYPE_ERROR, 0, t0);
+        stats.record(42, 0, t0);
+
+        let snapshot = stats.snapshot(t0 + Duration::from_millis(100));
+        assert_eq!(snapshot.video_frames, 3);
+        assert_eq!(snapshot.timeouts, 3);
+        assert_eq!(snapshot.audio_frames, 1);
+        assert_eq!(snapshot.metadata_frames, 1);
+        assert_eq!(snapshot.status_changes, 1);
+        assert_eq!(snapshot.errors, 1);
+        assert_eq!(snapshot.bytes_received, 3000);
+        assert_eq!(snapshot.average_interval, Some(Duration::from_millis(20)));
+        assert_eq!(snapshot.last_frame_age, Some(Duration::from_millis(60)));
+    }
+
+    #[test]
+    fn test_receiver_stats_single_frame() {
+        let stats = NdiReceiverStats::new();
+        let t0 = Instant::now();
+        stats.record(NDILIB_FRAME_TYPE_VIDEO, 10, t0);
+        let snapshot = stats.snapshot(t0);
+        assert_eq!(snapshot.average_interval, None);
+        assert_eq!(snapshot.last_frame_age, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_recv_color_format_from_name() {
+        let uyvy = RecvColorFormat::from_name("uyvy").unwrap();
+        let bgra = RecvColorFormat::from_name("BGRA").unwrap();
+        assert_eq!(uyvy, RecvColorFormat::default());
+        assert_eq!(uyvy.raw(), NDILIB_RECV_COLOR_FORMAT_UYVY_BGRA);
+        assert_eq!(bgra.raw(), NDILIB_RECV_COLOR_FORMAT_BGRX_BGRA);
+        assert!(RecvColorFormat::from_name("rgb").is_err());
+    }
+
+    #[test]
+    fn test_keepalive_pacing() {
+        let t0 = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut pacer = KeepalivePacer::new(second);
+        assert_eq!(pacer.wait(t0), Duration::ZERO);
+
+        // Due immediately, then once per interval
+        assert!(pacer.due(t0));
+        assert!(!pacer.due(t0));
+        assert!(!pacer.due(t0 + Duration::from_millis(999)));
+        assert_eq!(
+            pacer.wait(t0 + Duration::from_millis(400)),
+            Duration::from_millis(600)
+        );
+        assert!(pacer.due(t0 + second));
+
+        // Late by 2.5 intervals: one frame, not a burst of catch-up frames
+        let late = t0 + Duration::from_millis(4500);
+        assert!(pacer.due(late));
+        assert!(!pacer.due(late + Duration::from_millis(1)));
+        assert!(pacer.due(late + second));
+        assert_eq!(pacer.wait(late + Duration::from_secs(5)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_sender_types_are_thread_safe() {
+        fn assert_send<T: Send>() {}
+        fn assert_shared<T: Send + Sync + Clone + SenderStatus>() {}
+        assert_send::<NdiSender>();
+        assert_send::<NdiReceiver>();
+        assert_shared::<NdiSendHandle>();
+    }
+
+    #[test]
+    fn test_tally_polled_from_another_thread() {
+        let status = Arc::new(StubStatus::default());
+        let poller = {
+            let status: Arc<dyn SenderStatus> = status.clone();
+            std::thread::spawn(move || {
+                let mut seen = Vec::new();
+                while seen.last() != Some(&Tally::Program) {
+                    let tally = status.tally();
+                    if seen.last() != Some(&tally) {
+                        seen.push(tally);
+                    }
+                    std::thread::yield_now();
+                }
+                (seen, status.connections())
+            })
+        };
+
+        // The "sending" thread changes state while the poller watches
+        while status.polls.load(Ordering::Relaxed) == 0 {
+            std::thread::yield_now();
+        }
+        status.tally.store(1, Ordering::Relaxed);
+        let polls = status.polls.load(Ordering::Relaxed);
+        while status.polls.load(Ordering::Relaxed) < polls + 2 {
+            std::thread::yield_now();
+        }
+        status.tally.store(2, Ordering::Relaxed);
+
+        let (seen, connections) = poller.join().unwrap();
+        assert_eq!(seen.first(), Some(&Tally::Off));
+        assert_eq!(seen.last(), Some(&Tally::Program));
+        assert_eq!(connections, 1);
+    }
 
     #[test]
     fn test_yuyv_to_uyvy_scalar_basic() {
@@ -1098,6 +2631,35 @@ mod tests {
         }
     }
 
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_yuyv_to_uyvy_avx2_stream_matches_scalar() {
+        if !has_avx2() {
+            return;
+        }
+        // Every alignment of the source, including ones where pixel pairs
+        // never reach a 32-byte boundary
+        let yuyv: Vec<u8> = (0..4096 + 64).map(|i| (i * 7 % 251) as u8).collect();
+        for offset in 0..36 {
+            for len in [0, 4, 60, 64, 128, 1000, 4096] {
+                let src = &yuyv[offset..offset + len];
+                let mut streamed = vec![0u8; len];
+                unsafe { convert_yuyv_to_uyvy_avx2_stream_into(src, &mut streamed) };
+                assert_eq!(
+                    streamed,
+                    convert_yuyv_to_uyvy_scalar(src),
+                    "offset {} len {}",
+                    offset,
+                    len
+                );
+            }
+        }
+
+        let mut buffer = UyvyBuffer::new();
+        buffer.convert_yuyv_uncached(&yuyv, true);
+        assert_eq!(buffer.as_slice(), convert_yuyv_to_uyvy_scalar(&yuyv));
+    }
+
     #[cfg(target_arch = "x86_64")]
     #[test]
     fn test_yuyv_to_uyvy_avx2_non_aligned() {
@@ -1140,6 +2702,19 @@ mod tests {
         assert_eq!(uyvy[3], 110); // Y1
     }
 
+    #[test]
+    fn test_nv12_planes_with_padded_stride() {
+        // 2x2 frame with 4-byte line stride; padding bytes must be skipped
+        let y_plane = [100, 110, 0, 0, 120, 130, 0, 0];
+        let uv_plane = [64, 192, 0, 0];
+        let mut buffer = UyvyBuffer::new();
+        buffer.convert_nv12_planes(&y_plane, &uv_plane, 2, 2, 4);
+        assert_eq!(buffer.as_slice(), &[64, 100, 192, 110, 64, 120, 192, 130]);
+
+        let packed = [100, 110, 120, 130, 64, 192];
+        assert_eq!(buffer.as_slice(), convert_nv12_to_uyvy(&packed, 2, 2));
+    }
+
     #[test]
     fn test_nv12_to_uyvy_output_size() {
         // Full HD NV12
@@ -1157,7 +2732,7 @@ mod tests {
     fn test_bgra_to_uyvy_black() {
         // Black pixel: BGRA = (0, 0, 0, 255)
         let bgra = vec![0, 0, 0, 255, 0, 0, 0, 255]; // 2 black pixels
-        let uyvy = convert_bgra_to_uyvy(&bgra, 2, 1);
+        let uyvy = convert_bgra_to_uyvy(&bgra, 2, 1, 8, ColorRange::Limited);
 
         assert_eq!(uyvy.len(), 4);
         // Y should be ~16 (video black), U and V should be ~128 (neutral)
@@ -1171,7 +2746,7 @@ mod tests {
     fn test_bgra_to_uyvy_white() {
         // White pixel: BGRA = (255, 255, 255, 255)
         let bgra = vec![255, 255, 255, 255, 255, 255, 255, 255];
-        let uyvy = convert_bgra_to_uyvy(&bgra, 2, 1);
+        let uyvy = convert_bgra_to_uyvy(&bgra, 2, 1, 8, ColorRange::Limited);
 
         assert_eq!(uyvy.len(), 4);
         // Y should be 235 (video white)
@@ -1179,11 +2754,71 @@ mod tests {
         assert_eq!(uyvy[3], 235, "Y1 should be video white (235)");
     }
 
+    #[test]
+    fn test_bgra_to_uyvy_full_range() {
+        // Black, white and pure red as full and limited range UYVY
+        let black = [0, 0, 0, 255, 0, 0, 0, 255];
+        let white = [255; 8];
+        let red = [0, 0, 255, 255, 0, 0, 255, 255];
+        assert_eq!(
+            convert_bgra_to_uyvy(&black, 2, 1, 8, ColorRange::Full),
+            [128, 0, 128, 0]
+        );
+        assert_eq!(
+            convert_bgra_to_uyvy(&black, 2, 1, 8, ColorRange::Limited),
+            [128, 16, 128, 16]
+        );
+        assert_eq!(
+            convert_bgra_to_uyvy(&white, 2, 1, 8, ColorRange::Full),
+            [128, 255, 128, 255]
+        );
+        assert_eq!(
+            convert_bgra_to_uyvy(&red, 2, 1, 8, ColorRange::Full),
+            [85, 77, 255, 77]
+        );
+        assert_eq!(
+            convert_bgra_to_uyvy(&red, 2, 1, 8, ColorRange::Limited),
+            [90, 82, 240, 82]
+        );
+    }
+
+    #[test]
+    fn test_uyvy_buffer_copy_compresses_full_range() {
+        // Two rows of one pixel pair with 4 bytes of row padding
+        let uyvy = [128, 0, 128, 255, 9, 9, 9, 9, 0, 128, 255, 64, 9, 9, 9, 9];
+        let mut buffer = UyvyBuffer::new();
+        buffer.copy_uyvy(&uyvy, 2, 2, 8);
+        assert_eq!(buffer.as_slice(), [128, 0, 128, 255, 0, 128, 255, 64]);
+        buffer.compress_full_range();
+        assert_eq!(buffer.as_slice(), [128, 16, 128, 235, 16, 126, 240, 71]);
+    }
+
+    #[test]
+    fn test_bgra_to_uyvy_padded_rows() {
+        // 6x3 BGRA with rows padded to 32 bytes must match the tight frame
+        let (width, height, stride) = (6, 3, 32);
+        let tight: Vec<u8> = (0..width * height * 4)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect();
+        let mut padded = vec![0xEE; stride * height];
+        for (row, src) in tight.chunks_exact(width * 4).enumerate() {
+            padded[row * stride..row * stride + width * 4].copy_from_slice(src);
+        }
+        let expected = convert_bgra_to_uyvy(&tight, width, height, width * 4, ColorRange::Limited);
+        assert_eq!(
+            convert_bgra_to_uyvy(&padded, width, height, stride, ColorRange::Limited),
+            expected
+        );
+        let mut buffer = UyvyBuffer::new();
+        buffer.convert_bgra(&padded, width, height, stride);
+        assert_eq!(buffer.as_slice(), expected);
+    }
+
     #[test]
     fn test_bgra_to_uyvy_output_size() {
         for (width, height) in [(2, 1), (4, 2), (1920, 1080)] {
             let bgra = vec![128u8; width * height * 4];
-            let uyvy = convert_bgra_to_uyvy(&bgra, width, height);
+            let uyvy = convert_bgra_to_uyvy(&bgra, width, height, width * 4, ColorRange::Limited);
             assert_eq!(uyvy.len(), width * height * 2);
         }
     }
@@ -1215,6 +2850,22 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_received_frame_size_covers_every_plane() {
+        assert_eq!(
+            received_frame_size(NDILIBD_FOURCC_UYVY, 3840, 1080),
+            3840 * 1080
+        );
+        assert_eq!(
+            received_frame_size(NDILIBD_FOURCC_P216, 3840, 1080),
+            2 * 3840 * 1080
+        );
+        assert_eq!(
+            received_frame_size(NDILIBD_FOURCC_PA16, 3840, 1080),
+            3 * 3840 * 1080
+        );
+    }
+
     #[test]
     fn test_received_frame_construction() {
         let frame = ReceivedFrame {
@@ -1223,6 +2874,9 @@ mod tests {
             fourcc: NDILIBD_FOURCC_UYVY,
             stride: 3840,
             data: vec![0u8; 1920 * 1080 * 2],
+            picture_aspect_ratio: 0.0,
+            timestamp: 0,
+            timecode: 0,
         };
         assert_eq!(frame.width, 1920);
         assert_eq!(frame.height, 1080);
@@ -1230,6 +2884,156 @@ mod tests {
         assert_eq!(frame.data.len(), 1920 * 1080 * 2);
     }
 
+    #[test]
+    fn test_uyvy_buffer_matches_standalone() {
+        let yuyv: Vec<u8> = (0..64 * 4 * 2).map(|i| (i % 251) as u8).collect();
+        let nv12: Vec<u8> = (0..64 * 4 * 3 / 2).map(|i| (i % 241) as u8).collect();
+        let bgra: Vec<u8> = (0..64 * 4 * 4).map(|i| (i % 239) as u8).collect();
+        let mut buffer = UyvyBuffer::new();
+
+        buffer.convert_yuyv(&yuyv, true);
+        assert_eq!(buffer.as_slice(), convert_yuyv_to_uyvy_scalar(&yuyv));
+        buffer.convert_yuyv(&yuyv, false);
+        assert_eq!(buffer.as_slice(), convert_yuyv_to_uyvy_scalar(&yuyv));
+        buffer.convert_nv12(&nv12, 64, 4);
+        assert_eq!(buffer.as_slice(), convert_nv12_to_uyvy(&nv12, 64, 4));
+        buffer.convert_bgra(&bgra, 64, 4, 64 * 4);
+        assert_eq!(
+            buffer.as_slice(),
+            convert_bgra_to_uyvy(&bgra, 64, 4, 64 * 4, ColorRange::Limited)
+        );
+    }
+
+    #[test]
+    fn test_uyvy_buffer_soak_keeps_allocation() {
+        let (width, height) = (320, 180);
+        let yuyv = vec![128u8; width * height * 2];
+        let nv12 = vec![128u8; width * height * 3 / 2];
+        let bgra = vec![128u8; width * height * 4];
+        let mut buffer = UyvyBuffer::new();
+
+        buffer.convert_yuyv(&yuyv, true);
+        let ptr = buffer.as_ptr();
+        for frame in 0..1000 {
+            // Switching formats at the same resolution must not reallocate
+            match frame % 3 {
+                0 => buffer.convert_yuyv(&yuyv, frame % 2 == 0),
+                1 => buffer.convert_nv12(&nv12, width, height),
+                _ => buffer.convert_bgra(&bgra, width, height, width * 4),
+            }
+            assert_eq!(buffer.as_ptr(), ptr, "buffer moved at frame {}", frame);
+        }
+        assert_eq!(buffer.reallocations(), 0);
+
+        // Growing is the only reason to reallocate
+        buffer.convert_yuyv(&vec![128u8; width * height * 8], false);
+        buffer.convert_yuyv(&yuyv, false);
+        assert!(buffer.reallocations() <= 1);
+    }
+
+    #[test]
+    fn test_uyvy_buffer_reserved_for_4k() {
+        let (width, height) = (3840, 2160);
+        let mut buffer = UyvyBuffer::new();
+        buffer.reserve(uyvy_frame_size(width, height));
+        let ptr = buffer.as_ptr();
+        buffer.convert_yuyv(&vec![128u8; width * height * 2], false);
+        assert_eq!(buffer.as_ptr(), ptr);
+        assert_eq!(buffer.as_slice().len(), 3840 * 2160 * 2);
+
+        // 4K UYVY rows are 7680 bytes; padded ones are copied row by row
+        let stride = 7680 + 256;
+        let mut padded = vec![0u8; stride * height];
+        for (row, line) in padded.chunks_exact_mut(stride).enumerate() {
+            line[..7680].fill(row as u8);
+            line[7680..].fill(0xEE);
+        }
+        buffer.copy_uyvy(&padded, width, height, stride);
+        assert_eq!(buffer.as_ptr(), ptr);
+        assert_eq!(buffer.reallocations(), 0);
+        for (row, line) in buffer.as_slice().chunks_exact(7680).enumerate() {
+            assert!(line.iter().all(|&b| b == row as u8), "row {}", row);
+        }
+    }
+
+    #[test]
+    fn test_mismatched_mjpeg_is_scaled() {
+        // A dongle claiming 128x64 that sends 64x32 JPEGs
+        let decoded: Vec<u8> = (0..64 * 32 * 2).map(|i| (i % 233) as u8).collect();
+        let mjpeg = crate::jpeg::JpegEncoder::new(75).encode(&decoded, 64, 32);
+        let mut buffer = UyvyBuffer::new();
+
+        let size = fit_decoded_mjpeg(&mjpeg, &decoded, 128, 64, &mut buffer).unwrap();
+        assert_eq!(size, (64, 32));
+        assert_eq!(buffer.as_slice().len(), uyvy_frame_size(128, 64));
+        assert_eq!(
+            buffer.as_slice(),
+            scale_uyvy_nearest(&decoded, 64, 32, 128, 64)
+        );
+        assert_eq!(&buffer.as_slice()[..4], &decoded[..4]);
+
+        // The negotiated size is copied as is
+        let size = fit_decoded_mjpeg(&mjpeg, &decoded, 64, 32, &mut buffer).unwrap();
+        assert_eq!(size, (64, 32));
+        assert_eq!(buffer.as_slice(), decoded);
+
+        // Output that doesn't match the header is a corrupt frame
+        let error = fit_decoded_mjpeg(&mjpeg, &decoded[..1000], 64, 32, &mut buffer).unwrap_err();
+        assert_eq!(error.failure(), SendFailure::ConversionFailed);
+        assert_eq!(error.kind(), SendErrorKind::Transient);
+        assert!(fit_decoded_mjpeg(b"garbage", &decoded, 64, 32, &mut buffer).is_err());
+    }
+
+    #[test]
+    fn test_parse_major_version() {
+        assert_eq!(
+            parse_major_version("NDI SDK LINUX 12:21:33 Jun 25 2024 6.0.1.0"),
+            Some(6)
+        );
+        assert_eq!(
+            parse_major_version("NDI SDK LINUX 09:51:46 Mar 14 2023 5.5.3.0"),
+            Some(5)
+        );
+        assert_eq!(parse_major_version("6.1"), Some(6));
+        // Times, years and bare numbers aren't versions
+        assert_eq!(parse_major_version("NDI SDK 12:21:33 2024"), None);
+        assert_eq!(parse_major_version("v6..1"), None);
+        assert_eq!(parse_major_version(""), None);
+    }
+
+    #[test]
+    fn test_capabilities_gating() {
+        let full = NdiCapabilities {
+            version: Some("NDI SDK LINUX 12:21:33 Jun 25 2024 6.0.1.0".to_string()),
+            major: Some(6),
+            async_send: true,
+            recv_v3: true,
+            audio_v3: true,
+            tally: true,
+        };
+        assert!(full.degraded().is_empty());
+        assert_eq!(full.version_number(), Some("6.0.1.0"));
+
+        // Missing audio v3 is recorded but nothing depends on it
+        let old = NdiCapabilities {
+            async_send: false,
+            audio_v3: false,
+            tally: false,
+            ..full
+        };
+        assert!(!old.supports(NdiFeature::Tally));
+        assert!(old.supports(NdiFeature::RecvV3));
+        assert_eq!(
+            old.degraded(),
+            vec![NdiFeature::AsyncSend, NdiFeature::Tally]
+        );
+        assert_eq!(
+            NdiError::Unsupported(NdiFeature::RecvV3).to_string(),
+            "NDI runtime lacks recv_v3 (NDIlib_recv_capture_v3)"
+        );
+        assert_eq!(NdiCapabilities::default().version_number(), None);
+    }
+
     #[test]
     fn test_yuyv_to_uyvy_1080p_frame() {
         // Full 1080p frame
@@ -1237,4 +3041,214 @@ mod tests {
         let uyvy = convert_yuyv_to_uyvy_scalar(&yuyv);
         assert_eq!(uyvy.len(), 1920 * 1080 * 2);
     }
+
+    /// Sender and receiver logic against the in-process runtime; every test
+    /// uses its own NDI names since the stub's state is shared
+    #[cfg(feature = "ndi_stub")]
+    mod with_stub {
+        use super::*;
+        use crate::ndi::stub::{self, RecvEvent, StubFrame, FREED_BYTE};
+        use v4l::FourCC;
+
+        fn sender(name: &str) -> NdiSender {
+            NdiSender::new(name, FrameRate::default()).unwrap()
+        }
+
+        #[test]
+        fn test_send_uyvy_passes_stride_through() {
+            let mut sender = sender("stub-send-uyvy");
+            // 4x2 with four bytes of padding per row
+            let data: Vec<u8> = (0..24).collect();
+            sender
+                .send_frame_data(&data, 4, 2, FourCC::new(b"UYVY"), 12)
+                .unwrap();
+            let sent = stub::sent_frames("stub-send-uyvy");
+            assert_eq!(sent.len(), 1);
+            assert_eq!((sent[0].width, sent[0].height), (4, 2));
+            assert_eq!(sent[0].fourcc, NDILIBD_FOURCC_UYVY);
+            assert_eq!(sent[0].stride, 12);
+            assert_eq!(
+                sent[0].frame_format_type,
+                NDILIB_FRAME_FORMAT_TYPE_PROGRESSIVE
+            );
+            assert!(!sent[0].lent);
+            assert_eq!(sent[0].data, data);
+        }
+
+        #[test]
+        fn test_send_converts_to_packed_uyvy() {
+            let name = "stub-send-convert";
+            let mut sender = sender(name);
+            let yuyv: Vec<u8> = (0..16).collect();
+            sender
+                .send_frame_data(&yuyv, 4, 2, FourCC::new(b"YUYV"), 8)
+                .unwrap();
+            let bgra = vec![255u8; 4 * 4 * 2 + 2 * 8];
+            sender
+                .send_frame_data(&bgra, 4, 2, FourCC::new(b"BGRA"), 24)
+                .unwrap();
+            let nv12 = vec![128u8; 4 * 2 * 3 / 2];
+            sender
+                .send_frame_data(&nv12, 4, 2, FourCC::new(b"NV12"), 4)
+                .unwrap();
+
+            let sent = stub::sent_frames(name);
+            assert_eq!(sent.len(), 3);
+            for frame in &sent {
+                assert_eq!(frame.fourcc, NDILIBD_FOURCC_UYVY);
+                assert_eq!(frame.stride, 8);
+                assert_eq!(frame.data.len(), 16);
+            }
+            assert_eq!(sent[0].data, convert_yuyv_to_uyvy_scalar(&yuyv));
+            assert_eq!(
+                sent[1].data,
+                convert_bgra_to_uyvy(&bgra, 4, 2, 24, ColorRange::Limited)
+            );
+            assert_eq!(sent[2].data, [128, 128].repeat(8));
+
+            // Unknown formats fail without sending anything
+            let error = sender
+                .send_frame_data(&yuyv, 4, 2, FourCC::new(b"RGB3"), 12)
+                .unwrap_err();
+            assert_eq!(error.kind(), SendErrorKind::Format);
+            assert_eq!(error.failure(), SendFailure::UnsupportedFormat);
+            assert_eq!(stub::sent_frames(name).len(), 3);
+        }
+
+        #[test]
+        fn test_send_marks_anamorphic_frames() {
+            let name = "stub-send-aspect";
+            let mut sender = sender(name);
+            let send = |sender: &mut NdiSender, width: u32, height: u32| {
+                let uyvy = vec![128u8; width as usize * height as usize * 2];
+                sender
+                    .send_frame_data(&uyvy, width, height, FourCC::new(b"UYVY"), width * 2)
+                    .unwrap();
+            };
+            send(&mut sender, 1440, 1080);
+            send(&mut sender, 1920, 1080);
+            sender.set_aspect(AspectRatio::Fixed(4.0 / 3.0));
+            send(&mut sender, 1920, 1080);
+
+            let aspects: Vec<f32> = stub::sent_frames(name)
+                .iter()
+                .map(|frame| frame.picture_aspect_ratio)
+                .collect();
+            assert_eq!(aspects, [16.0 / 9.0, 0.0, 4.0 / 3.0]);
+        }
+
+        #[test]
+        fn test_send_counts_frames_and_connections() {
+            let name = "stub-send-count";
+            let mut sender = sender(name);
+            sender.set_deinterlace(DeinterlaceMode::Interlaced, FieldOrder::TopFirst);
+            let uyvy = [128u8, 16].repeat(8);
+            for _ in 0..5 {
+                sender
+                    .send_frame_data(&uyvy, 4, 2, FourCC::new(b"UYVY"), 8)
+                    .unwrap();
+            }
+            let sent = stub::sent_frames(name);
+            assert_eq!(sent.len(), 5);
+            assert!(sent
+                .iter()
+                .all(|frame| frame.frame_format_type == NDILIB_FRAME_FORMAT_TYPE_INTERLEAVED));
+
+            stub::set_connections(name, 2);
+            let handle = sender.handle();
+            assert_eq!(sender.connections(), 2);
+            sender.recreate().unwrap();
+            assert_eq!(handle.connections(), 2);
+            sender
+                .send_frame_data(&uyvy, 4, 2, FourCC::new(b"UYVY"), 8)
+                .unwrap();
+            assert_eq!(stub::sent_frames(name).len(), 6);
+
+            // The instance goes with the sender; handles report nothing
+            drop(sender);
+            assert_eq!(handle.connections(), 0);
+        }
+
+        #[test]
+        fn test_connect_matches_part_of_the_name() {
+            stub::add_source("STUB-HOST (stub connect)");
+            let receiver = NdiReceiver::connect("stub connect", 1, RecvColorFormat::Uyvy).unwrap();
+            assert_eq!(receiver.source_name(), "stub connect");
+
+            let started = Instant::now();
+            let error = match NdiReceiver::connect("stub absent", 1, RecvColorFormat::Uyvy) {
+                Err(e) => e,
+                Ok(_) => panic!("connected to a missing source"),
+            };
+            assert!(
+                matches!(error, NdiError::SourceNotFound { ref name } if name == "stub absent")
+            );
+            assert!(started.elapsed() >= Duration::from_secs(1));
+            assert!(started.elapsed() < Duration::from_secs(5));
+            stub::remove_source("STUB-HOST (stub connect)");
+        }
+
+        #[test]
+        fn test_capture_copies_before_freeing() {
+            let source = "STUB-HOST (stub capture)";
+            stub::add_source(source);
+            let stats = Arc::new(NdiReceiverStats::new());
+            let mut receiver = NdiReceiver::connect_with_stats(
+                "stub capture",
+                1,
+                RecvColorFormat::Uyvy,
+                Arc::clone(&stats),
+            )
+            .unwrap();
+            assert!(receiver.capture_frame(0).unwrap().is_none());
+
+            stub::queue_frame(source, StubFrame::uyvy(4, 2, 7));
+            stub::queue_frame(
+                source,
+                StubFrame {
+                    fourcc: NDILIBD_FOURCC_P216,
+                    data: vec![9; 32],
+                    ..StubFrame::uyvy(4, 2, 0)
+                },
+            );
+            // A video frame without data
+            stub::queue_frame(
+                source,
+                StubFrame {
+                    data: Vec::new(),
+                    ..StubFrame::uyvy(4, 2, 0)
+                },
+            );
+
+            // The stub overwrites a buffer once it is freed, so a copy made
+            // after the free would read FREED_BYTE
+            let first = receiver.capture_frame(0).unwrap().unwrap();
+            assert_eq!((first.width, first.height, first.stride), (4, 2, 8));
+            assert_eq!(first.data, vec![7; 16]);
+            assert_eq!(first.timecode, 0);
+            // P216 carries a chroma plane after the luma
+            let second = receiver.capture_frame(0).unwrap().unwrap();
+            assert_eq!(second.fourcc, NDILIBD_FOURCC_P216);
+            assert_eq!(second.data, vec![9; 32]);
+            assert!(!second.data.contains(&FREED_BYTE));
+            // Skipped, but still given back
+            assert!(receiver.capture_frame(0).unwrap().is_none());
+            assert!(receiver.capture_frame(0).unwrap().is_none());
+
+            assert_eq!(
+                stub::receiver_log(source),
+                [
+                    RecvEvent::Captured(0),
+                    RecvEvent::Freed(0),
+                    RecvEvent::Captured(1),
+                    RecvEvent::Freed(1),
+                    RecvEvent::Captured(2),
+                    RecvEvent::Freed(2),
+                ]
+            );
+            assert_eq!(stub::outstanding_frames(source), 0);
+            assert_eq!(stats.snapshot(Instant::now()).video_frames, 2);
+            stub::remove_source(source);
+        }
+    }
 }
@@ -0,0 +1,451 @@
+//! In-process NDI runtime for tests
+//!
+//! With the `ndi_stub` feature, `NdiLib::load()` returns this function table
+//! instead of opening libndi, so sender, finder and receiver code runs in CI
+//! without the proprietary runtime. Senders record every video frame they
+//! send under their NDI name, the finder lists the sources added with
+//! [`add_source`], and a receiver plays back the frames queued for its
+//! source with [`queue_frame`], logging every capture and free.
+//!
+//! The state is process-wide and tests run in parallel, so each test uses
+//! its own sender and source names. Release builds never include the stub.
+
+use std::collections::{HashMap, VecDeque};
+use std::ffi::{c_int, c_void, CStr, CString};
+use std::ptr;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use super::{
+    NDIlib_audio_frame_v2_t, NDIlib_find_create_t, NDIlib_recv_create_v3_t, NDIlib_send_create_t,
+    NDIlib_source_t, NDIlib_tally_t, NDIlib_video_frame_v2_recv_t, NDIlib_video_frame_v2_t, NdiLib,
+    RecvV3, NDILIBD_FOURCC_UYVY, NDILIB_FRAME_FORMAT_TYPE_PROGRESSIVE, NDILIB_FRAME_TYPE_ERROR,
+    NDILIB_FRAME_TYPE_NONE, NDILIB_FRAME_TYPE_VIDEO,
+};
+
+/// A video frame as a stub sender received it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentFrame {
+    pub width: u32,
+    pub height: u32,
+    pub fourcc: u32,
+    pub stride: u32,
+    /// Display width over height, 0 for square pixels
+    pub picture_aspect_ratio: f32,
+    /// NDI frame format type: 1 progressive, 0 interleaved fields
+    pub frame_format_type: i32,
+    /// Whether it came through the asynchronous send
+    pub lent: bool,
+    /// `stride` * `height` bytes
+    pub data: Vec<u8>,
+}
+
+/// A video frame for a stub receiver to deliver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StubFrame {
+    pub width: u32,
+    pub height: u32,
+    pub fourcc: u32,
+    pub stride: u32,
+    /// Every plane; empty hands out a frame without data
+    pub data: Vec<u8>,
+}
+
+impl StubFrame {
+    /// A tightly packed UYVY frame filled with `byte`
+    pub fn uyvy(width: u32, height: u32, byte: u8) -> Self {
+        Self {
+            width,
+            height,
+            fourcc: NDILIBD_FOURCC_UYVY,
+            stride: width * 2,
+            data: vec![byte; width as usize * 2 * height as usize],
+        }
+    }
+}
+
+/// What a stub receiver did, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvEvent {
+    /// Handed out the n-th frame queued for its source, counting from 0
+    Captured(u64),
+    /// The frame was freed
+    Freed(u64),
+}
+
+/// Bytes written over a frame's buffer when it is freed, so a reader that
+/// kept the pointer sees garbage
+pub const FREED_BYTE: u8 = 0xDD;
+
+/// Frame lent to the caller of `recv_capture_v3` until it is freed
+struct Outstanding {
+    index: u64,
+    buffer: Box<[u8]>,
+}
+
+struct Receiver {
+    source: String,
+    delivered: u64,
+    outstanding: Vec<Outstanding>,
+}
+
+/// Names and source structs returned by `find_get_current_sources`, which
+/// stay valid until the finder is destroyed
+struct Finder {
+    _names: Vec<CString>,
+    sources: Vec<NDIlib_source_t>,
+}
+
+#[derive(Default)]
+struct State {
+    next_handle: usize,
+    /// Live senders by handle -> NDI name
+    senders: HashMap<usize, String>,
+    finders: HashMap<usize, Finder>,
+    receivers: HashMap<usize, Receiver>,
+    /// Kept after the sender is destroyed, for inspection
+    sent: HashMap<String, Vec<SentFrame>>,
+    connections: HashMap<String, i32>,
+    sources: Vec<String>,
+    queued: HashMap<String, VecDeque<StubFrame>>,
+    log: HashMap<String, Vec<RecvEvent>>,
+}
+
+// SAFETY: the source structs of a finder point into its own `_names`, which
+// move along with them and are only read under the state lock
+unsafe impl Send for Finder {}
+
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+fn state() -> MutexGuard<'static, State> {
+    STATE
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+impl State {
+    fn handle(&mut self) -> *mut c_void {
+        self.next_handle += 1;
+        self.next_handle as *mut c_void
+    }
+}
+
+/// Longest a stub wait blocks, so polling loops don't spin
+const WAIT_SLICE: Duration = Duration::from_millis(10);
+
+fn wait(timeout_ms: u32) {
+    std::thread::sleep(WAIT_SLICE.min(Duration::from_millis(timeout_ms as u64)));
+}
+
+// =============================================================================
+// Inspection
+// =============================================================================
+
+/// Video frames sent so far by senders named `name`
+pub fn sent_frames(name: &str) -> Vec<SentFrame> {
+    state().sent.get(name).cloned().unwrap_or_default()
+}
+
+/// Report `count` receivers connected to senders named `name`
+pub fn set_connections(name: &str, count: i32) {
+    state().connections.insert(name.to_string(), count);
+}
+
+/// List `name` in every finder from now on
+pub fn add_source(name: &str) {
+    state().sources.push(name.to_string());
+}
+
+/// Stop listing `name`
+pub fn remove_source(name: &str) {
+    state().sources.retain(|source| source != name);
+}
+
+/// Queue `frame` for the receivers connected to `source`
+pub fn queue_frame(source: &str, frame: StubFrame) {
+    state()
+        .queued
+        .entry(source.to_string())
+        .or_default()
+        .push_back(frame);
+}
+
+/// Captures and frees of the receivers connected to `source`
+pub fn receiver_log(source: &str) -> Vec<RecvEvent> {
+    state().log.get(source).cloned().unwrap_or_default()
+}
+
+/// Frames of receivers connected to `source` that were not freed yet
+pub fn outstanding_frames(source: &str) -> usize {
+    state()
+        .receivers
+        .values()
+        .filter(|receiver| receiver.source == source)
+        .map(|receiver| receiver.outstanding.len())
+        .sum()
+}
+
+// =============================================================================
+// Function table
+// =============================================================================
+
+/// The stub runtime, in place of a loaded libndi
+pub(super) fn library() -> NdiLib {
+    NdiLib {
+        _library: None,
+        destroy,
+        send_create,
+        send_destroy,
+        send_send_video_v2,
+        send_send_audio_v2,
+        send_get_no_connections,
+        send_send_video_async_v2: Some(send_send_video_async_v2),
+        send_get_tally: Some(send_get_tally),
+        find_create_v2,
+        find_destroy,
+        find_wait_for_sources,
+        find_get_current_sources,
+        recv_destroy,
+        recv_free_video_v2,
+        recv_v3: Some(RecvV3 {
+            create: recv_create_v3,
+            capture: recv_capture_v3,
+        }),
+    }
+}
+
+unsafe extern "C" fn destroy() {}
+
+unsafe extern "C" fn send_create(settings: *const NDIlib_send_create_t) -> *mut c_void {
+    let name = CStr::from_ptr((*settings).p_ndi_name)
+        .to_string_lossy()
+        .into_owned();
+    let mut state = state();
+    let handle = state.handle();
+    state.senders.insert(handle as usize, name);
+    handle
+}
+
+unsafe extern "C" fn send_destroy(sender: *mut c_void) {
+    state().senders.remove(&(sender as usize));
+}
+
+unsafe fn record_video(sender: *mut c_void, frame: *const NDIlib_video_frame_v2_t, lent: bool) {
+    // A null frame flushes the asynchronous send
+    if frame.is_null() {
+        return;
+    }
+    let frame = &*frame;
+    let len = frame.line_stride_in_bytes.max(0) as usize * frame.yres.max(0) as usize;
+    let data = std::slice::from_raw_parts(frame.p_data, len).to_vec();
+    let mut state = state();
+    let Some(name) = state.senders.get(&(sender as usize)).cloned() else {
+        return;
+    };
+    state.sent.entry(name).or_default().push(SentFrame {
+        width: frame.xres as u32,
+        height: frame.yres as u32,
+        fourcc: frame.fourcc,
+        stride: frame.line_stride_in_bytes as u32,
+        picture_aspect_ratio: frame.picture_aspect_ratio,
+        frame_format_type: frame.frame_format_type,
+        lent,
+        data,
+    });
+}
+
+unsafe extern "C" fn send_send_video_v2(
+    sender: *mut c_void,
+    frame: *const NDIlib_video_frame_v2_t,
+) {
+    record_video(sender, frame, false);
+}
+
+unsafe extern "C" fn send_send_video_async_v2(
+    sender: *mut c_void,
+    frame: *const NDIlib_video_frame_v2_t,
+) {
+    record_video(sender, frame, true);
+}
+
+unsafe extern "C" fn send_send_audio_v2(
+    _sender: *mut c_void,
+    _frame: *const NDIlib_audio_frame_v2_t,
+) {
+}
+
+unsafe extern "C" fn send_get_no_connections(sender: *mut c_void, _timeout_ms: u32) -> c_int {
+    let state = state();
+    state
+        .senders
+        .get(&(sender as usize))
+        .and_then(|name| state.connections.get(name))
+        .copied()
+        .unwrap_or(0)
+}
+
+unsafe extern "C" fn send_get_tally(
+    _sender: *mut c_void,
+    tally: *mut NDIlib_tally_t,
+    _timeout_ms: u32,
+) -> bool {
+    *tally = NDIlib_tally_t::default();
+    false
+}
+
+unsafe extern "C" fn find_create_v2(_settings: *const NDIlib_find_create_t) -> *mut c_void {
+    let mut state = state();
+    let handle = state.handle();
+    state.finders.insert(
+        handle as usize,
+        Finder {
+            _names: Vec::new(),
+            sources: Vec::new(),
+        },
+    );
+    handle
+}
+
+unsafe extern "C" fn find_destroy(finder: *mut c_void) {
+    state().finders.remove(&(finder as usize));
+}
+
+unsafe extern "C" fn find_wait_for_sources(_finder: *mut c_void, timeout_ms: u32) -> bool {
+    wait(timeout_ms);
+    false
+}
+
+unsafe extern "C" fn find_get_current_sources(
+    finder: *mut c_void,
+    count: *mut u32,
+) -> *const NDIlib_source_t {
+    let mut state = state();
+    let names: Vec<CString> = state
+        .sources
+        .iter()
+        .filter_map(|name| CString::new(name.as_str()).ok())
+        .collect();
+    let sources: Vec<NDIlib_source_t> = names
+        .iter()
+        .map(|name| NDIlib_source_t {
+            p_ndi_name: name.as_ptr(),
+            p_url_address: ptr::null(),
+        })
+        .collect();
+    let Some(entry) = state.finders.get_mut(&(finder as usize)) else {
+        *count = 0;
+        return ptr::null();
+    };
+    // Replacing the list frees the previous one, as the SDK does
+    *entry = Finder {
+        _names: names,
+        sources,
+    };
+    *count = entry.sources.len() as u32;
+    entry.sources.as_ptr()
+}
+
+unsafe extern "C" fn recv_create_v3(settings: *const NDIlib_recv_create_v3_t) -> *mut c_void {
+    let name = (*settings).source_to_connect_to.p_ndi_name;
+    if name.is_null() {
+        return ptr::null_mut();
+    }
+    let source = CStr::from_ptr(name).to_string_lossy().into_owned();
+    let mut state = state();
+    let handle = state.handle();
+    state.receivers.insert(
+        handle as usize,
+        Receiver {
+            source,
+            delivered: 0,
+            outstanding: Vec::new(),
+        },
+    );
+    handle
+}
+
+unsafe extern "C" fn recv_destroy(receiver: *mut c_void) {
+    state().receivers.remove(&(receiver as usize));
+}
+
+unsafe extern "C" fn recv_capture_v3(
+    receiver: *mut c_void,
+    video: *mut NDIlib_video_frame_v2_recv_t,
+    _audio: *mut c_void,
+    _metadata: *mut c_void,
+    timeout_ms: u32,
+) -> c_int {
+    let mut guard = state();
+    let state = &mut *guard;
+    let Some(receiver) = state.receivers.get_mut(&(receiver as usize)) else {
+        return NDILIB_FRAME_TYPE_ERROR;
+    };
+    let Some(frame) = state
+        .queued
+        .get_mut(&receiver.source)
+        .and_then(VecDeque::pop_front)
+    else {
+        drop(guard);
+        wait(timeout_ms);
+        return NDILIB_FRAME_TYPE_NONE;
+    };
+    let index = receiver.delivered;
+    receiver.delivered += 1;
+    let mut buffer = frame.data.into_boxed_slice();
+    let p_data = if buffer.is_empty() {
+        ptr::null_mut()
+    } else {
+        buffer.as_mut_ptr()
+    };
+    receiver.outstanding.push(Outstanding { index, buffer });
+    state
+        .log
+        .entry(receiver.source.clone())
+        .or_default()
+        .push(RecvEvent::Captured(index));
+    *video = NDIlib_video_frame_v2_recv_t {
+        xres: frame.width as c_int,
+        yres: frame.height as c_int,
+        fourcc: frame.fourcc,
+        frame_rate_n: 60000,
+        frame_rate_d: 1001,
+        picture_aspect_ratio: 0.0,
+        frame_format_type: NDILIB_FRAME_FORMAT_TYPE_PROGRESSIVE,
+        timecode: index as i64,
+        p_data,
+        line_stride_in_bytes: frame.stride as c_int,
+        p_metadata: ptr::null(),
+        timestamp: index as i64,
+    };
+    NDILIB_FRAME_TYPE_VIDEO
+}
+
+unsafe extern "C" fn recv_free_video_v2(
+    receiver: *mut c_void,
+    video: *const NDIlib_video_frame_v2_recv_t,
+) {
+    let mut guard = state();
+    let state = &mut *guard;
+    let Some(receiver) = state.receivers.get_mut(&(receiver as usize)) else {
+        return;
+    };
+    // Frames without data are matched by their timecode, which is the index
+    let (p_data, timecode) = ((*video).p_data, (*video).timecode);
+    let Some(at) = receiver.outstanding.iter().position(|frame| {
+        if p_data.is_null() {
+            frame.buffer.is_empty() && frame.index as i64 == timecode
+        } else {
+            frame.buffer.as_ptr() == p_data.cast_const()
+        }
+    }) else {
+        return;
+    };
+    let mut frame = receiver.outstanding.remove(at);
+    frame.buffer.fill(FREED_BYTE);
+    state
+        .log
+        .entry(receiver.source.clone())
+        .or_default()
+        .push(RecvEvent::Freed(frame.index));
+}
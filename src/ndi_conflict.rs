@@ -0,0 +1,210 @@
+//! NDI source name collision handling
+//!
+//! Two boxes configured with the same `ndi_name` advertise the same source
+//! name, and receivers either suffix one of them or pick one at random. After
+//! the sender is created a short discovery pass looks for another machine
+//! advertising the name; `ndi.on_conflict` then renames this box with a
+//! suffix derived from its hostname or refuses to start.
+//!
+//! NDI source names are "HOSTNAME (name)"; our own source is recognised by
+//! its hostname part, so two boxes sharing both hostname and name go
+//! unnoticed.
+
+use anyhow::{anyhow, bail, Result};
+
+/// What to do when another machine already advertises our name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Skip the discovery pass
+    Off,
+    /// Rename to "<name>-<hostname>"
+    Suffix,
+    /// Refuse to start
+    Fail,
+}
+
+impl OnConflict {
+    /// Parse a policy name from configuration ("off", "suffix", "fail")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Ok(OnConflict::Off),
+            "suffix" => Ok(OnConflict::Suffix),
+            "fail" => Ok(OnConflict::Fail),
+            other => Err(anyhow!(
+                "Unsupported NDI conflict policy: {}. Supported: off, suffix, fail",
+                other
+            )),
+        }
+    }
+}
+
+/// Split a full NDI source name "HOSTNAME (name)" into host and name
+pub fn split_source_name(source: &str) -> Option<(&str, &str)> {
+    let (host, rest) = source.split_once(" (")?;
+    let name = rest.strip_suffix(')')?;
+    Some((host, name))
+}
+
+/// Whether `host` as advertised by NDI is the machine named `hostname`.
+/// NDI may advertise the short or upper-cased name.
+fn is_same_host(host: &str, hostname: &str) -> bool {
+    let short = |h: &str| h.split('.').next().unwrap_or(h).to_ascii_lowercase();
+    short(host) == short(hostname)
+}
+
+/// The first discovered source from another host advertising `name`
+pub fn find_conflict<'a>(sources: &'a [String], name: &str, hostname: &str) -> Option<&'a str> {
+    sources
+        .iter()
+        .map(String::as_str)
+        .find(|source| match split_source_name(source) {
+            Some((host, source_name)) => {
+                source_name.eq_ignore_ascii_case(name) && !is_same_host(host, hostname)
+            }
+            None => false,
+        })
+}
+
+/// Deterministic alternative name: `name` plus the short hostname, reduced
+/// to lowercase letters, digits and dashes
+pub fn suffixed_name(name: &str, hostname: &str) -> String {
+    let short = hostname.split('.').next().unwrap_or(hostname);
+    let suffix: String = short
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let suffix = suffix.trim_matches('-');
+    if suffix.is_empty() {
+        format!("{}-camera-box", name)
+    } else {
+        format!("{}-{}", name, suffix)
+    }
+}
+
+/// Check `name` against the discovered `sources`. Returns the name to
+/// stream as if it has to change, or fails under [`OnConflict::Fail`].
+pub fn resolve(
+    policy: OnConflict,
+    sources: &[String],
+    name: &str,
+    hostname: &str,
+) -> Result<Option<String>> {
+    if policy == OnConflict::Off {
+        return Ok(None);
+    }
+    let Some(other) = find_conflict(sources, name, hostname) else {
+        return Ok(None);
+    };
+    tracing::error!(
+        "NDI name conflict: '{}' is already advertised by another machine ({})",
+        name,
+        other
+    );
+    if policy == OnConflict::Fail {
+        bail!(
+            "NDI name '{}' is already in use by {} (ndi.on_conflict = \"fail\")",
+            name,
+            other
+        );
+    }
+    let renamed = suffixed_name(name, hostname);
+    if let Some(other) = find_conflict(sources, &renamed, hostname) {
+        bail!(
+            "NDI name '{}' and its fallback '{}' are both in use ({})",
+            name,
+            renamed,
+            other
+        );
+    }
+    tracing::error!("Streaming as '{}' instead of '{}'", renamed, name);
+    Ok(Some(renamed))
+}
+
+/// This machine's hostname as NDI advertises it
+pub fn system_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    let hostname = String::from_utf8_lossy(&buf[..len]).into_owned();
+    (!hostname.is_empty()).then_some(hostname)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_split_source_name() {
+        assert_eq!(split_source_name("CAM-2 (usb)"), Some(("CAM-2", "usb")));
+        // Names can contain parentheses themselves
+        assert_eq!(
+            split_source_name("STRIH-SNV (interkom (1))"),
+            Some(("STRIH-SNV", "interkom (1)"))
+        );
+        assert_eq!(split_source_name("usb"), None);
+    }
+
+    #[test]
+    fn test_find_conflict_excludes_own_source() {
+        let found = sources(&["CAM-1 (usb)", "CAM-1.lan (hdmi)", "STRIH (program)"]);
+        // Our own source, whatever case or domain NDI reports it with
+        assert_eq!(find_conflict(&found, "usb", "cam-1"), None);
+        assert_eq!(find_conflict(&found, "hdmi", "cam-1"), None);
+        // Another box with our name
+        assert_eq!(find_conflict(&found, "usb", "cam-2"), Some("CAM-1 (usb)"));
+        assert_eq!(find_conflict(&found, "USB", "cam-2"), Some("CAM-1 (usb)"));
+        // Partial names don't collide
+        assert_eq!(find_conflict(&found, "us", "cam-2"), None);
+        assert_eq!(find_conflict(&found, "gram", "cam-2"), None);
+    }
+
+    #[test]
+    fn test_suffixed_name() {
+        assert_eq!(suffixed_name("usb", "cam-2"), "usb-cam-2");
+        assert_eq!(suffixed_name("usb", "CAM_2.lan"), "usb-cam-2");
+        assert_eq!(suffixed_name("usb", "cam-2"), suffixed_name("usb", "cam-2"));
+        assert_eq!(suffixed_name("usb", "..."), "usb-camera-box");
+    }
+
+    #[test]
+    fn test_resolve_policies() {
+        let found = sources(&["CAM-1 (usb)", "CAM-2 (usb)"]);
+        assert_eq!(
+            resolve(OnConflict::Off, &found, "usb", "cam-2").unwrap(),
+            None
+        );
+        assert_eq!(
+            resolve(OnConflict::Suffix, &found, "hdmi", "cam-2").unwrap(),
+            None
+        );
+        assert_eq!(
+            resolve(OnConflict::Suffix, &found, "usb", "cam-2").unwrap(),
+            Some("usb-cam-2".to_string())
+        );
+        assert!(resolve(OnConflict::Fail, &found, "usb", "cam-2").is_err());
+
+        // The fallback name is taken too
+        let found = sources(&["CAM-1 (usb)", "CAM-3 (usb-cam-2)"]);
+        assert!(resolve(OnConflict::Suffix, &found, "usb", "cam-2").is_err());
+    }
+
+    #[test]
+    fn test_on_conflict_from_name() {
+        assert_eq!(OnConflict::from_name("Suffix").unwrap(), OnConflict::Suffix);
+        assert_eq!(OnConflict::from_name("fail").unwrap(), OnConflict::Fail);
+        assert_eq!(OnConflict::from_name("off").unwrap(), OnConflict::Off);
+        assert!(OnConflict::from_name("rename").is_err());
+    }
+}
@@ -7,9 +7,44 @@
 use anyhow::Result;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::aspect;
+use crate::control::DisplayControl;
 use crate::display::FramebufferDisplay;
-use crate::ndi::NdiReceiver;
+use crate::exposure::ExposureSettings;
+use crate::frame_age::{FrameVerdict, StaleFrameGuard};
+use crate::ndi::{NdiReceiver, NdiReceiverStats, ReceivedFrame, RecvColorFormat};
+use crate::ndi_supervisor::{RestartBackoff, RestartPolicy};
+use crate::processing::wall_clock;
+use crate::splash::{show_splash, SplashScreen, WAITING_FOR_SOURCE};
+use crate::standby::{StandbyScreen, StandbySettings};
+
+/// How often the framebuffer mode is re-read to catch a replugged monitor
+const MODE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Empty 100 ms polls before the standby screen replaces the last frame
+const STANDBY_AFTER_POLLS: u64 = 10;
+
+/// Longest wait for a frame while nothing is waiting to be drawn
+const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Default `display.max_fps`
+pub const DEFAULT_MAX_FPS: u32 = 30;
+
+/// Exit status after `display.idle_exit_mins` without video (EX_TEMPFAIL),
+/// which the systemd unit leaves stopped instead of restarting
+pub const IDLE_EXIT_CODE: i32 = 75;
+
+/// Backoff between attempts to open the framebuffer
+fn open_policy() -> RestartPolicy {
+    RestartPolicy {
+        max_consecutive_errors: 1,
+        initial_backoff: Duration::from_millis(500),
+        max_backoff: Duration::from_secs(30),
+        reload_library: false,
+    }
+}
 
 /// NDI display configuration
 pub struct NdiDisplayConfig {
@@ -19,6 +54,19 @@ pub struct NdiDisplayConfig {
     pub fb_device: String,
     /// Timeout for finding NDI source (seconds)
     pub find_timeout_secs: u32,
+    /// Format requested from the sender
+    pub color_format: RecvColorFormat,
+    /// Frames older than this by their NDI timestamp are dropped (zero
+    /// disables)
+    pub max_age: Duration,
+    /// Histogram and zebra shown at startup
+    pub exposure: ExposureSettings,
+    /// Dimming of the standby screen shown without video
+    pub standby: StandbySettings,
+    /// Most frames drawn per second, 0 draws every frame received
+    pub max_fps: u32,
+    /// Time without video before the display gives up (zero never does)
+    pub idle_exit: Duration,
 }
 
 impl Default for NdiDisplayConfig {
@@ -27,60 +75,151 @@ impl Default for NdiDisplayConfig {
             source_name: String::new(),
             fb_device: "/dev/fb0".to_string(),
             find_timeout_secs: 30,
+            color_format: RecvColorFormat::Uyvy,
+            max_age: Duration::from_millis(100),
+            exposure: ExposureSettings::default(),
+            standby: StandbySettings::default(),
+            max_fps: DEFAULT_MAX_FPS,
+            idle_exit: Duration::ZERO,
         }
     }
 }
 
+/// Decides when the display may draw again (`display.max_fps`). Frames
+/// keep being received in between; the loop holds on to the newest one and
+/// draws it once [`DrawLimiter::wait`] reaches zero.
+#[derive(Debug)]
+pub struct DrawLimiter {
+    /// Shortest time between two draws; None draws every frame
+    interval: Option<Duration>,
+    last_draw: Option<Instant>,
+}
+
+impl DrawLimiter {
+    /// At most `max_fps` draws per second, 0 for no limit
+    pub fn new(max_fps: u32) -> Self {
+        Self {
+            interval: (max_fps > 0).then(|| Duration::from_secs(1) / max_fps),
+            last_draw: None,
+        }
+    }
+
+    /// Time left at `now` before the next draw, zero once it is due
+    pub fn wait(&self, now: Instant) -> Duration {
+        match (self.interval, self.last_draw) {
+            (Some(interval), Some(last)) => (last + interval).saturating_duration_since(now),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// A video frame was drawn at `now`
+    pub fn drew(&mut self, now: Instant) {
+        self.last_draw = Some(now);
+    }
+}
+
+/// Decides when a display without video gives up (`display.idle_exit_mins`).
+/// Idle time runs while the splash or standby screen is shown, across
+/// reconnects, and starts over with the next frame drawn.
+#[derive(Debug)]
+pub struct IdleTimer {
+    /// Time without video before giving up; None never does
+    after: Option<Duration>,
+    idle_since: Option<Instant>,
+}
+
+impl IdleTimer {
+    /// Give up after `after` without video, zero for never
+    pub fn new(after: Duration) -> Self {
+        Self {
+            after: (!after.is_zero()).then_some(after),
+            idle_since: None,
+        }
+    }
+
+    /// A video frame was drawn
+    pub fn video(&mut self) {
+        self.idle_since = None;
+    }
+
+    /// No video at `now`; true once there has been none for the whole
+    /// idle time
+    pub fn expired(&mut self, now: Instant) -> bool {
+        let Some(after) = self.after else {
+            return false;
+        };
+        let since = *self.idle_since.get_or_insert(now);
+        now.saturating_duration_since(since) >= after
+    }
+}
+
+/// Why the display loop returned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayExit {
+    /// Shutdown was requested
+    Stopped,
+    /// No video for `idle_exit`: the process should exit with
+    /// [`IDLE_EXIT_CODE`]
+    Idle,
+}
+
 /// Run the NDI display loop with automatic reconnection
-/// This should be called from a low-priority thread
-pub fn run_display_loop(config: NdiDisplayConfig, running: Arc<AtomicBool>) -> Result<()> {
+/// This should be called from a low-priority thread. Source switches from the
+/// control socket close the receiver and reconnect to the new source.
+/// `opened` is the framebuffer the boot splash left on screen, if any; the
+/// splash stays up until the first frame. With `idle_exit` set, the loop
+/// also returns once there has been no video for that long.
+pub fn run_display_loop(
+    config: NdiDisplayConfig,
+    running: Arc<AtomicBool>,
+    mut control: DisplayControl,
+    opened: Option<FramebufferDisplay>,
+) -> Result<DisplayExit> {
     tracing::info!(
         "NDI display starting, searching for source: {}",
         config.source_name
     );
 
-    // Open framebuffer (retry indefinitely until display is connected)
-    let mut display;
-    let mut attempt = 0u32;
-    loop {
-        if !running.load(Ordering::Relaxed) {
-            anyhow::bail!("Shutdown requested");
-        }
-        attempt = attempt.saturating_add(1);
-        match FramebufferDisplay::open(&config.fb_device) {
-            Ok(d) => {
-                tracing::info!("Framebuffer opened successfully");
-                display = d;
-                break;
-            }
-            Err(e) => {
-                // Log every 30 seconds (15 attempts * 2 seconds)
-                if attempt % 15 == 1 {
-                    tracing::warn!(
-                        "Waiting for display (attempt {}): {} - will keep retrying...",
-                        attempt,
-                        e
-                    );
-                }
-                std::thread::sleep(std::time::Duration::from_secs(2));
-            }
-        }
-    }
-    let (fb_width, fb_height) = display.dimensions();
+    let Some(mut display) = opened.or_else(|| open_framebuffer(&config.fb_device, &running)) else {
+        anyhow::bail!("Shutdown requested");
+    };
+    display.set_exposure(config.exposure);
+    let mut standby = StandbyScreen::new(config.standby);
+    let mut splash = Some(SplashScreen::new());
+    let mut limiter = DrawLimiter::new(config.max_fps);
+    let mut idle = IdleTimer::new(config.idle_exit);
+    let mut exit = DisplayExit::Stopped;
+    let stats = control.stats();
+    let mut last_mode_check = Instant::now();
+    let mut connected_before = false;
 
     // Outer reconnection loop - keeps trying to connect/reconnect
-    while running.load(Ordering::Relaxed) {
+    'connect: while running.load(Ordering::Relaxed) {
+        let source_name = control.source();
+        show_idle(&mut display, &mut standby, &mut splash);
+        if idle.expired(Instant::now()) {
+            exit = DisplayExit::Idle;
+            break;
+        }
+
         // Try to connect to NDI source
-        tracing::info!(
-            "NDI display: connecting to source '{}'...",
-            config.source_name
+        tracing::info!("NDI display: connecting to source '{}'...", source_name);
+        let connected = NdiReceiver::connect_with_stats(
+            &source_name,
+            config.find_timeout_secs,
+            config.color_format,
+            stats.clone(),
         );
-        let mut receiver = match NdiReceiver::connect(&config.source_name, config.find_timeout_secs)
-        {
+        let mut receiver = match connected {
             Ok(r) => {
+                if connected_before {
+                    stats.reconnects.fetch_add(1, Ordering::Relaxed);
+                }
+                connected_before = true;
+                let (fb_width, fb_height) = display.dimensions();
                 tracing::info!(
                     "NDI display ready: {} -> framebuffer {}x{}",
-                    config.source_name,
+                    source_name,
                     fb_width,
                     fb_height
                 );
@@ -88,20 +227,59 @@ pub fn run_display_loop(config: NdiDisplayConfig, running: Arc<AtomicBool>) -> R
             }
             Err(e) => {
                 tracing::warn!("Failed to connect to NDI source: {}, retrying in 5s...", e);
-                std::thread::sleep(std::time::Duration::from_secs(5));
+                show_idle(&mut display, &mut standby, &mut splash);
+                if !control.source_changed() {
+                    std::thread::sleep(std::time::Duration::from_secs(5));
+                }
                 continue;
             }
         };
 
         let mut frame_count: u64 = 0;
-        let mut last_report = std::time::Instant::now();
         let mut no_frame_count: u64 = 0;
+        // The newest frame received since the last draw
+        let mut pending: Option<ReceivedFrame> = None;
         let mut first_frame = true;
+        // Sender clocks differ per source, so the offset is estimated anew
+        let mut age_guard = StaleFrameGuard::new(config.max_age, std::time::Instant::now());
 
-        // Inner display loop - runs until source disappears
+        // Inner display loop - runs until source disappears or is switched
         while running.load(Ordering::Relaxed) {
-            // Capture frame with 100ms timeout
-            match receiver.capture_frame(100) {
+            if control.source_changed() {
+                tracing::info!("NDI display: switching source...");
+                break;
+            }
+            if last_mode_check.elapsed() >= MODE_CHECK_INTERVAL {
+                last_mode_check = Instant::now();
+                if !refresh_framebuffer(&mut display, &config.fb_device, &running) {
+                    break;
+                }
+            }
+            if let Some(enabled) = control.overlay_update() {
+                tracing::info!(
+                    "NDI display: overlay {}",
+                    if enabled { "enabled" } else { "disabled" }
+                );
+            }
+            if let Some(exposure) = control.exposure_update() {
+                tracing::info!(
+                    "NDI display: histogram {}, zebra {}",
+                    if exposure.histogram { "on" } else { "off" },
+                    if exposure.zebra {
+                        format!("at {}%", exposure.zebra_percent)
+                    } else {
+                        "off".to_string()
+                    }
+                );
+                display.set_exposure(exposure);
+            }
+
+            // Wait no longer than until a held frame is due
+            let timeout = match pending {
+                Some(_) => limiter.wait(Instant::now()).min(POLL_TIMEOUT),
+                None => POLL_TIMEOUT,
+            };
+            match receiver.capture_frame(timeout.as_micros().div_ceil(1000) as u32) {
                 Ok(Some(frame)) => {
                     no_frame_count = 0;
 
@@ -120,34 +298,28 @@ pub fn run_display_loop(config: NdiDisplayConfig, running: Arc<AtomicBool>) -> R
                         first_frame = false;
                     }
 
-                    // Display the frame (ignore errors - display may be disconnected)
-                    if let Err(e) =
-                        display.display_frame(&frame.data, frame.width, frame.height, frame.fourcc)
+                    if let FrameVerdict::Drop(age) =
+                        age_guard.check(frame.timestamp, std::time::Instant::now())
                     {
-                        // Only log occasionally to avoid spam
-                        if frame_count.is_multiple_of(300) {
-                            tracing::warn!("Display write failed (monitor disconnected?): {}", e);
+                        let drops = stats.late_drops.fetch_add(1, Ordering::Relaxed) + 1;
+                        if drops.is_power_of_two() {
+                            tracing::debug!(
+                                "NDI display: dropped frame {:.0} ms late ({} late drops)",
+                                age.as_secs_f64() * 1000.0,
+                                drops
+                            );
                         }
+                        continue;
                     }
 
-                    frame_count += 1;
-
-                    // Report fps every 10 seconds (less frequent than camera)
-                    let elapsed = last_report.elapsed();
-                    if elapsed.as_secs() >= 10 {
-                        let fps = frame_count as f64 / elapsed.as_secs_f64();
-                        tracing::info!(
-                            "NDI display: {:.1} fps ({}x{} -> {}x{})",
-                            fps,
-                            frame.width,
-                            frame.height,
-                            fb_width,
-                            fb_height
-                        );
-                        frame_count = 0;
-                        last_report = std::time::Instant::now();
+                    // Only the newest frame is drawn
+                    if pending.replace(frame).is_some() {
+                        stats.rate_limited.fetch_add(1, Ordering::Relaxed);
                     }
                 }
+                Ok(None) if pending.is_some() => {
+                    // A shortened wait for the held frame, not a missing source
+                }
                 Ok(None) => {
                     // No frame available
                     no_frame_count += 1;
@@ -161,23 +333,202 @@ pub fn run_display_loop(config: NdiDisplayConfig, running: Arc<AtomicBool>) -> R
                     if no_frame_count == 50 {
                         tracing::warn!("NDI display: No frames received for 5 seconds");
                     }
+                    if no_frame_count >= STANDBY_AFTER_POLLS {
+                        show_idle(&mut display, &mut standby, &mut splash);
+                        if idle.expired(Instant::now()) {
+                            exit = DisplayExit::Idle;
+                            break 'connect;
+                        }
+                    }
+                    continue;
                 }
                 Err(e) => {
                     tracing::error!("NDI display: capture error: {}, reconnecting...", e);
                     break; // Exit inner loop to reconnect
                 }
             }
+
+            let now = Instant::now();
+            if !limiter.wait(now).is_zero() {
+                continue;
+            }
+            display.set_call_flash(control.call_blink(now) == Some(true));
+            let Some(frame) = pending.take() else {
+                continue;
+            };
+            limiter.drew(now);
+            if !draw_frame(
+                &mut display,
+                &mut standby,
+                &mut splash,
+                &mut idle,
+                &stats,
+                &frame,
+                frame_count,
+            ) {
+                // The monitor may be back at another resolution
+                last_mode_check = Instant::now();
+                if !refresh_framebuffer(&mut display, &config.fb_device, &running) {
+                    break;
+                }
+            }
+            frame_count += 1;
         }
 
         // Receiver will be dropped here, then we retry connection in outer loop
-        if running.load(Ordering::Relaxed) {
+        drop(receiver);
+        if running.load(Ordering::Relaxed) && !control.source_changed() {
             tracing::info!("NDI display: disconnected, will reconnect in 2s...");
             std::thread::sleep(std::time::Duration::from_secs(2));
         }
     }
 
+    if exit == DisplayExit::Idle {
+        tracing::info!(
+            "NDI display: no video for {} minutes, exiting",
+            config.idle_exit.as_secs() / 60
+        );
+    }
+    // Leave a black screen rather than the last frame
+    if let Err(e) = display.clear() {
+        tracing::warn!("NDI display: blanking on stop failed: {}", e);
+    }
     tracing::info!("NDI display stopped");
-    Ok(())
+    Ok(exit)
+}
+
+/// Show a received frame, counting it as displayed; the first one ends
+/// the splash and any idle time. Returns false if the framebuffer write failed; errors are
+/// only logged every 300 frames, as the monitor may be unplugged for a
+/// while.
+fn draw_frame(
+    display: &mut FramebufferDisplay,
+    standby: &mut StandbyScreen,
+    splash: &mut Option<SplashScreen>,
+    idle: &mut IdleTimer,
+    stats: &NdiReceiverStats,
+    frame: &ReceivedFrame,
+    frame_count: u64,
+) -> bool {
+    // Shown at the aspect NDI receivers use, e.g. anamorphic 16:9
+    let aspect = aspect::display_aspect(frame.width, frame.height, frame.picture_aspect_ratio);
+    match display.display_frame_with_aspect(
+        &frame.data,
+        frame.width,
+        frame.height,
+        frame.stride,
+        frame.fourcc,
+        aspect,
+    ) {
+        Ok(true) => {
+            standby.reset();
+            idle.video();
+            *splash = None;
+            stats.displayed.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+        // A format we can't show: standby rather than garbage
+        Ok(false) => {
+            show_idle(display, standby, splash);
+            true
+        }
+        // Signals, not the monitor: skip the frame and keep the device
+        Err(e) if e.is_interrupted() => {
+            stats.write_interrupted.fetch_add(1, Ordering::Relaxed);
+            true
+        }
+        Err(e) => {
+            if frame_count.is_multiple_of(300) {
+                tracing::warn!("Display write failed (monitor disconnected?): {}", e);
+            }
+            false
+        }
+    }
+}
+
+/// Without video: the splash until the first frame, standby after it
+fn show_idle(
+    display: &mut FramebufferDisplay,
+    standby: &mut StandbyScreen,
+    splash: &mut Option<SplashScreen>,
+) {
+    match splash {
+        Some(splash) => show_splash(display, splash, WAITING_FOR_SOURCE),
+        None => show_standby(display, standby),
+    }
+}
+
+/// Draw the standby screen with the current time; like video frames, a
+/// failed write is left to the next mode check
+fn show_standby(display: &mut FramebufferDisplay, standby: &mut StandbyScreen) {
+    let clock = wall_clock();
+    if let Err(e) = display.show_standby(standby, Instant::now(), &clock[..5]) {
+        tracing::debug!("NDI display: standby write failed: {}", e);
+    }
+}
+
+/// Open the framebuffer, retrying with backoff until a display is connected.
+/// None if shutdown was requested first.
+fn open_framebuffer(device: &str, running: &AtomicBool) -> Option<FramebufferDisplay> {
+    open_framebuffer_until(device, || running.load(Ordering::Relaxed))
+}
+
+/// Open the framebuffer like [`open_framebuffer`], giving up once
+/// `keep_trying` returns false
+pub(crate) fn open_framebuffer_until(
+    device: &str,
+    keep_trying: impl Fn() -> bool,
+) -> Option<FramebufferDisplay> {
+    let mut backoff = RestartBackoff::new(open_policy());
+    let mut attempt = 0u32;
+    while keep_trying() {
+        let now = Instant::now();
+        if !backoff.ready(now) {
+            std::thread::sleep(Duration::from_millis(100));
+            continue;
+        }
+        attempt = attempt.saturating_add(1);
+        backoff.restarted(now);
+        match FramebufferDisplay::open(device) {
+            Ok(display) => {
+                tracing::info!("Framebuffer opened successfully");
+                return Some(display);
+            }
+            Err(e) => {
+                if attempt.is_power_of_two() {
+                    tracing::warn!(
+                        "Waiting for display (attempt {}): {} - will keep retrying...",
+                        attempt,
+                        e
+                    );
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Pick up a framebuffer mode change, reopening the device if it went away.
+/// Returns false if shutdown was requested while reopening.
+fn refresh_framebuffer(
+    display: &mut FramebufferDisplay,
+    device: &str,
+    running: &AtomicBool,
+) -> bool {
+    match display.refresh_mode() {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!("NDI display: framebuffer lost ({}), reopening...", e);
+            match open_framebuffer(device, running) {
+                Some(mut reopened) => {
+                    reopened.set_exposure(display.exposure());
+                    *display = reopened;
+                    true
+                }
+                None => false,
+            }
+        }
+    }
 }
 
 /// Apply low-priority settings for the display thread
@@ -218,6 +569,17 @@ mod tests {
         assert!(config.source_name.is_empty());
         assert_eq!(config.fb_device, "/dev/fb0");
         assert_eq!(config.find_timeout_secs, 30);
+        assert_eq!(config.color_format, RecvColorFormat::Uyvy);
+        assert_eq!(config.max_age, Duration::from_millis(100));
+        assert_eq!(config.standby, StandbySettings::default());
+        assert_eq!(config.max_fps, 30);
+        assert_eq!(config.idle_exit, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_open_framebuffer_stops_on_shutdown() {
+        let running = AtomicBool::new(false);
+        assert!(open_framebuffer("/dev/does-not-exist/fb9", &running).is_none());
     }
 
     #[test]
@@ -226,10 +588,25 @@ mod tests {
             source_name: "STRIH-SNV (interkom)".to_string(),
             fb_device: "/dev/fb1".to_string(),
             find_timeout_secs: 60,
+            color_format: RecvColorFormat::Bgra,
+            max_age: Duration::ZERO,
+            exposure: ExposureSettings {
+                histogram: true,
+                zebra: true,
+                zebra_percent: 100,
+            },
+            standby: StandbySettings {
+                dim: 0.5,
+                dim_after: Duration::from_secs(60),
+            },
+            max_fps: 0,
+            idle_exit: Duration::from_secs(30 * 60),
         };
         assert_eq!(config.source_name, "STRIH-SNV (interkom)");
         assert_eq!(config.fb_device, "/dev/fb1");
         assert_eq!(config.find_timeout_secs, 60);
+        assert_eq!(config.color_format, RecvColorFormat::Bgra);
+        assert!(config.exposure.zebra);
     }
 
     #[test]
@@ -238,10 +615,89 @@ mod tests {
             source_name: "test".to_string(),
             fb_device: "/dev/fb0".to_string(),
             find_timeout_secs: 10,
+            color_format: RecvColorFormat::Uyvy,
+            max_age: Duration::from_millis(100),
+            exposure: ExposureSettings::default(),
+            standby: StandbySettings::default(),
+            max_fps: 25,
+            idle_exit: Duration::ZERO,
         };
         // Verify all fields are accessible
         assert!(!config.source_name.is_empty());
         assert!(!config.fb_device.is_empty());
         assert!(config.find_timeout_secs > 0);
     }
+
+    #[test]
+    fn test_draw_limiter_paces_draws() {
+        let start = Instant::now();
+        let mut limiter = DrawLimiter::new(30);
+        // The first frame is drawn at once
+        assert_eq!(limiter.wait(start), Duration::ZERO);
+        limiter.drew(start);
+
+        let interval = Duration::from_secs(1) / 30;
+        assert_eq!(limiter.wait(start), interval);
+        let soon = start + Duration::from_millis(10);
+        assert_eq!(limiter.wait(soon), interval - Duration::from_millis(10));
+        assert_eq!(limiter.wait(start + interval), Duration::ZERO);
+
+        // A late draw paces from when it happened
+        let late = start + Duration::from_millis(100);
+        assert_eq!(limiter.wait(late), Duration::ZERO);
+        limiter.drew(late);
+        assert_eq!(
+            limiter.wait(late + Duration::from_millis(20)),
+            interval - Duration::from_millis(20)
+        );
+    }
+
+    #[test]
+    fn test_draw_limiter_counts_draws_per_second() {
+        // A 60 fps source into a 30 fps limit, on a fake clock
+        let start = Instant::now();
+        let mut limiter = DrawLimiter::new(30);
+        let mut drawn = 0;
+        for frame in 0..60u32 {
+            let now = start + Duration::from_secs(1) * frame / 60;
+            if limiter.wait(now).is_zero() {
+                limiter.drew(now);
+                drawn += 1;
+            }
+        }
+        assert_eq!(drawn, 30);
+    }
+
+    #[test]
+    fn test_draw_limiter_zero_is_unlimited() {
+        let start = Instant::now();
+        let mut limiter = DrawLimiter::new(0);
+        limiter.drew(start);
+        assert_eq!(limiter.wait(start), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_idle_timer_runs_without_video() {
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        let mut idle = IdleTimer::new(minute * 30);
+        // Idle time starts at the first check without video
+        assert!(!idle.expired(start + minute));
+        assert!(!idle.expired(start + minute * 30));
+        assert!(idle.expired(start + minute * 31));
+
+        // A frame starts it over
+        idle.video();
+        assert!(!idle.expired(start + minute * 40));
+        assert!(!idle.expired(start + minute * 69));
+        assert!(idle.expired(start + minute * 70));
+    }
+
+    #[test]
+    fn test_idle_timer_zero_never_expires() {
+        let start = Instant::now();
+        let mut idle = IdleTimer::new(Duration::ZERO);
+        assert!(!idle.expired(start));
+        assert!(!idle.expired(start + Duration::from_secs(86_400 * 365)));
+    }
 }
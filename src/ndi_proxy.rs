@@ -0,0 +1,324 @@
+//! Low-resolution NDI proxy
+//!
+//! A multiviewer wall pulls every source at once, and full-resolution
+//! streams add up on the network. With `[ndi.proxy]` enabled a second NDI
+//! sender, named `<ndi_name><name_suffix>`, carries every `fps_divisor`th
+//! frame scaled down to the proxy size. [`NdiProxy`] runs as the last
+//! frame processor: it scales a due frame with the macropixel UYVY scaler,
+//! which is cheap at proxy size, and hands it to the proxy's own thread
+//! with `try_send`. A due frame is skipped, and counted as dropped, while
+//! that thread is still sending the previous one or when the capture loop
+//! went over budget on the frame before, so the proxy never holds up the
+//! main send.
+
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use v4l::FourCC;
+
+use crate::capture::{FrameInfo, FrameRate};
+use crate::config::ProxyConfig;
+use crate::display::scale_uyvy_nearest;
+use crate::ndi_supervisor::VideoSender;
+use crate::output::{Frame, OutputStats, QUEUE_FRAMES};
+use crate::pipeline::PipelineStats;
+use crate::processing::{FrameAction, FrameProcessor};
+use crate::threads;
+
+/// Stats component of the proxy
+pub const STATS_NAME: &str = "ndi-proxy";
+
+/// Picks every `divisor`th frame, starting with the first
+#[derive(Debug)]
+pub struct ProxyPacer {
+    divisor: u64,
+    frames: u64,
+}
+
+impl ProxyPacer {
+    pub fn new(divisor: u32) -> Self {
+        Self {
+            divisor: divisor.max(1) as u64,
+            frames: 0,
+        }
+    }
+
+    /// Count a frame; true if the proxy gets it
+    pub fn due(&mut self) -> bool {
+        let due = self.frames % self.divisor == 0;
+        self.frames += 1;
+        due
+    }
+}
+
+/// Frame rate of a proxy sending every `divisor`th frame at `rate`
+pub fn proxy_rate(rate: FrameRate, divisor: u32) -> FrameRate {
+    FrameRate {
+        numerator: rate.numerator,
+        denominator: rate.denominator * divisor.max(1),
+    }
+}
+
+/// Frame processor feeding the proxy sender; never changes or drops a
+/// frame. Dropping it stops the proxy.
+pub struct NdiProxy {
+    width: u32,
+    height: u32,
+    pacer: ProxyPacer,
+    budget: Arc<PipelineStats>,
+    /// `frames_over_budget` as of the previous frame
+    over_budget: u64,
+    frames: Option<SyncSender<Frame>>,
+    worker: Option<JoinHandle<()>>,
+    stats: Arc<OutputStats>,
+}
+
+impl NdiProxy {
+    /// Start sending proxy frames through `sender` on its own thread.
+    /// `budget` tells when the capture loop runs late.
+    pub fn start(
+        config: &ProxyConfig,
+        sender: Box<dyn VideoSender + Send>,
+        budget: Arc<PipelineStats>,
+        stats: Arc<OutputStats>,
+    ) -> Self {
+        let (frames_tx, frames_rx) = sync_channel(QUEUE_FRAMES);
+        let worker_stats = Arc::clone(&stats);
+        let worker = threads::spawn(threads::NDI_PROXY, move || {
+            run_sender(sender, frames_rx, &worker_stats)
+        });
+        Self {
+            width: config.width & !1,
+            height: config.height,
+            pacer: ProxyPacer::new(config.fps_divisor),
+            over_budget: budget.frames_over_budget.load(Ordering::Relaxed),
+            budget,
+            frames: Some(frames_tx),
+            worker: Some(worker),
+            stats,
+        }
+    }
+
+    /// Tightly packed `uyvy` scaled to the proxy size
+    pub fn downscale(&self, uyvy: &[u8], width: u32, height: u32) -> Frame {
+        Frame {
+            uyvy: scale_uyvy_nearest(uyvy, width, height, self.width, self.height),
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+impl FrameProcessor for NdiProxy {
+    fn name(&self) -> &str {
+        "ndi proxy"
+    }
+
+    fn process(&mut self, uyvy: &mut [u8], width: u32, height: u32) -> FrameAction {
+        let over_budget = self.budget.frames_over_budget.load(Ordering::Relaxed);
+        let late = over_budget != self.over_budget;
+        self.over_budget = over_budget;
+        if !self.pacer.due() {
+            return FrameAction::Send;
+        }
+        if late {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            return FrameAction::Send;
+        }
+        if let Some(frames) = &self.frames {
+            match frames.try_send(self.downscale(uyvy, width, height)) {
+                Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+                Err(TrySendError::Full(_)) => {
+                    self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        FrameAction::Send
+    }
+}
+
+impl Drop for NdiProxy {
+    fn drop(&mut self) {
+        // Closing the channel ends the sender thread
+        self.frames = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run_sender(
+    mut sender: Box<dyn VideoSender + Send>,
+    frames: Receiver<Frame>,
+    stats: &OutputStats,
+) {
+    let mut failing = false;
+    for frame in frames {
+        let info = FrameInfo::new(
+            frame.width,
+            frame.height,
+            FourCC::new(b"UYVY"),
+            frame.width * 2,
+        );
+        match sender.send_frame(&frame.uyvy, info) {
+            Ok(()) => {
+                stats.frames.fetch_add(1, Ordering::Relaxed);
+                if failing {
+                    tracing::info!("NDI proxy recovered");
+                    failing = false;
+                }
+            }
+            Err(e) => {
+                stats.errors.fetch_add(1, Ordering::Relaxed);
+                if !failing {
+                    tracing::warn!("NDI proxy send failed: {:#}", e);
+                    failing = true;
+                }
+            }
+        }
+        if let Some(connections) = sender.connections() {
+            stats.clients.store(connections as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::sync::mpsc::{channel, Sender};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    /// Reports the size of every frame sent, optionally holding each send
+    /// until released
+    struct SizeLog {
+        sizes: Sender<(u32, u32, usize)>,
+        gate: Option<Arc<Mutex<()>>>,
+    }
+
+    impl VideoSender for SizeLog {
+        fn send_frame(&mut self, data: &[u8], info: FrameInfo) -> Result<()> {
+            if let Some(gate) = &self.gate {
+                drop(gate.lock().unwrap());
+            }
+            let _ = self.sizes.send((info.width, info.height, data.len()));
+            Ok(())
+        }
+
+        fn recreate(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn config(fps_divisor: u32) -> ProxyConfig {
+        ProxyConfig {
+            enable: true,
+            fps_divisor,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pacer_sends_every_nth_frame() {
+        let pattern = |divisor| {
+            let mut pacer = ProxyPacer::new(divisor);
+            (0..7).map(|_| pacer.due()).collect::<Vec<_>>()
+        };
+        assert_eq!(pattern(1), [true; 7]);
+        assert_eq!(pattern(2), [true, false, true, false, true, false, true]);
+        assert_eq!(pattern(3), [true, false, false, true, false, false, true]);
+        // 0 is treated as 1
+        assert_eq!(pattern(0), [true; 7]);
+
+        let rate = FrameRate {
+            numerator: 60000,
+            denominator: 1001,
+        };
+        let halved = proxy_rate(rate, 2);
+        assert_eq!((halved.numerator, halved.denominator), (60000, 2002));
+    }
+
+    #[test]
+    fn test_downscale_to_proxy_size() {
+        let (sizes, _log) = channel();
+        let sender = Box::new(SizeLog { sizes, gate: None });
+        let stats = Arc::new(OutputStats::default());
+        let proxy = NdiProxy::start(
+            &ProxyConfig {
+                width: 481,
+                height: 270,
+                ..config(1)
+            },
+            sender,
+            Arc::default(),
+            stats,
+        );
+        for (width, height) in [(1920, 1080), (3840, 2160), (1280, 720)] {
+            let uyvy = vec![128u8; width as usize * height as usize * 2];
+            let frame = proxy.downscale(&uyvy, width, height);
+            // Odd widths round down to whole macropixels
+            assert_eq!((frame.width, frame.height), (480, 270));
+            assert_eq!(frame.uyvy.len(), 480 * 270 * 2);
+        }
+    }
+
+    #[test]
+    fn test_proxy_sends_every_nth_frame_scaled() {
+        let (sizes, log) = channel();
+        let sender = Box::new(SizeLog { sizes, gate: None });
+        let stats = Arc::new(OutputStats::default());
+        let mut proxy = NdiProxy::start(&config(3), sender, Arc::default(), Arc::clone(&stats));
+        let mut uyvy = vec![128u8; 1920 * 1080 * 2];
+        for _ in 0..9 {
+            assert_eq!(proxy.process(&mut uyvy, 1920, 1080), FrameAction::Send);
+            // Let the sender keep up so no frame is dropped
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        drop(proxy);
+        let sent: Vec<_> = log.try_iter().collect();
+        assert_eq!(sent, [(640, 360, 640 * 360 * 2); 3]);
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_proxy_skips_frames_instead_of_waiting() {
+        let (sizes, log) = channel();
+        let gate = Arc::new(Mutex::new(()));
+        let held = gate.lock().unwrap();
+        let sender = Box::new(SizeLog {
+            sizes,
+            gate: Some(Arc::clone(&gate)),
+        });
+        let stats = Arc::new(OutputStats::default());
+        let budget = Arc::new(PipelineStats::default());
+        let mut proxy =
+            NdiProxy::start(&config(1), sender, Arc::clone(&budget), Arc::clone(&stats));
+        let mut uyvy = vec![128u8; 64 * 36 * 2];
+
+        // The sender is stuck on the first frame and one more is queued
+        for _ in 0..5 {
+            assert_eq!(proxy.process(&mut uyvy, 64, 36), FrameAction::Send);
+        }
+        assert!(stats.dropped.load(Ordering::Relaxed) >= 3);
+        drop(held);
+        drop(proxy);
+        assert!(log.try_iter().count() <= 2);
+
+        // A frame over budget costs the proxy the next frame
+        let (sizes, log) = channel();
+        let sender = Box::new(SizeLog { sizes, gate: None });
+        let stats = Arc::new(OutputStats::default());
+        let mut proxy =
+            NdiProxy::start(&config(1), sender, Arc::clone(&budget), Arc::clone(&stats));
+        budget.frames_over_budget.fetch_add(1, Ordering::Relaxed);
+        proxy.process(&mut uyvy, 64, 36);
+        std::thread::sleep(Duration::from_millis(20));
+        proxy.process(&mut uyvy, 64, 36);
+        drop(proxy);
+        assert_eq!(log.try_iter().count(), 1);
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,1276 @@
+//! Supervised NDI sender
+//!
+//! Wraps the NDI sender so that repeated send failures destroy and recreate
+//! it (optionally reloading libndi) with exponential backoff, instead of
+//! leaving the capture pipeline stuck on a dead sender.
+
+use anyhow::Result;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use v4l::FourCC;
+
+use crate::aspect::AspectRatio;
+use crate::capture::{FrameInfo, FrameRate};
+use crate::deinterlace::{DeinterlaceMode, FieldOrder};
+use crate::intercom::Tally;
+use crate::ndi::{NdiSender, SendErrorKind, SendFailure};
+use crate::pacing::{FramePacer, PaceDecision, PacingMode};
+use crate::processing::SharedProcessors;
+
+/// A video sender the supervisor can restart
+pub trait VideoSender {
+    fn send_frame(&mut self, data: &[u8], info: FrameInfo) -> Result<()>;
+
+    /// Destroy and recreate the sender instance in place
+    fn recreate(&mut self) -> Result<()>;
+
+    /// Send interleaved 16-bit audio whose first sample was captured at
+    /// `captured`; senders without audio ignore it
+    fn send_audio(
+        &mut self,
+        _samples: &[i16],
+        _channels: u32,
+        _sample_rate: u32,
+        _captured: Option<Instant>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Take back the frame lent to the last asynchronous send, waiting
+    /// until the sender is done with it. Returns false if the buffer changed
+    /// while the sender still held it.
+    fn release_frame(&mut self) -> Result<bool> {
+        Ok(true)
+    }
+
+    /// Whether the last send lent `data` and still holds it, so its buffer
+    /// must stay untouched until the next send or `release_frame` returns
+    fn lends(&self, _data: &[u8]) -> bool {
+        false
+    }
+
+    /// Connected receivers, if the sender can tell
+    fn connections(&self) -> Option<u32> {
+        None
+    }
+
+    /// Whether a receiver shows this source on program or preview, if the
+    /// sender can tell
+    fn tally(&self) -> Option<Tally> {
+        None
+    }
+
+    /// Time the last frame spent in conversion before it was sent, if the
+    /// sender can tell
+    fn last_convert(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl VideoSender for Box<dyn VideoSender + Send> {
+    fn send_frame(&mut self, data: &[u8], info: FrameInfo) -> Result<()> {
+        (**self).send_frame(data, info)
+    }
+
+    fn recreate(&mut self) -> Result<()> {
+        (**self).recreate()
+    }
+
+    fn send_audio(
+        &mut self,
+        samples: &[i16],
+        channels: u32,
+        sample_rate: u32,
+        captured: Option<Instant>,
+    ) -> Result<()> {
+        (**self).send_audio(samples, channels, sample_rate, captured)
+    }
+
+    fn release_frame(&mut self) -> Result<bool> {
+        (**self).release_frame()
+    }
+
+    fn lends(&self, data: &[u8]) -> bool {
+        (**self).lends(data)
+    }
+
+    fn connections(&self) -> Option<u32> {
+        (**self).connections()
+    }
+
+    fn tally(&self) -> Option<Tally> {
+        (**self).tally()
+    }
+
+    fn last_convert(&self) -> Option<Duration> {
+        (**self).last_convert()
+    }
+}
+
+impl VideoSender for NdiSender {
+    fn send_frame(&mut self, data: &[u8], info: FrameInfo) -> Result<()> {
+        Ok(NdiSender::send_frame_zero_copy(self, data, info)?)
+    }
+
+    fn recreate(&mut self) -> Result<()> {
+        Ok(NdiSender::recreate(self)?)
+    }
+
+    fn send_audio(
+        &mut self,
+        samples: &[i16],
+        channels: u32,
+        sample_rate: u32,
+        captured: Option<Instant>,
+    ) -> Result<()> {
+        Ok(NdiSender::send_audio(
+            self,
+            samples,
+            channels,
+            sample_rate,
+            captured,
+        )?)
+    }
+
+    fn release_frame(&mut self) -> Result<bool> {
+        Ok(NdiSender::release_frame(self)?)
+    }
+
+    fn lends(&self, data: &[u8]) -> bool {
+        NdiSender::lends(self, data)
+    }
+
+    fn connections(&self) -> Option<u32> {
+        Some(NdiSender::connections(self))
+    }
+
+    fn tally(&self) -> Option<Tally> {
+        Some(NdiSender::tally(self))
+    }
+
+    fn last_convert(&self) -> Option<Duration> {
+        Some(NdiSender::last_convert(self))
+    }
+}
+
+/// Parameters needed to (re)create the NDI sender
+#[derive(Debug, Clone)]
+pub struct NdiSenderSettings {
+    pub name: String,
+    pub frame_rate: FrameRate,
+    /// NDI groups, comma-separated (None = default group)
+    pub groups: Option<String>,
+    pub deinterlace: DeinterlaceMode,
+    pub field_order: FieldOrder,
+    pub pacing: PacingMode,
+    /// Send held UYVY frames asynchronously, straight from the capture buffer
+    pub zero_copy: bool,
+    /// Picture aspect ratio sent with each frame
+    pub aspect: AspectRatio,
+    /// Frame processors, kept across sender restarts
+    pub processors: SharedProcessors,
+    /// Negotiated capture size, to size the conversion buffers up front
+    pub frame_size: (u32, u32),
+}
+
+impl NdiSenderSettings {
+    pub fn create(&self) -> Result<NdiSender> {
+        let mut sender = NdiSender::with_options(
+            &self.name,
+            self.frame_rate,
+            self.groups.as_deref(),
+            self.pacing == PacingMode::ClockVideo,
+        )?;
+        sender.set_deinterlace(self.deinterlace, self.field_order);
+        sender.set_processors(Arc::clone(&self.processors));
+        sender.set_zero_copy(self.zero_copy);
+        sender.set_aspect(self.aspect);
+        sender.reserve_frame(self.frame_size.0, self.frame_size.1);
+        Ok(sender)
+    }
+}
+
+// =============================================================================
+// Restart Policy
+// =============================================================================
+
+/// When and how often the sender is restarted
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Consecutive send errors before the sender is restarted
+    pub max_consecutive_errors: u32,
+    /// Minimum time between the first restart and the next one
+    pub initial_backoff: Duration,
+    /// Upper bound of the doubling backoff
+    pub max_backoff: Duration,
+    /// Reload libndi on restart instead of only recreating the sender
+    pub reload_library: bool,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_consecutive_errors: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            reload_library: false,
+        }
+    }
+}
+
+/// Error counting and exponential backoff between restarts
+#[derive(Debug)]
+pub struct RestartBackoff {
+    policy: RestartPolicy,
+    consecutive_errors: u32,
+    backoff: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl RestartBackoff {
+    pub fn new(policy: RestartPolicy) -> Self {
+        Self {
+            backoff: policy.initial_backoff,
+            policy,
+            consecutive_errors: 0,
+            retry_at: None,
+        }
+    }
+
+    /// A frame went out; the sender is healthy again
+    pub fn on_success(&mut self) {
+        self.consecutive_errors = 0;
+        self.backoff = self.policy.initial_backoff;
+    }
+
+    /// Record a send error. Returns true if the sender should be restarted now.
+    pub fn on_error(&mut self, now: Instant) -> bool {
+        self.consecutive_errors = self.consecutive_errors.saturating_add(1);
+        self.consecutive_errors >= self.policy.max_consecutive_errors && self.ready(now)
+    }
+
+    /// True once the backoff since the last restart has elapsed
+    pub fn ready(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|at| now >= at)
+    }
+
+    /// Record a restart attempt and double the backoff
+    pub fn restarted(&mut self, now: Instant) {
+        self.consecutive_errors = 0;
+        self.retry_at = Some(now + self.backoff);
+        self.backoff = (self.backoff * 2).min(self.policy.max_backoff);
+    }
+
+    /// Backoff applied after the next restart
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+}
+
+// =============================================================================
+// Circuit Breaker
+// =============================================================================
+
+/// Consecutive failures of one [`SendFailure`] that open the send breaker
+pub const BREAKER_THRESHOLD: u32 = 10;
+
+/// Interval between trial sends while the breaker is open
+pub const BREAKER_RETRY: Duration = Duration::from_secs(1);
+
+/// Send circuit breaker state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Every frame is sent
+    Closed,
+    /// Frames are dropped unconverted until the retry is due
+    Open,
+    /// One trial frame is being sent
+    HalfOpen,
+}
+
+/// State changes worth one log line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerTransition {
+    /// `failures` consecutive sends failed with `failure`
+    Opened { failure: SendFailure, failures: u32 },
+    /// A trial send worked after `suppressed` frames were dropped
+    Recovered { suppressed: u64 },
+}
+
+/// Stops the capture loop from converting and logging every frame while
+/// sends keep failing the same way, e.g. after a source switched to an
+/// unsupported format. Open after `threshold` consecutive failures of one
+/// [`SendFailure`], then try one frame per `retry` interval until a send
+/// succeeds.
+#[derive(Debug)]
+pub struct SendBreaker {
+    threshold: u32,
+    retry: Duration,
+    state: BreakerState,
+    failure: Option<SendFailure>,
+    failures: u32,
+    retry_at: Option<Instant>,
+    suppressed: u64,
+}
+
+impl SendBreaker {
+    pub fn new(threshold: u32, retry: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            retry,
+            state: BreakerState::Closed,
+            failure: None,
+            failures: 0,
+            retry_at: None,
+            suppressed: 0,
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.state
+    }
+
+    /// Whether to send the frame at `now`. While open, frames are counted as
+    /// suppressed until the next trial is due.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        match self.state {
+            BreakerState::Closed | BreakerState::HalfOpen => true,
+            BreakerState::Open if self.retry_at.is_none_or(|at| now >= at) => {
+                self.state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open => {
+                self.suppressed += 1;
+                false
+            }
+        }
+    }
+
+    /// A send went through
+    pub fn on_success(&mut self) -> Option<BreakerTransition> {
+        let recovered =
+            (self.state != BreakerState::Closed).then_some(BreakerTransition::Recovered {
+                suppressed: self.suppressed,
+            });
+        self.state = BreakerState::Closed;
+        self.failure = None;
+        self.failures = 0;
+        self.retry_at = None;
+        self.suppressed = 0;
+        recovered
+    }
+
+    /// A send failed with `failure`
+    pub fn on_failure(&mut self, now: Instant, failure: SendFailure) -> Option<BreakerTransition> {
+        if self.failure == Some(failure) {
+            self.failures = self.failures.saturating_add(1);
+        } else {
+            self.failure = Some(failure);
+            self.failures = 1;
+        }
+        match self.state {
+            BreakerState::HalfOpen => {
+                // Trial failed: stay open quietly
+                self.state = BreakerState::Open;
+                self.retry_at = Some(now + self.retry);
+                None
+            }
+            BreakerState::Closed if self.failures >= self.threshold => {
+                self.state = BreakerState::Open;
+                self.retry_at = Some(now + self.retry);
+                Some(BreakerTransition::Opened {
+                    failure,
+                    failures: self.failures,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+// =============================================================================
+// Supervisor
+// =============================================================================
+
+/// NDI sender counters (shared with status consumers)
+#[derive(Debug, Default)]
+pub struct NdiSenderStats {
+    pub frames_sent: AtomicU64,
+    /// Video payload bytes handed to the sender
+    pub bytes_sent: AtomicU64,
+    pub send_errors: AtomicU64,
+    pub restarts: AtomicU64,
+    pub restart_failures: AtomicU64,
+    /// Frames discarded while no sender was available
+    pub frames_dropped: AtomicU64,
+    /// Frames skipped by software pacing (source faster than the target rate)
+    pub pacing_dropped: AtomicU64,
+    /// Extra sends by software pacing (source slower than the target rate)
+    pub pacing_repeated: AtomicU64,
+    /// Sender recreations after local address changes
+    pub reannounces: AtomicU64,
+    /// Zero-copy frames whose buffer changed before the sender released it
+    pub zero_copy_torn: AtomicU64,
+    /// MJPEG frames the decoder rejected
+    pub mjpeg_decode_failures: AtomicU64,
+    /// Sends attempted while no receiver was connected
+    pub sends_disconnected: AtomicU64,
+    /// Frames handed to the sender, converted and rejected per pixel format
+    pub formats: FormatCounters,
+}
+
+/// Pixel formats counted separately; formats seen after these are all
+/// counted in one "other" slot
+pub const FORMAT_SLOTS: usize = 8;
+
+/// Key of the slot counting every format beyond [`FORMAT_SLOTS`]
+const OTHER_FORMATS: u32 = u32::MAX;
+
+/// Per-format frame counts, a fixed map keyed by the fourcc as u32. Slots
+/// are claimed in the order formats first show up, without locking.
+#[derive(Debug, Default)]
+pub struct FormatCounters {
+    slots: [FormatSlot; FORMAT_SLOTS],
+}
+
+#[derive(Debug, Default)]
+struct FormatSlot {
+    /// Fourcc of the format counted here; 0 while unclaimed
+    fourcc: AtomicU32,
+    received: AtomicU64,
+    converted: AtomicU64,
+    rejected: AtomicU64,
+}
+
+/// Counts of one pixel format, read from [`FormatCounters`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatCount {
+    /// `None` for the slot shared by formats beyond [`FORMAT_SLOTS`]
+    pub fourcc: Option<FourCC>,
+    /// Frames handed to the sender
+    pub received: u64,
+    /// Frames converted and sent
+    pub converted: u64,
+    /// Frames whose format is unsupported or that failed to convert
+    pub rejected: u64,
+}
+
+impl FormatCount {
+    /// Metric name of the format, e.g. "UYVY"
+    pub fn name(&self) -> String {
+        let Some(fourcc) = self.fourcc else {
+            return "other".to_string();
+        };
+        // Codes like "Y16 " are padded with spaces; others aren't text at all
+        match fourcc.str() {
+            Ok(name) if name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b' ') => {
+                name.trim_end().to_string()
+            }
+            _ => format!("{:08x}", u32::from(fourcc)),
+        }
+    }
+}
+
+impl FormatCounters {
+    /// Slot counting `fourcc`, claiming a free one for a new format
+    fn slot(&self, fourcc: FourCC) -> &FormatSlot {
+        let key = u32::from(fourcc);
+        let (last, named) = self.slots.split_last().expect("FORMAT_SLOTS > 0");
+        if key != 0 && key != OTHER_FORMATS {
+            for slot in named {
+                let claimed =
+                    match slot
+                        .fourcc
+                        .compare_exchange(0, key, Ordering::Relaxed, Ordering::Relaxed)
+                    {
+                        Ok(_) => key,
+                        Err(current) => current,
+                    };
+                if claimed == key {
+                    return slot;
+                }
+            }
+        }
+        last.fourcc.store(OTHER_FORMATS, Ordering::Relaxed);
+        last
+    }
+
+    pub fn received(&self, fourcc: FourCC) {
+        self.slot(fourcc).received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn converted(&self, fourcc: FourCC) {
+        self.slot(fourcc).converted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn rejected(&self, fourcc: FourCC) {
+        self.slot(fourcc).rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts of every format seen so far, in the order they showed up
+    pub fn counts(&self) -> Vec<FormatCount> {
+        self.slots
+            .iter()
+            .filter_map(|slot| {
+                let fourcc = match slot.fourcc.load(Ordering::Relaxed) {
+                    0 => return None,
+                    OTHER_FORMATS => None,
+                    key => Some(FourCC::from(key)),
+                };
+                Some(FormatCount {
+                    fourcc,
+                    received: slot.received.load(Ordering::Relaxed),
+                    converted: slot.converted.load(Ordering::Relaxed),
+                    rejected: slot.rejected.load(Ordering::Relaxed),
+                })
+            })
+            .collect()
+    }
+
+    /// Counts of `fourcc`, if it has a slot of its own
+    pub fn get(&self, fourcc: FourCC) -> Option<FormatCount> {
+        self.counts()
+            .into_iter()
+            .find(|count| count.fourcc == Some(fourcc))
+    }
+}
+
+type SenderFactory<S> = Box<dyn FnMut() -> Result<S> + Send>;
+
+/// Owns the NDI sender and restarts it on repeated send errors
+pub struct NdiSenderSupervisor<S: VideoSender = NdiSender> {
+    sender: Option<S>,
+    factory: SenderFactory<S>,
+    backoff: RestartBackoff,
+    reload_library: bool,
+    pacer: Option<FramePacer>,
+    stats: Arc<NdiSenderStats>,
+}
+
+impl NdiSenderSupervisor<NdiSender> {
+    /// Create the NDI sender and supervise it
+    pub fn new(settings: NdiSenderSettings, policy: RestartPolicy) -> Result<Self> {
+        let pacer =
+            (settings.pacing == PacingMode::Software).then(|| FramePacer::new(settings.frame_rate));
+        let mut supervisor = Self::with_factory(policy, Box::new(move || settings.create()))?;
+        supervisor.pacer = pacer;
+        Ok(supervisor)
+    }
+}
+
+impl<S: VideoSender> NdiSenderSupervisor<S> {
+    /// Supervise senders built by `factory`, which is called once up front and
+    /// again for every library reload
+    pub fn with_factory(policy: RestartPolicy, mut factory: SenderFactory<S>) -> Result<Self> {
+        let sender = factory()?;
+        Ok(Self {
+            sender: Some(sender),
+            factory,
+            reload_library: policy.reload_library,
+            backoff: RestartBackoff::new(policy),
+            pacer: None,
+            stats: Arc::new(NdiSenderStats::default()),
+        })
+    }
+
+    /// Count into an existing stats block, e.g. one created before the sender
+    pub fn with_stats(mut self, stats: Arc<NdiSenderStats>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// Pace sends onto a fixed schedule (software pacing)
+    pub fn with_pacer(mut self, pacer: FramePacer) -> Self {
+        self.pacer = Some(pacer);
+        self
+    }
+
+    pub fn stats(&self) -> Arc<NdiSenderStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Send a frame, restarting the sender after repeated errors
+    pub fn send_frame_data(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        fourcc: FourCC,
+        stride: u32,
+    ) -> Result<()> {
+        self.send_frame_zero_copy(data, FrameInfo::new(width, height, fourcc, stride))
+    }
+
+    /// Zero-copy send from FrameInfo (callback-compatible)
+    #[inline]
+    pub fn send_frame_zero_copy(&mut self, data: &[u8], info: FrameInfo) -> Result<()> {
+        self.send_frame_at(Instant::now(), data, info)
+    }
+
+    fn send_frame_at(&mut self, now: Instant, data: &[u8], info: FrameInfo) -> Result<()> {
+        let copies = match self.pacer.as_mut().map(|pacer| pacer.on_frame(now)) {
+            None => 1,
+            Some(PaceDecision::Drop) => {
+                self.stats.pacing_dropped.fetch_add(1, Ordering::Relaxed);
+                return Ok(());
+            }
+            Some(PaceDecision::Send(copies)) => {
+                self.stats
+                    .pacing_repeated
+                    .fetch_add(copies as u64 - 1, Ordering::Relaxed);
+                copies
+            }
+        };
+        self.send_once(now, data, info)?;
+        // Repeats are stamped by NDI; they'd share the original's timecode
+        let repeat = FrameInfo {
+            captured: None,
+            ..info
+        };
+        for _ in 1..copies {
+            self.send_once(now, data, repeat)?;
+        }
+        Ok(())
+    }
+
+    fn send_once(&mut self, now: Instant, data: &[u8], info: FrameInfo) -> Result<()> {
+        let Some(sender) = self.sender.as_mut() else {
+            // Library reload failed earlier - retry once the backoff elapses
+            if self.backoff.ready(now) {
+                self.restart(now);
+            }
+            self.stats.frames_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        };
+
+        let formats = &self.stats.formats;
+        formats.received(info.fourcc);
+        if sender.connections() == Some(0) {
+            self.stats
+                .sends_disconnected
+                .fetch_add(1, Ordering::Relaxed);
+        }
+        match sender.send_frame(data, info) {
+            Ok(()) => {
+                formats.converted(info.fourcc);
+                let sent = self.stats.frames_sent.fetch_add(1, Ordering::Relaxed) + 1;
+                self.stats
+                    .bytes_sent
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
+                if sent.is_multiple_of(300) {
+                    tracing::debug!("Sent {} frames", sent);
+                }
+                self.backoff.on_success();
+                Ok(())
+            }
+            Err(e) => {
+                self.stats.send_errors.fetch_add(1, Ordering::Relaxed);
+                match SendFailure::of(&e) {
+                    SendFailure::UnsupportedFormat => formats.rejected(info.fourcc),
+                    SendFailure::ConversionFailed => {
+                        formats.rejected(info.fourcc);
+                        if info.fourcc == FourCC::new(b"MJPG") {
+                            self.stats
+                                .mjpeg_decode_failures
+                                .fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                    SendFailure::SendFailed => {}
+                }
+                // A new sender can't fix an unconvertible format
+                if SendErrorKind::of(&e) == SendErrorKind::Transient && self.backoff.on_error(now) {
+                    tracing::warn!("NDI sender failing ({}), restarting", e);
+                    self.restart(now);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Send audio on the current sender. Audio never triggers a restart and
+    /// is discarded while no sender is available.
+    pub fn send_audio(
+        &mut self,
+        samples: &[i16],
+        channels: u32,
+        sample_rate: u32,
+        captured: Option<Instant>,
+    ) -> Result<()> {
+        match self.sender.as_mut() {
+            Some(sender) => sender.send_audio(samples, channels, sample_rate, captured),
+            None => Ok(()),
+        }
+    }
+
+    /// Take back the frame lent to the current sender; call it before the
+    /// source may reuse the frame's buffer
+    pub fn release_frame(&mut self) {
+        let Some(sender) = self.sender.as_mut() else {
+            return;
+        };
+        match sender.release_frame() {
+            Ok(true) => {}
+            Ok(false) => {
+                self.stats.zero_copy_torn.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Zero-copy frame buffer changed while NDI still held it");
+            }
+            Err(e) => tracing::debug!("Failed to release the held frame: {:#}", e),
+        }
+    }
+
+    /// Whether the current sender still holds `data` from the last send
+    pub fn lends(&self, data: &[u8]) -> bool {
+        self.sender
+            .as_ref()
+            .is_some_and(|sender| sender.lends(data))
+    }
+
+    /// Connected receivers of the current sender, if known
+    pub fn connections(&self) -> Option<u32> {
+        self.sender.as_ref().and_then(|sender| sender.connections())
+    }
+
+    /// Tally of the current sender, if known
+    pub fn tally(&self) -> Option<Tally> {
+        self.sender.as_ref().and_then(|sender| sender.tally())
+    }
+
+    /// Conversion time of the last frame the current sender sent, if known
+    pub fn last_convert(&self) -> Option<Duration> {
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.last_convert())
+    }
+
+    /// Recreate the sender with the same parameters so NDI advertises the
+    /// current local addresses. If that fails the sender is dropped: frames
+    /// are discarded (and counted) until the backoff allows a new one.
+    pub fn reannounce(&mut self, now: Instant) {
+        let Some(sender) = self.sender.as_mut() else {
+            // A restart is already pending and will announce afresh
+            return;
+        };
+        self.stats.reannounces.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = sender.recreate() {
+            self.sender = None;
+            self.backoff.restarted(now);
+            self.stats.restart_failures.fetch_add(1, Ordering::Relaxed);
+            tracing::error!("NDI sender re-announce failed: {}", e);
+        }
+    }
+
+    fn restart(&mut self, now: Instant) {
+        let retry_in = self.backoff.backoff();
+        self.backoff.restarted(now);
+        self.stats.restarts.fetch_add(1, Ordering::Relaxed);
+
+        let result = match self.sender.as_mut() {
+            Some(sender) if !self.reload_library => sender.recreate(),
+            _ => {
+                // Drop the old sender (and its library handle) before reloading
+                self.sender = None;
+                (self.factory)().map(|sender| self.sender = Some(sender))
+            }
+        };
+
+        if let Err(e) = result {
+            self.stats.restart_failures.fetch_add(1, Ordering::Relaxed);
+            tracing::error!(
+                "NDI sender restart failed: {} (next attempt in {:?})",
+                e,
+                retry_in
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ndi::SendError;
+    use std::sync::atomic::AtomicBool;
+
+    #[derive(Default)]
+    struct Control {
+        failing: AtomicBool,
+        /// Fail with a format error instead of a transient one
+        format_error: AtomicBool,
+        recreate_fails: AtomicBool,
+        recreated: AtomicU32,
+        created: AtomicU32,
+    }
+
+    struct FakeSender {
+        control: Arc<Control>,
+    }
+
+    impl VideoSender for FakeSender {
+        fn send_frame(&mut self, _: &[u8], _: FrameInfo) -> Result<()> {
+            if self.control.failing.load(Ordering::Relaxed) {
+                if self.control.format_error.load(Ordering::Relaxed) {
+                    return Err(SendError::new(
+                        SendErrorKind::Format,
+                        anyhow::anyhow!("Unsupported video format"),
+                    )
+                    .into());
+                }
+                anyhow::bail!("send failed");
+            }
+            Ok(())
+        }
+
+        fn recreate(&mut self) -> Result<()> {
+            self.control.recreated.fetch_add(1, Ordering::Relaxed);
+            if self.control.recreate_fails.load(Ordering::Relaxed) {
+                anyhow::bail!("recreate failed");
+            }
+            Ok(())
+        }
+    }
+
+    fn policy(reload_library: bool) -> RestartPolicy {
+        RestartPolicy {
+            max_consecutive_errors: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(400),
+            reload_library,
+        }
+    }
+
+    fn supervisor(reload_library: bool) -> (NdiSenderSupervisor<FakeSender>, Arc<Control>) {
+        let control = Arc::new(Control::default());
+        let factory_control = Arc::clone(&control);
+        let supervisor = NdiSenderSupervisor::with_factory(
+            policy(reload_library),
+            Box::new(move || {
+                factory_control.created.fetch_add(1, Ordering::Relaxed);
+                Ok(FakeSender {
+                    control: Arc::clone(&factory_control),
+                })
+            }),
+        )
+        .unwrap();
+        (supervisor, control)
+    }
+
+    fn send(s: &mut NdiSenderSupervisor<FakeSender>, now: Instant) -> Result<()> {
+        s.send_frame_at(now, &[0; 8], FrameInfo::new(4, 1, FourCC::new(b"UYVY"), 8))
+    }
+
+    #[test]
+    fn test_backoff_requires_consecutive_errors() {
+        let mut backoff = RestartBackoff::new(policy(false));
+        let now = Instant::now();
+        assert!(!backoff.on_error(now));
+        assert!(!backoff.on_error(now));
+        backoff.on_success();
+        assert!(!backoff.on_error(now));
+        assert!(!backoff.on_error(now));
+        assert!(backoff.on_error(now));
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let mut backoff = RestartBackoff::new(policy(false));
+        let t0 = Instant::now();
+        backoff.restarted(t0);
+        assert_eq!(backoff.backoff(), Duration::from_millis(200));
+        assert!(!backoff.ready(t0 + Duration::from_millis(99)));
+        assert!(backoff.ready(t0 + Duration::from_millis(100)));
+        backoff.restarted(t0);
+        backoff.restarted(t0);
+        backoff.restarted(t0);
+        assert_eq!(backoff.backoff(), Duration::from_millis(400));
+
+        backoff.on_success();
+        assert_eq!(backoff.backoff(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_breaker_opens_after_consecutive_failures_of_one_kind() {
+        let mut breaker = SendBreaker::new(3, Duration::from_secs(1));
+        let t0 = Instant::now();
+        assert_eq!(breaker.on_failure(t0, SendFailure::UnsupportedFormat), None);
+        assert_eq!(breaker.on_failure(t0, SendFailure::UnsupportedFormat), None);
+        // A different failure restarts the count
+        assert_eq!(breaker.on_failure(t0, SendFailure::SendFailed), None);
+        assert_eq!(breaker.on_failure(t0, SendFailure::UnsupportedFormat), None);
+        assert_eq!(breaker.on_failure(t0, SendFailure::UnsupportedFormat), None);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert_eq!(
+            breaker.on_failure(t0, SendFailure::UnsupportedFormat),
+            Some(BreakerTransition::Opened {
+                failure: SendFailure::UnsupportedFormat,
+                failures: 3
+            })
+        );
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        // A success while closed resets the count
+        let mut breaker = SendBreaker::new(2, Duration::from_secs(1));
+        breaker.on_failure(t0, SendFailure::UnsupportedFormat);
+        assert_eq!(breaker.on_success(), None);
+        assert_eq!(breaker.on_failure(t0, SendFailure::UnsupportedFormat), None);
+    }
+
+    #[test]
+    fn test_breaker_half_open_trials() {
+        let mut breaker = SendBreaker::new(1, Duration::from_secs(1));
+        let t0 = Instant::now();
+        assert!(breaker.allow(t0));
+        breaker.on_failure(t0, SendFailure::UnsupportedFormat);
+
+        // Open: frames are suppressed until the retry is due
+        assert!(!breaker.allow(t0 + Duration::from_millis(500)));
+        assert!(!breaker.allow(t0 + Duration::from_millis(999)));
+        assert!(breaker.allow(t0 + Duration::from_secs(1)));
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+
+        // Failed trial: open again for another interval, without a new transition
+        let t1 = t0 + Duration::from_secs(1);
+        assert_eq!(breaker.on_failure(t1, SendFailure::UnsupportedFormat), None);
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.allow(t1 + Duration::from_millis(500)));
+        assert!(breaker.allow(t1 + Duration::from_secs(1)));
+
+        // Successful trial closes it and reports what was dropped
+        assert_eq!(
+            breaker.on_success(),
+            Some(BreakerTransition::Recovered { suppressed: 3 })
+        );
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.allow(t1 + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_supervisor_keeps_sender_on_format_errors() {
+        let (mut s, control) = supervisor(false);
+        control.failing.store(true, Ordering::Relaxed);
+        control.format_error.store(true, Ordering::Relaxed);
+        let t0 = Instant::now();
+        for _ in 0..10 {
+            let e = send(&mut s, t0).unwrap_err();
+            assert_eq!(SendErrorKind::of(&e), SendErrorKind::Format);
+        }
+        // Restarting can't fix the format
+        assert_eq!(control.recreated.load(Ordering::Relaxed), 0);
+        assert_eq!(s.stats().send_errors.load(Ordering::Relaxed), 10);
+        assert_eq!(
+            SendErrorKind::of(&anyhow::anyhow!("send failed")),
+            SendErrorKind::Transient
+        );
+    }
+
+    #[test]
+    fn test_supervisor_recreates_after_repeated_errors() {
+        let (mut s, control) = supervisor(false);
+        let t0 = Instant::now();
+        assert!(send(&mut s, t0).is_ok());
+
+        control.failing.store(true, Ordering::Relaxed);
+        for _ in 0..3 {
+            assert!(send(&mut s, t0).is_err());
+        }
+        assert_eq!(control.recreated.load(Ordering::Relaxed), 1);
+        assert_eq!(control.created.load(Ordering::Relaxed), 1);
+        assert_eq!(s.stats().restarts.load(Ordering::Relaxed), 1);
+        assert_eq!(s.stats().send_errors.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_supervisor_backs_off_between_restarts() {
+        let (mut s, control) = supervisor(false);
+        control.failing.store(true, Ordering::Relaxed);
+        let t0 = Instant::now();
+        for _ in 0..6 {
+            let _ = send(&mut s, t0);
+        }
+        // Second burst within the backoff window doesn't restart again
+        assert_eq!(control.recreated.load(Ordering::Relaxed), 1);
+
+        let t1 = t0 + Duration::from_millis(100);
+        let _ = send(&mut s, t1);
+        assert_eq!(control.recreated.load(Ordering::Relaxed), 2);
+
+        // Next window is twice as long
+        for _ in 0..3 {
+            let _ = send(&mut s, t1 + Duration::from_millis(150));
+        }
+        assert_eq!(control.recreated.load(Ordering::Relaxed), 2);
+        let _ = send(&mut s, t1 + Duration::from_millis(200));
+        assert_eq!(control.recreated.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_supervisor_reloads_library_when_enabled() {
+        let (mut s, control) = supervisor(true);
+        control.failing.store(true, Ordering::Relaxed);
+        let t0 = Instant::now();
+        for _ in 0..3 {
+            let _ = send(&mut s, t0);
+        }
+        assert_eq!(control.created.load(Ordering::Relaxed), 2);
+        assert_eq!(control.recreated.load(Ordering::Relaxed), 0);
+
+        control.failing.store(false, Ordering::Relaxed);
+        assert!(send(&mut s, t0).is_ok());
+        assert_eq!(s.stats().frames_sent.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_supervisor_counters_survive_recreation() {
+        let (mut s, control) = supervisor(true);
+        let t0 = Instant::now();
+        send(&mut s, t0).unwrap();
+        control.failing.store(true, Ordering::Relaxed);
+        for _ in 0..3 {
+            let _ = send(&mut s, t0);
+        }
+        control.failing.store(false, Ordering::Relaxed);
+        send(&mut s, t0).unwrap();
+        assert_eq!(control.created.load(Ordering::Relaxed), 2);
+        let stats = s.stats();
+        assert_eq!(stats.frames_sent.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.bytes_sent.load(Ordering::Relaxed), 16);
+
+        // A new supervisor counting into the same block carries on from there
+        let (s, _control) = supervisor(false);
+        let mut s = s.with_stats(Arc::clone(&stats));
+        send(&mut s, t0).unwrap();
+        assert_eq!(stats.frames_sent.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.bytes_sent.load(Ordering::Relaxed), 24);
+        assert_eq!(stats.send_errors.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_supervisor_software_pacing_counts() {
+        let (s, _control) = supervisor(false);
+        let mut s = s.with_pacer(FramePacer::new(FrameRate {
+            numerator: 50,
+            denominator: 1,
+        }));
+        let t0 = Instant::now();
+        send(&mut s, t0).unwrap();
+        send(&mut s, t0 + Duration::from_millis(1)).unwrap();
+        send(&mut s, t0 + Duration::from_millis(40)).unwrap();
+
+        let stats = s.stats();
+        assert_eq!(stats.frames_sent.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.pacing_dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.pacing_repeated.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_supervisor_counts_failed_restarts() {
+        let (mut s, control) = supervisor(false);
+        control.failing.store(true, Ordering::Relaxed);
+        control.recreate_fails.store(true, Ordering::Relaxed);
+        let t0 = Instant::now();
+        for _ in 0..3 {
+            let _ = send(&mut s, t0);
+        }
+        assert_eq!(s.stats().restart_failures.load(Ordering::Relaxed), 1);
+
+        // Recovered sender resets the error streak
+        control.failing.store(false, Ordering::Relaxed);
+        assert!(send(&mut s, t0).is_ok());
+        assert_eq!(s.backoff.backoff(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_supervisor_reannounce_recreates_sender() {
+        let (mut s, control) = supervisor(false);
+        let t0 = Instant::now();
+        s.reannounce(t0);
+        assert_eq!(control.recreated.load(Ordering::Relaxed), 1);
+        assert_eq!(s.stats().reannounces.load(Ordering::Relaxed), 1);
+        assert!(send(&mut s, t0).is_ok());
+
+        // A failed recreate drops frames until the backoff allows a new sender
+        control.recreate_fails.store(true, Ordering::Relaxed);
+        s.reannounce(t0);
+        assert!(send(&mut s, t0).is_ok());
+        assert_eq!(s.stats().frames_dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(control.created.load(Ordering::Relaxed), 1);
+        assert!(send(&mut s, t0 + Duration::from_millis(100)).is_ok());
+        assert_eq!(control.created.load(Ordering::Relaxed), 2);
+        assert!(send(&mut s, t0 + Duration::from_millis(100)).is_ok());
+        assert_eq!(s.stats().frames_sent.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_supervisor_drops_frames_while_reload_pending() {
+        let control = Arc::new(Control::default());
+        let factory_control = Arc::clone(&control);
+        let mut s = NdiSenderSupervisor::with_factory(
+            policy(true),
+            Box::new(move || {
+                let n = factory_control.created.fetch_add(1, Ordering::Relaxed);
+                if n == 1 {
+                    anyhow::bail!("library load failed");
+                }
+                Ok(FakeSender {
+                    control: Arc::clone(&factory_control),
+                })
+            }),
+        )
+        .unwrap();
+
+        control.failing.store(true, Ordering::Relaxed);
+        let t0 = Instant::now();
+        for _ in 0..3 {
+            let _ = send(&mut s, t0);
+        }
+        assert_eq!(s.stats().restart_failures.load(Ordering::Relaxed), 1);
+
+        // No sender: frames are dropped until the backoff allows a new reload
+        control.failing.store(false, Ordering::Relaxed);
+        assert!(send(&mut s, t0 + Duration::from_millis(50)).is_ok());
+        assert_eq!(s.stats().frames_dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(control.created.load(Ordering::Relaxed), 2);
+
+        assert!(send(&mut s, t0 + Duration::from_millis(100)).is_ok());
+        assert_eq!(control.created.load(Ordering::Relaxed), 3);
+        assert!(send(&mut s, t0 + Duration::from_millis(101)).is_ok());
+        assert_eq!(s.stats().frames_sent.load(Ordering::Relaxed), 1);
+    }
+
+    /// Fails the way the NDI sender does: UYVY and MJPEG are supported,
+    /// MJPEG frames starting with 0 don't decode, and nothing sends while
+    /// `available` is false
+    struct FormatSender {
+        available: Arc<AtomicBool>,
+    }
+
+    impl VideoSender for FormatSender {
+        fn send_frame(&mut self, data: &[u8], info: FrameInfo) -> Result<()> {
+            let failure = match info.fourcc.str().unwrap_or("") {
+                "UYVY" => None,
+                "MJPG" if data.first() == Some(&0) => Some(SendFailure::ConversionFailed),
+                "MJPG" => None,
+                _ => Some(SendFailure::UnsupportedFormat),
+            }
+            .or((!self.available.load(Ordering::Relaxed)).then_some(SendFailure::SendFailed));
+            match failure {
+                Some(failure) => Err(SendError::new(
+                    failure,
+                    SendErrorKind::Transient,
+                    anyhow::anyhow!("{:?}", failure),
+                )
+                .into()),
+                None => Ok(()),
+            }
+        }
+
+        fn recreate(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn connections(&self) -> Option<u32> {
+            Some(self.available.load(Ordering::Relaxed) as u32)
+        }
+    }
+
+    #[test]
+    fn test_supervisor_counts_per_format() {
+        let available = Arc::new(AtomicBool::new(true));
+        let sender_available = Arc::clone(&available);
+        let mut s = NdiSenderSupervisor::with_factory(
+            RestartPolicy {
+                max_consecutive_errors: 100,
+                ..policy(false)
+            },
+            Box::new(move || {
+                Ok(FormatSender {
+                    available: Arc::clone(&sender_available),
+                })
+            }),
+        )
+        .unwrap();
+        let t0 = Instant::now();
+        let uyvy = FourCC::new(b"UYVY");
+        let mjpg = FourCC::new(b"MJPG");
+        let rgb3 = FourCC::new(b"RGB3");
+        let mut frame = |data: &[u8], fourcc| {
+            let result = s.send_frame_at(t0, data, FrameInfo::new(4, 1, fourcc, 8));
+            result.map_err(|e| SendFailure::of(&e))
+        };
+
+        assert_eq!(frame(&[1; 8], uyvy), Ok(()));
+        assert_eq!(frame(&[1; 8], mjpg), Ok(()));
+        assert_eq!(frame(&[0; 8], mjpg), Err(SendFailure::ConversionFailed));
+        assert_eq!(frame(&[1; 8], rgb3), Err(SendFailure::UnsupportedFormat));
+        assert_eq!(frame(&[1; 8], rgb3), Err(SendFailure::UnsupportedFormat));
+        assert_eq!(frame(&[1; 8], uyvy), Ok(()));
+        available.store(false, Ordering::Relaxed);
+        assert_eq!(frame(&[1; 8], uyvy), Err(SendFailure::SendFailed));
+        assert_eq!(frame(&[1; 8], mjpg), Err(SendFailure::SendFailed));
+        assert_eq!(frame(&[0; 8], mjpg), Err(SendFailure::ConversionFailed));
+
+        let stats = s.stats();
+        let count = |fourcc| stats.formats.get(fourcc).unwrap();
+        assert_eq!(
+            count(uyvy),
+            FormatCount {
+                fourcc: Some(uyvy),
+                received: 3,
+                converted: 2,
+                rejected: 0
+            }
+        );
+        assert_eq!(
+            count(mjpg),
+            FormatCount {
+                fourcc: Some(mjpg),
+                received: 4,
+                converted: 1,
+                rejected: 2
+            }
+        );
+        assert_eq!(
+            count(rgb3),
+            FormatCount {
+                fourcc: Some(rgb3),
+                received: 2,
+                converted: 0,
+                rejected: 2
+            }
+        );
+        let names: Vec<String> = stats
+            .formats
+            .counts()
+            .iter()
+            .map(FormatCount::name)
+            .collect();
+        assert_eq!(names, ["UYVY", "MJPG", "RGB3"]);
+
+        assert_eq!(stats.mjpeg_decode_failures.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.sends_disconnected.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.send_errors.load(Ordering::Relaxed), 6);
+        assert_eq!(stats.frames_sent.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_format_counters_share_the_last_slot() {
+        let formats = FormatCounters::default();
+        let fourccs: Vec<FourCC> = (1..=FORMAT_SLOTS as u32 + 2).map(FourCC::from).collect();
+        for fourcc in &fourccs {
+            formats.received(*fourcc);
+        }
+        formats.rejected(fourccs[0]);
+        formats.converted(fourccs[FORMAT_SLOTS + 1]);
+
+        let counts = formats.counts();
+        assert_eq!(counts.len(), FORMAT_SLOTS);
+        assert_eq!(formats.get(fourccs[0]).unwrap().rejected, 1);
+        assert_eq!(formats.get(fourccs[FORMAT_SLOTS - 2]).unwrap().received, 1);
+        // Formats past the named slots are counted together
+        assert_eq!(formats.get(fourccs[FORMAT_SLOTS - 1]), None);
+        let other = counts.last().unwrap();
+        assert_eq!(other.name(), "other");
+        assert_eq!((other.received, other.converted), (3, 1));
+        // Codes that aren't text are named in hex
+        assert_eq!(counts[0].name(), "00000001");
+    }
+}
@@ -0,0 +1,503 @@
+//! Socket helpers for the intercom and the status page
+//!
+//! Thin `setsockopt`/`getsockopt` wrappers for QoS marking (DSCP),
+//! receive buffer sizing, pinning a socket to a network interface and
+//! sharing a port with other listeners, plus address resolution and
+//! binding that work the same for IPv4 and IPv6.
+
+use anyhow::{anyhow, bail, Result};
+use std::io::ErrorKind;
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, TcpListener, ToSocketAddrs,
+    UdpSocket,
+};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+
+/// DSCP Expedited Forwarding class, used for voice traffic
+pub const DSCP_EF: u8 = 46;
+
+/// Default receive buffer size for the VBAN receiver
+pub const DEFAULT_RECV_BUFFER_SIZE: usize = 256 * 1024;
+
+fn setsockopt<T>(
+    socket: &UdpSocket,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: &T,
+) -> std::io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            value as *const T as *const libc::c_void,
+            std::mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn getsockopt_int(
+    socket: &UdpSocket,
+    level: libc::c_int,
+    name: libc::c_int,
+) -> std::io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret == 0 {
+        Ok(value)
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn is_ipv6(socket: &UdpSocket) -> Result<bool> {
+    Ok(socket.local_addr()?.is_ipv6())
+}
+
+/// Mark outgoing packets with a DSCP value (0-63), via IP_TOS or IPV6_TCLASS
+pub fn set_dscp(socket: &UdpSocket, dscp: u8) -> Result<()> {
+    if dscp > 63 {
+        bail!("Invalid DSCP value {} (must be 0-63)", dscp);
+    }
+    let tos = (dscp as libc::c_int) << 2;
+    let (level, name) = if is_ipv6(socket)? {
+        (libc::IPPROTO_IPV6, libc::IPV6_TCLASS)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_TOS)
+    };
+    setsockopt(socket, level, name, &tos).map_err(|e| anyhow!("Failed to set DSCP {}: {}", dscp, e))
+}
+
+/// Read back the DSCP value currently set on a socket
+pub fn dscp(socket: &UdpSocket) -> Result<u8> {
+    let (level, name) = if is_ipv6(socket)? {
+        (libc::IPPROTO_IPV6, libc::IPV6_TCLASS)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_TOS)
+    };
+    let tos =
+        getsockopt_int(socket, level, name).map_err(|e| anyhow!("Failed to read DSCP: {}", e))?;
+    Ok(((tos >> 2) & 0x3F) as u8)
+}
+
+/// Request a receive buffer size (SO_RCVBUF). The kernel may clamp it to
+/// net.core.rmem_max and reports back double the requested value.
+pub fn set_recv_buffer_size(socket: &UdpSocket, bytes: usize) -> Result<()> {
+    let size = libc::c_int::try_from(bytes)
+        .map_err(|_| anyhow!("Receive buffer size too large: {}", bytes))?;
+    setsockopt(socket, libc::SOL_SOCKET, libc::SO_RCVBUF, &size)
+        .map_err(|e| anyhow!("Failed to set receive buffer to {} bytes: {}", bytes, e))
+}
+
+/// Read back the effective receive buffer size (SO_RCVBUF)
+pub fn recv_buffer_size(socket: &UdpSocket) -> Result<usize> {
+    let size = getsockopt_int(socket, libc::SOL_SOCKET, libc::SO_RCVBUF)
+        .map_err(|e| anyhow!("Failed to read receive buffer size: {}", e))?;
+    Ok(size.max(0) as usize)
+}
+
+/// Pin a socket to a network interface (SO_BINDTODEVICE). Requires CAP_NET_ADMIN.
+pub fn bind_to_device(socket: &UdpSocket, interface: &str) -> Result<()> {
+    if interface.is_empty() || interface.len() >= libc::IFNAMSIZ || interface.contains('\0') {
+        bail!("Invalid interface name: {:?}", interface);
+    }
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            interface.as_ptr() as *const libc::c_void,
+            interface.len() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        return Ok(());
+    }
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EPERM) => Err(anyhow!(
+            "Cannot bind to interface {}: CAP_NET_ADMIN is required (run as root or grant the capability)",
+            interface
+        )),
+        Some(libc::ENODEV) => Err(anyhow!("Cannot bind to interface {}: no such device", interface)),
+        _ => Err(anyhow!("Cannot bind to interface {}: {}", interface, err)),
+    }
+}
+
+/// Read back the interface a socket is bound to, if any
+pub fn bound_device(socket: &UdpSocket) -> Result<Option<String>> {
+    let mut buf = [0u8; libc::IFNAMSIZ];
+    let mut len = buf.len() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            buf.as_mut_ptr() as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(anyhow!(
+            "Failed to read bound interface: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    let end = buf[..len as usize]
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(len as usize);
+    if end == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(String::from_utf8_lossy(&buf[..end]).into_owned()))
+    }
+}
+
+/// Bind a UDP socket with SO_REUSEADDR and SO_REUSEPORT set, so it can
+/// share a well-known port (e.g. mDNS 5353) with other responders
+pub fn bind_udp_reuse(addr: SocketAddrV4) -> Result<UdpSocket> {
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(anyhow!(
+            "Failed to create UDP socket: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    // Owns the fd from here on, closing it on every error path
+    let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+    let one: libc::c_int = 1;
+    setsockopt(&socket, libc::SOL_SOCKET, libc::SO_REUSEADDR, &one)
+        .map_err(|e| anyhow!("Failed to set SO_REUSEADDR: {}", e))?;
+    setsockopt(&socket, libc::SOL_SOCKET, libc::SO_REUSEPORT, &one)
+        .map_err(|e| anyhow!("Failed to set SO_REUSEPORT: {}", e))?;
+
+    let sockaddr = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: addr.port().to_be(),
+        sin_addr: libc::in_addr {
+            s_addr: u32::from(*addr.ip()).to_be(),
+        },
+        sin_zero: [0; 8],
+    };
+    let ret = unsafe {
+        libc::bind(
+            fd,
+            &sockaddr as *const libc::sockaddr_in as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(anyhow!(
+            "Failed to bind {}: {}",
+            addr,
+            std::io::Error::last_os_error()
+        ));
+    }
+    Ok(socket)
+}
+
+// =============================================================================
+// Addresses
+// =============================================================================
+
+/// `host` without the brackets around an IPv6 literal ("[::1]" -> "::1")
+pub fn unbracket(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+/// "host:port", bracketing IPv6 literals so the result parses back
+pub fn host_port(host: &str, port: u16) -> String {
+    let host = unbracket(host);
+    if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Resolve a hostname, IPv4 literal or (optionally bracketed) IPv6 literal
+pub fn resolve(host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = (unbracket(host), port).to_socket_addrs()?.collect();
+    if addrs.is_empty() {
+        return Err(std::io::Error::new(
+            ErrorKind::NotFound,
+            format!("no addresses for {}", host),
+        ));
+    }
+    Ok(addrs)
+}
+
+/// Wildcard address of `peer`'s family, for a socket that sends to it
+pub fn unspecified_for(peer: &SocketAddr) -> SocketAddr {
+    match peer {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    }
+}
+
+/// `peer` as a socket bound to `local` can address it: IPv4 peers of a
+/// dual-stack IPv6 socket become IPv4-mapped addresses
+pub fn peer_for(local: &SocketAddr, peer: SocketAddr) -> SocketAddr {
+    match (local, peer) {
+        (SocketAddr::V6(_), SocketAddr::V4(v4)) => {
+            SocketAddr::new(IpAddr::V6(v4.ip().to_ipv6_mapped()), v4.port())
+        }
+        _ => peer,
+    }
+}
+
+/// Bind a UDP socket to `addr`. The IPv6 wildcard is bound dual-stack
+/// (IPV6_V6ONLY off) so IPv4 peers are heard too, as IPv4-mapped
+/// addresses; on hosts without IPv6 it falls back to the IPv4 wildcard on
+/// the same port.
+pub fn bind_udp(addr: SocketAddr) -> std::io::Result<UdpSocket> {
+    match addr {
+        SocketAddr::V6(v6) if v6.ip().is_unspecified() => {
+            match bind_dual_stack(v6, libc::SOCK_DGRAM) {
+                Err(e) if no_ipv6(&e) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, v6.port())),
+                result => result.map(UdpSocket::from),
+            }
+        }
+        _ => UdpSocket::bind(addr),
+    }
+}
+
+/// Listen for TCP connections on `addr`, dual-stack for the IPv6 wildcard
+/// like [`bind_udp`]
+pub fn bind_tcp(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    match addr {
+        SocketAddr::V6(v6) if v6.ip().is_unspecified() => {
+            match bind_dual_stack(v6, libc::SOCK_STREAM) {
+                Err(e) if no_ipv6(&e) => TcpListener::bind((Ipv4Addr::UNSPECIFIED, v6.port())),
+                result => result.map(TcpListener::from),
+            }
+        }
+        _ => TcpListener::bind(addr),
+    }
+}
+
+/// The host has no IPv6 to bind
+fn no_ipv6(error: &std::io::Error) -> bool {
+    matches!(
+        error.raw_os_error(),
+        Some(libc::EAFNOSUPPORT) | Some(libc::EADDRNOTAVAIL)
+    )
+}
+
+/// An IPv6 socket of `kind` bound to `addr` with IPV6_V6ONLY off; stream
+/// sockets also listen, with SO_REUSEADDR as std sets it
+fn bind_dual_stack(addr: SocketAddrV6, kind: libc::c_int) -> std::io::Result<OwnedFd> {
+    let fd = unsafe { libc::socket(libc::AF_INET6, kind | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // Owns the fd from here on, closing it on every error path
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+    let set = |level, name, value: libc::c_int| {
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                &value as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    };
+    set(libc::IPPROTO_IPV6, libc::IPV6_V6ONLY, 0)?;
+    if kind == libc::SOCK_STREAM {
+        set(libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)?;
+    }
+
+    let sockaddr = libc::sockaddr_in6 {
+        sin6_family: libc::AF_INET6 as libc::sa_family_t,
+        sin6_port: addr.port().to_be(),
+        sin6_flowinfo: addr.flowinfo(),
+        sin6_addr: libc::in6_addr {
+            s6_addr: addr.ip().octets(),
+        },
+        sin6_scope_id: addr.scope_id(),
+    };
+    let ret = unsafe {
+        libc::bind(
+            fd,
+            &sockaddr as *const libc::sockaddr_in6 as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if kind == libc::SOCK_STREAM && unsafe { libc::listen(fd, 128) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loopback_socket() -> UdpSocket {
+        UdpSocket::bind("127.0.0.1:0").unwrap()
+    }
+
+    #[test]
+    fn test_dscp_roundtrip() {
+        let socket = loopback_socket();
+        set_dscp(&socket, DSCP_EF).unwrap();
+        assert_eq!(dscp(&socket).unwrap(), DSCP_EF);
+        set_dscp(&socket, 0).unwrap();
+        assert_eq!(dscp(&socket).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_dscp_ipv6_roundtrip() {
+        // Skip on hosts without IPv6 loopback
+        let Ok(socket) = UdpSocket::bind("[::1]:0") else {
+            return;
+        };
+        set_dscp(&socket, DSCP_EF).unwrap();
+        assert_eq!(dscp(&socket).unwrap(), DSCP_EF);
+    }
+
+    #[test]
+    fn test_dscp_rejects_out_of_range() {
+        let socket = loopback_socket();
+        let err = set_dscp(&socket, 64).unwrap_err();
+        assert!(err.to_string().contains("Invalid DSCP"));
+    }
+
+    #[test]
+    fn test_recv_buffer_size_grows() {
+        let socket = loopback_socket();
+        set_recv_buffer_size(&socket, 64 * 1024).unwrap();
+        // Linux doubles the requested size for bookkeeping overhead
+        assert!(recv_buffer_size(&socket).unwrap() >= 64 * 1024);
+    }
+
+    #[test]
+    fn test_bind_to_device_invalid_name() {
+        let socket = loopback_socket();
+        assert!(bind_to_device(&socket, "").is_err());
+        assert!(bind_to_device(&socket, "an-interface-name-too-long").is_err());
+    }
+
+    #[test]
+    fn test_bind_to_device_loopback() {
+        let socket = loopback_socket();
+        match bind_to_device(&socket, "lo") {
+            Ok(()) => assert_eq!(bound_device(&socket).unwrap().as_deref(), Some("lo")),
+            // Unprivileged test runs must get the capability hint
+            Err(e) => assert!(e.to_string().contains("CAP_NET_ADMIN")),
+        }
+    }
+
+    #[test]
+    fn test_bind_udp_reuse_shares_port() {
+        let first = bind_udp_reuse("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = match first.local_addr().unwrap() {
+            std::net::SocketAddr::V4(addr) => addr,
+            other => panic!("unexpected address {}", other),
+        };
+        let second = bind_udp_reuse(addr).unwrap();
+        assert_eq!(second.local_addr().unwrap().port(), addr.port());
+    }
+
+    #[test]
+    fn test_resolve_ipv4_literal() {
+        let addrs = resolve("192.168.1.10", 6980).unwrap();
+        assert_eq!(addrs, vec!["192.168.1.10:6980".parse().unwrap()]);
+    }
+
+    #[test]
+    fn test_resolve_ipv6_literals() {
+        let expected: SocketAddr = "[fd00::10]:6980".parse().unwrap();
+        assert_eq!(resolve("fd00::10", 6980).unwrap(), vec![expected]);
+        assert_eq!(resolve("[fd00::10]", 6980).unwrap(), vec![expected]);
+        assert!(resolve("[fd00::10", 6980).is_err());
+    }
+
+    #[test]
+    fn test_resolve_hostname() {
+        let addrs = resolve("localhost", 6980).unwrap();
+        assert!(addrs
+            .iter()
+            .all(|a| a.ip().is_loopback() && a.port() == 6980));
+    }
+
+    #[test]
+    fn test_host_port_brackets_ipv6() {
+        assert_eq!(host_port("strih.lan", 6980), "strih.lan:6980");
+        assert_eq!(host_port("10.0.0.1", 6980), "10.0.0.1:6980");
+        assert_eq!(host_port("::1", 6980), "[::1]:6980");
+        assert_eq!(host_port("[::1]", 6980), "[::1]:6980");
+        assert!(host_port("fe80::1", 1).parse::<SocketAddr>().is_ok());
+    }
+
+    #[test]
+    fn test_unspecified_and_peer_for() {
+        let v4: SocketAddr = "10.0.0.1:6980".parse().unwrap();
+        let v6: SocketAddr = "[fd00::1]:6980".parse().unwrap();
+        assert_eq!(unspecified_for(&v4), "0.0.0.0:0".parse().unwrap());
+        assert_eq!(unspecified_for(&v6), "[::]:0".parse().unwrap());
+        assert_eq!(peer_for(&unspecified_for(&v4), v4), v4);
+        assert_eq!(
+            peer_for(&unspecified_for(&v6), v4),
+            "[::ffff:10.0.0.1]:6980".parse().unwrap()
+        );
+        assert_eq!(peer_for(&unspecified_for(&v6), v6), v6);
+    }
+
+    #[test]
+    fn test_bind_udp_dual_stack_hears_ipv4() {
+        let socket = bind_udp("[::]:0".parse().unwrap()).unwrap();
+        socket
+            .set_read_timeout(Some(std::time::Duration::from_secs(1)))
+            .unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.send_to(b"VBAN", ("127.0.0.1", port)).unwrap();
+        let mut buf = [0u8; 16];
+        let (len, from) = socket.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"VBAN");
+        assert_eq!(from.ip().to_canonical(), client.local_addr().unwrap().ip());
+    }
+
+    #[test]
+    fn test_bind_tcp_dual_stack_accepts_ipv4() {
+        let listener = bind_tcp("[::]:0".parse().unwrap()).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = std::net::TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let (_, from) = listener.accept().unwrap();
+        assert_eq!(from.ip().to_canonical(), client.local_addr().unwrap().ip());
+    }
+
+    #[test]
+    fn test_bound_device_default_none() {
+        let socket = loopback_socket();
+        assert_eq!(bound_device(&socket).unwrap(), None);
+    }
+}
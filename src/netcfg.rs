@@ -0,0 +1,600 @@
+//! Network provisioning from the `[network]` config section
+//!
+//! `camera-box netcfg apply` configures a headless box without a separate
+//! network manager: for `mode = "static"` it brings the interface up, assigns
+//! the address, replaces the default route over rtnetlink and writes
+//! resolv.conf; `mode = "dhcp"` leaves addressing to the system, optionally
+//! running a DHCP client. The kernel hostname is set from `hostname` in both
+//! modes.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+use crate::config::NetworkConfig;
+
+/// resolv.conf written for static configurations
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+
+/// Capability bit of CAP_NET_ADMIN in /proc/self/status CapEff
+const CAP_NET_ADMIN: u32 = 12;
+
+const NLMSG_HDRLEN: usize = 16;
+
+/// An IPv4 address with prefix length, e.g. "192.168.1.100/24"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    pub addr: Ipv4Addr,
+    pub prefix: u8,
+}
+
+impl Cidr {
+    pub fn parse(text: &str) -> Result<Self> {
+        let (addr, prefix) = text.trim().split_once('/').ok_or_else(|| {
+            anyhow!(
+                "Invalid address {:?}, expected e.g. \"192.168.1.100/24\"",
+                text
+            )
+        })?;
+        let addr = addr
+            .parse::<Ipv4Addr>()
+            .map_err(|_| anyhow!("Invalid address {:?}: bad IPv4 address {:?}", text, addr))?;
+        let prefix = prefix
+            .parse::<u8>()
+            .ok()
+            .filter(|prefix| *prefix <= 32)
+            .ok_or_else(|| anyhow!("Invalid address {:?}: prefix must be 0-32", text))?;
+        Ok(Self { addr, prefix })
+    }
+
+    pub fn netmask(&self) -> Ipv4Addr {
+        let bits = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+        Ipv4Addr::from(bits)
+    }
+
+    pub fn broadcast(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.addr) | !u32::from(self.netmask()))
+    }
+
+    /// True if `addr` is on this subnet
+    pub fn contains(&self, addr: Ipv4Addr) -> bool {
+        let mask = u32::from(self.netmask());
+        u32::from(addr) & mask == u32::from(self.addr) & mask
+    }
+}
+
+/// How the interface gets its address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkPlan {
+    Dhcp {
+        /// DHCP client command run with the interface name, if any
+        client: Option<String>,
+    },
+    Static {
+        address: Cidr,
+        gateway: Option<Ipv4Addr>,
+        dns: Vec<IpAddr>,
+    },
+}
+
+impl NetworkPlan {
+    /// Check a `[network]` section and turn it into the steps to apply
+    pub fn from_config(config: &NetworkConfig) -> Result<Self> {
+        match config.mode.to_ascii_lowercase().as_str() {
+            "dhcp" => Ok(NetworkPlan::Dhcp {
+                client: config.dhcp_client.clone().filter(|c| !c.trim().is_empty()),
+            }),
+            "static" => {
+                let address = config
+                    .address
+                    .as_deref()
+                    .ok_or_else(|| anyhow!("Static network mode requires an address"))?;
+                let address = Cidr::parse(address)?;
+                let gateway = config
+                    .gateway
+                    .as_deref()
+                    .map(|gateway| {
+                        gateway
+                            .trim()
+                            .parse::<Ipv4Addr>()
+                            .map_err(|_| anyhow!("Invalid gateway {:?}", gateway))
+                    })
+                    .transpose()?;
+                if let Some(gateway) = gateway {
+                    if !address.contains(gateway) {
+                        bail!(
+                            "Gateway {} is not on {}/{}",
+                            gateway,
+                            address.addr,
+                            address.prefix
+                        );
+                    }
+                }
+                let dns = config
+                    .dns
+                    .as_deref()
+                    .unwrap_or("")
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|server| !server.is_empty())
+                    .map(|server| {
+                        server
+                            .parse::<IpAddr>()
+                            .map_err(|_| anyhow!("Invalid DNS server {:?}", server))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(NetworkPlan::Static {
+                    address,
+                    gateway,
+                    dns,
+                })
+            }
+            other => Err(anyhow!(
+                "Unsupported network mode: {}. Supported: dhcp, static",
+                other
+            )),
+        }
+    }
+}
+
+/// Configure the network and hostname. Fails up front without CAP_NET_ADMIN.
+pub fn apply(config: &NetworkConfig, hostname: &str) -> Result<()> {
+    let plan = NetworkPlan::from_config(config)?;
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    if !has_capability(&status, CAP_NET_ADMIN) {
+        bail!("Configuring the network needs CAP_NET_ADMIN: run as root or grant the capability");
+    }
+
+    set_hostname(hostname)?;
+    tracing::info!("Hostname set to {}", hostname);
+
+    match plan {
+        NetworkPlan::Dhcp { client: None } => {
+            tracing::info!(
+                "{}: DHCP, leaving addressing to the system",
+                config.interface
+            );
+        }
+        NetworkPlan::Dhcp {
+            client: Some(client),
+        } => {
+            tracing::info!("{}: running {}", config.interface, client);
+            let status = std::process::Command::new(&client)
+                .arg(&config.interface)
+                .status()
+                .with_context(|| format!("Failed to run DHCP client {}", client))?;
+            if !status.success() {
+                bail!("DHCP client {} failed: {}", client, status);
+            }
+        }
+        NetworkPlan::Static {
+            address,
+            gateway,
+            dns,
+        } => {
+            let index = interface_index(&config.interface)?;
+            let netlink = Netlink::open()?;
+            netlink
+                .request(&link_up_message(1, index))
+                .with_context(|| format!("Failed to bring up {}", config.interface))?;
+            netlink
+                .request(&new_address_message(2, index, address))
+                .with_context(|| format!("Failed to assign {}/{}", address.addr, address.prefix))?;
+            tracing::info!("{}: {}/{}", config.interface, address.addr, address.prefix);
+            if let Some(gateway) = gateway {
+                netlink
+                    .request(&default_route_message(3, index, gateway))
+                    .with_context(|| format!("Failed to set default route via {}", gateway))?;
+                tracing::info!("Default route via {}", gateway);
+            }
+            if !dns.is_empty() {
+                write_resolv_conf(Path::new(RESOLV_CONF), &dns)?;
+                tracing::info!("DNS: {:?}", dns);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether the CapEff line of a /proc/<pid>/status text has `capability` set
+pub fn has_capability(status: &str, capability: u32) -> bool {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+        .is_some_and(|caps| caps & (1 << capability) != 0)
+}
+
+fn set_hostname(hostname: &str) -> Result<()> {
+    if hostname.is_empty() || hostname.len() > 64 {
+        bail!("Invalid hostname {:?}", hostname);
+    }
+    let ret =
+        unsafe { libc::sethostname(hostname.as_ptr() as *const libc::c_char, hostname.len()) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error()).context("Failed to set hostname");
+    }
+    Ok(())
+}
+
+fn interface_index(name: &str) -> Result<u32> {
+    let c_name = std::ffi::CString::new(name).map_err(|_| anyhow!("Invalid interface name"))?;
+    let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+    if index == 0 {
+        bail!("Network interface {} not found", name);
+    }
+    Ok(index)
+}
+
+/// resolv.conf content for the given name servers
+pub fn resolv_conf(dns: &[IpAddr]) -> String {
+    let mut content = String::from("# Written by camera-box netcfg\n");
+    for server in dns {
+        content.push_str(&format!("nameserver {}\n", server));
+    }
+    content
+}
+
+fn write_resolv_conf(path: &Path, dns: &[IpAddr]) -> Result<()> {
+    let tmp = path.with_extension("conf.tmp");
+    std::fs::write(&tmp, resolv_conf(dns))
+        .with_context(|| format!("Failed to write {}", tmp.display()))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to replace {}", path.display()))
+}
+
+// ============================================================================
+// rtnetlink messages
+// ============================================================================
+
+/// A netlink message under construction: header, fixed payload, attributes
+struct Message {
+    buf: Vec<u8>,
+}
+
+impl Message {
+    fn new(kind: u16, flags: u16, seq: u32) -> Self {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(&0u32.to_ne_bytes()); // length, set by finish()
+        buf.extend_from_slice(&kind.to_ne_bytes());
+        buf.extend_from_slice(
+            &(flags | libc::NLM_F_REQUEST as u16 | libc::NLM_F_ACK as u16).to_ne_bytes(),
+        );
+        buf.extend_from_slice(&seq.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes()); // port id, filled in by the kernel
+        Self { buf }
+    }
+
+    fn payload(mut self, bytes: &[u8]) -> Self {
+        self.buf.extend_from_slice(bytes);
+        self.align();
+        self
+    }
+
+    fn attr(mut self, kind: u16, value: &[u8]) -> Self {
+        self.buf
+            .extend_from_slice(&((4 + value.len()) as u16).to_ne_bytes());
+        self.buf.extend_from_slice(&kind.to_ne_bytes());
+        self.buf.extend_from_slice(value);
+        self.align();
+        self
+    }
+
+    fn align(&mut self) {
+        while !self.buf.len().is_multiple_of(4) {
+            self.buf.push(0);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let len = self.buf.len() as u32;
+        self.buf[..4].copy_from_slice(&len.to_ne_bytes());
+        self.buf
+    }
+}
+
+/// RTM_NEWLINK setting IFF_UP on interface `index`
+fn link_up_message(seq: u32, index: u32) -> Vec<u8> {
+    // struct ifinfomsg: family, pad, type, index, flags, change
+    let mut info = [0u8; 16];
+    info[4..8].copy_from_slice(&(index as i32).to_ne_bytes());
+    info[8..12].copy_from_slice(&(libc::IFF_UP as u32).to_ne_bytes());
+    info[12..16].copy_from_slice(&(libc::IFF_UP as u32).to_ne_bytes());
+    Message::new(libc::RTM_NEWLINK, 0, seq)
+        .payload(&info)
+        .finish()
+}
+
+/// RTM_NEWADDR assigning `address` to interface `index`
+fn new_address_message(seq: u32, index: u32, address: Cidr) -> Vec<u8> {
+    // struct ifaddrmsg: family, prefixlen, flags, scope, index
+    let mut info = [0u8; 8];
+    info[0] = libc::AF_INET as u8;
+    info[1] = address.prefix;
+    info[3] = libc::RT_SCOPE_UNIVERSE;
+    info[4..8].copy_from_slice(&index.to_ne_bytes());
+    let flags = (libc::NLM_F_CREATE | libc::NLM_F_REPLACE) as u16;
+    Message::new(libc::RTM_NEWADDR, flags, seq)
+        .payload(&info)
+        .attr(libc::IFA_LOCAL, &address.addr.octets())
+        .attr(libc::IFA_ADDRESS, &address.addr.octets())
+        .attr(libc::IFA_BROADCAST, &address.broadcast().octets())
+        .finish()
+}
+
+/// RTM_NEWROUTE replacing the IPv4 default route with one via `gateway`
+fn default_route_message(seq: u32, index: u32, gateway: Ipv4Addr) -> Vec<u8> {
+    // struct rtmsg: family, dst_len, src_len, tos, table, protocol, scope, type, flags
+    let mut info = [0u8; 12];
+    info[0] = libc::AF_INET as u8;
+    info[4] = libc::RT_TABLE_MAIN;
+    info[5] = libc::RTPROT_STATIC;
+    info[6] = libc::RT_SCOPE_UNIVERSE;
+    info[7] = libc::RTN_UNICAST;
+    let flags = (libc::NLM_F_CREATE | libc::NLM_F_REPLACE) as u16;
+    Message::new(libc::RTM_NEWROUTE, flags, seq)
+        .payload(&info)
+        .attr(libc::RTA_GATEWAY, &gateway.octets())
+        .attr(libc::RTA_OIF, &index.to_ne_bytes())
+        .finish()
+}
+
+/// Result of the NLMSG_ERROR acknowledgement for `seq` in a receive buffer;
+/// None if the buffer holds no acknowledgement for it
+fn parse_ack(buf: &[u8], seq: u32) -> Option<io::Result<()>> {
+    let mut at = 0;
+    while at + NLMSG_HDRLEN <= buf.len() {
+        let word = |offset: usize| u32::from_ne_bytes(buf[offset..offset + 4].try_into().unwrap());
+        let len = word(at) as usize;
+        let kind = u16::from_ne_bytes([buf[at + 4], buf[at + 5]]);
+        if len < NLMSG_HDRLEN || at + len > buf.len() {
+            return None;
+        }
+        if kind == libc::NLMSG_ERROR as u16 && word(at + 8) == seq && len >= NLMSG_HDRLEN + 4 {
+            let errno = word(at + NLMSG_HDRLEN) as i32;
+            return Some(if errno == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::from_raw_os_error(-errno))
+            });
+        }
+        at += (len + 3) & !3;
+    }
+    None
+}
+
+/// NETLINK_ROUTE socket sending one request at a time
+struct Netlink {
+    fd: OwnedFd,
+}
+
+impl Netlink {
+    fn open() -> Result<Self> {
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error()).context("Failed to open netlink socket");
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error()).context("Failed to bind netlink socket");
+        }
+        Ok(Self { fd })
+    }
+
+    /// Send `message` and wait for its acknowledgement
+    fn request(&self, message: &[u8]) -> Result<()> {
+        let seq = u32::from_ne_bytes(message[8..12].try_into().unwrap());
+        let sent = unsafe {
+            libc::send(
+                self.fd.as_raw_fd(),
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+                0,
+            )
+        };
+        if sent < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let mut buf = [0u8; 4096];
+        loop {
+            let received = unsafe {
+                libc::recv(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if received < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(err.into());
+            }
+            if let Some(result) = parse_ack(&buf[..received as usize], seq) {
+                return Ok(result?);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(mode: &str) -> NetworkConfig {
+        NetworkConfig {
+            mode: mode.to_string(),
+            interface: "eth0".to_string(),
+            address: None,
+            gateway: None,
+            dns: None,
+            dhcp_client: None,
+        }
+    }
+
+    #[test]
+    fn test_cidr_parse() {
+        let cidr = Cidr::parse("192.168.1.100/24").unwrap();
+        assert_eq!(cidr.addr, Ipv4Addr::new(192, 168, 1, 100));
+        assert_eq!(cidr.prefix, 24);
+        assert_eq!(cidr.netmask(), Ipv4Addr::new(255, 255, 255, 0));
+        assert_eq!(cidr.broadcast(), Ipv4Addr::new(192, 168, 1, 255));
+        assert!(cidr.contains(Ipv4Addr::new(192, 168, 1, 1)));
+        assert!(!cidr.contains(Ipv4Addr::new(192, 168, 2, 1)));
+
+        let host = Cidr::parse("10.0.0.5/32").unwrap();
+        assert_eq!(host.netmask(), Ipv4Addr::new(255, 255, 255, 255));
+        assert_eq!(
+            Cidr::parse("10.0.0.5/0").unwrap().netmask(),
+            Ipv4Addr::UNSPECIFIED
+        );
+
+        assert!(Cidr::parse("192.168.1.100").is_err());
+        assert!(Cidr::parse("192.168.1.300/24").is_err());
+        assert!(Cidr::parse("192.168.1.1/33").is_err());
+        assert!(Cidr::parse("fe80::1/64").is_err());
+    }
+
+    #[test]
+    fn test_plan_from_config() {
+        assert_eq!(
+            NetworkPlan::from_config(&network("dhcp")).unwrap(),
+            NetworkPlan::Dhcp { client: None }
+        );
+
+        let mut config = network("static");
+        assert!(NetworkPlan::from_config(&config).is_err());
+        config.address = Some("192.168.1.100/24".to_string());
+        config.gateway = Some("192.168.1.1".to_string());
+        config.dns = Some("192.168.1.1, 1.1.1.1".to_string());
+        match NetworkPlan::from_config(&config).unwrap() {
+            NetworkPlan::Static { gateway, dns, .. } => {
+                assert_eq!(gateway, Some(Ipv4Addr::new(192, 168, 1, 1)));
+                assert_eq!(dns.len(), 2);
+            }
+            other => panic!("unexpected plan: {:?}", other),
+        }
+
+        config.gateway = Some("10.0.0.1".to_string());
+        assert!(NetworkPlan::from_config(&config).is_err());
+        assert!(NetworkPlan::from_config(&network("ppp")).is_err());
+    }
+
+    #[test]
+    fn test_new_address_message_layout() {
+        let cidr = Cidr::parse("192.168.1.100/24").unwrap();
+        let msg = new_address_message(7, 3, cidr);
+        // header + ifaddrmsg + three 8-byte IPv4 attributes
+        assert_eq!(msg.len(), 16 + 8 + 3 * 8);
+        assert_eq!(
+            u32::from_ne_bytes(msg[0..4].try_into().unwrap()),
+            msg.len() as u32
+        );
+        assert_eq!(u16::from_ne_bytes([msg[4], msg[5]]), libc::RTM_NEWADDR);
+        let flags = u16::from_ne_bytes([msg[6], msg[7]]);
+        let expected =
+            libc::NLM_F_REQUEST | libc::NLM_F_ACK | libc::NLM_F_CREATE | libc::NLM_F_REPLACE;
+        assert_eq!(flags, expected as u16);
+        assert_eq!(u32::from_ne_bytes(msg[8..12].try_into().unwrap()), 7);
+        // ifaddrmsg
+        assert_eq!(msg[16], libc::AF_INET as u8);
+        assert_eq!(msg[17], 24);
+        assert_eq!(u32::from_ne_bytes(msg[20..24].try_into().unwrap()), 3);
+        // IFA_LOCAL then IFA_ADDRESS then IFA_BROADCAST
+        assert_eq!(u16::from_ne_bytes([msg[24], msg[25]]), 8);
+        assert_eq!(u16::from_ne_bytes([msg[26], msg[27]]), libc::IFA_LOCAL);
+        assert_eq!(&msg[28..32], &[192, 168, 1, 100]);
+        assert_eq!(u16::from_ne_bytes([msg[34], msg[35]]), libc::IFA_ADDRESS);
+        assert_eq!(u16::from_ne_bytes([msg[42], msg[43]]), libc::IFA_BROADCAST);
+        assert_eq!(&msg[44..48], &[192, 168, 1, 255]);
+    }
+
+    #[test]
+    fn test_route_and_link_message_layout() {
+        let msg = default_route_message(9, 2, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(msg.len(), 16 + 12 + 8 + 8);
+        assert_eq!(u16::from_ne_bytes([msg[4], msg[5]]), libc::RTM_NEWROUTE);
+        // Default route: dst_len 0 in the main table
+        assert_eq!(msg[17], 0);
+        assert_eq!(msg[20], libc::RT_TABLE_MAIN);
+        assert_eq!(u16::from_ne_bytes([msg[30], msg[31]]), libc::RTA_GATEWAY);
+        assert_eq!(&msg[32..36], &[10, 0, 0, 1]);
+        assert_eq!(u16::from_ne_bytes([msg[38], msg[39]]), libc::RTA_OIF);
+        assert_eq!(u32::from_ne_bytes(msg[40..44].try_into().unwrap()), 2);
+
+        let msg = link_up_message(1, 4);
+        assert_eq!(msg.len(), 32);
+        assert_eq!(u16::from_ne_bytes([msg[4], msg[5]]), libc::RTM_NEWLINK);
+        assert_eq!(i32::from_ne_bytes(msg[20..24].try_into().unwrap()), 4);
+        assert_eq!(
+            u32::from_ne_bytes(msg[24..28].try_into().unwrap()),
+            libc::IFF_UP as u32
+        );
+    }
+
+    #[test]
+    fn test_parse_ack() {
+        let ack = |seq: u32, errno: i32| {
+            let mut buf = Vec::new();
+            buf.extend_from_slice(&36u32.to_ne_bytes());
+            buf.extend_from_slice(&(libc::NLMSG_ERROR as u16).to_ne_bytes());
+            buf.extend_from_slice(&0u16.to_ne_bytes());
+            buf.extend_from_slice(&seq.to_ne_bytes());
+            buf.extend_from_slice(&0u32.to_ne_bytes());
+            buf.extend_from_slice(&errno.to_ne_bytes());
+            buf.extend_from_slice(&[0; 16]); // echoed request header
+            buf
+        };
+        assert!(parse_ack(&ack(5, 0), 5).unwrap().is_ok());
+        let err = parse_ack(&ack(5, -libc::EEXIST), 5).unwrap().unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EEXIST));
+        assert!(parse_ack(&ack(4, 0), 5).is_none());
+        assert!(parse_ack(&ack(5, 0)[..10], 5).is_none());
+    }
+
+    #[test]
+    fn test_has_capability() {
+        let status = "Name:\tcamera-box\nCapPrm:\t0000000000000000\nCapEff:\t0000000000001000\n";
+        assert!(has_capability(status, CAP_NET_ADMIN));
+        assert!(!has_capability(status, 21));
+        assert!(!has_capability(
+            "CapEff:\t0000000000000000\n",
+            CAP_NET_ADMIN
+        ));
+        assert!(!has_capability("", CAP_NET_ADMIN));
+    }
+
+    #[test]
+    fn test_resolv_conf() {
+        let dns: Vec<IpAddr> = vec![
+            "192.168.1.1".parse().unwrap(),
+            "2001:db8::1".parse().unwrap(),
+        ];
+        assert_eq!(
+            resolv_conf(&dns),
+            "# Written by camera-box netcfg\nnameserver 192.168.1.1\nnameserver 2001:db8::1\n"
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("resolv.conf");
+        write_resolv_conf(&path, &dns).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), resolv_conf(&dns));
+    }
+}
@@ -0,0 +1,227 @@
+//! Local address change detection
+//!
+//! NDI advertises the sender's addresses when it is created, so after a
+//! DHCP renew onto a different subnet receivers keep trying the old one.
+//! The capture loop polls the local address set and re-announces the
+//! sender when it changes.
+
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::ffi::CStr;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+
+/// How often the local addresses are compared
+pub const ADDRESS_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An address assigned to a local interface
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LocalAddress {
+    pub interface: String,
+    pub addr: IpAddr,
+}
+
+impl fmt::Display for LocalAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.interface, self.addr)
+    }
+}
+
+pub type AddressSet = BTreeSet<LocalAddress>;
+
+/// IPv4 and IPv6 addresses of all non-loopback interfaces (getifaddrs)
+pub fn local_addresses() -> Result<AddressSet> {
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let mut addresses = AddressSet::new();
+    let mut entry = ifaddrs;
+    while !entry.is_null() {
+        let ifa = unsafe { &*entry };
+        entry = ifa.ifa_next;
+        if ifa.ifa_addr.is_null() || ifa.ifa_flags & libc::IFF_LOOPBACK as u32 != 0 {
+            continue;
+        }
+        let addr = match unsafe { (*ifa.ifa_addr).sa_family } as i32 {
+            libc::AF_INET => {
+                let sin = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)))
+            }
+            libc::AF_INET6 => {
+                let sin6 = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr))
+            }
+            _ => continue,
+        };
+        let interface = unsafe { CStr::from_ptr(ifa.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+        addresses.insert(LocalAddress { interface, addr });
+    }
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Ok(addresses)
+}
+
+/// Addresses gained and lost between two address sets
+#[derive(Debug, Default, PartialEq)]
+pub struct AddressChange {
+    pub added: Vec<LocalAddress>,
+    pub removed: Vec<LocalAddress>,
+}
+
+impl AddressChange {
+    pub fn between(old: &AddressSet, new: &AddressSet) -> Self {
+        Self {
+            added: new.difference(old).cloned().collect(),
+            removed: old.difference(new).cloned().collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl fmt::Display for AddressChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changes: Vec<String> = self
+            .added
+            .iter()
+            .map(|a| format!("+{}", a))
+            .chain(self.removed.iter().map(|a| format!("-{}", a)))
+            .collect();
+        write!(f, "{}", changes.join(", "))
+    }
+}
+
+/// Polls the address set at a fixed interval and reports changes. The first
+/// successful read only sets the baseline; failed reads are skipped.
+#[derive(Debug)]
+pub struct AddressMonitor {
+    known: Option<AddressSet>,
+    interval: Duration,
+    next_poll: Instant,
+}
+
+impl AddressMonitor {
+    pub fn new(now: Instant, interval: Duration) -> Self {
+        Self {
+            known: None,
+            interval,
+            next_poll: now,
+        }
+    }
+
+    /// Read the addresses with `read` if the interval elapsed
+    pub fn poll(
+        &mut self,
+        now: Instant,
+        read: impl FnOnce() -> Result<AddressSet>,
+    ) -> Option<AddressChange> {
+        if now < self.next_poll {
+            return None;
+        }
+        self.next_poll = now + self.interval;
+        let current = match read() {
+            Ok(current) => current,
+            Err(e) => {
+                tracing::debug!("Failed to read local addresses: {:#}", e);
+                return None;
+            }
+        };
+        let change = self
+            .known
+            .as_ref()
+            .map(|known| AddressChange::between(known, &current))
+            .filter(|change| !change.is_empty());
+        self.known = Some(current);
+        change
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(addrs: &[(&str, &str)]) -> AddressSet {
+        addrs
+            .iter()
+            .map(|(interface, addr)| LocalAddress {
+                interface: interface.to_string(),
+                addr: addr.parse().unwrap(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_address_change_between() {
+        let old = set(&[("eth0", "192.168.1.20"), ("eth0", "fe80::1")]);
+        let new = set(&[("eth0", "10.0.0.20"), ("eth0", "fe80::1")]);
+        let change = AddressChange::between(&old, &new);
+        assert_eq!(
+            change.added,
+            set(&[("eth0", "10.0.0.20")])
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(change.removed.len(), 1);
+        assert_eq!(change.to_string(), "+eth0 10.0.0.20, -eth0 192.168.1.20");
+        assert!(AddressChange::between(&old, &old.clone()).is_empty());
+
+        // The same address moving to another interface is a change too
+        let moved = set(&[("wlan0", "192.168.1.20"), ("eth0", "fe80::1")]);
+        assert!(!AddressChange::between(&old, &moved).is_empty());
+    }
+
+    #[test]
+    fn test_monitor_polls_at_interval() {
+        let t0 = Instant::now();
+        let mut monitor = AddressMonitor::new(t0, Duration::from_secs(10));
+        let a = set(&[("eth0", "192.168.1.20")]);
+        let b = set(&[("eth0", "10.0.0.20")]);
+
+        // First read is the baseline
+        assert_eq!(monitor.poll(t0, || Ok(a.clone())), None);
+        // Not due yet: the reader isn't called
+        assert_eq!(
+            monitor.poll(t0 + Duration::from_secs(5), || panic!("polled early")),
+            None
+        );
+        assert_eq!(
+            monitor.poll(t0 + Duration::from_secs(10), || Ok(a.clone())),
+            None
+        );
+
+        let change = monitor.poll(t0 + Duration::from_secs(20), || Ok(b.clone()));
+        assert_eq!(change.unwrap().added.len(), 1);
+        assert_eq!(
+            monitor.poll(t0 + Duration::from_secs(30), || Ok(b.clone())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_monitor_skips_failed_reads() {
+        let t0 = Instant::now();
+        let mut monitor = AddressMonitor::new(t0, Duration::from_secs(10));
+        let a = set(&[("eth0", "192.168.1.20")]);
+        assert_eq!(monitor.poll(t0, || Ok(a.clone())), None);
+        assert_eq!(
+            monitor.poll(t0 + Duration::from_secs(10), || anyhow::bail!("EMFILE")),
+            None
+        );
+        // Compared against the last good read
+        let change = monitor.poll(t0 + Duration::from_secs(20), || Ok(AddressSet::new()));
+        assert_eq!(change.unwrap().removed, a.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_local_addresses_skip_loopback() {
+        let addresses = local_addresses().unwrap();
+        assert!(addresses.iter().all(|a| !a.addr.is_loopback()));
+    }
+}
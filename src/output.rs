@@ -0,0 +1,698 @@
+//! Secondary outputs beside NDI
+//!
+//! NDI stays the primary output; every `[[output]]` entry adds another
+//! [`VideoSender`], e.g. for receivers that can't do NDI. [`OutputFanout`]
+//! runs as a frame processor after the overlays: at each output's frame
+//! rate it copies the frame as sent once, shares the copy between the
+//! outputs due for a frame and hands it over with `try_send` on a bounded
+//! channel. An output still busy with the previous frame misses this one,
+//! which is counted, so a slow output never holds up the main NDI send.
+//! Each output sends its frames on its own thread.
+//!
+//! The `mjpeg_http` output scales the frames down, encodes them as JPEG and
+//! serves them as a `multipart/x-mixed-replace` stream, which browsers and
+//! VLC play as a live preview. The `ndi` output sends them, scaled down, on
+//! a further NDI sender named `<ndi_name><name_suffix>`.
+
+use anyhow::{anyhow, Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use v4l::FourCC;
+
+use crate::capture::{FrameInfo, FrameRate};
+use crate::config::OutputConfig;
+use crate::display::scale_uyvy_nearest;
+use crate::jpeg::JpegEncoder;
+use crate::ndi::NdiSender;
+use crate::ndi_supervisor::VideoSender;
+use crate::net;
+use crate::processing::{FrameAction, FrameProcessor};
+use crate::stats::{Component, StatsRegistry};
+use crate::threads;
+
+/// Frames queued per sink besides the one it works on; more are dropped
+pub const QUEUE_FRAMES: usize = 1;
+
+/// Multipart boundary of the MJPEG stream
+pub const BOUNDARY: &str = "camera-box-frame";
+
+/// Viewers served at once by one `mjpeg_http` output
+pub const MAX_CLIENTS: usize = 8;
+
+/// How often the accept loop checks for shutdown
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+
+/// A viewer that can't take a frame for this long is disconnected
+const CLIENT_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Secondary output kinds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    /// JPEG preview over HTTP
+    MjpegHttp,
+    /// A further NDI sender
+    Ndi,
+}
+
+impl OutputKind {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "mjpeg_http" => Ok(OutputKind::MjpegHttp),
+            "ndi" => Ok(OutputKind::Ndi),
+            other => Err(anyhow!(
+                "Unsupported output type: {}. Supported: mjpeg_http, ndi",
+                other
+            )),
+        }
+    }
+}
+
+/// A frame as sent, shared by the sinks it is handed to
+#[derive(Debug)]
+pub struct Frame {
+    pub uyvy: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// An opened secondary output
+pub struct Output {
+    /// Name for logs and stats, unique among the outputs
+    pub name: String,
+    /// Run on the output's own thread. Send errors are logged and counted;
+    /// the output gets the next frame regardless.
+    pub sender: Box<dyn VideoSender + Send>,
+    /// Counters of this output, kept by the fan-out and the sender together
+    pub stats: Arc<OutputStats>,
+}
+
+/// Open the output `config` describes. `ndi_name` and `ndi_groups` are the
+/// main sender's; `ndi` outputs are named and grouped after them.
+pub fn open(config: &OutputConfig, ndi_name: &str, ndi_groups: Option<&str>) -> Result<Output> {
+    match OutputKind::from_name(&config.kind)? {
+        OutputKind::MjpegHttp => {
+            let sink = MjpegHttpSink::bind(config)?;
+            Ok(Output {
+                name: format!("mjpeg_{}", sink.local_addr().port()),
+                stats: sink.stats(),
+                sender: Box::new(sink),
+            })
+        }
+        OutputKind::Ndi => {
+            let name = format!("{}{}", ndi_name, config.name_suffix);
+            Ok(Output {
+                name: format!("ndi{}", config.name_suffix),
+                sender: Box::new(NdiOutput::open(&name, config, ndi_groups)?),
+                stats: Arc::default(),
+            })
+        }
+    }
+}
+
+// =============================================================================
+// Fan-out
+// =============================================================================
+
+/// Counters of one output
+#[derive(Debug, Default)]
+pub struct OutputStats {
+    /// Frames the sink consumed
+    pub frames: AtomicU64,
+    /// Frames due while the sink was still busy
+    pub dropped: AtomicU64,
+    /// Frames the sink failed on
+    pub errors: AtomicU64,
+    /// Viewers connected, for sinks that serve them
+    pub clients: AtomicU64,
+}
+
+impl OutputStats {
+    /// Report the counters under the output's name
+    pub fn register(self: &Arc<Self>, registry: &StatsRegistry, name: &str) {
+        let clients = Arc::clone(self);
+        registry.register(
+            Component::new(name)
+                .counter("frames", self, |s| &s.frames)
+                .counter("dropped", self, |s| &s.dropped)
+                .counter("errors", self, |s| &s.errors)
+                .gauge("clients", move || {
+                    clients.clients.load(Ordering::Relaxed) as f64
+                }),
+        );
+    }
+}
+
+struct Port {
+    frames: SyncSender<Arc<Frame>>,
+    interval: Duration,
+    next_due: Option<Instant>,
+    stats: Arc<OutputStats>,
+}
+
+/// Frame processor handing frames to the secondary outputs; never changes
+/// or drops a frame. Dropping it stops the outputs.
+#[derive(Default)]
+pub struct OutputFanout {
+    ports: Vec<Port>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl OutputFanout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ports.is_empty()
+    }
+
+    /// Start `output` on its own thread, handing it at most `fps` frames a
+    /// second
+    pub fn add(&mut self, output: Output, fps: u32) -> Arc<OutputStats> {
+        let (frames_tx, frames_rx) = sync_channel(QUEUE_FRAMES);
+        let stats = Arc::clone(&output.stats);
+        tracing::info!("Output {} at {} fps", output.name, fps);
+        self.workers.push(threads::spawn(threads::OUTPUT, move || {
+            run_output(output, frames_rx)
+        }));
+        self.ports.push(Port {
+            frames: frames_tx,
+            interval: Duration::from_secs(1) / fps.max(1),
+            next_due: None,
+            stats: Arc::clone(&stats),
+        });
+        stats
+    }
+
+    fn process_at(&mut self, uyvy: &[u8], width: u32, height: u32, now: Instant) {
+        let mut frame: Option<Arc<Frame>> = None;
+        for port in &mut self.ports {
+            if port.next_due.is_some_and(|due| now < due) {
+                continue;
+            }
+            port.next_due = Some(match port.next_due {
+                // Keep the cadence unless the frames came late
+                Some(due) if now - due < port.interval => due + port.interval,
+                _ => now + port.interval,
+            });
+            let frame = frame.get_or_insert_with(|| {
+                Arc::new(Frame {
+                    uyvy: uyvy.to_vec(),
+                    width,
+                    height,
+                })
+            });
+            match port.frames.try_send(Arc::clone(frame)) {
+                Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+                Err(TrySendError::Full(_)) => {
+                    port.stats.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
+
+impl FrameProcessor for OutputFanout {
+    fn name(&self) -> &str {
+        "outputs"
+    }
+
+    fn process(&mut self, uyvy: &mut [u8], width: u32, height: u32) -> FrameAction {
+        self.process_at(uyvy, width, height, Instant::now());
+        FrameAction::Send
+    }
+}
+
+impl Drop for OutputFanout {
+    fn drop(&mut self) {
+        // Closing the channels ends the sink threads
+        self.ports.clear();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn run_output(mut output: Output, frames: Receiver<Arc<Frame>>) {
+    let stats = &output.stats;
+    let mut failing = false;
+    for frame in frames {
+        let info = FrameInfo::new(
+            frame.width,
+            frame.height,
+            FourCC::new(b"UYVY"),
+            frame.width * 2,
+        );
+        match output.sender.send_frame(&frame.uyvy, info) {
+            Ok(()) => {
+                stats.frames.fetch_add(1, Ordering::Relaxed);
+                if failing {
+                    tracing::info!("Output {} recovered", output.name);
+                    failing = false;
+                }
+            }
+            Err(e) => {
+                stats.errors.fetch_add(1, Ordering::Relaxed);
+                if !failing {
+                    tracing::warn!("Output {} failed: {:#}", output.name, e);
+                    failing = true;
+                }
+            }
+        }
+        if let Some(connections) = output.sender.connections() {
+            stats.clients.store(connections as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+// =============================================================================
+// NDI
+// =============================================================================
+
+/// Sends frames, scaled to the output size, on a further NDI sender
+pub struct NdiOutput {
+    sender: NdiSender,
+    width: u32,
+    height: u32,
+}
+
+impl NdiOutput {
+    /// Create the NDI sender `name`, advertised at the output's frame rate
+    pub fn open(name: &str, config: &OutputConfig, groups: Option<&str>) -> Result<Self> {
+        let rate = FrameRate {
+            numerator: config.fps,
+            denominator: 1,
+        };
+        let sender = NdiSender::with_groups(name, rate, groups)?;
+        tracing::info!(
+            "NDI output '{}' at {}x{}",
+            name,
+            config.width,
+            config.height
+        );
+        Ok(Self {
+            sender,
+            width: config.width & !1,
+            height: config.height,
+        })
+    }
+}
+
+impl VideoSender for NdiOutput {
+    fn send_frame(&mut self, data: &[u8], info: FrameInfo) -> Result<()> {
+        if (info.width, info.height) == (self.width, self.height) {
+            return Ok(self.sender.send_frame_zero_copy(data, info)?);
+        }
+        let scaled = scale_uyvy_nearest(data, info.width, info.height, self.width, self.height);
+        let info = FrameInfo::new(
+            self.width,
+            self.height,
+            FourCC::new(b"UYVY"),
+            self.width * 2,
+        );
+        Ok(self.sender.send_frame_zero_copy(&scaled, info)?)
+    }
+
+    fn recreate(&mut self) -> Result<()> {
+        Ok(self.sender.recreate()?)
+    }
+
+    fn connections(&self) -> Option<u32> {
+        Some(self.sender.connections())
+    }
+}
+
+// =============================================================================
+// MJPEG over HTTP
+// =============================================================================
+
+/// Response header of the stream
+pub fn stream_header(boundary: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: multipart/x-mixed-replace; boundary={}\r\n\
+         Cache-Control: no-cache, no-store\r\n\
+         Pragma: no-cache\r\n\
+         Connection: close\r\n\
+         \r\n",
+        boundary
+    )
+}
+
+/// One JPEG of the stream with its part header
+pub fn multipart_part(boundary: &str, jpeg: &[u8]) -> Vec<u8> {
+    let header = format!(
+        "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        boundary,
+        jpeg.len()
+    );
+    let mut part = Vec::with_capacity(header.len() + jpeg.len() + 2);
+    part.extend_from_slice(header.as_bytes());
+    part.extend_from_slice(jpeg);
+    part.extend_from_slice(b"\r\n");
+    part
+}
+
+/// The latest JPEG, handed from the encoder to every viewer
+#[derive(Default)]
+struct Latest {
+    state: Mutex<LatestState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct LatestState {
+    sequence: u64,
+    jpeg: Option<Arc<Vec<u8>>>,
+    closed: bool,
+}
+
+impl Latest {
+    fn publish(&self, jpeg: Vec<u8>) {
+        let mut state = self.state.lock().unwrap();
+        state.sequence += 1;
+        state.jpeg = Some(Arc::new(jpeg));
+        self.changed.notify_all();
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.changed.notify_all();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    /// The first JPEG after `sequence`, None once closed and sent
+    fn next_after(&self, sequence: u64) -> Option<(u64, Arc<Vec<u8>>)> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.sequence > sequence {
+                if let Some(jpeg) = &state.jpeg {
+                    return Some((state.sequence, Arc::clone(jpeg)));
+                }
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+}
+
+/// Scales, encodes and serves frames as an MJPEG stream on `port`
+pub struct MjpegHttpSink {
+    width: u32,
+    height: u32,
+    encoder: JpegEncoder,
+    latest: Arc<Latest>,
+    stats: Arc<OutputStats>,
+    server: Option<JoinHandle<()>>,
+    local_addr: SocketAddr,
+}
+
+impl MjpegHttpSink {
+    /// Listen on `config.port` on every address
+    pub fn bind(config: &OutputConfig) -> Result<Self> {
+        let listener = net::bind_tcp(SocketAddr::from((Ipv6Addr::UNSPECIFIED, config.port)))
+            .with_context(|| format!("Failed to listen on port {}", config.port))?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        let latest = Arc::new(Latest::default());
+        let stats = Arc::new(OutputStats::default());
+        let (server_latest, server_stats) = (Arc::clone(&latest), Arc::clone(&stats));
+        let server = threads::spawn(threads::MJPEG_HTTP, move || {
+            serve(listener, server_latest, server_stats)
+        });
+        tracing::info!(
+            "MJPEG preview at http://{}/ ({}x{})",
+            local_addr,
+            config.width,
+            config.height
+        );
+        Ok(Self {
+            width: config.width,
+            height: config.height,
+            encoder: JpegEncoder::new(config.quality),
+            latest,
+            stats,
+            server: Some(server),
+            local_addr,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Counters of this output; the server counts the viewers
+    pub fn stats(&self) -> Arc<OutputStats> {
+        Arc::clone(&self.stats)
+    }
+}
+
+impl VideoSender for MjpegHttpSink {
+    /// Encode a tightly packed UYVY frame for the viewers
+    fn send_frame(&mut self, data: &[u8], info: FrameInfo) -> Result<()> {
+        // Nobody watching: skip the encode
+        if self.stats.clients.load(Ordering::Relaxed) == 0 {
+            return Ok(());
+        }
+        let jpeg = if (info.width, info.height) == (self.width, self.height) {
+            self.encoder.encode(data, info.width, info.height)
+        } else {
+            let scaled = scale_uyvy_nearest(data, info.width, info.height, self.width, self.height);
+            self.encoder.encode(&scaled, self.width & !1, self.height)
+        };
+        self.latest.publish(jpeg);
+        Ok(())
+    }
+
+    /// Nothing to recreate: the server keeps running
+    fn recreate(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for MjpegHttpSink {
+    fn drop(&mut self) {
+        self.latest.close();
+        if let Some(server) = self.server.take() {
+            let _ = server.join();
+        }
+    }
+}
+
+fn serve(listener: TcpListener, latest: Arc<Latest>, stats: Arc<OutputStats>) {
+    let mut clients: Vec<JoinHandle<()>> = Vec::new();
+    while !latest.is_closed() {
+        clients.retain(|client| !client.is_finished());
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                if clients.len() >= MAX_CLIENTS {
+                    tracing::warn!("MJPEG viewer {} refused: {} connected", peer, MAX_CLIENTS);
+                    let _ = reject(stream);
+                    continue;
+                }
+                let (latest, stats) = (Arc::clone(&latest), Arc::clone(&stats));
+                clients.push(threads::spawn(threads::MJPEG_CLIENT, move || {
+                    stats.clients.fetch_add(1, Ordering::Relaxed);
+                    match stream_to(stream, &latest) {
+                        Ok(()) => tracing::debug!("MJPEG viewer {} left", peer),
+                        Err(e) => tracing::debug!("MJPEG viewer {} left: {}", peer, e),
+                    }
+                    stats.clients.fetch_sub(1, Ordering::Relaxed);
+                }));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                std::thread::sleep(ACCEPT_POLL);
+            }
+            Err(e) => {
+                tracing::warn!("MJPEG accept failed: {}", e);
+                std::thread::sleep(ACCEPT_POLL);
+            }
+        }
+    }
+    for client in clients {
+        let _ = client.join();
+    }
+}
+
+fn reject(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\n\r\n")
+}
+
+/// Read the request, then send every new JPEG until the viewer goes away
+/// or the output stops
+fn stream_to(stream: TcpStream, latest: &Latest) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_WRITE_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Headers don't matter; read up to the blank line
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim_end().is_empty() {
+        line.clear();
+    }
+    let mut stream = &stream;
+    if !request.starts_with("GET ") {
+        return stream.write_all(
+            b"HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\nConnection: close\r\n\r\n",
+        );
+    }
+    stream.write_all(stream_header(BOUNDARY).as_bytes())?;
+    let mut sequence = 0;
+    while let Some((next, jpeg)) = latest.next_after(sequence) {
+        sequence = next;
+        stream.write_all(&multipart_part(BOUNDARY, &jpeg))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_multipart_framing() {
+        let header = stream_header("b");
+        assert!(header.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(header.contains("Content-Type: multipart/x-mixed-replace; boundary=b\r\n"));
+        assert!(header.ends_with("\r\n\r\n"));
+
+        let part = multipart_part("b", &[0xFF, 0xD8, 0xFF, 0xD9]);
+        let expected =
+            b"--b\r\nContent-Type: image/jpeg\r\nContent-Length: 4\r\n\r\n\xFF\xD8\xFF\xD9\r\n";
+        assert_eq!(part, expected.to_vec());
+    }
+
+    /// Sender that reports each frame it starts on and waits for a go-ahead
+    struct GatedSink {
+        started: SyncSender<u32>,
+        proceed: Receiver<()>,
+    }
+
+    fn gated(started: SyncSender<u32>, proceed: Receiver<()>) -> Output {
+        Output {
+            name: "gated".to_string(),
+            sender: Box::new(GatedSink { started, proceed }),
+            stats: Arc::default(),
+        }
+    }
+
+    impl VideoSender for GatedSink {
+        fn send_frame(&mut self, _data: &[u8], info: FrameInfo) -> Result<()> {
+            let _ = self.started.send(info.width);
+            let _ = self.proceed.recv();
+            Ok(())
+        }
+
+        fn recreate(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_fanout_drops_while_busy() {
+        let (started_tx, started) = sync_channel(8);
+        let (proceed, proceed_rx) = sync_channel(8);
+        let mut fanout = OutputFanout::new();
+        let stats = fanout.add(gated(started_tx, proceed_rx), 10);
+        let t0 = Instant::now();
+        let step = Duration::from_millis(100);
+        let frame = [0u8; 16];
+
+        // The sink takes frame 1 and blocks on it
+        fanout.process_at(&frame, 1, 1, t0);
+        assert_eq!(started.recv().unwrap(), 1);
+        // Frame 2 waits in the queue, frame 3 finds it full
+        fanout.process_at(&frame, 2, 1, t0 + step);
+        fanout.process_at(&frame, 3, 1, t0 + step * 2);
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 1);
+
+        for _ in 0..2 {
+            proceed.send(()).unwrap();
+        }
+        assert_eq!(started.recv().unwrap(), 2);
+        drop(fanout);
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 2);
+        assert!(started.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_fanout_rate_limit() {
+        let (started_tx, started) = sync_channel(64);
+        let (proceed, proceed_rx) = sync_channel(64);
+        for _ in 0..64 {
+            proceed.send(()).unwrap();
+        }
+        let mut fanout = OutputFanout::new();
+        let stats = fanout.add(gated(started_tx, proceed_rx), 5);
+        // One second of 50 fps: every tenth frame is due
+        let t0 = Instant::now();
+        for index in 0..50 {
+            fanout.process_at(&[0; 4], index, 1, t0 + Duration::from_millis(20) * index);
+            if index % 10 == 0 {
+                assert_eq!(started.recv().unwrap(), index);
+            }
+        }
+        let mut frame = [0u8; 4];
+        assert_eq!(fanout.process(&mut frame, 2, 1), FrameAction::Send);
+        drop(fanout);
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 5);
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_mjpeg_http_serves_frames() {
+        let config = OutputConfig {
+            port: 0,
+            width: 32,
+            height: 16,
+            ..OutputConfig::default()
+        };
+        let mut sink = MjpegHttpSink::bind(&config).unwrap();
+        let mut client = TcpStream::connect(("127.0.0.1", sink.local_addr().port())).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: box\r\n\r\n")
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while sink.stats.clients.load(Ordering::Relaxed) == 0 {
+            assert!(Instant::now() < deadline, "viewer never connected");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let frame = [128, 16, 128, 16].repeat(32 * 32);
+        let info = FrameInfo::new(64, 32, FourCC::new(b"UYVY"), 128);
+        sink.send_frame(&frame, info).unwrap();
+        drop(sink);
+
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        let header = stream_header(BOUNDARY);
+        assert!(response.starts_with(header.as_bytes()));
+        let part = &response[header.len()..];
+        let prefix = format!("--{}\r\nContent-Type: image/jpeg\r\n", BOUNDARY);
+        assert!(part.starts_with(prefix.as_bytes()));
+        let body = part
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|at| &part[at + 4..])
+            .unwrap();
+        assert_eq!(&body[..2], &[0xFF, 0xD8]);
+        assert!(body.ends_with(b"\xFF\xD9\r\n"));
+    }
+}
@@ -0,0 +1,216 @@
+//! NDI frame rate conformance
+//!
+//! Without `clock_video` the NDI stream runs at whatever rate the dongle
+//! delivers, e.g. 59.94 drifting towards 60.1, which upsets recorders that
+//! expect an exact rate. Software pacing keeps the synchronous send but maps
+//! every captured frame onto a fixed schedule at the configured rate: a frame
+//! landing on an already-filled slot is dropped, and a frame arriving after
+//! missed slots is sent again to fill them.
+
+use anyhow::{anyhow, Result};
+use std::time::Instant;
+
+use crate::capture::FrameRate;
+
+/// Most slots a single late frame fills; larger gaps resynchronise the schedule
+const MAX_COPIES: u64 = 3;
+
+/// How far (in periods) a frame may sit off its slot before it is dropped or
+/// repeated; the margin beyond half a period is the hysteresis
+const SLACK: f64 = 0.9;
+
+/// How the NDI sender paces output frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacingMode {
+    /// Send frames as they arrive
+    Off,
+    /// Let the NDI SDK clock video sends (`clock_video` create flag)
+    ClockVideo,
+    /// Drop or repeat frames to hit the exact frame rate
+    Software,
+}
+
+impl PacingMode {
+    /// Parse a mode name from configuration ("off", "clock_video", "software")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "off" => Ok(PacingMode::Off),
+            "clock_video" => Ok(PacingMode::ClockVideo),
+            "software" => Ok(PacingMode::Software),
+            other => Err(anyhow!(
+                "Unsupported pacing mode: {}. Supported: off, clock_video, software",
+                other
+            )),
+        }
+    }
+}
+
+/// What to do with a captured frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaceDecision {
+    /// The frame's slot was already filled
+    Drop,
+    /// Send the frame this many times (more than once fills missed slots)
+    Send(u32),
+}
+
+/// Monotonic output schedule at a fixed frame rate
+#[derive(Debug)]
+pub struct FramePacer {
+    /// Slot duration in nanoseconds (fractional rates need the precision)
+    period_ns: f64,
+    start: Option<Instant>,
+    /// Index of the next unfilled slot
+    next_slot: u64,
+}
+
+impl FramePacer {
+    pub fn new(rate: FrameRate) -> Self {
+        let fps = rate.numerator.max(1) as f64 / rate.denominator.max(1) as f64;
+        Self {
+            period_ns: 1e9 / fps,
+            start: None,
+            next_slot: 0,
+        }
+    }
+
+    /// Decide how to handle a frame captured at `now`. A frame only drops or
+    /// repeats once it is almost a whole period off schedule, so jitter near a
+    /// slot boundary can't alternate drops and repeats.
+    pub fn on_frame(&mut self, now: Instant) -> PaceDecision {
+        let Some(start) = self.start else {
+            return self.resync(now);
+        };
+        let elapsed = now.saturating_duration_since(start).as_nanos() as f64;
+        // Position relative to the next unfilled slot, in periods
+        let offset = elapsed / self.period_ns - self.next_slot as f64;
+        if offset < -SLACK {
+            return PaceDecision::Drop;
+        }
+        let missed = if offset > SLACK {
+            (offset - SLACK).floor() as u64 + 1
+        } else {
+            0
+        };
+        if missed + 1 > MAX_COPIES {
+            // A stall, not drift: start a fresh schedule instead of a burst
+            return self.resync(now);
+        }
+        self.next_slot += missed + 1;
+        PaceDecision::Send(missed as u32 + 1)
+    }
+
+    fn resync(&mut self, now: Instant) -> PaceDecision {
+        self.start = Some(now);
+        self.next_slot = 1;
+        PaceDecision::Send(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const NTSC_60: FrameRate = FrameRate {
+        numerator: 60000,
+        denominator: 1001,
+    };
+
+    /// Feed frames at `input_fps` with a deterministic +-`jitter_us` wobble
+    /// and return (frames sent, frames dropped, extra copies)
+    fn simulate(rate: FrameRate, input_fps: f64, jitter_us: i64, frames: u64) -> (u64, u64, u64) {
+        let mut pacer = FramePacer::new(rate);
+        let t0 = Instant::now();
+        let (mut sent, mut dropped, mut repeated) = (0, 0, 0);
+        for i in 0..frames {
+            let ideal_us = (i as f64 * 1e6 / input_fps) as i64;
+            // Pseudo-random jitter pattern in [-jitter, +jitter]
+            let wobble = ((i * 7919) % 201) as i64 - 100;
+            let at = ideal_us + wobble * jitter_us / 100;
+            let now = t0 + Duration::from_micros(at.max(0) as u64);
+            match pacer.on_frame(now) {
+                PaceDecision::Drop => dropped += 1,
+                PaceDecision::Send(n) => {
+                    sent += n as u64;
+                    repeated += n as u64 - 1;
+                }
+            }
+        }
+        (sent, dropped, repeated)
+    }
+
+    #[test]
+    fn test_mode_from_name() {
+        assert_eq!(PacingMode::from_name("off").unwrap(), PacingMode::Off);
+        assert_eq!(
+            PacingMode::from_name("clock_video").unwrap(),
+            PacingMode::ClockVideo
+        );
+        assert_eq!(
+            PacingMode::from_name("Software").unwrap(),
+            PacingMode::Software
+        );
+        assert!(PacingMode::from_name("vsync").is_err());
+    }
+
+    #[test]
+    fn test_matching_rate_with_jitter_passes_through() {
+        // 5ms of jitter on a 16.7ms period never changes the frame count
+        let (sent, dropped, repeated) = simulate(NTSC_60, 59.94, 5000, 6000);
+        assert_eq!((sent, dropped, repeated), (6000, 0, 0));
+    }
+
+    #[test]
+    fn test_fast_source_drops_to_target_rate() {
+        // 60.1 fps in for 100s against a 59.94 schedule: ~16 extra frames
+        let (sent, dropped, repeated) = simulate(NTSC_60, 60.1, 2000, 6010);
+        assert_eq!(repeated, 0);
+        assert!((14..=18).contains(&dropped), "dropped {}", dropped);
+        let expected = (6010.0 / 60.1 * 59.94) as u64;
+        assert!(sent.abs_diff(expected) <= 2, "sent {}", sent);
+    }
+
+    #[test]
+    fn test_slow_source_repeats_to_target_rate() {
+        let rate = FrameRate {
+            numerator: 60,
+            denominator: 1,
+        };
+        let (sent, dropped, repeated) = simulate(rate, 59.94, 2000, 5994);
+        assert_eq!(dropped, 0);
+        assert!((4..=8).contains(&repeated), "repeated {}", repeated);
+        assert!(sent.abs_diff(6000) <= 2, "sent {}", sent);
+    }
+
+    #[test]
+    fn test_stall_resynchronises() {
+        let rate = FrameRate {
+            numerator: 50,
+            denominator: 1,
+        };
+        let mut pacer = FramePacer::new(rate);
+        let t0 = Instant::now();
+        assert_eq!(pacer.on_frame(t0), PaceDecision::Send(1));
+        assert_eq!(
+            pacer.on_frame(t0 + Duration::from_millis(20)),
+            PaceDecision::Send(1)
+        );
+        // One missed slot is filled
+        assert_eq!(
+            pacer.on_frame(t0 + Duration::from_millis(60)),
+            PaceDecision::Send(2)
+        );
+        // A one-second gap restarts the schedule instead of a 50-frame burst
+        let t1 = t0 + Duration::from_secs(1);
+        assert_eq!(pacer.on_frame(t1), PaceDecision::Send(1));
+        assert_eq!(
+            pacer.on_frame(t1 + Duration::from_millis(1)),
+            PaceDecision::Drop
+        );
+        assert_eq!(
+            pacer.on_frame(t1 + Duration::from_millis(20)),
+            PaceDecision::Send(1)
+        );
+    }
+}
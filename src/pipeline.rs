@@ -0,0 +1,1669 @@
+//! Embeddable capture → NDI pipeline
+//!
+//! Everything between the capture device and the NDI sender: device
+//! (re)opening with saved camera controls, the stall watchdog, embedded
+//! audio, pacing and the supervised sender. The daemon's `main` is a thin
+//! wrapper around it; other applications can run one pipeline per device.
+//!
+//! ```no_run
+//! use camera_box::config::Config;
+//! use camera_box::pipeline::Pipeline;
+//!
+//! let mut pipeline = Pipeline::builder(Config::default()).device("/dev/video0").build()?;
+//! let events = pipeline.subscribe();
+//! pipeline.start()?;
+//! while let Ok(event) = events.recv() {
+//!     println!("{:?}", event);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use anyhow::{bail, Result};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use v4l::FourCC;
+
+use crate::aspect::AspectRatio;
+use crate::av_clock;
+use crate::camera_controls;
+use crate::capture::{FrameInfo, FrameRate, IoMode, VideoCapture, FRAME_TIMEOUT};
+use crate::capture_audio::{self, AudioCaptureSettings, AudioQueue};
+use crate::color_range::RangeMode;
+use crate::config::{Config, ProxyConfig};
+use crate::crop::CropRect;
+use crate::deinterlace::{DeinterlaceMode, FieldOrder};
+use crate::file_source::{self, FileSource};
+use crate::frame_budget::FrameBudget;
+use crate::image_source::{self, ImageSource};
+use crate::intercom::Tally;
+use crate::ndi::{self, NdiSender, SendErrorKind, SendFailure};
+use crate::ndi_conflict::{self, OnConflict};
+use crate::ndi_proxy::{self, NdiProxy};
+use crate::ndi_supervisor::{
+    BreakerState, BreakerTransition, FormatCounters, NdiSenderSettings, NdiSenderStats,
+    NdiSenderSupervisor, RestartPolicy, SendBreaker, VideoSender, BREAKER_RETRY, BREAKER_THRESHOLD,
+};
+use crate::netwatch::{self, AddressMonitor, ADDRESS_POLL_INTERVAL};
+use crate::output::{self, OutputFanout, OutputStats};
+use crate::pacing::{FramePacer, PacingMode};
+use crate::processing::{
+    FrameProcessor, ImageOverlay, ProcessorChain, SharedProcessors, TimestampBurnIn,
+};
+use crate::realtime;
+use crate::recorder::{Recorder, RecorderStats, SegmentWriter, SEGMENT_BYTES};
+use crate::replay::{ReplayHandle, ReplayRecorder};
+use crate::stats::{Component, StatsRegistry, Value};
+use crate::test_pattern::TestPattern;
+use crate::threads;
+use crate::timing::{FrameTiming, PipelineTiming};
+use crate::usb_reset::UsbReset;
+use crate::watchdog::CaptureWatchdog;
+
+/// How often the NDI connection count is sampled
+const CONNECTIONS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Delay between attempts to reopen a lost device
+const REOPEN_RETRY: Duration = Duration::from_secs(1);
+
+/// Failed reopens after which a reopen is preceded by a USB reset
+const USB_RESET_AFTER_FAILED_REOPENS: u32 = 2;
+
+/// How long the startup discovery pass looks for NDI name conflicts
+const CONFLICT_DISCOVERY: Duration = Duration::from_secs(2);
+
+// =============================================================================
+// Sources
+// =============================================================================
+
+/// Something that delivers video frames
+pub trait FrameSource: Send {
+    fn dimensions(&self) -> (u32, u32);
+    fn frame_rate(&self) -> FrameRate;
+    fn field_order(&self) -> FieldOrder;
+
+    /// Wait up to `timeout` for a frame and pass it to `callback`. Returns
+    /// false if no frame arrived in time.
+    fn process_frame(
+        &mut self,
+        timeout: Duration,
+        callback: &mut dyn FnMut(&[u8], FrameInfo),
+    ) -> Result<bool>;
+
+    /// Dequeue and requeue a frame that is already waiting, without
+    /// processing it. Returns false if none was ready. Sources that hold the
+    /// last frame's buffer (see [`FrameInfo::held`]) must keep holding it.
+    fn skip_frame(&mut self) -> Result<bool> {
+        self.process_frame(Duration::ZERO, &mut |_, _| {})
+    }
+}
+
+impl FrameSource for VideoCapture {
+    fn dimensions(&self) -> (u32, u32) {
+        VideoCapture::dimensions(self)
+    }
+
+    fn frame_rate(&self) -> FrameRate {
+        VideoCapture::frame_rate(self)
+    }
+
+    fn field_order(&self) -> FieldOrder {
+        VideoCapture::field_order(self)
+    }
+
+    fn process_frame(
+        &mut self,
+        timeout: Duration,
+        callback: &mut dyn FnMut(&[u8], FrameInfo),
+    ) -> Result<bool> {
+        Ok(self.process_frame_timeout(timeout, callback)?)
+    }
+
+    fn skip_frame(&mut self) -> Result<bool> {
+        Ok(VideoCapture::skip_frame(self)?)
+    }
+}
+
+impl FrameSource for TestPattern {
+    fn dimensions(&self) -> (u32, u32) {
+        TestPattern::dimensions(self)
+    }
+
+    fn frame_rate(&self) -> FrameRate {
+        TestPattern::frame_rate(self)
+    }
+
+    fn field_order(&self) -> FieldOrder {
+        FieldOrder::Progressive
+    }
+
+    fn process_frame(
+        &mut self,
+        timeout: Duration,
+        callback: &mut dyn FnMut(&[u8], FrameInfo),
+    ) -> Result<bool> {
+        Ok(self.process_frame_timeout(timeout, callback))
+    }
+}
+
+impl FrameSource for ImageSource {
+    fn dimensions(&self) -> (u32, u32) {
+        ImageSource::dimensions(self)
+    }
+
+    fn frame_rate(&self) -> FrameRate {
+        ImageSource::frame_rate(self)
+    }
+
+    fn field_order(&self) -> FieldOrder {
+        FieldOrder::Progressive
+    }
+
+    fn process_frame(
+        &mut self,
+        timeout: Duration,
+        callback: &mut dyn FnMut(&[u8], FrameInfo),
+    ) -> Result<bool> {
+        Ok(self.process_frame_timeout(timeout, callback))
+    }
+}
+
+impl FrameSource for FileSource {
+    fn dimensions(&self) -> (u32, u32) {
+        FileSource::dimensions(self)
+    }
+
+    fn frame_rate(&self) -> FrameRate {
+        FileSource::frame_rate(self)
+    }
+
+    fn field_order(&self) -> FieldOrder {
+        FieldOrder::Progressive
+    }
+
+    fn process_frame(
+        &mut self,
+        timeout: Duration,
+        callback: &mut dyn FnMut(&[u8], FrameInfo),
+    ) -> Result<bool> {
+        self.process_frame_timeout(timeout, callback)
+    }
+}
+
+type SourceFactory = Box<dyn FnMut() -> Result<Box<dyn FrameSource>> + Send>;
+type SenderFactory =
+    Box<dyn FnMut(&NdiSenderSettings) -> Result<Box<dyn VideoSender + Send>> + Send>;
+
+/// Open a V4L2 device with the configured crop and rate, apply the range
+/// override and I/O mode and reapply its saved camera controls
+fn open_device(
+    device_path: &str,
+    controls_file: &str,
+    range: RangeMode,
+    io_mode: IoMode,
+    crop: Option<CropRect>,
+    target_rate: FrameRate,
+    format_priority: &[FourCC],
+) -> Result<Box<dyn FrameSource>> {
+    let mut capture =
+        VideoCapture::open_with_crop(device_path, crop, target_rate, format_priority)?;
+    capture.set_range_mode(range);
+    capture.set_io_mode(io_mode)?;
+    if !controls_file.is_empty() {
+        if let Err(e) = camera_controls::restore_saved(device_path, controls_file) {
+            tracing::warn!("Failed to restore camera controls: {:#}", e);
+        }
+    }
+    Ok(Box::new(capture))
+}
+
+/// Create the sender, then look for another machine advertising its name.
+/// Returns the sender and, under [`OnConflict::Suffix`], the name to use
+/// instead. A failed discovery pass only skips the check.
+fn check_name_conflict(
+    settings: &NdiSenderSettings,
+    policy: OnConflict,
+) -> Result<(NdiSender, Option<String>)> {
+    let sender = settings.create()?;
+    let Some(hostname) = ndi_conflict::system_hostname() else {
+        tracing::warn!("NDI name check skipped: hostname unknown");
+        return Ok((sender, None));
+    };
+    let sources = match ndi::find_sources(CONFLICT_DISCOVERY, settings.groups.as_deref()) {
+        Ok(sources) => sources,
+        Err(e) => {
+            tracing::warn!("NDI name check skipped: {:#}", e);
+            return Ok((sender, None));
+        }
+    };
+    let renamed = ndi_conflict::resolve(policy, &sources, &settings.name, &hostname)?;
+    Ok((sender, renamed))
+}
+
+/// USB reset for `device_path` if the device is on USB and we may reset it
+fn checked_usb_reset(device_path: &str) -> Option<UsbReset> {
+    let usb_reset = UsbReset::new(device_path);
+    match usb_reset.check() {
+        Ok(usb_device) => {
+            tracing::info!("USB reset enabled for {}", usb_device.display());
+            Some(usb_reset)
+        }
+        Err(e) => {
+            tracing::warn!("capture.usb_reset disabled: {:#}", e);
+            None
+        }
+    }
+}
+
+// =============================================================================
+// Events and stats
+// =============================================================================
+
+/// Notifications for embedding applications
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineEvent {
+    /// The source's frame rate, sent on start and whenever a reopen changes it
+    FrameRateChanged(FrameRate),
+    /// The source stalled and is being reopened
+    DeviceLost,
+    /// The source was reopened after a loss
+    DeviceRecovered,
+    /// Number of NDI receivers connected changed
+    NdiConnections(u32),
+    /// A receiver put this source on program or preview, or took it off
+    TallyChanged(Tally),
+    /// The first frame since start was captured and handed to NDI
+    FirstFrame,
+}
+
+/// Pipeline counters (shared with status consumers)
+#[derive(Debug, Default)]
+pub struct PipelineStats {
+    pub frames_captured: AtomicU64,
+    pub capture_errors: AtomicU64,
+    pub device_losses: AtomicU64,
+    pub stall_recoveries: AtomicU64,
+    /// USB resets of the capture dongle (`capture.usb_reset`)
+    pub usb_resets: AtomicU64,
+    pub ndi_connections: AtomicU32,
+    /// Tally of the NDI sender, as `Tally as u8`
+    pub tally: AtomicU8,
+    /// Frames not sent while the send circuit breaker was open
+    pub sends_suppressed: AtomicU64,
+    /// Queued frames dequeued without conversion to catch up
+    pub frames_skipped: AtomicU64,
+    /// Frames whose conversion and send took longer than a frame interval
+    pub frames_over_budget: AtomicU64,
+    /// Embedded audio blocks sent after a video frame
+    pub audio_blocks: AtomicU64,
+    /// Capture time of the last audio block's end minus that of the video
+    /// frame it followed, in microseconds (valid once `audio_blocks` > 0)
+    pub av_offset_us: AtomicI64,
+    pub sender: Arc<NdiSenderStats>,
+}
+
+impl PipelineStats {
+    /// Report the capture counters as "capture" and the sender's as "ndi"
+    pub fn register(self: &Arc<Self>, registry: &StatsRegistry) {
+        let audio = Arc::clone(self);
+        registry.register(
+            Component::new("capture")
+                .counter("frames", self, |s| &s.frames_captured)
+                .counter("errors", self, |s| &s.capture_errors)
+                .counter("device_losses", self, |s| &s.device_losses)
+                .counter("stall_recoveries", self, |s| &s.stall_recoveries)
+                .counter("usb_resets", self, |s| &s.usb_resets)
+                .counter("skipped", self, |s| &s.frames_skipped)
+                .counter("over_budget", self, |s| &s.frames_over_budget)
+                .gauge("av_offset_ms", move || {
+                    if audio.audio_blocks.load(Ordering::Relaxed) > 0 {
+                        audio.av_offset_us.load(Ordering::Relaxed) as f64 / 1000.0
+                    } else {
+                        f64::NAN
+                    }
+                }),
+        );
+        let connections = Arc::clone(self);
+        let tally = Arc::clone(self);
+        let formats = Arc::clone(self);
+        registry.register(
+            Component::new("ndi")
+                .counter("frames", &self.sender, |s| &s.frames_sent)
+                .counter("bytes", &self.sender, |s| &s.bytes_sent)
+                .counter("send_errors", &self.sender, |s| &s.send_errors)
+                .counter("dropped", &self.sender, |s| &s.frames_dropped)
+                .counter("restarts", &self.sender, |s| &s.restarts)
+                .counter("restart_failures", &self.sender, |s| &s.restart_failures)
+                .counter("reannounces", &self.sender, |s| &s.reannounces)
+                .counter("pacing_dropped", &self.sender, |s| &s.pacing_dropped)
+                .counter("pacing_repeated", &self.sender, |s| &s.pacing_repeated)
+                .counter("zero_copy_torn", &self.sender, |s| &s.zero_copy_torn)
+                .counter("mjpeg_decode_failures", &self.sender, |s| {
+                    &s.mjpeg_decode_failures
+                })
+                .counter("sends_disconnected", &self.sender, |s| {
+                    &s.sends_disconnected
+                })
+                .family(move || format_metrics(&formats.sender.formats))
+                .gauge("connections", move || {
+                    connections.ndi_connections.load(Ordering::Relaxed) as f64
+                })
+                .gauge("tally", move || tally.tally.load(Ordering::Relaxed) as f64),
+        );
+    }
+}
+
+/// "format.UYVY.received" and its siblings for every format seen so far
+fn format_metrics(formats: &FormatCounters) -> Vec<(String, Value)> {
+    formats
+        .counts()
+        .into_iter()
+        .flat_map(|count| {
+            let name = count.name();
+            [
+                ("received", count.received),
+                ("converted", count.converted),
+                ("rejected", count.rejected),
+            ]
+            .map(|(metric, value)| (format!("format.{}.{}", name, metric), Value::Counter(value)))
+        })
+        .collect()
+}
+
+/// Fan-out of events to every subscriber; closed receivers are pruned
+#[derive(Default, Clone)]
+struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<PipelineEvent>>>>,
+}
+
+impl EventBus {
+    fn subscribe(&self) -> Receiver<PipelineEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.lock().push(tx);
+        rx
+    }
+
+    fn emit(&self, event: PipelineEvent) {
+        self.lock().retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Sender<PipelineEvent>>> {
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// =============================================================================
+// Builder
+// =============================================================================
+
+/// Configures a [`Pipeline`] from a [`Config`]
+pub struct PipelineBuilder {
+    config: Config,
+    device: Option<String>,
+    test_pattern: Option<(u32, u32, FrameRate)>,
+    realtime: bool,
+    stall_timeout: Option<Duration>,
+    source_factory: Option<SourceFactory>,
+    sender_factory: Option<SenderFactory>,
+    processors: Vec<Box<dyn FrameProcessor>>,
+}
+
+impl PipelineBuilder {
+    /// Capture from this device instead of `config.device`
+    pub fn device(mut self, device_path: impl Into<String>) -> Self {
+        self.device = Some(device_path.into());
+        self
+    }
+
+    /// Stream generated color bars instead of a capture device
+    pub fn test_pattern(mut self, width: u32, height: u32, frame_rate: FrameRate) -> Self {
+        self.test_pattern = Some((width, height, frame_rate));
+        self
+    }
+
+    /// Apply SCHED_FIFO, mlockall and CPU pinning to the capture thread
+    pub fn realtime(mut self, enabled: bool) -> Self {
+        self.realtime = enabled;
+        self
+    }
+
+    /// Override `capture.stall_timeout_secs` (zero disables the watchdog)
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = Some(timeout);
+        self
+    }
+
+    /// Open sources with `factory` instead of a device or the test pattern;
+    /// it is called again whenever the watchdog reopens the source
+    pub fn source_factory<F>(mut self, factory: F) -> Self
+    where
+        F: FnMut() -> Result<Box<dyn FrameSource>> + Send + 'static,
+    {
+        self.source_factory = Some(Box::new(factory));
+        self
+    }
+
+    /// Build senders with `factory` instead of creating NDI senders; this
+    /// also skips the NDI name conflict check
+    pub fn sender_factory<F>(mut self, factory: F) -> Self
+    where
+        F: FnMut(&NdiSenderSettings) -> Result<Box<dyn VideoSender + Send>> + Send + 'static,
+    {
+        self.sender_factory = Some(Box::new(factory));
+        self
+    }
+
+    /// Run `processor` on every frame before it is sent. Processors run in
+    /// the order added, after the overlay and burn-in from `[ndi]`.
+    pub fn processor(mut self, processor: impl FrameProcessor + 'static) -> Self {
+        self.processors.push(Box::new(processor));
+        self
+    }
+
+    /// Validate the configuration and resolve the capture device
+    pub fn build(self) -> Result<Pipeline> {
+        let config = self.config;
+        let deinterlace = DeinterlaceMode::from_name(&config.capture.deinterlace)?;
+        let pacing = PacingMode::from_name(&config.ndi.pacing)?;
+        let aspect = config.ndi.aspect.aspect()?;
+        let on_conflict = match self.sender_factory {
+            Some(_) => OnConflict::Off,
+            None => OnConflict::from_name(&config.ndi.on_conflict)?,
+        };
+        let range = RangeMode::from_name(&config.capture.range)?;
+        let io_mode = IoMode::from_name(&config.capture.io_mode)?;
+        let crop = config.capture.crop.as_ref().map(CropRect::from_config);
+        let target_rate = config.capture.frame_rate()?;
+        let format_priority = config.capture.formats()?;
+        let audio = config
+            .capture
+            .audio
+            .as_ref()
+            .map(AudioCaptureSettings::from_config)
+            .transpose()?;
+
+        let mut processors = ProcessorChain::new();
+        if let Some(overlay) = &config.ndi.overlay {
+            processors.push(Box::new(ImageOverlay::from_config(overlay)?));
+        }
+        if config.ndi.timestamp_burn_in {
+            processors.push(Box::new(TimestampBurnIn::new()));
+        }
+        for processor in self.processors {
+            processors.push(processor);
+        }
+        // After the overlays, so the recording matches what is sent
+        let recording = if config.record.enable {
+            let record = &config.record;
+            match SegmentWriter::new(&record.path, SEGMENT_BYTES, record.max_disk_mb * 1_000_000) {
+                Ok(writer) => {
+                    let recorder = Recorder::spawn(writer);
+                    let stats = recorder.stats();
+                    processors.push(Box::new(recorder));
+                    Some(stats)
+                }
+                Err(e) => {
+                    tracing::error!("Recording disabled: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let mut fanout = OutputFanout::new();
+        let mut outputs = Vec::new();
+        for settings in &config.outputs {
+            match output::open(settings, &config.ndi_name, config.ndi_groups.as_deref()) {
+                Ok(output) => {
+                    let name = output.name.clone();
+                    outputs.push((name, fanout.add(output, settings.fps)));
+                }
+                Err(e) => tracing::error!("Output {} disabled: {:#}", settings.kind, e),
+            }
+        }
+        if !fanout.is_empty() {
+            processors.push(Box::new(fanout));
+        }
+        // Started with the sender, once its name and frame rate are known
+        let proxy = config.ndi.proxy.enable.then(|| {
+            let stats = Arc::new(OutputStats::default());
+            outputs.push((ndi_proxy::STATS_NAME.to_string(), Arc::clone(&stats)));
+            (config.ndi.proxy.clone(), stats)
+        });
+        // Last, so the thumbnails show the frames as sent
+        let replay = config.capture.replay.as_ref().map(|replay| {
+            let (recorder, handle) = ReplayRecorder::new(replay.frames, &replay.dir);
+            processors.push(Box::new(recorder));
+            handle
+        });
+
+        let mut usb_reset = None;
+        let (device_path, source_factory): (Option<String>, SourceFactory) =
+            match (self.source_factory, self.test_pattern) {
+                (Some(factory), _) => (None, factory),
+                (None, Some((width, height, rate))) => (
+                    None,
+                    Box::new(move || Ok(Box::new(TestPattern::new(width, height, rate)) as _)),
+                ),
+                (None, None) => {
+                    let device_path = match self.device {
+                        Some(device) => device,
+                        None => config.device_path()?,
+                    };
+                    let path = device_path.clone();
+                    let image = image_source::image_path(&device_path);
+                    let clip = file_source::file_path(&device_path);
+                    let factory: SourceFactory = match (image, clip) {
+                        (Some(image), _) => {
+                            let image = image.to_string();
+                            let settings = config.capture.image.clone();
+                            Box::new(move || {
+                                Ok(Box::new(ImageSource::from_config(&image, &settings)?) as _)
+                            })
+                        }
+                        (None, Some(clip)) => {
+                            let clip = clip.to_string();
+                            let settings = config.capture.file.clone();
+                            Box::new(move || {
+                                Ok(Box::new(FileSource::from_config(&clip, &settings)?) as _)
+                            })
+                        }
+                        (None, None) => {
+                            if config.capture.usb_reset {
+                                usb_reset = checked_usb_reset(&device_path);
+                            }
+                            let controls_file = config.capture.controls_file.clone();
+                            Box::new(move || {
+                                open_device(
+                                    &path,
+                                    &controls_file,
+                                    range,
+                                    io_mode,
+                                    crop,
+                                    target_rate,
+                                    &format_priority,
+                                )
+                            })
+                        }
+                    };
+                    (Some(device_path), factory)
+                }
+            };
+
+        let sender_factory = self.sender_factory.unwrap_or_else(|| {
+            Box::new(|settings: &NdiSenderSettings| {
+                Ok(Box::new(settings.create()?) as Box<dyn VideoSender + Send>)
+            })
+        });
+
+        Ok(Pipeline {
+            setup: Some(Setup {
+                source_factory,
+                sender_factory,
+                audio,
+                usb_reset,
+            }),
+            device_path,
+            ndi_name: config.ndi_name.clone(),
+            ndi_groups: config.ndi_groups.clone(),
+            processors: Arc::new(Mutex::new(processors)),
+            replay,
+            timing: config
+                .capture
+                .timing
+                .then(|| Arc::new(PipelineTiming::new())),
+            recording,
+            outputs,
+            proxy,
+            output_rate: match (config.ndi.frame_rate_n, config.ndi.frame_rate_d) {
+                (Some(numerator), denominator) => Some(FrameRate {
+                    numerator,
+                    denominator: denominator.unwrap_or(1),
+                }),
+                _ => None,
+            },
+            deinterlace,
+            pacing,
+            zero_copy: config.ndi.zero_copy_uyvy,
+            aspect,
+            on_conflict,
+            restart_policy: RestartPolicy {
+                reload_library: config.ndi_reload_library,
+                ..Default::default()
+            },
+            stall_timeout: self
+                .stall_timeout
+                .unwrap_or(Duration::from_secs(config.capture.stall_timeout_secs)),
+            realtime: self.realtime,
+            running: Arc::new(AtomicBool::new(false)),
+            stats: Arc::new(PipelineStats::default()),
+            events: EventBus::default(),
+            threads: Vec::new(),
+        })
+    }
+}
+
+// =============================================================================
+// Pipeline
+// =============================================================================
+
+/// Parts consumed by `start()`
+struct Setup {
+    source_factory: SourceFactory,
+    sender_factory: SenderFactory,
+    audio: Option<AudioCaptureSettings>,
+    usb_reset: Option<UsbReset>,
+}
+
+/// A capture → NDI pipeline running on its own threads
+pub struct Pipeline {
+    setup: Option<Setup>,
+    device_path: Option<String>,
+    ndi_name: String,
+    ndi_groups: Option<String>,
+    processors: SharedProcessors,
+    replay: Option<ReplayHandle>,
+    timing: Option<Arc<PipelineTiming>>,
+    recording: Option<Arc<RecorderStats>>,
+    outputs: Vec<(String, Arc<OutputStats>)>,
+    proxy: Option<(ProxyConfig, Arc<OutputStats>)>,
+    output_rate: Option<FrameRate>,
+    deinterlace: DeinterlaceMode,
+    pacing: PacingMode,
+    zero_copy: bool,
+    aspect: AspectRatio,
+    on_conflict: OnConflict,
+    restart_policy: RestartPolicy,
+    stall_timeout: Duration,
+    realtime: bool,
+    running: Arc<AtomicBool>,
+    stats: Arc<PipelineStats>,
+    events: EventBus,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Pipeline {
+    pub fn builder(config: Config) -> PipelineBuilder {
+        PipelineBuilder {
+            config,
+            device: None,
+            test_pattern: None,
+            realtime: false,
+            stall_timeout: None,
+            source_factory: None,
+            sender_factory: None,
+            processors: Vec::new(),
+        }
+    }
+
+    /// Capture device path (None for the test pattern)
+    pub fn device_path(&self) -> Option<&str> {
+        self.device_path.as_deref()
+    }
+
+    /// Dumps the replay ring (None without `[capture.replay]`)
+    pub fn replay(&self) -> Option<ReplayHandle> {
+        self.replay.clone()
+    }
+
+    /// Per-stage timing of the capture loop (None unless `capture.timing`)
+    pub fn timing(&self) -> Option<Arc<PipelineTiming>> {
+        self.timing.clone()
+    }
+
+    /// Recorder counters (None unless `[record]` is enabled)
+    pub fn recording(&self) -> Option<Arc<RecorderStats>> {
+        self.recording.clone()
+    }
+
+    /// Name and counters of every `[[output]]` that started and of the
+    /// NDI proxy
+    pub fn outputs(&self) -> &[(String, Arc<OutputStats>)] {
+        &self.outputs
+    }
+
+    pub fn stats(&self) -> Arc<PipelineStats> {
+        Arc::clone(&self.stats)
+    }
+
+    /// Receive events from now on; subscribe before `start()` to see them all
+    pub fn subscribe(&self) -> Receiver<PipelineEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Open the source and the sender, then stream on a background thread.
+    /// A pipeline can only be started once.
+    pub fn start(&mut self) -> Result<()> {
+        let Some(mut setup) = self.setup.take() else {
+            bail!("Pipeline was already started");
+        };
+
+        let source = (setup.source_factory)()?;
+        let (width, height) = source.dimensions();
+        let frame_rate = self.output_rate.unwrap_or_else(|| source.frame_rate());
+        tracing::info!("Capturing at {}x{}", width, height);
+        if self.pacing != PacingMode::Off {
+            tracing::info!(
+                "NDI pacing: {:?} at {}/{}",
+                self.pacing,
+                frame_rate.numerator,
+                frame_rate.denominator
+            );
+        }
+
+        let mut settings = NdiSenderSettings {
+            name: self.ndi_name.clone(),
+            frame_rate,
+            groups: self.ndi_groups.clone(),
+            deinterlace: self.deinterlace,
+            field_order: source.field_order(),
+            pacing: self.pacing,
+            zero_copy: self.zero_copy,
+            aspect: self.aspect,
+            processors: Arc::clone(&self.processors),
+            frame_size: (width, height),
+        };
+        // The sender that passed the name check is the supervisor's first
+        let mut checked = None;
+        if self.on_conflict != OnConflict::Off {
+            let (sender, renamed) = check_name_conflict(&settings, self.on_conflict)?;
+            match renamed {
+                Some(name) => {
+                    drop(sender);
+                    settings.name = name.clone();
+                    self.ndi_name = name;
+                }
+                None => checked = Some(sender),
+            }
+        }
+        if let Some((config, stats)) = self.proxy.take() {
+            self.start_proxy(&config, frame_rate, stats);
+            // The checked sender was set up before the proxy joined the chain
+            if let Some(sender) = &mut checked {
+                sender.set_processors(Arc::clone(&self.processors));
+            }
+        }
+        let mut sender_factory = setup.sender_factory;
+        let mut sender = NdiSenderSupervisor::with_factory(
+            self.restart_policy.clone(),
+            Box::new(move || match checked.take() {
+                Some(sender) => Ok(Box::new(sender) as Box<dyn VideoSender + Send>),
+                None => sender_factory(&settings),
+            }),
+        )?
+        .with_stats(Arc::clone(&self.stats.sender));
+        if self.pacing == PacingMode::Software {
+            sender = sender.with_pacer(FramePacer::new(frame_rate));
+        }
+        tracing::info!("NDI sender ready, streaming as '{}'", self.ndi_name);
+
+        self.running.store(true, Ordering::Relaxed);
+        self.events
+            .emit(PipelineEvent::FrameRateChanged(source.frame_rate()));
+
+        // Embedded HDMI audio: captured on its own thread, sent from the video loop
+        let audio_queue = setup.audio.take().map(|settings| {
+            let queue = Arc::new(settings.queue());
+            let queue_clone = Arc::clone(&queue);
+            let running = Arc::clone(&self.running);
+            self.threads
+                .push(threads::spawn(threads::HDMI_AUDIO, move || {
+                    capture_audio::run_audio_capture(settings, queue_clone, running)
+                }));
+            queue
+        });
+
+        let mut worker = Worker {
+            budget: FrameBudget::new(source.frame_rate()),
+            source: Some(source),
+            source_factory: setup.source_factory,
+            usb_reset: setup.usb_reset,
+            sender,
+            breaker: SendBreaker::new(BREAKER_THRESHOLD, BREAKER_RETRY),
+            audio_queue,
+            watchdog: CaptureWatchdog::new(Instant::now()),
+            addresses: AddressMonitor::new(Instant::now(), ADDRESS_POLL_INTERVAL),
+            stall_timeout: self.stall_timeout,
+            running: Arc::clone(&self.running),
+            stats: Arc::clone(&self.stats),
+            timing: self.timing.clone(),
+            events: self.events.clone(),
+        };
+        let realtime = self.realtime;
+        self.threads.push(threads::spawn(threads::CAPTURE, move || {
+            if realtime {
+                // Apply real-time optimizations BEFORE entering the capture loop
+                realtime::apply_realtime_optimizations();
+            }
+            worker.run();
+        }));
+        Ok(())
+    }
+
+    /// Open the proxy's NDI sender and add the proxy to the frame
+    /// processors; without a sender the proxy is left out
+    fn start_proxy(&self, config: &ProxyConfig, frame_rate: FrameRate, stats: Arc<OutputStats>) {
+        let name = format!("{}{}", self.ndi_name, config.name_suffix);
+        let rate = ndi_proxy::proxy_rate(frame_rate, config.fps_divisor);
+        match NdiSender::with_groups(&name, rate, self.ndi_groups.as_deref()) {
+            Ok(sender) => {
+                tracing::info!(
+                    "NDI proxy '{}' at {}x{}, every {} frame(s)",
+                    name,
+                    config.width,
+                    config.height,
+                    config.fps_divisor
+                );
+                let proxy =
+                    NdiProxy::start(config, Box::new(sender), Arc::clone(&self.stats), stats);
+                self.processors
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .push(Box::new(proxy));
+            }
+            Err(e) => tracing::error!("NDI proxy disabled: {}", e),
+        }
+    }
+
+    /// Stop streaming and wait for the pipeline threads to finish
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// =============================================================================
+// Capture Loop
+// =============================================================================
+
+struct Worker {
+    source: Option<Box<dyn FrameSource>>,
+    source_factory: SourceFactory,
+    usb_reset: Option<UsbReset>,
+    sender: NdiSenderSupervisor<Box<dyn VideoSender + Send>>,
+    breaker: SendBreaker,
+    budget: FrameBudget,
+    audio_queue: Option<Arc<AudioQueue>>,
+    watchdog: CaptureWatchdog,
+    addresses: AddressMonitor,
+    stall_timeout: Duration,
+    running: Arc<AtomicBool>,
+    stats: Arc<PipelineStats>,
+    timing: Option<Arc<PipelineTiming>>,
+    events: EventBus,
+}
+
+/// Send one frame through the circuit breaker: while it is open, frames are
+/// dropped before conversion and failures are logged once, not per frame
+fn send_guarded(
+    sender: &mut NdiSenderSupervisor<Box<dyn VideoSender + Send>>,
+    breaker: &mut SendBreaker,
+    stats: &PipelineStats,
+    data: &[u8],
+    info: FrameInfo,
+) {
+    let now = Instant::now();
+    if !breaker.allow(now) {
+        stats.sends_suppressed.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    match sender.send_frame_zero_copy(data, info) {
+        Ok(()) => {
+            if let Some(BreakerTransition::Recovered { suppressed }) = breaker.on_success() {
+                tracing::info!(
+                    "Sending recovered ({} frames dropped while paused)",
+                    suppressed
+                );
+            }
+        }
+        Err(e) => {
+            let failure = SendFailure::of(&e);
+            match breaker.on_failure(now, failure) {
+                Some(BreakerTransition::Opened { failures, .. }) => tracing::error!(
+                    "Failed to send {} frames in a row ({:?}, {:?}): {:#}; pausing sends, retrying every {:?}",
+                    failures,
+                    failure,
+                    SendErrorKind::of(&e),
+                    e,
+                    BREAKER_RETRY
+                ),
+                _ if breaker.state() == BreakerState::Closed => {
+                    tracing::error!("Failed to send frame: {}", e)
+                }
+                _ => tracing::debug!("Trial send failed: {:#}", e),
+            }
+        }
+    }
+}
+
+/// Capture time of the end of the audio sent with a video frame, relative
+/// to the frame's, in microseconds; positive when the audio runs ahead
+fn av_offset(audio_start: Instant, samples: &[i16], queue: &AudioQueue, video: Instant) -> i64 {
+    let frames = samples.len() as u64 / queue.channels().max(1) as u64;
+    let audio_end = audio_start + av_clock::frames_duration(frames, queue.sample_rate());
+    match audio_end.checked_duration_since(video) {
+        Some(ahead) => ahead.as_micros() as i64,
+        None => -(video.duration_since(audio_end).as_micros() as i64),
+    }
+}
+
+/// Dequeue up to `count` waiting frames without converting them. Returns
+/// the number skipped.
+fn skip_frames(
+    source: &mut dyn FrameSource,
+    budget: &mut FrameBudget,
+    stats: &PipelineStats,
+    count: u32,
+) -> u32 {
+    let mut skipped = 0;
+    while skipped < count {
+        match source.skip_frame() {
+            Ok(true) => skipped += 1,
+            Ok(false) => {
+                budget.drained();
+                break;
+            }
+            Err(e) => {
+                tracing::debug!("Failed to skip frame: {:#}", e);
+                break;
+            }
+        }
+    }
+    budget.skipped(skipped);
+    stats
+        .frames_skipped
+        .fetch_add(skipped as u64, Ordering::Relaxed);
+    tracing::debug!("Capture {} frame(s) behind, skipped {}", count + 1, skipped);
+    skipped
+}
+
+impl Worker {
+    fn run(&mut self) {
+        // Wait at most one stall timeout per frame so stalls are noticed in time
+        let frame_timeout = if self.stall_timeout.is_zero() {
+            FRAME_TIMEOUT
+        } else {
+            FRAME_TIMEOUT.min(self.stall_timeout)
+        };
+        let mut audio_samples = Vec::new();
+        let mut next_connections_poll = Instant::now();
+
+        while self.running.load(Ordering::Relaxed) {
+            let now = Instant::now();
+            if self.watchdog.check(now, self.stall_timeout) {
+                tracing::warn!(
+                    "Capture stalled: no frame for {:.1}s, reopening device",
+                    self.watchdog.since_last_frame(now).as_secs_f64()
+                );
+            }
+            if self.watchdog.take_reopen_request() && !self.reopen() {
+                break;
+            }
+            if now >= next_connections_poll {
+                self.poll_connections();
+                self.poll_tally();
+                next_connections_poll = now + CONNECTIONS_POLL_INTERVAL;
+            }
+            if let Some(change) = self.addresses.poll(now, netwatch::local_addresses) {
+                tracing::info!(
+                    "Local addresses changed ({}), re-announcing NDI sender",
+                    change
+                );
+                self.sender.reannounce(now);
+            }
+            let Some(source) = self.source.as_mut() else {
+                break;
+            };
+
+            // Behind by more than a frame: drop the oldest queued frames
+            let skip = self.budget.frames_to_skip();
+            if skip > 0 && skip_frames(source.as_mut(), &mut self.budget, &self.stats, skip) > 0 {
+                self.watchdog.frame_received(Instant::now());
+            }
+
+            // ZERO-COPY: Process frame directly from the capture buffer
+            let sender = &mut self.sender;
+            let breaker = &mut self.breaker;
+            let stats = &self.stats;
+            let started = Instant::now();
+            let mut timing = None;
+            let result = source.process_frame(frame_timeout, &mut |data, info| {
+                let received = Instant::now();
+                send_guarded(sender, breaker, stats, data, info.with_captured(received));
+                // The source requeues the previous frame's buffer once this
+                // callback returns. Lending this frame gave that one back;
+                // otherwise wait until the sender is done with it.
+                if !sender.lends(data) {
+                    sender.release_frame();
+                }
+                timing = Some((received - started, received, received.elapsed()));
+            });
+
+            match result {
+                Ok(true) => {
+                    if let (Some(recorder), Some((wait, received, work))) = (&self.timing, timing) {
+                        // The source requeued the buffer after the callback
+                        let requeue = (received + work).elapsed();
+                        let convert = self.sender.last_convert().unwrap_or_default().min(work);
+                        recorder.record(&FrameTiming {
+                            dequeue: wait,
+                            convert,
+                            send: work - convert,
+                            requeue,
+                        });
+                    }
+                    if self.stats.frames_captured.fetch_add(1, Ordering::Relaxed) == 0 {
+                        self.events.emit(PipelineEvent::FirstFrame);
+                    }
+                    self.watchdog.frame_received(Instant::now());
+                    if let Some((wait, _, work)) = timing {
+                        if self.budget.record(wait, work) {
+                            self.stats
+                                .frames_over_budget
+                                .fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+
+                    // Audio captured since the previous frame
+                    if let Some(queue) = &self.audio_queue {
+                        let audio_captured = queue.drain_into(&mut audio_samples);
+                        if !audio_samples.is_empty() {
+                            if let (Some(audio), Some((_, video, _))) = (audio_captured, timing) {
+                                let offset = av_offset(audio, &audio_samples, queue, video);
+                                self.stats.av_offset_us.store(offset, Ordering::Relaxed);
+                                self.stats.audio_blocks.fetch_add(1, Ordering::Relaxed);
+                            }
+                            if let Err(e) = self.sender.send_audio(
+                                &audio_samples,
+                                queue.channels(),
+                                queue.sample_rate(),
+                                audio_captured,
+                            ) {
+                                tracing::debug!("Failed to send audio: {}", e);
+                            }
+                        }
+                    }
+                }
+                Ok(false) => {
+                    // No frame within the timeout; the watchdog decides when to reopen
+                    tracing::debug!("No frame within {:?}", frame_timeout);
+                }
+                Err(e) => {
+                    self.stats.capture_errors.fetch_add(1, Ordering::Relaxed);
+                    tracing::error!("Failed to capture frame: {}", e);
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+        self.sender.release_frame();
+    }
+
+    /// Tear the wedged source down and open it again, retrying every second.
+    /// Returns false if shutdown was requested first.
+    fn reopen(&mut self) -> bool {
+        self.stats.device_losses.fetch_add(1, Ordering::Relaxed);
+        self.events.emit(PipelineEvent::DeviceLost);
+        let previous_rate = self.source.as_ref().map(|source| source.frame_rate());
+        // The lent frame's buffer goes away with the source
+        self.sender.release_frame();
+        drop(self.source.take());
+        self.reset_usb_if_wedged();
+
+        while self.running.load(Ordering::Relaxed) {
+            match (self.source_factory)() {
+                Ok(source) => {
+                    self.watchdog.reopened(Instant::now());
+                    self.stats
+                        .stall_recoveries
+                        .store(self.watchdog.stall_recoveries(), Ordering::Relaxed);
+                    tracing::info!(
+                        "Capture reopened (stall recoveries: {})",
+                        self.watchdog.stall_recoveries()
+                    );
+                    let rate = source.frame_rate();
+                    self.budget.reset(rate);
+                    self.source = Some(source);
+                    self.events.emit(PipelineEvent::DeviceRecovered);
+                    if previous_rate != Some(rate) {
+                        self.events.emit(PipelineEvent::FrameRateChanged(rate));
+                    }
+                    return true;
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to reopen capture source: {:#}", e);
+                    std::thread::sleep(REOPEN_RETRY);
+                }
+            }
+        }
+        false
+    }
+
+    /// Re-enumerate the dongle once plain reopens have failed; a failed
+    /// reset is logged and the plain reopen still follows
+    fn reset_usb_if_wedged(&self) {
+        let Some(usb_reset) = &self.usb_reset else {
+            return;
+        };
+        let failed = self.watchdog.failed_reopens();
+        if failed < USB_RESET_AFTER_FAILED_REOPENS {
+            return;
+        }
+        tracing::warn!("{} reopens brought no frames back", failed);
+        self.stats.usb_resets.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = usb_reset.reset() {
+            tracing::error!("USB reset failed: {:#}", e);
+        }
+    }
+
+    fn poll_connections(&mut self) {
+        let Some(connections) = self.sender.connections() else {
+            return;
+        };
+        let previous = self
+            .stats
+            .ndi_connections
+            .swap(connections, Ordering::Relaxed);
+        if previous != connections {
+            self.events.emit(PipelineEvent::NdiConnections(connections));
+        }
+    }
+
+    fn poll_tally(&mut self) {
+        let Some(tally) = self.sender.tally() else {
+            return;
+        };
+        let previous = self.stats.tally.swap(tally as u8, Ordering::Relaxed);
+        if previous != tally as u8 {
+            self.events.emit(PipelineEvent::TallyChanged(tally));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fakes::{BufferEvent, RecordingSink, ScriptedSource, SinkLog};
+
+    const RATE: FrameRate = FrameRate {
+        numerator: 200,
+        denominator: 1,
+    };
+
+    /// Scripted sources are unpaced; a low nominal rate keeps a scheduling
+    /// hiccup from reading as a capture backlog
+    const SCRIPTED_RATE: FrameRate = FrameRate {
+        numerator: 30,
+        denominator: 1,
+    };
+
+    fn builder(log: &Arc<SinkLog>) -> PipelineBuilder {
+        let log = Arc::clone(log);
+        Pipeline::builder(Config::default())
+            .test_pattern(64, 8, RATE)
+            .sender_factory(move |_| Ok(Box::new(RecordingSink::new(&log)) as _))
+    }
+
+    /// Pipeline over one scripted source; the watchdog is off unless set
+    fn scripted(log: &Arc<SinkLog>, source: ScriptedSource) -> PipelineBuilder {
+        let mut source = Some(source);
+        builder(log)
+            .stall_timeout(Duration::ZERO)
+            .source_factory(move || match source.take() {
+                Some(source) => Ok(Box::new(source) as _),
+                None => bail!("Scripted source already opened"),
+            })
+    }
+
+    fn wait_for(events: &Receiver<PipelineEvent>, wanted: &PipelineEvent) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match events.recv_timeout(left) {
+                Ok(event) if &event == wanted => return true,
+                Ok(_) => {}
+                Err(_) => return false,
+            }
+        }
+        false
+    }
+
+    /// Wait until `done` holds, for at most five seconds
+    fn wait_until(done: impl Fn() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
+
+    #[test]
+    fn test_pipeline_streams_test_pattern() {
+        let log = SinkLog::new();
+        let mut pipeline = builder(&log).build().unwrap();
+        assert!(pipeline.device_path().is_none());
+        let events = pipeline.subscribe();
+        pipeline.start().unwrap();
+        assert!(pipeline.start().is_err());
+        assert!(wait_for(&events, &PipelineEvent::FrameRateChanged(RATE)));
+        assert!(wait_for(&events, &PipelineEvent::FirstFrame));
+
+        wait_until(|| log.frame_count() >= 10);
+        pipeline.stop();
+        assert!(!pipeline.is_running());
+
+        let stats = pipeline.stats();
+        let frames = log.frames();
+        let sent = frames.len() as u64;
+        assert!(sent >= 10);
+        for frame in &frames {
+            assert_eq!(
+                frame.data.len(),
+                (frame.info.stride * frame.info.height) as usize
+            );
+        }
+        assert_eq!(stats.frames_captured.load(Ordering::Relaxed), sent);
+        assert_eq!(stats.sender.frames_sent.load(Ordering::Relaxed), sent);
+        assert_eq!(stats.device_losses.load(Ordering::Relaxed), 0);
+
+        // The same counters reach the stats report
+        let registry = StatsRegistry::new();
+        stats.register(&registry);
+        let snapshot = registry.snapshot(Instant::now());
+        assert_eq!(
+            snapshot.get("capture.frames"),
+            Some(crate::stats::Value::Counter(sent))
+        );
+        assert_eq!(
+            snapshot.get("ndi.frames"),
+            Some(crate::stats::Value::Counter(sent))
+        );
+        assert_eq!(
+            snapshot.get("ndi.format.UYVY.converted"),
+            Some(crate::stats::Value::Counter(sent))
+        );
+        assert_eq!(
+            snapshot.get("ndi.format.UYVY.rejected"),
+            Some(crate::stats::Value::Counter(0))
+        );
+    }
+
+    #[test]
+    fn test_pipeline_records_frames_sent() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = Config::default();
+        config.record.enable = true;
+        config.record.path = dir.path().to_str().unwrap().to_string();
+        let log = SinkLog::new();
+        let sink_log = Arc::clone(&log);
+        let mut pipeline = Pipeline::builder(config)
+            .test_pattern(64, 8, RATE)
+            .sender_factory(move |_| Ok(Box::new(RecordingSink::new(&sink_log)) as _))
+            .build()
+            .unwrap();
+        let recording = pipeline.recording().unwrap();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| recording.frames.load(Ordering::Relaxed) >= 5));
+        pipeline.stop();
+        // Dropping the pipeline finishes the recording
+        drop(pipeline);
+
+        let frames = recording.frames.load(Ordering::Relaxed);
+        let index = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|p| p.extension().is_some_and(|e| e == "idx"))
+            .unwrap();
+        let entries = crate::recorder::read_index(&index).unwrap();
+        assert_eq!(entries.len() as u64, frames);
+        assert!(entries.iter().all(|e| (e.width, e.height) == (64, 8)));
+        assert_eq!(recording.bytes.load(Ordering::Relaxed), frames * 64 * 8 * 2);
+    }
+
+    /// Pipeline with `ndi.zero_copy_uyvy` over sinks that hold frames
+    fn zero_copy(log: &Arc<SinkLog>) -> PipelineBuilder {
+        let mut config = Config::default();
+        config.ndi.zero_copy_uyvy = true;
+        let log = Arc::clone(log);
+        Pipeline::builder(config)
+            .test_pattern(64, 8, RATE)
+            .sender_factory(move |settings| {
+                Ok(Box::new(RecordingSink::new(&log).holding(settings.zero_copy)) as _)
+            })
+    }
+
+    #[test]
+    fn test_zero_copy_soak_with_test_pattern() {
+        let log = SinkLog::new();
+        let mut pipeline = zero_copy(&log).build().unwrap();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| log.frame_count() >= 200));
+        pipeline.stop();
+
+        let stats = pipeline.stats();
+        assert!(log.frames_held() >= 200);
+        assert_eq!(log.torn_frames(), 0);
+        assert_eq!(stats.sender.zero_copy_torn.load(Ordering::Relaxed), 0);
+        assert_eq!(stats.sender.send_errors.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_zero_copy_releases_before_the_buffer_is_refilled() {
+        // The scripted source refills a frame's buffer two frames later, so
+        // a frame still lent by then would be torn, timeout or not
+        let log = SinkLog::new();
+        let source = ScriptedSource::new(64, 8, SCRIPTED_RATE)
+            .with_frames(50)
+            .with_timeouts(1)
+            .with_frames(50);
+        let mut source = Some(source);
+        let mut pipeline = zero_copy(&log)
+            .stall_timeout(Duration::ZERO)
+            .source_factory(move || match source.take() {
+                Some(source) => Ok(Box::new(source) as _),
+                None => bail!("Scripted source already opened"),
+            })
+            .build()
+            .unwrap();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| log.frame_count() == 100));
+        pipeline.stop();
+
+        assert_eq!(log.frames_held(), 100);
+        assert_eq!(log.torn_frames(), 0);
+        let sequence: Vec<u8> = log.frames().iter().map(|frame| frame.data[0]).collect();
+        assert_eq!(sequence, (0..100).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_zero_copy_overlaps_the_next_dequeue_with_the_lent_frame() {
+        let log = SinkLog::new();
+        let source = ScriptedSource::new(64, 8, SCRIPTED_RATE)
+            .with_frames(20)
+            .traced(&log);
+        let mut source = Some(source);
+        let mut pipeline = zero_copy(&log)
+            .stall_timeout(Duration::ZERO)
+            .source_factory(move || match source.take() {
+                Some(source) => Ok(Box::new(source) as _),
+                None => bail!("Scripted source already opened"),
+            })
+            .build()
+            .unwrap();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| log.frame_count() == 20));
+        pipeline.stop();
+
+        let events = log.buffer_events();
+        let at = |event| events.iter().position(|e| *e == event).unwrap();
+        for k in 0..19 {
+            // Frame k+1 is dequeued and sent while frame k is still lent...
+            assert!(at(BufferEvent::Dequeued(k + 1)) < at(BufferEvent::Released(k)));
+            assert!(at(BufferEvent::Sent(k + 1)) < at(BufferEvent::Released(k)));
+            // ...and frame k's buffer is requeued only once it was given back
+            assert!(at(BufferEvent::Released(k)) < at(BufferEvent::Requeued(k)));
+        }
+        assert_eq!(log.frames_held(), 20);
+        assert_eq!(log.torn_frames(), 0);
+        let stats = pipeline.stats();
+        assert_eq!(stats.sender.zero_copy_torn.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_pipeline_recovers_stalled_source() {
+        let log = SinkLog::new();
+        let opens = Arc::new(AtomicU32::new(0));
+        let opens_clone = Arc::clone(&opens);
+        // Every opened source stalls after 5 frames
+        let mut pipeline = builder(&log)
+            .stall_timeout(Duration::from_millis(100))
+            .source_factory(move || {
+                opens_clone.fetch_add(1, Ordering::Relaxed);
+                Ok(Box::new(TestPattern::new(64, 8, RATE).with_frame_limit(5)) as _)
+            })
+            .build()
+            .unwrap();
+        let events = pipeline.subscribe();
+        pipeline.start().unwrap();
+
+        assert!(wait_for(&events, &PipelineEvent::DeviceLost));
+        assert!(wait_for(&events, &PipelineEvent::DeviceRecovered));
+        pipeline.stop();
+
+        let stats = pipeline.stats();
+        assert!(stats.device_losses.load(Ordering::Relaxed) >= 1);
+        assert!(stats.stall_recoveries.load(Ordering::Relaxed) >= 1);
+        assert!(opens.load(Ordering::Relaxed) >= 2);
+        assert!(log.frame_count() >= 5);
+    }
+
+    #[test]
+    fn test_pipeline_reports_tally_changes() {
+        let log = SinkLog::new();
+        let mut pipeline = builder(&log).build().unwrap();
+        let events = pipeline.subscribe();
+        pipeline.start().unwrap();
+        assert!(wait_for(&events, &PipelineEvent::FirstFrame));
+
+        log.set_tally(Tally::Program);
+        assert!(wait_for(
+            &events,
+            &PipelineEvent::TallyChanged(Tally::Program)
+        ));
+        assert_eq!(
+            pipeline.stats().tally.load(Ordering::Relaxed),
+            Tally::Program as u8
+        );
+        log.set_tally(Tally::Off);
+        assert!(wait_for(&events, &PipelineEvent::TallyChanged(Tally::Off)));
+    }
+
+    #[test]
+    fn test_pipeline_reports_ndi_connections() {
+        let log = SinkLog::new();
+        log.set_connections(2);
+        let mut pipeline = builder(&log).build().unwrap();
+        let events = pipeline.subscribe();
+        pipeline.start().unwrap();
+
+        assert!(wait_for(&events, &PipelineEvent::NdiConnections(2)));
+        assert_eq!(pipeline.stats().ndi_connections.load(Ordering::Relaxed), 2);
+        drop(pipeline);
+        // Dropping the pipeline stops it and closes the event channel
+        assert!(events.recv_timeout(Duration::from_secs(1)).is_err());
+    }
+
+    #[test]
+    fn test_pipeline_counts_every_scripted_frame() {
+        let log = SinkLog::new();
+        let source = ScriptedSource::new(64, 8, SCRIPTED_RATE)
+            .with_frames(12)
+            .with_timeouts(2)
+            .with_frames(8);
+        let mut pipeline = scripted(&log, source).build().unwrap();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| log.frame_count() == 20));
+        pipeline.stop();
+
+        // Timeouts are neither frames nor errors; frames arrive in order
+        let stats = pipeline.stats();
+        assert_eq!(stats.frames_captured.load(Ordering::Relaxed), 20);
+        assert_eq!(stats.sender.frames_sent.load(Ordering::Relaxed), 20);
+        assert_eq!(stats.capture_errors.load(Ordering::Relaxed), 0);
+        let sequence: Vec<u8> = log.frames().iter().map(|frame| frame.data[0]).collect();
+        assert_eq!(sequence, (0..20).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_pipeline_skips_frames_when_over_budget() {
+        let log = SinkLog::new();
+        // 12 ms per send against 5 ms frames, with every frame already queued
+        log.set_send_delay(Duration::from_millis(12));
+        let source = ScriptedSource::new(64, 8, RATE).with_frames(40);
+        let mut pipeline = scripted(&log, source).build().unwrap();
+        let stats = pipeline.stats();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| {
+            log.frame_count() as u64 + stats.frames_skipped.load(Ordering::Relaxed) == 40
+        }));
+        pipeline.stop();
+
+        let skipped = stats.frames_skipped.load(Ordering::Relaxed);
+        assert!(skipped > 0);
+        assert!(stats.frames_over_budget.load(Ordering::Relaxed) > 0);
+        assert_eq!(stats.frames_captured.load(Ordering::Relaxed) + skipped, 40);
+        // Skipped frames are simply missing; the rest stay in order
+        let sequence: Vec<u8> = log.frames().iter().map(|frame| frame.data[0]).collect();
+        assert!(sequence.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_pipeline_times_each_stage() {
+        let log = SinkLog::new();
+        log.set_send_delay(Duration::from_millis(3));
+        assert!(builder(&log).build().unwrap().timing().is_none());
+
+        let mut config = Config::default();
+        config.capture.timing = true;
+        let mut source = Some(ScriptedSource::new(64, 8, SCRIPTED_RATE).with_frames(10));
+        let sink = Arc::clone(&log);
+        let mut pipeline = Pipeline::builder(config)
+            .stall_timeout(Duration::ZERO)
+            .source_factory(move || match source.take() {
+                Some(source) => Ok(Box::new(source) as _),
+                None => bail!("Scripted source already opened"),
+            })
+            .sender_factory(move |_| Ok(Box::new(RecordingSink::new(&sink)) as _))
+            .build()
+            .unwrap();
+        let timing = pipeline.timing().unwrap();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| timing
+            .summary()
+            .is_some_and(|(_, frames)| frames == 10)));
+        pipeline.stop();
+
+        // The sink converts nothing, so its delay is all send time
+        let (stages, _) = timing.summary().unwrap();
+        assert_eq!(stages[1].0, crate::timing::Stage::Convert);
+        assert_eq!(stages[1].1.p99, Duration::ZERO);
+        assert_eq!(stages[2].0, crate::timing::Stage::Send);
+        assert!(stages[2].1.p50 >= Duration::from_millis(3));
+    }
+
+    #[test]
+    fn test_pipeline_continues_after_capture_errors() {
+        let log = SinkLog::new();
+        let source = ScriptedSource::new(64, 8, SCRIPTED_RATE)
+            .with_frames(3)
+            .with_error("VIDIOC_DQBUF: No such device")
+            .with_error("VIDIOC_DQBUF: No such device")
+            .with_frames(3);
+        let mut pipeline = scripted(&log, source).build().unwrap();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| log.frame_count() == 6));
+        pipeline.stop();
+
+        let stats = pipeline.stats();
+        assert_eq!(stats.capture_errors.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.frames_captured.load(Ordering::Relaxed), 6);
+        assert_eq!(stats.device_losses.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_pipeline_restarts_failing_sender() {
+        let log = SinkLog::new();
+        // Enough consecutive failures for one restart (default policy: 5)
+        log.fail_next_sends(5);
+        let source = ScriptedSource::new(64, 8, SCRIPTED_RATE).with_frames(10);
+        let mut pipeline = scripted(&log, source).build().unwrap();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| log.frame_count() == 5));
+        pipeline.stop();
+
+        let stats = pipeline.stats();
+        assert_eq!(log.recreations(), 1);
+        assert_eq!(stats.sender.restarts.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.sender.send_errors.load(Ordering::Relaxed), 5);
+        assert_eq!(stats.sender.frames_sent.load(Ordering::Relaxed), 5);
+        // Frames whose send failed still count as captured
+        assert_eq!(stats.frames_captured.load(Ordering::Relaxed), 10);
+        assert_eq!(log.frames()[0].data[0], 5);
+    }
+
+    #[test]
+    fn test_pipeline_breaker_pauses_failing_sends() {
+        let log = SinkLog::new();
+        log.fail_next_sends(BREAKER_THRESHOLD);
+        // 200 fps: far more frames than the threshold arrive within the retry interval
+        let mut pipeline = builder(&log).stall_timeout(Duration::ZERO).build().unwrap();
+        pipeline.start().unwrap();
+        let stats = pipeline.stats();
+        assert!(wait_until(|| stats
+            .sends_suppressed
+            .load(Ordering::Relaxed)
+            > 0));
+        assert_eq!(log.frame_count(), 0);
+
+        // The trial send after the retry interval succeeds and closes the breaker
+        assert!(wait_until(|| log.frame_count() >= 10));
+        let suppressed = stats.sends_suppressed.load(Ordering::Relaxed);
+        assert!(wait_until(|| log.frame_count() >= 100));
+        pipeline.stop();
+        assert_eq!(stats.sends_suppressed.load(Ordering::Relaxed), suppressed);
+        assert_eq!(
+            stats.sender.send_errors.load(Ordering::Relaxed),
+            BREAKER_THRESHOLD as u64
+        );
+    }
+
+    #[test]
+    fn test_pipeline_stops_promptly_while_source_stalls() {
+        let log = SinkLog::new();
+        let source = ScriptedSource::new(64, 8, SCRIPTED_RATE).with_frames(1);
+        let mut pipeline = scripted(&log, source).build().unwrap();
+        pipeline.start().unwrap();
+        assert!(wait_until(|| log.frame_count() == 1));
+
+        // The exhausted source blocks for a full frame timeout per poll
+        let started = Instant::now();
+        pipeline.stop();
+        assert!(started.elapsed() <= FRAME_TIMEOUT + Duration::from_millis(500));
+        assert!(!pipeline.is_running());
+    }
+
+    #[test]
+    fn test_build_rejects_bad_config() {
+        let mut config = Config::default();
+        config.ndi.pacing = "sometimes".to_string();
+        assert!(Pipeline::builder(config)
+            .test_pattern(64, 8, RATE)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_events_and_stats_are_send() {
+        fn assert_send_static<T: Send + 'static>() {}
+        assert_send_static::<PipelineEvent>();
+        assert_send_static::<Arc<PipelineStats>>();
+        assert_send_static::<Receiver<PipelineEvent>>();
+        // Dropped on the shutdown thread
+        assert_send_static::<Pipeline>();
+    }
+}
@@ -0,0 +1,247 @@
+//! Placeholders in configured names
+//!
+//! Boxes are imaged from one golden config, so names that must be unique per
+//! box can refer to the machine instead: `ndi_name = "{hostname}-cam"`.
+//! `{hostname}` is the kernel's hostname (not the `hostname` key), `{serial}`
+//! the DMI product serial and `{mac}` the MAC address of the interface
+//! holding the default route. `{{` and `}}` are literal braces.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs;
+use std::path::PathBuf;
+
+/// Placeholder names, as written between braces
+pub const NAMES: [&str; 3] = ["hostname", "serial", "mac"];
+
+/// One piece of a parsed template
+#[derive(Debug, PartialEq)]
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+fn parse(template: &str) -> Result<Vec<Part<'_>>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(at) = rest.find(['{', '}']) {
+        if at > 0 {
+            parts.push(Part::Text(&rest[..at]));
+        }
+        let brace = &rest[at..at + 1];
+        if rest[at + 1..].starts_with(brace) {
+            parts.push(Part::Text(brace));
+            rest = &rest[at + 2..];
+            continue;
+        }
+        if brace == "}" {
+            bail!("unmatched '}}' (write '}}}}' for a literal brace)");
+        }
+        let Some(len) = rest[at + 1..].find('}') else {
+            bail!("unclosed '{{' (write '{{{{' for a literal brace)");
+        };
+        let name = &rest[at + 1..at + 1 + len];
+        if !NAMES.contains(&name) {
+            bail!(
+                "unknown placeholder {{{}}} (supported: {{hostname}}, {{serial}}, {{mac}})",
+                name
+            );
+        }
+        parts.push(Part::Placeholder(name));
+        rest = &rest[at + 2 + len..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    Ok(parts)
+}
+
+/// Check a template's syntax and placeholder names without resolving them
+pub fn check(template: &str) -> Result<()> {
+    parse(template).map(drop)
+}
+
+/// Expand the placeholders in `template`, looking each one up with `resolve`
+pub fn expand(template: &str, mut resolve: impl FnMut(&str) -> Result<String>) -> Result<String> {
+    let mut expanded = String::with_capacity(template.len());
+    for part in parse(template)? {
+        match part {
+            Part::Text(text) => expanded.push_str(text),
+            Part::Placeholder(name) => expanded.push_str(&resolve(name)?),
+        }
+    }
+    Ok(expanded)
+}
+
+/// Placeholder values read from the running system
+pub struct SystemValues {
+    root: PathBuf,
+}
+
+impl Default for SystemValues {
+    fn default() -> Self {
+        Self::with_root("/")
+    }
+}
+
+impl SystemValues {
+    /// Read `/sys` and `/proc` below `root` instead of `/` (for tests)
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Value of the placeholder `name`
+    pub fn value(&self, name: &str) -> Result<String> {
+        match name {
+            "hostname" => crate::ndi_conflict::system_hostname()
+                .ok_or_else(|| anyhow!("{{hostname}}: the kernel hostname is not set")),
+            "serial" => self.serial().context("{serial}"),
+            "mac" => self.mac().context("{mac}"),
+            other => bail!("unknown placeholder {{{}}}", other),
+        }
+    }
+
+    fn read(&self, path: &str) -> Result<String> {
+        let path = self.root.join(path);
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(content.trim().to_string())
+    }
+
+    /// DMI product serial (readable by root only)
+    fn serial(&self) -> Result<String> {
+        let serial = self.read("sys/class/dmi/id/product_serial")?;
+        if serial.is_empty() {
+            bail!("the DMI product serial is empty");
+        }
+        Ok(serial)
+    }
+
+    /// MAC of the default route's interface as 12 lowercase hex digits
+    /// (short enough for a 16 byte VBAN stream name)
+    fn mac(&self) -> Result<String> {
+        let interface = self.primary_interface()?;
+        let address = self.read(&format!("sys/class/net/{}/address", interface))?;
+        let mac: String = address.chars().filter(|c| *c != ':').collect();
+        if mac.len() != 12 || mac.chars().any(|c| !c.is_ascii_hexdigit()) {
+            bail!("{} has no usable MAC address ({:?})", interface, address);
+        }
+        Ok(mac.to_ascii_lowercase())
+    }
+
+    /// Interface of the IPv4 default route, else the first non-loopback one
+    fn primary_interface(&self) -> Result<String> {
+        if let Ok(routes) = self.read("proc/net/route") {
+            let default = routes.lines().skip(1).find_map(|line| {
+                let mut fields = line.split_whitespace();
+                let interface = fields.next()?;
+                (fields.next()? == "00000000").then(|| interface.to_string())
+            });
+            if let Some(interface) = default {
+                return Ok(interface);
+            }
+        }
+        let mut interfaces: Vec<String> = fs::read_dir(self.root.join("sys/class/net"))
+            .context("Failed to list network interfaces")?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| name != "lo")
+            .collect();
+        interfaces.sort();
+        interfaces
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("no network interface found"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(name: &str) -> Result<String> {
+        Ok(match name {
+            "hostname" => "cam-7".to_string(),
+            "serial" => "SN123".to_string(),
+            _ => "00e04c680001".to_string(),
+        })
+    }
+
+    fn fake_root() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, content: &str| {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        write("sys/class/dmi/id/product_serial", "PF2ABCDE\n");
+        write("sys/class/net/eth0/address", "00:E0:4C:68:00:01\n");
+        write("sys/class/net/wlan0/address", "a4:5e:60:11:22:33\n");
+        write(
+            "proc/net/route",
+            "Iface\tDestination\tGateway\tFlags\n\
+             wlan0\t0002A8C0\t00000000\t0001\n\
+             eth0\t00000000\t0102A8C0\t0003\n",
+        );
+        dir
+    }
+
+    #[test]
+    fn test_expand_each_placeholder() {
+        assert_eq!(expand("{hostname}-cam", values).unwrap(), "cam-7-cam");
+        assert_eq!(expand("box-{serial}", values).unwrap(), "box-SN123");
+        assert_eq!(expand("{mac}", values).unwrap(), "00e04c680001");
+        assert_eq!(expand("usb", values).unwrap(), "usb");
+        assert_eq!(
+            expand("{hostname}/{serial}", values).unwrap(),
+            "cam-7/SN123"
+        );
+    }
+
+    #[test]
+    fn test_literal_braces() {
+        assert_eq!(expand("{{hostname}}", values).unwrap(), "{hostname}");
+        assert_eq!(expand("a{{b}}c-{hostname}", values).unwrap(), "a{b}c-cam-7");
+        assert!(expand("cam}", values).is_err());
+        assert!(expand("cam{", values).is_err());
+    }
+
+    #[test]
+    fn test_unknown_placeholder_is_an_error() {
+        let error = expand("{host}-cam", values).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "unknown placeholder {host} (supported: {hostname}, {serial}, {mac})"
+        );
+        assert!(check("{hostname}-{mac}").is_ok());
+        assert!(check("{}").is_err());
+        // Plain names never call the resolver
+        assert_eq!(expand("usb", |_| bail!("resolved")).unwrap(), "usb");
+    }
+
+    #[test]
+    fn test_system_values_from_sysfs() {
+        let root = fake_root();
+        let system = SystemValues::with_root(root.path());
+        assert_eq!(system.value("serial").unwrap(), "PF2ABCDE");
+        // The default route's interface, not the first one listed
+        assert_eq!(system.value("mac").unwrap(), "00e04c680001");
+
+        // Without a default route: the first interface by name
+        fs::write(root.path().join("proc/net/route"), "Iface\tDestination\n").unwrap();
+        assert_eq!(system.value("mac").unwrap(), "00e04c680001");
+        fs::remove_dir_all(root.path().join("sys/class/net/eth0")).unwrap();
+        assert_eq!(system.value("mac").unwrap(), "a45e60112233");
+
+        fs::write(root.path().join("sys/class/dmi/id/product_serial"), "\n").unwrap();
+        assert!(system.value("serial").is_err());
+    }
+
+    #[test]
+    fn test_system_hostname_placeholder() {
+        let system = SystemValues::default();
+        let expected = crate::ndi_conflict::system_hostname().unwrap();
+        assert_eq!(
+            expand("{hostname}-cam", |name| system.value(name)).unwrap(),
+            format!("{}-cam", expected)
+        );
+    }
+}
@@ -0,0 +1,790 @@
+//! Capture format enumeration and selection
+//!
+//! Lists every fourcc, frame size and frame interval a device offers and picks
+//! the combination to stream, so `open()` asks for a mode the driver actually
+//! has instead of probing with `set_format`, and `--probe` can explain why a
+//! device ended up at e.g. YUYV 30 fps instead of 60. Size and rate decide
+//! first; `capture.format_priority` only orders formats that reach both
+//! equally, so NV12 from an ISP at 60 fps beats YUYV capped at 30.
+
+use anyhow::{anyhow, ensure, Context, Result};
+use std::cmp::Ordering;
+use std::fmt::Write as _;
+use v4l::frameinterval::FrameIntervalEnum;
+use v4l::framesize::FrameSizeEnum;
+use v4l::video::Capture;
+use v4l::{Device, FourCC};
+
+use crate::capture::FrameRate;
+
+/// Capture size aimed for unless a larger [`AUTO_SIZES`] entry reaches
+/// the target rate
+pub const TARGET_WIDTH: u32 = 1920;
+pub const TARGET_HEIGHT: u32 = 1080;
+
+/// Sizes the selection aims for, largest first; the first one offered at
+/// the full target rate becomes the target size
+pub const AUTO_SIZES: [(u32, u32); 3] = [(3840, 2160), (2560, 1440), (TARGET_WIDTH, TARGET_HEIGHT)];
+
+/// Preferred capture rate unless `capture.fps` asks for another
+pub const TARGET_RATE: FrameRate = FrameRate {
+    numerator: 60,
+    denominator: 1,
+};
+
+/// Formats the NDI sender can convert, cheapest first; the default
+/// `capture.format_priority`
+pub const CONVERTIBLE_FOURCCS: [&str; 8] = [
+    "UYVY", "YUYV", "NV12", "NM12", "BGRA", "BGR4", "RX24", "MJPG",
+];
+
+/// The convertible formats in the default order
+pub fn default_format_priority() -> Vec<FourCC> {
+    CONVERTIBLE_FOURCCS
+        .iter()
+        .map(|name| fourcc_of(name))
+        .collect()
+}
+
+/// Parse `capture.format_priority`: convertible fourccs (any case), each
+/// listed once. Formats left out are never selected.
+pub fn parse_format_priority(names: &[String]) -> Result<Vec<FourCC>> {
+    ensure!(!names.is_empty(), "must list at least one format");
+    let mut formats = Vec::with_capacity(names.len());
+    for name in names {
+        let upper = name.to_ascii_uppercase();
+        if !CONVERTIBLE_FOURCCS.contains(&upper.as_str()) {
+            return Err(anyhow!(
+                "{:?} is not a convertible format. Supported: {}",
+                name,
+                CONVERTIBLE_FOURCCS.join(", ")
+            ));
+        }
+        let fourcc = fourcc_of(&upper);
+        ensure!(!formats.contains(&fourcc), "{} is listed twice", upper);
+        formats.push(fourcc);
+    }
+    Ok(formats)
+}
+
+fn fourcc_of(name: &str) -> FourCC {
+    let mut repr = [0u8; 4];
+    repr.copy_from_slice(&name.as_bytes()[..4]);
+    FourCC::new(&repr)
+}
+
+/// One fourcc/size/rate combination offered by a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    pub fourcc: FourCC,
+    pub width: u32,
+    pub height: u32,
+    pub rate: FrameRate,
+}
+
+impl Mode {
+    pub fn new(fourcc: &[u8; 4], width: u32, height: u32, fps: u32) -> Self {
+        Self {
+            fourcc: FourCC::new(fourcc),
+            width,
+            height,
+            rate: FrameRate {
+                numerator: fps,
+                denominator: 1,
+            },
+        }
+    }
+
+    /// Sort key for selection; lower is better. Size comes first (the exact
+    /// `target_size`, then the largest below it, then the smallest above
+    /// it), then frame rate up to `target_rate`, then the place of the
+    /// format in `priority`. None for formats not in `priority`.
+    fn rank(
+        &self,
+        target_size: (u32, u32),
+        target_rate: FrameRate,
+        priority: &[FourCC],
+    ) -> Option<(u8, u64, u64, u64, usize)> {
+        let fourcc = priority.iter().position(|format| *format == self.fourcc)?;
+        let area = self.width as u64 * self.height as u64;
+        let target_area = target_size.0 as u64 * target_size.1 as u64;
+        let (size_class, size_key) = if (self.width, self.height) == target_size {
+            (0, 0)
+        } else if area <= target_area {
+            (1, u64::MAX - area)
+        } else {
+            (2, area)
+        };
+        let (rate_key, rate_distance) = rate_rank(self.rate, target_rate);
+        Some((size_class, size_key, rate_key, rate_distance, fourcc))
+    }
+}
+
+/// Sort key of `rate` against `target`; lower is better: the highest rate
+/// up to the target, then the closest one above it
+fn rate_rank(rate: FrameRate, target: FrameRate) -> (u64, u64) {
+    let fps = millifps(rate);
+    let target = millifps(target);
+    (u64::MAX - fps.min(target), fps.abs_diff(target))
+}
+
+/// True if `rate` is `target` or its NTSC (1000/1001) variant or faster
+fn reaches(rate: FrameRate, target: FrameRate) -> bool {
+    rate.numerator as u64 * target.denominator as u64 * 1001
+        >= target.numerator as u64 * rate.denominator as u64 * 1000
+}
+
+/// The rate to ask for among those a device offers for one format and size
+pub fn best_rate(rates: &[FrameRate], target: FrameRate) -> Option<FrameRate> {
+    rates
+        .iter()
+        .copied()
+        .min_by_key(|rate| rate_rank(*rate, target))
+}
+
+/// Whether the selector picked a mode, and why not
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Selected,
+    Rejected(String),
+}
+
+/// Everything a capture device offers on the single-planar capture API
+#[derive(Debug, Clone)]
+pub struct DeviceReport {
+    pub device: String,
+    pub card: String,
+    pub driver: String,
+    pub modes: Vec<Mode>,
+    /// Rate the selection aims for
+    pub target_rate: FrameRate,
+    /// Formats the selection may pick, most preferred first
+    pub format_priority: Vec<FourCC>,
+}
+
+impl DeviceReport {
+    /// Enumerate the formats of an open device, for a selection aiming at
+    /// `target_rate` among `format_priority`
+    pub fn enumerate(
+        device: &Device,
+        device_path: &str,
+        target_rate: FrameRate,
+        format_priority: &[FourCC],
+    ) -> Result<Self> {
+        let caps = device
+            .query_caps()
+            .context("Failed to query capabilities")?;
+        let mut modes = Vec::new();
+        let formats = Capture::enum_formats(device).context("Failed to enumerate formats")?;
+        for format in formats {
+            let sizes = Capture::enum_framesizes(device, format.fourcc).unwrap_or_default();
+            for (width, height) in sizes.into_iter().flat_map(|size| frame_sizes(size.size)) {
+                let intervals = Capture::enum_frameintervals(device, format.fourcc, width, height)
+                    .unwrap_or_default();
+                for rate in intervals
+                    .into_iter()
+                    .flat_map(|interval| frame_rates(interval.interval, target_rate))
+                {
+                    modes.push(Mode {
+                        fourcc: format.fourcc,
+                        width,
+                        height,
+                        rate,
+                    });
+                }
+            }
+        }
+        Ok(Self {
+            device: device_path.to_string(),
+            card: caps.card,
+            driver: caps.driver,
+            modes,
+            target_rate,
+            format_priority: format_priority.to_vec(),
+        })
+    }
+
+    /// The size the selection aims for: the largest of [`AUTO_SIZES`] that
+    /// a selectable mode offers at the full target rate, else 1920x1080.
+    /// A 4K30-only dongle that also has 1080p60 stays at 1080p.
+    pub fn target_size(&self) -> (u32, u32) {
+        AUTO_SIZES
+            .into_iter()
+            .find(|&size| {
+                self.modes.iter().any(|mode| {
+                    (mode.width, mode.height) == size
+                        && self.format_priority.contains(&mode.fourcc)
+                        && reaches(mode.rate, self.target_rate)
+                })
+            })
+            .unwrap_or((TARGET_WIDTH, TARGET_HEIGHT))
+    }
+
+    /// The mode `open()` streams, if any offered mode can be converted
+    pub fn select(&self) -> Option<Mode> {
+        let target_size = self.target_size();
+        self.modes
+            .iter()
+            .filter_map(|mode| {
+                mode.rank(target_size, self.target_rate, &self.format_priority)
+                    .map(|rank| (rank, mode))
+            })
+            .min_by(|a, b| a.0.cmp(&b.0))
+            .map(|(_, mode)| *mode)
+    }
+
+    /// Verdict for each mode, in `modes` order
+    pub fn verdicts(&self) -> Vec<Verdict> {
+        let selected = self.select();
+        let target_size = self.target_size();
+        let mut picked = false;
+        self.modes
+            .iter()
+            .map(|mode| match selected {
+                Some(best) if *mode == best && !picked => {
+                    picked = true;
+                    Verdict::Selected
+                }
+                best => Verdict::Rejected(rejection(
+                    mode,
+                    best,
+                    target_size,
+                    self.target_rate,
+                    &self.format_priority,
+                )),
+            })
+            .collect()
+    }
+
+    /// Human-readable table, one row per mode
+    pub fn to_table(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "{}: {} ({})", self.device, self.card, self.driver);
+        if self.modes.is_empty() {
+            out.push_str("No enumerable capture modes\n");
+            return out;
+        }
+        let _ = writeln!(out, "  {:<6} {:>11} {:>8}  RESULT", "FOURCC", "SIZE", "FPS");
+        for (mode, verdict) in self.modes.iter().zip(self.verdicts()) {
+            let (marker, result) = match verdict {
+                Verdict::Selected => ("*", "selected".to_string()),
+                Verdict::Rejected(reason) => (" ", format!("rejected: {}", reason)),
+            };
+            let _ = writeln!(
+                out,
+                "{} {:<6} {:>11} {:>8}  {}",
+                marker,
+                mode.fourcc.to_string(),
+                format!("{}x{}", mode.width, mode.height),
+                format_fps(mode.rate),
+                result
+            );
+        }
+        out
+    }
+
+    /// JSON document with the same content as the table
+    pub fn to_json(&self) -> String {
+        let modes: Vec<String> = self
+            .modes
+            .iter()
+            .zip(self.verdicts())
+            .map(|(mode, verdict)| {
+                let (selected, reason) = match verdict {
+                    Verdict::Selected => (true, "null".to_string()),
+                    Verdict::Rejected(reason) => (false, json_string(&reason)),
+                };
+                format!(
+                    "{{\"fourcc\":{},\"width\":{},\"height\":{},\"fps\":{},\
+                     \"numerator\":{},\"denominator\":{},\"selected\":{},\"rejected\":{}}}",
+                    json_string(&mode.fourcc.to_string()),
+                    mode.width,
+                    mode.height,
+                    format_fps(mode.rate),
+                    mode.rate.numerator,
+                    mode.rate.denominator,
+                    selected,
+                    reason
+                )
+            })
+            .collect();
+        format!(
+            "{{\"device\":{},\"card\":{},\"driver\":{},\"modes\":[{}]}}",
+            json_string(&self.device),
+            json_string(&self.card),
+            json_string(&self.driver),
+            modes.join(",")
+        )
+    }
+}
+
+/// Why `mode` lost to `best`
+fn rejection(
+    mode: &Mode,
+    best: Option<Mode>,
+    target_size: (u32, u32),
+    target_rate: FrameRate,
+    priority: &[FourCC],
+) -> String {
+    let Some(rank) = mode.rank(target_size, target_rate, priority) else {
+        let name = mode.fourcc.to_string();
+        return if CONVERTIBLE_FOURCCS.contains(&name.as_str()) {
+            format!("{} is not in capture.format_priority", name)
+        } else {
+            format!("no NDI conversion for {}", name)
+        };
+    };
+    let best = best.expect("a mode with a rank is selectable");
+    let best_rank = best
+        .rank(target_size, target_rate, priority)
+        .expect("selected mode has a rank");
+    match (rank.0, rank.1).cmp(&(best_rank.0, best_rank.1)) {
+        Ordering::Greater if best_rank.0 == 0 => {
+            return format!("not {}x{}", target_size.0, target_size.1)
+        }
+        Ordering::Greater => {
+            return format!(
+                "{}x{} is closer to {}x{}",
+                best.width, best.height, target_size.0, target_size.1
+            )
+        }
+        Ordering::Less => unreachable!("selected mode ranks first"),
+        Ordering::Equal => {}
+    }
+    if (rank.2, rank.3) != (best_rank.2, best_rank.3) {
+        return if millifps(mode.rate) < millifps(best.rate) {
+            format!("{} fps available", format_fps(best.rate))
+        } else {
+            format!(
+                "{} fps is closer to {} fps",
+                format_fps(best.rate),
+                format_fps(target_rate)
+            )
+        };
+    }
+    if rank.4 != best_rank.4 {
+        return format!("{} comes first in capture.format_priority", best.fourcc);
+    }
+    "duplicate of the selected mode".to_string()
+}
+
+/// Frame rate in thousandths of a frame per second
+fn millifps(rate: FrameRate) -> u64 {
+    rate.numerator as u64 * 1000 / rate.denominator.max(1) as u64
+}
+
+fn format_fps(rate: FrameRate) -> String {
+    let fps = format!(
+        "{:.3}",
+        rate.numerator as f64 / rate.denominator.max(1) as f64
+    );
+    fps.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Frame sizes worth listing; a stepwise range contributes the
+/// [`AUTO_SIZES`] on its grid and its largest size
+fn frame_sizes(size: FrameSizeEnum) -> Vec<(u32, u32)> {
+    match size {
+        FrameSizeEnum::Discrete(discrete) => vec![(discrete.width, discrete.height)],
+        FrameSizeEnum::Stepwise(range) => {
+            let on_grid = |value: u32, min: u32, max: u32, step: u32| {
+                (min..=max).contains(&value) && (value - min).is_multiple_of(step.max(1))
+            };
+            let mut sizes: Vec<(u32, u32)> = AUTO_SIZES
+                .into_iter()
+                .filter(|&(width, height)| {
+                    on_grid(width, range.min_width, range.max_width, range.step_width)
+                        && on_grid(
+                            height,
+                            range.min_height,
+                            range.max_height,
+                            range.step_height,
+                        )
+                })
+                .collect();
+            let largest = (range.max_width, range.max_height);
+            if !sizes.contains(&largest) {
+                sizes.push(largest);
+            }
+            sizes
+        }
+    }
+}
+
+/// Frame rates worth listing; a stepwise range contributes its fastest rate
+/// and `target` when it falls inside the range
+pub fn frame_rates(interval: FrameIntervalEnum, target: FrameRate) -> Vec<FrameRate> {
+    // Intervals are seconds per frame, the inverse of a rate
+    let rate = |fraction: v4l::Fraction| FrameRate {
+        numerator: fraction.denominator,
+        denominator: fraction.numerator,
+    };
+    match interval {
+        FrameIntervalEnum::Discrete(fraction) => vec![rate(fraction)],
+        FrameIntervalEnum::Stepwise(range) => {
+            let fastest = rate(range.min);
+            let slowest = rate(range.max);
+            let mut rates = vec![fastest];
+            let wanted = millifps(target);
+            if millifps(fastest) > wanted && millifps(slowest) <= wanted {
+                rates.push(target);
+            }
+            rates
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canned(modes: Vec<Mode>) -> DeviceReport {
+        DeviceReport {
+            device: "/dev/video0".to_string(),
+            card: "USB Video".to_string(),
+            driver: "uvcvideo".to_string(),
+            modes,
+            target_rate: TARGET_RATE,
+            format_priority: default_format_priority(),
+        }
+    }
+
+    #[test]
+    fn test_hdmi_dongle_prefers_mjpg_60_over_slow_yuyv() {
+        // MS2109 style: raw 1080p only at 5 fps, MJPG at full rate
+        let report = canned(vec![
+            Mode::new(b"YUYV", 1920, 1080, 5),
+            Mode::new(b"YUYV", 1280, 720, 10),
+            Mode::new(b"MJPG", 1920, 1080, 60),
+            Mode::new(b"MJPG", 1920, 1080, 30),
+        ]);
+        assert_eq!(report.select(), Some(Mode::new(b"MJPG", 1920, 1080, 60)));
+        let verdicts = report.verdicts();
+        assert_eq!(verdicts[2], Verdict::Selected);
+        assert_eq!(
+            verdicts[0],
+            Verdict::Rejected("60 fps available".to_string())
+        );
+        assert_eq!(verdicts[1], Verdict::Rejected("not 1920x1080".to_string()));
+    }
+
+    #[test]
+    fn test_same_rate_prefers_cheaper_fourcc() {
+        let report = canned(vec![
+            Mode::new(b"MJPG", 1920, 1080, 60),
+            Mode::new(b"YUYV", 1920, 1080, 60),
+            Mode::new(b"UYVY", 1920, 1080, 60),
+        ]);
+        assert_eq!(report.select(), Some(Mode::new(b"UYVY", 1920, 1080, 60)));
+        assert_eq!(
+            report.verdicts()[1],
+            Verdict::Rejected("UYVY comes first in capture.format_priority".to_string())
+        );
+    }
+
+    fn priority(names: &[&str]) -> Vec<FourCC> {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        parse_format_priority(&names).unwrap()
+    }
+
+    #[test]
+    fn test_isp_nv12_at_full_rate_beats_preferred_yuyv() {
+        // NV12 from the ISP reaches 60 fps, the raw formats stop at 30
+        let modes = vec![
+            Mode::new(b"YUYV", 1920, 1080, 30),
+            Mode::new(b"UYVY", 1920, 1080, 30),
+            Mode::new(b"NV12", 1920, 1080, 60),
+            Mode::new(b"NV12", 1920, 1080, 30),
+            Mode::new(b"MJPG", 1920, 1080, 60),
+        ];
+        let mut report = canned(modes);
+        let chosen = report.select().unwrap();
+        assert_eq!(
+            (
+                chosen.fourcc,
+                chosen.width,
+                chosen.height,
+                chosen.rate.numerator
+            ),
+            (FourCC::new(b"NV12"), 1920, 1080, 60)
+        );
+        assert_eq!(
+            report.verdicts()[1],
+            Verdict::Rejected("60 fps available".to_string())
+        );
+
+        // At 30 fps every format gets there; the priority decides
+        report.target_rate = FrameRate {
+            numerator: 30,
+            denominator: 1,
+        };
+        assert_eq!(report.select(), Some(Mode::new(b"UYVY", 1920, 1080, 30)));
+        report.format_priority = priority(&["NV12", "UYVY", "YUYV", "MJPG"]);
+        assert_eq!(report.select(), Some(Mode::new(b"NV12", 1920, 1080, 30)));
+        assert_eq!(
+            report.verdicts()[1],
+            Verdict::Rejected("NV12 comes first in capture.format_priority".to_string())
+        );
+    }
+
+    #[test]
+    fn test_formats_left_out_of_the_priority_are_skipped() {
+        let mut report = canned(vec![
+            Mode::new(b"MJPG", 1920, 1080, 60),
+            Mode::new(b"YUYV", 1920, 1080, 30),
+            Mode::new(b"YUYV", 1280, 720, 60),
+        ]);
+        report.format_priority = priority(&["yuyv"]);
+        // Without MJPG, 1080p at 30 beats 720p at 60: size comes first
+        assert_eq!(report.select(), Some(Mode::new(b"YUYV", 1920, 1080, 30)));
+        assert_eq!(
+            report.verdicts()[0],
+            Verdict::Rejected("MJPG is not in capture.format_priority".to_string())
+        );
+
+        report.format_priority = priority(&["NV12"]);
+        assert_eq!(report.select(), None);
+    }
+
+    #[test]
+    fn test_parse_format_priority() {
+        let names = |list: &[&str]| list.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            parse_format_priority(&names(&["nv12", "UYVY"])).unwrap(),
+            vec![FourCC::new(b"NV12"), FourCC::new(b"UYVY")]
+        );
+        assert_eq!(
+            parse_format_priority(&names(&CONVERTIBLE_FOURCCS)).unwrap(),
+            default_format_priority()
+        );
+        let error = parse_format_priority(&names(&["H264"])).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("\"H264\" is not a convertible format"));
+        assert!(parse_format_priority(&names(&["NV12", "nv12"])).is_err());
+        assert!(parse_format_priority(&names(&["NV1"])).is_err());
+        assert!(parse_format_priority(&[]).is_err());
+    }
+
+    #[test]
+    fn test_size_fallback_and_rate_cap() {
+        // No 1080p: the largest size below it wins over 4K
+        let report = canned(vec![
+            Mode::new(b"YUYV", 3840, 2160, 30),
+            Mode::new(b"YUYV", 1280, 720, 60),
+            Mode::new(b"YUYV", 640, 480, 60),
+        ]);
+        assert_eq!(report.select(), Some(Mode::new(b"YUYV", 1280, 720, 60)));
+
+        // Rates above the target count as the target, the closest one wins
+        let report = canned(vec![
+            Mode::new(b"YUYV", 1920, 1080, 120),
+            Mode::new(b"YUYV", 1920, 1080, 60),
+        ]);
+        assert_eq!(report.select(), Some(Mode::new(b"YUYV", 1920, 1080, 60)));
+    }
+
+    #[test]
+    fn test_4k_at_full_rate_is_the_target() {
+        // HDMI capture card with 4K60: the largest full-rate size wins
+        let report = canned(vec![
+            Mode::new(b"YUYV", 1920, 1080, 60),
+            Mode::new(b"YUYV", 2560, 1440, 60),
+            Mode::new(b"YUYV", 3840, 2160, 60),
+        ]);
+        assert_eq!(report.target_size(), (3840, 2160));
+        assert_eq!(report.select(), Some(Mode::new(b"YUYV", 3840, 2160, 60)));
+        assert_eq!(
+            report.verdicts()[0],
+            Verdict::Rejected("not 3840x2160".to_string())
+        );
+
+        // 4K only at 30: 1440p60 is the largest size at full rate
+        let report = canned(vec![
+            Mode::new(b"NV12", 3840, 2160, 30),
+            Mode::new(b"NV12", 2560, 1440, 60),
+            Mode::new(b"NV12", 1920, 1080, 60),
+        ]);
+        assert_eq!(report.select(), Some(Mode::new(b"NV12", 2560, 1440, 60)));
+
+        // 59.94 counts as full rate for a 60 fps target
+        let ntsc = Mode {
+            rate: FrameRate {
+                numerator: 60000,
+                denominator: 1001,
+            },
+            ..Mode::new(b"UYVY", 3840, 2160, 0)
+        };
+        let report = canned(vec![Mode::new(b"UYVY", 1920, 1080, 60), ntsc]);
+        assert_eq!(report.select(), Some(ntsc));
+
+        // 4K60 only in a format left out of the priority doesn't count
+        let mut report = canned(vec![
+            Mode::new(b"MJPG", 3840, 2160, 60),
+            Mode::new(b"YUYV", 3840, 2160, 30),
+            Mode::new(b"YUYV", 1920, 1080, 60),
+        ]);
+        report.format_priority = priority(&["YUYV"]);
+        assert_eq!(report.target_size(), (1920, 1080));
+        assert_eq!(report.select(), Some(Mode::new(b"YUYV", 1920, 1080, 60)));
+    }
+
+    #[test]
+    fn test_ntsc_rate_and_unsupported_fourcc() {
+        let ntsc = Mode {
+            rate: FrameRate {
+                numerator: 60000,
+                denominator: 1001,
+            },
+            ..Mode::new(b"YUYV", 1920, 1080, 0)
+        };
+        let report = canned(vec![
+            Mode::new(b"H264", 1920, 1080, 60),
+            Mode::new(b"YUYV", 1920, 1080, 30),
+            ntsc,
+        ]);
+        assert_eq!(report.select(), Some(ntsc));
+        assert_eq!(
+            report.verdicts()[0],
+            Verdict::Rejected("no NDI conversion for H264".to_string())
+        );
+
+        let report = canned(vec![Mode::new(b"H264", 1920, 1080, 60)]);
+        assert_eq!(report.select(), None);
+    }
+
+    #[test]
+    fn test_best_rate_picks_highest_up_to_target() {
+        let rate = |numerator, denominator| FrameRate {
+            numerator,
+            denominator,
+        };
+        let ntsc = rate(60000, 1001);
+        // Intervals as a UVC dongle lists them, slowest first
+        let offered = [rate(5, 1), rate(30000, 1001), ntsc, rate(25, 1)];
+        assert_eq!(best_rate(&offered, TARGET_RATE), Some(ntsc));
+        assert_eq!(best_rate(&offered, rate(30, 1)), Some(rate(30000, 1001)));
+        assert_eq!(best_rate(&offered, rate(25, 1)), Some(rate(25, 1)));
+
+        // Only faster rates: the closest one
+        let fast = [rate(120, 1), rate(60, 1), rate(100, 1)];
+        assert_eq!(best_rate(&fast, rate(50, 1)), Some(rate(60, 1)));
+        assert_eq!(best_rate(&[], TARGET_RATE), None);
+
+        // 60 and 59.94 both offered: exact when asked for, else the higher
+        let both = [rate(60, 1), ntsc, rate(50, 1)];
+        assert_eq!(best_rate(&both, ntsc), Some(ntsc));
+        assert_eq!(best_rate(&both, TARGET_RATE), Some(rate(60, 1)));
+    }
+
+    #[test]
+    fn test_select_follows_target_rate() {
+        let ntsc = Mode {
+            rate: FrameRate {
+                numerator: 60000,
+                denominator: 1001,
+            },
+            ..Mode::new(b"YUYV", 1920, 1080, 0)
+        };
+        let mut report = canned(vec![
+            Mode::new(b"YUYV", 1920, 1080, 60),
+            ntsc,
+            Mode::new(b"YUYV", 1920, 1080, 50),
+        ]);
+        assert_eq!(report.select(), Some(Mode::new(b"YUYV", 1920, 1080, 60)));
+
+        report.target_rate = ntsc.rate;
+        assert_eq!(report.select(), Some(ntsc));
+        assert_eq!(
+            report.verdicts()[0],
+            Verdict::Rejected("59.94 fps is closer to 59.94 fps".to_string())
+        );
+
+        report.target_rate = FrameRate {
+            numerator: 50,
+            denominator: 1,
+        };
+        assert_eq!(report.select(), Some(Mode::new(b"YUYV", 1920, 1080, 50)));
+    }
+
+    #[test]
+    fn test_table_and_json_output() {
+        let mut report = canned(vec![
+            Mode::new(b"YUYV", 1920, 1080, 60),
+            Mode::new(b"YUYV", 1920, 1080, 30),
+        ]);
+        report.card = "Cam \"1\"".to_string();
+
+        let table = report.to_table();
+        assert!(table.contains("* YUYV     1920x1080       60  selected"));
+        assert!(table.contains("rejected: 60 fps available"));
+
+        let json = report.to_json();
+        assert!(json.starts_with("{\"device\":\"/dev/video0\",\"card\":\"Cam \\\"1\\\"\""));
+        assert!(json.contains(
+            "{\"fourcc\":\"YUYV\",\"width\":1920,\"height\":1080,\"fps\":60,\
+             \"numerator\":60,\"denominator\":1,\"selected\":true,\"rejected\":null}"
+        ));
+        assert_eq!(report.to_json().matches("\"selected\":false").count(), 1);
+    }
+
+    #[test]
+    fn test_stepwise_sizes_and_rates() {
+        let sizes = frame_sizes(FrameSizeEnum::Stepwise(v4l::framesize::Stepwise {
+            min_width: 320,
+            max_width: 3840,
+            step_width: 8,
+            min_height: 240,
+            max_height: 2160,
+            step_height: 8,
+        }));
+        assert_eq!(sizes, vec![(3840, 2160), (2560, 1440), (1920, 1080)]);
+        let sizes = frame_sizes(FrameSizeEnum::Stepwise(v4l::framesize::Stepwise {
+            min_width: 320,
+            max_width: 2048,
+            step_width: 16,
+            min_height: 240,
+            max_height: 1536,
+            step_height: 16,
+        }));
+        assert_eq!(sizes, vec![(2048, 1536)]);
+
+        let rates = frame_rates(
+            FrameIntervalEnum::Stepwise(v4l::frameinterval::Stepwise {
+                min: v4l::Fraction::new(1, 120),
+                max: v4l::Fraction::new(1, 1),
+                step: v4l::Fraction::new(1, 120),
+            }),
+            TARGET_RATE,
+        );
+        assert_eq!(millifps(rates[0]), 120_000);
+        assert_eq!(rates[1], TARGET_RATE);
+    }
+
+    #[test]
+    fn test_format_fps() {
+        assert_eq!(format_fps(TARGET_RATE), "60");
+        let ntsc = FrameRate {
+            numerator: 30000,
+            denominator: 1001,
+        };
+        assert_eq!(format_fps(ntsc), "29.97");
+    }
+}
@@ -0,0 +1,498 @@
+//! Frame processing hooks
+//!
+//! A chain of [`FrameProcessor`]s runs on every frame after conversion to
+//! UYVY and before the NDI send, e.g. to watermark a station logo. Two
+//! processors are built in and configured from `[ndi]`: a static image
+//! overlay and a wall-clock timestamp burn-in. Embedding applications add
+//! their own with `PipelineBuilder::processor`.
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::color_range::ColorRange;
+use crate::compositor::{mix, scale_alpha};
+use crate::config::OverlayConfig;
+use crate::ndi::{convert_bgra_to_uyvy_into, uyvy_frame_size};
+
+/// What to do with a frame after a processor ran
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameAction {
+    /// Hand the frame to the next processor, then send it
+    Send,
+    /// Drop the frame; later processors don't run
+    Skip,
+}
+
+/// Per-frame hook on the UYVY frame about to be sent
+pub trait FrameProcessor: Send {
+    /// Short name for logs
+    fn name(&self) -> &str;
+
+    /// Modify `uyvy` in place. The frame is tightly packed: each row is
+    /// `uyvy_frame_size(width, 1)` bytes.
+    fn process(&mut self, uyvy: &mut [u8], width: u32, height: u32) -> FrameAction;
+}
+
+/// Processors run in order on every frame
+#[derive(Default)]
+pub struct ProcessorChain {
+    processors: Vec<Box<dyn FrameProcessor>>,
+}
+
+/// Chain shared by the sender and every sender recreated from its settings
+pub type SharedProcessors = Arc<Mutex<ProcessorChain>>;
+
+impl ProcessorChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, processor: Box<dyn FrameProcessor>) {
+        tracing::info!("Frame processor added: {}", processor.name());
+        self.processors.push(processor);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.processors.len()
+    }
+
+    /// Run the processors until one skips the frame
+    pub fn run(&mut self, uyvy: &mut [u8], width: u32, height: u32) -> FrameAction {
+        for processor in &mut self.processors {
+            if processor.process(uyvy, width, height) == FrameAction::Skip {
+                return FrameAction::Skip;
+            }
+        }
+        FrameAction::Send
+    }
+}
+
+impl std::fmt::Debug for ProcessorChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.processors.iter().map(|p| p.name()))
+            .finish()
+    }
+}
+
+// =============================================================================
+// Image Overlay
+// =============================================================================
+
+/// Static image composited onto every frame, e.g. a station logo
+pub struct ImageOverlay {
+    /// The image converted to UYVY
+    uyvy: Vec<u8>,
+    /// Per-pixel alpha with the overall opacity applied
+    alpha: Vec<u8>,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+}
+
+impl ImageOverlay {
+    /// Overlay a `width`x`height` BGRA image with its top-left corner at
+    /// `x`,`y` (`x` rounded down to even). `opacity` (0.0-1.0) scales the
+    /// image's own alpha.
+    pub fn from_bgra(
+        bgra: &[u8],
+        width: u32,
+        height: u32,
+        x: u32,
+        y: u32,
+        opacity: f32,
+    ) -> Result<Self> {
+        let expected = width as usize * height as usize * 4;
+        if width == 0 || height == 0 || bgra.len() != expected {
+            bail!(
+                "Overlay image is {} bytes, expected {} for {}x{} BGRA",
+                bgra.len(),
+                expected,
+                width,
+                height
+            );
+        }
+        let mut uyvy = vec![0; uyvy_frame_size(width as usize, height as usize)];
+        convert_bgra_to_uyvy_into(
+            bgra,
+            width as usize,
+            height as usize,
+            width as usize * 4,
+            ColorRange::Limited,
+            &mut uyvy,
+        );
+        let alpha = bgra
+            .chunks_exact(4)
+            .map(|px| scale_alpha(px[3], opacity))
+            .collect();
+        Ok(Self {
+            uyvy,
+            alpha,
+            width,
+            height,
+            x: x & !1,
+            y,
+        })
+    }
+
+    /// Load a raw BGRA file, e.g. from
+    /// `convert logo.png -depth 8 bgra:logo.bgra`
+    pub fn load(
+        path: &Path,
+        width: u32,
+        height: u32,
+        x: u32,
+        y: u32,
+        opacity: f32,
+    ) -> Result<Self> {
+        let bgra = std::fs::read(path)
+            .with_context(|| format!("Failed to read overlay {}", path.display()))?;
+        Self::from_bgra(&bgra, width, height, x, y, opacity)
+            .with_context(|| format!("Invalid overlay {}", path.display()))
+    }
+
+    pub fn from_config(config: &OverlayConfig) -> Result<Self> {
+        Self::load(
+            Path::new(&config.image),
+            config.width,
+            config.height,
+            config.x,
+            config.y,
+            config.alpha,
+        )
+    }
+}
+
+impl FrameProcessor for ImageOverlay {
+    fn name(&self) -> &str {
+        "image overlay"
+    }
+
+    fn process(&mut self, uyvy: &mut [u8], width: u32, height: u32) -> FrameAction {
+        let frame_row = uyvy_frame_size(width as usize, 1);
+        let image_row = uyvy_frame_size(self.width as usize, 1);
+        // Whole pixel pairs inside the frame
+        let pairs = (width.min(self.x + self.width).saturating_sub(self.x) / 2) as usize;
+        let rows = height.min(self.y + self.height).saturating_sub(self.y) as usize;
+
+        for row in 0..rows {
+            let dst_start = (self.y as usize + row) * frame_row + self.x as usize * 2;
+            let Some(dst) = uyvy.get_mut(dst_start..dst_start + pairs * 4) else {
+                break;
+            };
+            let src = &self.uyvy[row * image_row..];
+            let alpha = &self.alpha[row * self.width as usize..];
+            for (pair, (d, s)) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)).enumerate() {
+                let a0 = alpha[pair * 2] as u32;
+                let a1 = alpha.get(pair * 2 + 1).copied().unwrap_or(0) as u32;
+                let ac = (a0 + a1).div_ceil(2);
+                d[0] = mix(d[0], s[0], ac);
+                d[1] = mix(d[1], s[1], a0);
+                d[2] = mix(d[2], s[2], ac);
+                d[3] = mix(d[3], s[3], a1);
+            }
+        }
+        FrameAction::Send
+    }
+}
+
+// =============================================================================
+// Timestamp Burn-in
+// =============================================================================
+
+/// 3x5 glyphs for "0123456789:.", "A"-"Z", "/" and "-", one row per byte,
+/// bit 2 = left column
+const GLYPHS: [[u8; 5]; 40] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111], // 0
+    [0b010, 0b110, 0b010, 0b010, 0b111], // 1
+    [0b111, 0b001, 0b111, 0b100, 0b111], // 2
+    [0b111, 0b001, 0b111, 0b001, 0b111], // 3
+    [0b101, 0b101, 0b111, 0b001, 0b001], // 4
+    [0b111, 0b100, 0b111, 0b001, 0b111], // 5
+    [0b111, 0b100, 0b111, 0b101, 0b111], // 6
+    [0b111, 0b001, 0b001, 0b001, 0b001], // 7
+    [0b111, 0b101, 0b111, 0b101, 0b111], // 8
+    [0b111, 0b101, 0b111, 0b001, 0b111], // 9
+    [0b000, 0b010, 0b000, 0b010, 0b000], // :
+    [0b000, 0b000, 0b000, 0b000, 0b010], // .
+    [0b010, 0b101, 0b111, 0b101, 0b101], // A
+    [0b110, 0b101, 0b110, 0b101, 0b110], // B
+    [0b011, 0b100, 0b100, 0b100, 0b011], // C
+    [0b110, 0b101, 0b101, 0b101, 0b110], // D
+    [0b111, 0b100, 0b110, 0b100, 0b111], // E
+    [0b111, 0b100, 0b110, 0b100, 0b100], // F
+    [0b011, 0b100, 0b101, 0b101, 0b011], // G
+    [0b101, 0b101, 0b111, 0b101, 0b101], // H
+    [0b111, 0b010, 0b010, 0b010, 0b111], // I
+    [0b001, 0b001, 0b001, 0b101, 0b010], // J
+    [0b101, 0b101, 0b110, 0b101, 0b101], // K
+    [0b100, 0b100, 0b100, 0b100, 0b111], // L
+    [0b101, 0b111, 0b111, 0b101, 0b101], // M
+    [0b110, 0b101, 0b101, 0b101, 0b101], // N
+    [0b010, 0b101, 0b101, 0b101, 0b010], // O
+    [0b110, 0b101, 0b110, 0b100, 0b100], // P
+    [0b010, 0b101, 0b101, 0b110, 0b011], // Q
+    [0b110, 0b101, 0b110, 0b101, 0b101], // R
+    [0b011, 0b100, 0b010, 0b001, 0b110], // S
+    [0b111, 0b010, 0b010, 0b010, 0b010], // T
+    [0b101, 0b101, 0b101, 0b101, 0b111], // U
+    [0b101, 0b101, 0b101, 0b101, 0b010], // V
+    [0b101, 0b101, 0b111, 0b111, 0b101], // W
+    [0b101, 0b101, 0b010, 0b101, 0b101], // X
+    [0b101, 0b101, 0b010, 0b010, 0b010], // Y
+    [0b111, 0b001, 0b010, 0b100, 0b111], // Z
+    [0b001, 0b001, 0b010, 0b100, 0b100], // /
+    [0b000, 0b000, 0b111, 0b000, 0b000], // -
+];
+
+const BLACK: u8 = 16;
+const WHITE: u8 = 235;
+const NEUTRAL_CHROMA: u8 = 128;
+
+/// Wall-clock UTC time as "HH:MM:SS.mmm"
+pub(crate) fn wall_clock() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = since_epoch.as_secs() % 86_400;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Burns the wall-clock time into the top-left corner, white on a black
+/// box, for glass-to-glass latency measurements against a reference clock
+pub struct TimestampBurnIn {
+    clock: fn() -> String,
+}
+
+impl TimestampBurnIn {
+    pub fn new() -> Self {
+        Self { clock: wall_clock }
+    }
+
+    /// Size of the black box `render` draws for `text`, an even width
+    pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+        let width = (text.len() as u32 * 4 * scale + scale).div_ceil(2) * 2;
+        (width, 7 * scale)
+    }
+
+    /// Render `text` (digits, letters, ':', '.', '/' and '-') with font
+    /// pixels of `scale`x`scale` at `x`,`y`; other characters are blank
+    pub fn render(
+        uyvy: &mut [u8],
+        width: u32,
+        height: u32,
+        text: &str,
+        x: u32,
+        y: u32,
+        scale: u32,
+    ) {
+        let (box_width, box_height) = Self::text_size(text, scale);
+        let frame_row = uyvy_frame_size(width as usize, 1);
+        let x = x & !1;
+
+        for row in 0..box_height.min(height.saturating_sub(y)) {
+            let line = (y + row) as usize * frame_row;
+            for col in 0..box_width.min(width.saturating_sub(x)) {
+                let px = x + col;
+                // Font pixel under this frame pixel, if any
+                let lit = row >= scale && row < 6 * scale && col >= scale && {
+                    let (gx, gy) = ((col - scale) / scale, (row - scale) / scale);
+                    let (index, column) = ((gx / 4) as usize, gx % 4);
+                    column < 3
+                        && text
+                            .as_bytes()
+                            .get(index)
+                            .and_then(|&c| glyph_index(c))
+                            .is_some_and(|g| GLYPHS[g][gy as usize] & (0b100 >> column) != 0)
+                };
+                let pair = line + (px as usize / 2) * 4;
+                let Some(pixel) = uyvy.get_mut(pair..pair + 4) else {
+                    continue;
+                };
+                pixel[0] = NEUTRAL_CHROMA;
+                pixel[2] = NEUTRAL_CHROMA;
+                pixel[1 + (px as usize % 2) * 2] = if lit { WHITE } else { BLACK };
+            }
+        }
+    }
+}
+
+impl Default for TimestampBurnIn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn glyph_index(c: u8) -> Option<usize> {
+    match c {
+        b'0'..=b'9' => Some((c - b'0') as usize),
+        b':' => Some(10),
+        b'.' => Some(11),
+        b'A'..=b'Z' => Some((c - b'A') as usize + 12),
+        // Lowercase shares the capitals
+        b'a'..=b'z' => Some((c - b'a') as usize + 12),
+        b'/' => Some(38),
+        b'-' => Some(39),
+        _ => None,
+    }
+}
+
+impl FrameProcessor for TimestampBurnIn {
+    fn name(&self) -> &str {
+        "timestamp burn-in"
+    }
+
+    fn process(&mut self, uyvy: &mut [u8], width: u32, height: u32) -> FrameAction {
+        // Readable from a phone camera at any resolution: ~1/30 of the height
+        let scale = (height / 200).max(1);
+        Self::render(uyvy, width, height, &(self.clock)(), 16, 16, scale);
+        FrameAction::Send
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mid-grey limited range UYVY frame
+    fn grey(width: u32, height: u32) -> Vec<u8> {
+        [128, 126].repeat(uyvy_frame_size(width as usize, height as usize) / 2)
+    }
+
+    struct Counter {
+        calls: Arc<Mutex<Vec<&'static str>>>,
+        name: &'static str,
+        action: FrameAction,
+    }
+
+    impl FrameProcessor for Counter {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn process(&mut self, uyvy: &mut [u8], _width: u32, _height: u32) -> FrameAction {
+            self.calls.lock().unwrap().push(self.name);
+            uyvy[1] = uyvy[1].wrapping_add(1);
+            self.action
+        }
+    }
+
+    #[test]
+    fn test_chain_runs_in_order_until_skip() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut chain = ProcessorChain::new();
+        for (name, action) in [
+            ("a", FrameAction::Send),
+            ("b", FrameAction::Skip),
+            ("c", FrameAction::Send),
+        ] {
+            chain.push(Box::new(Counter {
+                calls: Arc::clone(&calls),
+                name,
+                action,
+            }));
+        }
+        assert_eq!(format!("{:?}", chain), r#"["a", "b", "c"]"#);
+
+        let mut frame = grey(4, 2);
+        assert_eq!(chain.run(&mut frame, 4, 2), FrameAction::Skip);
+        assert_eq!(*calls.lock().unwrap(), ["a", "b"]);
+        assert_eq!(frame[1], 128);
+
+        let mut empty = ProcessorChain::new();
+        assert!(empty.is_empty());
+        assert_eq!(empty.run(&mut frame, 4, 2), FrameAction::Send);
+    }
+
+    #[test]
+    fn test_mix_alpha_math() {
+        assert_eq!(mix(16, 235, 255), 235);
+        assert_eq!(mix(16, 235, 0), 16);
+        assert_eq!(mix(0, 255, 128), 128);
+        assert_eq!(mix(200, 100, 51), 180);
+    }
+
+    #[test]
+    fn test_overlay_composites_with_alpha() {
+        // 2x1 white image: left pixel opaque, right pixel transparent
+        let bgra = [255, 255, 255, 255, 255, 255, 255, 0];
+        let mut overlay = ImageOverlay::from_bgra(&bgra, 2, 1, 2, 1, 1.0).unwrap();
+        let mut frame = grey(4, 2);
+        assert_eq!(overlay.process(&mut frame, 4, 2), FrameAction::Send);
+
+        // Row 0 and the first pair of row 1 are untouched
+        assert_eq!(&frame[..12], &grey(4, 2)[..12]);
+        // Y0 is white, Y1 keeps the frame, chroma mixes at half coverage
+        assert_eq!(&frame[12..16], [128, 235, 128, 126]);
+
+        // Half opacity halves the coverage
+        let mut overlay = ImageOverlay::from_bgra(&bgra, 2, 1, 0, 0, 0.5).unwrap();
+        let mut frame = grey(4, 2);
+        overlay.process(&mut frame, 4, 2);
+        assert_eq!(frame[1], mix(126, 235, 128));
+        assert_eq!(frame[3], 126);
+    }
+
+    #[test]
+    fn test_overlay_clips_to_frame() {
+        let bgra = [255u8; 4 * 4 * 4];
+        let mut overlay = ImageOverlay::from_bgra(&bgra, 4, 4, 3, 1, 1.0).unwrap();
+        let mut frame = grey(4, 2);
+        overlay.process(&mut frame, 4, 2);
+        // x rounds down to 2: only the right pair of the bottom row is covered
+        assert_eq!(&frame[..12], &grey(4, 2)[..12]);
+        assert_eq!(&frame[12..16], [128, 235, 128, 235]);
+
+        // Entirely outside the frame
+        let mut overlay = ImageOverlay::from_bgra(&bgra, 4, 4, 8, 8, 1.0).unwrap();
+        let mut frame = grey(4, 2);
+        overlay.process(&mut frame, 4, 2);
+        assert_eq!(frame, grey(4, 2));
+    }
+
+    #[test]
+    fn test_overlay_rejects_wrong_size() {
+        assert!(ImageOverlay::from_bgra(&[0; 12], 2, 2, 0, 0, 1.0).is_err());
+        assert!(ImageOverlay::from_bgra(&[], 0, 0, 0, 0, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_timestamp_burn_in_renders_text() {
+        let (width, height) = (40, 8);
+        let mut frame = grey(width, height);
+        TimestampBurnIn::render(&mut frame, width, height, "1", 0, 0, 1);
+        let luma = |x: usize, y: usize| frame[y * width as usize * 2 + x * 2 + 1];
+        // Box border is black, the "1" stem (glyph column 1) is white
+        assert_eq!(luma(0, 0), BLACK);
+        assert_eq!(luma(2, 1), WHITE);
+        assert_eq!(luma(2, 3), WHITE);
+        assert_eq!(luma(1, 3), BLACK);
+        // Outside the box the frame is untouched
+        assert_eq!(luma(10, 0), 126);
+        assert_eq!(luma(0, 7), 126);
+
+        // Clock format
+        let text = wall_clock();
+        assert_eq!(text.len(), 12);
+        assert!(text.bytes().all(|c| glyph_index(c).is_some()));
+
+        // Letters in either case, and a blank for anything else
+        assert_eq!(glyph_index(b'a'), glyph_index(b'A'));
+        assert_eq!(glyph_index(b'Z'), Some(37));
+        assert!(b"/dev/video0".iter().all(|&c| glyph_index(c).is_some()));
+        assert!(glyph_index(b' ').is_none());
+    }
+}
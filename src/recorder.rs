@@ -0,0 +1,556 @@
+//! Local ISO recording of the stream sent
+//!
+//! For redundancy the box can record what it sends to its own SD card or
+//! USB storage. A frame processor copies every UYVY frame as sent into a
+//! bounded channel, and a writer thread appends it to a raw segment file
+//! (`rec-<unix seconds>-<sequence>.uyvy`) and one line to the segment's
+//! index (`.idx` beside it): byte offset, send time in unix microseconds,
+//! width and height. Both files grow frame by frame, so a recording is
+//! usable up to its last indexed frame even after a power cut. A segment
+//! ends at [`SEGMENT_BYTES`], below the FAT32 file size limit.
+//!
+//! Capture never waits for the disk: frames that don't fit in the channel
+//! are dropped and counted. When the disk fills up or the recordings reach
+//! `max_disk_mb`, recording stops for the rest of the session with an
+//! error in the log and `record.stopped` in the stats.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::processing::{FrameAction, FrameProcessor};
+use crate::stats::{Component, StatsRegistry};
+use crate::threads;
+
+/// Frames queued for the writer; more are dropped
+pub const QUEUE_FRAMES: usize = 8;
+
+/// Size after which a new segment is started
+pub const SEGMENT_BYTES: u64 = 1 << 30;
+
+/// First line of every index
+pub const INDEX_HEADER: &str = "# camera-box uyvy index v1: offset unix_us width height";
+
+const SEGMENT_PREFIX: &str = "rec-";
+
+/// Why recording stopped
+#[derive(Debug, thiserror::Error)]
+pub enum RecordError {
+    #[error("Disk full in {dir}")]
+    DiskFull { dir: String },
+    #[error("Recordings reached the {limit_mb} MB limit")]
+    LimitReached { limit_mb: u64 },
+    #[error("Recording write failed in {dir}")]
+    Write {
+        dir: String,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl RecordError {
+    fn io(dir: &Path, source: io::Error) -> Self {
+        let dir = dir.display().to_string();
+        if source.raw_os_error() == Some(libc::ENOSPC) {
+            RecordError::DiskFull { dir }
+        } else {
+            RecordError::Write { dir, source }
+        }
+    }
+}
+
+// =============================================================================
+// Segments
+// =============================================================================
+
+/// One frame in a segment index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexEntry {
+    /// Byte offset of the frame in the segment
+    pub offset: u64,
+    pub unix_us: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl IndexEntry {
+    /// The entry as an index line, without the newline
+    pub fn line(&self) -> String {
+        format!(
+            "{} {} {} {}",
+            self.offset, self.unix_us, self.width, self.height
+        )
+    }
+
+    /// Parse an index line; None for the header, blank or malformed lines
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_ascii_whitespace().map(str::parse::<u64>);
+        let mut next = || fields.next()?.ok();
+        let entry = Self {
+            offset: next()?,
+            unix_us: next()?,
+            width: u32::try_from(next()?).ok()?,
+            height: u32::try_from(next()?).ok()?,
+        };
+        Some(entry)
+    }
+}
+
+/// Read the frames of a segment index; a torn last line is skipped
+pub fn read_index(path: &Path) -> io::Result<Vec<IndexEntry>> {
+    let text = fs::read_to_string(path)?;
+    Ok(text.lines().filter_map(IndexEntry::parse).collect())
+}
+
+/// Index path of a segment
+pub fn index_path(segment: &Path) -> PathBuf {
+    segment.with_extension("idx")
+}
+
+fn unix_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0)
+}
+
+/// Whether `path` is a segment or index written here
+fn is_recording(path: &Path) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    name.starts_with(SEGMENT_PREFIX) && (name.ends_with(".uyvy") || name.ends_with(".idx"))
+}
+
+struct Segment {
+    data: File,
+    index: BufWriter<File>,
+    len: u64,
+}
+
+/// Appends frames to raw UYVY segments with their indexes
+pub struct SegmentWriter {
+    dir: PathBuf,
+    segment_bytes: u64,
+    /// 0 for no limit
+    max_bytes: u64,
+    /// Bytes of the recordings in `dir`, including earlier runs'
+    used: u64,
+    current: Option<Segment>,
+    segments: Vec<PathBuf>,
+    sequence: u32,
+}
+
+impl SegmentWriter {
+    /// Record into `dir`, creating it. Recordings already there count
+    /// towards `max_bytes` (0 for no limit).
+    pub fn new(
+        dir: impl Into<PathBuf>,
+        segment_bytes: u64,
+        max_bytes: u64,
+    ) -> Result<Self, RecordError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|e| RecordError::io(&dir, e))?;
+        let mut used = 0;
+        for entry in fs::read_dir(&dir).map_err(|e| RecordError::io(&dir, e))? {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            if is_recording(&entry.path()) {
+                used += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+        }
+        Ok(Self {
+            dir,
+            segment_bytes: segment_bytes.max(1),
+            max_bytes,
+            used,
+            current: None,
+            segments: Vec::new(),
+            sequence: 0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Bytes of all recordings in the directory
+    pub fn used_bytes(&self) -> u64 {
+        self.used
+    }
+
+    /// Segments written by this writer, oldest first
+    pub fn segments(&self) -> &[PathBuf] {
+        &self.segments
+    }
+
+    /// Append one frame sent at `unix_us`, starting a new segment when the
+    /// current one would grow past the segment size
+    pub fn write_frame(
+        &mut self,
+        uyvy: &[u8],
+        width: u32,
+        height: u32,
+        unix_us: u64,
+    ) -> Result<(), RecordError> {
+        let frame_bytes = uyvy.len() as u64;
+        if self.max_bytes > 0 && self.used + frame_bytes > self.max_bytes {
+            return Err(RecordError::LimitReached {
+                limit_mb: self.max_bytes / 1_000_000,
+            });
+        }
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|s| s.len > 0 && s.len + frame_bytes > self.segment_bytes)
+        {
+            self.finish()?;
+        }
+        if self.current.is_none() {
+            self.open_segment()?;
+        }
+
+        let dir = &self.dir;
+        let segment = self.current.as_mut().expect("segment just opened");
+        let entry = IndexEntry {
+            offset: segment.len,
+            unix_us,
+            width,
+            height,
+        };
+        let line = entry.line();
+        segment
+            .data
+            .write_all(uyvy)
+            .and_then(|()| writeln!(segment.index, "{}", line))
+            .and_then(|()| segment.index.flush())
+            .map_err(|e| RecordError::io(dir, e))?;
+        segment.len += frame_bytes;
+        self.used += frame_bytes + line.len() as u64 + 1;
+        Ok(())
+    }
+
+    /// Flush and close the current segment (if any)
+    pub fn finish(&mut self) -> Result<(), RecordError> {
+        if let Some(mut segment) = self.current.take() {
+            segment
+                .index
+                .flush()
+                .map_err(|e| RecordError::io(&self.dir, e))?;
+        }
+        Ok(())
+    }
+
+    fn open_segment(&mut self) -> Result<(), RecordError> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = self.dir.join(format!(
+            "{}{:010}-{:04}.uyvy",
+            SEGMENT_PREFIX, secs, self.sequence
+        ));
+        self.sequence = self.sequence.wrapping_add(1);
+        let data = File::create(&path).map_err(|e| RecordError::io(&self.dir, e))?;
+        let mut index = File::create(index_path(&path))
+            .map(BufWriter::new)
+            .map_err(|e| RecordError::io(&self.dir, e))?;
+        writeln!(index, "{}", INDEX_HEADER).map_err(|e| RecordError::io(&self.dir, e))?;
+        self.used += INDEX_HEADER.len() as u64 + 1;
+        tracing::info!("Recording to {}", path.display());
+        self.current = Some(Segment {
+            data,
+            index,
+            len: 0,
+        });
+        self.segments.push(path);
+        Ok(())
+    }
+}
+
+impl Drop for SegmentWriter {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+// =============================================================================
+// Recorder
+// =============================================================================
+
+/// Counters of the recorder
+#[derive(Debug, Default)]
+pub struct RecorderStats {
+    pub frames: AtomicU64,
+    pub bytes: AtomicU64,
+    /// Frames the writer could not keep up with
+    pub dropped: AtomicU64,
+    /// Set once recording stopped on a full disk or the size limit
+    pub stopped: AtomicBool,
+}
+
+impl RecorderStats {
+    /// Report the counters as "record"
+    pub fn register(self: &Arc<Self>, registry: &StatsRegistry) {
+        let stopped = Arc::clone(self);
+        registry.register(
+            Component::new("record")
+                .counter("frames", self, |s| &s.frames)
+                .counter("bytes", self, |s| &s.bytes)
+                .counter("dropped", self, |s| &s.dropped)
+                .gauge("stopped", move || {
+                    stopped.stopped.load(Ordering::Relaxed) as u8 as f64
+                }),
+        );
+    }
+}
+
+struct RecordedFrame {
+    uyvy: Vec<u8>,
+    width: u32,
+    height: u32,
+    unix_us: u64,
+}
+
+/// Frame processor handing frames to the writer thread; never changes or
+/// drops a frame. Dropping it finishes the recording.
+pub struct Recorder {
+    frames: Option<SyncSender<RecordedFrame>>,
+    /// Buffers of written frames, reused for the next copies
+    free: Receiver<Vec<u8>>,
+    stats: Arc<RecorderStats>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    /// Start the writer thread on `writer`
+    pub fn spawn(writer: SegmentWriter) -> Self {
+        let (frames_tx, frames_rx) = sync_channel(QUEUE_FRAMES);
+        let (free_tx, free_rx) = sync_channel(QUEUE_FRAMES);
+        let stats = Arc::new(RecorderStats::default());
+        let writer_stats = Arc::clone(&stats);
+        let writer = threads::spawn(threads::RECORD, move || {
+            run_writer(writer, frames_rx, free_tx, &writer_stats)
+        });
+        Self {
+            frames: Some(frames_tx),
+            free: free_rx,
+            stats,
+            writer: Some(writer),
+        }
+    }
+
+    pub fn stats(&self) -> Arc<RecorderStats> {
+        Arc::clone(&self.stats)
+    }
+}
+
+impl FrameProcessor for Recorder {
+    fn name(&self) -> &str {
+        "recorder"
+    }
+
+    fn process(&mut self, uyvy: &mut [u8], width: u32, height: u32) -> FrameAction {
+        let Some(frames) = &self.frames else {
+            return FrameAction::Send;
+        };
+        if self.stats.stopped.load(Ordering::Relaxed) {
+            return FrameAction::Send;
+        }
+        let mut buffer = self.free.try_recv().unwrap_or_default();
+        buffer.clear();
+        buffer.extend_from_slice(uyvy);
+        let frame = RecordedFrame {
+            uyvy: buffer,
+            width,
+            height,
+            unix_us: unix_micros(),
+        };
+        match frames.try_send(frame) {
+            Ok(()) | Err(TrySendError::Disconnected(_)) => {}
+            Err(TrySendError::Full(_)) => {
+                self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        FrameAction::Send
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer drain the queue and finish
+        self.frames = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn run_writer(
+    mut writer: SegmentWriter,
+    frames: Receiver<RecordedFrame>,
+    free: SyncSender<Vec<u8>>,
+    stats: &RecorderStats,
+) {
+    for frame in frames {
+        if let Err(e) = writer.write_frame(&frame.uyvy, frame.width, frame.height, frame.unix_us) {
+            stats.stopped.store(true, Ordering::Relaxed);
+            tracing::error!(
+                "RECORDING STOPPED: {} ({} frames, {} MB recorded this session)",
+                e,
+                stats.frames.load(Ordering::Relaxed),
+                stats.bytes.load(Ordering::Relaxed) / 1_000_000
+            );
+            break;
+        }
+        stats.frames.fetch_add(1, Ordering::Relaxed);
+        stats
+            .bytes
+            .fetch_add(frame.uyvy.len() as u64, Ordering::Relaxed);
+        let _ = free.try_send(frame.uyvy);
+    }
+    if let Err(e) = writer.finish() {
+        tracing::warn!("Failed to finish the recording: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32, fill: u8) -> Vec<u8> {
+        vec![fill; width as usize * height as usize * 2]
+    }
+
+    #[test]
+    fn test_index_entry_round_trip() {
+        let entry = IndexEntry {
+            offset: 8_294_400,
+            unix_us: 1_760_000_000_123_456,
+            width: 1920,
+            height: 1080,
+        };
+        assert_eq!(entry.line(), "8294400 1760000000123456 1920 1080");
+        assert_eq!(IndexEntry::parse(&entry.line()), Some(entry));
+        assert_eq!(IndexEntry::parse(INDEX_HEADER), None);
+        assert_eq!(IndexEntry::parse(""), None);
+        // Torn by a power cut
+        assert_eq!(IndexEntry::parse("8294400 17600000"), None);
+        assert_eq!(IndexEntry::parse("0 0 4294967296 1"), None);
+    }
+
+    #[test]
+    fn test_segments_and_index() {
+        let dir = tempfile::tempdir().unwrap();
+        // Room for two 4x2 frames (16 bytes each) per segment
+        let mut writer = SegmentWriter::new(dir.path(), 32, 0).unwrap();
+        for (i, fill) in [1u8, 2, 3].into_iter().enumerate() {
+            writer
+                .write_frame(&frame(4, 2, fill), 4, 2, 1000 + i as u64)
+                .unwrap();
+        }
+        writer.write_frame(&frame(2, 2, 4), 2, 2, 1003).unwrap();
+        writer.finish().unwrap();
+
+        let segments = writer.segments().to_vec();
+        assert_eq!(segments.len(), 2);
+        assert!(segments[0] < segments[1]);
+        let first = fs::read(&segments[0]).unwrap();
+        assert_eq!(first, [frame(4, 2, 1), frame(4, 2, 2)].concat());
+        let index = read_index(&index_path(&segments[0])).unwrap();
+        assert_eq!(
+            index,
+            [
+                IndexEntry {
+                    offset: 0,
+                    unix_us: 1000,
+                    width: 4,
+                    height: 2
+                },
+                IndexEntry {
+                    offset: 16,
+                    unix_us: 1001,
+                    width: 4,
+                    height: 2
+                },
+            ]
+        );
+        let text = fs::read_to_string(index_path(&segments[1])).unwrap();
+        assert_eq!(text, format!("{}\n0 1002 4 2\n16 1003 2 2\n", INDEX_HEADER));
+
+        let on_disk: u64 = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().metadata().unwrap().len())
+            .sum();
+        assert_eq!(writer.used_bytes(), on_disk);
+    }
+
+    #[test]
+    fn test_limit_counts_earlier_recordings() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("rec-0000000001-0000.uyvy"), [0; 100]).unwrap();
+        fs::write(dir.path().join("notes.txt"), [0; 1000]).unwrap();
+
+        let mut writer = SegmentWriter::new(dir.path(), SEGMENT_BYTES, 190).unwrap();
+        assert_eq!(writer.used_bytes(), 100);
+        // 100 + the index header and line + 16 bytes of frame fit, another
+        // 16 don't
+        writer.write_frame(&frame(4, 2, 1), 4, 2, 0).unwrap();
+        assert_eq!(writer.used_bytes(), 180);
+        assert!(matches!(
+            writer.write_frame(&frame(4, 2, 1), 4, 2, 0),
+            Err(RecordError::LimitReached { .. })
+        ));
+    }
+
+    #[test]
+    fn test_recorder_records_until_stopped() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = SegmentWriter::new(dir.path(), SEGMENT_BYTES, 0).unwrap();
+        let mut recorder = Recorder::spawn(writer);
+        let stats = recorder.stats();
+        let mut sent = frame(4, 2, 7);
+        for written in 1..=3 {
+            assert_eq!(recorder.process(&mut sent, 4, 2), FrameAction::Send);
+            // Let the writer keep up
+            while stats.frames.load(Ordering::Relaxed) < written {
+                std::thread::yield_now();
+            }
+        }
+        drop(recorder);
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.bytes.load(Ordering::Relaxed), 3 * 16);
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 0);
+        assert!(!stats.stopped.load(Ordering::Relaxed));
+
+        let segment = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .find(|p| p.extension().is_some_and(|e| e == "uyvy"))
+            .unwrap();
+        assert_eq!(read_index(&index_path(&segment)).unwrap().len(), 3);
+        assert_eq!(fs::read(&segment).unwrap(), frame(4, 2, 7).repeat(3));
+    }
+
+    #[test]
+    fn test_recorder_stops_at_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = SegmentWriter::new(dir.path(), SEGMENT_BYTES, 10).unwrap();
+        let mut recorder = Recorder::spawn(writer);
+        let stats = recorder.stats();
+        let mut sent = frame(4, 2, 7);
+        recorder.process(&mut sent, 4, 2);
+        while !stats.stopped.load(Ordering::Relaxed) {
+            std::thread::yield_now();
+        }
+        // Frames keep flowing to NDI untouched
+        assert_eq!(recorder.process(&mut sent, 4, 2), FrameAction::Send);
+        assert_eq!(sent, frame(4, 2, 7));
+        drop(recorder);
+        assert_eq!(stats.frames.load(Ordering::Relaxed), 0);
+    }
+}
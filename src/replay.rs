@@ -0,0 +1,560 @@
+//! Instant replay of the last frames sent
+//!
+//! When a glitch shows up on air, the frames around it are what matters. A
+//! frame processor keeps the last N frames as 480p BGRA thumbnails in a ring
+//! (full frames would be 4 MB each) and a dump writes them out as numbered
+//! PNGs, triggered by SIGUSR2 or the control socket's `dump-ring`.
+//!
+//! Thumbnails are sampled nearest-neighbour straight from the UYVY frame
+//! into buffers reused from slot to slot. When that takes longer than
+//! [`THUMBNAIL_BUDGET`], only every other frame is kept.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::color_range::ColorRange;
+use crate::display::yuv_to_bgr;
+use crate::processing::{FrameAction, FrameProcessor};
+
+/// Thumbnail height; smaller frames are kept at their own size
+pub const THUMBNAIL_HEIGHT: u32 = 480;
+
+/// Average thumbnailing time above which only every other frame is kept
+pub const THUMBNAIL_BUDGET: Duration = Duration::from_millis(2);
+
+/// Weight of the newest measurement in the thumbnailing time average
+const COST_SMOOTHING: f64 = 0.05;
+
+// =============================================================================
+// Thumbnails
+// =============================================================================
+
+/// One downscaled frame
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Thumbnail {
+    /// Frame number since the recorder started
+    pub sequence: u64,
+    pub width: u32,
+    pub height: u32,
+    pub bgra: Vec<u8>,
+}
+
+/// Thumbnail size for a `width`x`height` frame: 480 lines at the frame's
+/// aspect ratio, width rounded down to even
+pub fn thumbnail_size(width: u32, height: u32) -> (u32, u32) {
+    if height <= THUMBNAIL_HEIGHT {
+        return (width, height);
+    }
+    let scaled = (width as u64 * THUMBNAIL_HEIGHT as u64 / height as u64) as u32;
+    ((scaled & !1).max(2), THUMBNAIL_HEIGHT)
+}
+
+/// Nearest-neighbour downscale of tightly packed limited range UYVY into
+/// `bgra`, which is resized to `dst_w`x`dst_h` and keeps its allocation
+pub fn downscale_uyvy_to_bgra(
+    uyvy: &[u8],
+    width: u32,
+    height: u32,
+    dst_w: u32,
+    dst_h: u32,
+    bgra: &mut Vec<u8>,
+) {
+    bgra.resize(dst_w as usize * dst_h as usize * 4, 0);
+    let row_bytes = width as usize * 2;
+    for (dst_y, row) in bgra.chunks_exact_mut(dst_w as usize * 4).enumerate() {
+        let src_y = (dst_y as u64 * height as u64 / dst_h as u64) as usize;
+        let src_row = &uyvy[(src_y * row_bytes).min(uyvy.len())..];
+        for (dst_x, pixel) in row.chunks_exact_mut(4).enumerate() {
+            let src_x = (dst_x as u64 * width as u64 / dst_w as u64) as usize;
+            let pair = src_x / 2 * 4;
+            let Some(yuv) = src_row.get(pair..pair + 4) else {
+                pixel.copy_from_slice(&[0, 0, 0, 255]);
+                continue;
+            };
+            let luma = yuv[1 + (src_x % 2) * 2] as i32;
+            let [b, g, r] = yuv_to_bgr(
+                luma,
+                yuv[0] as i32 - 128,
+                yuv[2] as i32 - 128,
+                ColorRange::Limited,
+            );
+            pixel.copy_from_slice(&[b, g, r, 255]);
+        }
+    }
+}
+
+// =============================================================================
+// Ring
+// =============================================================================
+
+/// The last `capacity` thumbnails; once full, the oldest slot (and its
+/// buffer) is reused for the next one
+#[derive(Debug)]
+pub struct ThumbnailRing {
+    slots: Vec<Thumbnail>,
+    capacity: usize,
+    /// Thumbnails written since the ring was created or taken
+    written: u64,
+}
+
+impl ThumbnailRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            capacity: capacity.max(1),
+            written: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Slot for the next thumbnail
+    pub fn next_slot(&mut self) -> &mut Thumbnail {
+        let index = (self.written % self.capacity as u64) as usize;
+        self.written += 1;
+        if index == self.slots.len() {
+            self.slots.push(Thumbnail::default());
+        }
+        &mut self.slots[index]
+    }
+
+    /// Index of the oldest thumbnail in `slots`
+    fn oldest(&self) -> usize {
+        if self.slots.len() < self.capacity {
+            0
+        } else {
+            (self.written % self.capacity as u64) as usize
+        }
+    }
+
+    /// Thumbnails from oldest to newest
+    pub fn iter(&self) -> impl Iterator<Item = &Thumbnail> {
+        let (newer, older) = self.slots.split_at(self.oldest());
+        older.iter().chain(newer)
+    }
+
+    /// Remove and return the thumbnails from oldest to newest, leaving the
+    /// ring empty
+    pub fn take(&mut self) -> Vec<Thumbnail> {
+        let oldest = self.oldest();
+        let mut slots = std::mem::take(&mut self.slots);
+        slots.rotate_left(oldest);
+        self.written = 0;
+        slots
+    }
+}
+
+// =============================================================================
+// Recorder
+// =============================================================================
+
+/// Frame processor filling the ring; never changes or drops a frame
+pub struct ReplayRecorder {
+    ring: Arc<Mutex<ThumbnailRing>>,
+    frames: u64,
+    /// Average time spent thumbnailing one frame, in seconds
+    cost: f64,
+    every_other: bool,
+}
+
+impl ReplayRecorder {
+    /// Recorder keeping `frames` thumbnails, and the handle that dumps them
+    /// to `dir`
+    pub fn new(frames: usize, dir: impl Into<PathBuf>) -> (Self, ReplayHandle) {
+        let ring = Arc::new(Mutex::new(ThumbnailRing::new(frames)));
+        let handle = ReplayHandle {
+            ring: Arc::clone(&ring),
+            dir: dir.into(),
+        };
+        let recorder = Self {
+            ring,
+            frames: 0,
+            cost: 0.0,
+            every_other: false,
+        };
+        (recorder, handle)
+    }
+
+    /// True while only every other frame is thumbnailed
+    pub fn is_decimating(&self) -> bool {
+        self.every_other
+    }
+
+    /// Fold one thumbnailing time into the average and pick the frame step;
+    /// going back to every frame needs the average well under budget
+    fn record_cost(&mut self, elapsed: Duration) {
+        self.cost += (elapsed.as_secs_f64() - self.cost) * COST_SMOOTHING;
+        let budget = THUMBNAIL_BUDGET.as_secs_f64();
+        let every_other = if self.every_other {
+            self.cost > budget / 2.0
+        } else {
+            self.cost > budget
+        };
+        if every_other != self.every_other {
+            tracing::debug!(
+                "Replay thumbnails take {:.2}ms, keeping {}",
+                self.cost * 1000.0,
+                if every_other {
+                    "every other frame"
+                } else {
+                    "every frame"
+                }
+            );
+            self.every_other = every_other;
+        }
+    }
+}
+
+impl FrameProcessor for ReplayRecorder {
+    fn name(&self) -> &str {
+        "replay ring"
+    }
+
+    fn process(&mut self, uyvy: &mut [u8], width: u32, height: u32) -> FrameAction {
+        let sequence = self.frames;
+        self.frames += 1;
+        if self.every_other && sequence % 2 == 1 {
+            return FrameAction::Send;
+        }
+
+        let started = Instant::now();
+        let (dst_w, dst_h) = thumbnail_size(width, height);
+        {
+            let mut ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+            let slot = ring.next_slot();
+            slot.sequence = sequence;
+            slot.width = dst_w;
+            slot.height = dst_h;
+            downscale_uyvy_to_bgra(uyvy, width, height, dst_w, dst_h, &mut slot.bgra);
+        }
+        // Time spent per frame sent, so skipped frames count as free
+        let step = if self.every_other { 2 } else { 1 };
+        self.record_cost(started.elapsed() / step);
+        FrameAction::Send
+    }
+}
+
+// =============================================================================
+// Dump
+// =============================================================================
+
+/// Dumps the ring of a running recorder; cheap to clone
+#[derive(Clone)]
+pub struct ReplayHandle {
+    ring: Arc<Mutex<ThumbnailRing>>,
+    dir: PathBuf,
+}
+
+impl ReplayHandle {
+    /// Write the buffered thumbnails, oldest first, as 0001.png, 0002.png,
+    /// ... into a new `replay-<unix time>` directory. The ring is emptied, so
+    /// capture is only held up for the swap, not for the encoding.
+    pub fn dump(&self) -> Result<(PathBuf, usize)> {
+        let thumbnails = self.ring.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if thumbnails.is_empty() {
+            bail!("Replay ring is empty");
+        }
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let dir = self.dir.join(format!(
+            "replay-{}.{:03}",
+            stamp.as_secs(),
+            stamp.subsec_millis()
+        ));
+        write_thumbnails(&dir, &thumbnails)?;
+        tracing::info!(
+            "Dumped {} replay frames ({}..{}) to {}",
+            thumbnails.len(),
+            thumbnails[0].sequence,
+            thumbnails[thumbnails.len() - 1].sequence,
+            dir.display()
+        );
+        Ok((dir, thumbnails.len()))
+    }
+}
+
+/// Write `thumbnails` as numbered PNGs into `dir`, creating it
+pub fn write_thumbnails(dir: &Path, thumbnails: &[Thumbnail]) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for (index, thumbnail) in thumbnails.iter().enumerate() {
+        let path = dir.join(format!("{:04}.png", index + 1));
+        let png = encode_png(&thumbnail.bgra, thumbnail.width, thumbnail.height);
+        fs::write(&path, png).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+// =============================================================================
+// PNG Encoding
+// =============================================================================
+
+/// CRC-32 (IEEE) lookup table for PNG chunks
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+};
+
+fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        crc = CRC_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    crc ^ 0xFFFF_FFFF
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 bytes is the most that can be summed before the modulo is due
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc32(&[kind, data]).to_be_bytes());
+}
+
+/// Encode BGRA as an 8-bit RGB PNG. The image data is stored without
+/// compression: dumps are rare, and this needs neither a codec nor ffmpeg.
+pub fn encode_png(bgra: &[u8], width: u32, height: u32) -> Vec<u8> {
+    // Filter type 0 (none) before every row
+    let mut raw = Vec::with_capacity(height as usize * (1 + width as usize * 3));
+    for row in bgra.chunks_exact(width as usize * 4).take(height as usize) {
+        raw.push(0);
+        for pixel in row.chunks_exact(4) {
+            raw.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+    }
+
+    // zlib stream of stored deflate blocks
+    let mut zlib = Vec::with_capacity(raw.len() + raw.len() / 65535 * 5 + 16);
+    zlib.extend_from_slice(&[0x78, 0x01]);
+    let blocks = raw.chunks(65535).collect::<Vec<_>>();
+    if blocks.is_empty() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    for (index, block) in blocks.iter().enumerate() {
+        let len = block.len() as u16;
+        zlib.push((index == blocks.len() - 1) as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // 8 bits, truecolor, deflate, adaptive filtering, no interlace
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_source::png_dimensions;
+
+    fn push(ring: &mut ThumbnailRing, sequence: u64) {
+        ring.next_slot().sequence = sequence;
+    }
+
+    fn sequences(ring: &ThumbnailRing) -> Vec<u64> {
+        ring.iter().map(|t| t.sequence).collect()
+    }
+
+    /// Pixel rows of a PNG written by `encode_png`, as RGB
+    fn decode_stored_png(png: &[u8]) -> Vec<u8> {
+        let idat_len = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let zlib = &png[41..41 + idat_len];
+        let mut raw = Vec::new();
+        let mut at = 2;
+        loop {
+            let last = zlib[at] & 1 == 1;
+            let len = u16::from_le_bytes([zlib[at + 1], zlib[at + 2]]) as usize;
+            raw.extend_from_slice(&zlib[at + 5..at + 5 + len]);
+            at += 5 + len;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(
+            u32::from_be_bytes(zlib[at..at + 4].try_into().unwrap()),
+            adler32(&raw)
+        );
+        raw
+    }
+
+    #[test]
+    fn test_ring_keeps_the_newest_in_order() {
+        let mut ring = ThumbnailRing::new(3);
+        assert!(ring.is_empty());
+        push(&mut ring, 0);
+        push(&mut ring, 1);
+        assert_eq!(sequences(&ring), [0, 1]);
+        push(&mut ring, 2);
+        push(&mut ring, 3);
+        push(&mut ring, 4);
+        assert_eq!(ring.len(), 3);
+        assert_eq!(sequences(&ring), [2, 3, 4]);
+        push(&mut ring, 5);
+        push(&mut ring, 6);
+        assert_eq!(sequences(&ring), [4, 5, 6]);
+    }
+
+    #[test]
+    fn test_ring_take_empties_in_order() {
+        let mut ring = ThumbnailRing::new(4);
+        for sequence in 0..6 {
+            push(&mut ring, sequence);
+        }
+        let taken: Vec<u64> = ring.take().iter().map(|t| t.sequence).collect();
+        assert_eq!(taken, [2, 3, 4, 5]);
+        assert!(ring.is_empty());
+        push(&mut ring, 6);
+        assert_eq!(sequences(&ring), [6]);
+    }
+
+    #[test]
+    fn test_ring_reuses_slot_buffers() {
+        let mut ring = ThumbnailRing::new(2);
+        ring.next_slot().bgra = Vec::with_capacity(1000);
+        push(&mut ring, 1);
+        let slot = ring.next_slot();
+        assert!(slot.bgra.capacity() >= 1000);
+    }
+
+    #[test]
+    fn test_thumbnail_size() {
+        assert_eq!(thumbnail_size(1920, 1080), (852, 480));
+        assert_eq!(thumbnail_size(1280, 720), (852, 480));
+        assert_eq!(thumbnail_size(720, 576), (600, 480));
+        assert_eq!(thumbnail_size(640, 480), (640, 480));
+        assert_eq!(thumbnail_size(64, 8), (64, 8));
+    }
+
+    #[test]
+    fn test_downscale_picks_nearest_pixels() {
+        // 4x2 UYVY: top row white/black pair, then red-ish; bottom row gray
+        let uyvy = [
+            128, 235, 128, 16, 84, 81, 255, 81, //
+            128, 126, 128, 126, 128, 126, 128, 126,
+        ];
+        let mut bgra = Vec::new();
+        downscale_uyvy_to_bgra(&uyvy, 4, 2, 2, 1, &mut bgra);
+        assert_eq!(bgra.len(), 2 * 4);
+        assert_eq!(&bgra[..4], &[255, 255, 255, 255]);
+        // Second pixel samples x=2 of the red pair
+        assert!(bgra[6] > 200 && bgra[4] < 40 && bgra[5] < 40);
+    }
+
+    #[test]
+    fn test_recorder_thumbnails_and_dumps_pngs() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut recorder, handle) = ReplayRecorder::new(2, dir.path());
+        // 4x2 white frame, then black, then gray
+        for luma in [235u8, 16, 126] {
+            let mut uyvy = [128, luma, 128, luma].repeat(4);
+            assert_eq!(recorder.process(&mut uyvy, 4, 2), FrameAction::Send);
+            assert_eq!(uyvy, [128, luma, 128, luma].repeat(4));
+        }
+
+        let (dump, count) = handle.dump().unwrap();
+        assert_eq!(count, 2);
+        let mut files: Vec<String> = fs::read_dir(&dump)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["0001.png", "0002.png"]);
+
+        // Oldest kept frame (black) first
+        let png = fs::read(dump.join("0001.png")).unwrap();
+        assert_eq!(png_dimensions(&png).unwrap(), (4, 2));
+        let raw = decode_stored_png(&png);
+        assert_eq!(raw.len(), 2 * (1 + 4 * 3));
+        assert_eq!(&raw[..4], &[0, 0, 0, 0]);
+        let png = fs::read(dump.join("0002.png")).unwrap();
+        let raw = decode_stored_png(&png);
+        assert!(raw[1..13].iter().all(|&c| (125..=130).contains(&c)));
+
+        // The dump emptied the ring
+        assert!(handle.dump().is_err());
+    }
+
+    #[test]
+    fn test_encode_png_splits_large_images_into_blocks() {
+        let (width, height) = (200, 120);
+        let bgra: Vec<u8> = (0..width * height * 4).map(|i| (i % 251) as u8).collect();
+        let png = encode_png(&bgra, width, height);
+        assert_eq!(png_dimensions(&png).unwrap(), (width, height));
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+        let raw = decode_stored_png(&png);
+        assert_eq!(raw.len(), (height * (1 + width * 3)) as usize);
+        // First pixel of the second row, swapped to RGB
+        let row = 1 + width as usize * 3;
+        let src = width as usize * 4;
+        assert_eq!(
+            &raw[row + 1..row + 4],
+            &[bgra[src + 2], bgra[src + 1], bgra[src]]
+        );
+    }
+
+    #[test]
+    fn test_slow_thumbnails_fall_back_to_every_other_frame() {
+        let (mut recorder, _handle) = ReplayRecorder::new(4, "/tmp");
+        for _ in 0..200 {
+            recorder.record_cost(THUMBNAIL_BUDGET * 3);
+        }
+        assert!(recorder.is_decimating());
+        // Just under budget isn't enough to go back
+        for _ in 0..200 {
+            recorder.record_cost(THUMBNAIL_BUDGET * 3 / 4);
+        }
+        assert!(recorder.is_decimating());
+        for _ in 0..200 {
+            recorder.record_cost(THUMBNAIL_BUDGET / 4);
+        }
+        assert!(!recorder.is_decimating());
+    }
+}
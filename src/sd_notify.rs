@@ -0,0 +1,67 @@
+//! systemd notification protocol (sd_notify)
+//!
+//! Sends `KEY=value` datagrams to the socket named by `$NOTIFY_SOCKET` so the
+//! service status shows up in `systemctl status camera-box`. Outside systemd
+//! the variable is unset and notifications are skipped.
+
+use std::io;
+use std::os::unix::net::UnixDatagram;
+
+/// Send `message` (newline-separated assignments) to systemd.
+/// Returns false if not running under a notifying service manager.
+pub fn notify(message: &str) -> io::Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(path) => notify_socket(&path.to_string_lossy(), message).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// Update the free-form service status line (errors are ignored)
+pub fn status(text: &str) {
+    if let Err(e) = notify(&format!("STATUS={}", text)) {
+        tracing::debug!("sd_notify failed: {}", e);
+    }
+}
+
+/// Tell systemd that startup finished (errors are ignored)
+pub fn ready() {
+    if let Err(e) = notify("READY=1") {
+        tracing::debug!("sd_notify failed: {}", e);
+    }
+}
+
+/// Send `message` to a notify socket path; a leading '@' is an abstract name
+pub fn notify_socket(path: &str, message: &str) -> io::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(message.as_bytes(), &addr)?;
+    } else {
+        socket.send_to(message.as_bytes(), path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notify_socket_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let listener = UnixDatagram::bind(&path).unwrap();
+
+        notify_socket(path.to_str().unwrap(), "STATUS=Streaming").unwrap();
+
+        let mut buf = [0u8; 64];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"STATUS=Streaming");
+    }
+
+    #[test]
+    fn test_notify_socket_missing_is_error() {
+        assert!(notify_socket("/nonexistent/notify.sock", "READY=1").is_err());
+    }
+}
@@ -0,0 +1,502 @@
+//! VBAN serial bridge
+//!
+//! Relays a VBAN SERIAL stream to a local UART and back: payloads of
+//! packets carrying the configured stream name are written to the serial
+//! device, and bytes read from it are sent to the target as the same
+//! stream. Used to reach a lens controller from a PTZ controller that
+//! speaks VBAN serial.
+//!
+//! The UART is written without blocking; whatever it can't take is
+//! dropped and counted rather than delaying the network side. A device
+//! that disappears (USB adapter unplugged) is reopened until it is back.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::io_util;
+use crate::net;
+use crate::stats::{Component, StatsRegistry};
+use crate::vban::{VbanSerialHeader, VBAN_PORT, VBAN_SERIAL_MAX_PAYLOAD};
+
+/// How long one poll waits, bounding the reaction to shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Wait between attempts to reopen a lost serial device
+const REOPEN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Largest UDP datagram read
+const RECV_BUFFER_SIZE: usize = 2048;
+
+/// termios speed for a baud rate, None if the kernel has no constant for it
+pub fn baud_rate(baud: u32) -> Option<libc::speed_t> {
+    Some(match baud {
+        1200 => libc::B1200,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        460800 => libc::B460800,
+        500000 => libc::B500000,
+        921600 => libc::B921600,
+        1000000 => libc::B1000000,
+        1500000 => libc::B1500000,
+        2000000 => libc::B2000000,
+        3000000 => libc::B3000000,
+        _ => return None,
+    })
+}
+
+/// Serial bridge configuration
+#[derive(Debug, Clone)]
+pub struct SerialBridgeConfig {
+    /// UART device path
+    pub device: String,
+    pub baud: u32,
+    /// VBAN serial stream name, both directions
+    pub stream_name: String,
+    /// Channel ident sent in outgoing packets
+    pub channel: u8,
+    /// Host that bytes read from the UART are sent to
+    pub target_host: String,
+    pub target_port: u16,
+    /// Address the bridge listens on for incoming serial packets
+    pub listen: String,
+}
+
+impl Default for SerialBridgeConfig {
+    fn default() -> Self {
+        Self {
+            device: "/dev/ttyUSB0".to_string(),
+            baud: 9600,
+            stream_name: "serial".to_string(),
+            channel: 0,
+            target_host: String::new(),
+            target_port: VBAN_PORT,
+            listen: "[::]:6981".to_string(),
+        }
+    }
+}
+
+/// Bridge counters
+#[derive(Debug, Default)]
+pub struct SerialBridgeStats {
+    /// Bytes written to the UART
+    pub to_serial: AtomicU64,
+    /// Bytes read from the UART and sent as VBAN
+    pub from_serial: AtomicU64,
+    /// Bytes the UART could not take
+    pub dropped: AtomicU64,
+    /// Times the device came back after it was missing or lost
+    pub reopens: AtomicU64,
+}
+
+impl SerialBridgeStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report the counters as "serial"
+    pub fn register(self: &Arc<Self>, registry: &StatsRegistry) {
+        registry.register(
+            Component::new("serial")
+                .counter("to_uart", self, |s| &s.to_serial)
+                .counter("from_uart", self, |s| &s.from_serial)
+                .counter("dropped", self, |s| &s.dropped)
+                .counter("reopens", self, |s| &s.reopens),
+        );
+    }
+}
+
+/// A UART in raw mode, opened non-blocking
+pub struct SerialPort {
+    file: File,
+}
+
+impl SerialPort {
+    /// Open `path` as 8N1 raw at `baud`, without flow control
+    pub fn open(path: &str, baud: u32) -> Result<Self> {
+        let speed = baud_rate(baud).ok_or_else(|| anyhow!("Unsupported baud rate: {}", baud))?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(path)
+            .with_context(|| format!("Failed to open serial device {}", path))?;
+        let fd = file.as_raw_fd();
+        unsafe {
+            let mut tio: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut tio) != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("{} is not a serial device", path));
+            }
+            libc::cfmakeraw(&mut tio);
+            tio.c_cflag |= libc::CLOCAL | libc::CREAD;
+            tio.c_cflag &= !(libc::CRTSCTS | libc::CSTOPB | libc::PARENB);
+            libc::cfsetispeed(&mut tio, speed);
+            libc::cfsetospeed(&mut tio, speed);
+            if libc::tcsetattr(fd, libc::TCSANOW, &tio) != 0 {
+                return Err(std::io::Error::last_os_error())
+                    .with_context(|| format!("Failed to configure {} at {} baud", path, baud));
+            }
+        }
+        Ok(Self { file })
+    }
+
+    /// Write what the UART takes right now, returning the bytes written
+    fn write_available(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let mut written = 0;
+        while written < data.len() {
+            match self.file.write(&data[written..]) {
+                Ok(0) => break,
+                Ok(n) => written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(written)
+    }
+}
+
+/// Wait up to `timeout` for any of `fds` to become readable. Errors and
+/// hangups count as readable, so the following read reports them.
+fn poll_readable<const N: usize>(fds: [RawFd; N], timeout: Duration) -> std::io::Result<[bool; N]> {
+    let mut pollfds = fds.map(|fd| libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+    let ret = unsafe {
+        libc::poll(
+            pollfds.as_mut_ptr(),
+            N as libc::nfds_t,
+            timeout.as_millis() as libc::c_int,
+        )
+    };
+    if ret < 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() == ErrorKind::Interrupted {
+            return Ok([false; N]);
+        }
+        return Err(err);
+    }
+    Ok(pollfds.map(|p| p.revents != 0))
+}
+
+/// Relays between a VBAN serial stream and a UART
+pub struct SerialBridge {
+    config: SerialBridgeConfig,
+    socket: UdpSocket,
+    target: SocketAddr,
+    header: VbanSerialHeader,
+    stats: Arc<SerialBridgeStats>,
+}
+
+impl SerialBridge {
+    /// Bind the listen socket and resolve the target. The serial device is
+    /// opened by [`SerialBridge::run`], so a missing adapter doesn't stop
+    /// the bridge from starting.
+    pub fn bind(config: SerialBridgeConfig, stats: Arc<SerialBridgeStats>) -> Result<Self> {
+        if baud_rate(config.baud).is_none() {
+            bail!("Unsupported baud rate: {}", config.baud);
+        }
+        let listen: SocketAddr = config
+            .listen
+            .parse()
+            .with_context(|| format!("Invalid listen address: {}", config.listen))?;
+        let socket = net::bind_udp(listen)
+            .with_context(|| format!("Failed to bind serial bridge to {}", listen))?;
+        socket.set_nonblocking(true)?;
+        let local = socket.local_addr()?;
+        let target = net::resolve(&config.target_host, config.target_port)
+            .with_context(|| format!("Cannot resolve serial target {}", config.target_host))?
+            .into_iter()
+            .find(|addr| local.is_ipv6() || addr.is_ipv4())
+            .ok_or_else(|| anyhow!("No IPv4 address for {}", config.target_host))?;
+        let header = VbanSerialHeader::new(&config.stream_name, config.baud, config.channel);
+        Ok(Self {
+            target: net::peer_for(&local, target),
+            socket,
+            header,
+            config,
+            stats,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Relay until `running` is cleared, reopening the device when it is lost
+    pub fn run(mut self, running: &AtomicBool) {
+        tracing::info!(
+            "Serial bridge: {} at {} baud <-> VBAN '{}' (to {}, listening on {})",
+            self.config.device,
+            self.config.baud,
+            self.config.stream_name,
+            self.target,
+            self.socket
+                .local_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default()
+        );
+        let mut reported = false;
+        while running.load(Ordering::Relaxed) {
+            let mut port = match SerialPort::open(&self.config.device, self.config.baud) {
+                Ok(port) => port,
+                Err(e) => {
+                    if !reported {
+                        tracing::warn!("Serial bridge: {:#}, retrying", e);
+                        reported = true;
+                    }
+                    std::thread::sleep(REOPEN_INTERVAL);
+                    continue;
+                }
+            };
+            if reported {
+                tracing::info!("Serial bridge: {} is back", self.config.device);
+                self.stats.reopens.fetch_add(1, Ordering::Relaxed);
+            }
+            reported = false;
+            if let Err(e) = self.relay(&mut port, running) {
+                tracing::warn!(
+                    "Serial bridge: {} lost ({}), reopening",
+                    self.config.device,
+                    e
+                );
+                reported = true;
+            }
+        }
+    }
+
+    /// Relay in both directions until `running` is cleared or the device fails
+    fn relay(&mut self, port: &mut SerialPort, running: &AtomicBool) -> std::io::Result<()> {
+        let mut datagram = [0u8; RECV_BUFFER_SIZE];
+        let mut serial = [0u8; VBAN_SERIAL_MAX_PAYLOAD];
+        let mut packet = Vec::with_capacity(RECV_BUFFER_SIZE);
+        let fds = [self.socket.as_raw_fd(), port.file.as_raw_fd()];
+        while running.load(Ordering::Relaxed) {
+            let [network, uart] = poll_readable(fds, POLL_INTERVAL)?;
+            if network {
+                self.network_to_serial(port, &mut datagram)?;
+            }
+            if uart {
+                let n = match port.file.read(&mut serial) {
+                    Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                    Ok(n) => n,
+                    Err(e)
+                        if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted) =>
+                    {
+                        continue
+                    }
+                    Err(e) => return Err(e),
+                };
+                self.send(&serial[..n], &mut packet);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drain the socket, writing matching payloads to the UART
+    fn network_to_serial(&self, port: &mut SerialPort, buf: &mut [u8]) -> std::io::Result<()> {
+        loop {
+            let len = match self.socket.recv_from(buf) {
+                Ok((len, _)) => len,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    tracing::debug!("Serial bridge receive failed: {}", e);
+                    return Ok(());
+                }
+            };
+            let Ok((header, payload)) = VbanSerialHeader::decode(&buf[..len]) else {
+                continue;
+            };
+            if !header.matches_name(&self.config.stream_name) {
+                continue;
+            }
+            let written = port.write_available(payload)?;
+            self.stats
+                .to_serial
+                .fetch_add(written as u64, Ordering::Relaxed);
+            let dropped = payload.len() - written;
+            if dropped > 0 {
+                let total = self
+                    .stats
+                    .dropped
+                    .fetch_add(dropped as u64, Ordering::Relaxed);
+                tracing::debug!(
+                    "Serial bridge: UART busy, dropped {} bytes ({} total)",
+                    dropped,
+                    total + dropped as u64
+                );
+            }
+        }
+    }
+
+    /// Send bytes read from the UART as one VBAN serial packet
+    fn send(&mut self, data: &[u8], packet: &mut Vec<u8>) {
+        if self.header.encode_packet(data, packet).is_err() {
+            return;
+        }
+        self.header.frame_counter = self.header.frame_counter.wrapping_add(1);
+        match io_util::retry_eintr(|| self.socket.send_to(packet, self.target)) {
+            Ok(_) => {
+                self.stats
+                    .from_serial
+                    .fetch_add(data.len() as u64, Ordering::Relaxed);
+            }
+            Err(e) => tracing::debug!("Serial bridge send failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::FromRawFd;
+
+    /// Pseudo-terminal master and the path of its slave
+    fn pty_pair() -> (File, String) {
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+            assert!(fd >= 0, "{}", std::io::Error::last_os_error());
+            let master = File::from_raw_fd(fd);
+            assert_eq!(libc::grantpt(fd), 0);
+            assert_eq!(libc::unlockpt(fd), 0);
+            let mut name = [0 as libc::c_char; 128];
+            assert_eq!(libc::ptsname_r(fd, name.as_mut_ptr(), name.len()), 0);
+            let path = std::ffi::CStr::from_ptr(name.as_ptr())
+                .to_string_lossy()
+                .into_owned();
+            (master, path)
+        }
+    }
+
+    fn read_exact_timeout(file: &mut File, len: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 256];
+        while data.len() < len {
+            let [ready] = poll_readable([file.as_raw_fd()], Duration::from_secs(2)).unwrap();
+            assert!(ready, "timed out after {:?}", data);
+            let n = file.read(&mut buf).unwrap();
+            data.extend_from_slice(&buf[..n]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_baud_rates() {
+        assert_eq!(baud_rate(9600), Some(libc::B9600));
+        assert_eq!(baud_rate(115200), Some(libc::B115200));
+        assert_eq!(baud_rate(12345), None);
+        assert!(SerialPort::open("/dev/null", 12345).is_err());
+        // Not a tty
+        assert!(SerialPort::open("/dev/null", 9600).is_err());
+    }
+
+    #[test]
+    fn test_bridge_over_pty() {
+        let (mut master, slave) = pty_pair();
+        // Raw mode before any bytes pass, and kept while the test runs
+        let _slave = SerialPort::open(&slave, 9600).unwrap();
+
+        let controller = UdpSocket::bind("127.0.0.1:0").unwrap();
+        controller
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        let stats = Arc::new(SerialBridgeStats::new());
+        let bridge = SerialBridge::bind(
+            SerialBridgeConfig {
+                device: slave,
+                stream_name: "ptz".to_string(),
+                channel: 2,
+                target_host: "127.0.0.1".to_string(),
+                target_port: controller.local_addr().unwrap().port(),
+                listen: "127.0.0.1:0".to_string(),
+                ..Default::default()
+            },
+            Arc::clone(&stats),
+        )
+        .unwrap();
+        let bridge_addr = bridge.local_addr().unwrap();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = Arc::clone(&running);
+            std::thread::spawn(move || bridge.run(&running))
+        };
+
+        // VBAN -> UART; other streams and protocols are ignored
+        let mut packet = Vec::new();
+        VbanSerialHeader::new("other", 9600, 0)
+            .encode_packet(b"nope", &mut packet)
+            .unwrap();
+        controller.send_to(&packet, bridge_addr).unwrap();
+        VbanSerialHeader::new("ptz", 9600, 0)
+            .encode_packet(&[0x81, 0x01, 0x06, 0x01, 0xFF], &mut packet)
+            .unwrap();
+        controller.send_to(&packet, bridge_addr).unwrap();
+        assert_eq!(
+            read_exact_timeout(&mut master, 5),
+            [0x81, 0x01, 0x06, 0x01, 0xFF]
+        );
+
+        // UART -> VBAN
+        master.write_all(&[0x90, 0x50, 0xFF]).unwrap();
+        let mut buf = [0u8; RECV_BUFFER_SIZE];
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            let (len, _) = controller.recv_from(&mut buf).unwrap();
+            let (header, payload) = VbanSerialHeader::decode(&buf[..len]).unwrap();
+            assert_eq!(header.stream_name_str(), "ptz");
+            assert_eq!(header.channel, 2);
+            assert_eq!(header.bit_rate(), 9600);
+            received.extend_from_slice(payload);
+        }
+        assert_eq!(received, [0x90, 0x50, 0xFF]);
+
+        running.store(false, Ordering::Relaxed);
+        thread.join().unwrap();
+        assert_eq!(stats.to_serial.load(Ordering::Relaxed), 5);
+        assert_eq!(stats.from_serial.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_bind_rejects_bad_settings() {
+        let stats = Arc::new(SerialBridgeStats::new());
+        let config = SerialBridgeConfig {
+            target_host: "127.0.0.1".to_string(),
+            listen: "127.0.0.1:0".to_string(),
+            ..Default::default()
+        };
+        assert!(SerialBridge::bind(
+            SerialBridgeConfig {
+                baud: 1234,
+                ..config.clone()
+            },
+            Arc::clone(&stats)
+        )
+        .is_err());
+        assert!(SerialBridge::bind(
+            SerialBridgeConfig {
+                listen: "nowhere".to_string(),
+                ..config.clone()
+            },
+            Arc::clone(&stats)
+        )
+        .is_err());
+        assert!(SerialBridge::bind(config, stats).is_ok());
+    }
+}
@@ -0,0 +1,251 @@
+//! Totals that outlive a run
+//!
+//! Live counters are kept in shared stats blocks for the life of the
+//! process, so sender restarts and display reconnects don't reset them. At
+//! shutdown the frames captured over all runs and the length of this run are
+//! written to a small TOML file, and the next start logs them as the
+//! previous session.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::stats::{Component, StatsRegistry};
+
+/// Default state file location
+pub const STATE_FILE: &str = "/var/lib/camera-box/state.toml";
+
+/// Current state file format version
+pub const STATE_VERSION: u32 = 1;
+
+/// Contents of the state file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionState {
+    pub version: u32,
+    /// Frames captured over all runs that stopped cleanly
+    #[serde(default)]
+    pub total_frames: u64,
+    /// Frames captured in the last run
+    #[serde(default)]
+    pub last_run_frames: u64,
+    /// Length of the last run in seconds
+    #[serde(default)]
+    pub last_run_secs: u64,
+    /// When the last run stopped, in seconds since the Unix epoch (0 = never)
+    #[serde(default)]
+    pub last_run_ended: u64,
+}
+
+impl Default for SessionState {
+    fn default() -> Self {
+        Self {
+            version: STATE_VERSION,
+            total_frames: 0,
+            last_run_frames: 0,
+            last_run_secs: 0,
+            last_run_ended: 0,
+        }
+    }
+}
+
+impl SessionState {
+    /// Load the state; a missing file is a first run. A file written by a
+    /// newer version is ignored rather than misread.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        let state: Self = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if state.version > STATE_VERSION {
+            tracing::warn!(
+                "{} has version {} (supported: {}), starting a new total",
+                path.display(),
+                state.version,
+                STATE_VERSION
+            );
+            return Ok(Self::default());
+        }
+        Ok(Self {
+            version: STATE_VERSION,
+            ..state
+        })
+    }
+
+    /// Write the state atomically (temp file + rename), creating the directory
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let content = toml::to_string(self).context("Failed to serialize session state")?;
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, content)
+            .with_context(|| format!("Failed to write {}", tmp.display()))?;
+        std::fs::rename(&tmp, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+        Ok(())
+    }
+
+    /// The state after a run of `frames` frames lasting `duration` that
+    /// stopped at `ended`
+    pub fn after_run(&self, frames: u64, duration: Duration, ended: SystemTime) -> Self {
+        Self {
+            version: STATE_VERSION,
+            total_frames: self.total_frames.saturating_add(frames),
+            last_run_frames: frames,
+            last_run_secs: duration.as_secs(),
+            last_run_ended: ended
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        }
+    }
+
+    /// Startup log line for the last run, if one stopped cleanly
+    pub fn summary(&self, now: SystemTime) -> Option<String> {
+        if self.last_run_ended == 0 {
+            return None;
+        }
+        let ended = UNIX_EPOCH + Duration::from_secs(self.last_run_ended);
+        let ago = now.duration_since(ended).unwrap_or_default();
+        Some(format!(
+            "Previous session: ran {}, {} frames ({} in total), stopped {} ago",
+            format_duration(Duration::from_secs(self.last_run_secs)),
+            self.last_run_frames,
+            self.total_frames,
+            format_duration(ago)
+        ))
+    }
+}
+
+/// "1d 02h 03m", "2h 03m 10s", "5m 02s" or "12s"
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, mins, secs) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if days > 0 {
+        format!("{}d {:02}h {:02}m", days, hours, mins)
+    } else if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, mins, secs)
+    } else if mins > 0 {
+        format!("{}m {:02}s", mins, secs)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// This run's start and the state it carries on from
+#[derive(Debug)]
+pub struct Session {
+    previous: SessionState,
+    started: Instant,
+}
+
+impl Session {
+    pub fn new(previous: SessionState, started: Instant) -> Self {
+        Self { previous, started }
+    }
+
+    pub fn previous(&self) -> &SessionState {
+        &self.previous
+    }
+
+    pub fn uptime(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.started)
+    }
+
+    /// Frames over all runs, given `frames` captured in this one
+    pub fn total_frames(&self, frames: u64) -> u64 {
+        self.previous.total_frames.saturating_add(frames)
+    }
+
+    /// Report the uptime and the frames over all runs as "session";
+    /// `frames` reads the frames captured in this run
+    pub fn register(
+        self: &Arc<Self>,
+        registry: &StatsRegistry,
+        frames: impl Fn() -> u64 + Send + Sync + 'static,
+    ) {
+        let uptime = Arc::clone(self);
+        let total = Arc::clone(self);
+        registry.register(
+            Component::new("session")
+                .gauge("uptime_s", move || {
+                    uptime.uptime(Instant::now()).as_secs() as f64
+                })
+                .gauge("total_frames", move || total.total_frames(frames()) as f64),
+        );
+    }
+
+    /// The state to save when this run stops at `now` (`ended` on the wall
+    /// clock) after capturing `frames`
+    pub fn finish(&self, frames: u64, now: Instant, ended: SystemTime) -> SessionState {
+        self.previous.after_run(frames, self.uptime(now), ended)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_missing_is_first_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let state = SessionState::load(dir.path().join("state.toml")).unwrap();
+        assert_eq!(state, SessionState::default());
+        assert_eq!(state.summary(SystemTime::now()), None);
+    }
+
+    #[test]
+    fn test_save_load_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lib/state.toml");
+        let ended = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let state = SessionState::default().after_run(500, Duration::from_secs(20), ended);
+        state.save(&path).unwrap();
+
+        let loaded = SessionState::load(&path).unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.total_frames, 500);
+        assert_eq!(loaded.last_run_ended, 1_700_000_000);
+        assert!(!path.with_extension("toml.tmp").exists());
+
+        std::fs::write(&path, "version = 99\ntotal_frames = 7\n").unwrap();
+        assert_eq!(SessionState::load(&path).unwrap(), SessionState::default());
+        std::fs::write(&path, "not toml [").unwrap();
+        assert!(SessionState::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_totals_carry_over_runs() {
+        let t0 = Instant::now();
+        let ended = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let first = Session::new(SessionState::default(), t0);
+        let state = first.finish(300, t0 + Duration::from_secs(12), ended);
+        assert_eq!(state.total_frames, 300);
+        assert_eq!(state.last_run_secs, 12);
+
+        let second = Session::new(state, t0);
+        assert_eq!(second.total_frames(50), 350);
+        let state = second.finish(50, t0 + Duration::from_secs(2), ended);
+        assert_eq!(state.total_frames, 350);
+        assert_eq!(state.last_run_frames, 50);
+        assert_eq!(
+            state.summary(ended + Duration::from_secs(65)).unwrap(),
+            "Previous session: ran 2s, 50 frames (350 in total), stopped 1m 05s ago"
+        );
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0s");
+        assert_eq!(format_duration(Duration::from_secs(302)), "5m 02s");
+        assert_eq!(format_duration(Duration::from_secs(7390)), "2h 03m 10s");
+        assert_eq!(format_duration(Duration::from_secs(93_780)), "1d 02h 03m");
+    }
+}
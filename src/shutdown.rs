@@ -0,0 +1,126 @@
+//! Bounded shutdown
+//!
+//! On SIGINT or SIGTERM the components stop one after another: the capture
+//! pipeline drops its NDI senders so receivers lose the source at once
+//! instead of when its mDNS record expires, the display leaves a black
+//! screen rather than the last frame, and the intercom tells the mixer it
+//! went offline. Each component is a named step of a [`Shutdown`], which
+//! runs the steps in order on their own thread and gives up once its
+//! deadline has passed, so a step stuck in a driver can't keep the box
+//! from stopping.
+
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::threads;
+
+/// Longest a clean shutdown may take before the process exits anyway
+pub const DEADLINE: Duration = Duration::from_secs(2);
+
+/// How a shutdown ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every step finished
+    Completed,
+    /// The step still running at the deadline, or that panicked; later
+    /// steps did not run
+    Unfinished(&'static str),
+}
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+/// Named shutdown steps, run in the order they were added
+#[derive(Default)]
+pub struct Shutdown {
+    steps: Vec<(&'static str, Hook)>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `hook` after the steps added before it
+    pub fn step(mut self, name: &'static str, hook: impl FnOnce() + Send + 'static) -> Self {
+        self.steps.push((name, Box::new(hook)));
+        self
+    }
+
+    /// Run the steps, waiting at most `deadline` for all of them
+    pub fn run(self, deadline: Duration) -> Outcome {
+        let end = Instant::now() + deadline;
+        let names: Vec<&'static str> = self.steps.iter().map(|(name, _)| *name).collect();
+        let (done_tx, done_rx) = mpsc::channel();
+        threads::spawn(threads::SHUTDOWN, move || {
+            for (name, hook) in self.steps {
+                tracing::debug!("Stopping {}", name);
+                hook();
+                let _ = done_tx.send(name);
+            }
+        });
+        for name in names {
+            let left = end.saturating_duration_since(Instant::now());
+            if done_rx.recv_timeout(left).is_err() {
+                return Outcome::Unfinished(name);
+            }
+        }
+        Outcome::Completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn logging(log: &Arc<Mutex<Vec<&'static str>>>, name: &'static str) -> impl FnOnce() {
+        let log = Arc::clone(log);
+        move || log.lock().unwrap().push(name)
+    }
+
+    #[test]
+    fn test_steps_run_in_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let outcome = Shutdown::new()
+            .step("capture", logging(&log, "capture"))
+            .step("display", logging(&log, "display"))
+            .step("intercom", logging(&log, "intercom"))
+            .run(DEADLINE);
+        assert_eq!(outcome, Outcome::Completed);
+        assert_eq!(*log.lock().unwrap(), ["capture", "display", "intercom"]);
+
+        assert_eq!(Shutdown::new().run(Duration::ZERO), Outcome::Completed);
+    }
+
+    #[test]
+    fn test_stuck_step_is_abandoned_at_the_deadline() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (_release, stuck) = mpsc::channel::<()>();
+        let started = Instant::now();
+        let outcome = Shutdown::new()
+            .step("capture", logging(&log, "capture"))
+            .step("display", move || {
+                let _ = stuck.recv();
+            })
+            .step("intercom", logging(&log, "intercom"))
+            .run(Duration::from_millis(100));
+        let elapsed = started.elapsed();
+        assert_eq!(outcome, Outcome::Unfinished("display"));
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(1), "{:?}", elapsed);
+        assert_eq!(*log.lock().unwrap(), ["capture"]);
+    }
+
+    #[test]
+    fn test_panicking_step_ends_the_shutdown() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let started = Instant::now();
+        let outcome = Shutdown::new()
+            .step("capture", || panic!("sender gone"))
+            .step("display", logging(&log, "display"))
+            .run(DEADLINE);
+        assert_eq!(outcome, Outcome::Unfinished("capture"));
+        assert!(started.elapsed() < DEADLINE);
+        assert!(log.lock().unwrap().is_empty());
+    }
+}
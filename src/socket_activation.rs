@@ -0,0 +1,177 @@
+//! systemd socket activation (LISTEN_FDS)
+//!
+//! With `camera-box.socket` enabled, systemd holds the control socket while
+//! camera-box is stopped, e.g. after `display.idle_exit_mins`, and starts the
+//! service on the first connection, such as `camera-box ctl wake`. The
+//! started process adopts the listening socket systemd passes as fd 3
+//! onwards instead of binding its own, so the connection that woke it is
+//! answered. Outside socket activation `LISTEN_PID` is unset and nothing is
+//! adopted.
+
+use std::ops::Range;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// First fd systemd passes (SD_LISTEN_FDS_START)
+pub const LISTEN_FDS_START: RawFd = 3;
+
+/// Whether the passed fds were taken, so no fd gets two owners
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// The fds passed to process `pid`, from the `LISTEN_PID` and `LISTEN_FDS`
+/// values; empty if unset, malformed or meant for another process
+pub fn passed_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> Range<RawFd> {
+    let for_us = listen_pid.and_then(|value| value.trim().parse::<u32>().ok()) == Some(pid);
+    let count = listen_fds
+        .filter(|_| for_us)
+        .and_then(|value| value.trim().parse::<RawFd>().ok())
+        .unwrap_or(0)
+        .clamp(0, RawFd::MAX - LISTEN_FDS_START);
+    LISTEN_FDS_START..LISTEN_FDS_START + count
+}
+
+fn getsockopt_int(fd: RawFd, name: libc::c_int) -> Option<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            name,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    (ret == 0).then_some(value)
+}
+
+/// Whether `fd` is a listening Unix stream socket, the only kind the
+/// control server can serve
+pub fn is_unix_listener(fd: RawFd) -> bool {
+    if getsockopt_int(fd, libc::SO_TYPE) != Some(libc::SOCK_STREAM)
+        || getsockopt_int(fd, libc::SO_ACCEPTCONN) != Some(1)
+    {
+        return false;
+    }
+    let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockname(
+            fd,
+            &mut addr as *mut libc::sockaddr_storage as *mut libc::sockaddr,
+            &mut len,
+        )
+    };
+    ret == 0 && addr.ss_family == libc::AF_UNIX as libc::sa_family_t
+}
+
+/// Take the first listening Unix stream socket among `fds`, blocking and
+/// close-on-exec like the sockets std opens. Other fds are left alone.
+///
+/// # Safety
+///
+/// The fds must not be owned by anything else: the listener returned
+/// closes its fd when dropped.
+pub unsafe fn adopt_unix_listener(mut fds: Range<RawFd>) -> Option<UnixListener> {
+    let fd = fds.find(|&fd| is_unix_listener(fd))?;
+    let listener = unsafe {
+        libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        UnixListener::from_raw_fd(fd)
+    };
+    if let Err(e) = listener.set_nonblocking(false) {
+        tracing::debug!("Passed control socket stays non-blocking: {}", e);
+    }
+    Some(listener)
+}
+
+/// The control socket systemd passed to this process, if any. Only the
+/// first call can adopt it.
+pub fn control_listener() -> Option<UnixListener> {
+    let fds = passed_fds(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    if fds.is_empty() || TAKEN.swap(true, Ordering::SeqCst) {
+        return None;
+    }
+    // Nothing else in the process knows about the passed fds
+    unsafe { adopt_unix_listener(fds) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::io::{AsRawFd, IntoRawFd};
+    use std::os::unix::net::{UnixDatagram, UnixStream};
+
+    #[test]
+    fn test_passed_fds() {
+        assert_eq!(passed_fds(Some("42"), Some("1"), 42), 3..4);
+        assert_eq!(passed_fds(Some("42"), Some("3"), 42), 3..6);
+        assert_eq!(passed_fds(Some(" 42\n"), Some("2 "), 42), 3..5);
+        // Meant for another process, e.g. inherited by a child
+        assert!(passed_fds(Some("41"), Some("1"), 42).is_empty());
+        // Not socket activated
+        assert!(passed_fds(None, None, 42).is_empty());
+        assert!(passed_fds(None, Some("1"), 42).is_empty());
+        assert!(passed_fds(Some("42"), None, 42).is_empty());
+        // Malformed
+        for count in ["", "0", "-1", "one"] {
+            assert!(
+                passed_fds(Some("42"), Some(count), 42).is_empty(),
+                "{:?}",
+                count
+            );
+        }
+        assert!(passed_fds(Some("pid"), Some("1"), 42).is_empty());
+    }
+
+    #[test]
+    fn test_adopts_listening_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let fd = UnixListener::bind(&path).unwrap().into_raw_fd();
+        assert!(is_unix_listener(fd));
+
+        let listener = unsafe { adopt_unix_listener(fd..fd + 1) }.unwrap();
+        assert_eq!(listener.as_raw_fd(), fd);
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        assert_eq!(flags & libc::FD_CLOEXEC, libc::FD_CLOEXEC);
+
+        // A client queued on the socket is served by the adopted listener
+        let mut client = UnixStream::connect(&path).unwrap();
+        client.write_all(b"wake\n").unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(server).read_line(&mut line).unwrap();
+        assert_eq!(line, "wake\n");
+    }
+
+    #[test]
+    fn test_skips_other_fds() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = tempfile::tempfile().unwrap();
+        let datagram = UnixDatagram::bind(dir.path().join("dgram.sock")).unwrap();
+        let listener = UnixListener::bind(dir.path().join("control.sock")).unwrap();
+        let connected = UnixStream::connect(dir.path().join("control.sock")).unwrap();
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        for (name, fd) in [
+            ("file", file.as_raw_fd()),
+            ("datagram", datagram.as_raw_fd()),
+            ("connected stream", connected.as_raw_fd()),
+            ("tcp listener", tcp.as_raw_fd()),
+            ("closed fd", -1),
+        ] {
+            assert!(!is_unix_listener(fd), "{}", name);
+            assert!(
+                unsafe { adopt_unix_listener(fd..fd + 1) }.is_none(),
+                "{}",
+                name
+            );
+        }
+        assert!(is_unix_listener(listener.as_raw_fd()));
+    }
+}
@@ -0,0 +1,218 @@
+//! Boot splash on the framebuffer
+//!
+//! Loading the NDI library, waiting for the capture device and finding the
+//! display's source can take 10 s or more, and until then the monitor shows
+//! the console. The splash paints the name of the box with a progress line
+//! taken from the [`StartupEvent`]s. With a display configured, the display
+//! takes over the open framebuffer without clearing it and keeps the splash
+//! up until its first frame; without one, the last splash stays on screen.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::color_range::ColorRange;
+use crate::compositor::blend_over;
+use crate::display::{convert_uyvy_to_bgra, FramebufferDisplay};
+use crate::ndi_display::open_framebuffer_until;
+use crate::processing::TimestampBurnIn;
+use crate::startup::{Prerequisite, StartupEvent};
+use crate::threads;
+
+/// Framebuffer the splash uses without a display section
+pub const DEFAULT_FB_DEVICE: &str = "/dev/fb0";
+
+/// Progress line while the display looks for its NDI source
+pub const WAITING_FOR_SOURCE: &str = "waiting for source...";
+
+const TITLE: &str = "CAMERA BOX";
+
+/// Time between checks for a new startup event
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The progress line for `event`
+pub fn splash_message(event: &StartupEvent) -> String {
+    match event {
+        StartupEvent::Boot => "starting...".to_string(),
+        StartupEvent::Waiting { prerequisite, .. } => match prerequisite {
+            Prerequisite::NdiLibrary => "loading NDI...".to_string(),
+            Prerequisite::DeviceNode(path) => format!("opening {}...", path.display()),
+            Prerequisite::NetworkRoute => "waiting for network...".to_string(),
+            Prerequisite::AlsaCard(_) => "waiting for audio...".to_string(),
+        },
+        StartupEvent::Starting(step) => format!("starting {}...", step),
+        StartupEvent::Ready => "ready".to_string(),
+    }
+}
+
+/// `text` rendered at `scale` as BGRA, with its size
+fn text_bgra(text: &str, scale: u32) -> (Vec<u8>, u32, u32) {
+    let (width, height) = TimestampBurnIn::text_size(text, scale);
+    let mut uyvy = vec![0u8; width as usize * height as usize * 2];
+    TimestampBurnIn::render(&mut uyvy, width, height, text, 0, 0, scale);
+    (
+        convert_uyvy_to_bgra(&uyvy, width, height, ColorRange::Limited),
+        width,
+        height,
+    )
+}
+
+/// Splash frames for the framebuffer
+#[derive(Default)]
+pub struct SplashScreen {
+    /// Size and message on screen, to skip redrawing an unchanged splash
+    shown: Option<(u32, u32, String)>,
+    frame: Vec<u8>,
+}
+
+impl SplashScreen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The `width`x`height` BGRA splash with `message` under the title, or
+    /// None while the one last returned is still current
+    pub fn render(&mut self, width: u32, height: u32, message: &str) -> Option<&[u8]> {
+        let shown = (width, height, message.to_string());
+        if self.shown.as_ref() == Some(&shown) {
+            return None;
+        }
+
+        self.frame.resize(width as usize * height as usize * 4, 0);
+        for px in self.frame.chunks_exact_mut(4) {
+            px.copy_from_slice(&[0, 0, 0, 255]);
+        }
+        // Title centred a little above the middle, progress line below it
+        let title_scale = (height / 40).max(1);
+        let line_scale = (height / 120).max(1);
+        let (title, title_w, title_h) = text_bgra(TITLE, title_scale);
+        let (line, line_w, line_h) = text_bgra(message, line_scale);
+        let top = height.saturating_sub(title_h + line_h * 2) / 2;
+        for (text, text_w, text_h, y) in [
+            (&title, title_w, title_h, top),
+            (&line, line_w, line_h, top + title_h + line_h),
+        ] {
+            blend_over(
+                &mut self.frame,
+                text,
+                text_w,
+                text_h,
+                width,
+                height,
+                (width.saturating_sub(text_w) / 2) as i32,
+                y as i32,
+                1.0,
+            );
+        }
+        self.shown = Some(shown);
+        Some(&self.frame)
+    }
+}
+
+/// Draw the splash with `message`; a failed write is only logged, the
+/// next message tries again
+pub fn show_splash(display: &mut FramebufferDisplay, splash: &mut SplashScreen, message: &str) {
+    if let Err(e) = display.show_splash(splash, message) {
+        tracing::debug!("Splash write failed: {}", e);
+    }
+}
+
+/// The splash thread, showing startup progress until it is stopped or
+/// startup is ready
+pub struct SplashHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Option<FramebufferDisplay>>,
+}
+
+impl SplashHandle {
+    /// Stop the splash and take its framebuffer, still showing the last
+    /// splash; None if it never opened
+    pub fn finish(self) -> Option<FramebufferDisplay> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.thread.join().ok().flatten()
+    }
+}
+
+/// Show the progress published on `events` on `device`, opening it with
+/// the display's retries
+pub fn spawn_splash(
+    device: String,
+    events: watch::Receiver<StartupEvent>,
+    running: Arc<AtomicBool>,
+) -> SplashHandle {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = Arc::clone(&stop);
+    let thread = threads::spawn(threads::SPLASH, move || {
+        let ready = || *events.borrow() == StartupEvent::Ready;
+        let go_on = || running.load(Ordering::Relaxed) && !stopped.load(Ordering::Relaxed);
+        let mut display = open_framebuffer_until(&device, || go_on() && !ready())?;
+        let mut splash = SplashScreen::new();
+        loop {
+            let event = events.borrow().clone();
+            show_splash(&mut display, &mut splash, &splash_message(&event));
+            if event == StartupEvent::Ready || !go_on() {
+                break;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        Some(display)
+    });
+    SplashHandle { stop, thread }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_splash_message() {
+        let waiting = |prerequisite| StartupEvent::Waiting {
+            step: "capture",
+            prerequisite,
+        };
+        assert_eq!(splash_message(&StartupEvent::Boot), "starting...");
+        assert_eq!(
+            splash_message(&waiting(Prerequisite::NdiLibrary)),
+            "loading NDI..."
+        );
+        assert_eq!(
+            splash_message(&waiting(Prerequisite::DeviceNode(PathBuf::from(
+                "/dev/video0"
+            )))),
+            "opening /dev/video0..."
+        );
+        assert_eq!(
+            splash_message(&waiting(Prerequisite::NetworkRoute)),
+            "waiting for network..."
+        );
+        assert_eq!(
+            splash_message(&waiting(Prerequisite::AlsaCard("hw:CARD=HID".into()))),
+            "waiting for audio..."
+        );
+        assert_eq!(
+            splash_message(&StartupEvent::Starting("intercom")),
+            "starting intercom..."
+        );
+        assert_eq!(splash_message(&StartupEvent::Ready), "ready");
+    }
+
+    #[test]
+    fn test_splash_redraws_on_change() {
+        let mut splash = SplashScreen::new();
+        let first = splash.render(320, 180, "loading NDI...").unwrap().to_vec();
+        assert_eq!(first.len(), 320 * 180 * 4);
+        // White title and progress line on black
+        assert!(first.chunks_exact(4).any(|px| px[1] == 255));
+        assert_eq!(&first[..4], &[0, 0, 0, 255]);
+        assert!(splash.render(320, 180, "loading NDI...").is_none());
+
+        let next = splash.render(320, 180, WAITING_FOR_SOURCE).unwrap();
+        assert_ne!(next, first);
+        assert!(splash.render(640, 360, WAITING_FOR_SOURCE).is_some());
+        // Text wider than the screen is clipped
+        assert_eq!(splash.render(8, 4, "ready").unwrap().len(), 8 * 4 * 4);
+    }
+}
@@ -0,0 +1,270 @@
+//! Standby screen with burn-in protection
+//!
+//! While the HDMI display has no NDI video it shows the time (UTC, HH:MM)
+//! on black. Boxes can sit on standby for days, and a static picture burns
+//! into OLED field monitors, so the clock moves to another position every
+//! [`DRIFT_INTERVAL`] and, after `dim_after`, the whole screen is dimmed by
+//! a lookup table. Video coming back is shown at full brightness at once;
+//! the next standby starts undimmed.
+
+use std::time::{Duration, Instant};
+
+use crate::color_range::{range_table, ColorRange};
+use crate::compositor::blend_over;
+use crate::display::convert_uyvy_to_bgra;
+use crate::processing::TimestampBurnIn;
+
+/// Time the clock stays in one place
+pub const DRIFT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default time on standby before dimming
+pub const DEFAULT_DIM_AFTER: Duration = Duration::from_secs(600);
+
+/// Default brightness once dimmed
+pub const DEFAULT_DIM: f32 = 0.3;
+
+/// Positions per axis; coprime, so the clock visits every combination
+/// before repeating (176 steps, 88 minutes)
+const DRIFT_COLUMNS: u64 = 16;
+const DRIFT_ROWS: u64 = 11;
+
+/// When and how far the standby screen dims
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StandbySettings {
+    /// Brightness after `dim_after`, 0.0-1.0 (1.0 never dims)
+    pub dim: f32,
+    pub dim_after: Duration,
+}
+
+impl Default for StandbySettings {
+    fn default() -> Self {
+        Self {
+            dim: DEFAULT_DIM,
+            dim_after: DEFAULT_DIM_AFTER,
+        }
+    }
+}
+
+/// Top-left corner of the clock at drift `step`, for a clock that may move
+/// `range_x` and `range_y` pixels. Step 0 is the centre.
+pub fn drift_position(step: u64, range_x: u32, range_y: u32) -> (u32, u32) {
+    let column = (step * 5 + DRIFT_COLUMNS / 2) % DRIFT_COLUMNS;
+    let row = (step * 3 + DRIFT_ROWS / 2) % DRIFT_ROWS;
+    (
+        (range_x as u64 * column / (DRIFT_COLUMNS - 1)) as u32,
+        (range_y as u64 * row / (DRIFT_ROWS - 1)) as u32,
+    )
+}
+
+/// Lookup table scaling a channel to `factor` (0.0-1.0) of its value
+pub fn dim_table(factor: f32) -> [u8; 256] {
+    let span = (factor.clamp(0.0, 1.0) * 255.0).round() as i32;
+    range_table(0, span, 0)
+}
+
+/// Scale the color channels of BGRA pixels through `table`
+pub fn apply_table(bgra: &mut [u8], table: &[u8; 256]) {
+    for px in bgra.chunks_exact_mut(4) {
+        px[0] = table[px[0] as usize];
+        px[1] = table[px[1] as usize];
+        px[2] = table[px[2] as usize];
+    }
+}
+
+/// What is on screen, to skip redrawing an unchanged standby frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Shown {
+    width: u32,
+    height: u32,
+    step: u64,
+    dimmed: bool,
+    clock: String,
+}
+
+/// Standby frames for the framebuffer, timed from the start of standby
+pub struct StandbyScreen {
+    settings: StandbySettings,
+    dim: [u8; 256],
+    since: Option<Instant>,
+    shown: Option<Shown>,
+    frame: Vec<u8>,
+}
+
+impl StandbyScreen {
+    pub fn new(settings: StandbySettings) -> Self {
+        Self {
+            settings,
+            dim: dim_table(settings.dim),
+            since: None,
+            shown: None,
+            frame: Vec::new(),
+        }
+    }
+
+    /// Whether standby is showing
+    pub fn is_active(&self) -> bool {
+        self.since.is_some()
+    }
+
+    /// Video is back: the next standby starts centred and undimmed
+    pub fn reset(&mut self) {
+        self.since = None;
+        self.shown = None;
+    }
+
+    /// The `width`x`height` BGRA standby frame showing `clock` at `now`
+    /// (standby starts at the first call after a reset), or None while the
+    /// one last returned is still current
+    pub fn render(&mut self, width: u32, height: u32, now: Instant, clock: &str) -> Option<&[u8]> {
+        let elapsed = now.saturating_duration_since(*self.since.get_or_insert(now));
+        let shown = Shown {
+            width,
+            height,
+            step: elapsed.as_secs() / DRIFT_INTERVAL.as_secs(),
+            dimmed: self.settings.dim < 1.0 && elapsed >= self.settings.dim_after,
+            clock: clock.to_string(),
+        };
+        if self.shown.as_ref() == Some(&shown) {
+            return None;
+        }
+
+        // Clock at ~1/9 of the screen height, drawn as UYVY like the burn-in
+        let scale = (height / 64).max(1);
+        let (text_w, text_h) = TimestampBurnIn::text_size(clock, scale);
+        let mut text = vec![0u8; text_w as usize * text_h as usize * 2];
+        TimestampBurnIn::render(&mut text, text_w, text_h, clock, 0, 0, scale);
+        let text = convert_uyvy_to_bgra(&text, text_w, text_h, ColorRange::Limited);
+
+        self.frame.resize(width as usize * height as usize * 4, 0);
+        for px in self.frame.chunks_exact_mut(4) {
+            px.copy_from_slice(&[0, 0, 0, 255]);
+        }
+        let (x, y) = drift_position(
+            shown.step,
+            width.saturating_sub(text_w),
+            height.saturating_sub(text_h),
+        );
+        blend_over(
+            &mut self.frame,
+            &text,
+            text_w,
+            text_h,
+            width,
+            height,
+            x as i32,
+            y as i32,
+            1.0,
+        );
+        if shown.dimmed {
+            apply_table(&mut self.frame, &self.dim);
+        }
+        self.shown = Some(shown);
+        Some(&self.frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drift_stays_within_bounds() {
+        for (range_x, range_y) in [(0, 0), (1, 1), (1800, 950), (7, 3), (u32::MAX, u32::MAX)] {
+            let mut positions = std::collections::HashSet::new();
+            for step in 0..DRIFT_COLUMNS * DRIFT_ROWS * 2 {
+                let (x, y) = drift_position(step, range_x, range_y);
+                assert!(x <= range_x && y <= range_y, "step {}", step);
+                positions.insert((x, y));
+            }
+            if range_x >= 1000 {
+                // Every column and row combination shows up
+                assert_eq!(positions.len() as u64, DRIFT_COLUMNS * DRIFT_ROWS);
+            }
+        }
+        // Starts centred, and never stays put
+        assert_eq!(drift_position(0, 1500, 1000), (800, 500));
+        for step in 0..200 {
+            assert_ne!(
+                drift_position(step, 1500, 1000),
+                drift_position(step + 1, 1500, 1000)
+            );
+        }
+    }
+
+    #[test]
+    fn test_dim_table() {
+        let table = dim_table(0.3);
+        assert_eq!(table[0], 0);
+        assert_eq!(table[255], 77);
+        assert_eq!(table[128], 39);
+        assert!(table.windows(2).all(|w| w[0] <= w[1]));
+
+        let identity: Vec<u8> = (0..=255).collect();
+        assert_eq!(dim_table(1.0).to_vec(), identity);
+        assert_eq!(dim_table(2.0).to_vec(), identity);
+        assert!(dim_table(0.0).iter().all(|&v| v == 0));
+
+        let mut bgra = [255, 128, 10, 255];
+        apply_table(&mut bgra, &dim_table(0.5));
+        // Alpha is left alone
+        assert_eq!(bgra, [128, 64, 5, 255]);
+    }
+
+    fn brightest(frame: &[u8]) -> u8 {
+        frame.chunks_exact(4).map(|px| px[1]).max().unwrap()
+    }
+
+    #[test]
+    fn test_standby_drifts_and_dims() {
+        let (width, height) = (320, 180);
+        let mut standby = StandbyScreen::new(StandbySettings::default());
+        assert!(!standby.is_active());
+        let start = Instant::now();
+
+        let first = standby
+            .render(width, height, start, "12:34")
+            .unwrap()
+            .to_vec();
+        assert!(standby.is_active());
+        assert_eq!(first.len(), (width * height * 4) as usize);
+        assert_eq!(brightest(&first), 255);
+        // Unchanged until the clock moves
+        assert!(standby
+            .render(width, height, start + Duration::from_secs(29), "12:34")
+            .is_none());
+        let moved = standby
+            .render(width, height, start + DRIFT_INTERVAL, "12:34")
+            .unwrap()
+            .to_vec();
+        assert_ne!(moved, first);
+        assert_eq!(brightest(&moved), 255);
+
+        let dimmed = standby
+            .render(width, height, start + DEFAULT_DIM_AFTER, "12:34")
+            .unwrap();
+        assert_eq!(brightest(dimmed), 77);
+
+        // Video came back: full brightness and centred again
+        standby.reset();
+        let later = start + Duration::from_secs(3600);
+        let again = standby.render(width, height, later, "12:34").unwrap();
+        assert_eq!(again, first);
+    }
+
+    #[test]
+    fn test_standby_redraws_for_clock_and_mode() {
+        let mut standby = StandbyScreen::new(StandbySettings {
+            dim: 1.0,
+            dim_after: Duration::ZERO,
+        });
+        let now = Instant::now();
+        assert!(standby.render(64, 32, now, "12:34").is_some());
+        assert!(standby.render(64, 32, now, "12:35").is_some());
+        assert!(standby.render(128, 64, now, "12:35").is_some());
+        // A dim of 1.0 never dims
+        let frame = standby.render(128, 64, now, "12:36").unwrap();
+        assert_eq!(brightest(frame), 255);
+        // A clock larger than the screen is clipped
+        assert_eq!(standby.render(8, 4, now, "12:36").unwrap().len(), 8 * 4 * 4);
+    }
+}
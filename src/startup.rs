@@ -0,0 +1,559 @@
+//! Startup ordering and readiness gating
+//!
+//! At boot the network, the capture dongle and the headset enumerate in no
+//! particular order. Each component is a [`Step`] naming the
+//! [`Prerequisite`]s it needs and the steps it starts after; [`resolve`]
+//! orders the steps and [`Startup`] polls each prerequisite briefly before
+//! its step starts, so a slow boot logs one "waiting" line instead of a
+//! burst of errors. A prerequisite still missing at its timeout is reported
+//! and the component started anyway, on its own retries. Every step lands
+//! on a [`Timeline`], logged when the first frame is out, and progress goes
+//! out as [`StartupEvent`]s for the boot splash.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Time between checks of a missing prerequisite
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Something a component needs before it can start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Prerequisite {
+    /// A route over an interface other than loopback
+    NetworkRoute,
+    /// A device node, e.g. /dev/video0
+    DeviceNode(PathBuf),
+    /// The card of an ALSA device string, e.g. "hw:CARD=HID,DEV=0"
+    AlsaCard(String),
+    /// The NDI runtime library can be opened
+    NdiLibrary,
+}
+
+impl fmt::Display for Prerequisite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Prerequisite::NetworkRoute => write!(f, "network route"),
+            Prerequisite::DeviceNode(path) => write!(f, "{}", path.display()),
+            Prerequisite::AlsaCard(device) => write!(f, "ALSA card of {}", device),
+            Prerequisite::NdiLibrary => write!(f, "NDI library"),
+        }
+    }
+}
+
+impl Prerequisite {
+    /// Check the live system
+    pub fn is_met(&self) -> bool {
+        match self {
+            Prerequisite::NetworkRoute => {
+                let read = |path| std::fs::read_to_string(path).unwrap_or_default();
+                has_route(&read("/proc/net/route"), &read("/proc/net/ipv6_route"))
+            }
+            Prerequisite::DeviceNode(path) => path.exists(),
+            // Names not bound to a card, like "null", are always there
+            Prerequisite::AlsaCard(device) => {
+                crate::config::alsa_card_present(Path::new("/proc/asound"), device).unwrap_or(true)
+            }
+            Prerequisite::NdiLibrary => crate::ndi::library_loadable().is_ok(),
+        }
+    }
+}
+
+/// Whether the kernel routing tables (`/proc/net/route` and
+/// `/proc/net/ipv6_route`) hold a usable route: an IPv4 route that is up,
+/// or a non link-local IPv6 one, over an interface other than loopback
+pub fn has_route(ipv4: &str, ipv6: &str) -> bool {
+    const RTF_UP: u32 = 0x1;
+    let ipv4_route = ipv4.lines().skip(1).any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        fields.len() > 3
+            && fields[0] != "lo"
+            && u32::from_str_radix(fields[3], 16).is_ok_and(|flags| flags & RTF_UP != 0)
+    });
+    let ipv6_route = ipv6.lines().any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        fields.len() == 10
+            && fields[9] != "lo"
+            && !fields[0].starts_with("fe80")
+            && !fields[0].starts_with("ff")
+    });
+    ipv4_route || ipv6_route
+}
+
+/// Longest wait per kind of prerequisite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    pub network: Duration,
+    pub device: Duration,
+    pub audio: Duration,
+    pub ndi: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            network: Duration::from_secs(30),
+            device: Duration::from_secs(10),
+            audio: Duration::from_secs(10),
+            ndi: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn of(&self, prerequisite: &Prerequisite) -> Duration {
+        match prerequisite {
+            Prerequisite::NetworkRoute => self.network,
+            Prerequisite::DeviceNode(_) => self.device,
+            Prerequisite::AlsaCard(_) => self.audio,
+            Prerequisite::NdiLibrary => self.ndi,
+        }
+    }
+}
+
+/// A component to start, e.g. "capture" or "intercom"
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub name: &'static str,
+    pub prerequisites: Vec<Prerequisite>,
+    /// Steps that must have started first
+    pub after: Vec<&'static str>,
+}
+
+impl Step {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            prerequisites: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+
+    pub fn requires(mut self, prerequisite: Prerequisite) -> Self {
+        if !self.prerequisites.contains(&prerequisite) {
+            self.prerequisites.push(prerequisite);
+        }
+        self
+    }
+
+    pub fn after(mut self, step: &'static str) -> Self {
+        self.after.push(step);
+        self
+    }
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum StartupError {
+    #[error("startup step {0} is declared twice")]
+    Duplicate(&'static str),
+    #[error("startup step {step} starts after unknown step {after}")]
+    UnknownStep {
+        step: &'static str,
+        after: &'static str,
+    },
+    /// The steps that could not be placed
+    #[error("startup steps wait on each other: {}", .0.join(", "))]
+    Cycle(Vec<&'static str>),
+}
+
+/// Order `steps` so each starts after those it names. Steps free to start
+/// keep their declaration order.
+pub fn resolve(steps: &[Step]) -> Result<Vec<&'static str>, StartupError> {
+    for (i, step) in steps.iter().enumerate() {
+        if steps[..i].iter().any(|s| s.name == step.name) {
+            return Err(StartupError::Duplicate(step.name));
+        }
+        if let Some(after) = step
+            .after
+            .iter()
+            .find(|after| !steps.iter().any(|s| s.name == **after))
+        {
+            return Err(StartupError::UnknownStep {
+                step: step.name,
+                after: *after,
+            });
+        }
+    }
+
+    let mut order: Vec<&'static str> = Vec::with_capacity(steps.len());
+    while order.len() < steps.len() {
+        let next = steps.iter().find(|step| {
+            !order.contains(&step.name) && step.after.iter().all(|after| order.contains(after))
+        });
+        match next {
+            Some(step) => order.push(step.name),
+            None => {
+                let stuck = steps
+                    .iter()
+                    .map(|step| step.name)
+                    .filter(|name| !order.contains(name))
+                    .collect();
+                return Err(StartupError::Cycle(stuck));
+            }
+        }
+    }
+    Ok(order)
+}
+
+/// What happened when during startup, relative to its start
+#[derive(Debug, Clone)]
+pub struct Timeline {
+    start: Instant,
+    entries: Vec<(Duration, String)>,
+}
+
+impl Timeline {
+    pub fn new(start: Instant) -> Self {
+        Self {
+            start,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn mark(&mut self, now: Instant, event: impl Into<String>) {
+        self.entries
+            .push((now.saturating_duration_since(self.start), event.into()));
+    }
+
+    pub fn entries(&self) -> &[(Duration, String)] {
+        &self.entries
+    }
+
+    /// One line per entry, e.g. "+1.250s intercom started"
+    pub fn lines(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .map(|(at, event)| format!("+{:.3}s {}", at.as_secs_f64(), event))
+            .collect()
+    }
+
+    pub fn log(&self) {
+        tracing::info!("Startup timeline:");
+        for line in self.lines() {
+            tracing::info!("  {}", line);
+        }
+    }
+}
+
+/// How far startup got, as last published by [`Startup`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartupEvent {
+    /// Nothing started yet
+    Boot,
+    /// `step` waits for a missing `prerequisite`
+    Waiting {
+        step: &'static str,
+        prerequisite: Prerequisite,
+    },
+    /// `step` is starting
+    Starting(&'static str),
+    /// Every step has started
+    Ready,
+}
+
+type Check = Box<dyn Fn(&Prerequisite) -> bool + Send + Sync>;
+
+/// Waits for the prerequisites of each step and keeps the timeline
+pub struct Startup {
+    timeouts: Timeouts,
+    check: Check,
+    timeline: Timeline,
+    events: watch::Sender<StartupEvent>,
+}
+
+impl Startup {
+    pub fn new(timeouts: Timeouts) -> Self {
+        Self::with_check(timeouts, Box::new(Prerequisite::is_met))
+    }
+
+    fn with_check(timeouts: Timeouts, check: Check) -> Self {
+        Self {
+            timeouts,
+            check,
+            timeline: Timeline::new(Instant::now()),
+            events: watch::channel(StartupEvent::Boot).0,
+        }
+    }
+
+    /// The latest [`StartupEvent`], updated as startup goes on
+    pub fn subscribe(&self) -> watch::Receiver<StartupEvent> {
+        self.events.subscribe()
+    }
+
+    /// Wait until every prerequisite of `step` is met or has timed out,
+    /// then publish that it starts. Returns the prerequisites still missing.
+    pub async fn wait(&mut self, step: &Step) -> Vec<Prerequisite> {
+        let mut missing = Vec::new();
+        for prerequisite in &step.prerequisites {
+            if (self.check)(prerequisite) {
+                continue;
+            }
+            self.events.send_replace(StartupEvent::Waiting {
+                step: step.name,
+                prerequisite: prerequisite.clone(),
+            });
+            let timeout = self.timeouts.of(prerequisite);
+            tracing::info!(
+                "{}: waiting up to {}s for {}",
+                step.name,
+                timeout.as_secs(),
+                prerequisite
+            );
+            let started = Instant::now();
+            let met = loop {
+                if started.elapsed() >= timeout {
+                    break false;
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+                if (self.check)(prerequisite) {
+                    break true;
+                }
+            };
+            if met {
+                self.timeline
+                    .mark(Instant::now(), format!("{} ready", prerequisite));
+            } else {
+                tracing::warn!(
+                    "{}: {} still missing after {}s, starting anyway",
+                    step.name,
+                    prerequisite,
+                    timeout.as_secs()
+                );
+                self.timeline
+                    .mark(Instant::now(), format!("{} missing", prerequisite));
+                missing.push(prerequisite.clone());
+            }
+        }
+        self.events.send_replace(StartupEvent::Starting(step.name));
+        missing
+    }
+
+    /// Record that the step `name` started
+    pub fn started(&mut self, name: &str) {
+        self.timeline
+            .mark(Instant::now(), format!("{} started", name));
+    }
+
+    /// Record that every step has started
+    pub fn finished(&mut self) {
+        self.events.send_replace(StartupEvent::Ready);
+    }
+
+    pub fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    fn main_steps() -> Vec<Step> {
+        vec![
+            Step::new("display").after("capture"),
+            Step::new("intercom"),
+            Step::new("control"),
+            Step::new("mdns").after("capture"),
+            Step::new("capture").after("control"),
+        ]
+    }
+
+    #[test]
+    fn test_resolve_orders_dependencies_first() {
+        assert_eq!(
+            resolve(&main_steps()).unwrap(),
+            ["intercom", "control", "capture", "display", "mdns"]
+        );
+        // Without dependencies the declaration order is kept
+        let free = [Step::new("b"), Step::new("a"), Step::new("c")];
+        assert_eq!(resolve(&free).unwrap(), ["b", "a", "c"]);
+        assert_eq!(resolve(&[]).unwrap(), Vec::<&str>::new());
+
+        // A chain declared backwards
+        let chain = [
+            Step::new("c").after("b"),
+            Step::new("b").after("a"),
+            Step::new("a"),
+        ];
+        assert_eq!(resolve(&chain).unwrap(), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let cycle = [
+            Step::new("x"),
+            Step::new("a").after("b"),
+            Step::new("b").after("a"),
+            Step::new("c").after("a"),
+        ];
+        let error = resolve(&cycle).unwrap_err();
+        assert_eq!(error, StartupError::Cycle(vec!["a", "b", "c"]));
+        assert_eq!(
+            error.to_string(),
+            "startup steps wait on each other: a, b, c"
+        );
+        assert_eq!(
+            resolve(&[Step::new("a").after("a")]).unwrap_err(),
+            StartupError::Cycle(vec!["a"])
+        );
+        assert_eq!(
+            resolve(&[Step::new("a").after("gone")]).unwrap_err(),
+            StartupError::UnknownStep {
+                step: "a",
+                after: "gone"
+            }
+        );
+        assert_eq!(
+            resolve(&[Step::new("a"), Step::new("a")]).unwrap_err(),
+            StartupError::Duplicate("a")
+        );
+    }
+
+    #[test]
+    fn test_has_route() {
+        let ipv4_header = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n";
+        let lan = format!(
+            "{}eth0\t0009A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n",
+            ipv4_header
+        );
+        assert!(has_route(&lan, ""));
+        // Down or loopback only: no network yet
+        let down = format!(
+            "{}eth0\t0009A8C0\t00000000\t0000\t0\t0\t0\t00FFFFFF\n",
+            ipv4_header
+        );
+        assert!(!has_route(&down, ""));
+        assert!(!has_route(ipv4_header, ""));
+        assert!(!has_route("", ""));
+
+        let link_local = "fe800000000000000000000000000000 40 00000000000000000000000000000000 00 \
+            00000000000000000000000000000000 00000100 00000001 00000000 00000001 eth0\n\
+            00000000000000000000000000000001 80 00000000000000000000000000000000 00 \
+            00000000000000000000000000000000 00000000 00000002 00000000 80200001 lo\n";
+        assert!(!has_route(ipv4_header, link_local));
+        let global = "fd000000000000000000000000000000 40 00000000000000000000000000000000 00 \
+            00000000000000000000000000000000 00000100 00000001 00000000 00000001 eth0\n";
+        assert!(has_route(ipv4_header, global));
+    }
+
+    #[test]
+    fn test_prerequisite_display_and_timeouts() {
+        let video = Prerequisite::DeviceNode(PathBuf::from("/dev/video0"));
+        assert_eq!(video.to_string(), "/dev/video0");
+        assert_eq!(Prerequisite::NetworkRoute.to_string(), "network route");
+        let timeouts = Timeouts {
+            audio: Duration::from_secs(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            timeouts.of(&Prerequisite::AlsaCard("hw:CARD=HID".into())),
+            Duration::from_secs(3)
+        );
+        assert_eq!(timeouts.of(&video), Duration::from_secs(10));
+        // Names that aren't a card never hold up startup
+        assert!(Prerequisite::AlsaCard("null".into()).is_met());
+        assert!(!Prerequisite::DeviceNode(PathBuf::from("/dev/camera-box-none")).is_met());
+        // Declaring one twice checks it once
+        let step = Step::new("capture").requires(video.clone()).requires(video);
+        assert_eq!(step.prerequisites.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_wait_polls_until_met_or_timeout() {
+        let checks = Arc::new(AtomicU32::new(0));
+        let counted = Arc::clone(&checks);
+        // The route shows up on the third check, the card never
+        let check: Check = Box::new(move |prerequisite| match prerequisite {
+            Prerequisite::NetworkRoute => counted.fetch_add(1, Ordering::Relaxed) >= 2,
+            Prerequisite::AlsaCard(_) => false,
+            _ => true,
+        });
+        let timeouts = Timeouts {
+            network: Duration::from_secs(5),
+            audio: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut startup = Startup::with_check(timeouts, check);
+        let step = Step::new("intercom")
+            .requires(Prerequisite::NdiLibrary)
+            .requires(Prerequisite::NetworkRoute)
+            .requires(Prerequisite::AlsaCard("hw:CARD=HID".into()));
+        let missing = startup.wait(&step).await;
+        startup.started("intercom");
+
+        assert_eq!(missing, [Prerequisite::AlsaCard("hw:CARD=HID".into())]);
+        assert_eq!(checks.load(Ordering::Relaxed), 3);
+        let events: Vec<&str> = startup
+            .timeline()
+            .entries()
+            .iter()
+            .map(|(_, event)| event.as_str())
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "network route ready",
+                "ALSA card of hw:CARD=HID missing",
+                "intercom started"
+            ]
+        );
+        assert!(startup.timeline().entries()[0].0 >= POLL_INTERVAL * 2);
+    }
+
+    #[tokio::test]
+    async fn test_wait_publishes_events() {
+        // What was published while the network route was checked again
+        let events: Arc<Mutex<Option<watch::Receiver<StartupEvent>>>> = Arc::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let (events_in_check, seen_in_check) = (Arc::clone(&events), Arc::clone(&seen));
+        let check: Check = Box::new(move |prerequisite| {
+            if let Some(events) = events_in_check.lock().unwrap().as_ref() {
+                seen_in_check.lock().unwrap().push(events.borrow().clone());
+            }
+            *prerequisite != Prerequisite::NetworkRoute
+        });
+        // One more check after the first poll, then it times out
+        let timeouts = Timeouts {
+            network: POLL_INTERVAL,
+            ..Default::default()
+        };
+        let mut startup = Startup::with_check(timeouts, check);
+        let updates = startup.subscribe();
+        assert_eq!(*updates.borrow(), StartupEvent::Boot);
+        *events.lock().unwrap() = Some(startup.subscribe());
+
+        let step = Step::new("capture")
+            .requires(Prerequisite::NdiLibrary)
+            .requires(Prerequisite::NetworkRoute);
+        startup.wait(&step).await;
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [
+                StartupEvent::Boot,
+                StartupEvent::Boot,
+                StartupEvent::Waiting {
+                    step: "capture",
+                    prerequisite: Prerequisite::NetworkRoute
+                }
+            ]
+        );
+        assert_eq!(*updates.borrow(), StartupEvent::Starting("capture"));
+        startup.finished();
+        assert_eq!(*updates.borrow(), StartupEvent::Ready);
+    }
+
+    #[test]
+    fn test_timeline_lines() {
+        let start = Instant::now();
+        let mut timeline = Timeline::new(start);
+        timeline.mark(start + Duration::from_millis(1250), "intercom started");
+        timeline.mark(start + Duration::from_secs(3), "first frame");
+        assert_eq!(
+            timeline.lines(),
+            ["+1.250s intercom started", "+3.000s first frame"]
+        );
+    }
+}
@@ -0,0 +1,416 @@
+//! Periodic stats report
+//!
+//! Components register the counters they already keep (atomics in their
+//! stats structs) and gauges under a component name with a
+//! [`StatsRegistry`]. One [`StatsTicker`] samples them all every interval
+//! and logs a single line, with rates computed from counter deltas, so
+//! capture, display, intercom and the serial bridge report in step. The
+//! latest report is kept for `stats` on the control socket.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Seconds between reports
+pub const DEFAULT_INTERVAL_SECS: u64 = 10;
+
+/// A sampled metric
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    /// Monotonic count, reported with its rate
+    Counter(u64),
+    /// Current level, e.g. a buffer depth; NaN while unknown
+    Gauge(f64),
+}
+
+type Reader = Box<dyn Fn() -> Value + Send + Sync>;
+
+type FamilyReader = Box<dyn Fn() -> Vec<(String, Value)> + Send + Sync>;
+
+enum Metric {
+    Named(&'static str, Reader),
+    /// Metrics whose names are only known when read
+    Family(FamilyReader),
+}
+
+/// Named metrics of one component, registered together
+pub struct Component {
+    name: String,
+    metrics: Vec<Metric>,
+}
+
+impl Component {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            metrics: Vec::new(),
+        }
+    }
+
+    /// A counter kept in an atomic of `owner`, e.g.
+    /// `.counter("frames", &stats, |s| &s.frames_captured)`
+    pub fn counter<T: Send + Sync + 'static>(
+        mut self,
+        name: &'static str,
+        owner: &Arc<T>,
+        field: fn(&T) -> &AtomicU64,
+    ) -> Self {
+        let owner = Arc::clone(owner);
+        let read = move || Value::Counter(field(&owner).load(Ordering::Relaxed));
+        self.metrics.push(Metric::Named(name, Box::new(read)));
+        self
+    }
+
+    /// A gauge read by `read` at every report
+    pub fn gauge(
+        mut self,
+        name: &'static str,
+        read: impl Fn() -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        self.metrics
+            .push(Metric::Named(name, Box::new(move || Value::Gauge(read()))));
+        self
+    }
+
+    /// Metrics named when read, e.g. one set per pixel format seen so far;
+    /// `read` returns them as (name, value) pairs
+    pub fn family(
+        mut self,
+        read: impl Fn() -> Vec<(String, Value)> + Send + Sync + 'static,
+    ) -> Self {
+        self.metrics.push(Metric::Family(Box::new(read)));
+        self
+    }
+}
+
+/// Point-in-time values of every registered metric, keyed
+/// "component.metric" in registration order
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub at: Instant,
+    pub values: Vec<(String, Value)>,
+}
+
+impl Snapshot {
+    pub fn get(&self, key: &str) -> Option<Value> {
+        self.values.iter().find(|(k, _)| k == key).map(|(_, v)| *v)
+    }
+}
+
+/// One metric of a [`Report`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub key: String,
+    pub value: Value,
+    /// Per second since the previous snapshot, for counters present in both
+    pub rate: Option<f64>,
+}
+
+/// Metrics with rates over one interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub interval: Duration,
+    pub readings: Vec<Reading>,
+}
+
+impl Report {
+    /// Readings of `current`, counters with their rate since `previous`.
+    /// Counters that went backwards (a component registered anew) count
+    /// from zero.
+    pub fn between(previous: &Snapshot, current: &Snapshot) -> Self {
+        let interval = current.at.saturating_duration_since(previous.at);
+        let secs = interval.as_secs_f64().max(f64::EPSILON);
+        let readings = current
+            .values
+            .iter()
+            .map(|(key, value)| {
+                let rate = match (value, previous.get(key)) {
+                    (Value::Counter(now), Some(Value::Counter(before))) => {
+                        let delta = if *now >= before { now - before } else { *now };
+                        Some(delta as f64 / secs)
+                    }
+                    _ => None,
+                };
+                Reading {
+                    key: key.clone(),
+                    value: *value,
+                    rate,
+                }
+            })
+            .collect();
+        Self { interval, readings }
+    }
+
+    pub fn reading(&self, key: &str) -> Option<&Reading> {
+        self.readings.iter().find(|r| r.key == key)
+    }
+
+    /// Rate of the counter `key`, once it has one
+    pub fn rate(&self, key: &str) -> Option<f64> {
+        self.reading(key).and_then(|r| r.rate)
+    }
+
+    /// `key=value` pairs, counters followed by `key.rate=per_second`
+    pub fn line(&self) -> String {
+        let mut fields = Vec::with_capacity(self.readings.len() * 2);
+        for reading in &self.readings {
+            match reading.value {
+                Value::Counter(count) => fields.push(format!("{}={}", reading.key, count)),
+                Value::Gauge(level) => {
+                    fields.push(format!("{}={}", reading.key, format_gauge(level)))
+                }
+            }
+            if let Some(rate) = reading.rate {
+                fields.push(format!("{}.rate={:.1}", reading.key, rate));
+            }
+        }
+        fields.join(" ")
+    }
+}
+
+fn format_gauge(level: f64) -> String {
+    if level.is_nan() {
+        "n/a".to_string()
+    } else if level.fract() == 0.0 {
+        format!("{:.0}", level)
+    } else {
+        format!("{:.1}", level)
+    }
+}
+
+/// Components whose metrics are reported
+#[derive(Default)]
+pub struct StatsRegistry {
+    components: Mutex<Vec<Component>>,
+    latest: Mutex<Option<Report>>,
+}
+
+impl StatsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn components(&self) -> MutexGuard<'_, Vec<Component>> {
+        self.components.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Add `component`, replacing one registered under the same name
+    pub fn register(&self, component: Component) {
+        let mut components = self.components();
+        components.retain(|c| c.name != component.name);
+        components.push(component);
+    }
+
+    /// Stop reporting the component `name`, if registered
+    pub fn unregister(&self, name: &str) {
+        self.components().retain(|c| c.name != name);
+    }
+
+    /// Read every registered metric
+    pub fn snapshot(&self, at: Instant) -> Snapshot {
+        let values = self
+            .components()
+            .iter()
+            .flat_map(|component| {
+                component.metrics.iter().flat_map(|metric| match metric {
+                    Metric::Named(name, read) => {
+                        vec![(format!("{}.{}", component.name, name), read())]
+                    }
+                    Metric::Family(read) => read()
+                        .into_iter()
+                        .map(|(name, value)| (format!("{}.{}", component.name, name), value))
+                        .collect(),
+                })
+            })
+            .collect();
+        Snapshot { at, values }
+    }
+
+    /// Report of the last tick
+    pub fn latest(&self) -> Option<Report> {
+        self.latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// Produces one report per interval from a registry
+pub struct StatsTicker {
+    registry: Arc<StatsRegistry>,
+    previous: Snapshot,
+}
+
+impl StatsTicker {
+    /// Rates of the first report count from `now`
+    pub fn new(registry: Arc<StatsRegistry>, now: Instant) -> Self {
+        let previous = registry.snapshot(now);
+        Self { registry, previous }
+    }
+
+    /// Sample every component, log the report as one line and keep it as
+    /// the latest
+    pub fn tick(&mut self, now: Instant) -> Report {
+        let current = self.registry.snapshot(now);
+        let report = Report::between(&self.previous, &current);
+        self.previous = current;
+        if !report.readings.is_empty() {
+            tracing::info!(
+                target: "camera_box::stats",
+                interval_secs = report.interval.as_secs(),
+                "{}",
+                report.line()
+            );
+        }
+        *self
+            .registry
+            .latest
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(report.clone());
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Counters {
+        frames: AtomicU64,
+        errors: AtomicU64,
+    }
+
+    fn capture(counters: &Arc<Counters>) -> Component {
+        Component::new("capture")
+            .counter("frames", counters, |c| &c.frames)
+            .counter("errors", counters, |c| &c.errors)
+    }
+
+    #[test]
+    fn test_registration() {
+        let registry = StatsRegistry::new();
+        let counters = Arc::new(Counters::default());
+        counters.frames.store(7, Ordering::Relaxed);
+        registry.register(capture(&counters));
+        registry.register(Component::new("intercom").gauge("buffer", || 480.0));
+
+        let snapshot = registry.snapshot(Instant::now());
+        let keys: Vec<&str> = snapshot.values.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(
+            keys,
+            ["capture.frames", "capture.errors", "intercom.buffer"]
+        );
+        assert_eq!(snapshot.get("capture.frames"), Some(Value::Counter(7)));
+        assert_eq!(snapshot.get("intercom.buffer"), Some(Value::Gauge(480.0)));
+
+        // Registering a name again replaces the component
+        registry.register(Component::new("capture").gauge("fps", || 30.0));
+        let snapshot = registry.snapshot(Instant::now());
+        assert_eq!(snapshot.get("capture.frames"), None);
+        assert_eq!(snapshot.get("capture.fps"), Some(Value::Gauge(30.0)));
+    }
+
+    #[test]
+    fn test_family_metrics_are_named_when_read() {
+        let registry = StatsRegistry::new();
+        let counters = Arc::new(Counters::default());
+        let seen = Arc::clone(&counters);
+        registry.register(
+            Component::new("ndi")
+                .counter("frames", &counters, |c| &c.frames)
+                .family(move || {
+                    (0..seen.errors.load(Ordering::Relaxed))
+                        .map(|i| (format!("format{}", i), Value::Counter(i)))
+                        .collect()
+                }),
+        );
+        let keys = |snapshot: Snapshot| -> Vec<String> {
+            snapshot.values.into_iter().map(|(k, _)| k).collect()
+        };
+        assert_eq!(keys(registry.snapshot(Instant::now())), ["ndi.frames"]);
+
+        counters.errors.store(2, Ordering::Relaxed);
+        let snapshot = registry.snapshot(Instant::now());
+        assert_eq!(snapshot.get("ndi.format1"), Some(Value::Counter(1)));
+        assert_eq!(keys(snapshot), ["ndi.frames", "ndi.format0", "ndi.format1"]);
+    }
+
+    #[test]
+    fn test_rates_from_counter_deltas() {
+        let registry = Arc::new(StatsRegistry::new());
+        let counters = Arc::new(Counters::default());
+        registry.register(capture(&counters));
+        let start = Instant::now();
+        let mut ticker = StatsTicker::new(Arc::clone(&registry), start);
+
+        counters.frames.store(300, Ordering::Relaxed);
+        counters.errors.store(2, Ordering::Relaxed);
+        let report = ticker.tick(start + Duration::from_secs(10));
+        assert_eq!(report.interval, Duration::from_secs(10));
+        assert_eq!(report.rate("capture.frames"), Some(30.0));
+        assert_eq!(report.rate("capture.errors"), Some(0.2));
+        assert_eq!(
+            report.line(),
+            "capture.frames=300 capture.frames.rate=30.0 capture.errors=2 capture.errors.rate=0.2"
+        );
+        assert_eq!(registry.latest(), Some(report));
+
+        // The next interval counts from the last tick
+        counters.frames.store(450, Ordering::Relaxed);
+        let report = ticker.tick(start + Duration::from_secs(15));
+        assert_eq!(report.rate("capture.frames"), Some(30.0));
+        assert_eq!(report.rate("capture.errors"), Some(0.0));
+    }
+
+    #[test]
+    fn test_new_and_reset_counters() {
+        let start = Instant::now();
+        let previous = Snapshot {
+            at: start,
+            values: vec![("a.n".to_string(), Value::Counter(100))],
+        };
+        let current = Snapshot {
+            at: start + Duration::from_secs(2),
+            values: vec![
+                ("a.n".to_string(), Value::Counter(10)),
+                ("b.n".to_string(), Value::Counter(5)),
+                ("b.level".to_string(), Value::Gauge(f64::NAN)),
+            ],
+        };
+        let report = Report::between(&previous, &current);
+        // Went backwards: counted from zero
+        assert_eq!(report.rate("a.n"), Some(5.0));
+        // No previous value, no rate yet
+        assert_eq!(report.rate("b.n"), None);
+        assert_eq!(report.rate("b.level"), None);
+        assert_eq!(report.line(), "a.n=10 a.n.rate=5.0 b.n=5 b.level=n/a");
+    }
+
+    #[test]
+    fn test_unregistered_components_dont_break_the_ticker() {
+        let registry = Arc::new(StatsRegistry::new());
+        let start = Instant::now();
+        // Nothing registered at all
+        let mut ticker = StatsTicker::new(Arc::clone(&registry), start);
+        assert!(ticker
+            .tick(start + Duration::from_secs(1))
+            .readings
+            .is_empty());
+
+        let counters = Arc::new(Counters::default());
+        registry.register(capture(&counters));
+        registry.unregister("capture");
+        registry.unregister("never-registered");
+        let report = ticker.tick(start + Duration::from_secs(2));
+        assert!(report.readings.is_empty());
+        assert_eq!(report.line(), "");
+
+        // Back again after being gone for a tick: no rate until the next one
+        registry.register(capture(&counters));
+        let report = ticker.tick(start + Duration::from_secs(3));
+        assert_eq!(report.rate("capture.frames"), None);
+        let report = ticker.tick(start + Duration::from_secs(4));
+        assert_eq!(report.rate("capture.frames"), Some(0.0));
+    }
+}
@@ -0,0 +1,685 @@
+//! Status page with runtime controls
+//!
+//! The box serves a small HTTP page on `status_port`, the port its mDNS
+//! record advertises, so the crew can change settings from a phone during
+//! a show. `GET /` returns the page, which is built into the binary, and
+//! `GET /status` the control socket's `status` line. The POST endpoints take
+//! a JSON body and apply the same [`Command`]s as the control socket through
+//! the shared [`ControlHandles`]:
+//!
+//! - `/intercom/mute` `{"muted": true}`
+//! - `/intercom/gain` `{"headphone": 12.0, "sidetone": 80.0}`, either or both
+//! - `/display/source` `{"source": "CAM2"}`
+//! - `/snapshot` `{}` - write the replay thumbnails, as `dump-ring` does
+//! - `/camera` `{"control": "exposure_absolute", "value": 250}` - set and save
+//!   a V4L2 control
+//!
+//! Every response is JSON, `{"ok": true, "details": "..."}` or
+//! `{"ok": false, "error": "..."}`. The POST endpoints are off until
+//! `[status] token` is set, and then need `Authorization: Bearer <token>`,
+//! `Content-Type: application/json` and, from a browser, an `Origin` naming
+//! the box itself, so other web pages can't change settings. Each
+//! connection carries one request, and clients are served one at a time.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::control::{Command, ControlHandles, Gain};
+use crate::net;
+use crate::threads;
+
+/// The control page
+const PAGE: &str = include_str!("status_page.html");
+
+/// Most bytes read per request, headers and body together
+const MAX_REQUEST: u64 = 16 * 1024;
+
+/// Read and write timeout per client, so a stalled browser can't hold up
+/// the next one
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// =============================================================================
+// Requests and responses
+// =============================================================================
+
+/// The parts of an HTTP request the router looks at
+#[derive(Debug, Default)]
+struct Request {
+    method: String,
+    /// Target without the query string
+    path: String,
+    authorization: Option<String>,
+    content_type: Option<String>,
+    host: Option<String>,
+    /// Set by browsers on cross-site and same-site POSTs alike
+    origin: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Read one request from `stream`
+fn read_request(stream: impl Read) -> Result<Request> {
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        bail!("Malformed request line {:?}", line.trim_end());
+    };
+    let mut request = Request {
+        method: method.to_string(),
+        path: target.split('?').next().unwrap_or(target).to_string(),
+        ..Default::default()
+    };
+    let mut length = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            length = value
+                .parse()
+                .map_err(|_| anyhow!("Invalid Content-Length: {:?}", value))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            request.authorization = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("content-type") {
+            request.content_type = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("host") {
+            request.host = Some(value.to_string());
+        } else if name.eq_ignore_ascii_case("origin") {
+            request.origin = Some(value.to_string());
+        }
+    }
+    if length as u64 > MAX_REQUEST {
+        bail!("Request body of {} bytes is too long", length);
+    }
+    request.body.resize(length, 0);
+    reader
+        .read_exact(&mut request.body)
+        .context("Request body too long or cut short")?;
+    Ok(request)
+}
+
+#[derive(Debug)]
+struct Response {
+    /// Status code and reason, e.g. "200 OK"
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn page() -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: PAGE.to_string(),
+        }
+    }
+
+    fn ok(details: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body: json!({ "ok": true, "details": details }).to_string(),
+        }
+    }
+
+    fn error(status: &'static str, message: impl std::fmt::Display) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: json!({ "ok": false, "error": message.to_string() }).to_string(),
+        }
+    }
+
+    fn write_to(&self, mut writer: impl Write) -> std::io::Result<()> {
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.content_type,
+            self.body.len(),
+            self.body
+        )
+    }
+}
+
+// =============================================================================
+// Routes
+// =============================================================================
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MuteBody {
+    muted: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GainBody {
+    headphone: Option<f32>,
+    sidetone: Option<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourceBody {
+    source: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraBody {
+    control: String,
+    value: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmptyBody {}
+
+/// Parse a JSON body; an empty body reads as `{}`
+fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T> {
+    let body = if body.is_empty() { &b"{}"[..] } else { body };
+    serde_json::from_slice(body).map_err(|e| anyhow!("Invalid request body: {}", e))
+}
+
+/// Commands for a POST to `path`, None for an unknown path
+fn post_commands(path: &str, body: &[u8]) -> Option<Result<Vec<Command>>> {
+    let commands = match path {
+        "/intercom/mute" => {
+            parse_body(body).map(|body: MuteBody| vec![Command::IntercomMute(body.muted)])
+        }
+        "/intercom/gain" => parse_body(body).and_then(|body: GainBody| {
+            let gains = [
+                (Gain::Headphone, body.headphone),
+                (Gain::Sidetone, body.sidetone),
+            ];
+            let commands = gains
+                .into_iter()
+                .filter_map(|(gain, value)| value.map(|value| Command::intercom_gain(gain, value)))
+                .collect::<Result<Vec<_>>>()?;
+            if commands.is_empty() {
+                bail!("Expected headphone or sidetone");
+            }
+            Ok(commands)
+        }),
+        "/display/source" => parse_body(body).and_then(|body: SourceBody| {
+            if body.source.trim().is_empty() {
+                bail!("source must not be empty");
+            }
+            Ok(vec![Command::DisplaySource(body.source)])
+        }),
+        "/snapshot" => parse_body(body).map(|_: EmptyBody| vec![Command::DumpRing]),
+        "/camera" => parse_body(body)
+            .map(|body: CameraBody| vec![Command::Camera(Some(body.control), Some(body.value))]),
+        _ => return None,
+    };
+    Some(commands)
+}
+
+/// True if `request` carries `token`, compared in constant time
+fn authorized(request: &Request, token: &str) -> bool {
+    let given = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+        .unwrap_or_default();
+    !token.is_empty() && constant_time_eq(given.as_bytes(), token.as_bytes())
+}
+
+/// Byte-wise equality that takes as long wherever the first difference is
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// True if the body is declared JSON, which a cross-site form can't send
+/// without a CORS preflight this server refuses
+fn is_json(request: &Request) -> bool {
+    request
+        .content_type
+        .as_deref()
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media| media.trim().eq_ignore_ascii_case("application/json"))
+}
+
+/// False for a browser request from another site: its `Origin` must name
+/// the host it was sent to. Clients other than browsers send no `Origin`.
+fn same_origin(request: &Request) -> bool {
+    let Some(origin) = request.origin.as_deref() else {
+        return true;
+    };
+    let origin_host = origin
+        .split_once("://")
+        .map(|(_, host)| host.trim_end_matches('/'));
+    match (origin_host, request.host.as_deref()) {
+        (Some(origin_host), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+/// Run `commands` in order, stopping at the first that fails
+fn execute(handles: &ControlHandles, commands: Vec<Command>) -> Response {
+    let mut details = Vec::new();
+    for command in commands {
+        match handles.execute(command) {
+            Ok(detail) if detail.is_empty() => {}
+            Ok(detail) => details.push(detail),
+            Err(e) => return Response::error("409 Conflict", e),
+        }
+    }
+    Response::ok(details.join(" "))
+}
+
+fn route(request: &Request, token: &str, handles: &ControlHandles) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => Response::page(),
+        ("GET", "/status") => match handles.execute(Command::Status) {
+            Ok(status) => Response::ok(status),
+            Err(e) => Response::error("500 Internal Server Error", e),
+        },
+        ("GET", _) => Response::error("404 Not Found", "No such page"),
+        ("POST", path) => {
+            let Some(commands) = post_commands(path, &request.body) else {
+                return Response::error("404 Not Found", "No such endpoint");
+            };
+            if token.is_empty() {
+                return Response::error("403 Forbidden", "Controls need a [status] token");
+            }
+            if !same_origin(request) {
+                return Response::error("403 Forbidden", "Cross-origin request refused");
+            }
+            if !is_json(request) {
+                return Response::error(
+                    "415 Unsupported Media Type",
+                    "Content-Type must be application/json",
+                );
+            }
+            if !authorized(request, token) {
+                return Response::error("401 Unauthorized", "Missing or wrong token");
+            }
+            match commands {
+                Ok(commands) => execute(handles, commands),
+                Err(e) => Response::error("400 Bad Request", e),
+            }
+        }
+        _ => Response::error("405 Method Not Allowed", "Use GET or POST"),
+    }
+}
+
+// =============================================================================
+// Server
+// =============================================================================
+
+/// HTTP listener serving the status page and applying its control requests
+pub struct StatusServer {
+    listener: TcpListener,
+    token: String,
+    handles: Arc<ControlHandles>,
+}
+
+impl StatusServer {
+    /// Listen on `port` on all interfaces, IPv4 and IPv6. Control requests
+    /// need `token` and are refused while it is empty; `handles` are shared
+    /// with the control socket.
+    pub fn bind(port: u16, token: &str, handles: Arc<ControlHandles>) -> Result<Self> {
+        let listener = net::bind_tcp(SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)))
+            .with_context(|| format!("Failed to bind status port {}", port))?;
+        tracing::info!(
+            "Status page at http://{}/{}",
+            listener.local_addr()?,
+            if token.is_empty() {
+                " (read-only, no [status] token)"
+            } else {
+                " (token required)"
+            }
+        );
+        Ok(Self {
+            listener,
+            token: token.to_string(),
+            handles,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener
+            .local_addr()
+            .expect("bound listener has an address")
+    }
+
+    /// Serve clients on a background thread, one at a time
+    pub fn spawn(self) -> std::thread::JoinHandle<()> {
+        threads::spawn(threads::STATUS_HTTP, move || self.run())
+    }
+
+    fn run(&self) {
+        for stream in self.listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = self.handle_client(stream) {
+                        tracing::debug!("Status client error: {}", e);
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("Status page accept failed: {}", e);
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+    }
+
+    fn handle_client(&self, stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+        let response = match read_request(&stream) {
+            Ok(request) => route(&request, &self.token, &self.handles),
+            Err(e) => Response::error("400 Bad Request", format!("{:#}", e)),
+        };
+        response.write_to(&stream)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::{self, DisplayControl};
+    use crate::intercom::IntercomStats;
+    use serde_json::Value;
+    use tokio::sync::watch;
+
+    struct Served {
+        addr: SocketAddr,
+        display: DisplayControl,
+        mute: watch::Receiver<bool>,
+        stats: Arc<IntercomStats>,
+    }
+
+    fn serve(token: &str, with: impl FnOnce(ControlHandles) -> ControlHandles) -> Served {
+        let stats = Arc::new(IntercomStats::new());
+        stats.set_gains(12.0, 15.0, 100.0);
+        let (handles, display, mute) = control::channels("PROGRAM", Some(Arc::clone(&stats)));
+        let server = StatusServer::bind(0, token, Arc::new(with(handles))).unwrap();
+        let addr = SocketAddr::from(([127, 0, 0, 1], server.local_addr().port()));
+        server.spawn();
+        Served {
+            addr,
+            display,
+            mute,
+            stats,
+        }
+    }
+
+    /// Token of the servers the endpoint tests post to
+    const TOKEN: &str = "s3cret";
+
+    /// Send `method path` with an optional JSON body and bearer token;
+    /// returns the status code and the body
+    fn request(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        body: &str,
+        token: Option<&str>,
+    ) -> (u16, String) {
+        let auth = token
+            .map(|token| format!("Authorization: Bearer {}\r\n", token))
+            .unwrap_or_default();
+        let headers = format!("{}Content-Type: application/json\r\n", auth);
+        request_with(addr, method, path, &headers, body)
+    }
+
+    /// Send `method path` from host "box" with extra `headers`, each ending
+    /// in CRLF
+    fn request_with(
+        addr: SocketAddr,
+        method: &str,
+        path: &str,
+        headers: &str,
+        body: &str,
+    ) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: box\r\n{}Content-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            headers,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let code = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+        (code, body.to_string())
+    }
+
+    fn post(served: &Served, path: &str, body: &str) -> (u16, Value) {
+        let (code, body) = request(served.addr, "POST", path, body, Some(TOKEN));
+        (code, serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn test_page_and_status() {
+        let served = serve("", |handles| handles);
+        let (code, page) = request(served.addr, "GET", "/", "", None);
+        assert_eq!(code, 200);
+        assert!(page.contains("<title>camera-box</title>"));
+
+        let (code, body) = request(served.addr, "GET", "/status?t=1", "", None);
+        assert_eq!(code, 200);
+        let status: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(status["ok"], true);
+        let details = status["details"].as_str().unwrap();
+        assert!(
+            details.starts_with("display.source=\"PROGRAM\""),
+            "{}",
+            details
+        );
+        assert!(
+            details.contains("intercom.sidetone_gain=100.0"),
+            "{}",
+            details
+        );
+
+        assert_eq!(request(served.addr, "GET", "/admin", "", None).0, 404);
+        assert_eq!(request(served.addr, "POST", "/reboot", "{}", None).0, 404);
+        assert_eq!(request(served.addr, "DELETE", "/", "", None).0, 405);
+    }
+
+    #[test]
+    fn test_intercom_endpoints() {
+        let mut served = serve(TOKEN, |handles| handles);
+        let (code, reply) = post(&served, "/intercom/mute", r#"{"muted": false}"#);
+        assert_eq!((code, &reply["ok"]), (200, &Value::Bool(true)));
+        assert!(served.mute.has_changed().unwrap());
+        assert!(!*served.mute.borrow_and_update());
+
+        let (code, _) = post(&served, "/intercom/gain", r#"{"headphone": 8.5}"#);
+        assert_eq!(code, 200);
+        assert_eq!(served.stats.headphone_gain(), 8.5);
+        assert_eq!(served.stats.sidetone_gain(), 100.0);
+        let body = r#"{"headphone": 10, "sidetone": 40}"#;
+        assert_eq!(post(&served, "/intercom/gain", body).0, 200);
+        assert_eq!(served.stats.headphone_gain(), 10.0);
+        assert_eq!(served.stats.sidetone_gain(), 40.0);
+
+        // Out of range, missing or misspelled values change nothing
+        for body in [
+            r#"{"sidetone": 1001}"#,
+            r#"{"headphone": -1}"#,
+            "{}",
+            r#"{"sidetone_gain": 50}"#,
+            r#"{"muted": "no"}"#,
+        ] {
+            let (code, reply) = post(&served, "/intercom/gain", body);
+            assert_eq!(code, 400, "{}", body);
+            assert_eq!(reply["ok"], false);
+        }
+        assert_eq!(post(&served, "/intercom/mute", "not json").0, 400);
+        assert_eq!(served.stats.sidetone_gain(), 40.0);
+        assert!(!served.mute.has_changed().unwrap());
+    }
+
+    #[test]
+    fn test_display_source_endpoint() {
+        let mut served = serve(TOKEN, |handles| handles);
+        let (code, _) = post(&served, "/display/source", r#"{"source": "CAM2 (ndi)"}"#);
+        assert_eq!(code, 200);
+        assert!(served.display.source_changed());
+        assert_eq!(served.display.source(), "CAM2 (ndi)");
+
+        assert_eq!(
+            post(&served, "/display/source", r#"{"source": " "}"#).0,
+            400
+        );
+        assert!(!served.display.source_changed());
+
+        // Commands the box can't apply are refused with their reason
+        let Served { addr, display, .. } = served;
+        drop(display);
+        let (code, body) = request(
+            addr,
+            "POST",
+            "/display/source",
+            r#"{"source": "X"}"#,
+            Some(TOKEN),
+        );
+        assert_eq!(code, 409);
+        let reply: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(reply["error"], "Display is not enabled");
+    }
+
+    #[test]
+    fn test_snapshot_and_camera_endpoints() {
+        use crate::processing::FrameProcessor;
+        use crate::replay::ReplayRecorder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (mut recorder, replay) = ReplayRecorder::new(8, dir.path());
+        let served = serve(TOKEN, |handles| handles.with_replay(replay));
+        recorder.process(&mut [128, 16, 128, 16], 2, 1);
+
+        let (code, reply) = post(&served, "/snapshot", "");
+        assert_eq!(code, 200, "{}", reply);
+        let details = reply["details"].as_str().unwrap();
+        assert!(details.ends_with(" frames=1"), "{}", details);
+        let dumped = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(dumped, 1);
+
+        // No capture device in the test: the request parses, the camera refuses
+        let body = r#"{"control": "exposure_absolute", "value": 250}"#;
+        let (code, reply) = post(&served, "/camera", body);
+        assert_eq!(code, 409);
+        assert_eq!(reply["error"], "Camera controls are not available");
+        assert_eq!(post(&served, "/camera", r#"{"control": "gain"}"#).0, 400);
+    }
+
+    #[test]
+    fn test_token_guards_control_endpoints() {
+        let mut served = serve(TOKEN, |handles| handles);
+        let body = r#"{"muted": false}"#;
+        for token in [None, Some("wrong")] {
+            let (code, _) = request(served.addr, "POST", "/intercom/mute", body, token);
+            assert_eq!(code, 401);
+        }
+        assert!(!served.mute.has_changed().unwrap());
+
+        let (code, _) = request(served.addr, "POST", "/intercom/mute", body, Some(TOKEN));
+        assert_eq!(code, 200);
+        assert!(!*served.mute.borrow_and_update());
+
+        // Reading stays open
+        assert_eq!(request(served.addr, "GET", "/", "", None).0, 200);
+        assert_eq!(request(served.addr, "GET", "/status", "", None).0, 200);
+
+        assert!(constant_time_eq(b"s3cret", b"s3cret"));
+        assert!(!constant_time_eq(b"s3cret", b"s3crex"));
+        assert!(!constant_time_eq(b"s3cret", b"s3cre"));
+    }
+
+    #[test]
+    fn test_controls_off_without_token() {
+        let served = serve("", |handles| handles);
+        let body = r#"{"muted": false}"#;
+        for token in [None, Some("")] {
+            let (code, _) = request(served.addr, "POST", "/intercom/mute", body, token);
+            assert_eq!(code, 403);
+        }
+        assert!(!served.mute.has_changed().unwrap());
+        assert_eq!(request(served.addr, "GET", "/status", "", None).0, 200);
+    }
+
+    #[test]
+    fn test_cross_site_requests_refused() {
+        let mut served = serve(TOKEN, |handles| handles);
+        let body = r#"{"muted": false}"#;
+        let auth = format!("Authorization: Bearer {}\r\n", TOKEN);
+        let json = "Content-Type: application/json\r\n";
+
+        // Another site's page, even with the token the browser remembers
+        for origin in ["http://evil.example", "http://box.evil.example", "null"] {
+            let headers = format!("{}{}Origin: {}\r\n", auth, json, origin);
+            let (code, _) = request_with(served.addr, "POST", "/intercom/mute", &headers, body);
+            assert_eq!(code, 403, "{}", origin);
+        }
+        // A form post can't declare JSON
+        for content_type in ["", "Content-Type: text/plain\r\n"] {
+            let headers = format!("{}{}", auth, content_type);
+            let (code, _) = request_with(served.addr, "POST", "/intercom/mute", &headers, body);
+            assert_eq!(code, 415, "{:?}", content_type);
+        }
+        assert!(!served.mute.has_changed().unwrap());
+
+        // The box's own page
+        let headers = format!(
+            "{}Content-Type: application/json; charset=utf-8\r\nOrigin: http://BOX/\r\n",
+            auth
+        );
+        let (code, _) = request_with(served.addr, "POST", "/intercom/mute", &headers, body);
+        assert_eq!(code, 200);
+        assert!(!*served.mute.borrow_and_update());
+    }
+
+    #[test]
+    fn test_read_request() {
+        let raw = b"POST /intercom/mute?x=1 HTTP/1.1\r\nauthorization: Bearer t\r\n\
+                    Host: box:8090\r\nOrigin: http://box:8090\r\n\
+                    content-type: application/json\r\ncontent-length: 4\r\n\r\nbodyEXTRA";
+        let request = read_request(&raw[..]).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/intercom/mute");
+        assert_eq!(request.authorization.as_deref(), Some("Bearer t"));
+        assert_eq!(request.host.as_deref(), Some("box:8090"));
+        assert_eq!(request.origin.as_deref(), Some("http://box:8090"));
+        assert!(is_json(&request) && same_origin(&request));
+        assert_eq!(request.body, b"body");
+
+        assert!(read_request(&b"\r\n"[..]).is_err());
+        assert!(read_request(&b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"[..]).is_err());
+        let huge = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_REQUEST);
+        assert!(read_request(huge.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! Synthetic UYVY color bar source
+//!
+//! Stands in for a capture device when no camera is attached: bench setups,
+//! NDI receiver checks and pipeline tests. Frames are delivered at the
+//! configured rate on a monotonic schedule.
+
+use std::time::{Duration, Instant};
+use v4l::FourCC;
+
+use crate::capture::{FrameInfo, FrameRate};
+
+/// 75% color bars in BT.709 (Y, Cb, Cr): white, yellow, cyan, green,
+/// magenta, red, blue
+const BARS: [(u8, u8, u8); 7] = [
+    (180, 128, 128),
+    (168, 44, 136),
+    (145, 147, 44),
+    (133, 63, 52),
+    (63, 193, 204),
+    (51, 109, 212),
+    (28, 212, 120),
+];
+
+/// Render color bars as UYVY (width is rounded up to an even pixel count)
+pub fn color_bars_uyvy(width: u32, height: u32) -> Vec<u8> {
+    let pairs = width.div_ceil(2) as usize;
+    let line = uyvy_line(pairs, |pair| {
+        let bar = pair * 2 * BARS.len() / (pairs * 2).max(1);
+        BARS[bar.min(BARS.len() - 1)]
+    });
+    line.repeat(height as usize)
+}
+
+/// Bottom row of the SMPTE bars in BT.709: -I, 100% white, +Q, black and
+/// the PLUGE's -4% (below black) and +4% steps
+const MINUS_I: (u8, u8, u8) = (41, 150, 112);
+const WHITE_100: (u8, u8, u8) = (235, 128, 128);
+const PLUS_Q: (u8, u8, u8) = (32, 170, 146);
+const BLACK: (u8, u8, u8) = (16, 128, 128);
+const PLUGE_LOW: (u8, u8, u8) = (7, 128, 128);
+const PLUGE_HIGH: (u8, u8, u8) = (25, 128, 128);
+
+/// One UYVY line with `color(pair)` for every pixel pair
+fn uyvy_line(pairs: usize, color: impl Fn(usize) -> (u8, u8, u8)) -> Vec<u8> {
+    let mut line = Vec::with_capacity(pairs * 4);
+    for pair in 0..pairs {
+        let (y, cb, cr) = color(pair);
+        line.extend_from_slice(&[cb, y, cr, y]);
+    }
+    line
+}
+
+/// Render SMPTE color bars as UYVY: the 75% bars over the top two thirds,
+/// the reverse blue/magenta/cyan/white castellations, then -I, 100% white,
+/// +Q and the PLUGE across the bottom quarter
+pub fn smpte_bars_uyvy(width: u32, height: u32) -> Vec<u8> {
+    let pairs = width.div_ceil(2) as usize;
+    let bar = |pair: usize| (pair * BARS.len() / pairs.max(1)).min(BARS.len() - 1);
+
+    let top = uyvy_line(pairs, |pair| BARS[bar(pair)]);
+    let castellations = uyvy_line(pairs, |pair| match bar(pair) {
+        0 => BARS[6],
+        2 => BARS[4],
+        4 => BARS[2],
+        6 => BARS[0],
+        _ => BLACK,
+    });
+    // In twelfths of a bar: four blocks of 1 1/4 bars, then the PLUGE steps
+    // a third of a bar wide each
+    let bottom = uyvy_line(pairs, |pair| match pair * BARS.len() * 12 / pairs.max(1) {
+        0..15 => MINUS_I,
+        15..30 => WHITE_100,
+        30..45 => PLUS_Q,
+        60..64 => PLUGE_LOW,
+        68..72 => PLUGE_HIGH,
+        _ => BLACK,
+    });
+
+    let height = height as usize;
+    let top_rows = height * 2 / 3;
+    let castellation_rows = height * 3 / 4 - top_rows;
+    let mut frame = top.repeat(top_rows);
+    frame.extend(castellations.repeat(castellation_rows));
+    frame.extend(bottom.repeat(height - top_rows - castellation_rows));
+    frame
+}
+
+/// Color bars at a fixed frame rate
+pub struct TestPattern {
+    width: u32,
+    height: u32,
+    frame_rate: FrameRate,
+    period: Duration,
+    frame: Vec<u8>,
+    next_due: Option<Instant>,
+    frames: u64,
+    /// Stop delivering after this many frames, like a wedged device
+    frame_limit: Option<u64>,
+}
+
+impl TestPattern {
+    pub fn new(width: u32, height: u32, frame_rate: FrameRate) -> Self {
+        Self::with_frame(width, height, frame_rate, color_bars_uyvy(width, height))
+    }
+
+    /// SMPTE bars (see [`smpte_bars_uyvy`]) for line-up
+    pub fn smpte(width: u32, height: u32, frame_rate: FrameRate) -> Self {
+        Self::with_frame(width, height, frame_rate, smpte_bars_uyvy(width, height))
+    }
+
+    fn with_frame(width: u32, height: u32, frame_rate: FrameRate, frame: Vec<u8>) -> Self {
+        let fps = frame_rate.numerator.max(1) as f64 / frame_rate.denominator.max(1) as f64;
+        Self {
+            width,
+            height,
+            frame_rate,
+            period: Duration::from_secs_f64(1.0 / fps),
+            frame,
+            next_due: None,
+            frames: 0,
+            frame_limit: None,
+        }
+    }
+
+    /// Stall after `frames` frames (for exercising stall recovery)
+    pub fn with_frame_limit(mut self, frames: u64) -> Self {
+        self.frame_limit = Some(frames);
+        self
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn frame_rate(&self) -> FrameRate {
+        self.frame_rate
+    }
+
+    pub fn frame_info(&self) -> FrameInfo {
+        let stride = self.width.div_ceil(2) * 4;
+        // The bars are rendered once and never touched again
+        FrameInfo::new(self.width, self.height, FourCC::new(b"UYVY"), stride).with_held(true)
+    }
+
+    /// Wait for the next frame (up to `timeout`) and pass it to `callback`.
+    /// Returns false if no frame was due within the timeout.
+    pub fn process_frame_timeout<F>(&mut self, timeout: Duration, mut callback: F) -> bool
+    where
+        F: FnMut(&[u8], FrameInfo),
+    {
+        let now = Instant::now();
+        let stalled = self.frame_limit.is_some_and(|limit| self.frames >= limit);
+        let due = self.next_due.unwrap_or(now);
+        if stalled || due > now + timeout {
+            std::thread::sleep(timeout);
+            return false;
+        }
+        std::thread::sleep(due.saturating_duration_since(now));
+
+        callback(&self.frame, self.frame_info());
+        self.frames += 1;
+        // Schedule from the due time so the average rate stays exact
+        self.next_due = Some(due + self.period);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_bars_layout() {
+        let frame = color_bars_uyvy(14, 2);
+        assert_eq!(frame.len(), 14 * 2 * 2);
+        // One UYVY pair per bar at 14 pixels wide
+        for (i, &(y, cb, cr)) in BARS.iter().enumerate() {
+            assert_eq!(&frame[i * 4..i * 4 + 4], &[cb, y, cr, y]);
+        }
+        assert_eq!(&frame[..28], &frame[28..]);
+    }
+
+    /// (Y, Cb, Cr) of the pixel pair holding pixel (`x`, `y`)
+    fn pixel(frame: &[u8], width: u32, x: u32, y: u32) -> (u8, u8, u8) {
+        let offset = (y * width.div_ceil(2) * 4 + x / 2 * 4) as usize;
+        let pair = &frame[offset..offset + 4];
+        (pair[1], pair[0], pair[2])
+    }
+
+    #[test]
+    fn test_smpte_bars_layout() {
+        for (width, height) in [(1920, 1080), (1280, 720)] {
+            let frame = smpte_bars_uyvy(width, height);
+            assert_eq!(frame.len(), (width * height * 2) as usize);
+            let bar = width / 7;
+            let at = |x: u32, y: u32| pixel(&frame, width, x, y);
+
+            // Top two thirds: the seven 75% bars, sampled mid-bar
+            for (i, &color) in BARS.iter().enumerate() {
+                let x = bar * i as u32 + bar / 2;
+                assert_eq!(at(x, 0), color, "{}p bar {}", height, i);
+                assert_eq!(at(x, height * 2 / 3 - 1), color);
+            }
+            // Castellations below each bar
+            let castle = height * 2 / 3 + 1;
+            assert_eq!(at(bar / 2, castle), BARS[6]);
+            assert_eq!(at(bar + bar / 2, castle), BLACK);
+            assert_eq!(at(2 * bar + bar / 2, castle), BARS[4]);
+            assert_eq!(at(6 * bar + bar / 2, castle), BARS[0]);
+
+            // Bottom quarter: -I, white, +Q blocks and the PLUGE
+            let bottom = height * 3 / 4;
+            assert_eq!(at(0, bottom), MINUS_I);
+            assert_eq!(at(0, height - 1), MINUS_I);
+            assert_eq!(at(bar * 2, bottom), WHITE_100);
+            assert_eq!(at(bar * 3, bottom), PLUS_Q);
+            assert_eq!(at(bar * 4, bottom), BLACK);
+            assert_eq!(at(bar * 5 + bar / 6, bottom), PLUGE_LOW);
+            assert_eq!(at(bar * 5 + bar / 2, bottom), BLACK);
+            assert_eq!(at(bar * 5 + bar * 5 / 6, bottom), PLUGE_HIGH);
+            assert_eq!(at(width - 1, height - 1), BLACK);
+        }
+    }
+
+    #[test]
+    fn test_frames_follow_rate_and_limit() {
+        let rate = FrameRate {
+            numerator: 100,
+            denominator: 1,
+        };
+        let mut pattern = TestPattern::new(8, 4, rate).with_frame_limit(3);
+        let start = Instant::now();
+        let mut delivered = 0;
+        for _ in 0..3 {
+            assert!(
+                pattern.process_frame_timeout(Duration::from_millis(50), |data, info| {
+                    assert_eq!(data.len(), 8 * 4 * 2);
+                    assert_eq!(info.stride, 16);
+                    delivered += 1;
+                })
+            );
+        }
+        // Frames 0, 1 and 2 are due at 0, 10 and 20 ms
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(delivered, 3);
+        assert!(!pattern.process_frame_timeout(Duration::from_millis(5), |_, _| {}));
+    }
+}
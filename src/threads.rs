@@ -0,0 +1,393 @@
+//! Thread names and per-thread CPU usage
+//!
+//! Every long-lived thread is spawned under a name from [`THREADS`], so
+//! `top -H` on the box tells the components apart. [`ThreadCpu`] reads
+//! `/proc/self/task/*/stat` once per stats interval and reports the CPU
+//! each named thread used as the "cpu" stats component; tokio workers, NDI
+//! SDK threads and other unnamed ones are summed as "other". Linux keeps
+//! the first 15 bytes of a thread name.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+
+use crate::stats::{Component, StatsRegistry};
+
+pub const CAPTURE: &str = "capture";
+pub const HDMI_AUDIO: &str = "hdmi-audio";
+pub const RECORD: &str = "record";
+pub const OUTPUT: &str = "output";
+pub const MJPEG_HTTP: &str = "mjpeg-http";
+pub const MJPEG_CLIENT: &str = "mjpeg-client";
+pub const NDI_PROXY: &str = "ndi-proxy";
+pub const PIPELINE_EVENTS: &str = "pipe-events";
+pub const REPLAY_DUMP: &str = "replay-dump";
+pub const DISPLAY: &str = "display";
+pub const SPLASH: &str = "splash";
+pub const INTERCOM_AUDIO: &str = "ic-audio";
+pub const INTERCOM_RECEIVE: &str = "ic-receive";
+pub const INTERCOM_RESOLVE: &str = "ic-resolve";
+pub const INTERCOM_RECORD: &str = "ic-record";
+pub const INTERCOM_STOP: &str = "ic-stop";
+pub const MUTE_KEY: &str = "ic-mute-key";
+pub const MUTE_BUTTON: &str = "ic-mute-button";
+pub const TALLY_LED: &str = "ic-tally-led";
+pub const AUTO_UNMUTE: &str = "ic-auto-unmute";
+pub const AUDIO_ONLY: &str = "audio-only";
+pub const SERIAL: &str = "serial";
+pub const CONTROL: &str = "control";
+pub const STATUS_HTTP: &str = "status-http";
+pub const CONFIG_WATCH: &str = "config-watch";
+pub const SHUTDOWN: &str = "shutdown";
+pub const MDNS: &str = "mdns";
+pub const LINEUP: &str = "lineup";
+
+/// Threads not named in [`THREADS`]
+pub const OTHER: &str = "other";
+
+/// Thread name -> stats component it works for, in report order
+pub const THREADS: &[(&str, &str)] = &[
+    (CAPTURE, "capture"),
+    (HDMI_AUDIO, "capture"),
+    (RECORD, "record"),
+    (OUTPUT, "output"),
+    (MJPEG_HTTP, "output"),
+    (MJPEG_CLIENT, "output"),
+    (NDI_PROXY, "output"),
+    (PIPELINE_EVENTS, "capture"),
+    (REPLAY_DUMP, "capture"),
+    (DISPLAY, "display"),
+    (SPLASH, "display"),
+    (INTERCOM_AUDIO, "intercom"),
+    (INTERCOM_RECEIVE, "intercom"),
+    (INTERCOM_RESOLVE, "intercom"),
+    (INTERCOM_RECORD, "intercom"),
+    (INTERCOM_STOP, "intercom"),
+    (MUTE_KEY, "intercom"),
+    (MUTE_BUTTON, "intercom"),
+    (TALLY_LED, "intercom"),
+    (AUTO_UNMUTE, "intercom"),
+    (AUDIO_ONLY, "audio_only"),
+    (SERIAL, "serial"),
+    (CONTROL, "control"),
+    (STATUS_HTTP, "control"),
+    (CONFIG_WATCH, "control"),
+    (SHUTDOWN, "control"),
+    (MDNS, "mdns"),
+    (LINEUP, "lineup"),
+];
+
+/// Component the thread `name` works for, None for threads we didn't name
+pub fn component_of(name: &str) -> Option<&'static str> {
+    THREADS
+        .iter()
+        .find(|(thread, _)| *thread == name)
+        .map(|(_, component)| *component)
+}
+
+/// Spawn a thread named `name`
+pub fn spawn<F, T>(name: &str, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(f)
+        .expect("failed to spawn thread")
+}
+
+/// Run `f` with the current thread renamed to `name`, e.g. inside
+/// `spawn_blocking`, whose pool threads are shared; the old name is put
+/// back afterwards
+pub fn run_named<T>(name: &str, f: impl FnOnce() -> T) -> T {
+    let previous = current_name();
+    set_current_name(name);
+    let result = f();
+    if let Some(previous) = previous {
+        set_current_name(&previous);
+    }
+    result
+}
+
+/// Name of the calling thread as the kernel sees it
+pub fn current_name() -> Option<String> {
+    let mut buf = [0u8; 16];
+    // SAFETY: PR_GET_NAME writes at most 16 bytes, null-terminated
+    let result = unsafe { libc::prctl(libc::PR_GET_NAME, buf.as_mut_ptr() as libc::c_ulong) };
+    if result != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+fn set_current_name(name: &str) {
+    let mut buf = [0u8; 16];
+    let len = name.len().min(15);
+    buf[..len].copy_from_slice(&name.as_bytes()[..len]);
+    // SAFETY: `buf` is null-terminated and outlives the call
+    unsafe { libc::prctl(libc::PR_SET_NAME, buf.as_ptr() as libc::c_ulong) };
+}
+
+/// CPU time of one thread from its `/proc/<pid>/task/<tid>/stat` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskStat {
+    pub tid: u32,
+    pub name: String,
+    /// User plus system time in clock ticks
+    pub ticks: u64,
+}
+
+/// Parse a task stat line. The name sits in parentheses and may itself
+/// contain spaces and parentheses, so fields are counted from the last ')'.
+pub fn parse_stat(line: &str) -> Option<TaskStat> {
+    let open = line.find('(')?;
+    let close = line.rfind(')')?;
+    if close < open {
+        return None;
+    }
+    let tid = line[..open].trim().parse().ok()?;
+    // Fields after the name start at 3 (state); utime is 14, stime 15
+    let fields: Vec<&str> = line[close + 1..].split_whitespace().collect();
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(TaskStat {
+        tid,
+        name: line[open + 1..close].to_string(),
+        ticks: utime + stime,
+    })
+}
+
+type Usage = Arc<Mutex<Vec<(&'static str, f64)>>>;
+
+/// Samples the CPU use of this process's threads
+pub struct ThreadCpu {
+    task_dir: PathBuf,
+    ticks_per_sec: f64,
+    previous: HashMap<u32, u64>,
+    at: Option<Instant>,
+    /// Percent of one core per reported name, from the last interval
+    usage: Usage,
+    registered: Vec<&'static str>,
+}
+
+impl Default for ThreadCpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ThreadCpu {
+    pub fn new() -> Self {
+        // SAFETY: sysconf has no preconditions
+        let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        Self::with_task_dir(
+            "/proc/self/task",
+            if ticks > 0 { ticks as f64 } else { 100.0 },
+        )
+    }
+
+    fn with_task_dir(task_dir: impl Into<PathBuf>, ticks_per_sec: f64) -> Self {
+        Self {
+            task_dir: task_dir.into(),
+            ticks_per_sec,
+            previous: HashMap::new(),
+            at: None,
+            usage: Arc::new(Mutex::new(Vec::new())),
+            registered: Vec::new(),
+        }
+    }
+
+    fn read_tasks(&self) -> Vec<TaskStat> {
+        let Ok(entries) = std::fs::read_dir(&self.task_dir) else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| std::fs::read_to_string(entry.path().join("stat")).ok())
+            .filter_map(|line| parse_stat(&line))
+            .collect()
+    }
+
+    /// Measure the CPU used since the previous sample and report it as
+    /// "cpu" in `registry`: one gauge per named thread alive, in percent of
+    /// one core. The first sample only starts the count.
+    pub fn sample(&mut self, now: Instant, registry: &StatsRegistry) {
+        let tasks = self.read_tasks();
+        let previous = std::mem::take(&mut self.previous);
+        self.previous = tasks.iter().map(|t| (t.tid, t.ticks)).collect();
+        let Some(at) = self.at.replace(now) else {
+            return;
+        };
+        let secs = now.saturating_duration_since(at).as_secs_f64();
+        if secs <= 0.0 {
+            return;
+        }
+
+        let mut usage: Vec<(&'static str, f64)> = Vec::new();
+        for task in &tasks {
+            // Threads started during the interval count from zero
+            let ticks = task
+                .ticks
+                .saturating_sub(previous.get(&task.tid).copied().unwrap_or(0));
+            let percent = ticks as f64 * 100.0 / (self.ticks_per_sec * secs);
+            let name = THREADS
+                .iter()
+                .map(|(thread, _)| *thread)
+                .find(|thread| *thread == task.name)
+                .unwrap_or(OTHER);
+            match usage.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, total)) => *total += percent,
+                None => usage.push((name, percent)),
+            }
+        }
+        let order = |name: &str| {
+            THREADS
+                .iter()
+                .position(|(thread, _)| *thread == name)
+                .unwrap_or(THREADS.len())
+        };
+        usage.sort_by_key(|(name, _)| order(name));
+
+        let names: Vec<&'static str> = usage.iter().map(|(name, _)| *name).collect();
+        *self.usage.lock().unwrap_or_else(|e| e.into_inner()) = usage;
+        if names != self.registered {
+            let component = names
+                .iter()
+                .fold(Component::new("cpu"), |component, &name| {
+                    let usage = Arc::clone(&self.usage);
+                    component.gauge(name, move || {
+                        usage
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .iter()
+                            .find(|(n, _)| *n == name)
+                            .map_or(f64::NAN, |(_, percent)| *percent)
+                    })
+                });
+            registry.register(component);
+            self.registered = names;
+        }
+    }
+
+    /// Percent of one core the thread `name` (or [`OTHER`]) used in the
+    /// last interval
+    pub fn percent(&self, name: &str) -> Option<f64> {
+        self.usage
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, percent)| *percent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stats::Value;
+    use std::time::Duration;
+
+    const STAT_LINE: &str = "4242 (ic-audio) S 4200 4200 4200 0 -1 4194368 120 0 0 0 \
+        1534 287 0 0 -11 0 1 0 3471 1257472000 5321 18446744073709551615 1 1 0 0 0 0 \
+        0 4096 1260 0 0 0 -1 3 90 1 0 0 0 0 0 0 0 0 0 0 0";
+
+    #[test]
+    fn test_parse_stat() {
+        assert_eq!(
+            parse_stat(STAT_LINE),
+            Some(TaskStat {
+                tid: 4242,
+                name: "ic-audio".to_string(),
+                ticks: 1534 + 287,
+            })
+        );
+        // Names may hold spaces and parentheses
+        let odd = STAT_LINE.replace("(ic-audio)", "(a (b) c)");
+        assert_eq!(parse_stat(&odd).unwrap().name, "a (b) c");
+        assert_eq!(parse_stat(&odd).unwrap().ticks, 1821);
+
+        assert_eq!(parse_stat(""), None);
+        assert_eq!(parse_stat("12 (short) S 1 2 3"), None);
+        assert_eq!(parse_stat("x (name) S 1 2 3 4 5 6 7 8 9 10 11 12"), None);
+    }
+
+    #[test]
+    fn test_thread_names_fit_and_map_to_components() {
+        for (name, _) in THREADS {
+            assert!(name.len() <= 15, "{} is longer than Linux keeps", name);
+        }
+        let mut names: Vec<&str> = THREADS.iter().map(|(name, _)| *name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), THREADS.len());
+        assert_eq!(component_of(INTERCOM_RECEIVE), Some("intercom"));
+        assert_eq!(component_of(CAPTURE), Some("capture"));
+        assert_eq!(component_of("tokio-runtime-w"), None);
+    }
+
+    #[test]
+    fn test_spawn_and_run_named() {
+        let names = spawn(MDNS, || {
+            let inside = run_named(REPLAY_DUMP, current_name);
+            (inside, current_name())
+        })
+        .join()
+        .unwrap();
+        assert_eq!(names.0.as_deref(), Some(REPLAY_DUMP));
+        // The thread gets its own name back
+        assert_eq!(names.1.as_deref(), Some(MDNS));
+    }
+
+    fn write_task(dir: &std::path::Path, tid: u32, name: &str, utime: u64, stime: u64) {
+        let task = dir.join(tid.to_string());
+        std::fs::create_dir_all(&task).unwrap();
+        let line = STAT_LINE
+            .replacen("4242", &tid.to_string(), 1)
+            .replace("ic-audio", name)
+            .replace(" 1534 287 ", &format!(" {} {} ", utime, stime));
+        std::fs::write(task.join("stat"), line).unwrap();
+    }
+
+    #[test]
+    fn test_cpu_per_thread() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = StatsRegistry::new();
+        let mut cpu = ThreadCpu::with_task_dir(dir.path(), 100.0);
+        write_task(dir.path(), 10, CAPTURE, 1000, 500);
+        write_task(dir.path(), 11, "tokio-runtime-w", 50, 0);
+        write_task(dir.path(), 12, "tokio-runtime-w", 70, 0);
+        let start = Instant::now();
+        cpu.sample(start, &registry);
+        assert!(registry.snapshot(start).values.is_empty());
+
+        // 10s later: capture used 4s, the workers 1s together
+        write_task(dir.path(), 10, CAPTURE, 1300, 600);
+        write_task(dir.path(), 11, "tokio-runtime-w", 100, 0);
+        write_task(dir.path(), 12, "tokio-runtime-w", 120, 0);
+        // Started during the interval: all of its time counts
+        write_task(dir.path(), 13, DISPLAY, 150, 50);
+        let now = start + Duration::from_secs(10);
+        cpu.sample(now, &registry);
+        assert_eq!(cpu.percent(CAPTURE), Some(40.0));
+        assert_eq!(cpu.percent(DISPLAY), Some(20.0));
+        assert_eq!(cpu.percent(OTHER), Some(10.0));
+        let keys: Vec<String> = registry
+            .snapshot(now)
+            .values
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, ["cpu.capture", "cpu.display", "cpu.other"]);
+
+        // The display thread ended: its gauge goes away
+        std::fs::remove_dir_all(dir.path().join("13")).unwrap();
+        cpu.sample(now + Duration::from_secs(10), &registry);
+        let snapshot = registry.snapshot(now);
+        assert_eq!(snapshot.get("cpu.display"), None);
+        assert_eq!(snapshot.get("cpu.capture"), Some(Value::Gauge(0.0)));
+    }
+}
@@ -0,0 +1,336 @@
+//! Per-stage timing of the capture loop
+//!
+//! When the frame rate drops below the target, the frame counters only say
+//! that it did. With `capture.timing` on, the capture loop times each frame
+//! in four [`Stage`]s and keeps the last [`TIMING_SAMPLES`] of each in a
+//! [`TimingHistogram`]; the stats report carries p50/p95/p99 per stage and
+//! SIGUSR2 logs them once. Off, the loop pays one branch per frame.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::stats::{Component, StatsRegistry, Value};
+
+/// Frames kept per stage, 10 s at 60 fps
+pub const TIMING_SAMPLES: usize = 600;
+
+/// The last `capacity` durations, with percentiles over them
+#[derive(Debug, Clone)]
+pub struct TimingHistogram {
+    samples: Vec<Duration>,
+    /// Slot the next sample overwrites once the ring is full
+    next: usize,
+    capacity: usize,
+}
+
+/// The percentiles reported per stage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Percentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+impl TimingHistogram {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            samples: Vec::with_capacity(capacity),
+            next: 0,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Add a sample, replacing the oldest once full
+    pub fn record(&mut self, sample: Duration) {
+        if self.samples.len() < self.capacity {
+            self.samples.push(sample);
+        } else {
+            self.samples[self.next] = sample;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The `percentile` (0-100) of the samples by nearest rank, None
+    /// without samples
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        nearest_rank(&sorted, percentile)
+    }
+
+    /// p50, p95 and p99 from one sort, None without samples
+    pub fn percentiles(&self) -> Option<Percentiles> {
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        Some(Percentiles {
+            p50: nearest_rank(&sorted, 50.0)?,
+            p95: nearest_rank(&sorted, 95.0)?,
+            p99: nearest_rank(&sorted, 99.0)?,
+        })
+    }
+}
+
+/// The smallest sample with at least `percentile` percent of `sorted` at or
+/// below it
+fn nearest_rank(sorted: &[Duration], percentile: f64) -> Option<Duration> {
+    let rank = (percentile.clamp(0.0, 100.0) * sorted.len() as f64 / 100.0).ceil() as usize;
+    sorted.get(rank.max(1) - 1).copied()
+}
+
+/// Where a frame's time goes in the capture loop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Waiting for V4L2 to hand over the frame
+    Dequeue,
+    /// Converting to UYVY and running the frame processors
+    Convert,
+    /// Handing the frame to NDI, including software pacing
+    Send,
+    /// Giving buffers back: the previous frame's from NDI, this frame's
+    /// to V4L2
+    Requeue,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Dequeue, Stage::Convert, Stage::Send, Stage::Requeue];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Dequeue => "dequeue",
+            Stage::Convert => "convert",
+            Stage::Send => "send",
+            Stage::Requeue => "requeue",
+        }
+    }
+}
+
+/// The stage durations of one frame
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameTiming {
+    pub dequeue: Duration,
+    pub convert: Duration,
+    pub send: Duration,
+    pub requeue: Duration,
+}
+
+impl FrameTiming {
+    pub fn stage(&self, stage: Stage) -> Duration {
+        match stage {
+            Stage::Dequeue => self.dequeue,
+            Stage::Convert => self.convert,
+            Stage::Send => self.send,
+            Stage::Requeue => self.requeue,
+        }
+    }
+}
+
+/// The recent stage timings of the capture loop, shared with the stats
+/// report
+#[derive(Debug)]
+pub struct PipelineTiming {
+    stages: Mutex<[TimingHistogram; 4]>,
+}
+
+impl Default for PipelineTiming {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelineTiming {
+    pub fn new() -> Self {
+        Self {
+            stages: Mutex::new(Stage::ALL.map(|_| TimingHistogram::new(TIMING_SAMPLES))),
+        }
+    }
+
+    /// Add one frame's timings
+    pub fn record(&self, frame: &FrameTiming) {
+        let mut stages = self.stages.lock().unwrap_or_else(|e| e.into_inner());
+        for (histogram, stage) in stages.iter_mut().zip(Stage::ALL) {
+            histogram.record(frame.stage(stage));
+        }
+    }
+
+    /// Percentiles per stage and the frames they cover, None before the
+    /// first frame
+    pub fn summary(&self) -> Option<([(Stage, Percentiles); 4], usize)> {
+        let stages = self.stages.lock().unwrap_or_else(|e| e.into_inner());
+        let mut summary = [(Stage::Dequeue, Percentiles::default()); 4];
+        for ((entry, histogram), stage) in summary.iter_mut().zip(stages.iter()).zip(Stage::ALL) {
+            *entry = (stage, histogram.percentiles()?);
+        }
+        Some((summary, stages[0].len()))
+    }
+
+    /// One line per stage, e.g. "convert: p50 2.10 ms, p95 2.80 ms, p99
+    /// 4.05 ms (600 frames)"
+    pub fn lines(&self) -> Vec<String> {
+        let Some((summary, frames)) = self.summary() else {
+            return vec!["no frames timed yet".to_string()];
+        };
+        summary
+            .iter()
+            .map(|(stage, p)| {
+                format!(
+                    "{}: p50 {:.2} ms, p95 {:.2} ms, p99 {:.2} ms ({} frames)",
+                    stage.name(),
+                    ms(p.p50),
+                    ms(p.p95),
+                    ms(p.p99),
+                    frames
+                )
+            })
+            .collect()
+    }
+
+    /// Log the current percentiles, e.g. on SIGUSR2
+    pub fn dump(&self) {
+        tracing::info!("Capture timing:");
+        for line in self.lines() {
+            tracing::info!("  {}", line);
+        }
+    }
+
+    /// Report the percentiles as "timing", e.g. timing.convert.p95_ms
+    pub fn register(self: &Arc<Self>, registry: &StatsRegistry) {
+        let timing = Arc::clone(self);
+        registry.register(Component::new("timing").family(move || {
+            let Some((summary, _)) = timing.summary() else {
+                return Vec::new();
+            };
+            summary
+                .iter()
+                .flat_map(|(stage, p)| {
+                    [("p50_ms", p.p50), ("p95_ms", p.p95), ("p99_ms", p.p99)].map(
+                        |(name, value)| {
+                            (
+                                format!("{}.{}", stage.name(), name),
+                                Value::Gauge(ms(value)),
+                            )
+                        },
+                    )
+                })
+                .collect()
+        }));
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1e6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn millis(values: impl IntoIterator<Item = u64>) -> TimingHistogram {
+        let mut histogram = TimingHistogram::new(TIMING_SAMPLES);
+        for value in values {
+            histogram.record(Duration::from_millis(value));
+        }
+        histogram
+    }
+
+    #[test]
+    fn test_percentiles_of_known_samples() {
+        // 1..=100 ms, shuffled: the nth percentile is n ms
+        let histogram = millis((1..=100).map(|i| (i * 37) % 100 + 1));
+        assert_eq!(
+            histogram.percentiles(),
+            Some(Percentiles {
+                p50: Duration::from_millis(50),
+                p95: Duration::from_millis(95),
+                p99: Duration::from_millis(99),
+            })
+        );
+        assert_eq!(histogram.percentile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(
+            histogram.percentile(100.0),
+            Some(Duration::from_millis(100))
+        );
+
+        // Nearest rank on a small set
+        let small = millis([15, 20, 35, 40, 50]);
+        assert_eq!(small.percentile(30.0), Some(Duration::from_millis(20)));
+        assert_eq!(small.percentile(40.0), Some(Duration::from_millis(20)));
+        assert_eq!(small.percentile(50.0), Some(Duration::from_millis(35)));
+        assert_eq!(small.percentile(99.0), Some(Duration::from_millis(50)));
+
+        // One slow frame in a hundred only shows at p99
+        let spike = millis((0..99).map(|_| 2).chain([40]));
+        let p = spike.percentiles().unwrap();
+        assert_eq!(p.p95, Duration::from_millis(2));
+        assert_eq!(p.p99, Duration::from_millis(2));
+        assert_eq!(spike.percentile(100.0), Some(Duration::from_millis(40)));
+        let spikes = millis((0..98).map(|_| 2).chain([40, 40]));
+        assert_eq!(spikes.percentiles().unwrap().p99, Duration::from_millis(40));
+
+        assert!(millis([]).is_empty());
+        assert_eq!(millis([]).percentiles(), None);
+    }
+
+    #[test]
+    fn test_ring_keeps_the_latest_samples() {
+        let mut histogram = TimingHistogram::new(4);
+        for value in [100, 100, 100, 100, 1, 2, 3] {
+            histogram.record(Duration::from_millis(value));
+        }
+        assert_eq!(histogram.len(), 4);
+        // One 100 ms sample is left of the first four
+        assert_eq!(histogram.percentile(75.0), Some(Duration::from_millis(3)));
+        assert_eq!(
+            histogram.percentile(100.0),
+            Some(Duration::from_millis(100))
+        );
+        histogram.record(Duration::from_millis(4));
+        assert_eq!(histogram.percentile(100.0), Some(Duration::from_millis(4)));
+    }
+
+    #[test]
+    fn test_pipeline_timing_reports_each_stage() {
+        let timing = Arc::new(PipelineTiming::new());
+        assert!(timing.summary().is_none());
+        assert_eq!(timing.lines(), ["no frames timed yet"]);
+
+        for i in 1..=10u64 {
+            timing.record(&FrameTiming {
+                dequeue: Duration::from_millis(10 + i),
+                convert: Duration::from_millis(2),
+                send: Duration::from_micros(500),
+                requeue: Duration::ZERO,
+            });
+        }
+        let lines = timing.lines();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "dequeue: p50 15.00 ms, p95 20.00 ms, p99 20.00 ms (10 frames)"
+        );
+        assert_eq!(
+            lines[2],
+            "send: p50 0.50 ms, p95 0.50 ms, p99 0.50 ms (10 frames)"
+        );
+
+        let registry = StatsRegistry::new();
+        timing.register(&registry);
+        let snapshot = registry.snapshot(Instant::now());
+        assert_eq!(
+            snapshot.get("timing.convert.p95_ms"),
+            Some(Value::Gauge(2.0))
+        );
+        assert_eq!(
+            snapshot.get("timing.requeue.p99_ms"),
+            Some(Value::Gauge(0.0))
+        );
+    }
+}
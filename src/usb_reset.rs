@@ -0,0 +1,211 @@
+//! USB re-authorization of wedged capture dongles
+//!
+//! Some MacroSilicon dongles wedge so hard that closing and reopening the
+//! video node changes nothing; only re-enumerating the USB device brings them
+//! back. The kernel does that when the device's sysfs `authorized` attribute
+//! is toggled to 0 and back to 1, which needs root.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// sysfs directory with one entry per video device
+const SYSFS_VIDEO4LINUX: &str = "/sys/class/video4linux";
+
+/// Pause between deauthorizing and reauthorizing, so the driver has let go
+const SETTLE: Duration = Duration::from_millis(500);
+
+/// How long the video node may take to come back after reauthorizing
+const REAPPEAR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the video node is looked for while waiting
+const REAPPEAR_POLL: Duration = Duration::from_millis(100);
+
+/// sysfs directory of the USB device behind a video node. The `device` link
+/// points at a USB interface; the device is the nearest ancestor with an
+/// `idVendor` attribute.
+pub fn usb_device_dir(video4linux: &Path, device_path: &str) -> Result<PathBuf> {
+    // Follow /dev/v4l/by-id links to the videoN node
+    let node = fs::canonicalize(device_path).unwrap_or_else(|_| PathBuf::from(device_path));
+    let Some(name) = node.file_name() else {
+        bail!("{} is not a video device node", device_path);
+    };
+    let link = video4linux.join(name).join("device");
+    let interface =
+        fs::canonicalize(&link).with_context(|| format!("Failed to resolve {}", link.display()))?;
+    interface
+        .ancestors()
+        .find(|dir| dir.join("idVendor").is_file())
+        .map(Path::to_path_buf)
+        .with_context(|| format!("{} is not a USB device", device_path))
+}
+
+/// Deauthorize and reauthorize the USB device at `usb_device`
+pub fn toggle_authorized(usb_device: &Path) -> Result<()> {
+    let authorized = usb_device.join("authorized");
+    let write = |value: &str| {
+        fs::write(&authorized, value)
+            .with_context(|| format!("Failed to write {} to {}", value, authorized.display()))
+    };
+    write("0")?;
+    std::thread::sleep(SETTLE);
+    write("1")
+}
+
+/// Wait until `path` exists again. Returns false after `timeout`.
+pub fn wait_for_node(path: &Path, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if path.exists() {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(REAPPEAR_POLL);
+    }
+}
+
+/// Resets the USB device behind one capture device path
+#[derive(Debug, Clone)]
+pub struct UsbReset {
+    device_path: String,
+    video4linux: PathBuf,
+}
+
+impl UsbReset {
+    pub fn new(device_path: impl Into<String>) -> Self {
+        Self::with_sysfs(device_path, SYSFS_VIDEO4LINUX)
+    }
+
+    /// Look video nodes up in `video4linux` instead of /sys/class/video4linux
+    pub fn with_sysfs(device_path: impl Into<String>, video4linux: impl Into<PathBuf>) -> Self {
+        Self {
+            device_path: device_path.into(),
+            video4linux: video4linux.into(),
+        }
+    }
+
+    /// Check that the device is on USB and its `authorized` attribute is
+    /// writable (i.e. we run as root)
+    pub fn check(&self) -> Result<PathBuf> {
+        let usb_device = usb_device_dir(&self.video4linux, &self.device_path)?;
+        let authorized = usb_device.join("authorized");
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&authorized)
+            .with_context(|| format!("Cannot write {}", authorized.display()))?;
+        Ok(usb_device)
+    }
+
+    /// Re-enumerate the device and wait for its video node to come back
+    pub fn reset(&self) -> Result<()> {
+        let usb_device = usb_device_dir(&self.video4linux, &self.device_path)?;
+        tracing::warn!(
+            "Resetting USB device {} behind {}",
+            usb_device.display(),
+            self.device_path
+        );
+        toggle_authorized(&usb_device)?;
+        if !wait_for_node(Path::new(&self.device_path), REAPPEAR_TIMEOUT) {
+            bail!(
+                "{} did not reappear within {:?} of the USB reset",
+                self.device_path,
+                REAPPEAR_TIMEOUT
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    /// sysfs with video0 on a USB interface, video1 on a PCI device and a
+    /// /dev/v4l/by-id link to video0
+    fn fake_sysfs() -> (tempfile::TempDir, PathBuf) {
+        let root = tempfile::tempdir().unwrap();
+        let usb_device = root
+            .path()
+            .join("sys/devices/pci0000:00/0000:00:14.0/usb1/1-2");
+        let interface = usb_device.join("1-2:1.0");
+        fs::create_dir_all(&interface).unwrap();
+        fs::write(usb_device.join("idVendor"), "534d\n").unwrap();
+        fs::write(usb_device.join("authorized"), "1\n").unwrap();
+        let pci_device = root.path().join("sys/devices/pci0000:00/0000:03:00.0");
+        fs::create_dir_all(&pci_device).unwrap();
+
+        let video4linux = root.path().join("sys/class/video4linux");
+        for (node, device) in [("video0", &interface), ("video1", &pci_device)] {
+            fs::create_dir_all(video4linux.join(node)).unwrap();
+            symlink(device, video4linux.join(node).join("device")).unwrap();
+        }
+
+        let dev = root.path().join("dev");
+        fs::create_dir_all(dev.join("v4l/by-id")).unwrap();
+        fs::write(dev.join("video0"), "").unwrap();
+        symlink(
+            dev.join("video0"),
+            dev.join("v4l/by-id/usb-MACROSILICON-video-index0"),
+        )
+        .unwrap();
+        (root, video4linux)
+    }
+
+    #[test]
+    fn test_usb_device_dir_walks_up_from_the_interface() {
+        let (root, video4linux) = fake_sysfs();
+        let expected = fs::canonicalize(
+            root.path()
+                .join("sys/devices/pci0000:00/0000:00:14.0/usb1/1-2"),
+        )
+        .unwrap();
+        assert_eq!(
+            usb_device_dir(&video4linux, "/dev/video0").unwrap(),
+            expected
+        );
+        // By-id links resolve to the node they point at
+        let by_id = root
+            .path()
+            .join("dev/v4l/by-id/usb-MACROSILICON-video-index0");
+        assert_eq!(
+            usb_device_dir(&video4linux, by_id.to_str().unwrap()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_usb_device_dir_rejects_other_devices() {
+        let (_root, video4linux) = fake_sysfs();
+        let error = usb_device_dir(&video4linux, "/dev/video1").unwrap_err();
+        assert_eq!(error.to_string(), "/dev/video1 is not a USB device");
+        assert!(usb_device_dir(&video4linux, "/dev/video7").is_err());
+    }
+
+    #[test]
+    fn test_reset_reauthorizes_and_waits_for_node() {
+        let (root, video4linux) = fake_sysfs();
+        let node = root.path().join("dev/video0");
+        let reset = UsbReset::with_sysfs(node.to_str().unwrap(), &video4linux);
+        let usb_device = reset.check().unwrap();
+        fs::write(usb_device.join("authorized"), "0").unwrap();
+
+        reset.reset().unwrap();
+        assert_eq!(
+            fs::read_to_string(usb_device.join("authorized")).unwrap(),
+            "1"
+        );
+    }
+
+    #[test]
+    fn test_wait_for_node_times_out() {
+        let root = tempfile::tempdir().unwrap();
+        let node = root.path().join("video0");
+        assert!(!wait_for_node(&node, Duration::from_millis(150)));
+        fs::write(&node, "").unwrap();
+        assert!(wait_for_node(&node, Duration::ZERO));
+    }
+}
@@ -4,6 +4,7 @@
 //! Default port: 6980
 
 use anyhow::{anyhow, Result};
+use std::borrow::Cow;
 
 /// VBAN magic header bytes
 pub const VBAN_MAGIC: &[u8; 4] = b"VBAN";
@@ -17,6 +18,28 @@ pub const VBAN_HEADER_SIZE: usize = 28;
 /// Maximum stream name length (including null terminator)
 pub const VBAN_STREAM_NAME_SIZE: usize = 16;
 
+/// Why a VBAN header could not be built or a packet not decoded
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum VbanError {
+    /// VBAN only carries the rates in `SAMPLE_RATES`
+    #[error("Unsupported sample rate: {0}")]
+    UnsupportedSampleRate(u32),
+    #[error("VBAN packet too short: {len} bytes")]
+    PacketTooShort { len: usize },
+    #[error("Invalid VBAN magic")]
+    BadMagic,
+    #[error("Not a VBAN audio packet")]
+    NotAudio,
+    #[error("Not a VBAN ping packet")]
+    NotPing,
+    #[error("Not a VBAN serial packet")]
+    NotSerial,
+    #[error("Not a VBAN text packet")]
+    NotText,
+    #[error("VBAN payload too long: {len} bytes")]
+    PayloadTooLong { len: usize },
+}
+
 /// VBAN protocol types
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -61,6 +84,80 @@ impl VbanCodec {
             VbanCodec::Float64 => 8,
         }
     }
+
+    /// Parse codec from the VBAN header format byte (data type in lower 3 bits)
+    pub fn from_format(format: u8) -> Option<Self> {
+        match format & 0x07 {
+            0x00 => Some(VbanCodec::Pcm8),
+            0x01 => Some(VbanCodec::Pcm16),
+            0x02 => Some(VbanCodec::Pcm24),
+            0x03 => Some(VbanCodec::Pcm32),
+            0x04 => Some(VbanCodec::Float32),
+            0x05 => Some(VbanCodec::Float64),
+            _ => None,
+        }
+    }
+
+    /// Parse a transmit codec name from configuration ("pcm16", "pcm24", "float32")
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "pcm16" => Ok(VbanCodec::Pcm16),
+            "pcm24" => Ok(VbanCodec::Pcm24),
+            "float32" => Ok(VbanCodec::Float32),
+            other => Err(anyhow!(
+                "Unsupported VBAN codec: {}. Supported: pcm16, pcm24, float32",
+                other
+            )),
+        }
+    }
+}
+
+/// Encode i16 samples into VBAN payload bytes (little-endian).
+/// Supports PCM16, PCM24 and Float32; `out` must hold `samples.len() * bytes_per_sample()`.
+/// Returns the number of bytes written.
+pub fn encode_samples(samples: &[i16], codec: VbanCodec, out: &mut [u8]) -> usize {
+    let bps = codec.bytes_per_sample();
+    for (sample, dst) in samples.iter().zip(out.chunks_exact_mut(bps)) {
+        match codec {
+            VbanCodec::Pcm24 => {
+                // 24-bit little-endian: sample occupies the upper 16 bits
+                let value = (*sample as i32) << 8;
+                dst.copy_from_slice(&value.to_le_bytes()[..3]);
+            }
+            VbanCodec::Float32 => {
+                let value = *sample as f32 / 32767.0;
+                dst.copy_from_slice(&value.to_le_bytes());
+            }
+            _ => dst.copy_from_slice(&sample.to_le_bytes()),
+        }
+    }
+    samples.len() * bps
+}
+
+/// Decode VBAN payload bytes into i16 samples.
+/// Unknown codecs are treated as PCM16.
+pub fn decode_samples(data: &[u8], codec: VbanCodec) -> Vec<i16> {
+    match codec {
+        VbanCodec::Pcm24 => data
+            .chunks_exact(3)
+            .map(|chunk| {
+                // Sign-extend 24-bit value, keep the upper 16 bits
+                let value = i32::from_le_bytes([0, chunk[0], chunk[1], chunk[2]]) >> 8;
+                (value >> 8) as i16
+            })
+            .collect(),
+        VbanCodec::Float32 => data
+            .chunks_exact(4)
+            .map(|chunk| {
+                let f = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                (f * 32767.0).round().clamp(-32768.0, 32767.0) as i16
+            })
+            .collect(),
+        _ => data
+            .chunks_exact(2)
+            .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect(),
+    }
 }
 
 /// VBAN packet header
@@ -89,9 +186,9 @@ impl VbanHeader {
         sample_rate: u32,
         channels: u8,
         codec: VbanCodec,
-    ) -> Result<Self> {
+    ) -> Result<Self, VbanError> {
         let sample_rate_index = sample_rate_to_index(sample_rate)
-            .ok_or_else(|| anyhow!("Unsupported sample rate: {}", sample_rate))?;
+            .ok_or(VbanError::UnsupportedSampleRate(sample_rate))?;
 
         let mut name_bytes = [0u8; VBAN_STREAM_NAME_SIZE];
         let name_len = stream_name.len().min(VBAN_STREAM_NAME_SIZE - 1);
@@ -133,7 +230,12 @@ impl VbanHeader {
     /// Encode header to bytes
     pub fn encode(&self, samples_per_frame: usize) -> [u8; VBAN_HEADER_SIZE] {
         let mut buf = [0u8; VBAN_HEADER_SIZE];
+        self.encode_into(&mut buf, samples_per_frame);
+        buf
+    }
 
+    /// Encode header into the first `VBAN_HEADER_SIZE` bytes of `buf`
+    pub fn encode_into(&self, buf: &mut [u8], samples_per_frame: usize) {
         // Magic "VBAN"
         buf[0..4].copy_from_slice(VBAN_MAGIC);
 
@@ -154,25 +256,23 @@ impl VbanHeader {
 
         // Frame counter (little-endian)
         buf[24..28].copy_from_slice(&self.frame_counter.to_le_bytes());
-
-        buf
     }
 
     /// Decode header from bytes
-    pub fn decode(data: &[u8]) -> Result<Self> {
+    pub fn decode(data: &[u8]) -> Result<Self, VbanError> {
         if data.len() < VBAN_HEADER_SIZE {
-            return Err(anyhow!("VBAN packet too short: {} bytes", data.len()));
+            return Err(VbanError::PacketTooShort { len: data.len() });
         }
 
         // Check magic
         if &data[0..4] != VBAN_MAGIC {
-            return Err(anyhow!("Invalid VBAN magic"));
+            return Err(VbanError::BadMagic);
         }
 
         // Check protocol type (upper 3 bits of byte 4)
         let protocol = data[4] & 0xE0;
         if protocol != VbanProtocol::Audio as u8 {
-            return Err(anyhow!("Not a VBAN audio packet"));
+            return Err(VbanError::NotAudio);
         }
 
         let mut stream_name = [0u8; VBAN_STREAM_NAME_SIZE];
@@ -188,14 +288,26 @@ impl VbanHeader {
         })
     }
 
-    /// Get stream name as string
-    pub fn stream_name_str(&self) -> &str {
-        let end = self
-            .stream_name
-            .iter()
-            .position(|&b| b == 0)
-            .unwrap_or(VBAN_STREAM_NAME_SIZE);
-        std::str::from_utf8(&self.stream_name[..end]).unwrap_or("")
+    /// Stream name for display; bytes that aren't UTF-8 show as U+FFFD
+    pub fn stream_name_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.stream_name_bytes())
+    }
+
+    /// Raw stream name up to the first null
+    pub fn stream_name_bytes(&self) -> &[u8] {
+        fixed_str_bytes(&self.stream_name)
+    }
+
+    /// Whether the packet belongs to stream `name`. Only the bytes before
+    /// the first null count, so garbage some senders leave after it doesn't.
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.stream_name_bytes() == name.as_bytes()
+    }
+
+    /// Like [`Self::matches_name`], ignoring ASCII case
+    pub fn matches_name_ignore_case(&self, name: &str) -> bool {
+        self.stream_name_bytes()
+            .eq_ignore_ascii_case(name.as_bytes())
     }
 }
 
@@ -211,6 +323,458 @@ pub fn sample_rate_to_index(rate: u32) -> Option<u8> {
 /// Maximum VBAN packet size (header + 256 samples * 8 channels * 4 bytes)
 pub const MAX_VBAN_PACKET_SIZE: usize = VBAN_HEADER_SIZE + 256 * 8 * 4;
 
+/// Maximum samples per channel in one VBAN audio packet
+pub const VBAN_MAX_SAMPLES_PER_FRAME: usize = 256;
+
+/// Builds outbound stereo VBAN audio packets in a reusable scratch buffer,
+/// so the send path does not allocate per packet.
+pub struct VbanPacketWriter {
+    header: VbanHeader,
+    codec: VbanCodec,
+    buf: [u8; MAX_VBAN_PACKET_SIZE],
+}
+
+impl VbanPacketWriter {
+    /// Create a writer for a stereo stream. The header's codec selects the payload format.
+    pub fn new(header: VbanHeader) -> Self {
+        let codec = VbanCodec::from_format(header.codec).unwrap_or(VbanCodec::Pcm16);
+        Self {
+            header,
+            codec,
+            buf: [0u8; MAX_VBAN_PACKET_SIZE],
+        }
+    }
+
+    /// Write a packet from mono samples, duplicated to both channels.
+    /// At most `VBAN_MAX_SAMPLES_PER_FRAME` samples are used. Returns the filled packet.
+    pub fn write_mono_as_stereo(&mut self, samples: &[i16], frame_counter: u32) -> &[u8] {
+        let samples = &samples[..samples.len().min(VBAN_MAX_SAMPLES_PER_FRAME)];
+        self.header.frame_counter = frame_counter;
+        self.header.encode_into(&mut self.buf, samples.len());
+
+        let frame_size = 2 * self.codec.bytes_per_sample();
+        let mut len = VBAN_HEADER_SIZE;
+        for &sample in samples {
+            encode_samples(&[sample, sample], self.codec, &mut self.buf[len..]);
+            len += frame_size;
+        }
+        &self.buf[..len]
+    }
+}
+
+/// Stream name used by VBAN service packets
+pub const VBAN_SERVICE_STREAM_NAME: &str = "VBAN Service";
+
+/// Service type for device identification ("VBAN Ping")
+pub const VBAN_SERVICE_IDENTIFICATION: u8 = 0x00;
+
+/// Ping function: request
+pub const VBAN_PING_REQUEST: u8 = 0x00;
+
+/// Ping function: reply flag
+pub const VBAN_PING_REPLY: u8 = 0x80;
+
+/// Size of the PING0 identification payload
+pub const VBAN_PING_DATA_SIZE: usize = 676;
+
+/// Ping device type: receptor
+pub const VBAN_PING_TYPE_RECEPTOR: u32 = 0x0000_0001;
+/// Ping device type: transmitter
+pub const VBAN_PING_TYPE_TRANSMITTER: u32 = 0x0000_0002;
+
+/// Ping feature: audio streams
+pub const VBAN_PING_FEATURE_AUDIO: u32 = 0x0000_0001;
+/// Ping feature: voice over IP (intercom)
+pub const VBAN_PING_FEATURE_VOIP: u32 = 0x0000_0004;
+
+// Byte offsets of the PING0 fields within the payload
+const PING_OFFSET_TYPE: usize = 0;
+const PING_OFFSET_FEATURES: usize = 4;
+const PING_OFFSET_FEATURES_EX: usize = 8;
+const PING_OFFSET_PREFERRED_RATE: usize = 12;
+const PING_OFFSET_MIN_RATE: usize = 16;
+const PING_OFFSET_MAX_RATE: usize = 20;
+const PING_OFFSET_COLOR: usize = 24;
+const PING_OFFSET_VERSION: usize = 28;
+const PING_OFFSET_LANG: usize = 48;
+const PING_OFFSET_DEVICE_NAME: usize = 164;
+const PING_OFFSET_MANUFACTURER: usize = 228;
+const PING_OFFSET_APPLICATION: usize = 292;
+const PING_OFFSET_HOST_NAME: usize = 356;
+const PING_OFFSET_USER_NAME: usize = 420;
+const PING_OFFSET_USER_COMMENT: usize = 548;
+
+/// VBAN service identification (PING0) payload
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VbanPing {
+    /// Device type bitmask (VBAN_PING_TYPE_*)
+    pub device_type: u32,
+    /// Feature bitmask (VBAN_PING_FEATURE_*)
+    pub features: u32,
+    /// Preferred sample rate in Hz
+    pub preferred_rate: u32,
+    /// Minimum supported sample rate in Hz
+    pub min_rate: u32,
+    /// Maximum supported sample rate in Hz
+    pub max_rate: u32,
+    /// Application version (major, minor, patch, build)
+    pub version: [u8; 4],
+    /// Physical device name
+    pub device_name: String,
+    /// Manufacturer name
+    pub manufacturer_name: String,
+    /// Application name
+    pub application_name: String,
+    /// DNS host name
+    pub host_name: String,
+    /// User name
+    pub user_name: String,
+}
+
+impl VbanPing {
+    /// Encode a complete service packet (header + PING0 payload)
+    pub fn encode(&self, reply: bool, frame_counter: u32) -> Vec<u8> {
+        let mut buf = vec![0u8; VBAN_HEADER_SIZE + VBAN_PING_DATA_SIZE];
+
+        buf[0..4].copy_from_slice(VBAN_MAGIC);
+        buf[4] = VbanProtocol::Service as u8;
+        buf[5] = if reply {
+            VBAN_PING_REPLY
+        } else {
+            VBAN_PING_REQUEST
+        };
+        buf[6] = VBAN_SERVICE_IDENTIFICATION;
+        buf[7] = 0;
+        write_fixed_str(&mut buf[8..24], VBAN_SERVICE_STREAM_NAME);
+        buf[24..28].copy_from_slice(&frame_counter.to_le_bytes());
+
+        let data = &mut buf[VBAN_HEADER_SIZE..];
+        let put_u32 = |data: &mut [u8], offset: usize, value: u32| {
+            data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        };
+        put_u32(data, PING_OFFSET_TYPE, self.device_type);
+        put_u32(data, PING_OFFSET_FEATURES, self.features);
+        put_u32(data, PING_OFFSET_FEATURES_EX, 0);
+        put_u32(data, PING_OFFSET_PREFERRED_RATE, self.preferred_rate);
+        put_u32(data, PING_OFFSET_MIN_RATE, self.min_rate);
+        put_u32(data, PING_OFFSET_MAX_RATE, self.max_rate);
+        put_u32(data, PING_OFFSET_COLOR, 0);
+        // Version is stored as a little-endian number: build, patch, minor, major
+        data[PING_OFFSET_VERSION..PING_OFFSET_VERSION + 4].copy_from_slice(&[
+            self.version[3],
+            self.version[2],
+            self.version[1],
+            self.version[0],
+        ]);
+        write_fixed_str(&mut data[PING_OFFSET_LANG..PING_OFFSET_LANG + 8], "EN");
+        write_fixed_str(
+            &mut data[PING_OFFSET_DEVICE_NAME..PING_OFFSET_MANUFACTURER],
+            &self.device_name,
+        );
+        write_fixed_str(
+            &mut data[PING_OFFSET_MANUFACTURER..PING_OFFSET_APPLICATION],
+            &self.manufacturer_name,
+        );
+        write_fixed_str(
+            &mut data[PING_OFFSET_APPLICATION..PING_OFFSET_HOST_NAME],
+            &self.application_name,
+        );
+        write_fixed_str(
+            &mut data[PING_OFFSET_HOST_NAME..PING_OFFSET_USER_NAME],
+            &self.host_name,
+        );
+        write_fixed_str(
+            &mut data[PING_OFFSET_USER_NAME..PING_OFFSET_USER_COMMENT],
+            &self.user_name,
+        );
+
+        buf
+    }
+
+    /// Decode a service packet, returning the payload and whether it is a reply
+    pub fn decode(data: &[u8]) -> Result<(Self, bool), VbanError> {
+        if data.len() < VBAN_HEADER_SIZE + VBAN_PING_DATA_SIZE {
+            return Err(VbanError::PacketTooShort { len: data.len() });
+        }
+        if !is_ping(data) {
+            return Err(VbanError::NotPing);
+        }
+
+        let reply = data[5] & VBAN_PING_REPLY != 0;
+        let payload = &data[VBAN_HEADER_SIZE..VBAN_HEADER_SIZE + VBAN_PING_DATA_SIZE];
+        let get_u32 = |offset: usize| {
+            u32::from_le_bytes([
+                payload[offset],
+                payload[offset + 1],
+                payload[offset + 2],
+                payload[offset + 3],
+            ])
+        };
+        let v = &payload[PING_OFFSET_VERSION..PING_OFFSET_VERSION + 4];
+
+        let ping = Self {
+            device_type: get_u32(PING_OFFSET_TYPE),
+            features: get_u32(PING_OFFSET_FEATURES),
+            preferred_rate: get_u32(PING_OFFSET_PREFERRED_RATE),
+            min_rate: get_u32(PING_OFFSET_MIN_RATE),
+            max_rate: get_u32(PING_OFFSET_MAX_RATE),
+            version: [v[3], v[2], v[1], v[0]],
+            device_name: read_fixed_str(
+                &payload[PING_OFFSET_DEVICE_NAME..PING_OFFSET_MANUFACTURER],
+            ),
+            manufacturer_name: read_fixed_str(
+                &payload[PING_OFFSET_MANUFACTURER..PING_OFFSET_APPLICATION],
+            ),
+            application_name: read_fixed_str(
+                &payload[PING_OFFSET_APPLICATION..PING_OFFSET_HOST_NAME],
+            ),
+            host_name: read_fixed_str(&payload[PING_OFFSET_HOST_NAME..PING_OFFSET_USER_NAME]),
+            user_name: read_fixed_str(&payload[PING_OFFSET_USER_NAME..PING_OFFSET_USER_COMMENT]),
+        };
+        Ok((ping, reply))
+    }
+}
+
+// =============================================================================
+// Serial
+// =============================================================================
+
+/// VBAN serial bit rates (index -> bps); index 0 means unspecified
+pub const SERIAL_BIT_RATES: &[u32] = &[
+    0, 110, 150, 300, 600, 1200, 2400, 4800, 9600, 14400, 19200, 31250, 38400, 57600, 115200,
+    128000, 230400, 250000, 256000, 460800, 921600, 1000000, 1500000, 2000000, 3000000,
+];
+
+/// Largest payload of one VBAN packet
+pub const VBAN_SERIAL_MAX_PAYLOAD: usize = 1436;
+
+/// VBAN serial stream types (upper nibble of the format byte; the data
+/// type in the lower 3 bits is always 8-bit bytes)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VbanSerialType {
+    Generic = 0x00,
+    Midi = 0x10,
+    User = 0xF0,
+}
+
+/// VBAN SERIAL (protocol 0x20) packet header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VbanSerialHeader {
+    /// Index into `SERIAL_BIT_RATES` (0-31)
+    pub bit_rate_index: u8,
+    /// Channel ident (0-255), telling apart ports of one stream
+    pub channel: u8,
+    /// Format byte: stream type in the upper nibble
+    pub format: u8,
+    /// Stream name (up to 16 bytes, null-terminated)
+    pub stream_name: [u8; VBAN_STREAM_NAME_SIZE],
+    /// Frame counter
+    pub frame_counter: u32,
+}
+
+impl VbanSerialHeader {
+    /// Header for a generic serial stream. Bit rates VBAN has no index for
+    /// are sent as unspecified.
+    pub fn new(stream_name: &str, bit_rate: u32, channel: u8) -> Self {
+        let mut name = [0u8; VBAN_STREAM_NAME_SIZE];
+        write_fixed_str(&mut name, stream_name);
+        Self {
+            bit_rate_index: serial_bit_rate_to_index(bit_rate).unwrap_or(0),
+            channel,
+            format: VbanSerialType::Generic as u8,
+            stream_name: name,
+            frame_counter: 0,
+        }
+    }
+
+    /// Bit rate in bps, 0 if unspecified
+    pub fn bit_rate(&self) -> u32 {
+        SERIAL_BIT_RATES
+            .get((self.bit_rate_index & 0x1F) as usize)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Stream type from the format byte, None for reserved values
+    pub fn serial_type(&self) -> Option<VbanSerialType> {
+        match self.format & 0xF0 {
+            0x00 => Some(VbanSerialType::Generic),
+            0x10 => Some(VbanSerialType::Midi),
+            0xF0 => Some(VbanSerialType::User),
+            _ => None,
+        }
+    }
+
+    pub fn stream_name_str(&self) -> String {
+        read_fixed_str(&self.stream_name)
+    }
+
+    /// Raw stream name up to the first null
+    pub fn stream_name_bytes(&self) -> &[u8] {
+        fixed_str_bytes(&self.stream_name)
+    }
+
+    /// Whether the packet belongs to stream `name`, compared byte for byte
+    /// up to the first null
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.stream_name_bytes() == name.as_bytes()
+    }
+
+    /// Encode the header followed by `payload` into `buf`, replacing its
+    /// contents
+    pub fn encode_packet(&self, payload: &[u8], buf: &mut Vec<u8>) -> Result<(), VbanError> {
+        if payload.len() > VBAN_SERIAL_MAX_PAYLOAD {
+            return Err(VbanError::PayloadTooLong { len: payload.len() });
+        }
+        buf.clear();
+        buf.extend_from_slice(VBAN_MAGIC);
+        buf.push((self.bit_rate_index & 0x1F) | VbanProtocol::Serial as u8);
+        // Samples per frame are unused for serial
+        buf.push(0);
+        buf.push(self.channel);
+        buf.push(self.format);
+        buf.extend_from_slice(&self.stream_name);
+        buf.extend_from_slice(&self.frame_counter.to_le_bytes());
+        buf.extend_from_slice(payload);
+        Ok(())
+    }
+
+    /// Decode a serial packet into its header and payload
+    pub fn decode(data: &[u8]) -> Result<(Self, &[u8]), VbanError> {
+        if data.len() < VBAN_HEADER_SIZE {
+            return Err(VbanError::PacketTooShort { len: data.len() });
+        }
+        if &data[0..4] != VBAN_MAGIC {
+            return Err(VbanError::BadMagic);
+        }
+        if data[4] & 0xE0 != VbanProtocol::Serial as u8 {
+            return Err(VbanError::NotSerial);
+        }
+        let mut stream_name = [0u8; VBAN_STREAM_NAME_SIZE];
+        stream_name.copy_from_slice(&data[8..24]);
+        let header = Self {
+            bit_rate_index: data[4] & 0x1F,
+            channel: data[6],
+            format: data[7],
+            stream_name,
+            frame_counter: u32::from_le_bytes([data[24], data[25], data[26], data[27]]),
+        };
+        Ok((header, &data[VBAN_HEADER_SIZE..]))
+    }
+}
+
+/// Convert a serial bit rate to its VBAN index
+pub fn serial_bit_rate_to_index(bit_rate: u32) -> Option<u8> {
+    if bit_rate == 0 {
+        return None;
+    }
+    SERIAL_BIT_RATES
+        .iter()
+        .position(|&r| r == bit_rate)
+        .map(|i| i as u8)
+}
+
+// =============================================================================
+// Text
+// =============================================================================
+
+/// Format byte of a UTF-8 text stream (8-bit data, stream type 0x10)
+pub const VBAN_TEXT_UTF8: u8 = 0x10;
+
+/// Encode a VBAN TEXT (protocol 0x40) packet carrying `text` as UTF-8 into
+/// `buf`, replacing its contents. Text has no bit rate and uses channel 0.
+pub fn encode_text_packet(
+    stream_name: &str,
+    frame_counter: u32,
+    text: &str,
+    buf: &mut Vec<u8>,
+) -> Result<(), VbanError> {
+    if text.len() > VBAN_SERIAL_MAX_PAYLOAD {
+        return Err(VbanError::PayloadTooLong { len: text.len() });
+    }
+    let mut name = [0u8; VBAN_STREAM_NAME_SIZE];
+    write_fixed_str(&mut name, stream_name);
+    buf.clear();
+    buf.extend_from_slice(VBAN_MAGIC);
+    buf.push(VbanProtocol::Text as u8);
+    buf.push(0);
+    buf.push(0);
+    buf.push(VBAN_TEXT_UTF8);
+    buf.extend_from_slice(&name);
+    buf.extend_from_slice(&frame_counter.to_le_bytes());
+    buf.extend_from_slice(text.as_bytes());
+    Ok(())
+}
+
+/// A received VBAN TEXT packet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextPacket<'a> {
+    stream_name: &'a [u8],
+    /// The text; bytes that aren't UTF-8 show as U+FFFD
+    pub text: Cow<'a, str>,
+}
+
+impl TextPacket<'_> {
+    /// Whether the packet belongs to stream `name`, compared byte for byte
+    /// up to the first null
+    pub fn matches_name(&self, name: &str) -> bool {
+        self.stream_name == name.as_bytes()
+    }
+
+    /// Like [`Self::matches_name`], ignoring ASCII case
+    pub fn matches_name_ignore_case(&self, name: &str) -> bool {
+        self.stream_name.eq_ignore_ascii_case(name.as_bytes())
+    }
+}
+
+/// Decode a VBAN TEXT packet as written by [`encode_text_packet`]
+pub fn decode_text_packet(data: &[u8]) -> Result<TextPacket<'_>, VbanError> {
+    if data.len() < VBAN_HEADER_SIZE {
+        return Err(VbanError::PacketTooShort { len: data.len() });
+    }
+    if &data[0..4] != VBAN_MAGIC {
+        return Err(VbanError::BadMagic);
+    }
+    if data[4] & 0xE0 != VbanProtocol::Text as u8 {
+        return Err(VbanError::NotText);
+    }
+    Ok(TextPacket {
+        stream_name: fixed_str_bytes(&data[8..24]),
+        text: String::from_utf8_lossy(&data[VBAN_HEADER_SIZE..]),
+    })
+}
+
+/// Check whether a packet is a VBAN service identification (ping) packet
+pub fn is_ping(data: &[u8]) -> bool {
+    data.len() >= VBAN_HEADER_SIZE
+        && &data[0..4] == VBAN_MAGIC
+        && data[4] & 0xE0 == VbanProtocol::Service as u8
+        && data[6] == VBAN_SERVICE_IDENTIFICATION
+}
+
+/// Check whether a packet is a VBAN ping request (not a reply)
+pub fn is_ping_request(data: &[u8]) -> bool {
+    is_ping(data) && data[5] & VBAN_PING_REPLY == 0
+}
+
+/// Write a null-terminated string into a fixed-size field, truncating if needed
+fn write_fixed_str(field: &mut [u8], value: &str) {
+    let len = value.len().min(field.len().saturating_sub(1));
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
+}
+
+/// Read a null-terminated string from a fixed-size field
+fn read_fixed_str(field: &[u8]) -> String {
+    String::from_utf8_lossy(fixed_str_bytes(field)).into_owned()
+}
+
+/// The bytes of a fixed-size field before its first null
+fn fixed_str_bytes(field: &[u8]) -> &[u8] {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..end]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -296,6 +860,61 @@ mod tests {
         assert_eq!(name, "this_is_a_very_");
     }
 
+    /// An audio header whose name field is `name`, as sent
+    fn header_with_name(name: &[u8; VBAN_STREAM_NAME_SIZE]) -> VbanHeader {
+        let mut encoded = VbanHeader::new("x", 48000, 1, VbanCodec::Pcm16)
+            .unwrap()
+            .encode(256);
+        encoded[8..24].copy_from_slice(name);
+        VbanHeader::decode(&encoded).unwrap()
+    }
+
+    #[test]
+    fn test_stream_name_trailing_garbage() {
+        // VoiceMeeter sometimes leaves bytes of an older name after the null
+        let header = header_with_name(b"cam1\0\xe9\xff\x80Stream\0\0");
+        assert_eq!(header.stream_name_bytes(), b"cam1");
+        assert_eq!(header.stream_name_str(), "cam1");
+        assert!(header.matches_name("cam1"));
+        assert!(!header.matches_name("cam"));
+        assert!(!header.matches_name("cam1\u{e9}"));
+    }
+
+    #[test]
+    fn test_stream_name_non_utf8() {
+        let header = header_with_name(b"cam\xe91\0\0\0\0\0\0\0\0\0\0\0");
+        assert_eq!(header.stream_name_bytes(), b"cam\xe91");
+        // Shown lossily instead of as an empty name
+        assert_eq!(header.stream_name_str(), "cam\u{fffd}1");
+        assert!(!header.matches_name("cam1"));
+        assert!(!header.matches_name(""));
+
+        // A full 16-byte name has no null at all
+        let header = header_with_name(b"sixteen_byte_nam");
+        assert!(header.matches_name("sixteen_byte_nam"));
+    }
+
+    #[test]
+    fn test_stream_name_ignore_case() {
+        let header = header_with_name(b"Cam1\0\xff\xff\0\0\0\0\0\0\0\0\0");
+        assert!(!header.matches_name("cam1"));
+        assert!(header.matches_name_ignore_case("cam1"));
+        assert!(header.matches_name_ignore_case("CAM1"));
+        assert!(!header.matches_name_ignore_case("cam2"));
+
+        let (serial, _) = VbanSerialHeader::decode(&{
+            let mut buf = Vec::new();
+            VbanSerialHeader::new("ptz", 9600, 0)
+                .encode_packet(&[], &mut buf)
+                .unwrap();
+            buf[12] = 0xff;
+            buf
+        })
+        .unwrap();
+        assert!(serial.matches_name("ptz"));
+        assert_eq!(serial.stream_name_bytes(), b"ptz");
+    }
+
     #[test]
     fn test_stream_name_exactly_max_length() {
         let exact_name = "exactly15chars!"; // 15 chars
@@ -401,6 +1020,475 @@ mod tests {
         assert_eq!(VbanProtocol::Service as u8, 0x60);
     }
 
+    #[test]
+    fn test_codec_from_name() {
+        assert_eq!(VbanCodec::from_name("pcm16").unwrap(), VbanCodec::Pcm16);
+        assert_eq!(VbanCodec::from_name("PCM24").unwrap(), VbanCodec::Pcm24);
+        assert_eq!(VbanCodec::from_name("float32").unwrap(), VbanCodec::Float32);
+        assert!(VbanCodec::from_name("mp3").is_err());
+    }
+
+    #[test]
+    fn test_codec_from_format() {
+        assert_eq!(VbanCodec::from_format(0x01), Some(VbanCodec::Pcm16));
+        assert_eq!(VbanCodec::from_format(0x02), Some(VbanCodec::Pcm24));
+        assert_eq!(VbanCodec::from_format(0x04), Some(VbanCodec::Float32));
+        // Upper bits (codec type) are ignored
+        assert_eq!(VbanCodec::from_format(0x12), Some(VbanCodec::Pcm24));
+        assert_eq!(VbanCodec::from_format(0x07), None);
+    }
+
+    fn test_samples(len: usize) -> Vec<i16> {
+        let mut samples: Vec<i16> = (0..len)
+            .map(|i| ((i as i32 * 7919) % 65536 - 32768) as i16)
+            .collect();
+        if len >= 3 {
+            samples[0] = i16::MIN;
+            samples[1] = i16::MAX;
+            samples[2] = 0;
+        }
+        samples
+    }
+
+    #[test]
+    fn test_codec_roundtrip_all_tx_codecs() {
+        for codec in [VbanCodec::Pcm16, VbanCodec::Pcm24, VbanCodec::Float32] {
+            for chunk in [1, 2, 64, 128, 256, 512] {
+                let samples = test_samples(chunk);
+                let mut payload = vec![0u8; chunk * codec.bytes_per_sample()];
+                let written = encode_samples(&samples, codec, &mut payload);
+                assert_eq!(written, payload.len());
+
+                let decoded = decode_samples(&payload, codec);
+                assert_eq!(
+                    decoded, samples,
+                    "Round-trip failed for {:?} with {} samples",
+                    codec, chunk
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_pcm24_packing() {
+        let mut payload = [0u8; 6];
+        encode_samples(&[0x1234, -1], VbanCodec::Pcm24, &mut payload);
+        // 0x1234 << 8 = 0x123400, little-endian 3 bytes
+        assert_eq!(payload[0..3], [0x00, 0x34, 0x12]);
+        // -1 << 8 = 0xFFFF00
+        assert_eq!(payload[3..6], [0x00, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_pcm24_decode_sign_extension() {
+        // Most negative 24-bit value
+        let decoded = decode_samples(&[0x00, 0x00, 0x80], VbanCodec::Pcm24);
+        assert_eq!(decoded, vec![i16::MIN]);
+    }
+
+    #[test]
+    fn test_float32_encoding_range() {
+        let mut payload = [0u8; 8];
+        encode_samples(&[i16::MAX, 0], VbanCodec::Float32, &mut payload);
+        let max = f32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        let zero = f32::from_le_bytes([payload[4], payload[5], payload[6], payload[7]]);
+        assert!((max - 1.0).abs() < 1e-6);
+        assert_eq!(zero, 0.0);
+    }
+
+    /// Reference packet built the way the intercom send loop used to build it
+    fn hand_rolled_packet(
+        chunk: &[i16],
+        stream: &str,
+        codec: VbanCodec,
+        frame_counter: u32,
+    ) -> Vec<u8> {
+        let stereo_data: Vec<i16> = chunk.iter().flat_map(|&s| [s, s]).collect();
+        let mut stream_name_bytes = [0u8; 16];
+        stream_name_bytes[..stream.len()].copy_from_slice(stream.as_bytes());
+        let mut packet = vec![0u8; VBAN_HEADER_SIZE + stereo_data.len() * codec.bytes_per_sample()];
+        packet[0..4].copy_from_slice(b"VBAN");
+        packet[4] = 3; // 48kHz
+        packet[5] = (chunk.len().saturating_sub(1) & 0xFF) as u8;
+        packet[6] = 1; // 2 channels - 1
+        packet[7] = codec as u8;
+        packet[8..24].copy_from_slice(&stream_name_bytes);
+        packet[24..28].copy_from_slice(&frame_counter.to_le_bytes());
+        encode_samples(&stereo_data, codec, &mut packet[VBAN_HEADER_SIZE..]);
+        packet
+    }
+
+    #[test]
+    fn test_header_encode_into_matches_encode() {
+        let mut header = VbanHeader::new("cam1", 48000, 2, VbanCodec::Pcm16).unwrap();
+        header.frame_counter = 0xDEADBEEF;
+        let mut buf = [0xAAu8; VBAN_HEADER_SIZE + 4];
+        header.encode_into(&mut buf, 128);
+        assert_eq!(buf[..VBAN_HEADER_SIZE], header.encode(128));
+        // Bytes past the header are untouched
+        assert_eq!(buf[VBAN_HEADER_SIZE..], [0xAA; 4]);
+    }
+
+    #[test]
+    fn test_packet_writer_matches_hand_rolled() {
+        let chunk: Vec<i16> = (0..128).map(|i| (i * 257 - 16000) as i16).collect();
+        for codec in [VbanCodec::Pcm16, VbanCodec::Pcm24, VbanCodec::Float32] {
+            let header = VbanHeader::new("cam1", 48000, 2, codec).unwrap();
+            let mut writer = VbanPacketWriter::new(header);
+            for frame_counter in [0, 1, 0xFFFF_FFFF] {
+                let expected = hand_rolled_packet(&chunk, "cam1", codec, frame_counter);
+                assert_eq!(
+                    writer.write_mono_as_stereo(&chunk, frame_counter),
+                    &expected[..]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_packet_writer_partial_chunk() {
+        let chunk = [1i16, -1, i16::MAX];
+        let header = VbanHeader::new("cam1", 48000, 2, VbanCodec::Pcm16).unwrap();
+        let mut writer = VbanPacketWriter::new(header);
+        // Long packet first, so stale scratch bytes would show up if the length was wrong
+        writer.write_mono_as_stereo(&[7; 128], 0);
+        let expected = hand_rolled_packet(&chunk, "cam1", VbanCodec::Pcm16, 5);
+        assert_eq!(writer.write_mono_as_stereo(&chunk, 5), &expected[..]);
+    }
+
+    #[test]
+    fn test_packet_writer_caps_samples_per_frame() {
+        let header = VbanHeader::new("cam1", 48000, 2, VbanCodec::Float32).unwrap();
+        let mut writer = VbanPacketWriter::new(header);
+        let packet = writer.write_mono_as_stereo(&[0; 300], 0);
+        assert_eq!(packet.len(), VBAN_HEADER_SIZE + 256 * 2 * 4);
+        assert_eq!(packet[5], 255);
+    }
+
+    fn sample_ping() -> VbanPing {
+        VbanPing {
+            device_type: VBAN_PING_TYPE_RECEPTOR | VBAN_PING_TYPE_TRANSMITTER,
+            features: VBAN_PING_FEATURE_AUDIO | VBAN_PING_FEATURE_VOIP,
+            preferred_rate: 48000,
+            min_rate: 48000,
+            max_rate: 48000,
+            version: [1, 2, 3, 0],
+            device_name: "camera-box".to_string(),
+            manufacturer_name: "camera-box".to_string(),
+            application_name: "camera-box intercom".to_string(),
+            host_name: "CAM1".to_string(),
+            user_name: "CAM1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ping_header_golden_bytes() {
+        let packet = sample_ping().encode(true, 7);
+        assert_eq!(packet.len(), 704);
+        #[rustfmt::skip]
+        let expected: [u8; 28] = [
+            b'V', b'B', b'A', b'N',
+            0x60, // Service protocol
+            0x80, // Reply function
+            0x00, // Identification service
+            0x00,
+            b'V', b'B', b'A', b'N', b' ', b'S', b'e', b'r', b'v', b'i', b'c', b'e', 0, 0, 0, 0,
+            0x07, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(packet[..28], expected);
+    }
+
+    /// `struct tagVBAN_PING0` from the VBAN specification: field names and
+    /// sizes in declaration order, with no padding between them
+    #[rustfmt::skip]
+    const PING0_FIELDS: [(&str, usize); 22] = [
+        ("bitType", 4), ("bitfeature", 4), ("bitfeatureEx", 4),
+        ("PreferedRate", 4), ("MinRate", 4), ("MaxRate", 4),
+        ("color_rgb", 4), ("nVersion", 4),
+        ("GPS_Position", 8), ("USER_Position", 8), ("LangCode_ascii", 8),
+        ("reserved_ascii", 8), ("reservedEx", 64), ("DistantIP_ascii", 32),
+        ("DistantPort", 2), ("DistantReserved", 2),
+        ("DeviceName_ascii", 64), ("ManufacturerName_ascii", 64),
+        ("ApplicationName_ascii", 64), ("HostName_ascii", 64),
+        ("UserName_utf8", 128), ("UserComment_utf8", 128),
+    ];
+
+    /// Byte range of a PING0 field, from the spec sizes alone
+    fn ping0_field(name: &str) -> std::ops::Range<usize> {
+        let mut offset = 0;
+        for (field, size) in PING0_FIELDS {
+            if field == name {
+                return offset..offset + size;
+            }
+            offset += size;
+        }
+        panic!("no PING0 field {}", name);
+    }
+
+    #[test]
+    fn test_ping0_spec_layout() {
+        let total: usize = PING0_FIELDS.iter().map(|(_, size)| size).sum();
+        assert_eq!(total, 676);
+        assert_eq!(ping0_field("bitType"), 0..4);
+        assert_eq!(ping0_field("nVersion"), 28..32);
+        assert_eq!(ping0_field("LangCode_ascii"), 48..56);
+        assert_eq!(ping0_field("DeviceName_ascii"), 164..228);
+        assert_eq!(ping0_field("UserName_utf8"), 420..548);
+        assert_eq!(ping0_field("UserComment_utf8"), 548..676);
+
+        // The encoder's offsets follow the spec
+        for (offset, name) in [
+            (PING_OFFSET_TYPE, "bitType"),
+            (PING_OFFSET_FEATURES, "bitfeature"),
+            (PING_OFFSET_FEATURES_EX, "bitfeatureEx"),
+            (PING_OFFSET_PREFERRED_RATE, "PreferedRate"),
+            (PING_OFFSET_MIN_RATE, "MinRate"),
+            (PING_OFFSET_MAX_RATE, "MaxRate"),
+            (PING_OFFSET_COLOR, "color_rgb"),
+            (PING_OFFSET_VERSION, "nVersion"),
+            (PING_OFFSET_LANG, "LangCode_ascii"),
+            (PING_OFFSET_DEVICE_NAME, "DeviceName_ascii"),
+            (PING_OFFSET_MANUFACTURER, "ManufacturerName_ascii"),
+            (PING_OFFSET_APPLICATION, "ApplicationName_ascii"),
+            (PING_OFFSET_HOST_NAME, "HostName_ascii"),
+            (PING_OFFSET_USER_NAME, "UserName_utf8"),
+            (PING_OFFSET_USER_COMMENT, "UserComment_utf8"),
+        ] {
+            assert_eq!(offset, ping0_field(name).start, "{}", name);
+        }
+        assert_eq!(VBAN_PING_DATA_SIZE, 676);
+    }
+
+    #[test]
+    fn test_ping_payload_golden_layout() {
+        let packet = sample_ping().encode(false, 0);
+        assert_eq!(packet[5], VBAN_PING_REQUEST);
+
+        // Build the expected payload from the spec layout, not the encoder
+        let mut expected = [0u8; 676];
+        let mut put = |name: &str, bytes: &[u8]| {
+            let field = ping0_field(name);
+            assert!(bytes.len() <= field.len(), "{}", name);
+            expected[field.start..field.start + bytes.len()].copy_from_slice(bytes);
+        };
+        put("bitType", &[0x03, 0, 0, 0]); // receptor | transmitter
+        put("bitfeature", &[0x05, 0, 0, 0]); // audio | voip
+        put("PreferedRate", &[0x80, 0xBB, 0, 0]); // 48000
+        put("MinRate", &[0x80, 0xBB, 0, 0]);
+        put("MaxRate", &[0x80, 0xBB, 0, 0]);
+        put("nVersion", &[0x00, 0x03, 0x02, 0x01]); // 0x01020300 little-endian
+        put("LangCode_ascii", b"EN");
+        put("DeviceName_ascii", b"camera-box");
+        put("ManufacturerName_ascii", b"camera-box");
+        put("ApplicationName_ascii", b"camera-box intercom");
+        put("HostName_ascii", b"CAM1");
+        put("UserName_utf8", b"CAM1");
+
+        let data = &packet[VBAN_HEADER_SIZE..];
+        for (name, _) in PING0_FIELDS {
+            let field = ping0_field(name);
+            assert_eq!(data[field.clone()], expected[field], "{}", name);
+        }
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_ping_roundtrip() {
+        let ping = sample_ping();
+        let (decoded, reply) = VbanPing::decode(&ping.encode(true, 1)).unwrap();
+        assert!(reply);
+        assert_eq!(decoded, ping);
+    }
+
+    #[test]
+    fn test_ping_name_truncation() {
+        let ping = VbanPing {
+            host_name: "h".repeat(100),
+            ..sample_ping()
+        };
+        let (decoded, _) = VbanPing::decode(&ping.encode(false, 0)).unwrap();
+        assert_eq!(decoded.host_name.len(), 63);
+    }
+
+    #[test]
+    fn test_ping_request_detection() {
+        let request = sample_ping().encode(false, 0);
+        let reply = sample_ping().encode(true, 0);
+        assert!(is_ping_request(&request));
+        assert!(!is_ping_request(&reply));
+        assert!(is_ping(&reply));
+
+        let audio = VbanHeader::new("test", 48000, 2, VbanCodec::Pcm16)
+            .unwrap()
+            .encode(256);
+        assert!(!is_ping(&audio));
+    }
+
+    #[test]
+    fn test_ping_decode_too_short() {
+        let packet = sample_ping().encode(false, 0);
+        assert!(VbanPing::decode(&packet[..100]).is_err());
+    }
+
+    #[test]
+    fn test_decode_error_variants() {
+        assert_eq!(
+            VbanHeader::decode(&[0u8; 20]).unwrap_err(),
+            VbanError::PacketTooShort { len: 20 }
+        );
+        assert_eq!(
+            VbanHeader::decode(&[0u8; VBAN_HEADER_SIZE]).unwrap_err(),
+            VbanError::BadMagic
+        );
+        let ping = sample_ping().encode(true, 1);
+        assert_eq!(VbanHeader::decode(&ping).unwrap_err(), VbanError::NotAudio);
+        let mut audio = VbanHeader::new("test", 48000, 2, VbanCodec::Pcm16)
+            .unwrap()
+            .encode(256)
+            .to_vec();
+        audio.resize(VBAN_HEADER_SIZE + VBAN_PING_DATA_SIZE, 0);
+        assert_eq!(VbanPing::decode(&audio).unwrap_err(), VbanError::NotPing);
+        assert_eq!(
+            VbanHeader::new("test", 12345, 2, VbanCodec::Pcm16).unwrap_err(),
+            VbanError::UnsupportedSampleRate(12345)
+        );
+    }
+
+    #[test]
+    fn test_serial_golden_bytes() {
+        let mut header = VbanSerialHeader::new("ptz", 9600, 3);
+        header.frame_counter = 0x0102_0304;
+        let mut packet = Vec::new();
+        header
+            .encode_packet(&[0x81, 0x01, 0x06, 0xFF], &mut packet)
+            .unwrap();
+
+        assert_eq!(&packet[0..4], b"VBAN");
+        // 9600 bps is index 8, protocol 0x20 in the upper bits
+        assert_eq!(packet[4], 0x28);
+        assert_eq!(packet[5], 0);
+        assert_eq!(packet[6], 3);
+        assert_eq!(packet[7], VbanSerialType::Generic as u8);
+        assert_eq!(&packet[8..11], b"ptz");
+        assert!(packet[11..24].iter().all(|&b| b == 0));
+        assert_eq!(&packet[24..28], &[0x04, 0x03, 0x02, 0x01]);
+        assert_eq!(&packet[28..], &[0x81, 0x01, 0x06, 0xFF]);
+    }
+
+    #[test]
+    fn test_serial_roundtrip() {
+        let mut header = VbanSerialHeader::new("lens", 115200, 0);
+        header.format = VbanSerialType::Midi as u8;
+        let mut packet = Vec::new();
+        header.encode_packet(b"hello", &mut packet).unwrap();
+        let (decoded, payload) = VbanSerialHeader::decode(&packet).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.bit_rate(), 115200);
+        assert_eq!(decoded.serial_type(), Some(VbanSerialType::Midi));
+        assert_eq!(decoded.stream_name_str(), "lens");
+        assert_eq!(payload, b"hello");
+
+        // Empty payloads are valid, e.g. as keepalives
+        header.encode_packet(&[], &mut packet).unwrap();
+        assert_eq!(VbanSerialHeader::decode(&packet).unwrap().1, &[] as &[u8]);
+    }
+
+    #[test]
+    fn test_text_golden_bytes() {
+        let mut packet = Vec::new();
+        encode_text_packet("cam1", 7, "mic silent", &mut packet).unwrap();
+        assert_eq!(&packet[0..4], b"VBAN");
+        assert_eq!(packet[4], 0x40);
+        assert_eq!(&packet[5..8], &[0, 0, VBAN_TEXT_UTF8]);
+        assert_eq!(&packet[8..12], b"cam1");
+        assert!(packet[12..24].iter().all(|&b| b == 0));
+        assert_eq!(&packet[24..28], &[7, 0, 0, 0]);
+        assert_eq!(&packet[28..], b"mic silent");
+        // Not mistaken for audio or serial
+        assert_eq!(
+            VbanHeader::decode(&packet).unwrap_err(),
+            VbanError::NotAudio
+        );
+        assert_eq!(
+            VbanSerialHeader::decode(&packet).unwrap_err(),
+            VbanError::NotSerial
+        );
+
+        let long = "x".repeat(VBAN_SERIAL_MAX_PAYLOAD + 1);
+        assert_eq!(
+            encode_text_packet("cam1", 0, &long, &mut packet).unwrap_err(),
+            VbanError::PayloadTooLong {
+                len: VBAN_SERIAL_MAX_PAYLOAD + 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let mut packet = Vec::new();
+        encode_text_packet("Cam1", 3, "call=1", &mut packet).unwrap();
+        let text = decode_text_packet(&packet).unwrap();
+        assert_eq!(text.text, "call=1");
+        assert!(text.matches_name("Cam1"));
+        assert!(!text.matches_name("cam1"));
+        assert!(text.matches_name_ignore_case("cam1"));
+
+        // Audio is not text
+        let audio = VbanHeader::new("Cam1", 48000, 2, VbanCodec::Pcm16)
+            .unwrap()
+            .encode(1);
+        assert_eq!(decode_text_packet(&audio).unwrap_err(), VbanError::NotText);
+        assert_eq!(
+            decode_text_packet(&packet[..10]).unwrap_err(),
+            VbanError::PacketTooShort { len: 10 }
+        );
+    }
+
+    #[test]
+    fn test_serial_bit_rates() {
+        assert_eq!(serial_bit_rate_to_index(110), Some(1));
+        assert_eq!(serial_bit_rate_to_index(3000000), Some(24));
+        assert_eq!(serial_bit_rate_to_index(0), None);
+        assert_eq!(serial_bit_rate_to_index(12345), None);
+        // Rates without an index go out as unspecified
+        assert_eq!(VbanSerialHeader::new("x", 12345, 0).bit_rate(), 0);
+        for (i, &rate) in SERIAL_BIT_RATES.iter().enumerate().skip(1) {
+            assert_eq!(VbanSerialHeader::new("x", rate, 0).bit_rate_index, i as u8);
+        }
+    }
+
+    #[test]
+    fn test_serial_decode_errors() {
+        let audio = VbanHeader::new("test", 48000, 2, VbanCodec::Pcm16)
+            .unwrap()
+            .encode(1);
+        assert_eq!(
+            VbanSerialHeader::decode(&audio).unwrap_err(),
+            VbanError::NotSerial
+        );
+        assert_eq!(
+            VbanSerialHeader::decode(&audio[..10]).unwrap_err(),
+            VbanError::PacketTooShort { len: 10 }
+        );
+
+        let mut packet = Vec::new();
+        VbanSerialHeader::new("x", 9600, 0)
+            .encode_packet(b"x", &mut packet)
+            .unwrap();
+        assert_eq!(
+            VbanHeader::decode(&packet).unwrap_err(),
+            VbanError::NotAudio
+        );
+        assert_eq!(
+            VbanSerialHeader::new("x", 9600, 0)
+                .encode_packet(&[0; VBAN_SERIAL_MAX_PAYLOAD + 1], &mut packet)
+                .unwrap_err(),
+            VbanError::PayloadTooLong {
+                len: VBAN_SERIAL_MAX_PAYLOAD + 1
+            }
+        );
+    }
+
     #[test]
     fn test_constants() {
         assert_eq!(VBAN_PORT, 6980);
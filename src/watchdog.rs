@@ -0,0 +1,161 @@
+//! Capture stall watchdog
+//!
+//! After a USB glitch uvcvideo can stop delivering frames without ever
+//! reporting an error. The capture loop records every frame it receives and
+//! checks the timer between frames (frame waits are bounded, so a check
+//! happens at least once per timeout); when no frame arrived within the stall
+//! timeout it tears the device down and reopens it. A reopen that brings no
+//! frames back stalls again; those failed reopens are counted so the capture
+//! loop can escalate to a USB reset.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Frame timer of the capture loop (atomics, so status readers can share it)
+pub struct CaptureWatchdog {
+    epoch: Instant,
+    /// Milliseconds since `epoch` of the last frame (or last reopen)
+    last_frame_ms: AtomicU64,
+    reopen_requested: AtomicBool,
+    stall_recoveries: AtomicU64,
+    /// Stalls since the last frame
+    stalls_since_frame: AtomicU32,
+}
+
+impl CaptureWatchdog {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            epoch: now,
+            last_frame_ms: AtomicU64::new(0),
+            reopen_requested: AtomicBool::new(false),
+            stall_recoveries: AtomicU64::new(0),
+            stalls_since_frame: AtomicU32::new(0),
+        }
+    }
+
+    /// Record a frame arriving at `now`
+    pub fn frame_received(&self, now: Instant) {
+        self.stalls_since_frame.store(0, Ordering::Relaxed);
+        self.restart_timer(now);
+    }
+
+    fn restart_timer(&self, now: Instant) {
+        let ms = now.saturating_duration_since(self.epoch).as_millis() as u64;
+        self.last_frame_ms.store(ms, Ordering::Relaxed);
+    }
+
+    /// Time since the last frame
+    pub fn since_last_frame(&self, now: Instant) -> Duration {
+        let last = self.epoch + Duration::from_millis(self.last_frame_ms.load(Ordering::Relaxed));
+        now.saturating_duration_since(last)
+    }
+
+    /// Check for a stall at `now`. Returns true when a reopen was newly
+    /// requested; a zero timeout disables the watchdog.
+    pub fn check(&self, now: Instant, timeout: Duration) -> bool {
+        if timeout.is_zero() || self.reopen_requested.load(Ordering::Relaxed) {
+            return false;
+        }
+        if self.since_last_frame(now) < timeout {
+            return false;
+        }
+        self.reopen_requested.store(true, Ordering::Relaxed);
+        self.stalls_since_frame.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Consume a pending reopen request (capture loop side)
+    pub fn take_reopen_request(&self) -> bool {
+        self.reopen_requested.swap(false, Ordering::Relaxed)
+    }
+
+    /// Record a completed reopen; the stall timer restarts from `now`
+    pub fn reopened(&self, now: Instant) {
+        self.stall_recoveries.fetch_add(1, Ordering::Relaxed);
+        self.restart_timer(now);
+    }
+
+    /// Reopens since the last frame that stalled again without a frame
+    pub fn failed_reopens(&self) -> u32 {
+        self.stalls_since_frame
+            .load(Ordering::Relaxed)
+            .saturating_sub(1)
+    }
+
+    pub fn stall_recoveries(&self) -> u64 {
+        self.stall_recoveries.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[test]
+    fn test_no_stall_while_frames_flow() {
+        let start = Instant::now();
+        let watchdog = CaptureWatchdog::new(start);
+        for s in 1..20 {
+            let now = start + Duration::from_secs(s);
+            watchdog.frame_received(now);
+            assert!(!watchdog.check(now + Duration::from_millis(900), TIMEOUT));
+        }
+        assert!(!watchdog.take_reopen_request());
+    }
+
+    #[test]
+    fn test_stall_requests_reopen_once() {
+        let start = Instant::now();
+        let watchdog = CaptureWatchdog::new(start);
+        watchdog.frame_received(start + Duration::from_secs(1));
+
+        assert!(!watchdog.check(start + Duration::from_millis(5900), TIMEOUT));
+        assert!(watchdog.check(start + Duration::from_secs(6), TIMEOUT));
+        // Pending request isn't raised again until the loop consumes it
+        assert!(!watchdog.check(start + Duration::from_secs(7), TIMEOUT));
+        assert!(watchdog.take_reopen_request());
+        assert!(!watchdog.take_reopen_request());
+    }
+
+    #[test]
+    fn test_reopen_restarts_timer_and_counts() {
+        let start = Instant::now();
+        let watchdog = CaptureWatchdog::new(start);
+        assert!(watchdog.check(start + TIMEOUT, TIMEOUT));
+        assert!(watchdog.take_reopen_request());
+
+        let reopened = start + Duration::from_secs(8);
+        watchdog.reopened(reopened);
+        assert_eq!(watchdog.stall_recoveries(), 1);
+        assert_eq!(watchdog.since_last_frame(reopened), Duration::ZERO);
+
+        // Still no frames after the reopen: stalls again after a full timeout
+        assert!(!watchdog.check(reopened + Duration::from_secs(4), TIMEOUT));
+        assert!(watchdog.check(reopened + TIMEOUT, TIMEOUT));
+    }
+
+    #[test]
+    fn test_failed_reopens_count_until_a_frame() {
+        let start = Instant::now();
+        let watchdog = CaptureWatchdog::new(start);
+        let mut now = start;
+        for failed in 0..3 {
+            now += TIMEOUT;
+            assert!(watchdog.check(now, TIMEOUT));
+            assert_eq!(watchdog.failed_reopens(), failed);
+            assert!(watchdog.take_reopen_request());
+            watchdog.reopened(now);
+        }
+        watchdog.frame_received(now + Duration::from_secs(1));
+        assert_eq!(watchdog.failed_reopens(), 0);
+    }
+
+    #[test]
+    fn test_zero_timeout_disables() {
+        let start = Instant::now();
+        let watchdog = CaptureWatchdog::new(start);
+        assert!(!watchdog.check(start + Duration::from_secs(3600), Duration::ZERO));
+    }
+}
@@ -0,0 +1,347 @@
+//! Minimal WAV writer
+//!
+//! 16-bit PCM RIFF files, used for troubleshooting recordings of the intercom.
+//! Sizes in the header are written as zero and patched when the file is finalized.
+
+use anyhow::{anyhow, Context, Result};
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Size of the canonical PCM WAV header
+pub const WAV_HEADER_SIZE: usize = 44;
+
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Build a canonical 44-byte PCM WAV header
+pub fn wav_header(sample_rate: u32, channels: u16, data_bytes: u32) -> [u8; WAV_HEADER_SIZE] {
+    let block_align = channels * BITS_PER_SAMPLE / 8;
+    let byte_rate = sample_rate * block_align as u32;
+
+    let mut buf = [0u8; WAV_HEADER_SIZE];
+    buf[0..4].copy_from_slice(b"RIFF");
+    buf[4..8].copy_from_slice(&(36 + data_bytes).to_le_bytes());
+    buf[8..12].copy_from_slice(b"WAVE");
+    buf[12..16].copy_from_slice(b"fmt ");
+    buf[16..20].copy_from_slice(&16u32.to_le_bytes()); // fmt chunk size
+    buf[20..22].copy_from_slice(&1u16.to_le_bytes()); // PCM
+    buf[22..24].copy_from_slice(&channels.to_le_bytes());
+    buf[24..28].copy_from_slice(&sample_rate.to_le_bytes());
+    buf[28..32].copy_from_slice(&byte_rate.to_le_bytes());
+    buf[32..34].copy_from_slice(&block_align.to_le_bytes());
+    buf[34..36].copy_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+    buf[36..40].copy_from_slice(b"data");
+    buf[40..44].copy_from_slice(&data_bytes.to_le_bytes());
+    buf
+}
+
+/// Streaming 16-bit PCM WAV writer
+pub struct WavWriter<W: Write + Seek> {
+    inner: W,
+    sample_rate: u32,
+    channels: u16,
+    data_bytes: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    /// Create a WAV file at `path`
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32, channels: u16) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create WAV file {}", path.display()))?;
+        Self::new(BufWriter::new(file), sample_rate, channels)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Start a WAV stream, writing a placeholder header
+    pub fn new(mut inner: W, sample_rate: u32, channels: u16) -> Result<Self> {
+        if channels == 0 {
+            return Err(anyhow!("WAV channel count must be at least 1"));
+        }
+        inner.write_all(&wav_header(sample_rate, channels, 0))?;
+        Ok(Self {
+            inner,
+            sample_rate,
+            channels,
+            data_bytes: 0,
+        })
+    }
+
+    /// Append interleaved samples
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<()> {
+        let bytes = samples.len() as u64 * 2;
+        if self.data_bytes as u64 + bytes > (u32::MAX - 36) as u64 {
+            return Err(anyhow!("WAV file size limit reached"));
+        }
+        for sample in samples {
+            self.inner.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_bytes += bytes as u32;
+        Ok(())
+    }
+
+    /// Number of frames (samples per channel) written so far
+    pub fn frames(&self) -> u64 {
+        self.data_bytes as u64 / (2 * self.channels as u64)
+    }
+
+    /// Patch the RIFF and data sizes and return the underlying writer
+    pub fn finalize(mut self) -> Result<W> {
+        let header = wav_header(self.sample_rate, self.channels, self.data_bytes);
+        self.inner.seek(SeekFrom::Start(0))?;
+        self.inner.write_all(&header)?;
+        self.inner.seek(SeekFrom::End(0))?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+/// Rolling WAV recorder: starts a new file every `segment` and keeps the last `keep` files.
+/// Files are named `<prefix>-<unix seconds>-<sequence>.wav` so they sort chronologically.
+pub struct SegmentedWavWriter {
+    dir: PathBuf,
+    prefix: String,
+    sample_rate: u32,
+    channels: u16,
+    segment_frames: u64,
+    keep: usize,
+    current: Option<WavWriter<BufWriter<File>>>,
+    segments: VecDeque<PathBuf>,
+    sequence: u32,
+}
+
+impl SegmentedWavWriter {
+    /// Create the recorder, picking up segments left by earlier runs for retention
+    pub fn new<P: AsRef<Path>>(
+        dir: P,
+        prefix: &str,
+        sample_rate: u32,
+        channels: u16,
+        segment: Duration,
+        keep: usize,
+    ) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create recording directory {}", dir.display()))?;
+
+        let pattern = format!("{}-", prefix);
+        let mut existing: Vec<PathBuf> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with(&pattern) && n.ends_with(".wav"))
+            })
+            .collect();
+        existing.sort();
+
+        let segment_frames = ((segment.as_secs_f64() * sample_rate as f64) as u64).max(1);
+        let mut writer = Self {
+            dir,
+            prefix: prefix.to_string(),
+            sample_rate,
+            channels,
+            segment_frames,
+            keep: keep.max(1),
+            current: None,
+            segments: existing.into(),
+            sequence: 0,
+        };
+        writer.prune();
+        Ok(writer)
+    }
+
+    /// Append interleaved samples, rolling over to a new file at segment boundaries
+    pub fn write_samples(&mut self, mut samples: &[i16]) -> Result<()> {
+        let channels = self.channels as usize;
+        while !samples.is_empty() {
+            if self.current.is_none() {
+                self.open_segment()?;
+            }
+            let writer = self.current.as_mut().expect("segment just opened");
+            let remaining = (self.segment_frames - writer.frames()) as usize * channels;
+            let (now, rest) = samples.split_at(remaining.min(samples.len()));
+            writer.write_samples(now)?;
+            if writer.frames() >= self.segment_frames {
+                self.close_segment()?;
+            }
+            samples = rest;
+        }
+        Ok(())
+    }
+
+    /// Finalize the current segment (if any)
+    pub fn finish(&mut self) -> Result<()> {
+        self.close_segment()
+    }
+
+    /// Segment files currently retained, oldest first
+    pub fn segments(&self) -> impl Iterator<Item = &Path> {
+        self.segments.iter().map(|p| p.as_path())
+    }
+
+    fn open_segment(&mut self) -> Result<()> {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = self.dir.join(format!(
+            "{}-{:010}-{:04}.wav",
+            self.prefix, secs, self.sequence
+        ));
+        self.sequence = self.sequence.wrapping_add(1);
+        self.current = Some(WavWriter::create(&path, self.sample_rate, self.channels)?);
+        self.segments.push_back(path);
+        self.prune();
+        Ok(())
+    }
+
+    fn close_segment(&mut self) -> Result<()> {
+        if let Some(writer) = self.current.take() {
+            writer.finalize()?;
+        }
+        Ok(())
+    }
+
+    fn prune(&mut self) {
+        while self.segments.len() > self.keep {
+            if let Some(oldest) = self.segments.pop_front() {
+                if let Err(e) = fs::remove_file(&oldest) {
+                    tracing::warn!("Failed to remove old recording {}: {}", oldest.display(), e);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SegmentedWavWriter {
+    fn drop(&mut self) {
+        let _ = self.close_segment();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    }
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([data[offset], data[offset + 1]])
+    }
+
+    #[test]
+    fn test_header_fields() {
+        let header = wav_header(48000, 2, 1000);
+        assert_eq!(&header[0..4], b"RIFF");
+        assert_eq!(u32_at(&header, 4), 1036);
+        assert_eq!(&header[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&header, 16), 16);
+        assert_eq!(u16_at(&header, 20), 1); // PCM
+        assert_eq!(u16_at(&header, 22), 2);
+        assert_eq!(u32_at(&header, 24), 48000);
+        assert_eq!(u32_at(&header, 28), 192000); // byte rate
+        assert_eq!(u16_at(&header, 32), 4); // block align
+        assert_eq!(u16_at(&header, 34), 16);
+        assert_eq!(&header[36..40], b"data");
+        assert_eq!(u32_at(&header, 40), 1000);
+    }
+
+    #[test]
+    fn test_writer_patches_sizes_on_finalize() {
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), 48000, 1).unwrap();
+        writer.write_samples(&[1, -1, 32767, -32768]).unwrap();
+        assert_eq!(writer.frames(), 4);
+        let data = writer.finalize().unwrap().into_inner();
+
+        assert_eq!(data.len(), WAV_HEADER_SIZE + 8);
+        assert_eq!(u32_at(&data, 4), 36 + 8);
+        assert_eq!(u32_at(&data, 40), 8);
+        assert_eq!(
+            &data[WAV_HEADER_SIZE..],
+            &[0x01, 0x00, 0xFF, 0xFF, 0xFF, 0x7F, 0x00, 0x80]
+        );
+    }
+
+    #[test]
+    fn test_writer_empty_file() {
+        let writer = WavWriter::new(Cursor::new(Vec::new()), 44100, 2).unwrap();
+        let data = writer.finalize().unwrap().into_inner();
+        assert_eq!(data, wav_header(44100, 2, 0));
+    }
+
+    #[test]
+    fn test_writer_rejects_zero_channels() {
+        assert!(WavWriter::new(Cursor::new(Vec::new()), 48000, 0).is_err());
+    }
+
+    #[test]
+    fn test_segment_rollover() {
+        let dir = tempfile::tempdir().unwrap();
+        // 10ms segments at 1kHz = 10 frames each
+        let mut writer =
+            SegmentedWavWriter::new(dir.path(), "tx", 1000, 2, Duration::from_millis(10), 10)
+                .unwrap();
+        // 25 stereo frames -> 10 + 10 + 5
+        writer.write_samples(&[7i16; 50]).unwrap();
+        writer.finish().unwrap();
+
+        let segments: Vec<PathBuf> = writer.segments().map(|p| p.to_path_buf()).collect();
+        assert_eq!(segments.len(), 3);
+        let sizes: Vec<u32> = segments
+            .iter()
+            .map(|p| u32_at(&fs::read(p).unwrap(), 40))
+            .collect();
+        assert_eq!(sizes, vec![40, 40, 20]);
+        for path in &segments {
+            let data = fs::read(path).unwrap();
+            assert_eq!(u32_at(&data, 4) as usize, data.len() - 8);
+        }
+    }
+
+    #[test]
+    fn test_segment_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer =
+            SegmentedWavWriter::new(dir.path(), "rx", 1000, 1, Duration::from_millis(5), 2)
+                .unwrap();
+        writer.write_samples(&[0i16; 20]).unwrap();
+        writer.finish().unwrap();
+
+        let remaining = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(remaining, 2);
+        assert_eq!(writer.segments().count(), 2);
+    }
+
+    #[test]
+    fn test_segment_retention_includes_previous_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            "rx-0000000001-0000.wav",
+            "rx-0000000002-0000.wav",
+            "other.txt",
+        ] {
+            fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let mut writer =
+            SegmentedWavWriter::new(dir.path(), "rx", 1000, 1, Duration::from_secs(1), 2).unwrap();
+        writer.write_samples(&[0i16; 10]).unwrap();
+        writer.finish().unwrap();
+
+        // Oldest previous segment removed, unrelated files untouched
+        assert!(!dir.path().join("rx-0000000001-0000.wav").exists());
+        assert!(dir.path().join("rx-0000000002-0000.wav").exists());
+        assert!(dir.path().join("other.txt").exists());
+    }
+}
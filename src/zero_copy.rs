@@ -0,0 +1,111 @@
+//! Asynchronous zero-copy send bookkeeping
+//!
+//! With `ndi.zero_copy_uyvy` a UYVY frame is handed to NDI's asynchronous
+//! send straight from the capture buffer. NDI keeps reading that buffer
+//! after the call returns, until the next video send (or a flush) returns,
+//! so the capture must not requeue it before then. The capture streams
+//! requeue a frame's buffer only after the callback for the next frame has
+//! returned, so the next frame's send gives the buffer back just in time;
+//! the pipeline flushes instead when that frame isn't lent.
+//!
+//! A [`HeldFrame`] remembers a fingerprint of the frame, so a buffer that
+//! was overwritten while NDI still owned it shows up in the stats instead of
+//! as a torn picture on air.
+
+/// Samples taken by [`fingerprint`]
+const SAMPLES: usize = 64;
+/// Bytes per sample
+const SAMPLE_LEN: usize = 8;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Cheap content hash over evenly spaced samples of `data`, cheap enough to
+/// take on every frame
+pub fn fingerprint(data: &[u8]) -> u64 {
+    let step = (data.len() / SAMPLES).max(SAMPLE_LEN);
+    let mut hash = FNV_OFFSET ^ data.len() as u64;
+    for start in (0..data.len()).step_by(step) {
+        let end = (start + SAMPLE_LEN).min(data.len());
+        for &byte in &data[start..end] {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    hash
+}
+
+/// A frame buffer lent to an asynchronous send
+#[derive(Debug)]
+pub struct HeldFrame {
+    ptr: *const u8,
+    len: usize,
+    fingerprint: u64,
+}
+
+// Only the address is kept; it's read back on the sending thread
+unsafe impl Send for HeldFrame {}
+
+impl HeldFrame {
+    pub fn new(data: &[u8]) -> Self {
+        Self {
+            ptr: data.as_ptr(),
+            len: data.len(),
+            fingerprint: fingerprint(data),
+        }
+    }
+
+    /// Whether this is the frame lent from `data`'s buffer
+    pub fn is_buffer(&self, data: &[u8]) -> bool {
+        self.ptr == data.as_ptr() && self.len == data.len()
+    }
+
+    /// Whether the buffer still holds the frame it held when lent
+    ///
+    /// # Safety
+    /// The buffer must still be allocated (or mapped); only its contents
+    /// may have changed.
+    pub unsafe fn is_intact(&self) -> bool {
+        let data = std::slice::from_raw_parts(self.ptr, self.len);
+        fingerprint(data) == self.fingerprint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_sees_changed_samples() {
+        let mut frame = vec![16u8; 1920 * 2 * 1080];
+        let original = fingerprint(&frame);
+        assert_eq!(fingerprint(&frame), original);
+
+        // The first sample and a sample in the middle of the frame
+        frame[0] = 17;
+        assert_ne!(fingerprint(&frame), original);
+        frame[0] = 16;
+        let step = frame.len() / SAMPLES;
+        frame[step * 32] = 235;
+        assert_ne!(fingerprint(&frame), original);
+    }
+
+    #[test]
+    fn test_fingerprint_of_small_buffers() {
+        assert_ne!(fingerprint(&[]), fingerprint(&[0]));
+        assert_ne!(fingerprint(&[1, 2, 3]), fingerprint(&[1, 2, 4]));
+    }
+
+    #[test]
+    fn test_held_frame_detects_overwrite() {
+        let mut buffer = vec![128u8; 4096];
+        let held = HeldFrame::new(&buffer);
+        assert!(unsafe { held.is_intact() });
+        assert!(held.is_buffer(&buffer));
+        assert!(!held.is_buffer(&buffer[1..]));
+
+        // The next frame lands in the same buffer
+        buffer.fill(64);
+        assert!(!unsafe { held.is_intact() });
+    }
+}